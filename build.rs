@@ -0,0 +1,16 @@
+fn main() {
+    /* the LTTng-UST tracepoint provider, needs the lttng-ust headers and library */
+    #[cfg(feature = "lttng")]
+    {
+        println!("cargo:rerun-if-changed=lttng/rtipc_tp.c");
+        println!("cargo:rerun-if-changed=lttng/rtipc_tp.h");
+
+        cc::Build::new()
+            .file("lttng/rtipc_tp.c")
+            .include("lttng")
+            .compile("rtipc_tp");
+
+        println!("cargo:rustc-link-lib=lttng-ust");
+        println!("cargo:rustc-link-lib=dl");
+    }
+}
@@ -12,7 +12,6 @@ use rtipc::Consumer;
 use rtipc::PopResult;
 use rtipc::Producer;
 use rtipc::client_connect;
-use rtipc::error::*;
 use rtipc::{ChannelConfig, QueueConfig, VectorConfig};
 
 use crate::common::CommandId;
@@ -140,38 +139,29 @@ fn main() {
         },
     ];
 
-    let c2s_channels: [ChannelConfig; 1] = [ChannelConfig {
-        queue: QueueConfig {
-            additional_messages: 0,
-            message_size: unsafe { NonZeroUsize::new_unchecked(size_of::<MsgCommand>()) },
-            info: b"rpc command".to_vec(),
-        },
-        eventfd: true,
-    }];
+    let queue = |additional_messages, message_size, info: &[u8]| QueueConfig {
+        info: info.to_vec(),
+        ..QueueConfig::new(
+            additional_messages,
+            NonZeroUsize::new(message_size).unwrap(),
+        )
+    };
+
+    let c2s_channels: [ChannelConfig; 1] = [ChannelConfig::new(
+        queue(0, size_of::<MsgCommand>(), b"rpc command"),
+        true,
+    )];
 
     let s2c_channels: [ChannelConfig; 2] = [
-        ChannelConfig {
-            queue: QueueConfig {
-                additional_messages: 0,
-                message_size: unsafe { NonZeroUsize::new_unchecked(size_of::<MsgResponse>()) },
-                info: b"rpc response".to_vec(),
-            },
-            eventfd: false,
-        },
-        ChannelConfig {
-            queue: QueueConfig {
-                additional_messages: 10,
-                message_size: unsafe { NonZeroUsize::new_unchecked(size_of::<MsgEvent>()) },
-                info: b"rpc event".to_vec(),
-            },
-            eventfd: true,
-        },
+        ChannelConfig::new(queue(0, size_of::<MsgResponse>(), b"rpc response"), false),
+        ChannelConfig::new(queue(10, size_of::<MsgEvent>(), b"rpc event"), true),
     ];
 
     let vparam = VectorConfig {
         producers: c2s_channels.to_vec(),
         consumers: s2c_channels.to_vec(),
         info: b"rpc example".to_vec(),
+        ..Default::default()
     };
     let vec = client_connect("rtipc.sock", vparam).unwrap();
     let mut app = App::new(vec);
@@ -15,6 +15,20 @@ pub enum CommandId {
     Div = 4,
 }
 
+impl TryFrom<u32> for CommandId {
+    type Error = u32;
+
+    fn try_from(id: u32) -> Result<Self, Self::Error> {
+        match id {
+            1 => Ok(CommandId::Hello),
+            2 => Ok(CommandId::Stop),
+            3 => Ok(CommandId::SendEvent),
+            4 => Ok(CommandId::Div),
+            _ => Err(id),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct MsgCommand {
     pub id: u32,
@@ -36,7 +50,7 @@ pub struct MsgEvent {
 
 impl fmt::Display for MsgCommand {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "id: {}", self.id as u32)?;
+        writeln!(f, "id: {}", self.id)?;
         for (idx, arg) in self.args.iter().enumerate() {
             writeln!(f, "\targ[{}]: {}", idx, arg)?
         }
@@ -49,7 +63,7 @@ impl fmt::Display for MsgResponse {
         writeln!(
             f,
             "id: {}\n\tresult: {}\n\tdata: {}",
-            self.id as u32, self.result, self.data
+            self.id, self.result, self.data
         )
     }
 }
@@ -64,5 +78,5 @@ pub fn wait_pollin(fd: BorrowedFd, timeout: Duration) -> Result<bool, Errno> {
     let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
     let duration: PollTimeout = timeout.try_into().unwrap();
     poll(&mut fds, duration)?;
-    Ok(fds[0].revents().map_or(false, |flags| !flags.is_empty()))
+    Ok(fds[0].revents().is_some_and(|flags| !flags.is_empty()))
 }
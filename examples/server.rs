@@ -52,7 +52,6 @@ impl App {
     }
     fn run(&mut self) {
         let mut run = true;
-        let mut cnt = 0;
 
         while run {
             let eventfd = self.command.eventfd().unwrap();
@@ -69,7 +68,11 @@ impl App {
             let args: [i32; 3] = cmd.args;
             println!("server received command: {}", cmd);
 
-            let cmdid: CommandId = unsafe { ::std::mem::transmute(cmd.id) };
+            let Ok(cmdid) = CommandId::try_from(cmd.id) else {
+                self.response.current_message().result = -1;
+                self.response.force_push();
+                continue;
+            };
             self.response.current_message().result = match cmdid {
                 CommandId::Hello => 0,
                 CommandId::Stop => {
@@ -86,8 +89,6 @@ impl App {
                 }
             };
             self.response.force_push();
-
-            cnt = cnt + 1;
         }
     }
     fn send_events(&mut self, id: u32, num: u32, force: bool) -> i32 {
@@ -106,18 +107,14 @@ impl App {
         num as i32
     }
     fn div(&mut self, a: i32, b: i32) -> (i32, i32) {
-        if b == 0 {
-            return (-1, 0);
-        } else {
-            return (0, a / b);
-        }
+        if b == 0 { (-1, 0) } else { (0, a / b) }
     }
 }
 
 fn main() {
     let backlog = Backlog::new(1).unwrap();
     let server = Server::new("rtipc.sock", backlog).unwrap();
-    let vec = server.conditional_accept(|_| true).unwrap();
+    let (vec, _) = server.conditional_accept(|_, _| Ok(Vec::new())).unwrap();
     let mut app = App::new(vec);
     app.run();
 }
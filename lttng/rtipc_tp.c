@@ -0,0 +1,32 @@
+/* LTTng-UST tracepoint provider of the queues and the functions firing the
+ * tracepoints for the Rust side, built with the lttng feature */
+
+#define LTTNG_UST_TRACEPOINT_CREATE_PROBES
+#define LTTNG_UST_TRACEPOINT_DEFINE
+
+#include "rtipc_tp.h"
+
+void rtipc_lttng_push(uint64_t queue, uint64_t depth)
+{
+    lttng_ust_tracepoint(rtipc, push, queue, depth);
+}
+
+void rtipc_lttng_pop(uint64_t queue, uint64_t message, uint64_t size)
+{
+    lttng_ust_tracepoint(rtipc, pop, queue, message, size);
+}
+
+void rtipc_lttng_discard(uint64_t queue)
+{
+    lttng_ust_tracepoint(rtipc, discard, queue);
+}
+
+void rtipc_lttng_overrun(uint64_t queue)
+{
+    lttng_ust_tracepoint(rtipc, overrun, queue);
+}
+
+void rtipc_lttng_eventfd_write(uint64_t queue, uint64_t fd)
+{
+    lttng_ust_tracepoint(rtipc, eventfd_write, queue, fd);
+}
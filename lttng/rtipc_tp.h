@@ -0,0 +1,58 @@
+/* LTTng-UST tracepoint provider of the queues, see src/tracepoint.rs */
+
+#undef LTTNG_UST_TRACEPOINT_PROVIDER
+#define LTTNG_UST_TRACEPOINT_PROVIDER rtipc
+
+#undef LTTNG_UST_TRACEPOINT_INCLUDE
+#define LTTNG_UST_TRACEPOINT_INCLUDE "./rtipc_tp.h"
+
+#if !defined(RTIPC_TP_H) || defined(LTTNG_UST_TRACEPOINT_HEADER_MULTI_READ)
+#define RTIPC_TP_H
+
+#include <stdint.h>
+
+#include <lttng/tracepoint.h>
+
+LTTNG_UST_TRACEPOINT_EVENT_CLASS(rtipc, queue,
+    LTTNG_UST_TP_ARGS(uint64_t, queue),
+    LTTNG_UST_TP_FIELDS(
+        lttng_ust_field_integer_hex(uint64_t, queue, queue)
+    )
+)
+
+LTTNG_UST_TRACEPOINT_EVENT_INSTANCE(rtipc, queue, rtipc, discard,
+    LTTNG_UST_TP_ARGS(uint64_t, queue)
+)
+
+LTTNG_UST_TRACEPOINT_EVENT_INSTANCE(rtipc, queue, rtipc, overrun,
+    LTTNG_UST_TP_ARGS(uint64_t, queue)
+)
+
+LTTNG_UST_TRACEPOINT_EVENT(rtipc, push,
+    LTTNG_UST_TP_ARGS(uint64_t, queue, uint64_t, depth),
+    LTTNG_UST_TP_FIELDS(
+        lttng_ust_field_integer_hex(uint64_t, queue, queue)
+        lttng_ust_field_integer(uint64_t, depth, depth)
+    )
+)
+
+LTTNG_UST_TRACEPOINT_EVENT(rtipc, pop,
+    LTTNG_UST_TP_ARGS(uint64_t, queue, uint64_t, message, uint64_t, size),
+    LTTNG_UST_TP_FIELDS(
+        lttng_ust_field_integer_hex(uint64_t, queue, queue)
+        lttng_ust_field_integer_hex(uint64_t, message, message)
+        lttng_ust_field_integer(uint64_t, size, size)
+    )
+)
+
+LTTNG_UST_TRACEPOINT_EVENT(rtipc, eventfd_write,
+    LTTNG_UST_TP_ARGS(uint64_t, queue, uint64_t, fd),
+    LTTNG_UST_TP_FIELDS(
+        lttng_ust_field_integer_hex(uint64_t, queue, queue)
+        lttng_ust_field_integer(int, fd, fd)
+    )
+)
+
+#endif /* RTIPC_TP_H */
+
+#include <lttng/tracepoint-event.h>
@@ -0,0 +1,76 @@
+/* C ABI of rtipc, exported by librtipc_sys of the rtipc-sys crate.
+ * Messages are untyped, the slots of a queue are accessed by pointer. */
+#ifndef RTIPC_H
+#define RTIPC_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RTIPC_QUEUE_ERROR (-1)
+
+/* results of rtipc_consumer_pop and rtipc_consumer_flush */
+#define RTIPC_POP_NO_MESSAGE 0
+#define RTIPC_POP_NO_NEW_MESSAGE 1
+#define RTIPC_POP_SUCCESS 2
+#define RTIPC_POP_SUCCESS_MESSAGES_DISCARDED 3
+
+/* results of rtipc_producer_force_push and rtipc_producer_try_push */
+#define RTIPC_PUSH_SUCCESS 0
+#define RTIPC_PUSH_MESSAGE_DISCARDED 1
+#define RTIPC_PUSH_QUEUE_FULL 2
+
+typedef struct rtipc_vector_config rtipc_vector_config;
+typedef struct rtipc_vector rtipc_vector;
+typedef struct rtipc_server rtipc_server;
+typedef struct rtipc_producer rtipc_producer;
+typedef struct rtipc_consumer rtipc_consumer;
+
+rtipc_vector_config *rtipc_vector_config_new(void);
+void rtipc_vector_config_free(rtipc_vector_config *config);
+
+/* return the index of the added queue or -1 */
+int rtipc_vector_config_add_producer(rtipc_vector_config *config, size_t additional_messages,
+                                     size_t message_size, bool eventfd);
+int rtipc_vector_config_add_consumer(rtipc_vector_config *config, size_t additional_messages,
+                                     size_t message_size, bool eventfd);
+
+/* two connected vectors without a handshake, returns 0 or -1 */
+int rtipc_vector_create_pair(const rtipc_vector_config *config, rtipc_vector **owner,
+                             rtipc_vector **peer);
+
+/* return NULL on failure */
+rtipc_vector *rtipc_client_connect(const char *path, const rtipc_vector_config *config);
+rtipc_server *rtipc_server_new(const char *path, int backlog);
+rtipc_vector *rtipc_server_accept(const rtipc_server *server);
+void rtipc_server_free(rtipc_server *server);
+
+/* producers and consumers taken from the vector stay valid after it's freed */
+void rtipc_vector_free(rtipc_vector *vec);
+rtipc_producer *rtipc_vector_take_producer(rtipc_vector *vec, size_t index);
+rtipc_consumer *rtipc_vector_take_consumer(rtipc_vector *vec, size_t index);
+
+/* message written next, valid until the next push */
+void *rtipc_producer_msg(rtipc_producer *producer);
+int rtipc_producer_force_push(rtipc_producer *producer);
+int rtipc_producer_try_push(rtipc_producer *producer);
+/* -1 without eventfd, the fd stays owned by the producer */
+int rtipc_producer_eventfd(const rtipc_producer *producer);
+void rtipc_producer_free(rtipc_producer *producer);
+
+/* message popped last */
+const void *rtipc_consumer_msg(const rtipc_consumer *consumer);
+int rtipc_consumer_pop(rtipc_consumer *consumer);
+int rtipc_consumer_flush(rtipc_consumer *consumer);
+/* -1 without eventfd, the fd stays owned by the consumer */
+int rtipc_consumer_eventfd(const rtipc_consumer *consumer);
+void rtipc_consumer_free(rtipc_consumer *consumer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RTIPC_H */
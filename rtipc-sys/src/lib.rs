@@ -0,0 +1,375 @@
+use std::ffi::{CStr, c_char, c_int, c_void};
+use std::num::NonZeroUsize;
+use std::os::fd::{AsRawFd, BorrowedFd};
+use std::ptr::null_mut;
+
+use nix::sys::socket::Backlog;
+
+use rtipc_core::log::error;
+use rtipc_core::{
+    ChannelConfig, ChannelKind, ChannelVector, Consumer, ForcePushResult, PopResult, Producer,
+    QueueConfig, Server, TryPushResult, VectorConfig, client_connect,
+};
+
+/* C ABI of rtipc, declared in include/rtipc.h. Messages are untyped,
+ * C peers access the slots of a queue by pointer. The values of the results
+ * have to match the header. */
+pub const RTIPC_QUEUE_ERROR: c_int = -1;
+
+pub const RTIPC_POP_NO_MESSAGE: c_int = 0;
+pub const RTIPC_POP_NO_NEW_MESSAGE: c_int = 1;
+pub const RTIPC_POP_SUCCESS: c_int = 2;
+pub const RTIPC_POP_SUCCESS_MESSAGES_DISCARDED: c_int = 3;
+
+pub const RTIPC_PUSH_SUCCESS: c_int = 0;
+pub const RTIPC_PUSH_MESSAGE_DISCARDED: c_int = 1;
+pub const RTIPC_PUSH_QUEUE_FULL: c_int = 2;
+
+fn pop_result(result: PopResult) -> c_int {
+    match result {
+        PopResult::QueueError => RTIPC_QUEUE_ERROR,
+        PopResult::NoMessage => RTIPC_POP_NO_MESSAGE,
+        PopResult::NoNewMessage => RTIPC_POP_NO_NEW_MESSAGE,
+        PopResult::Success => RTIPC_POP_SUCCESS,
+        PopResult::SuccessMessagesDiscarded => RTIPC_POP_SUCCESS_MESSAGES_DISCARDED,
+    }
+}
+
+fn eventfd(fd: Option<BorrowedFd<'_>>) -> c_int {
+    fd.map_or(-1, |fd| fd.as_raw_fd())
+}
+
+fn into_raw<T>(value: T) -> *mut T {
+    Box::into_raw(Box::new(value))
+}
+
+/// Frees a pointer returned by into_raw, null is ignored.
+unsafe fn free<T>(ptr: *mut T) {
+    if !ptr.is_null() {
+        drop(unsafe { Box::from_raw(ptr) });
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn rtipc_vector_config_new() -> *mut VectorConfig {
+    into_raw(VectorConfig {
+        producers: Vec::new(),
+        consumers: Vec::new(),
+        info: Vec::new(),
+        arena: None,
+        heartbeat: false,
+    })
+}
+
+/// # Safety
+/// config has to be returned by rtipc_vector_config_new or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_vector_config_free(config: *mut VectorConfig) {
+    unsafe { free(config) }
+}
+
+fn channel_config(
+    additional_messages: usize,
+    message_size: usize,
+    eventfd: bool,
+) -> Option<ChannelConfig> {
+    Some(ChannelConfig {
+        queue: QueueConfig {
+            additional_messages,
+            message_size: NonZeroUsize::new(message_size)?,
+            info: Vec::new(),
+            schema: None,
+        },
+        kind: ChannelKind::Queue,
+        eventfd,
+    })
+}
+
+/// Adds a queue the creator of the vector produces, returns its index or -1.
+///
+/// # Safety
+/// config has to be returned by rtipc_vector_config_new.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_vector_config_add_producer(
+    config: *mut VectorConfig,
+    additional_messages: usize,
+    message_size: usize,
+    eventfd: bool,
+) -> c_int {
+    let Some(config) = (unsafe { config.as_mut() }) else {
+        return -1;
+    };
+
+    match channel_config(additional_messages, message_size, eventfd) {
+        Some(channel) => {
+            config.producers.push(channel);
+            (config.producers.len() - 1) as c_int
+        }
+        None => -1,
+    }
+}
+
+/// Adds a queue the creator of the vector consumes, returns its index or -1.
+///
+/// # Safety
+/// config has to be returned by rtipc_vector_config_new.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_vector_config_add_consumer(
+    config: *mut VectorConfig,
+    additional_messages: usize,
+    message_size: usize,
+    eventfd: bool,
+) -> c_int {
+    let Some(config) = (unsafe { config.as_mut() }) else {
+        return -1;
+    };
+
+    match channel_config(additional_messages, message_size, eventfd) {
+        Some(channel) => {
+            config.consumers.push(channel);
+            (config.consumers.len() - 1) as c_int
+        }
+        None => -1,
+    }
+}
+
+/// Creates two connected vectors without a handshake, returns 0 or -1.
+///
+/// # Safety
+/// config has to be returned by rtipc_vector_config_new, owner and peer have to be valid.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_vector_create_pair(
+    config: *const VectorConfig,
+    owner: *mut *mut ChannelVector,
+    peer: *mut *mut ChannelVector,
+) -> c_int {
+    let Some(config) = (unsafe { config.as_ref() }) else {
+        return -1;
+    };
+
+    match ChannelVector::create_pair(config.clone()) {
+        Ok((first, second)) => {
+            unsafe {
+                *owner = into_raw(first);
+                *peer = into_raw(second);
+            }
+            0
+        }
+        Err(e) => {
+            error!("rtipc_vector_create_pair failed {e:?}");
+            -1
+        }
+    }
+}
+
+/// Connects to the server listening at path, returns null on failure.
+///
+/// # Safety
+/// path has to be a nul terminated string, config has to be returned by rtipc_vector_config_new.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_client_connect(
+    path: *const c_char,
+    config: *const VectorConfig,
+) -> *mut ChannelVector {
+    if path.is_null() {
+        return null_mut();
+    }
+
+    let Some(config) = (unsafe { config.as_ref() }) else {
+        return null_mut();
+    };
+
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else {
+        return null_mut();
+    };
+
+    client_connect(path, config.clone())
+        .inspect_err(|e| error!("rtipc_client_connect failed {e:?}"))
+        .map_or(null_mut(), into_raw)
+}
+
+/// Listens at path, returns null on failure.
+///
+/// # Safety
+/// path has to be a nul terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_server_new(path: *const c_char, backlog: c_int) -> *mut Server {
+    if path.is_null() {
+        return null_mut();
+    }
+
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else {
+        return null_mut();
+    };
+
+    let Ok(backlog) = Backlog::new(backlog) else {
+        return null_mut();
+    };
+
+    Server::new(path, backlog)
+        .inspect_err(|e| error!("rtipc_server_new failed {e:?}"))
+        .map_or(null_mut(), into_raw)
+}
+
+/// Blocks until a client connected, returns null on failure.
+///
+/// # Safety
+/// server has to be returned by rtipc_server_new.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_server_accept(server: *const Server) -> *mut ChannelVector {
+    let Some(server) = (unsafe { server.as_ref() }) else {
+        return null_mut();
+    };
+
+    server
+        .accept()
+        .inspect_err(|e| error!("rtipc_server_accept failed {e:?}"))
+        .map_or(null_mut(), |(vec, _)| into_raw(vec))
+}
+
+/// # Safety
+/// server has to be returned by rtipc_server_new or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_server_free(server: *mut Server) {
+    unsafe { free(server) }
+}
+
+/// Producers and consumers taken from the vector stay valid after it's freed.
+///
+/// # Safety
+/// vec has to be returned by rtipc_client_connect, rtipc_server_accept,
+/// rtipc_vector_create_pair or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_vector_free(vec: *mut ChannelVector) {
+    unsafe { free(vec) }
+}
+
+/// Returns null if the index is out of range, the queue was taken already
+/// or isn't a plain queue.
+///
+/// # Safety
+/// vec has to be a vector returned by this API.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_vector_take_producer(
+    vec: *mut ChannelVector,
+    index: usize,
+) -> *mut Producer<u8> {
+    unsafe { vec.as_mut() }
+        .and_then(|vec| vec.take_producer::<u8>(index))
+        .map_or(null_mut(), into_raw)
+}
+
+/// Returns null if the index is out of range, the queue was taken already
+/// or isn't a plain queue.
+///
+/// # Safety
+/// vec has to be a vector returned by this API.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_vector_take_consumer(
+    vec: *mut ChannelVector,
+    index: usize,
+) -> *mut Consumer<u8> {
+    unsafe { vec.as_mut() }
+        .and_then(|vec| vec.take_consumer::<u8>(index))
+        .map_or(null_mut(), into_raw)
+}
+
+/// Message the producer writes next, valid until the next push.
+///
+/// # Safety
+/// producer has to be returned by rtipc_vector_take_producer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_producer_msg(producer: *mut Producer<u8>) -> *mut c_void {
+    unsafe { producer.as_mut() }.map_or(null_mut(), |producer| {
+        (producer.current_message() as *mut u8).cast()
+    })
+}
+
+/// # Safety
+/// producer has to be returned by rtipc_vector_take_producer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_producer_force_push(producer: *mut Producer<u8>) -> c_int {
+    let Some(producer) = (unsafe { producer.as_mut() }) else {
+        return RTIPC_QUEUE_ERROR;
+    };
+
+    match producer.force_push() {
+        ForcePushResult::QueueError => RTIPC_QUEUE_ERROR,
+        ForcePushResult::Success => RTIPC_PUSH_SUCCESS,
+        ForcePushResult::SuccessMessageDiscarded => RTIPC_PUSH_MESSAGE_DISCARDED,
+    }
+}
+
+/// # Safety
+/// producer has to be returned by rtipc_vector_take_producer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_producer_try_push(producer: *mut Producer<u8>) -> c_int {
+    let Some(producer) = (unsafe { producer.as_mut() }) else {
+        return RTIPC_QUEUE_ERROR;
+    };
+
+    match producer.try_push() {
+        TryPushResult::QueueError => RTIPC_QUEUE_ERROR,
+        TryPushResult::QueueFull => RTIPC_PUSH_QUEUE_FULL,
+        TryPushResult::Success => RTIPC_PUSH_SUCCESS,
+    }
+}
+
+/// Returns -1 if the queue has no eventfd, the fd stays owned by the producer.
+///
+/// # Safety
+/// producer has to be returned by rtipc_vector_take_producer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_producer_eventfd(producer: *const Producer<u8>) -> c_int {
+    unsafe { producer.as_ref() }.map_or(-1, |producer| eventfd(producer.eventfd()))
+}
+
+/// # Safety
+/// producer has to be returned by rtipc_vector_take_producer or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_producer_free(producer: *mut Producer<u8>) {
+    unsafe { free(producer) }
+}
+
+/// Message the consumer popped last.
+///
+/// # Safety
+/// consumer has to be returned by rtipc_vector_take_consumer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_consumer_msg(consumer: *const Consumer<u8>) -> *const c_void {
+    unsafe { consumer.as_ref() }
+        .and_then(|consumer| consumer.current_message())
+        .map_or(std::ptr::null(), |msg| (msg as *const u8).cast())
+}
+
+/// # Safety
+/// consumer has to be returned by rtipc_vector_take_consumer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_consumer_pop(consumer: *mut Consumer<u8>) -> c_int {
+    unsafe { consumer.as_mut() }.map_or(RTIPC_QUEUE_ERROR, |consumer| pop_result(consumer.pop()))
+}
+
+/// Pops the newest message, the ones in between are skipped.
+///
+/// # Safety
+/// consumer has to be returned by rtipc_vector_take_consumer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_consumer_flush(consumer: *mut Consumer<u8>) -> c_int {
+    unsafe { consumer.as_mut() }.map_or(RTIPC_QUEUE_ERROR, |consumer| pop_result(consumer.flush()))
+}
+
+/// Returns -1 if the queue has no eventfd, the fd stays owned by the consumer.
+///
+/// # Safety
+/// consumer has to be returned by rtipc_vector_take_consumer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_consumer_eventfd(consumer: *const Consumer<u8>) -> c_int {
+    unsafe { consumer.as_ref() }.map_or(-1, |consumer| eventfd(consumer.eventfd()))
+}
+
+/// # Safety
+/// consumer has to be returned by rtipc_vector_take_consumer or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtipc_consumer_free(consumer: *mut Consumer<u8>) {
+    unsafe { free(consumer) }
+}
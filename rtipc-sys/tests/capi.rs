@@ -0,0 +1,230 @@
+/* the C ABI called like a C application would, the results are compared with include/rtipc.h */
+use std::collections::HashMap;
+use std::ffi::{CString, c_char, c_int, c_void};
+use std::ptr::null_mut;
+use std::thread;
+
+mod common;
+
+/* the symbols are defined by the crate */
+use rtipc_sys as _;
+
+/* the handles are opaque to C */
+type Config = c_void;
+type Vector = c_void;
+type Server = c_void;
+type Producer = c_void;
+type Consumer = c_void;
+
+unsafe extern "C" {
+    fn rtipc_vector_config_new() -> *mut Config;
+    fn rtipc_vector_config_free(config: *mut Config);
+    fn rtipc_vector_config_add_producer(
+        config: *mut Config,
+        additional_messages: usize,
+        message_size: usize,
+        eventfd: bool,
+    ) -> c_int;
+    fn rtipc_vector_config_add_consumer(
+        config: *mut Config,
+        additional_messages: usize,
+        message_size: usize,
+        eventfd: bool,
+    ) -> c_int;
+    fn rtipc_vector_create_pair(
+        config: *const Config,
+        owner: *mut *mut Vector,
+        peer: *mut *mut Vector,
+    ) -> c_int;
+    fn rtipc_client_connect(path: *const c_char, config: *const Config) -> *mut Vector;
+    fn rtipc_server_new(path: *const c_char, backlog: c_int) -> *mut Server;
+    fn rtipc_server_accept(server: *const Server) -> *mut Vector;
+    fn rtipc_server_free(server: *mut Server);
+    fn rtipc_vector_free(vec: *mut Vector);
+    fn rtipc_vector_take_producer(vec: *mut Vector, index: usize) -> *mut Producer;
+    fn rtipc_vector_take_consumer(vec: *mut Vector, index: usize) -> *mut Consumer;
+    fn rtipc_producer_msg(producer: *mut Producer) -> *mut c_void;
+    fn rtipc_producer_force_push(producer: *mut Producer) -> c_int;
+    fn rtipc_producer_try_push(producer: *mut Producer) -> c_int;
+    fn rtipc_producer_eventfd(producer: *const Producer) -> c_int;
+    fn rtipc_producer_free(producer: *mut Producer);
+    fn rtipc_consumer_msg(consumer: *const Consumer) -> *const c_void;
+    fn rtipc_consumer_pop(consumer: *mut Consumer) -> c_int;
+    fn rtipc_consumer_flush(consumer: *mut Consumer) -> c_int;
+    fn rtipc_consumer_eventfd(consumer: *const Consumer) -> c_int;
+    fn rtipc_consumer_free(consumer: *mut Consumer);
+}
+
+/// Value of a define of include/rtipc.h, e.g. RTIPC_POP_SUCCESS.
+fn define(name: &str) -> c_int {
+    let defines: HashMap<&str, c_int> = include_str!("../include/rtipc.h")
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.strip_prefix("#define ")?.split(' ');
+            let name = words.next()?;
+            let value = words.next()?.trim_matches(['(', ')']).parse().ok()?;
+            Some((name, value))
+        })
+        .collect();
+
+    defines[name]
+}
+
+unsafe fn write(producer: *mut Producer, value: u64) {
+    unsafe { rtipc_producer_msg(producer).cast::<u64>().write(value) };
+}
+
+unsafe fn read(consumer: *const Consumer) -> Option<u64> {
+    let msg = unsafe { rtipc_consumer_msg(consumer) };
+    (!msg.is_null()).then(|| unsafe { msg.cast::<u64>().read() })
+}
+
+#[test]
+fn queues_are_used_through_the_c_abi() {
+    unsafe {
+        let config = rtipc_vector_config_new();
+        assert_eq!(rtipc_vector_config_add_producer(config, 0, 8, false), 0);
+        assert_eq!(rtipc_vector_config_add_consumer(config, 1, 8, false), 0);
+        assert_eq!(rtipc_vector_config_add_consumer(config, 1, 0, false), -1);
+
+        let mut owner = null_mut();
+        let mut peer = null_mut();
+        assert_eq!(rtipc_vector_create_pair(config, &mut owner, &mut peer), 0);
+        rtipc_vector_config_free(config);
+
+        let producer = rtipc_vector_take_producer(owner, 0);
+        let consumer = rtipc_vector_take_consumer(peer, 0);
+        assert!(rtipc_vector_take_producer(owner, 0).is_null());
+        assert!(rtipc_vector_take_consumer(peer, 1).is_null());
+
+        /* the queues outlive their vectors */
+        rtipc_vector_free(owner);
+        rtipc_vector_free(peer);
+
+        assert_eq!(rtipc_producer_eventfd(producer), -1);
+        assert_eq!(rtipc_consumer_eventfd(consumer), -1);
+
+        assert_eq!(rtipc_consumer_pop(consumer), define("RTIPC_POP_NO_MESSAGE"));
+
+        /* a queue of 3 messages, the consumer holds none */
+        for value in 1..=2 {
+            write(producer, value);
+            assert_eq!(
+                rtipc_producer_try_push(producer),
+                define("RTIPC_PUSH_SUCCESS")
+            );
+        }
+        assert_eq!(
+            rtipc_producer_try_push(producer),
+            define("RTIPC_PUSH_QUEUE_FULL")
+        );
+
+        assert_eq!(rtipc_consumer_pop(consumer), define("RTIPC_POP_SUCCESS"));
+        assert_eq!(read(consumer), Some(1));
+
+        /* the consumer holds 1, 3 replaces 2 */
+        write(producer, 3);
+        assert_eq!(
+            rtipc_producer_force_push(producer),
+            define("RTIPC_PUSH_MESSAGE_DISCARDED")
+        );
+        assert_eq!(
+            rtipc_consumer_pop(consumer),
+            define("RTIPC_POP_SUCCESS_MESSAGES_DISCARDED")
+        );
+        assert_eq!(read(consumer), Some(3));
+
+        /* flush skips to the newest message */
+        for value in 4..=6 {
+            write(producer, value);
+            rtipc_producer_force_push(producer);
+        }
+        assert_eq!(rtipc_consumer_flush(consumer), define("RTIPC_POP_SUCCESS"));
+        assert_eq!(read(consumer), Some(6));
+        assert_eq!(
+            rtipc_consumer_pop(consumer),
+            define("RTIPC_POP_NO_NEW_MESSAGE")
+        );
+
+        rtipc_producer_free(producer);
+        rtipc_consumer_free(consumer);
+    }
+}
+
+#[test]
+fn null_handles_are_refused() {
+    unsafe {
+        assert_eq!(
+            rtipc_vector_config_add_producer(null_mut(), 0, 8, false),
+            -1
+        );
+        assert_eq!(
+            rtipc_vector_create_pair(null_mut(), &mut null_mut(), &mut null_mut()),
+            -1
+        );
+        assert!(rtipc_client_connect(null_mut(), null_mut()).is_null());
+        assert!(rtipc_server_new(null_mut(), 1).is_null());
+        assert!(rtipc_vector_take_producer(null_mut(), 0).is_null());
+        assert!(rtipc_producer_msg(null_mut()).is_null());
+        assert!(rtipc_consumer_msg(null_mut()).is_null());
+        assert_eq!(
+            rtipc_producer_try_push(null_mut()),
+            define("RTIPC_QUEUE_ERROR")
+        );
+        assert_eq!(rtipc_consumer_pop(null_mut()), define("RTIPC_QUEUE_ERROR"));
+        assert_eq!(rtipc_producer_eventfd(null_mut()), -1);
+
+        /* freeing null is a no-op like free(3) */
+        rtipc_vector_config_free(null_mut());
+        rtipc_vector_free(null_mut());
+        rtipc_server_free(null_mut());
+        rtipc_producer_free(null_mut());
+        rtipc_consumer_free(null_mut());
+    }
+}
+
+#[test]
+fn clients_connect_through_the_c_abi() {
+    let path = CString::new(
+        common::socket_path("capi")
+            .into_os_string()
+            .into_encoded_bytes(),
+    )
+    .unwrap();
+
+    unsafe {
+        let server = rtipc_server_new(path.as_ptr(), 1);
+        assert!(!server.is_null());
+
+        let client = thread::spawn(move || {
+            let config = rtipc_vector_config_new();
+            rtipc_vector_config_add_producer(config, 0, 8, true);
+            let vec = rtipc_client_connect(path.as_ptr(), config) as usize;
+            rtipc_vector_config_free(config);
+            vec
+        });
+
+        let vec = rtipc_server_accept(server);
+        let client = client.join().unwrap() as *mut Vector;
+        assert!(!vec.is_null() && !client.is_null());
+        rtipc_server_free(server);
+
+        let producer = rtipc_vector_take_producer(client, 0);
+        let consumer = rtipc_vector_take_consumer(vec, 0);
+
+        write(producer, 42);
+        rtipc_producer_force_push(producer);
+
+        /* the eventfds of the queue were passed with the handshake */
+        assert!(rtipc_producer_eventfd(producer) >= 0);
+        assert!(rtipc_consumer_eventfd(consumer) >= 0);
+
+        assert_eq!(rtipc_consumer_pop(consumer), define("RTIPC_POP_SUCCESS"));
+        assert_eq!(read(consumer), Some(42));
+
+        rtipc_producer_free(producer);
+        rtipc_consumer_free(consumer);
+        rtipc_vector_free(client);
+        rtipc_vector_free(vec);
+    }
+}
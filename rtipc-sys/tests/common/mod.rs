@@ -0,0 +1,37 @@
+/* fixtures of the integration tests, every test uses a part of them */
+#![allow(dead_code)]
+
+use std::num::NonZeroUsize;
+
+use rtipc_core::*;
+
+/// Vector of queues with eventfds, every channel configured alike.
+pub fn vector(
+    producers: usize,
+    consumers: usize,
+    additional_messages: usize,
+    message_size: usize,
+) -> VectorConfig {
+    let channel = || {
+        ChannelConfig::new(
+            QueueConfig::new(
+                additional_messages,
+                NonZeroUsize::new(message_size).unwrap(),
+            ),
+            true,
+        )
+    };
+
+    VectorConfig {
+        producers: (0..producers).map(|_| channel()).collect(),
+        consumers: (0..consumers).map(|_| channel()).collect(),
+        ..Default::default()
+    }
+}
+
+/// Socket path in the temp dir, unique per test process and name.
+pub fn socket_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("rtipc-{name}-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
@@ -0,0 +1,71 @@
+/* include/rtipc.h is written by hand, these tests keep it in step with src/lib.rs */
+use std::collections::{BTreeMap, BTreeSet};
+
+use rtipc_sys::*;
+
+const HEADER: &str = include_str!("../include/rtipc.h");
+const SOURCE: &str = include_str!("../src/lib.rs");
+
+/// Name of the function declared or defined on a line, e.g. rtipc_server_new.
+fn function(line: &str) -> Option<&str> {
+    let (head, _) = line.split_once('(')?;
+    let name = head.rsplit([' ', '*']).next()?;
+    name.starts_with("rtipc_").then_some(name)
+}
+
+#[test]
+fn every_function_is_declared() {
+    let declared: BTreeSet<&str> = HEADER
+        .lines()
+        .filter(|line| !line.starts_with([' ', '/', '#']))
+        .filter_map(function)
+        .collect();
+
+    let exported: BTreeSet<&str> = SOURCE
+        .lines()
+        .filter(|line| line.starts_with("pub ") && line.contains("extern \"C\" fn "))
+        .filter_map(function)
+        .collect();
+
+    assert!(!exported.is_empty());
+    assert_eq!(declared, exported);
+}
+
+#[test]
+fn every_define_has_the_value_of_its_constant() {
+    let defines: BTreeMap<&str, &str> = HEADER
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.strip_prefix("#define RTIPC_")?.split(' ');
+            let name = words.next()?;
+            let value = words.next()?.trim_matches(['(', ')']);
+            Some((name, value))
+        })
+        .collect();
+
+    let constants: BTreeSet<&str> = SOURCE
+        .lines()
+        .filter_map(|line| line.strip_prefix("pub const RTIPC_")?.split(':').next())
+        .collect();
+
+    let values = [
+        ("QUEUE_ERROR", RTIPC_QUEUE_ERROR),
+        ("POP_NO_MESSAGE", RTIPC_POP_NO_MESSAGE),
+        ("POP_NO_NEW_MESSAGE", RTIPC_POP_NO_NEW_MESSAGE),
+        ("POP_SUCCESS", RTIPC_POP_SUCCESS),
+        (
+            "POP_SUCCESS_MESSAGES_DISCARDED",
+            RTIPC_POP_SUCCESS_MESSAGES_DISCARDED,
+        ),
+        ("PUSH_SUCCESS", RTIPC_PUSH_SUCCESS),
+        ("PUSH_MESSAGE_DISCARDED", RTIPC_PUSH_MESSAGE_DISCARDED),
+        ("PUSH_QUEUE_FULL", RTIPC_PUSH_QUEUE_FULL),
+    ];
+
+    assert_eq!(defines.keys().copied().collect::<BTreeSet<_>>(), constants);
+    assert_eq!(values.len(), constants.len());
+
+    for (name, value) in values {
+        assert_eq!(defines[name], value.to_string(), "RTIPC_{name}");
+    }
+}
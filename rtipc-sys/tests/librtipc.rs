@@ -0,0 +1,144 @@
+/* interoperability with a peer in C: tests/librtipc/peer.c is built against the C
+ * implementation at RTIPC_C_DIR, a directory with include/rtipc.h and lib/librtipc.so.
+ * Without RTIPC_C_DIR the peer is built against librtipc_sys of this crate. */
+#![cfg(target_os = "linux")]
+
+use std::os::fd::BorrowedFd;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use nix::sys::socket::Backlog;
+
+use rtipc_core::*;
+
+mod common;
+
+/// Directories with the header and the library the peer is built against,
+/// and the name of the library.
+fn c_library() -> (PathBuf, PathBuf, &'static str) {
+    if let Some(dir) = std::env::var_os("RTIPC_C_DIR") {
+        let dir = PathBuf::from(dir);
+        return (dir.join("include"), dir.join("lib"), "rtipc");
+    }
+
+    /* the cdylib is built next to the test binary */
+    let deps = std::env::current_exe().unwrap();
+    (
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("include"),
+        deps.parent().unwrap().to_path_buf(),
+        "rtipc_sys",
+    )
+}
+
+/// Builds tests/librtipc/peer.c, returns the path of the binary.
+fn build_peer(role: &str) -> PathBuf {
+    let (include, lib, name) = c_library();
+
+    let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/librtipc/peer.c");
+    let binary = std::env::temp_dir().join(format!("rtipc-{role}-{}", std::process::id()));
+
+    let status = Command::new(std::env::var_os("CC").unwrap_or("cc".into()))
+        .arg(&source)
+        .arg("-o")
+        .arg(&binary)
+        .arg("-I")
+        .arg(&include)
+        .arg("-L")
+        .arg(&lib)
+        .arg(format!("-Wl,-rpath,{}", lib.display()))
+        .arg(format!("-l{name}"))
+        .status()
+        .unwrap();
+
+    assert!(status.success(), "building {} failed", source.display());
+
+    binary
+}
+
+fn spawn_peer(binary: &Path, role: &str, path: &Path) -> Child {
+    /* cargo points LD_LIBRARY_PATH at the target dir, the rpath of the peer applies */
+    Command::new(binary)
+        .arg(role)
+        .arg(path)
+        .env_remove("LD_LIBRARY_PATH")
+        .spawn()
+        .unwrap()
+}
+
+/// Sends 3, 2, 1 and 0 to the peer, which echoes every value incremented.
+fn exchange(vector: &mut ChannelVector) {
+    let mut producer = vector.take_producer::<u64>(0).unwrap();
+    let mut consumer = vector.take_consumer::<u64>(0).unwrap();
+
+    for value in (0..=3).rev() {
+        *producer.current_message() = value;
+        assert!(producer.force_push() == ForcePushResult::Success);
+
+        let eventfd: BorrowedFd<'_> = consumer.eventfd().unwrap();
+        let mut fds = [PollFd::new(eventfd, PollFlags::POLLIN)];
+        assert_eq!(poll(&mut fds, PollTimeout::from(5000u16)).unwrap(), 1);
+
+        assert!(consumer.pop() == PopResult::Success);
+        assert_eq!(consumer.current_message(), Some(&(value + 1)));
+    }
+}
+
+#[test]
+fn c_clients_talk_to_rust_servers() {
+    let peer = build_peer("c-client");
+
+    let path = common::socket_path("c-client");
+    let server = Server::new(path.as_path(), Backlog::new(1).unwrap()).unwrap();
+
+    let mut child = spawn_peer(&peer, "client", &path);
+
+    /* a peer that gave up doesn't leave accept waiting */
+    let mut vector = loop {
+        match server.accept_nonblocking() {
+            Ok((vector, _)) => break vector,
+            Err(TransferError::WouldBlock) => {
+                assert!(child.try_wait().unwrap().is_none(), "peer exited");
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(e) => panic!("{e:?}"),
+        }
+    };
+
+    exchange(&mut vector);
+
+    assert!(child.wait().unwrap().success());
+    let _ = std::fs::remove_file(peer);
+}
+
+#[test]
+fn rust_clients_talk_to_c_servers() {
+    let peer = build_peer("c-server");
+
+    let path = common::socket_path("c-server");
+
+    let mut child = spawn_peer(&peer, "server", &path);
+
+    /* servers of librtipc speak the request layout before the TLV protocol */
+    let options = ConnectOptions {
+        legacy: true,
+        ..Default::default()
+    };
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+
+    let mut vector = loop {
+        match client_connect_with(path.as_path(), common::vector(1, 1, 1, 8), &options) {
+            Ok(vector) => break vector,
+            Err(e) if Instant::now() > deadline => panic!("peer not listening {e:?}"),
+            Err(_) => thread::sleep(Duration::from_millis(10)),
+        }
+    };
+
+    exchange(&mut vector);
+
+    assert!(child.wait().unwrap().success());
+    let _ = std::fs::remove_file(peer);
+}
@@ -0,0 +1,101 @@
+/* Echo peer of tests/librtipc.rs, built against a C implementation of include/rtipc.h.
+ *
+ * peer client|server <socket path>
+ *
+ * The vector of the client has a producer and a consumer of uint64_t with eventfds.
+ * Every value received is sent back incremented, the peer exits after echoing 0. */
+#include <poll.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "rtipc.h"
+
+static rtipc_vector *connect_client(const char *path)
+{
+    rtipc_vector_config *config = rtipc_vector_config_new();
+
+    if (!config)
+        return NULL;
+
+    rtipc_vector *vec = NULL;
+
+    if (rtipc_vector_config_add_producer(config, 1, sizeof(uint64_t), true) == 0 &&
+        rtipc_vector_config_add_consumer(config, 1, sizeof(uint64_t), true) == 0)
+        vec = rtipc_client_connect(path, config);
+
+    rtipc_vector_config_free(config);
+
+    return vec;
+}
+
+static rtipc_vector *accept_client(const char *path)
+{
+    rtipc_server *server = rtipc_server_new(path, 1);
+
+    if (!server)
+        return NULL;
+
+    rtipc_vector *vec = rtipc_server_accept(server);
+
+    rtipc_server_free(server);
+
+    return vec;
+}
+
+static int echo(rtipc_producer *producer, rtipc_consumer *consumer)
+{
+    for (;;) {
+        struct pollfd fd = { .fd = rtipc_consumer_eventfd(consumer), .events = POLLIN };
+
+        if (poll(&fd, 1, 5000) != 1)
+            return -1;
+
+        int result = rtipc_consumer_pop(consumer);
+
+        if (result == RTIPC_QUEUE_ERROR)
+            return -1;
+
+        if (result != RTIPC_POP_SUCCESS)
+            continue;
+
+        uint64_t value = *(const uint64_t *)rtipc_consumer_msg(consumer);
+
+        *(uint64_t *)rtipc_producer_msg(producer) = value + 1;
+
+        if (rtipc_producer_force_push(producer) == RTIPC_QUEUE_ERROR)
+            return -1;
+
+        if (value == 0)
+            return 0;
+    }
+}
+
+int main(int argc, char *argv[])
+{
+    if (argc != 3) {
+        fprintf(stderr, "usage: %s client|server <socket path>\n", argv[0]);
+        return 2;
+    }
+
+    rtipc_vector *vec = strcmp(argv[1], "server") == 0 ? accept_client(argv[2])
+                                                        : connect_client(argv[2]);
+
+    if (!vec) {
+        fprintf(stderr, "no vector\n");
+        return 1;
+    }
+
+    /* the channels of the vector are numbered from the point of view of each peer */
+    rtipc_producer *producer = rtipc_vector_take_producer(vec, 0);
+    rtipc_consumer *consumer = rtipc_vector_take_consumer(vec, 0);
+
+    rtipc_vector_free(vec);
+
+    int ret = producer && consumer ? echo(producer, consumer) : -1;
+
+    rtipc_producer_free(producer);
+    rtipc_consumer_free(consumer);
+
+    return ret == 0 ? 0 : 1;
+}
@@ -0,0 +1,50 @@
+use std::ffi::{c_char, c_int};
+use std::num::NonZeroUsize;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+
+use nix::{Result, errno::Errno};
+
+use crate::trace::*;
+
+#[link(name = "android")]
+unsafe extern "C" {
+    fn ASharedMemory_create(name: *const c_char, size: usize) -> c_int;
+    fn ASharedMemory_getSize(fd: c_int) -> usize;
+}
+
+/// Apps may not be allowed to create memfds, the shared memory is created with
+/// ASharedMemory of the NDK instead, backed by ashmem or by a memfd depending on the
+/// Android version. The size of an ashmem region can't change once it's mapped.
+pub fn shmfd_create(size: NonZeroUsize) -> Result<OwnedFd> {
+    let fd = unsafe { ASharedMemory_create(c"rtipc".as_ptr(), size.get()) };
+
+    if fd < 0 {
+        let e = Errno::last();
+        error!("ASharedMemory_create failed {e:?}");
+        return Err(e);
+    }
+
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Size of the shared memory created by ASharedMemory_create, None for other fds.
+/// fstat reports a size of 0 for ashmem regions.
+pub(crate) fn ashmem_size(fd: BorrowedFd<'_>) -> Option<NonZeroUsize> {
+    let size = unsafe { ASharedMemory_getSize(fd.as_raw_fd()) };
+
+    /* the -1 of a failed ioctl turns into usize::MAX */
+    NonZeroUsize::new(size).filter(|size| size.get() != usize::MAX)
+}
+
+/// Android has no /dev/shm, named shared memory isn't supported.
+pub(crate) fn shm_named_create(_size: NonZeroUsize) -> Result<(OwnedFd, String)> {
+    error!("named shared memory isn't supported on Android");
+    Err(Errno::EOPNOTSUPP)
+}
+
+pub(crate) fn shm_named_open(_name: &str) -> Result<OwnedFd> {
+    error!("named shared memory isn't supported on Android");
+    Err(Errno::EOPNOTSUPP)
+}
+
+pub(crate) fn shm_named_unlink(_name: &str) {}
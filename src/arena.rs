@@ -0,0 +1,257 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::ArenaConfig;
+use crate::Layout;
+use crate::error::*;
+use crate::mem_align;
+use crate::shm::{Chunk, Span};
+
+/* a block is owned by the side that allocated it until the peer frees it,
+ * the owner of the vector and its peer allocate with different states */
+const BLOCK_FREE: u32 = 0;
+const BLOCK_ALLOCATED_BY_OWNER: u32 = 1;
+const BLOCK_ALLOCATED_BY_PEER: u32 = 2;
+
+/* the upper half of a block state counts the allocations of the block,
+ * a handle of an earlier allocation doesn't match it anymore */
+const GENERATION_SHIFT: u32 = 16;
+const OWNER_MASK: u32 = (1 << GENERATION_SHIFT) - 1;
+
+/// Maximum number of blocks of an arena, the index takes 16 bits of a handle.
+pub const MAX_ARENA_BLOCKS: usize = 1 << 16;
+
+/// Reference to a block inside an [`Arena`].
+/// The handle is 8 bytes and `Copy`, so it can be sent through any channel.
+/// It packs the length, the generation and the index of the block.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct ArenaHandle(u64);
+
+impl ArenaHandle {
+    fn new(index: usize, generation: u16, len: usize) -> Result<Self, ArenaError> {
+        let index = u16::try_from(index).map_err(|_| ArenaError::HandleOverflow)?;
+        let len = u32::try_from(len).map_err(|_| ArenaError::HandleOverflow)?;
+        Ok(Self(
+            ((len as u64) << 32) | ((generation as u64) << 16) | index as u64,
+        ))
+    }
+
+    pub fn from_raw(raw: u64) -> Self {
+        Self(raw)
+    }
+
+    pub fn to_raw(self) -> u64 {
+        self.0
+    }
+
+    pub fn index(&self) -> usize {
+        (self.0 & u16::MAX as u64) as usize
+    }
+
+    /// Allocation of the block the handle was created by.
+    pub fn generation(&self) -> u16 {
+        (self.0 >> 16) as u16
+    }
+
+    pub fn len(&self) -> usize {
+        (self.0 >> 32) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Block allocator for large payloads. The arena is no region of its own, it's placed
+/// in the shared memory of the vector behind the channels and sized by its ArenaConfig.
+/// Blocks are allocated by one side, the handle is sent
+/// through a channel and the other side frees the block after reading it.
+/// Only the receiving side frees a block and resolves its handle, the allocating
+/// side writes it and releases blocks it didn't send.
+pub struct Arena {
+    _chunk: Chunk,
+    block_size: NonZeroUsize,
+    states: Vec<*mut u32>,
+    blocks: Vec<*mut u8>,
+    next: usize,
+    /// state of the blocks allocated by this side
+    allocated: u32,
+    /// state of the blocks allocated by the peer
+    received: u32,
+}
+
+impl Arena {
+    pub(crate) fn new(
+        chunk: Chunk,
+        config: &ArenaConfig,
+        layout: Layout,
+        owner: bool,
+    ) -> Result<Self, ShmMapError> {
+        let num_blocks = config.num_blocks.get();
+        let block_size =
+            NonZeroUsize::new(mem_align(config.block_size.get(), layout.cacheline_size)).unwrap();
+
+        let mut offset_state = 0;
+        let mut offset = mem_align(num_blocks * size_of::<u32>(), layout.cacheline_size);
+
+        let mut states: Vec<*mut u32> = Vec::with_capacity(num_blocks);
+        let mut blocks: Vec<*mut u8> = Vec::with_capacity(num_blocks);
+
+        for _ in 0..num_blocks {
+            let state: *mut u32 = chunk.get_ptr(offset_state)?;
+            let block: *mut () = chunk.get_span_ptr(&Span {
+                offset,
+                size: block_size,
+            })?;
+
+            states.push(state);
+            blocks.push(block.cast());
+
+            offset_state += size_of::<u32>();
+            offset += block_size.get();
+        }
+
+        let (allocated, received) = if owner {
+            (BLOCK_ALLOCATED_BY_OWNER, BLOCK_ALLOCATED_BY_PEER)
+        } else {
+            (BLOCK_ALLOCATED_BY_PEER, BLOCK_ALLOCATED_BY_OWNER)
+        };
+
+        Ok(Self {
+            _chunk: chunk,
+            block_size: config.block_size,
+            states,
+            blocks,
+            next: 0,
+            allocated,
+            received,
+        })
+    }
+
+    pub(crate) fn init(&self) {
+        for idx in 0..self.states.len() {
+            self.state(idx).store(BLOCK_FREE, Ordering::SeqCst);
+        }
+    }
+
+    fn state(&self, idx: usize) -> &AtomicU32 {
+        unsafe { AtomicU32::from_ptr(self.states[idx]) }
+    }
+
+    /// State of the block of handle while it is allocated by owner.
+    fn handle_state(handle: ArenaHandle, owner: u32) -> u32 {
+        ((handle.generation() as u32) << GENERATION_SHIFT) | owner
+    }
+
+    fn has_state(&self, handle: ArenaHandle, owner: u32) -> bool {
+        handle.index() < self.states.len()
+            && handle.len() <= self.block_size.get()
+            && self.state(handle.index()).load(Ordering::SeqCst)
+                == Self::handle_state(handle, owner)
+    }
+
+    fn set_free(&self, handle: ArenaHandle, owner: u32) -> bool {
+        if handle.index() >= self.states.len() {
+            return false;
+        }
+
+        /* the generation stays, the next allocation increments it */
+        let state = Self::handle_state(handle, owner);
+        let free = state & !OWNER_MASK;
+
+        self.state(handle.index())
+            .compare_exchange(state, free, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size.get()
+    }
+
+    pub fn num_blocks(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Allocates a block for a payload of `len` bytes.
+    /// Returns None if `len` exceeds the block size or all blocks are in use.
+    pub fn alloc(&mut self, len: usize) -> Option<ArenaHandle> {
+        self.try_alloc(len).ok()
+    }
+
+    /// Allocates a block for a payload of `len` bytes like alloc, with the reason of a failure.
+    pub fn try_alloc(&mut self, len: usize) -> Result<ArenaHandle, ArenaError> {
+        if len > self.block_size.get() {
+            return Err(ArenaError::PayloadTooLarge {
+                len,
+                block_size: self.block_size.get(),
+            });
+        }
+
+        let num_blocks = self.states.len();
+
+        for i in 0..num_blocks {
+            let idx = (self.next + i) % num_blocks;
+
+            let state = self.state(idx).load(Ordering::SeqCst);
+
+            if state & OWNER_MASK != BLOCK_FREE {
+                continue;
+            }
+
+            let generation = ((state >> GENERATION_SHIFT) as u16).wrapping_add(1);
+            let handle = ArenaHandle::new(idx, generation, len)?;
+
+            if self
+                .state(idx)
+                .compare_exchange(
+                    state,
+                    Self::handle_state(handle, self.allocated),
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .is_ok()
+            {
+                self.next = (idx + 1) % num_blocks;
+                return Ok(handle);
+            }
+        }
+
+        Err(ArenaError::Exhausted)
+    }
+
+    /// Payload of a block allocated by this side for writing, only valid before the handle is sent.
+    pub fn get_mut(&mut self, handle: ArenaHandle) -> Option<&mut [u8]> {
+        if !self.has_state(handle, self.allocated) {
+            return None;
+        }
+
+        let ptr = self.blocks[handle.index()];
+        Some(unsafe { std::slice::from_raw_parts_mut(ptr, handle.len()) })
+    }
+
+    /// Resolves a handle received from the peer to its payload.
+    pub fn get(&self, handle: ArenaHandle) -> Option<&[u8]> {
+        if !self.has_state(handle, self.received) {
+            return None;
+        }
+
+        let ptr = self.blocks[handle.index()];
+        Some(unsafe { std::slice::from_raw_parts(ptr, handle.len()) })
+    }
+
+    /// Returns a block received from the peer back to the arena.
+    /// Returns false if the handle doesn't reference a block allocated by the peer.
+    pub fn free(&mut self, handle: ArenaHandle) -> bool {
+        self.set_free(handle, self.received)
+    }
+
+    /// Returns a block allocated by this side and never sent back to the arena.
+    /// Returns false if the handle doesn't reference a block allocated by this side.
+    pub fn release(&mut self, handle: ArenaHandle) -> bool {
+        self.set_free(handle, self.allocated)
+    }
+}
+
+// the chunk of the arena in the vector's shared memory is used by no one else
+unsafe impl Send for Arena {}
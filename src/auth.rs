@@ -0,0 +1,193 @@
+use nix::errno::Errno;
+
+use crate::error::*;
+use crate::header::{verify_header, write_cookie};
+
+#[cfg(feature = "hmac")]
+use crate::{header::HEADER_SIZE, tlv::FLAG_CRITICAL, unix::random_u64};
+
+#[cfg(feature = "hmac")]
+use hmac::{Hmac, Mac};
+
+#[cfg(feature = "hmac")]
+type HmacSha256 = Hmac<sha2::Sha256>;
+
+/* last record of a signed message, HMAC-SHA256 over all preceding bytes */
+#[cfg(feature = "hmac")]
+const SIGNATURE_TAG: u16 = 0x7fff;
+
+#[cfg(feature = "hmac")]
+const SIGNATURE_SIZE: usize = 32;
+
+#[cfg(feature = "hmac")]
+const SIGNATURE_RECORD_SIZE: usize = 8 + SIGNATURE_SIZE;
+
+/* random u64 in front of the signature of a signed request, the signed replies
+ * to the request carry it as well, so a recorded reply can't answer another request */
+#[cfg(feature = "hmac")]
+const NONCE_TAG: u16 = 0x7ffe;
+
+#[cfg(feature = "hmac")]
+const NONCE_RECORD_SIZE: usize = 8 + size_of::<u64>();
+
+/// Stamps the cookie into handshake messages and signs them with a shared key.
+/// Without a key (or without the hmac feature) messages aren't signed.
+#[derive(Clone, Default)]
+pub(crate) struct Authenticator {
+    cookie: u32,
+    #[cfg(feature = "hmac")]
+    key: Option<Vec<u8>>,
+}
+
+impl Authenticator {
+    pub(crate) fn set_cookie(&mut self, cookie: u32) {
+        self.cookie = cookie;
+    }
+
+    #[cfg(feature = "hmac")]
+    pub(crate) fn set_key(&mut self, key: Option<Vec<u8>>) {
+        self.key = key;
+    }
+
+    /// Messages without a valid header are left to the parser,
+    /// only legacy responses have none.
+    fn check_cookie(&self, msg: &[u8]) -> Result<(), RequestError> {
+        let cookie = verify_header(msg).map_or(0, |h| h.cookie);
+
+        if cookie != self.cookie {
+            return Err(HeaderError::CookieMismatch.into());
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "hmac")]
+    fn mac(key: &[u8], msg: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+        mac.update(msg);
+        mac
+    }
+
+    /// Sets the cookie and appends the signature record to a message.
+    pub(crate) fn sign(&self, mut msg: Vec<u8>) -> Vec<u8> {
+        write_cookie(&mut msg, self.cookie);
+
+        #[cfg(feature = "hmac")]
+        if let Some(key) = &self.key {
+            msg.extend_from_slice(&SIGNATURE_TAG.to_le_bytes());
+            msg.extend_from_slice(&FLAG_CRITICAL.to_le_bytes());
+            msg.extend_from_slice(&(SIGNATURE_SIZE as u32).to_le_bytes());
+
+            let signature = Self::mac(key, &msg).finalize().into_bytes();
+            msg.extend_from_slice(&signature);
+            return msg;
+        }
+
+        msg
+    }
+
+    /// Signs a request like sign, with a key the request carries a new nonce.
+    #[allow(unused_mut)]
+    pub(crate) fn sign_request(&self, mut msg: Vec<u8>) -> Result<Vec<u8>, Errno> {
+        #[cfg(feature = "hmac")]
+        if self.key.is_some() {
+            msg.extend_from_slice(&NONCE_TAG.to_le_bytes());
+            msg.extend_from_slice(&FLAG_CRITICAL.to_le_bytes());
+            msg.extend_from_slice(&(size_of::<u64>() as u32).to_le_bytes());
+            msg.extend_from_slice(&random_u64()?.to_le_bytes());
+        }
+
+        Ok(self.sign(msg))
+    }
+
+    /// Signs a reply to request (a response or an ack), it carries the nonce of request.
+    pub(crate) fn sign_reply(&self, mut msg: Vec<u8>, request: &[u8]) -> Vec<u8> {
+        msg.extend_from_slice(self.nonce(request));
+        self.sign(msg)
+    }
+
+    /// Nonce record of a signed request, empty without a key.
+    #[allow(unused_variables)]
+    fn nonce<'a>(&self, request: &'a [u8]) -> &'a [u8] {
+        #[cfg(feature = "hmac")]
+        if self.key.is_some() {
+            let end = request.len().saturating_sub(SIGNATURE_RECORD_SIZE);
+            return &request[end.saturating_sub(NONCE_RECORD_SIZE)..end];
+        }
+
+        &[]
+    }
+
+    /// Verifies a request signed by sign_request, returns it without signature and nonce.
+    pub(crate) fn verify_request<'a>(&self, msg: &'a [u8]) -> Result<&'a [u8], RequestError> {
+        let content = self.verify(msg)?;
+
+        #[cfg(feature = "hmac")]
+        if self.key.is_some() {
+            let nonce = self.nonce(msg);
+
+            if nonce.len() != NONCE_RECORD_SIZE
+                || nonce[0..2] != NONCE_TAG.to_le_bytes()
+                || nonce[4..8] != (size_of::<u64>() as u32).to_le_bytes()
+            {
+                return Err(RequestError::MissingRecord(NONCE_TAG));
+            }
+
+            return Ok(&content[..content.len() - NONCE_RECORD_SIZE]);
+        }
+
+        Ok(content)
+    }
+
+    /// Verifies a reply to request signed by sign_reply, returns it without signature and nonce.
+    pub(crate) fn verify_reply<'a>(
+        &self,
+        msg: &'a [u8],
+        request: &[u8],
+    ) -> Result<&'a [u8], RequestError> {
+        let content = self.verify(msg)?;
+        let nonce = self.nonce(request);
+
+        /* the nonce is signed along with the reply */
+        let Some(content) = content.strip_suffix(nonce) else {
+            return Err(RequestError::NonceMismatch);
+        };
+
+        Ok(content)
+    }
+
+    /// Verifies the signature record and the cookie, returns the message without the signature.
+    pub(crate) fn verify<'a>(&self, msg: &'a [u8]) -> Result<&'a [u8], RequestError> {
+        let msg = self.verify_signature(msg)?;
+
+        self.check_cookie(msg)?;
+
+        Ok(msg)
+    }
+
+    fn verify_signature<'a>(&self, msg: &'a [u8]) -> Result<&'a [u8], RequestError> {
+        #[cfg(feature = "hmac")]
+        if let Some(key) = &self.key {
+            if msg.len() < HEADER_SIZE + SIGNATURE_RECORD_SIZE {
+                return Err(RequestError::SignatureMismatch);
+            }
+
+            let (signed, signature) = msg.split_at(msg.len() - SIGNATURE_SIZE);
+            let record = &signed[signed.len() - (SIGNATURE_RECORD_SIZE - SIGNATURE_SIZE)..];
+
+            if record[0..2] != SIGNATURE_TAG.to_le_bytes()
+                || record[4..8] != (SIGNATURE_SIZE as u32).to_le_bytes()
+            {
+                return Err(RequestError::SignatureMismatch);
+            }
+
+            Self::mac(key, signed)
+                .verify_slice(signature)
+                .map_err(|_| RequestError::SignatureMismatch)?;
+
+            return Ok(&msg[..msg.len() - SIGNATURE_RECORD_SIZE]);
+        }
+
+        Ok(msg)
+    }
+}
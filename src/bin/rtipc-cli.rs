@@ -0,0 +1,390 @@
+//! Inspection and test tool for commissioning rtipc links: connects with the vector of a
+//! config file, dumps what was negotiated, sends and receives raw messages and measures
+//! round trips against an echo server.
+
+use std::collections::BTreeMap;
+use std::os::fd::BorrowedFd;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use nix::sys::socket::Backlog;
+
+use rtipc::*;
+
+const USAGE: &str = "usage:
+    rtipc-cli info <address> <config>
+    rtipc-cli send <address> <config> <producer> <hex>...
+    rtipc-cli recv <address> <config> <consumer> [count]
+    rtipc-cli bench <address> <config> <producer> <consumer> [rounds]
+    rtipc-cli echo <path>
+
+address is the path of a unix socket or tcp:<host>:<port>, config a .json, .yaml or
+.toml file with the vector. echo serves clients on a unix socket and pushes every
+message of consumer i back on producer i, e.g. as the peer of bench.";
+
+/// time recv and bench wait for a message
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type CliResult = Result<(), String>;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let result = match args.as_slice() {
+        ["info", address, config] => info(address, config),
+        ["send", address, config, producer, hex @ ..] if !hex.is_empty() => {
+            send(address, config, producer, hex)
+        }
+        ["recv", address, config, consumer] => recv(address, config, consumer, "1"),
+        ["recv", address, config, consumer, count] => recv(address, config, consumer, count),
+        ["bench", address, config, producer, consumer] => {
+            bench(address, config, producer, consumer, "1000")
+        }
+        ["bench", address, config, producer, consumer, rounds] => {
+            bench(address, config, producer, consumer, rounds)
+        }
+        ["echo", path] => echo(path),
+        _ => Err(USAGE.to_owned()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn connect(address: &str, config: &str) -> Result<(ChannelVector, VectorConfig), String> {
+    let vconfig = VectorConfig::load(config).map_err(|e| format!("{config}: {e:?}"))?;
+
+    let vector = match address.strip_prefix("tcp:") {
+        Some(addr) => client_connect_tcp(addr, vconfig.clone(), &ConnectOptions::default()),
+        None => client_connect(address, vconfig.clone()),
+    }
+    .map_err(|e| format!("{address}: {e:?}"))?;
+
+    Ok((vector, vconfig))
+}
+
+fn index(arg: &str, channels: &[ChannelConfig], what: &str) -> Result<usize, String> {
+    arg.parse()
+        .ok()
+        .filter(|&i| i < channels.len())
+        .ok_or_else(|| format!("no {what} {arg}, the vector has {}", channels.len()))
+}
+
+fn printable(info: &[u8]) -> String {
+    match std::str::from_utf8(info) {
+        Ok(text) if text.chars().all(|c| !c.is_control()) => format!("{text:?}"),
+        _ => to_hex(info),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn from_hex(args: &[&str]) -> Result<Vec<u8>, String> {
+    let digits: String = args
+        .concat()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits in {digits}"));
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|e| format!("{digits}: {e}")))
+        .collect()
+}
+
+fn info(address: &str, config: &str) -> CliResult {
+    let (vector, vconfig) = connect(address, config)?;
+
+    let report = vector.memory_report();
+
+    println!("vector info {}", printable(vector.info()));
+    println!("server info {}", printable(vector.server_info()));
+    if let Some(token) = vector.session_token() {
+        println!("session {token:#x}");
+    }
+    println!(
+        "shared memory {} bytes, {} mapped, {} locked",
+        vector.total_shm_size(),
+        report.mapped,
+        report.locked
+    );
+
+    let channels = [
+        ("producer", &vconfig.producers, &report.producers),
+        ("consumer", &vconfig.consumers, &report.consumers),
+    ];
+
+    for (what, configs, usage) in channels {
+        for (i, (config, usage)) in configs.iter().zip(usage.iter()).enumerate() {
+            let info = match what {
+                "producer" => vector.producer_info(i),
+                _ => vector.consumer_info(i),
+            };
+
+            println!(
+                "{what} {i}: {:?}, message {} bytes, {} slots of {} bytes, {} bytes shm, \
+                 eventfd {}, schema {}, info {}",
+                usage.kind,
+                config.queue.message_size,
+                usage.slots,
+                usage.slot_size,
+                usage.shm_size,
+                config.eventfd,
+                config
+                    .queue
+                    .schema
+                    .map_or("-".to_owned(), |s| format!("{s:#018x}")),
+                info.map_or("-".to_owned(), |info| printable(info)),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Slot of the current message, the slot holds at least size bytes.
+fn producer_slot(producer: &mut Producer<u8>, size: usize) -> &mut [u8] {
+    let ptr: *mut u8 = producer.current_message();
+    unsafe { std::slice::from_raw_parts_mut(ptr, size) }
+}
+
+fn consumer_slot(consumer: &Consumer<u8>, size: usize) -> Option<&[u8]> {
+    let ptr: *const u8 = consumer.current_message()?;
+    Some(unsafe { std::slice::from_raw_parts(ptr, size) })
+}
+
+fn send(address: &str, config: &str, producer: &str, hex: &[&str]) -> CliResult {
+    let (mut vector, vconfig) = connect(address, config)?;
+
+    let index = index(producer, &vconfig.producers, "producer")?;
+    let size = vconfig.producers[index].queue.message_size.get();
+
+    let msg = from_hex(hex)?;
+    if msg.len() > size {
+        return Err(format!(
+            "{} bytes exceed the message size {size}",
+            msg.len()
+        ));
+    }
+
+    let mut producer = vector
+        .take_producer::<u8>(index)
+        .ok_or_else(|| format!("producer {index} isn't a queue"))?;
+
+    let slot = producer_slot(&mut producer, size);
+    slot[..msg.len()].copy_from_slice(&msg);
+    slot[msg.len()..].fill(0);
+
+    match producer.try_push() {
+        TryPushResult::Success => Ok(()),
+        TryPushResult::QueueFull => Err("queue full".to_owned()),
+        TryPushResult::QueueError => Err("queue error".to_owned()),
+    }
+}
+
+/// Waits for the next message, on the eventfd if there is one.
+fn wait(consumer: &mut Consumer<u8>, deadline: Instant) -> Result<(), String> {
+    loop {
+        match consumer.pop() {
+            PopResult::Success | PopResult::SuccessMessagesDiscarded => return Ok(()),
+            PopResult::QueueError => return Err("queue error".to_owned()),
+            PopResult::NoMessage | PopResult::NoNewMessage => {}
+        }
+
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err("timeout".to_owned());
+        }
+
+        if let Some(eventfd) = consumer.eventfd() {
+            wait_pollin(eventfd, left)?;
+        } else {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+fn wait_pollin(fd: BorrowedFd<'_>, timeout: Duration) -> Result<(), String> {
+    let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+    let timeout = PollTimeout::try_from(timeout).unwrap_or(PollTimeout::MAX);
+
+    match poll(&mut fds, timeout) {
+        Ok(_) | Err(nix::errno::Errno::EINTR) => Ok(()),
+        Err(e) => Err(format!("poll: {e}")),
+    }
+}
+
+fn recv(address: &str, config: &str, consumer: &str, count: &str) -> CliResult {
+    let (mut vector, vconfig) = connect(address, config)?;
+
+    let index = index(consumer, &vconfig.consumers, "consumer")?;
+    let size = vconfig.consumers[index].queue.message_size.get();
+    let count: usize = count
+        .parse()
+        .map_err(|_| format!("invalid count {count}"))?;
+
+    let mut consumer = vector
+        .take_consumer::<u8>(index)
+        .ok_or_else(|| format!("consumer {index} isn't a queue"))?;
+
+    for _ in 0..count {
+        wait(&mut consumer, Instant::now() + TIMEOUT)?;
+
+        let slot = consumer_slot(&consumer, size).ok_or("queue error")?;
+        println!("{}", to_hex(slot));
+    }
+
+    Ok(())
+}
+
+fn bench(address: &str, config: &str, producer: &str, consumer: &str, rounds: &str) -> CliResult {
+    let (mut vector, vconfig) = connect(address, config)?;
+
+    let pindex = index(producer, &vconfig.producers, "producer")?;
+    let cindex = index(consumer, &vconfig.consumers, "consumer")?;
+    let rounds: usize = rounds
+        .parse()
+        .ok()
+        .filter(|&r| r > 0)
+        .ok_or_else(|| format!("invalid rounds {rounds}"))?;
+
+    let psize = vconfig.producers[pindex].queue.message_size.get();
+    let csize = vconfig.consumers[cindex].queue.message_size.get();
+    /* the sequence number of a round, truncated to the smaller message */
+    let seq_size = psize.min(csize).min(size_of::<u64>());
+
+    let mut producer = vector
+        .take_producer::<u8>(pindex)
+        .ok_or_else(|| format!("producer {pindex} isn't a queue"))?;
+    let mut consumer = vector
+        .take_consumer::<u8>(cindex)
+        .ok_or_else(|| format!("consumer {cindex} isn't a queue"))?;
+
+    let mut samples = Vec::with_capacity(rounds);
+
+    for round in 0..rounds as u64 {
+        let seq = &round.to_le_bytes()[..seq_size];
+
+        let slot = producer_slot(&mut producer, psize);
+        slot.fill(0);
+        slot[..seq_size].copy_from_slice(seq);
+
+        let start = Instant::now();
+        producer.force_push();
+
+        let deadline = start + TIMEOUT;
+
+        /* skip stale echoes of earlier rounds */
+        loop {
+            wait(&mut consumer, deadline).map_err(|e| format!("round {round}: {e}"))?;
+            let slot = consumer_slot(&consumer, csize).ok_or("queue error")?;
+            if &slot[..seq_size] == seq {
+                break;
+            }
+        }
+
+        samples.push(start.elapsed());
+    }
+
+    samples.sort();
+
+    let total: Duration = samples.iter().sum();
+    let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+
+    println!(
+        "{rounds} round trips: min {:?}, avg {:?}, p50 {:?}, p99 {:?}, max {:?}",
+        samples[0],
+        total / rounds as u32,
+        percentile(50),
+        percentile(99),
+        samples[rounds - 1]
+    );
+
+    Ok(())
+}
+
+/// consumer i of a client, producer i and the bytes copied between their slots
+type Echo = (Consumer<u8>, Producer<u8>, usize);
+
+fn echo(path: &str) -> CliResult {
+    let server =
+        Server::new(path, Backlog::new(16).unwrap()).map_err(|e| format!("{path}: {e}"))?;
+    let mut server = ServerLoop::new(server);
+
+    let mut echoes: BTreeMap<ClientId, Vec<Echo>> = BTreeMap::new();
+
+    loop {
+        let idle = echoes.is_empty();
+        let timeout = if idle { None } else { Some(Duration::ZERO) };
+
+        for event in server.poll(timeout).map_err(|e| format!("poll: {e}"))? {
+            match event {
+                ServerEvent::Connected(id) => {
+                    let Some(client) = server.client(id) else {
+                        continue;
+                    };
+
+                    let vector = client.vector();
+                    let report = vector.memory_report();
+
+                    let pairs: Vec<Echo> = (0..report.consumers.len().min(report.producers.len()))
+                        .filter_map(|i| {
+                            let size = report.consumers[i]
+                                .slot_size
+                                .min(report.producers[i].slot_size);
+                            let consumer = vector.take_consumer::<u8>(i)?;
+                            let producer = vector.take_producer::<u8>(i)?;
+                            Some((consumer, producer, size))
+                        })
+                        .collect();
+
+                    println!("client {id:?} connected, echoing {} channels", pairs.len());
+                    echoes.insert(id, pairs);
+                }
+                ServerEvent::Disconnected(id) => {
+                    println!("client {id:?} disconnected");
+                    echoes.remove(&id);
+                }
+                ServerEvent::AcceptFailed(e) => eprintln!("handshake failed: {e:?}"),
+                ServerEvent::Message(..) => {}
+            }
+        }
+
+        /* a burst of polls between checks of the listener and the control connections */
+        for _ in 0..1000 {
+            for (consumer, producer, size) in echoes.values_mut().flatten() {
+                if !matches!(
+                    consumer.pop(),
+                    PopResult::Success | PopResult::SuccessMessagesDiscarded
+                ) {
+                    continue;
+                }
+
+                let Some(msg) = consumer_slot(consumer, *size) else {
+                    continue;
+                };
+
+                let msg = msg.to_vec();
+                producer_slot(producer, *size).copy_from_slice(&msg);
+                producer.force_push();
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::mem::MaybeUninit;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering, fence};
+
+use crate::Layout;
+use crate::MIN_MSGS;
+use crate::QueueConfig;
+use crate::error::*;
+use crate::mem_align;
+use crate::shm::{Chunk, Span};
+
+/// Ring for a single producer and any number of consumers.
+/// Consumers never write to shared memory, each consumer keeps its own cursor.
+/// Every slot has a sequence: 2 * pos + 1 while the producer writes message pos,
+/// 2 * pos + 2 when message pos is complete. head is the number of complete messages.
+pub(crate) struct BroadcastQueue {
+    _chunk: Chunk,
+    message_size: NonZeroUsize,
+    head: *mut u64,
+    seqs: Vec<*mut u64>,
+    messages: Vec<*mut ()>,
+}
+
+impl BroadcastQueue {
+    pub(crate) fn new(
+        chunk: Chunk,
+        config: &QueueConfig,
+        layout: Layout,
+    ) -> Result<Self, ShmMapError> {
+        let queue_len = config.additional_messages + MIN_MSGS;
+        let message_size =
+            NonZeroUsize::new(mem_align(config.message_size.get(), layout.cacheline_size)).unwrap();
+
+        let mut offset_seq = 0;
+        let mut offset = mem_align((1 + queue_len) * size_of::<u64>(), layout.cacheline_size);
+
+        let head: *mut u64 = chunk.get_ptr(offset_seq)?;
+        offset_seq += size_of::<u64>();
+
+        if !head.is_aligned() {
+            return Err(ShmMapError::Misalignment);
+        }
+
+        let mut seqs: Vec<*mut u64> = Vec::with_capacity(queue_len);
+        let mut messages: Vec<*mut ()> = Vec::with_capacity(queue_len);
+
+        for _ in 0..queue_len {
+            let seq: *mut u64 = chunk.get_ptr(offset_seq)?;
+            let message: *mut () = chunk.get_span_ptr(&Span {
+                offset,
+                size: message_size,
+            })?;
+
+            seqs.push(seq);
+            messages.push(message);
+
+            offset_seq += size_of::<u64>();
+            offset += message_size.get();
+        }
+
+        Ok(Self {
+            _chunk: chunk,
+            message_size,
+            head,
+            seqs,
+            messages,
+        })
+    }
+
+    pub(crate) fn shm_size(config: &QueueConfig, layout: Layout) -> NonZeroUsize {
+        let n = MIN_MSGS + config.additional_messages;
+        let size = mem_align((1 + n) * size_of::<u64>(), layout.cacheline_size)
+            + n * mem_align(config.message_size.get(), layout.cacheline_size);
+        NonZeroUsize::new(size).unwrap()
+    }
+
+    pub(crate) fn init(&self) {
+        self.head().store(0, Ordering::SeqCst);
+
+        for idx in 0..self.len() {
+            self.seq(idx).store(0, Ordering::SeqCst);
+        }
+    }
+
+    pub(crate) fn message_size(&self) -> NonZeroUsize {
+        self.message_size
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.seqs.len()
+    }
+
+    fn head(&self) -> &AtomicU64 {
+        unsafe { AtomicU64::from_ptr(self.head) }
+    }
+
+    fn seq(&self, idx: usize) -> &AtomicU64 {
+        unsafe { AtomicU64::from_ptr(self.seqs[idx]) }
+    }
+
+    fn slot(&self, pos: u64) -> usize {
+        (pos % self.len() as u64) as usize
+    }
+
+    pub(crate) fn head_load(&self) -> u64 {
+        self.head().load(Ordering::Acquire)
+    }
+
+    /// Marks the slot of message pos as being written.
+    pub(crate) fn begin_write(&self, pos: u64) {
+        self.seq(self.slot(pos))
+            .store(2 * pos + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+    }
+
+    /// Publishes message pos.
+    pub(crate) fn end_write(&self, pos: u64) {
+        self.seq(self.slot(pos))
+            .store(2 * pos + 2, Ordering::Release);
+        self.head().store(pos + 1, Ordering::Release);
+    }
+
+    pub(crate) fn message(&self, pos: u64) -> *mut () {
+        self.messages[self.slot(pos)]
+    }
+
+    /// Copies message pos, returns None if the producer already overwrote it.
+    /// pos follows the head in shared memory, the sequence wraps instead of overflowing.
+    pub(crate) fn read<T: Copy>(&self, pos: u64) -> Option<T> {
+        let slot = self.slot(pos);
+        let expected = pos.wrapping_mul(2).wrapping_add(2);
+
+        if self.seq(slot).load(Ordering::Acquire) != expected {
+            return None;
+        }
+
+        let mut val = MaybeUninit::<T>::uninit();
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                self.messages[slot].cast::<u8>(),
+                val.as_mut_ptr().cast::<u8>(),
+                size_of::<T>(),
+            );
+        }
+
+        fence(Ordering::Acquire);
+
+        if self.seq(slot).load(Ordering::Relaxed) != expected {
+            return None;
+        }
+
+        Some(unsafe { val.assume_init() })
+    }
+}
+
+// every BroadcastQueue has its own shared memory region
+unsafe impl Send for BroadcastQueue {}
+// consumers only read from shared memory
+unsafe impl Sync for BroadcastQueue {}
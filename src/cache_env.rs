@@ -1,21 +1,10 @@
-use std::sync::atomic::{AtomicUsize, Ordering};
-
-use crate::log::*;
-
-pub(crate) fn max_cacheline_size() -> usize {
-    static CLS: AtomicUsize = AtomicUsize::new(0);
-
-    let mut cls = CLS.load(Ordering::Relaxed);
-
-    if cls != 0 {
-        return cls;
-    }
+use crate::cacheline::CachelineSource;
 
+/// Cache line size of the target, set by CACHELINE_SIZE at compile time.
+pub(crate) fn detect_cacheline_size() -> (usize, CachelineSource) {
     let cls_str = env!("CACHELINE_SIZE");
-    cls = cls_str.parse::<usize>().unwrap();
-
-    CLS.store(cls, Ordering::Relaxed);
-
-    info!("cache line size = {cls}");
-    cls
+    (
+        cls_str.parse::<usize>().unwrap(),
+        CachelineSource::Predefined,
+    )
 }
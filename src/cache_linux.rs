@@ -1,8 +1,8 @@
 use std::fs::read_to_string;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::log::*;
+use crate::cacheline::CachelineSource;
+use crate::trace::*;
 
 #[derive(Debug, PartialEq, Eq)]
 enum CacheType {
@@ -57,33 +57,102 @@ fn read_cache(cpu: usize, index: usize) -> Result<Cache, std::io::Error> {
     })
 }
 
-pub fn max_cacheline_size() -> usize {
-    static CLS: AtomicUsize = AtomicUsize::new(0);
+/// Parses a cpu list like /sys/devices/system/cpu/online, e.g. "0-3,6,8-9".
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
 
-    let mut cls = CLS.load(Ordering::Relaxed);
-
-    if cls != 0 {
-        return cls;
+    for range in list.trim_end().split(',') {
+        match range.split_once('-') {
+            Some((first, last)) => {
+                let first = first.parse::<usize>().ok()?;
+                let last = last.parse::<usize>().ok()?;
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(range.parse::<usize>().ok()?),
+        }
     }
 
-    // TODO: replace this with max_align_t
-    cls = std::mem::align_of::<f64>();
+    Some(cpus)
+}
 
-    for index in 0..4 {
-        if let Ok(cache) = read_cache(0, index) {
-            if cache.cache_type != CacheType::Data {
-                continue;
-            }
-            if cache.level > 2 {
+fn online_cpus() -> Vec<usize> {
+    read_to_string("/sys/devices/system/cpu/online")
+        .ok()
+        .and_then(|list| parse_cpu_list(&list))
+        .unwrap_or_else(|| vec![0])
+}
+
+/// Largest cache line of the L1 and L2 data caches of all online cpus,
+/// the cores of hybrid systems may have different cache lines.
+fn sysfs_cacheline_size() -> Option<usize> {
+    let mut max = None;
+
+    for cpu in online_cpus() {
+        /* the indices are contiguous, the first missing one ends the caches of the cpu */
+        for index in 0.. {
+            let Ok(cache) = read_cache(cpu, index) else {
+                break;
+            };
+
+            if cache.cache_type != CacheType::Data || cache.level > 2 {
                 continue;
             }
-            if cache.cls > cls {
-                cls = cache.cls;
-            }
+
+            max = max.max(Some(cache.cls));
         }
     }
 
-    CLS.store(cls, Ordering::Relaxed);
-    info!("cache line size = {cls}");
-    cls
+    max
+}
+
+#[cfg(any(all(target_os = "linux", target_env = "gnu"), target_os = "android"))]
+fn sysconf_cacheline_size() -> Option<usize> {
+    let size = unsafe { nix::libc::sysconf(nix::libc::_SC_LEVEL1_DCACHE_LINESIZE) };
+    usize::try_from(size).ok().filter(|&size| size > 0)
+}
+
+#[cfg(target_os = "macos")]
+fn sysconf_cacheline_size() -> Option<usize> {
+    crate::macos::sysctl_cacheline_size()
+}
+
+#[cfg(not(any(
+    all(target_os = "linux", target_env = "gnu"),
+    target_os = "android",
+    target_os = "macos"
+)))]
+fn sysconf_cacheline_size() -> Option<usize> {
+    None
+}
+
+/// Cache line size of the machine and where it was found.
+pub(crate) fn detect_cacheline_size() -> (usize, CachelineSource) {
+    // TODO: replace this with max_align_t
+    let min = std::mem::align_of::<f64>();
+
+    if let Some(cls) = sysfs_cacheline_size() {
+        return (cls.max(min), CachelineSource::Sysfs);
+    }
+
+    if let Some(cls) = sysconf_cacheline_size() {
+        return (cls.max(min), CachelineSource::Sysconf);
+    }
+
+    (min, CachelineSource::Default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_lists_are_parsed() {
+        assert_eq!(parse_cpu_list("0\n"), Some(vec![0]));
+        assert_eq!(
+            parse_cpu_list("0-3,6,8-9\n"),
+            Some(vec![0, 1, 2, 3, 6, 8, 9])
+        );
+        assert_eq!(parse_cpu_list("0-x"), None);
+        assert_eq!(parse_cpu_list(""), None);
+    }
 }
@@ -0,0 +1,128 @@
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::*;
+use crate::mem_align;
+use crate::trace::*;
+
+#[cfg(feature = "predefined_cacheline_size")]
+use crate::cache_env::detect_cacheline_size;
+#[cfg(not(feature = "predefined_cacheline_size"))]
+use crate::cache_linux::detect_cacheline_size;
+
+/// Environment variable overriding the detected cache line size, e.g. for a container
+/// image running on hosts unknown at build time.
+pub const CACHELINE_SIZE_VAR: &str = "RTIPC_CACHELINE_SIZE";
+
+/// Largest cache line size accepted, the headers of the handshake carry it in a u16.
+pub const MAX_CACHELINE_SIZE: usize = 4096;
+
+/// Where max_cacheline_size got its value from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CachelineSource {
+    /// set_cacheline_size
+    Override,
+    /// CACHELINE_SIZE_VAR
+    Environment,
+    /// CACHELINE_SIZE at compile time, the predefined_cacheline_size feature
+    Predefined,
+    /// the caches of the online cpus in /sys/devices/system/cpu
+    Sysfs,
+    /// sysconf(_SC_LEVEL1_DCACHE_LINESIZE), the hw.cachelinesize sysctl on macOS
+    Sysconf,
+    /// nothing found, the alignment of f64
+    Default,
+}
+
+impl CachelineSource {
+    fn from_raw(raw: usize) -> Self {
+        match raw {
+            0 => Self::Override,
+            1 => Self::Environment,
+            2 => Self::Predefined,
+            3 => Self::Sysfs,
+            4 => Self::Sysconf,
+            _ => Self::Default,
+        }
+    }
+}
+
+/* 0 until set_cacheline_size or the first max_cacheline_size */
+static CLS: AtomicUsize = AtomicUsize::new(0);
+static SOURCE: AtomicUsize = AtomicUsize::new(CachelineSource::Default as usize);
+
+fn store(size: usize, source: CachelineSource) {
+    SOURCE.store(source as usize, Ordering::Relaxed);
+    CLS.store(size, Ordering::Relaxed);
+    info!("cache line size = {size} ({source:?})");
+}
+
+/// Whether size is a power of two between the alignment of f64 and MAX_CACHELINE_SIZE.
+pub(crate) fn is_valid(size: usize) -> bool {
+    size.is_power_of_two() && (std::mem::align_of::<f64>()..=MAX_CACHELINE_SIZE).contains(&size)
+}
+
+/// Detected cache line size, the alignment of f64 if the machine reports an invalid one.
+fn detect() -> (usize, CachelineSource) {
+    let (size, source) = detect_cacheline_size();
+
+    if is_valid(size) {
+        return (size, source);
+    }
+
+    error!("{source:?} reports {size} as cache line size");
+    (std::mem::align_of::<f64>(), CachelineSource::Default)
+}
+
+fn env_cacheline_size() -> Option<usize> {
+    let value = env::var(CACHELINE_SIZE_VAR).ok()?;
+
+    match value.parse::<usize>() {
+        Ok(size) if is_valid(size) => Some(size),
+        _ => {
+            error!("{CACHELINE_SIZE_VAR}={value} isn't a valid cache line size");
+            None
+        }
+    }
+}
+
+/// Cache line size the shared memory is aligned to. Set by set_cacheline_size,
+/// otherwise taken from CACHELINE_SIZE_VAR or detected once.
+pub fn max_cacheline_size() -> usize {
+    let cls = CLS.load(Ordering::Relaxed);
+
+    if cls != 0 {
+        return cls;
+    }
+
+    let (cls, source) = env_cacheline_size()
+        .map(|cls| (cls, CachelineSource::Environment))
+        .unwrap_or_else(detect);
+
+    store(cls, source);
+    cls
+}
+
+/// Source of the value of max_cacheline_size.
+pub fn cacheline_source() -> CachelineSource {
+    max_cacheline_size();
+    CachelineSource::from_raw(SOURCE.load(Ordering::Relaxed))
+}
+
+/// Overrides the detected cache line size, has to be called before the first vector
+/// is created. Fails unless size is a power of two of at least the alignment of f64
+/// and at most MAX_CACHELINE_SIZE.
+pub fn set_cacheline_size(size: usize) -> Result<(), ResourceError> {
+    if !is_valid(size) {
+        error!("{size} isn't a valid cache line size");
+        return Err(ResourceError::InvalidArgument);
+    }
+
+    store(size, CachelineSource::Override);
+    Ok(())
+}
+
+/// size rounded up to whole cache lines.
+pub fn cacheline_aligned(size: usize) -> usize {
+    mem_align(size, max_cacheline_size())
+}
@@ -0,0 +1,209 @@
+use std::os::fd::BorrowedFd;
+use std::ptr::NonNull;
+
+use capnp::message::{self, Allocator, HeapAllocator, ReaderOptions, ReaderSegments};
+
+use crate::channel::{ChannelVector, Consumer, Producer};
+use crate::queue::{ForcePushResult, PopResult, TryPushResult};
+
+/// u32 number of words used by the message in native byte order and 4 reserved bytes,
+/// in front of the segment of a slot
+const SLOT_HEADER_SIZE: usize = 8;
+
+const BYTES_PER_WORD: usize = 8;
+
+/// Allocates the single segment of a Cap'n Proto message in the slot of a CapnpProducer.
+/// Further segments of a message exceeding the slot come from the heap,
+/// CapnpProducer::build refuses such a message.
+pub struct SlotAllocator<'a> {
+    slot: &'a mut [u8],
+    allocated: bool,
+    /// words requested beyond the slot
+    spilled: usize,
+    heap: HeapAllocator,
+}
+
+impl<'a> SlotAllocator<'a> {
+    /// The slot holds an empty message until the builder is dropped.
+    fn new(slot: &'a mut [u8]) -> Self {
+        let mut allocator = Self {
+            slot,
+            allocated: false,
+            spilled: 0,
+            heap: HeapAllocator::new(),
+        };
+
+        allocator.clear();
+
+        allocator
+    }
+
+    /// Leaves an empty message, a null root pointer, in the slot.
+    fn clear(&mut self) {
+        let segment = self.segment();
+        let words = segment.len().min(BYTES_PER_WORD);
+
+        segment[..words].fill(0);
+
+        self.set_words_used((words / BYTES_PER_WORD) as u32);
+    }
+
+    fn segment(&mut self) -> &mut [u8] {
+        let words = (self.slot.len() - SLOT_HEADER_SIZE) / BYTES_PER_WORD;
+        &mut self.slot[SLOT_HEADER_SIZE..][..words * BYTES_PER_WORD]
+    }
+
+    fn set_words_used(&mut self, words: u32) {
+        self.slot[..4].copy_from_slice(&words.to_ne_bytes());
+        self.slot[4..SLOT_HEADER_SIZE].fill(0);
+    }
+}
+
+unsafe impl Allocator for SlotAllocator<'_> {
+    fn allocate_segment(&mut self, minimum_size: u32) -> (NonNull<u8>, u32) {
+        let words = self.segment().len() / BYTES_PER_WORD;
+
+        if self.allocated || minimum_size as usize > words {
+            self.spilled += minimum_size as usize;
+            return self.heap.allocate_segment(minimum_size);
+        }
+
+        self.allocated = true;
+
+        let segment = self.segment();
+
+        /* the slot still holds an earlier message, segments have to be zeroed */
+        segment.fill(0);
+
+        (NonNull::from(segment).cast(), words as u32)
+    }
+
+    unsafe fn deallocate_segment(&mut self, ptr: NonNull<u8>, word_size: u32, words_used: u32) {
+        if ptr.as_ptr() == self.segment().as_mut_ptr() {
+            self.set_words_used(words_used);
+        } else {
+            unsafe { self.heap.deallocate_segment(ptr, word_size, words_used) };
+        }
+    }
+}
+
+/// The segment of a message in the slot of a CapnpConsumer.
+pub struct SlotSegment<'a>(&'a [u8]);
+
+impl ReaderSegments for SlotSegment<'_> {
+    fn get_segment(&self, idx: u32) -> Option<&[u8]> {
+        (idx == 0).then_some(self.0)
+    }
+
+    fn len(&self) -> usize {
+        1
+    }
+}
+
+/// Producer of a queue carrying Cap'n Proto messages, the message is built in place in
+/// the slot of the queue. A slot holds a single segment behind an 8 byte header, the
+/// message size of the channel bounds the size of the messages.
+pub struct CapnpProducer {
+    producer: Producer<u64>,
+}
+
+impl CapnpProducer {
+    /// Builds the current message with build, e.g. by init_root of the builder,
+    /// and records its size once build returns. The message isn't pushed yet.
+    /// A message exceeding the slot is refused and the slot is left empty.
+    pub fn build<R>(
+        &mut self,
+        build: impl FnOnce(&mut message::Builder<&mut SlotAllocator<'_>>) -> R,
+    ) -> capnp::Result<R> {
+        let mut allocator = SlotAllocator::new(self.producer.message_slot());
+
+        let result = build(&mut message::Builder::new(&mut allocator));
+
+        if allocator.spilled > 0 {
+            let size =
+                (allocator.segment().len() / BYTES_PER_WORD + allocator.spilled) * BYTES_PER_WORD;
+            allocator.clear();
+            return Err(capnp::Error::failed(format!(
+                "message of {size} bytes exceeds the slot"
+            )));
+        }
+
+        Ok(result)
+    }
+
+    pub fn force_push(&mut self) -> ForcePushResult {
+        self.producer.force_push()
+    }
+
+    pub fn try_push(&mut self) -> TryPushResult {
+        self.producer.try_push()
+    }
+
+    pub fn eventfd(&self) -> Option<BorrowedFd<'_>> {
+        self.producer.eventfd()
+    }
+}
+
+/// Consumer of a queue carrying Cap'n Proto messages, the message is read in place.
+pub struct CapnpConsumer {
+    consumer: Consumer<u64>,
+    options: ReaderOptions,
+}
+
+impl CapnpConsumer {
+    /// Reader of the current message, get_root of the reader gives the typed view.
+    /// The reader borrows the slot, it has to be dropped before the next pop.
+    pub fn reader(&self) -> Option<message::Reader<SlotSegment<'_>>> {
+        let slot = self.consumer.message_slot()?;
+
+        let words = u32::from_ne_bytes(slot[..4].try_into().unwrap()) as usize;
+
+        /* a corrupt size is caught by the bounds checks of the reader */
+        let segment = &slot[SLOT_HEADER_SIZE..];
+        let len = (words * BYTES_PER_WORD).min(segment.len() / BYTES_PER_WORD * BYTES_PER_WORD);
+
+        Some(message::Reader::new(
+            SlotSegment(&segment[..len]),
+            self.options,
+        ))
+    }
+
+    /// Limits of the reader, e.g. the traversal limit for messages of an untrusted peer.
+    pub fn set_reader_options(&mut self, options: ReaderOptions) {
+        self.options = options;
+    }
+
+    pub fn pop(&mut self) -> PopResult {
+        self.consumer.pop()
+    }
+
+    /// See Consumer::pop_spin.
+    pub fn pop_spin(&mut self, polls: u32) -> PopResult {
+        self.consumer.pop_spin(polls)
+    }
+
+    pub fn flush(&mut self) -> PopResult {
+        self.consumer.flush()
+    }
+
+    pub fn eventfd(&self) -> Option<BorrowedFd<'_>> {
+        self.consumer.eventfd()
+    }
+}
+
+impl ChannelVector {
+    /// Takes a queue producer for Cap'n Proto messages,
+    /// the message size of the channel has to hold the 8 byte slot header.
+    pub fn take_capnp_producer(&mut self, index: usize) -> Option<CapnpProducer> {
+        let producer = self.take_producer::<u64>(index)?;
+        Some(CapnpProducer { producer })
+    }
+
+    pub fn take_capnp_consumer(&mut self, index: usize) -> Option<CapnpConsumer> {
+        let consumer = self.take_consumer::<u64>(index)?;
+        Some(CapnpConsumer {
+            consumer,
+            options: ReaderOptions::new(),
+        })
+    }
+}
@@ -2,37 +2,76 @@ use std::{
     borrow::BorrowMut,
     marker::PhantomData,
     mem::size_of,
+    num::NonZeroUsize,
     os::fd::{AsFd, BorrowedFd},
+    sync::{Arc, atomic::Ordering},
 };
 
-use nix::sys::eventfd::EventFd;
+use nix::errno::Errno;
 
+#[cfg(feature = "socket")]
+use crate::control::{Control, ControlMessage};
 use crate::{
+    ArenaConfig, ChannelConfig, ChannelKind, ChannelUsage, EventFd, Layout, MemoryReport,
+    VectorConfig, VectorMetrics,
+    arena::Arena,
+    broadcast::BroadcastQueue,
+    counters::CounterArray,
+    descriptor::Descriptor,
+    dmabuf::DmaBuf,
     error::*,
-    queue::{ConsumerQueue, ForcePushResult, PopResult, ProducerQueue, Queue, TryPushResult},
+    heartbeat::Heartbeat,
+    metrics::{MetricsHandle, QueueCounters},
+    mpsc::MpscQueue,
+    queue::{
+        ConsumerQueue, ForcePushResult, PopResult, ProducerQueue, Queue, TryPushResult,
+        check_poison, spin_pop,
+    },
+    quota::QuotaCharge,
     resource::{ChannelResource, VectorResource},
-    shm::SharedMemory,
+    seqlock::{SeqLock, next_sequence},
+    shm::{MemoryRegion, SharedMemory},
+    trace::*,
+    tracepoint::tracepoint,
 };
 
+/// Channels of a vector to be placed in its shared memory.
+struct Placement {
+    producers: Vec<ChannelResource>,
+    consumers: Vec<ChannelResource>,
+    info: Vec<u8>,
+    arena: Option<ArenaConfig>,
+    heartbeat: bool,
+    owner: bool,
+    resumed: bool,
+    layout: Layout,
+}
+
 pub struct Producer<T: Copy> {
     queue: ProducerQueue,
     eventfd: Option<EventFd>,
     cache: Option<Box<T>>,
+    metrics: Arc<QueueCounters>,
     _type: PhantomData<T>,
 }
 
 impl<T: Copy> Producer<T> {
-    fn new(channel: Channel) -> Result<Self, ShmMapError> {
-        if size_of::<T>() > channel.queue.message_size().get() {
+    fn new(
+        queue: Queue,
+        eventfd: Option<EventFd>,
+        metrics: Arc<QueueCounters>,
+    ) -> Result<Self, ShmMapError> {
+        if size_of::<T>() > queue.message_size().get() {
             return Err(ShmMapError::OutOfBounds);
         }
 
-        let queue = ProducerQueue::new(channel.queue);
+        let queue = ProducerQueue::new(queue);
 
         Ok(Self {
             queue,
-            eventfd: channel.eventfd,
+            eventfd,
             cache: None,
+            metrics,
             _type: PhantomData,
         })
     }
@@ -52,8 +91,19 @@ impl<T: Copy> Producer<T> {
 
         let result = self.queue.force_push();
 
-        if result == ForcePushResult::Success {
-            self.eventfd.as_ref().map(|fd| fd.write(1));
+        match result {
+            ForcePushResult::Success => {
+                self.pushed();
+                self.notify();
+            }
+            ForcePushResult::SuccessMessageDiscarded => {
+                self.pushed();
+                self.metrics.discarded();
+                tracepoint!(discard, self.queue.addr());
+                #[cfg(feature = "tracing")]
+                tracing::debug!("push discarded the oldest message");
+            }
+            ForcePushResult::QueueError => self.broken(),
         }
 
         result
@@ -62,24 +112,63 @@ impl<T: Copy> Producer<T> {
     pub fn try_push(&mut self) -> TryPushResult {
         if let Some(ref cache) = self.cache {
             if self.queue.full() {
+                self.full();
                 return TryPushResult::QueueFull;
             }
             *self.current_message() = *cache.clone();
         }
 
         let result = self.queue.try_push();
-        if result == TryPushResult::Success {
-            self.eventfd.as_ref().map(|fd| fd.write(1));
+
+        match result {
+            TryPushResult::Success => {
+                self.pushed();
+                self.notify();
+            }
+            TryPushResult::QueueFull => self.full(),
+            TryPushResult::QueueError => self.broken(),
         }
+
         result
     }
 
-    pub fn eventfd(&self) -> Option<BorrowedFd<'_>> {
-        self.eventfd.as_ref().map(|fd| fd.as_fd())
+    fn pushed(&self) {
+        let depth = self.queue.depth();
+        self.metrics.pushed(depth);
+        tracepoint!(push, self.queue.addr(), depth);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(depth, "pushed");
+    }
+
+    fn full(&self) {
+        self.metrics.full();
+        #[cfg(feature = "tracing")]
+        tracing::debug!("push refused, queue full");
+    }
+
+    fn broken(&self) {
+        #[cfg(feature = "tracing")]
+        tracing::error!("push failed, queue corrupted");
+    }
+
+    fn notify(&self) {
+        if let Some(fd) = self.eventfd.as_ref()
+            && fd.write(1).is_ok()
+        {
+            self.metrics.woken();
+            tracepoint!(
+                eventfd_write,
+                self.queue.addr(),
+                std::os::fd::AsRawFd::as_raw_fd(&fd.as_fd())
+            );
+        }
     }
 
-    pub fn take_eventfd(&mut self) -> Option<EventFd> {
-        self.eventfd.take()
+    /// The whole slot of the current message, padding included, bypassing the cache.
+    #[cfg(any(feature = "capnp", feature = "flatbuffers"))]
+    pub(crate) fn message_slot(&mut self) -> &mut [u8] {
+        let size = self.queue.message_size().get();
+        unsafe { std::slice::from_raw_parts_mut(self.queue.current_message().cast(), size) }
     }
 
     pub fn enable_cache(&mut self) {
@@ -95,44 +184,88 @@ impl<T: Copy> Producer<T> {
     }
 }
 
+/// Queue taken from a vector that moves to the shared memory of the resized vector.
+trait Resizable {
+    fn switch(&mut self, queue: Queue) -> Result<(), ShmMapError>;
+}
+
+impl<T: Copy> Resizable for Producer<T> {
+    /* the queue already holds the messages moved by the consumer,
+     * the current message is written again */
+    fn switch(&mut self, queue: Queue) -> Result<(), ShmMapError> {
+        if size_of::<T>() > queue.message_size().get() {
+            return Err(ShmMapError::OutOfBounds);
+        }
+
+        self.queue = ProducerQueue::new(queue);
+        Ok(())
+    }
+}
+
 pub struct Consumer<T: Copy> {
     queue: ConsumerQueue,
     eventfd: Option<EventFd>,
+    metrics: Arc<QueueCounters>,
     _type: PhantomData<T>,
 }
 
 impl<T: Copy> Consumer<T> {
-    fn new(channel: Channel) -> Result<Self, ShmMapError> {
-        if size_of::<T>() > channel.queue.message_size().get() {
+    fn new(
+        queue: Queue,
+        eventfd: Option<EventFd>,
+        metrics: Arc<QueueCounters>,
+    ) -> Result<Self, ShmMapError> {
+        if size_of::<T>() > queue.message_size().get() {
             return Err(ShmMapError::OutOfBounds);
         }
 
-        let queue = ConsumerQueue::new(channel.queue);
+        let queue = ConsumerQueue::new(queue);
 
         Ok(Self {
             queue,
-            eventfd: channel.eventfd,
+            eventfd,
+            metrics,
             _type: PhantomData,
         })
     }
 
     pub fn current_message(&self) -> Option<&T> {
         let ptr: *const T = self.queue.current_message()?.cast();
+        check_poison(ptr.cast(), size_of::<T>());
         Some(unsafe { &*ptr })
     }
 
+    /// The whole slot of the current message, padding included.
+    #[cfg(any(feature = "capnp", feature = "flatbuffers"))]
+    pub(crate) fn message_slot(&self) -> Option<&[u8]> {
+        let ptr = self.queue.current_message()?;
+        let size = self.queue.message_size().get();
+        check_poison(ptr, size);
+        Some(unsafe { std::slice::from_raw_parts(ptr.cast(), size) })
+    }
+
     pub fn pop(&mut self) -> PopResult {
-        if let Some(eventfd) = self.eventfd.as_ref()
-            && eventfd.read().is_err()
-        {
-            if self.queue.current_message().is_some() {
-                return PopResult::NoNewMessage;
-            } else {
-                return PopResult::NoMessage;
+        if let Some(eventfd) = self.eventfd.as_ref() {
+            if eventfd.read().is_err() {
+                if self.queue.current_message().is_some() {
+                    return PopResult::NoNewMessage;
+                } else {
+                    return PopResult::NoMessage;
+                }
             }
+            self.metrics.woken();
         }
 
-        self.queue.pop()
+        let result = self.queue.pop();
+        self.popped(result)
+    }
+
+    /// Busy waits for the next message, e.g. in a hard real-time loop on an isolated
+    /// core, by polling pop up to polls times with a growing backoff of spin loop hints.
+    /// Without an eventfd neither push nor pop enter the kernel, see
+    /// VectorConfig::is_syscall_free.
+    pub fn pop_spin(&mut self, polls: u32) -> PopResult {
+        spin_pop(polls, || self.pop())
     }
 
     pub fn flush(&mut self) -> PopResult {
@@ -143,107 +276,1866 @@ impl<T: Copy> Consumer<T> {
             }
             result
         } else {
-            self.queue.flush()
+            let result = self.queue.flush();
+            self.popped(result)
         }
     }
 
-    pub fn eventfd(&self) -> Option<BorrowedFd<'_>> {
-        self.eventfd.as_ref().map(|fd| fd.as_fd())
+    /// Counters shared with the vector, see ChannelVector::metrics.
+    pub(crate) fn counters(&self) -> &QueueCounters {
+        &self.metrics
+    }
+
+    fn popped(&self, result: PopResult) -> PopResult {
+        match result {
+            PopResult::Success => self.metrics.popped(),
+            PopResult::SuccessMessagesDiscarded => {
+                self.metrics.popped();
+                self.metrics.discarded();
+                tracepoint!(overrun, self.queue.addr());
+                #[cfg(feature = "tracing")]
+                tracing::debug!("popped after discarded messages");
+            }
+            PopResult::QueueError => {
+                #[cfg(feature = "tracing")]
+                tracing::error!("pop failed, queue corrupted");
+            }
+            _ => {}
+        }
+        #[cfg(any(feature = "usdt", feature = "lttng", feature = "ftrace"))]
+        if matches!(
+            result,
+            PopResult::Success | PopResult::SuccessMessagesDiscarded
+        ) {
+            tracepoint!(
+                pop,
+                self.queue.addr(),
+                self.queue.current_message().map_or(0, |msg| msg as usize),
+                size_of::<T>()
+            );
+        }
+        #[cfg(feature = "tracing")]
+        tracing::trace!(result = ?result, "pop");
+        result
+    }
+
+    /// Pops the next message and pushes it to producer with try_push, e.g. for a client
+    /// relaying the messages of one channel to another. Both queues have to be placed in the
+    /// shared memory of the same vector, a relay between two vectors fails with
+    /// ForwardError::SeparateMemory. Every queue has its own slots, so the message is copied
+    /// once from slot to slot. Nothing is popped while the queue of producer is full.
+    pub fn try_forward(
+        &mut self,
+        producer: &mut Producer<T>,
+    ) -> Result<TryPushResult, ForwardError> {
+        if !self.queue.same_memory(&producer.queue) {
+            return Err(ForwardError::SeparateMemory);
+        }
+
+        if producer.queue.full() {
+            producer.full();
+            return Ok(TryPushResult::QueueFull);
+        }
+
+        self.pass(producer)?;
+
+        Ok(producer.try_push())
+    }
+
+    /// Like try_forward, but pushes with force_push, which discards the oldest message of
+    /// producer if its queue is full.
+    pub fn force_forward(
+        &mut self,
+        producer: &mut Producer<T>,
+    ) -> Result<ForcePushResult, ForwardError> {
+        if !self.queue.same_memory(&producer.queue) {
+            return Err(ForwardError::SeparateMemory);
+        }
+
+        self.pass(producer)?;
+
+        Ok(producer.force_push())
     }
 
-    pub fn take_eventfd(&mut self) -> Option<EventFd> {
-        self.eventfd.take()
+    fn pass(&mut self, producer: &mut Producer<T>) -> Result<(), ForwardError> {
+        match self.pop() {
+            PopResult::Success | PopResult::SuccessMessagesDiscarded => {}
+            result => return Err(ForwardError::Pop(result)),
+        }
+
+        let msg = self
+            .current_message()
+            .ok_or(ForwardError::Pop(PopResult::QueueError))?;
+        *producer.current_message() = *msg;
+
+        Ok(())
     }
 }
 
-pub(crate) struct Channel {
-    queue: Queue,
-    info: Vec<u8>,
-    eventfd: Option<EventFd>,
+impl<T: Copy> Resizable for Consumer<T> {
+    fn switch(&mut self, queue: Queue) -> Result<(), ShmMapError> {
+        if size_of::<T>() > queue.message_size().get() {
+            return Err(ShmMapError::OutOfBounds);
+        }
+
+        if self.queue.migrate(queue) > 0 {
+            self.eventfd.as_ref().map(|fd| fd.write(1));
+        }
+
+        Ok(())
+    }
 }
 
-pub struct ChannelVector {
-    producers: Vec<Option<Channel>>,
-    consumers: Vec<Option<Channel>>,
-    info: Vec<u8>,
+/// Queues taken from a vector, by their index in the vector. They are moved along
+/// by ChannelVector::resize and ChannelVector::accept_resize, every taken queue
+/// of the vector has to be added.
+#[derive(Default)]
+pub struct ResizeChannels<'a> {
+    producers: Vec<(usize, &'a mut dyn Resizable)>,
+    consumers: Vec<(usize, &'a mut dyn Resizable)>,
 }
 
-impl ChannelVector {
-    fn create_channels(
-        rscs: Vec<ChannelResource>,
-        shm: &SharedMemory,
-        shm_offset: &mut usize,
-        shm_init: bool,
-    ) -> Result<Vec<Option<Channel>>, ShmMapError> {
-        let mut channels = Vec::<Option<Channel>>::with_capacity(rscs.len());
+impl<'a> ResizeChannels<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        for rsc in rscs {
-            let shm_size = rsc.config.shm_size();
+    pub fn producer<T: Copy>(mut self, index: usize, producer: &'a mut Producer<T>) -> Self {
+        self.producers.push((index, producer));
+        self
+    }
 
-            let chunk = shm.alloc(*shm_offset, shm_size)?;
-            let queue = Queue::new(chunk, &rsc.config)?;
+    pub fn consumer<T: Copy>(mut self, index: usize, consumer: &'a mut Consumer<T>) -> Self {
+        self.consumers.push((index, consumer));
+        self
+    }
+}
 
-            if shm_init {
-                queue.init();
+/// Typed side of a channel taken from a vector, see ChannelVector::take_as.
+trait FromChannel: Sized {
+    const KIND: ChannelKind;
+
+    /// None if the message type doesn't fit into a message of the channel.
+    fn from_channel(storage: Storage, eventfd: Option<EventFd>) -> Option<Self>;
+}
+
+fn fits<T>(message_size: NonZeroUsize) -> Option<()> {
+    (size_of::<T>() <= message_size.get()).then_some(())
+}
+
+/* the channel sides owning their eventfd, the producers of multi-producer channels share it */
+macro_rules! eventfd_accessors {
+    ($($side:ident),+) => {$(
+        impl<T: Copy> $side<T> {
+            pub fn eventfd(&self) -> Option<BorrowedFd<'_>> {
+                self.eventfd.as_ref().map(|fd| fd.as_fd())
             }
 
-            let channel = Channel {
-                queue,
-                info: rsc.config.info,
-                eventfd: rsc.eventfd,
-            };
+            pub fn take_eventfd(&mut self) -> Option<EventFd> {
+                self.eventfd.take()
+            }
+        }
+    )+};
+}
 
-            channels.push(Some(channel));
+eventfd_accessors!(
+    Producer,
+    Consumer,
+    StateProducer,
+    StateConsumer,
+    MpscConsumer,
+    BroadcastProducer,
+    BroadcastConsumer,
+    PriorityProducer,
+    PriorityConsumer,
+    ConflatedProducer,
+    ConflatedConsumer
+);
 
-            *shm_offset += shm_size.get();
+pub struct StateProducer<T: Copy> {
+    state: SeqLock,
+    eventfd: Option<EventFd>,
+    _type: PhantomData<T>,
+}
+
+impl<T: Copy> FromChannel for StateProducer<T> {
+    const KIND: ChannelKind = ChannelKind::State;
+
+    fn from_channel(storage: Storage, eventfd: Option<EventFd>) -> Option<Self> {
+        let Storage::State(state) = storage else {
+            return None;
+        };
+        fits::<T>(state.message_size())?;
+
+        Some(Self {
+            state,
+            eventfd,
+            _type: PhantomData,
+        })
+    }
+}
+
+impl<T: Copy> StateProducer<T> {
+    /// Replaces the current state, never blocks.
+    pub fn write(&mut self, val: &T) {
+        self.state.write(val);
+        self.eventfd.as_ref().map(|fd| fd.write(1));
+    }
+}
+
+pub struct StateConsumer<T: Copy> {
+    state: SeqLock,
+    eventfd: Option<EventFd>,
+    _type: PhantomData<T>,
+}
+
+impl<T: Copy> FromChannel for StateConsumer<T> {
+    const KIND: ChannelKind = ChannelKind::State;
+
+    fn from_channel(storage: Storage, eventfd: Option<EventFd>) -> Option<Self> {
+        let Storage::State(state) = storage else {
+            return None;
+        };
+        fits::<T>(state.message_size())?;
+
+        Some(Self {
+            state,
+            eventfd,
+            _type: PhantomData,
+        })
+    }
+}
+
+impl<T: Copy> StateConsumer<T> {
+    fn clear_eventfd(&self) {
+        if let Some(eventfd) = self.eventfd.as_ref() {
+            while eventfd.read().is_ok() {}
         }
-        Ok(channels)
     }
 
-    pub fn new(vrsc: VectorResource) -> Result<Self, ResourceError> {
-        let shm = SharedMemory::new(vrsc.shmfd)?;
+    /// Copy of the latest state, retries a bounded number of times while the producer
+    /// is updating it. Returns None if no state was written yet or the producer didn't
+    /// finish its update, e.g. because it died in the middle of it.
+    pub fn read(&self) -> Option<T> {
+        self.clear_eventfd();
+        self.state.read().map(|(val, _)| val)
+    }
 
-        let mut shm_offset = 0;
+    /// Single read attempt, returns None if the producer is just updating the state
+    /// or no state was written yet.
+    pub fn try_read(&self) -> Option<T> {
+        self.clear_eventfd();
+        self.state.try_read()
+    }
 
-        let consumers;
-        let producers;
+    /// Changes with every write, can be used to detect updates.
+    pub fn sequence(&self) -> u32 {
+        self.state.sequence()
+    }
+}
 
-        if vrsc.owner {
-            producers = Self::create_channels(vrsc.producers, &shm, &mut shm_offset, !vrsc.owner)?;
-            consumers = Self::create_channels(vrsc.consumers, &shm, &mut shm_offset, !vrsc.owner)?;
-        } else {
-            consumers = Self::create_channels(vrsc.consumers, &shm, &mut shm_offset, !vrsc.owner)?;
-            producers = Self::create_channels(vrsc.producers, &shm, &mut shm_offset, !vrsc.owner)?;
+/// Writer side of a counter channel, every value is a shared atomic u64.
+/// All modifying functions return the previous value or None if idx is out of range.
+pub struct CounterProducer {
+    counters: CounterArray,
+}
+
+impl FromChannel for CounterProducer {
+    const KIND: ChannelKind = ChannelKind::Counters;
+
+    fn from_channel(storage: Storage, _: Option<EventFd>) -> Option<Self> {
+        let Storage::Counters(counters) = storage else {
+            return None;
+        };
+
+        Some(Self { counters })
+    }
+}
+
+impl CounterProducer {
+    pub fn len(&self) -> usize {
+        self.counters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counters.len() == 0
+    }
+
+    pub fn get(&self, idx: usize) -> Option<u64> {
+        Some(self.counters.get(idx)?.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, idx: usize, val: u64) -> Option<u64> {
+        Some(self.counters.get(idx)?.swap(val, Ordering::Relaxed))
+    }
+
+    pub fn add(&self, idx: usize, val: u64) -> Option<u64> {
+        Some(self.counters.get(idx)?.fetch_add(val, Ordering::Relaxed))
+    }
+
+    pub fn sub(&self, idx: usize, val: u64) -> Option<u64> {
+        Some(self.counters.get(idx)?.fetch_sub(val, Ordering::Relaxed))
+    }
+}
+
+/// Reader side of a counter channel.
+pub struct CounterConsumer {
+    counters: CounterArray,
+}
+
+impl FromChannel for CounterConsumer {
+    const KIND: ChannelKind = ChannelKind::Counters;
+
+    fn from_channel(storage: Storage, _: Option<EventFd>) -> Option<Self> {
+        let Storage::Counters(counters) = storage else {
+            return None;
+        };
+
+        Some(Self { counters })
+    }
+}
+
+impl CounterConsumer {
+    pub fn len(&self) -> usize {
+        self.counters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counters.len() == 0
+    }
+
+    pub fn get(&self, idx: usize) -> Option<u64> {
+        Some(self.counters.get(idx)?.load(Ordering::Relaxed))
+    }
+
+    /// Values of all counters, the counters are read one after another,
+    /// so the snapshot is not atomic as a whole.
+    pub fn snapshot(&self) -> Vec<u64> {
+        (0..self.counters.len())
+            .filter_map(|idx| self.get(idx))
+            .collect()
+    }
+}
+
+struct MpscShared {
+    queue: MpscQueue,
+    eventfd: Option<EventFd>,
+}
+
+/// Producer handle of a multi-producer channel, every clone can push concurrently.
+/// Processes sharing the mapping (e.g. after fork) can push as well.
+pub struct MpscProducer<T: Copy> {
+    shared: Arc<MpscShared>,
+    _type: PhantomData<T>,
+}
+
+impl<T: Copy> Clone for MpscProducer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            _type: PhantomData,
         }
+    }
+}
 
-        Ok(Self {
-            producers,
-            consumers,
-            info: vrsc.info,
+impl<T: Copy> FromChannel for MpscProducer<T> {
+    const KIND: ChannelKind = ChannelKind::MultiProducer;
+
+    fn from_channel(storage: Storage, eventfd: Option<EventFd>) -> Option<Self> {
+        let Storage::Mpsc(queue) = storage else {
+            return None;
+        };
+        fits::<T>(queue.message_size())?;
+
+        Some(Self {
+            shared: Arc::new(MpscShared { queue, eventfd }),
+            _type: PhantomData,
         })
     }
+}
 
-    pub fn consumer_info(&self, index: usize) -> Option<&Vec<u8>> {
-        self.consumers.get(index)?.as_ref().map(|c| &c.info)
+impl<T: Copy> MpscProducer<T> {
+    /// Copies msg into the queue, messages are never discarded.
+    pub fn push(&self, msg: &T) -> TryPushResult {
+        let result = self.shared.queue.push(msg);
+
+        if result == TryPushResult::Success {
+            self.shared.eventfd.as_ref().map(|fd| fd.write(1));
+        }
+
+        result
     }
 
-    pub fn producer_info(&self, index: usize) -> Option<&Vec<u8>> {
-        self.producers.get(index)?.as_ref().map(|c| &c.info)
+    pub fn eventfd(&self) -> Option<BorrowedFd<'_>> {
+        self.shared.eventfd.as_ref().map(|fd| fd.as_fd())
     }
+}
 
-    pub fn take_consumer<T: Copy>(&mut self, index: usize) -> Option<Consumer<T>> {
-        let channel = self.consumers.get_mut(index)?.take()?;
-        let consumer = Consumer::new(channel).ok()?;
-        Some(consumer)
+pub struct MpscConsumer<T: Copy> {
+    queue: MpscQueue,
+    eventfd: Option<EventFd>,
+    tail: u64,
+    current: Option<u64>,
+    _type: PhantomData<T>,
+}
+
+impl<T: Copy> FromChannel for MpscConsumer<T> {
+    const KIND: ChannelKind = ChannelKind::MultiProducer;
+
+    fn from_channel(storage: Storage, eventfd: Option<EventFd>) -> Option<Self> {
+        let Storage::Mpsc(queue) = storage else {
+            return None;
+        };
+        fits::<T>(queue.message_size())?;
+
+        let tail = queue.consumer_pos();
+
+        Some(Self {
+            queue,
+            eventfd,
+            tail,
+            current: None,
+            _type: PhantomData,
+        })
     }
+}
 
-    pub fn take_producer<T: Copy>(&mut self, index: usize) -> Option<Producer<T>> {
-        let channel = self.producers.get_mut(index)?.take()?;
-        let producer = Producer::new(channel).ok()?;
-        Some(producer)
+impl<T: Copy> MpscConsumer<T> {
+    pub fn current_message(&self) -> Option<&T> {
+        let ptr: *const T = self.queue.message(self.current?).cast();
+        Some(unsafe { &*ptr })
     }
 
-    pub fn info(&self) -> &Vec<u8> {
-        &self.info
+    /// Releases the current message and takes the next one.
+    pub fn pop(&mut self) -> PopResult {
+        let no_message = if self.current.is_some() {
+            PopResult::NoNewMessage
+        } else {
+            PopResult::NoMessage
+        };
+
+        if let Some(eventfd) = self.eventfd.as_ref()
+            && eventfd.read().is_err()
+        {
+            return no_message;
+        }
+
+        if !self.queue.ready(self.tail) {
+            return no_message;
+        }
+
+        if let Some(current) = self.current {
+            self.queue.release(current);
+        }
+
+        self.current = Some(self.tail);
+        self.tail += 1;
+
+        PopResult::Success
+    }
+
+    /// Same as Consumer::pop_spin.
+    pub fn pop_spin(&mut self, polls: u32) -> PopResult {
+        spin_pop(polls, || self.pop())
+    }
+}
+
+/// Producer of a broadcast channel, never waits for consumers.
+pub struct BroadcastProducer<T: Copy> {
+    queue: BroadcastQueue,
+    eventfd: Option<EventFd>,
+    head: u64,
+    _type: PhantomData<T>,
+}
+
+impl<T: Copy> FromChannel for BroadcastProducer<T> {
+    const KIND: ChannelKind = ChannelKind::Broadcast;
+
+    fn from_channel(storage: Storage, eventfd: Option<EventFd>) -> Option<Self> {
+        let Storage::Broadcast(queue) = storage else {
+            return None;
+        };
+        fits::<T>(queue.message_size())?;
+
+        let head = queue.head_load();
+        queue.begin_write(head);
+
+        Some(Self {
+            queue,
+            eventfd,
+            head,
+            _type: PhantomData,
+        })
+    }
+}
+
+impl<T: Copy> BroadcastProducer<T> {
+    pub fn current_message(&mut self) -> &mut T {
+        unsafe { &mut *self.queue.message(self.head).cast::<T>() }
+    }
+
+    /// Publishes the current message, overwrites the oldest message.
+    pub fn push(&mut self) {
+        self.queue.end_write(self.head);
+        self.head += 1;
+        self.queue.begin_write(self.head);
+
+        self.eventfd.as_ref().map(|fd| fd.write(1));
+    }
+}
+
+/// Consumer of a broadcast channel, every clone has its own cursor.
+/// Consumers only read the shared memory, so slow consumers miss messages
+/// instead of blocking the producer. Messages are copied on pop.
+/// Clones stay in the process, a BroadcastHub fans the channel out to other processes.
+pub struct BroadcastConsumer<T: Copy> {
+    queue: Arc<BroadcastQueue>,
+    eventfd: Option<EventFd>,
+    cursor: u64,
+    message: Option<T>,
+    /// the head in shared memory went back, every pop fails with QueueError
+    poisoned: bool,
+}
+
+impl<T: Copy> Clone for BroadcastConsumer<T> {
+    /// The clone starts at the same position, but doesn't own the eventfd.
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+            eventfd: None,
+            cursor: self.cursor,
+            message: self.message,
+            poisoned: self.poisoned,
+        }
+    }
+}
+
+impl<T: Copy> FromChannel for BroadcastConsumer<T> {
+    const KIND: ChannelKind = ChannelKind::Broadcast;
+
+    fn from_channel(storage: Storage, eventfd: Option<EventFd>) -> Option<Self> {
+        let Storage::Broadcast(queue) = storage else {
+            return None;
+        };
+        fits::<T>(queue.message_size())?;
+
+        Some(Self {
+            queue: Arc::new(queue),
+            eventfd,
+            cursor: 0,
+            message: None,
+            poisoned: false,
+        })
+    }
+}
+
+impl<T: Copy> BroadcastConsumer<T> {
+    pub fn current_message(&self) -> Option<&T> {
+        self.message.as_ref()
+    }
+
+    /// Fails with QueueError once the head in shared memory went behind the cursor,
+    /// which only a corrupt or hostile producer writes.
+    pub fn pop(&mut self) -> PopResult {
+        if let Some(eventfd) = self.eventfd.as_ref() {
+            let _ = eventfd.read();
+        }
+
+        if self.poisoned {
+            return PopResult::QueueError;
+        }
+
+        /* the slot after head is always blocked by the producer */
+        let window = self.queue.len() as u64 - 1;
+        let mut discarded = false;
+
+        loop {
+            let head = self.queue.head_load();
+
+            if self.cursor == head {
+                return if self.message.is_some() {
+                    PopResult::NoNewMessage
+                } else {
+                    PopResult::NoMessage
+                };
+            }
+
+            let Some(lag) = head.checked_sub(self.cursor) else {
+                error!("broadcast head {head} behind cursor {}", self.cursor);
+                self.poisoned = true;
+                return PopResult::QueueError;
+            };
+
+            if lag > window {
+                self.cursor = head - window;
+                discarded = true;
+            }
+
+            if let Some(message) = self.queue.read(self.cursor) {
+                self.message = Some(message);
+                self.cursor += 1;
+
+                return if discarded {
+                    PopResult::SuccessMessagesDiscarded
+                } else {
+                    PopResult::Success
+                };
+            }
+
+            /* producer overran us while reading */
+            self.cursor += 1;
+            discarded = true;
+        }
+    }
+
+    /// Same as Consumer::pop_spin.
+    pub fn pop_spin(&mut self, polls: u32) -> PopResult {
+        spin_pop(polls, || self.pop())
+    }
+}
+
+/// Producer of a priority channel, composed of a high and a low priority queue.
+/// The current message is always located in the low priority queue,
+/// push_priority copies it to the high priority queue.
+/// Both queues share one eventfd, every push of either queue writes it once.
+pub struct PriorityProducer<T: Copy> {
+    high: ProducerQueue,
+    low: ProducerQueue,
+    eventfd: Option<EventFd>,
+    _type: PhantomData<T>,
+}
+
+impl<T: Copy> FromChannel for PriorityProducer<T> {
+    const KIND: ChannelKind = ChannelKind::Priority;
+
+    fn from_channel(storage: Storage, eventfd: Option<EventFd>) -> Option<Self> {
+        let Storage::Priority(high, low) = storage else {
+            return None;
+        };
+        fits::<T>(low.message_size())?;
+
+        Some(Self {
+            high: ProducerQueue::new(high),
+            low: ProducerQueue::new(low),
+            eventfd,
+            _type: PhantomData,
+        })
+    }
+}
+
+impl<T: Copy> PriorityProducer<T> {
+    pub fn current_message(&mut self) -> &mut T {
+        unsafe { &mut *self.low.current_message().cast::<T>() }
+    }
+
+    fn notify(&self) {
+        self.eventfd.as_ref().map(|fd| fd.write(1));
+    }
+
+    /// Pushes the current message on the low priority queue.
+    pub fn force_push(&mut self) -> ForcePushResult {
+        let result = self.low.force_push();
+
+        if result == ForcePushResult::Success {
+            self.notify();
+        }
+
+        result
+    }
+
+    /// Pushes the current message on the low priority queue.
+    pub fn try_push(&mut self) -> TryPushResult {
+        let result = self.low.try_push();
+
+        if result == TryPushResult::Success {
+            self.notify();
+        }
+
+        result
+    }
+
+    /// Pushes the current message on the high priority queue,
+    /// the oldest high priority message is discarded if the queue is full.
+    pub fn push_priority(&mut self) -> ForcePushResult {
+        let msg = *self.current_message();
+
+        unsafe {
+            *self.high.current_message().cast::<T>() = msg;
+        }
+
+        let result = self.high.force_push();
+
+        if result == ForcePushResult::Success {
+            self.notify();
+        }
+
+        result
+    }
+}
+
+/// Consumer of a priority channel, pop drains the high priority queue first.
+/// Both queues share one eventfd, so a wakeup doesn't tell the queue of the message:
+/// every pop reads the eventfd once and takes a high priority message if there is one,
+/// even if the wakeup was for a low priority message pushed earlier.
+pub struct PriorityConsumer<T: Copy> {
+    high: ConsumerQueue,
+    low: ConsumerQueue,
+    eventfd: Option<EventFd>,
+    priority: Option<bool>,
+    _type: PhantomData<T>,
+}
+
+impl<T: Copy> FromChannel for PriorityConsumer<T> {
+    const KIND: ChannelKind = ChannelKind::Priority;
+
+    fn from_channel(storage: Storage, eventfd: Option<EventFd>) -> Option<Self> {
+        let Storage::Priority(high, low) = storage else {
+            return None;
+        };
+        fits::<T>(low.message_size())?;
+
+        Some(Self {
+            high: ConsumerQueue::new(high),
+            low: ConsumerQueue::new(low),
+            eventfd,
+            priority: None,
+            _type: PhantomData,
+        })
+    }
+}
+
+impl<T: Copy> PriorityConsumer<T> {
+    pub fn current_message(&self) -> Option<&T> {
+        let queue = if self.priority? {
+            &self.high
+        } else {
+            &self.low
+        };
+        let ptr: *const T = queue.current_message()?.cast();
+        check_poison(ptr.cast(), size_of::<T>());
+        Some(unsafe { &*ptr })
+    }
+
+    /// Returns true if the current message was pushed with push_priority.
+    pub fn is_priority(&self) -> bool {
+        self.priority == Some(true)
+    }
+
+    fn success(result: &PopResult) -> bool {
+        *result == PopResult::Success || *result == PopResult::SuccessMessagesDiscarded
+    }
+
+    pub fn pop(&mut self) -> PopResult {
+        let no_message = if self.priority.is_some() {
+            PopResult::NoNewMessage
+        } else {
+            PopResult::NoMessage
+        };
+
+        if let Some(eventfd) = self.eventfd.as_ref()
+            && eventfd.read().is_err()
+        {
+            return no_message;
+        }
+
+        let result = self.high.pop();
+
+        if Self::success(&result) || result == PopResult::QueueError {
+            self.priority = Some(true);
+            return result;
+        }
+
+        let result = self.low.pop();
+
+        if Self::success(&result) || result == PopResult::QueueError {
+            self.priority = Some(false);
+            return result;
+        }
+
+        no_message
+    }
+
+    /// Same as Consumer::pop_spin.
+    pub fn pop_spin(&mut self, polls: u32) -> PopResult {
+        spin_pop(polls, || self.pop())
+    }
+}
+
+/// Producer of a conflated channel, every push replaces the pending message in place.
+pub struct ConflatedProducer<T: Copy> {
+    state: SeqLock,
+    eventfd: Option<EventFd>,
+    _type: PhantomData<T>,
+}
+
+impl<T: Copy> FromChannel for ConflatedProducer<T> {
+    const KIND: ChannelKind = ChannelKind::Conflated;
+
+    fn from_channel(storage: Storage, eventfd: Option<EventFd>) -> Option<Self> {
+        let Storage::Conflated(state) = storage else {
+            return None;
+        };
+        fits::<T>(state.message_size())?;
+
+        Some(Self {
+            state,
+            eventfd,
+            _type: PhantomData,
+        })
+    }
+}
+
+impl<T: Copy> ConflatedProducer<T> {
+    /// Overwrites the pending message, if the consumer didn't pop it yet.
+    pub fn push(&mut self, msg: &T) {
+        self.state.write(msg);
+        self.eventfd.as_ref().map(|fd| fd.write(1));
+    }
+}
+
+/// Consumer of a conflated channel, there is at most one pending message,
+/// which is always the newest one. Messages are copied on pop.
+pub struct ConflatedConsumer<T: Copy> {
+    state: SeqLock,
+    eventfd: Option<EventFd>,
+    sequence: u32,
+    message: Option<T>,
+}
+
+impl<T: Copy> FromChannel for ConflatedConsumer<T> {
+    const KIND: ChannelKind = ChannelKind::Conflated;
+
+    fn from_channel(storage: Storage, eventfd: Option<EventFd>) -> Option<Self> {
+        let Storage::Conflated(state) = storage else {
+            return None;
+        };
+        fits::<T>(state.message_size())?;
+
+        Some(Self {
+            state,
+            eventfd,
+            sequence: 0,
+            message: None,
+        })
+    }
+}
+
+impl<T: Copy> ConflatedConsumer<T> {
+    pub fn current_message(&self) -> Option<&T> {
+        self.message.as_ref()
+    }
+
+    pub fn pop(&mut self) -> PopResult {
+        if let Some(eventfd) = self.eventfd.as_ref() {
+            while eventfd.read().is_ok() {}
+        }
+
+        let no_message = || match self.message {
+            Some(_) => PopResult::NoNewMessage,
+            None => PopResult::NoMessage,
+        };
+
+        let sequence = self.state.sequence();
+
+        if sequence == 0 {
+            return PopResult::NoMessage;
+        }
+
+        if sequence == self.sequence {
+            return PopResult::NoNewMessage;
+        }
+
+        /* retries while the producer is just replacing the message, a producer
+         * dying in the middle of it leaves no new message */
+        let Some((message, sequence)) = self.state.read() else {
+            return no_message();
+        };
+
+        let discarded = sequence != next_sequence(self.sequence);
+
+        self.sequence = sequence;
+        self.message = Some(message);
+
+        if discarded {
+            PopResult::SuccessMessagesDiscarded
+        } else {
+            PopResult::Success
+        }
+    }
+
+    /// Same as Consumer::pop_spin.
+    pub fn pop_spin(&mut self, polls: u32) -> PopResult {
+        spin_pop(polls, || self.pop())
+    }
+}
+
+pub(crate) enum Storage {
+    Queue(Queue),
+    Priority(Queue, Queue),
+    State(SeqLock),
+    Counters(CounterArray),
+    Mpsc(MpscQueue),
+    Broadcast(BroadcastQueue),
+    Conflated(SeqLock),
+}
+
+impl Storage {
+    fn kind(&self) -> ChannelKind {
+        match self {
+            Storage::Queue(_) => ChannelKind::Queue,
+            Storage::Priority(_, _) => ChannelKind::Priority,
+            Storage::State(_) => ChannelKind::State,
+            Storage::Counters(_) => ChannelKind::Counters,
+            Storage::Mpsc(_) => ChannelKind::MultiProducer,
+            Storage::Broadcast(_) => ChannelKind::Broadcast,
+            Storage::Conflated(_) => ChannelKind::Conflated,
+        }
+    }
+}
+
+pub(crate) struct Channel {
+    storage: Storage,
+    info: Vec<u8>,
+    schema: Option<u64>,
+    eventfd: Option<EventFd>,
+}
+
+pub struct ChannelVector {
+    producers: Vec<Option<Channel>>,
+    consumers: Vec<Option<Channel>>,
+    arena: Option<Arena>,
+    heartbeat: Option<Heartbeat>,
+    info: Vec<u8>,
+    server_info: Vec<u8>,
+    payload: Vec<u8>,
+    #[cfg(feature = "socket")]
+    control: Option<Control>,
+    session: Option<u64>,
+    resumed: bool,
+    dmabufs: Vec<DmaBuf>,
+    /// bytes of shared memory used by the channels, the arena and the heartbeat stamps
+    shm_size: usize,
+    /// kept for the memory report, the channels keep the memory mapped anyway
+    shm: Option<Arc<SharedMemory>>,
+    producer_usage: Vec<ChannelUsage>,
+    consumer_usage: Vec<ChannelUsage>,
+    /// shared with the taken queues, they keep counting after a resize
+    producer_metrics: Vec<Arc<QueueCounters>>,
+    consumer_metrics: Vec<Arc<QueueCounters>>,
+}
+
+impl ChannelVector {
+    fn create_channels(
+        rscs: Vec<ChannelResource>,
+        shm: &SharedMemory,
+        shm_offset: &mut usize,
+        shm_init: bool,
+        layout: Layout,
+    ) -> Result<Vec<Option<Channel>>, ShmMapError> {
+        let mut channels = Vec::<Option<Channel>>::with_capacity(rscs.len());
+
+        for rsc in rscs {
+            let shm_size = rsc.shm_size(layout);
+
+            let mut chunk = shm.alloc(*shm_offset, shm_size)?;
+
+            if let Some(size) = NonZeroUsize::new(Descriptor::shm_size(layout)) {
+                let (head, storage) = chunk.split(size)?;
+                let descriptor = Descriptor::new(head, rsc.kind, &rsc.config, *shm_offset)?;
+
+                if shm_init {
+                    descriptor.init();
+                } else {
+                    descriptor.verify()?;
+                }
+
+                chunk = storage;
+            }
+
+            let storage = match rsc.kind {
+                ChannelKind::Queue => {
+                    let queue = Queue::new(chunk, &rsc.config, layout)?;
+                    if shm_init {
+                        queue.init();
+                    }
+                    Storage::Queue(queue)
+                }
+                ChannelKind::Priority => {
+                    let (high, low) = chunk.split(rsc.config.shm_size(layout))?;
+                    let high = Queue::new(high, &rsc.config, layout)?;
+                    let low = Queue::new(low, &rsc.config, layout)?;
+                    if shm_init {
+                        high.init();
+                        low.init();
+                    }
+                    Storage::Priority(high, low)
+                }
+                ChannelKind::State => {
+                    let state = SeqLock::new(chunk, &rsc.config, layout)?;
+                    if shm_init {
+                        state.init();
+                    }
+                    Storage::State(state)
+                }
+                ChannelKind::Counters => {
+                    let counters = CounterArray::new(chunk, &rsc.config)?;
+                    if shm_init {
+                        counters.init();
+                    }
+                    Storage::Counters(counters)
+                }
+                ChannelKind::MultiProducer => {
+                    let queue = MpscQueue::new(chunk, &rsc.config, layout)?;
+                    if shm_init {
+                        queue.init();
+                    }
+                    Storage::Mpsc(queue)
+                }
+                ChannelKind::Broadcast => {
+                    let queue = BroadcastQueue::new(chunk, &rsc.config, layout)?;
+                    if shm_init {
+                        queue.init();
+                    }
+                    Storage::Broadcast(queue)
+                }
+                ChannelKind::Conflated => {
+                    let state = SeqLock::new(chunk, &rsc.config, layout)?;
+                    if shm_init {
+                        state.init();
+                    }
+                    Storage::Conflated(state)
+                }
+            };
+
+            let channel = Channel {
+                storage,
+                info: rsc.config.info,
+                schema: rsc.config.schema,
+                eventfd: rsc.eventfd,
+            };
+
+            channels.push(Some(channel));
+
+            *shm_offset += shm_size.get();
+        }
+        Ok(channels)
+    }
+
+    pub fn new(vrsc: VectorResource) -> Result<Self, ResourceError> {
+        let layout = vrsc.layout();
+        let shm_size = vrsc.get_config().calc_layout_shm_size(layout);
+
+        /* a vector received from a pool maps only its own region */
+        let pool_size = vrsc.pool_offset.and_then(|_| NonZeroUsize::new(shm_size));
+
+        let shm = match (vrsc.memory, vrsc.region, vrsc.pool_offset.zip(pool_size)) {
+            (Some(memory), _, _) => Self::place_in(memory, shm_size, layout, vrsc.charge)?,
+            (None, Some(region), _) => SharedMemory::carve(region, vrsc.charge),
+            (None, None, Some((offset, size))) => {
+                SharedMemory::map(&vrsc.shmfd, offset, size, vrsc.charge, vrsc.map)?
+            }
+            (None, None, None) => SharedMemory::new(vrsc.shmfd, vrsc.charge, vrsc.map)?,
+        };
+
+        if vrsc.map.lock {
+            shm.lock()?;
+        }
+
+        if let Some(include) = vrsc.map.core_dump {
+            shm.set_core_dump(include)?;
+        }
+
+        let dmabufs = vrsc
+            .dmabufs
+            .into_iter()
+            .map(DmaBuf::map)
+            .collect::<Result<Vec<DmaBuf>, Errno>>()?;
+
+        let placement = Placement {
+            producers: vrsc.producers,
+            consumers: vrsc.consumers,
+            info: vrsc.info,
+            arena: vrsc.arena,
+            heartbeat: vrsc.heartbeat,
+            owner: vrsc.owner,
+            resumed: vrsc.resumed,
+            layout,
+        };
+
+        let mut vec = Self::place(&shm, placement)?;
+        vec.dmabufs = dmabufs;
+        Ok(vec)
+    }
+
+    /// Vector in memory supplied by the user, e.g. DeviceMemory or a BAR mapping both
+    /// peers have access to, without any handshake. Both peers call it with the vconfig
+    /// of the owner, the other peer gets producers and consumers swapped. The peer that
+    /// isn't the owner initializes the memory, so it has to create its vector first.
+    /// The vector can't have eventfds.
+    pub fn with_region(
+        vconfig: VectorConfig,
+        region: Box<dyn MemoryRegion>,
+        owner: bool,
+    ) -> Result<Self, TransferError> {
+        vconfig.validate(usize::MAX)?;
+
+        if vconfig.count_fds() != 1 {
+            return Err(ConfigError::EventFdsUnsupported.into());
+        }
+
+        let layout = Layout::native();
+        let shm_size = vconfig.calc_layout_shm_size(layout);
+        let shm = Self::place_in(region, shm_size, layout, None)?;
+
+        let channels = |configs: &Vec<ChannelConfig>| {
+            configs
+                .iter()
+                .map(|config| ChannelResource {
+                    config: config.queue.clone(),
+                    kind: config.kind,
+                    eventfd: None,
+                })
+                .collect()
+        };
+
+        let (producers, consumers) = if owner {
+            (channels(&vconfig.producers), channels(&vconfig.consumers))
+        } else {
+            (channels(&vconfig.consumers), channels(&vconfig.producers))
+        };
+
+        let placement = Placement {
+            producers,
+            consumers,
+            info: vconfig.info,
+            arena: vconfig.arena,
+            heartbeat: vconfig.heartbeat,
+            owner,
+            resumed: false,
+            layout,
+        };
+
+        Ok(Self::place(&shm, placement)?)
+    }
+
+    fn place_in(
+        memory: Box<dyn MemoryRegion>,
+        shm_size: usize,
+        layout: Layout,
+        charge: Option<QuotaCharge>,
+    ) -> Result<Arc<SharedMemory>, ResourceError> {
+        if memory.size().get() < shm_size {
+            error!(
+                "region of {} bytes can't hold {shm_size} bytes",
+                memory.size()
+            );
+            return Err(ShmMapError::OutOfBounds.into());
+        }
+
+        if !(memory.map().as_ptr() as usize).is_multiple_of(layout.cacheline_size) {
+            error!("region {:p} isn't cache line aligned", memory.map());
+            return Err(ShmMapError::Misalignment.into());
+        }
+
+        Ok(SharedMemory::with_memory(memory, charge))
+    }
+
+    fn place(shm: &Arc<SharedMemory>, vrsc: Placement) -> Result<Self, ResourceError> {
+        let layout = vrsc.layout;
+
+        /* only queues maintain the caches, the other kinds access their slots in place */
+        if !shm.is_coherent() {
+            let maintained = vrsc.producers.iter().chain(vrsc.consumers.iter()).all(|c| {
+                matches!(
+                    c.kind,
+                    ChannelKind::Queue | ChannelKind::Priority | ChannelKind::Counters
+                )
+            });
+
+            if !maintained || vrsc.arena.is_some() {
+                error!("non-coherent memory supports only queues and counters");
+                return Err(ResourceError::InvalidArgument);
+            }
+        }
+
+        let mut shm_offset = 0;
+
+        let usage = |channels: &Vec<ChannelResource>| {
+            channels
+                .iter()
+                .map(|c| c.kind.usage(&c.config, layout))
+                .collect()
+        };
+
+        let producer_usage = usage(&vrsc.producers);
+        let consumer_usage = usage(&vrsc.consumers);
+
+        let counters =
+            |channels: &Vec<ChannelResource>| channels.iter().map(|_| Arc::default()).collect();
+        let producer_metrics = counters(&vrsc.producers);
+        let consumer_metrics = counters(&vrsc.consumers);
+
+        let consumers;
+        let producers;
+
+        let shm_init = !vrsc.owner && !vrsc.resumed;
+
+        if vrsc.owner {
+            producers =
+                Self::create_channels(vrsc.producers, shm, &mut shm_offset, shm_init, layout)?;
+            consumers =
+                Self::create_channels(vrsc.consumers, shm, &mut shm_offset, shm_init, layout)?;
+        } else {
+            consumers =
+                Self::create_channels(vrsc.consumers, shm, &mut shm_offset, shm_init, layout)?;
+            producers =
+                Self::create_channels(vrsc.producers, shm, &mut shm_offset, shm_init, layout)?;
+        }
+
+        let arena = match vrsc.arena {
+            Some(config) => {
+                let chunk = shm.alloc(shm_offset, config.shm_size(layout))?;
+                shm_offset += config.shm_size(layout).get();
+                let arena = Arena::new(chunk, &config, layout, vrsc.owner)?;
+                if shm_init {
+                    arena.init();
+                }
+                Some(arena)
+            }
+            None => None,
+        };
+
+        let heartbeat = if vrsc.heartbeat {
+            let chunk = shm.alloc(shm_offset, Heartbeat::shm_size(layout))?;
+            let heartbeat = Heartbeat::new(chunk, layout, vrsc.owner)?;
+            shm_offset += Heartbeat::shm_size(layout).get();
+            if shm_init {
+                heartbeat.init();
+            }
+            /* the peer is alive from the start */
+            heartbeat.beat();
+            Some(heartbeat)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            producers,
+            consumers,
+            arena,
+            heartbeat,
+            info: vrsc.info,
+            server_info: Vec::with_capacity(0),
+            payload: Vec::with_capacity(0),
+            #[cfg(feature = "socket")]
+            control: None,
+            session: None,
+            resumed: vrsc.resumed,
+            dmabufs: Vec::new(),
+            shm_size: shm_offset,
+            shm: Some(shm.clone()),
+            producer_usage,
+            consumer_usage,
+            producer_metrics,
+            consumer_metrics,
+        })
+    }
+
+    /// Two connected vectors without any handshake, for single-binary applications that
+    /// fork or run both sides in threads. The first vector is vconfig, the second its peer
+    /// with producers and consumers swapped. Both stay mapped across fork, each process
+    /// drops the vector it doesn't use.
+    pub fn create_pair(vconfig: VectorConfig) -> Result<(Self, Self), TransferError> {
+        vconfig.validate(usize::MAX)?;
+
+        let owner = VectorResource::allocate(&vconfig)?;
+        let peer = owner.peer()?;
+
+        /* the peer initializes the shared memory */
+        let peer = Self::new(peer)?;
+        let owner = Self::new(owner)?;
+
+        Ok((owner, peer))
+    }
+
+    /// Vector returned to a server for a resumed session, the channels stay with
+    /// the vector of the original session.
+    pub(crate) fn resumed_session(token: u64) -> Self {
+        Self {
+            session: Some(token),
+            resumed: true,
+            ..Self::without_channels()
+        }
+    }
+
+    /// Vector without channels, e.g. the one a server keeps for a client of a BroadcastHub.
+    pub(crate) fn without_channels() -> Self {
+        Self {
+            producers: Vec::new(),
+            consumers: Vec::new(),
+            arena: None,
+            heartbeat: None,
+            info: Vec::with_capacity(0),
+            server_info: Vec::with_capacity(0),
+            payload: Vec::with_capacity(0),
+            #[cfg(feature = "socket")]
+            control: None,
+            session: None,
+            resumed: false,
+            dmabufs: Vec::new(),
+            shm_size: 0,
+            shm: None,
+            producer_usage: Vec::new(),
+            consumer_usage: Vec::new(),
+            producer_metrics: Vec::new(),
+            consumer_metrics: Vec::new(),
+        }
+    }
+
+    pub fn consumer_info(&self, index: usize) -> Option<&Vec<u8>> {
+        self.consumers.get(index)?.as_ref().map(|c| &c.info)
+    }
+
+    /// Shared memory of the vector in the layout agreed on with the peer, at least
+    /// VectorConfig::calc_shm_size. 0 for the vector of a resumed session.
+    pub fn total_shm_size(&self) -> usize {
+        self.shm_size
+    }
+
+    /// Memory used by the vector, taken channels are still part of it.
+    pub fn memory_report(&self) -> MemoryReport {
+        let mapped = self.shm.as_ref().map_or(0, |shm| shm.size().get());
+
+        let locked = if self.shm.as_ref().is_some_and(|shm| shm.is_locked()) {
+            mapped
+        } else {
+            0
+        };
+
+        MemoryReport {
+            mapped,
+            locked,
+            used: self.shm_size,
+            dmabufs: self.dmabufs.iter().map(DmaBuf::size).sum(),
+            producers: self.producer_usage.clone(),
+            consumers: self.consumer_usage.clone(),
+        }
+    }
+
+    /// Snapshot of the counters of the channels, e.g. for exporting them periodically
+    /// from another thread than those of the producers and consumers.
+    pub fn metrics(&self) -> VectorMetrics {
+        self.metrics_handle().snapshot()
+    }
+
+    /// Counters of the channels, the handle outlives the vector and can be moved to
+    /// another thread, see PrometheusExporter.
+    pub fn metrics_handle(&self) -> MetricsHandle {
+        MetricsHandle::new(&self.producer_metrics, &self.consumer_metrics)
+    }
+
+    /// dma-buf the messages refer to by index, in the order the client attached them.
+    pub fn dmabuf(&self, index: usize) -> Option<&DmaBuf> {
+        self.dmabufs.get(index)
+    }
+
+    pub fn producer_info(&self, index: usize) -> Option<&Vec<u8>> {
+        self.producers.get(index)?.as_ref().map(|c| &c.info)
+    }
+
+    /// Info of the producer at index, None for a taken producer, None as well past
+    /// the last producer.
+    pub(crate) fn producer_slot(&self, index: usize) -> Option<Option<&[u8]>> {
+        Some(
+            self.producers
+                .get(index)?
+                .as_ref()
+                .map(|c| c.info.as_slice()),
+        )
+    }
+
+    pub(crate) fn consumer_slot(&self, index: usize) -> Option<Option<&[u8]>> {
+        Some(
+            self.consumers
+                .get(index)?
+                .as_ref()
+                .map(|c| c.info.as_slice()),
+        )
+    }
+
+    pub fn consumer_schema(&self, index: usize) -> Option<u64> {
+        self.consumers.get(index)?.as_ref()?.schema
+    }
+
+    pub fn producer_schema(&self, index: usize) -> Option<u64> {
+        self.producers.get(index)?.as_ref()?.schema
+    }
+
+    /// Returns false if the channel has a fingerprint different from schema,
+    /// channels without fingerprint match every schema.
+    fn schema_matches(channels: &[Option<Channel>], index: usize, schema: u64) -> bool {
+        match channels
+            .get(index)
+            .and_then(|c| c.as_ref())
+            .and_then(|c| c.schema)
+        {
+            Some(fingerprint) if fingerprint != schema => {
+                error!("channel {index}: schema mismatch {fingerprint:#x} != {schema:#x}");
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn take_channel(
+        channels: &mut [Option<Channel>],
+        index: usize,
+        kind: ChannelKind,
+    ) -> Option<Channel> {
+        let slot = channels.get_mut(index)?;
+
+        if slot.as_ref()?.storage.kind() != kind {
+            return None;
+        }
+
+        slot.take()
+    }
+
+    fn take_as<C: FromChannel>(channels: &mut [Option<Channel>], index: usize) -> Option<C> {
+        let channel = Self::take_channel(channels, index, C::KIND)?;
+        C::from_channel(channel.storage, channel.eventfd)
+    }
+
+    pub fn consumer_kind(&self, index: usize) -> Option<ChannelKind> {
+        self.consumers
+            .get(index)?
+            .as_ref()
+            .map(|c| c.storage.kind())
+    }
+
+    pub fn producer_kind(&self, index: usize) -> Option<ChannelKind> {
+        self.producers
+            .get(index)?
+            .as_ref()
+            .map(|c| c.storage.kind())
+    }
+
+    /// Fails if the channel has a schema fingerprint, T doesn't state one,
+    /// see take_consumer_checked and take_consumer_unchecked.
+    pub fn take_consumer<T: Copy>(&mut self, index: usize) -> Option<Consumer<T>> {
+        if let Some(schema) = self.consumer_schema(index) {
+            error!("consumer {index} has schema {schema:#x}, take it with take_consumer_checked");
+            return None;
+        }
+        self.take_consumer_unchecked(index)
+    }
+
+    /// Fails if the channel has a schema fingerprint, T doesn't state one,
+    /// see take_producer_checked and take_producer_unchecked.
+    pub fn take_producer<T: Copy>(&mut self, index: usize) -> Option<Producer<T>> {
+        if let Some(schema) = self.producer_schema(index) {
+            error!("producer {index} has schema {schema:#x}, take it with take_producer_checked");
+            return None;
+        }
+        self.take_producer_unchecked(index)
+    }
+
+    /// Like take_consumer, but fails if the peer announced a different schema for the channel.
+    pub fn take_consumer_checked<T: Copy>(
+        &mut self,
+        index: usize,
+        schema: u64,
+    ) -> Option<Consumer<T>> {
+        if !Self::schema_matches(&self.consumers, index, schema) {
+            return None;
+        }
+        self.take_consumer_unchecked(index)
+    }
+
+    /// Like take_producer, but fails if the peer announced a different schema for the channel.
+    pub fn take_producer_checked<T: Copy>(
+        &mut self,
+        index: usize,
+        schema: u64,
+    ) -> Option<Producer<T>> {
+        if !Self::schema_matches(&self.producers, index, schema) {
+            return None;
+        }
+        self.take_producer_unchecked(index)
+    }
+
+    /// Like take_consumer, but ignores the schema of the channel, e.g. for a relay
+    /// passing the messages on without reading them.
+    pub fn take_consumer_unchecked<T: Copy>(&mut self, index: usize) -> Option<Consumer<T>> {
+        let channel = Self::take_channel(&mut self.consumers, index, ChannelKind::Queue)?;
+        let Storage::Queue(queue) = channel.storage else {
+            return None;
+        };
+        let metrics = self.consumer_metrics.get(index)?.clone();
+        let consumer = Consumer::new(queue, channel.eventfd, metrics).ok()?;
+        Some(consumer)
+    }
+
+    /// Like take_producer, but ignores the schema of the channel.
+    pub fn take_producer_unchecked<T: Copy>(&mut self, index: usize) -> Option<Producer<T>> {
+        let channel = Self::take_channel(&mut self.producers, index, ChannelKind::Queue)?;
+        let Storage::Queue(queue) = channel.storage else {
+            return None;
+        };
+        let metrics = self.producer_metrics.get(index)?.clone();
+        let producer = Producer::new(queue, channel.eventfd, metrics).ok()?;
+        Some(producer)
+    }
+
+    pub fn take_priority_consumer<T: Copy>(&mut self, index: usize) -> Option<PriorityConsumer<T>> {
+        Self::take_as(&mut self.consumers, index)
+    }
+
+    pub fn take_priority_producer<T: Copy>(&mut self, index: usize) -> Option<PriorityProducer<T>> {
+        Self::take_as(&mut self.producers, index)
+    }
+
+    pub fn take_state_consumer<T: Copy>(&mut self, index: usize) -> Option<StateConsumer<T>> {
+        Self::take_as(&mut self.consumers, index)
+    }
+
+    pub fn take_state_producer<T: Copy>(&mut self, index: usize) -> Option<StateProducer<T>> {
+        Self::take_as(&mut self.producers, index)
+    }
+
+    pub fn take_conflated_consumer<T: Copy>(
+        &mut self,
+        index: usize,
+    ) -> Option<ConflatedConsumer<T>> {
+        Self::take_as(&mut self.consumers, index)
+    }
+
+    pub fn take_conflated_producer<T: Copy>(
+        &mut self,
+        index: usize,
+    ) -> Option<ConflatedProducer<T>> {
+        Self::take_as(&mut self.producers, index)
+    }
+
+    pub fn take_counter_consumer(&mut self, index: usize) -> Option<CounterConsumer> {
+        Self::take_as(&mut self.consumers, index)
+    }
+
+    pub fn take_counter_producer(&mut self, index: usize) -> Option<CounterProducer> {
+        Self::take_as(&mut self.producers, index)
+    }
+
+    pub fn take_mpsc_consumer<T: Copy>(&mut self, index: usize) -> Option<MpscConsumer<T>> {
+        Self::take_as(&mut self.consumers, index)
+    }
+
+    pub fn take_mpsc_producer<T: Copy>(&mut self, index: usize) -> Option<MpscProducer<T>> {
+        Self::take_as(&mut self.producers, index)
+    }
+
+    pub fn take_broadcast_consumer<T: Copy>(
+        &mut self,
+        index: usize,
+    ) -> Option<BroadcastConsumer<T>> {
+        Self::take_as(&mut self.consumers, index)
+    }
+
+    pub fn take_broadcast_producer<T: Copy>(
+        &mut self,
+        index: usize,
+    ) -> Option<BroadcastProducer<T>> {
+        Self::take_as(&mut self.producers, index)
+    }
+
+    pub fn take_arena(&mut self) -> Option<Arena> {
+        self.arena.take()
+    }
+
+    pub fn take_heartbeat(&mut self) -> Option<Heartbeat> {
+        self.heartbeat.take()
+    }
+
+    pub fn info(&self) -> &Vec<u8> {
+        &self.info
+    }
+
+    /// Info the server attached to its response, always empty on the server side.
+    pub fn server_info(&self) -> &Vec<u8> {
+        &self.server_info
+    }
+
+    pub(crate) fn set_server_info(&mut self, info: Vec<u8>) {
+        self.server_info = info;
+    }
+
+    /// Payload returned by the server's accept filter, always empty on the server side.
+    pub fn payload(&self) -> &Vec<u8> {
+        &self.payload
+    }
+
+    pub(crate) fn set_payload(&mut self, payload: Vec<u8>) {
+        self.payload = payload;
+    }
+
+    /// Connection to the peer, only available for vectors created by a handshake.
+    #[cfg(feature = "socket")]
+    pub fn take_control(&mut self) -> Option<Control> {
+        self.control.take()
+    }
+
+    #[cfg(feature = "socket")]
+    pub(crate) fn set_control(&mut self, control: Control) {
+        self.control = Some(control);
+    }
+
+    /// Token for resuming the vector after a reconnect,
+    /// only set if the server keeps sessions.
+    pub fn session_token(&self) -> Option<u64> {
+        self.session
+    }
+
+    pub(crate) fn set_session_token(&mut self, token: u64) {
+        self.session = (token != 0).then_some(token);
+    }
+
+    /// Returns true if the vector continues a previous session.
+    pub fn is_resumed(&self) -> bool {
+        self.resumed
+    }
+
+    /// Moves the vector to new shared memory with the queues of vconfig without dropping
+    /// the connection, e.g. for deeper queues. vconfig may only add messages to the queues
+    /// of the vector, other channel kinds, an arena and heartbeat stamps aren't supported.
+    /// Messages not consumed yet are moved along, the eventfds stay the same. The message
+    /// a consumer popped last is dropped, its current_message is None until the next pop.
+    /// The peer answers with accept_resize, neither peer may use its queues until the call
+    /// returns. control is the Control taken from the vector.
+    #[cfg(feature = "socket")]
+    pub fn resize(
+        &mut self,
+        control: &Control,
+        vconfig: VectorConfig,
+        mut channels: ResizeChannels,
+    ) -> Result<(), TransferError> {
+        let vconfig = Self::without_eventfds(vconfig);
+        vconfig.validate(usize::MAX)?;
+        self.check_resize(&vconfig, &channels)?;
+
+        let rsc = VectorResource::allocate_layout(&vconfig, control.layout())?;
+        control.send_resize(&rsc)?;
+
+        let answer = control.wait_for(|msg| matches!(msg, ControlMessage::Resized(_)))?;
+
+        if let ControlMessage::Resized(false) = answer {
+            error!("peer refused the resize");
+            return Err(TransferError::Rejected(Rejection::new(
+                Rejection::UNSPECIFIED,
+                "resize refused",
+            )));
+        }
+
+        /* the peer initialized the shared memory and moved its consumers already */
+        let mut next = Self::new(rsc)?;
+
+        Self::switch_channels(
+            &mut self.consumers,
+            &mut next.consumers,
+            &mut channels.consumers,
+            true,
+        )?;
+        Self::switch_channels(
+            &mut self.producers,
+            &mut next.producers,
+            &mut channels.producers,
+            false,
+        )?;
+
+        control.send(&ControlMessage::Switched)?;
+
+        Ok(self.adopt(next)?)
+    }
+
+    /// Answers the Resize message last received by control, see resize.
+    /// The peer is told if the resize is refused.
+    #[cfg(feature = "socket")]
+    pub fn accept_resize(
+        &mut self,
+        control: &Control,
+        mut channels: ResizeChannels,
+    ) -> Result<(), TransferError> {
+        let rsc = control
+            .take_resize()
+            .ok_or(ResourceError::InvalidArgument)?;
+
+        let next = self
+            .check_resize(&rsc.get_config(), &channels)
+            .and_then(|_| Ok(Self::new(rsc)?));
+
+        let mut next = match next {
+            Ok(next) => next,
+            Err(e) => {
+                control.send(&ControlMessage::Resized(false))?;
+                return Err(e);
+            }
+        };
+
+        Self::switch_channels(
+            &mut self.consumers,
+            &mut next.consumers,
+            &mut channels.consumers,
+            true,
+        )?;
+
+        control.send(&ControlMessage::Resized(true))?;
+        control.wait_for(|msg| matches!(msg, ControlMessage::Switched))?;
+
+        /* the peer moved its consumers, our producers continue behind the moved messages */
+        Self::switch_channels(
+            &mut self.producers,
+            &mut next.producers,
+            &mut channels.producers,
+            false,
+        )?;
+
+        Ok(self.adopt(next)?)
+    }
+
+    /// The channels keep the eventfds of the vector.
+    fn without_eventfds(vconfig: VectorConfig) -> VectorConfig {
+        let strip = |configs: Vec<ChannelConfig>| {
+            configs
+                .into_iter()
+                .map(|config| ChannelConfig {
+                    eventfd: false,
+                    ..config
+                })
+                .collect()
+        };
+
+        VectorConfig {
+            producers: strip(vconfig.producers),
+            consumers: strip(vconfig.consumers),
+            ..vconfig
+        }
+    }
+
+    /// Fails unless vconfig only adds messages to the queues of the vector
+    /// and channels holds every queue taken from the vector.
+    fn check_resize(
+        &self,
+        vconfig: &VectorConfig,
+        channels: &ResizeChannels,
+    ) -> Result<(), TransferError> {
+        /* the shared memory beyond the channels holds the arena and the heartbeat stamps */
+        let channels_size: usize = self
+            .producer_usage
+            .iter()
+            .chain(&self.consumer_usage)
+            .map(|usage| usage.shm_size)
+            .sum();
+
+        if vconfig.arena.is_some() || vconfig.heartbeat || channels_size != self.shm_size {
+            error!("resize: vectors with arena or heartbeat stamps can't be resized");
+            return Err(ResourceError::InvalidArgument.into());
+        }
+
+        let grows = |configs: &[ChannelConfig], usage: &[ChannelUsage]| {
+            configs.len() == usage.len()
+                && configs.iter().zip(usage).all(|(config, usage)| {
+                    let next = config.kind.usage(&config.queue, Layout::native());
+                    config.kind == ChannelKind::Queue
+                        && usage.kind == ChannelKind::Queue
+                        && next.slot_size == usage.slot_size
+                        && next.slots >= usage.slots
+                })
+        };
+
+        if !grows(&vconfig.producers, &self.producer_usage)
+            || !grows(&vconfig.consumers, &self.consumer_usage)
+        {
+            error!("resize: only the number of messages of queues can grow");
+            return Err(ResourceError::InvalidArgument.into());
+        }
+
+        if !Self::all_covered(&self.producers, &channels.producers)
+            || !Self::all_covered(&self.consumers, &channels.consumers)
+        {
+            error!("resize: taken queues are missing");
+            return Err(ResourceError::InvalidArgument.into());
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if every taken channel is in taken exactly once.
+    fn all_covered(slots: &[Option<Channel>], taken: &[(usize, &mut dyn Resizable)]) -> bool {
+        let missing = slots.iter().filter(|slot| slot.is_none()).count();
+
+        missing == taken.len()
+            && slots
+                .iter()
+                .enumerate()
+                .all(|(index, slot)| slot.is_some() || taken.iter().any(|(i, _)| *i == index))
+    }
+
+    /// Moves the channels in slots and taken to the queues of next,
+    /// consumers take the messages they didn't consume yet along.
+    fn switch_channels(
+        slots: &mut [Option<Channel>],
+        next: &mut [Option<Channel>],
+        taken: &mut [(usize, &mut dyn Resizable)],
+        consumer: bool,
+    ) -> Result<(), ResourceError> {
+        for (index, slot) in next.iter_mut().enumerate() {
+            let Some(Channel {
+                storage: Storage::Queue(queue),
+                ..
+            }) = slot.take()
+            else {
+                return Err(ShmMapError::OutOfBounds.into());
+            };
+
+            let Some(mut channel) = slots[index].take() else {
+                if let Some((_, resizable)) = taken.iter_mut().find(|(i, _)| *i == index) {
+                    resizable.switch(queue)?;
+                }
+                continue;
+            };
+
+            let Storage::Queue(old) = channel.storage else {
+                return Err(ShmMapError::OutOfBounds.into());
+            };
+
+            let queue = if consumer {
+                let mut consumer = ConsumerQueue::new(old);
+                if consumer.migrate(queue) > 0 {
+                    channel.eventfd.as_ref().map(|fd| fd.write(1));
+                }
+                consumer.into_queue()
+            } else {
+                queue
+            };
+
+            channel.storage = Storage::Queue(queue);
+            slots[index] = Some(channel);
+        }
+
+        Ok(())
+    }
+
+    /// Takes over the shared memory of the resized vector, the old one is unmapped
+    /// once the last of its queues is dropped.
+    fn adopt(&mut self, next: ChannelVector) -> Result<(), ResourceError> {
+        if let Some(shm) = &next.shm
+            && self.shm.as_ref().is_some_and(|shm| shm.is_locked())
+        {
+            shm.lock()?;
+        }
+
+        self.shm = next.shm;
+        self.shm_size = next.shm_size;
+        self.producer_usage = next.producer_usage;
+        self.consumer_usage = next.consumer_usage;
+        Ok(())
     }
 }
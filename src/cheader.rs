@@ -0,0 +1,300 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::descriptor::Descriptor;
+use crate::error::*;
+use crate::queue::Queue;
+use crate::trace::*;
+use crate::{ChannelConfig, ChannelKind, Layout, VectorConfig, is_supported_index_size};
+
+/// Rust type with a C counterpart, declares a struct field of the type in C.
+pub trait CType {
+    fn c_decl(name: &str) -> String;
+}
+
+macro_rules! c_types {
+    ($($ty:ty => $c:literal),*) => {
+        $(impl CType for $ty {
+            fn c_decl(name: &str) -> String {
+                format!("{} {name}", $c)
+            }
+        })*
+    };
+}
+
+c_types!(
+    u8 => "uint8_t", u16 => "uint16_t", u32 => "uint32_t", u64 => "uint64_t",
+    i8 => "int8_t", i16 => "int16_t", i32 => "int32_t", i64 => "int64_t",
+    usize => "size_t", isize => "ptrdiff_t", f32 => "float", f64 => "double", bool => "bool"
+);
+
+impl<T: CType, const N: usize> CType for [T; N] {
+    fn c_decl(name: &str) -> String {
+        T::c_decl(&format!("{name}[{N}]"))
+    }
+}
+
+/// Field of a message struct, see c_message.
+pub struct CField {
+    pub name: &'static str,
+    /// declaration of the field in C, e.g. uint8_t data[16]
+    pub decl: String,
+    pub offset: usize,
+}
+
+/// #[repr(C)] message struct with a C typedef, implemented by c_message.
+pub trait CMessage: Sized {
+    const C_NAME: &'static str;
+
+    fn c_fields() -> Vec<CField>;
+}
+
+/// Declares a #[repr(C)] message struct and implements CMessage and CType for it,
+/// so CHeader emits its typedef. Fields are integers, floats, bool, arrays of them and
+/// other message structs. cbindgen takes the struct as well.
+///
+/// ```text
+/// c_message! {
+///     #[derive(Clone, Copy)]
+///     pub struct MsgCommand {
+///         pub id: u32,
+///         pub value: f64,
+///         pub data: [u8; 16],
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! c_message {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$fmeta:meta])* $fvis:vis $field:ident : $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(C)]
+        $vis struct $name {
+            $($(#[$fmeta])* $fvis $field: $ty,)*
+        }
+
+        impl $crate::CType for $name {
+            fn c_decl(name: &str) -> ::std::string::String {
+                ::std::format!("{} {}", ::core::stringify!($name), name)
+            }
+        }
+
+        impl $crate::CMessage for $name {
+            const C_NAME: &'static str = ::core::stringify!($name);
+
+            fn c_fields() -> ::std::vec::Vec<$crate::CField> {
+                ::std::vec![$($crate::CField {
+                    name: ::core::stringify!($field),
+                    decl: <$ty as $crate::CType>::c_decl(::core::stringify!($field)),
+                    offset: ::core::mem::offset_of!($name, $field),
+                },)*]
+            }
+        }
+    };
+}
+
+/// Generates a C header with the message structs and the shared memory layout of
+/// vectors, so C peers place the messages at the offsets of the Rust peer.
+/// Sizes and field offsets of the structs are checked by static asserts, a C compiler
+/// with another ABI refuses the header instead of misreading the messages.
+pub struct CHeader {
+    guard: String,
+    body: String,
+}
+
+impl CHeader {
+    /// guard is the include guard, e.g. ROBOT_MESSAGES_H
+    pub fn new(guard: &str) -> Self {
+        Self {
+            guard: guard.to_owned(),
+            body: String::new(),
+        }
+    }
+
+    /// typedef of T, message structs used as fields have to be added before.
+    pub fn message<T: CMessage>(mut self) -> Self {
+        let name = T::C_NAME;
+        let fields = T::c_fields();
+
+        let _ = writeln!(self.body, "typedef struct {name} {{");
+        for field in &fields {
+            let _ = writeln!(self.body, "    {};", field.decl);
+        }
+        let _ = writeln!(self.body, "}} {name};\n");
+
+        let _ = writeln!(
+            self.body,
+            "RTIPC_LAYOUT_ASSERT(sizeof({name}) == {}, \"size of {name}\");",
+            size_of::<T>()
+        );
+        for field in &fields {
+            let _ = writeln!(
+                self.body,
+                "RTIPC_LAYOUT_ASSERT(offsetof({name}, {0}) == {1}, \"offset of {name}.{0}\");",
+                field.name, field.offset
+            );
+        }
+        self.body.push('\n');
+
+        self
+    }
+
+    /// Layout of a vector in the native layout, see vector_with_layout.
+    pub fn vector(self, prefix: &str, config: &VectorConfig) -> Self {
+        self.layout(prefix, config, Layout::native())
+    }
+
+    /// Layout of a vector as negotiated with a peer, e.g. with larger cache lines,
+    /// see VectorResource::allocate_with_layout. Every channel gets defines
+    /// prefix_name_OFFSET, _SHM_SIZE, _MESSAGE_SIZE, _SLOTS and _SLOT_SIZE, queues
+    /// _MESSAGES_OFFSET, the offset of their first slot. The name is the info of the
+    /// channel if it's a C identifier, e.g. a topic name, PRODUCER_i or CONSUMER_i
+    /// otherwise. Producers and consumers are those of config, the owner of the vector.
+    pub fn vector_with_layout(
+        self,
+        prefix: &str,
+        config: &VectorConfig,
+        cacheline_size: usize,
+        index_size: usize,
+    ) -> Result<Self, ResourceError> {
+        if !cacheline_size.is_power_of_two()
+            || cacheline_size < size_of::<u64>()
+            || !is_supported_index_size(index_size)
+        {
+            error!("layout {cacheline_size} {index_size} not supported");
+            return Err(ResourceError::InvalidArgument);
+        }
+
+        let layout = Layout {
+            cacheline_size,
+            index_size,
+            descriptors: true,
+        };
+
+        Ok(self.layout(prefix, config, layout))
+    }
+
+    fn layout(mut self, prefix: &str, config: &VectorConfig, layout: Layout) -> Self {
+        let prefix = prefix.to_uppercase();
+
+        let _ = writeln!(
+            self.body,
+            "#define {prefix}_CACHELINE_SIZE {}",
+            layout.cacheline_size
+        );
+        let _ = writeln!(
+            self.body,
+            "#define {prefix}_INDEX_SIZE {}",
+            layout.index_size
+        );
+        let _ = writeln!(
+            self.body,
+            "#define {prefix}_SHM_SIZE {}\n",
+            config.calc_layout_shm_size(layout)
+        );
+
+        let channels = config
+            .producers
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c, format!("PRODUCER_{i}")))
+            .chain(
+                config
+                    .consumers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| (c, format!("CONSUMER_{i}"))),
+            );
+
+        let mut offset = 0;
+
+        for (channel, fallback) in channels {
+            let name = channel_name(config, channel).unwrap_or(fallback);
+            let usage = channel.kind.usage(&channel.queue, layout);
+
+            let _ = writeln!(self.body, "/* {:?} */", channel.kind);
+
+            let mut define = |field: &str, value: usize| {
+                let _ = writeln!(self.body, "#define {prefix}_{name}_{field} {value}");
+            };
+
+            define("OFFSET", offset);
+            define("SHM_SIZE", usage.shm_size);
+            define("MESSAGE_SIZE", channel.queue.message_size.get());
+            define("SLOTS", usage.slots);
+            define("SLOT_SIZE", usage.slot_size);
+
+            if channel.kind == ChannelKind::Queue {
+                let messages =
+                    Descriptor::shm_size(layout) + Queue::messages_offset(&channel.queue, layout);
+                define("MESSAGES_OFFSET", offset + messages);
+            }
+
+            self.body.push('\n');
+
+            offset += usage.shm_size;
+        }
+
+        self
+    }
+
+    pub fn render(&self) -> String {
+        let guard = &self.guard;
+
+        format!(
+            "/* generated by rtipc, do not edit */\n\
+             #ifndef {guard}\n\
+             #define {guard}\n\n\
+             #include <stdbool.h>\n\
+             #include <stddef.h>\n\
+             #include <stdint.h>\n\n\
+             #ifndef RTIPC_LAYOUT_ASSERT\n\
+             #ifdef __cplusplus\n\
+             #define RTIPC_LAYOUT_ASSERT(cond, msg) static_assert(cond, msg)\n\
+             #else\n\
+             #define RTIPC_LAYOUT_ASSERT(cond, msg) _Static_assert(cond, msg)\n\
+             #endif\n\
+             #endif\n\n\
+             {}\
+             #endif /* {guard} */\n",
+            self.body
+        )
+    }
+
+    /// Writes the header, e.g. from a build script, an unchanged header is left alone,
+    /// so its modification time doesn't trigger rebuilds of the C peers.
+    pub fn write(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let header = self.render();
+
+        if std::fs::read_to_string(&path).is_ok_and(|old| old == header) {
+            return Ok(());
+        }
+
+        std::fs::write(path, header)
+    }
+}
+
+/// Info of the channel as a C identifier, if it is one and no other channel has it.
+fn channel_name(config: &VectorConfig, channel: &ChannelConfig) -> Option<String> {
+    let info = std::str::from_utf8(&channel.queue.info).ok()?;
+
+    let valid = info
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && info.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    let unique = config
+        .producers
+        .iter()
+        .chain(&config.consumers)
+        .filter(|c| c.queue.info == channel.queue.info)
+        .count()
+        == 1;
+
+    (valid && unique).then(|| info.to_uppercase())
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+use std::path::Path;
+
+use serde::{Deserialize, Deserializer};
+
+use crate::error::*;
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+use crate::trace::*;
+use crate::{ChannelConfig, ChannelKind, QueueConfig, VectorConfig, mem_align};
+
+/// Info of a channel or a vector, a string or a sequence of bytes.
+pub(crate) fn info<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Info {
+        Text(String),
+        Bytes(Vec<u8>),
+    }
+
+    Ok(match Info::deserialize(deserializer)? {
+        Info::Text(text) => text.into_bytes(),
+        Info::Bytes(bytes) => bytes,
+    })
+}
+
+impl VectorConfig {
+    /// Vector of a json document, validated like a vector of a client.
+    #[cfg(feature = "json")]
+    pub fn from_json(json: &str) -> Result<Self, LoadError> {
+        let config: Self =
+            serde_json::from_str(json).map_err(|e| LoadError::Parse(e.to_string()))?;
+        config.validate(usize::MAX)?;
+        Ok(config)
+    }
+
+    /// Vector of a yaml document, validated like a vector of a client.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(yaml: &str) -> Result<Self, LoadError> {
+        let config: Self =
+            serde_yaml::from_str(yaml).map_err(|e| LoadError::Parse(e.to_string()))?;
+        config.validate(usize::MAX)?;
+        Ok(config)
+    }
+
+    /// Vector of a toml document, validated like a vector of a client.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(toml: &str) -> Result<Self, LoadError> {
+        let config: Self = toml::from_str(toml).map_err(|e| LoadError::Parse(e.to_string()))?;
+        config.validate(usize::MAX)?;
+        Ok(config)
+    }
+
+    /// Loads a vector from a config file, the extension picks the format:
+    /// .json with the json feature, .yaml or .yml with the yaml feature,
+    /// .toml with the toml feature.
+    #[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+
+        let parse: fn(&str) -> Result<Self, LoadError> =
+            match path.extension().and_then(|e| e.to_str()) {
+                #[cfg(feature = "json")]
+                Some("json") => Self::from_json,
+                #[cfg(feature = "yaml")]
+                Some("yaml" | "yml") => Self::from_yaml,
+                #[cfg(feature = "toml")]
+                Some("toml") => Self::from_toml,
+                _ => {
+                    error!("no loader for {}", path.display());
+                    return Err(LoadError::UnknownFormat);
+                }
+            };
+
+        let text = std::fs::read_to_string(path).map_err(LoadError::Io)?;
+
+        parse(&text)
+    }
+}
+
+/// Defaults of the channels of a profile, e.g. low-latency or bulk, only the message
+/// size and the info differ between the channels.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelProfile {
+    #[serde(default)]
+    pub additional_messages: usize,
+    #[serde(default)]
+    pub eventfd: bool,
+    #[serde(default)]
+    pub kind: ChannelKind,
+    /// message sizes are rounded up to a multiple of it, a power of two,
+    /// e.g. the block size of a DMA engine
+    pub alignment: Option<NonZeroUsize>,
+}
+
+impl ChannelProfile {
+    pub fn channel(&self, message_size: NonZeroUsize, info: &[u8]) -> ChannelConfig {
+        let message_size = match self.alignment {
+            Some(alignment) => {
+                NonZeroUsize::new(mem_align(message_size.get(), alignment.get())).unwrap()
+            }
+            None => message_size,
+        };
+
+        ChannelConfig {
+            queue: QueueConfig {
+                additional_messages: self.additional_messages,
+                message_size,
+                info: info.to_vec(),
+                schema: None,
+            },
+            kind: self.kind,
+            eventfd: self.eventfd,
+        }
+    }
+}
+
+/// Named channel profiles, every table of a toml file is a profile:
+///
+/// ```text
+/// [low-latency]
+/// kind = "conflated"
+///
+/// [bulk]
+/// additional_messages = 64
+/// eventfd = true
+/// alignment = 4096
+/// ```
+///
+/// The application picks the profile at runtime, e.g. by a command line argument.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct ChannelProfiles {
+    profiles: HashMap<String, ChannelProfile>,
+}
+
+impl ChannelProfiles {
+    #[cfg(feature = "toml")]
+    pub fn from_toml(toml: &str) -> Result<Self, LoadError> {
+        let profiles: Self = toml::from_str(toml).map_err(|e| LoadError::Parse(e.to_string()))?;
+        profiles.validate()?;
+        Ok(profiles)
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let text = std::fs::read_to_string(path).map_err(LoadError::Io)?;
+        Self::from_toml(&text)
+    }
+
+    /// Refuses alignments that aren't a power of two.
+    pub fn validate(&self) -> Result<(), LoadError> {
+        for (name, profile) in &self.profiles {
+            if profile
+                .alignment
+                .is_some_and(|alignment| !alignment.is_power_of_two())
+            {
+                return Err(LoadError::Parse(format!(
+                    "alignment of profile {name} isn't a power of two"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ChannelProfile> {
+        self.profiles.get(name)
+    }
+
+    pub fn insert(&mut self, name: &str, profile: ChannelProfile) {
+        self.profiles.insert(name.to_owned(), profile);
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::VecDeque;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd};
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::auth::Authenticator;
+use crate::error::*;
+use crate::header::has_descriptors;
+use crate::protocol::{create_control, is_resize, parse_control, parse_control_record};
+use crate::quota::QuotaLedger;
+use crate::resource::{Unsealed, VectorResource};
+use crate::socket::PeerCredentials;
+use crate::unix_message::{UnixMessageRx, UnixMessageTx};
+use crate::{ChannelConfig, Layout, ServerLimits, VectorConfig};
+
+/// Application defined configuration value, e.g. a rate limit or an enable flag.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigRecord {
+    pub key: u32,
+    pub value: Vec<u8>,
+}
+
+/// Called by Control::receive for every Config message.
+pub type ConfigHandler = Box<dyn Fn(&[ConfigRecord]) + Send + Sync>;
+
+/// Out-of-band messages exchanged over the socket of the handshake.
+/// Channels and indices are seen from the sender, the receiver gets
+/// AddConsumer for an AddProducer of the sender and vice versa.
+#[derive(Clone)]
+pub enum ControlMessage {
+    /// the peer is expected to answer with a Pong carrying the same token
+    Ping(u64),
+    Pong(u64),
+    /// asks the peer for its statistics
+    StatsQuery,
+    /// application defined statistics, answer to StatsQuery
+    Stats(Vec<u8>),
+    /// asks the peer to add a channel, e.g. by reconnecting with an extended vector
+    AddProducer(ChannelConfig),
+    AddConsumer(ChannelConfig),
+    /// the channel with this index isn't used anymore
+    CloseProducer(usize),
+    CloseConsumer(usize),
+    /// the peer is asked to stop using the vector
+    Shutdown,
+    /// the peer stopped using the vector, sent when its Control is dropped
+    Goodbye,
+    /// configuration update pushed by the peer, usually the server
+    Config(Vec<ConfigRecord>),
+    /// the peer grows the queues of the vector to this vconfig, seen from the receiver,
+    /// sent by ChannelVector::resize and answered by ChannelVector::accept_resize
+    Resize(VectorConfig),
+    /// the peer moved its consumers to the resized vector, false if it refused the resize
+    Resized(bool),
+    /// the peer that started the resize moved its consumers as well
+    Switched,
+}
+
+/// Checks a server applies to the vectors of its clients, see Server::set_limits,
+/// the vectors a client resizes to are checked the same way.
+#[derive(Clone, Default)]
+pub(crate) struct Admission {
+    pub limits: ServerLimits,
+    pub unsealed: Unsealed,
+    /// ledger and credentials of the client the resized vectors are charged to
+    pub quota: Option<(Arc<QuotaLedger>, PeerCredentials)>,
+}
+
+/// Connection to the peer that stays open after the handshake.
+/// Dropping it (or the ChannelVector still owning it) sends Goodbye to the peer.
+pub struct Control {
+    /// shared with the server for notifying the client on shutdown
+    socket: Arc<OwnedFd>,
+    auth: Authenticator,
+    /// protocol version negotiated by the handshake
+    version: u16,
+    config_handler: Option<ConfigHandler>,
+    answer_pings: bool,
+    admission: Admission,
+    /// vector received with the last Resize message, see ChannelVector::accept_resize
+    resize: Mutex<Option<VectorResource>>,
+    /// messages received while waiting for the peer during a resize
+    backlog: Mutex<VecDeque<ControlMessage>>,
+}
+
+impl Control {
+    pub(crate) fn new(socket: OwnedFd, auth: Authenticator, version: u16) -> Self {
+        Self {
+            socket: Arc::new(socket),
+            auth,
+            version,
+            config_handler: None,
+            answer_pings: false,
+            admission: Admission::default(),
+            resize: Mutex::new(None),
+            backlog: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Config messages are handed to handler and aren't returned by receive anymore.
+    pub fn set_config_handler(&mut self, handler: ConfigHandler) {
+        self.config_handler = Some(handler);
+    }
+
+    /// Ping messages are answered with Pong and aren't returned by receive anymore,
+    /// e.g. for a peer checking the connection with a keepalive.
+    pub fn set_answer_pings(&mut self, answer: bool) {
+        self.answer_pings = answer;
+    }
+
+    /// Vectors received with Resize messages are refused unless admission accepts them.
+    pub(crate) fn set_admission(&mut self, admission: Admission) {
+        self.admission = admission;
+    }
+
+    /// Layout of a vector the peer understands, e.g. for a resize.
+    pub(crate) fn layout(&self) -> Layout {
+        Layout {
+            descriptors: has_descriptors(self.version),
+            ..Layout::native()
+        }
+    }
+
+    /// Socket of the connection as long as the Control isn't dropped.
+    pub(crate) fn socket(&self) -> Weak<OwnedFd> {
+        Arc::downgrade(&self.socket)
+    }
+
+    pub fn send(&self, msg: &ControlMessage) -> Result<(), TransferError> {
+        send_control(self.socket.as_fd(), &self.auth, msg, self.version)
+    }
+
+    /// Blocks until the peer sends a message, fails with ENOMSG once the peer disconnected.
+    /// With a config handler, the handler is called for Config messages and receive keeps waiting,
+    /// the same applies to answered pings. A Resize exceeding the limits of the server
+    /// is answered with Resized(false) and fails receive.
+    pub fn receive(&self) -> Result<ControlMessage, TransferError> {
+        if let Some(msg) = self.backlog.lock().unwrap().pop_front() {
+            return Ok(msg);
+        }
+
+        self.next()
+    }
+
+    fn next(&self) -> Result<ControlMessage, TransferError> {
+        loop {
+            let mut msg = UnixMessageRx::receive(self.socket.as_raw_fd())?;
+            let fds = msg.take_fds();
+            let content = self.auth.verify(msg.content())?;
+
+            /* the peer waits for the answer of a refused Resize */
+            let msg = match self.parse(content, fds) {
+                Err(e) if is_resize(content) => {
+                    self.send(&ControlMessage::Resized(false))?;
+                    return Err(e);
+                }
+                msg => msg?,
+            };
+
+            match (msg, &self.config_handler) {
+                (ControlMessage::Config(records), Some(handler)) => handler(&records),
+                (ControlMessage::Ping(token), _) if self.answer_pings => {
+                    self.send(&ControlMessage::Pong(token))?
+                }
+                (msg, _) => return Ok(msg),
+            }
+        }
+    }
+
+    fn parse(
+        &self,
+        content: &[u8],
+        fds: VecDeque<OwnedFd>,
+    ) -> Result<ControlMessage, TransferError> {
+        let msg = parse_control(content, &self.admission.limits)?;
+
+        if let ControlMessage::Resize(_) = msg {
+            self.receive_resize(content, fds)?;
+        }
+
+        Ok(msg)
+    }
+
+    /// Maps the vector of a Resize message, see ChannelVector::accept_resize.
+    fn receive_resize(&self, content: &[u8], fds: VecDeque<OwnedFd>) -> Result<(), TransferError> {
+        let admission = &self.admission;
+        let request = parse_control_record(content)?.value;
+
+        let mut rsc = VectorResource::deserialize_backed(
+            request,
+            fds,
+            &admission.limits,
+            admission.unsealed,
+        )?;
+
+        /* the old vector stays charged until it is dropped after the switch */
+        if let Some((quota, cred)) = &admission.quota {
+            rsc.charge = Some(quota.charge(cred, &rsc.get_config(), rsc.layout())?);
+        }
+
+        *self.resize.lock().unwrap() = Some(rsc);
+        Ok(())
+    }
+
+    /// Blocks until the peer sends a message accepted by expected,
+    /// other messages are returned by the following calls of receive.
+    pub(crate) fn wait_for(
+        &self,
+        expected: fn(&ControlMessage) -> bool,
+    ) -> Result<ControlMessage, TransferError> {
+        loop {
+            let msg = self.next()?;
+
+            if expected(&msg) {
+                return Ok(msg);
+            }
+
+            self.backlog.lock().unwrap().push_back(msg);
+        }
+    }
+
+    /// Sends the Resize message for the vector of rsc with its shared memory attached.
+    pub(crate) fn send_resize(&self, rsc: &VectorResource) -> Result<(), TransferError> {
+        let (_, fds) = rsc.serialize()?;
+        let content = create_control(&ControlMessage::Resize(rsc.get_config()), self.version)?;
+        UnixMessageTx::new(self.auth.sign(content), fds).send(self.socket.as_raw_fd())?;
+        Ok(())
+    }
+
+    /// Vector of the last Resize message received, None if it was taken already.
+    pub(crate) fn take_resize(&self) -> Option<VectorResource> {
+        self.resize.lock().unwrap().take()
+    }
+
+    pub fn ping(&self, token: u64) -> Result<(), TransferError> {
+        self.send(&ControlMessage::Ping(token))
+    }
+
+    /// Pushes configuration records to the peer.
+    pub fn push_config(&self, records: &[ConfigRecord]) -> Result<(), TransferError> {
+        self.send(&ControlMessage::Config(records.to_vec()))
+    }
+
+    pub fn shutdown(&self) -> Result<(), TransferError> {
+        self.send(&ControlMessage::Shutdown)
+    }
+
+    /// socket for polling, readable when a message arrived or the peer disconnected
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
+
+pub(crate) fn send_control(
+    socket: BorrowedFd<'_>,
+    auth: &Authenticator,
+    msg: &ControlMessage,
+    version: u16,
+) -> Result<(), TransferError> {
+    let msg = UnixMessageTx::new(
+        auth.sign(create_control(msg, version)?),
+        Vec::with_capacity(0),
+    );
+    msg.send(socket.as_raw_fd())?;
+    Ok(())
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        /* the peer may be gone already */
+        let _ = self.send(&ControlMessage::Goodbye);
+    }
+}
@@ -0,0 +1,65 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::Layout;
+use crate::QueueConfig;
+use crate::error::*;
+use crate::mem_align;
+use crate::shm::{Chunk, Span};
+
+/// Array of atomic u64 values shared by both sides,
+/// message_size / 8 counters are available.
+pub(crate) struct CounterArray {
+    _chunk: Chunk,
+    counters: *mut u64,
+    len: usize,
+}
+
+impl CounterArray {
+    pub(crate) fn new(chunk: Chunk, config: &QueueConfig) -> Result<Self, ShmMapError> {
+        let len = config.message_size.get() / size_of::<u64>();
+
+        let size = NonZeroUsize::new(len * size_of::<u64>()).ok_or(ShmMapError::OutOfBounds)?;
+
+        let counters: *mut () = chunk.get_span_ptr(&Span { offset: 0, size })?;
+
+        if !counters.cast::<u64>().is_aligned() {
+            return Err(ShmMapError::Misalignment);
+        }
+
+        Ok(Self {
+            _chunk: chunk,
+            counters: counters.cast(),
+            len,
+        })
+    }
+
+    pub(crate) fn shm_size(config: &QueueConfig, layout: Layout) -> NonZeroUsize {
+        NonZeroUsize::new(mem_align(config.message_size.get(), layout.cacheline_size)).unwrap()
+    }
+
+    pub(crate) fn init(&self) {
+        for idx in 0..self.len {
+            self.counter(idx).store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    fn counter(&self, idx: usize) -> &AtomicU64 {
+        unsafe { AtomicU64::from_ptr(self.counters.add(idx)) }
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> Option<&AtomicU64> {
+        if idx < self.len {
+            Some(self.counter(idx))
+        } else {
+            None
+        }
+    }
+}
+
+// every CounterArray has its own shared memory region
+unsafe impl Send for CounterArray {}
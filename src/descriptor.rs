@@ -0,0 +1,110 @@
+use crate::error::*;
+use crate::shm::Chunk;
+use crate::trace::*;
+use crate::{ChannelKind, Layout, QueueConfig, mem_align, schema_fingerprint};
+
+/// "RTIC" before and its complement after the fields
+const CANARY: u32 = 0x43495452;
+
+/// First cache line of a channel chunk, written by the peer initializing the shared
+/// memory. The other peer compares it with the channel it computed, so builds with
+/// different offsets or channel configs fail before any queue operation.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Fields {
+    head: u32,
+    kind: u32,
+    additional_messages: u32,
+    message_size: u32,
+    /// offset of the chunk in the shared memory
+    offset: u64,
+    checksum: u32,
+    tail: u32,
+}
+
+pub(crate) struct Descriptor {
+    chunk: Chunk,
+    fields: Fields,
+}
+
+impl Descriptor {
+    pub(crate) fn shm_size(layout: Layout) -> usize {
+        if layout.descriptors {
+            mem_align(size_of::<Fields>(), layout.cacheline_size)
+        } else {
+            0
+        }
+    }
+
+    pub(crate) fn new(
+        chunk: Chunk,
+        kind: ChannelKind,
+        config: &QueueConfig,
+        offset: usize,
+    ) -> Result<Self, ShmMapError> {
+        let mut fields = Fields {
+            head: CANARY,
+            kind: kind.to_raw(),
+            additional_messages: config.additional_messages as u32,
+            message_size: config.message_size.get() as u32,
+            offset: offset as u64,
+            checksum: 0,
+            tail: !CANARY,
+        };
+
+        fields.checksum = Self::checksum(&fields);
+
+        /* the chunk has to hold the fields */
+        chunk.get_ptr::<Fields>(0)?;
+
+        Ok(Self { chunk, fields })
+    }
+
+    fn checksum(fields: &Fields) -> u32 {
+        let bytes = [
+            fields.kind.to_le_bytes(),
+            fields.additional_messages.to_le_bytes(),
+            fields.message_size.to_le_bytes(),
+            (fields.offset as u32).to_le_bytes(),
+            ((fields.offset >> 32) as u32).to_le_bytes(),
+        ]
+        .concat();
+
+        schema_fingerprint(&bytes) as u32
+    }
+
+    fn ptr(&self) -> *mut Fields {
+        self.chunk.get_ptr::<Fields>(0).unwrap()
+    }
+
+    pub(crate) fn init(&self) {
+        unsafe { self.ptr().write_volatile(self.fields) };
+    }
+
+    /// Compares the descriptor written by the peer with ours.
+    pub(crate) fn verify(&self) -> Result<(), ShmMapError> {
+        let found = unsafe { self.ptr().read_volatile() };
+
+        if found == self.fields {
+            return Ok(());
+        }
+
+        if found.head != CANARY || found.tail != !CANARY {
+            error!("channel at {}: canary overwritten", self.fields.offset);
+        } else {
+            error!(
+                "channel at {}: peer has kind {} with {}+{} messages of {} bytes at {}",
+                self.fields.offset,
+                found.kind,
+                crate::MIN_MSGS,
+                found.additional_messages,
+                found.message_size,
+                found.offset
+            );
+        }
+
+        Err(ShmMapError::DescriptorMismatch {
+            offset: self.fields.offset as usize,
+        })
+    }
+}
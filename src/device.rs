@@ -0,0 +1,78 @@
+use std::{num::NonZeroUsize, os::fd::BorrowedFd, ptr::NonNull};
+
+use crate::shm::MemoryRegion;
+
+/// Cache maintenance of [ptr, ptr + len), e.g. DC CVAC / DC IVAC loops on aarch64.
+pub type CacheOp = fn(ptr: *const u8, len: usize);
+
+/// Device memory mapped by the caller, e.g. a reserved-memory region shared with a
+/// coprocessor, see ChannelVector::with_region. The memory isn't checked like a received
+/// memfd, it stays mapped by the caller and isn't unmapped on drop.
+#[derive(Debug)]
+pub struct DeviceMemory {
+    ptr: NonNull<u8>,
+    size: NonZeroUsize,
+    clean: Option<CacheOp>,
+    invalidate: Option<CacheOp>,
+}
+
+impl DeviceMemory {
+    /// Cache coherent device memory.
+    ///
+    /// # Safety
+    ///
+    /// ptr has to point to size bytes mapped readable and writable until the vector
+    /// placed in the memory is dropped, no one else may use the memory meanwhile.
+    pub unsafe fn new(ptr: NonNull<u8>, size: NonZeroUsize) -> Self {
+        Self {
+            ptr,
+            size,
+            clean: None,
+            invalidate: None,
+        }
+    }
+
+    /// Memory the device accesses without snooping our caches. Only queues and counters
+    /// support it, the indices have to be mapped coherent, e.g. uncached.
+    pub fn with_cache_ops(self, clean: CacheOp, invalidate: CacheOp) -> Self {
+        Self {
+            clean: Some(clean),
+            invalidate: Some(invalidate),
+            ..self
+        }
+    }
+}
+
+impl MemoryRegion for DeviceMemory {
+    fn map(&self) -> NonNull<u8> {
+        self.ptr
+    }
+
+    fn size(&self) -> NonZeroUsize {
+        self.size
+    }
+
+    fn as_fd(&self) -> Option<BorrowedFd<'_>> {
+        None
+    }
+
+    fn coherent(&self) -> bool {
+        self.clean.is_none()
+    }
+
+    fn clean(&self, ptr: *const u8, len: usize) {
+        if let Some(clean) = self.clean {
+            clean(ptr, len);
+        }
+    }
+
+    fn invalidate(&self, ptr: *const u8, len: usize) {
+        if let Some(invalidate) = self.invalidate {
+            invalidate(ptr, len);
+        }
+    }
+}
+
+// the caller guarantees exclusive use of the memory, see DeviceMemory::new
+unsafe impl Send for DeviceMemory {}
+unsafe impl Sync for DeviceMemory {}
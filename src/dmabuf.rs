@@ -0,0 +1,119 @@
+use std::{
+    num::NonZeroUsize,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
+    ptr::NonNull,
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::{FcntlArg, OFlag, fcntl},
+    libc::{self, c_void},
+    sys::mman::{MapFlags, ProtFlags},
+};
+
+#[cfg(not(feature = "rustix"))]
+use nix::sys::mman::{mmap, munmap};
+
+#[cfg(feature = "rustix")]
+use crate::sys_rustix::{mmap, munmap};
+use crate::trace::*;
+use crate::unix::check_dmabuf;
+
+/// _IOW('b', 0, struct dma_buf_sync) from linux/dma-buf.h
+const DMA_BUF_IOCTL_SYNC: libc::c_ulong = 0x40086200;
+
+const DMA_BUF_SYNC_READ: u64 = 1;
+const DMA_BUF_SYNC_WRITE: u64 = 2;
+const DMA_BUF_SYNC_END: u64 = 4;
+
+/// dma-buf attached to a vector, e.g. a GPU or camera buffer. The buffers are passed
+/// by reference, the messages of the channels carry the index of the buffer, see
+/// ChannelVector::dmabuf. CPU access has to be bracketed by begin_cpu_access and
+/// end_cpu_access, the device may access the buffer at any other time.
+#[derive(Debug)]
+pub struct DmaBuf {
+    fd: OwnedFd,
+    ptr: NonNull<c_void>,
+    size: NonZeroUsize,
+    writable: bool,
+}
+
+impl DmaBuf {
+    /// Maps the whole buffer, read-only if the fd was opened read-only.
+    pub(crate) fn map(fd: OwnedFd) -> Result<Self, Errno> {
+        let size = check_dmabuf(fd.as_fd())?;
+
+        let flags = OFlag::from_bits_truncate(fcntl(&fd, FcntlArg::F_GETFL)?);
+        let writable = flags & OFlag::O_ACCMODE == OFlag::O_RDWR;
+
+        let prot = if writable {
+            ProtFlags::PROT_READ | ProtFlags::PROT_WRITE
+        } else {
+            ProtFlags::PROT_READ
+        };
+
+        let ptr = unsafe { mmap(None, size, prot, MapFlags::MAP_SHARED, &fd, 0) }
+            .inspect_err(|e| error!("mmap of dma-buf failed {e:?}"))?;
+
+        Ok(Self {
+            fd,
+            ptr,
+            size,
+            writable,
+        })
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr().cast()
+    }
+
+    pub fn size(&self) -> usize {
+        self.size.get()
+    }
+
+    /// false for buffers passed read-only, writes to them fault
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    fn sync(&self, flags: u64) -> Result<(), Errno> {
+        let res = unsafe { libc::ioctl(self.fd.as_raw_fd(), DMA_BUF_IOCTL_SYNC as _, &flags) };
+        Errno::result(res).map(drop)
+    }
+
+    fn access(write: bool) -> u64 {
+        if write {
+            DMA_BUF_SYNC_READ | DMA_BUF_SYNC_WRITE
+        } else {
+            DMA_BUF_SYNC_READ
+        }
+    }
+
+    /// Waits for the device and makes its writes visible to the CPU.
+    pub fn begin_cpu_access(&self, write: bool) -> Result<(), Errno> {
+        self.sync(Self::access(write))
+    }
+
+    /// Flushes the writes of the CPU for the device, write has to match begin_cpu_access.
+    pub fn end_cpu_access(&self, write: bool) -> Result<(), Errno> {
+        self.sync(Self::access(write) | DMA_BUF_SYNC_END)
+    }
+}
+
+impl AsFd for DmaBuf {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl Drop for DmaBuf {
+    fn drop(&mut self) {
+        if let Err(_e) = unsafe { munmap(self.ptr, self.size.get()) } {
+            error!("munmap of dma-buf failed with : {_e}");
+        }
+    }
+}
+
+// the mapping is owned by the buffer, access is synchronized by the user
+unsafe impl Send for DmaBuf {}
+unsafe impl Sync for DmaBuf {}
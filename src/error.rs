@@ -1,18 +1,51 @@
+use crate::queue::PopResult;
 use nix::errno::Errno;
 
 #[derive(Debug)]
 pub enum ShmMapError {
     OutOfBounds,
     Misalignment,
+    /// locking size bytes exceeds RLIMIT_MEMLOCK of the process
+    LockLimit {
+        size: usize,
+        limit: u64,
+    },
+    /// the descriptor of the channel at offset doesn't match the channel of the peer
+    DescriptorMismatch {
+        offset: usize,
+    },
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum HeaderError {
     SizeExceedsRequest,
     MagicMismatch,
     VersionMismatch,
     CachelineSizeMismatch,
+    InvalidCachelineSize,
     AtomicSizeMismatch,
+    EndiannessMismatch,
+    CookieMismatch,
+}
+
+/// Reason Arena::try_alloc didn't hand out a block.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ArenaError {
+    /// the payload doesn't fit into a block
+    PayloadTooLarge { len: usize, block_size: usize },
+    /// all blocks are in use
+    Exhausted,
+    /// index or length of the block don't fit into the u32 halves of a handle
+    HandleOverflow,
+}
+
+/// Reason Consumer::try_forward or Consumer::force_forward didn't forward a message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ForwardError {
+    /// the queues aren't placed in the same shared memory
+    SeparateMemory,
+    /// pop didn't return a new message
+    Pop(PopResult),
 }
 
 #[derive(Debug)]
@@ -22,19 +55,105 @@ pub enum ResourceError {
     ShmMapError(ShmMapError),
 }
 
+/// Reason VectorConfig::validate refused a vector.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// the vector has neither channels nor an arena nor heartbeat stamps
+    NoChannels,
+    /// message size or queue length of the channel don't fit into the u32 fields of a request
+    QueueOverflow { producer: bool, index: usize },
+    /// the infos make the request larger than the maximum message size
+    InfoTooLong { size: usize, max: usize },
+    /// the shared memory of the vector exceeds max, size is usize::MAX if it overflows
+    ShmSizeExceeded { size: usize, max: usize },
+    /// the transport can't pass eventfds, e.g. the tcp or vsock handshake
+    EventFdsUnsupported,
+    /// the name of a topic is empty or taken, or its depth or sample size is 0
+    InvalidTopic,
+    /// block size or number of blocks of the arena don't fit into the u32 fields of a request,
+    /// or the arena has more than MAX_ARENA_BLOCKS blocks
+    ArenaOverflow,
+    /// the number of channels or fds, or the index of a channel don't fit into the u32 fields
+    /// of a request
+    ChannelOverflow,
+    /// cache line size or index width of the layout don't fit into the u16 fields of a header
+    LayoutOverflow,
+}
+
 #[derive(Debug)]
 pub enum RequestError {
     OutOfBounds,
+    InvalidChannelKind,
+    MalformedRecord(u16),
+    MissingRecord(u16),
+    UnknownRecord(u16),
+    SignatureMismatch,
+    /// a signed reply doesn't carry the nonce of the request, e.g. a replayed response
+    NonceMismatch,
+    LimitExceeded,
     HeaderError(HeaderError),
 }
 
+/// Reason a server refused a connection, sent to the client in the response.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rejection {
+    pub code: u32,
+    pub message: String,
+}
+
+impl Rejection {
+    /// the server failed to handle the request, e.g. a malformed request
+    pub const UNSPECIFIED: u32 = 0;
+
+    pub fn new(code: u32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum TransferError {
+    ConfigError(ConfigError),
     ResourceError(ResourceError),
     RequestError(RequestError),
     MissingFileDescriptor,
-    Rejected,
+    /// the number of received fds doesn't match the shared memory fd plus the eventfds
+    FileDescriptorCountMismatch {
+        expected: usize,
+        received: usize,
+    },
+    /// the size of the received shared memory doesn't match the layout of the vector
+    ShmSizeMismatch {
+        expected: usize,
+        received: usize,
+    },
+    Rejected(Rejection),
     ResponseError,
+    /// the peer didn't complete the handshake before the deadline
+    TimedOut,
+    /// no client is waiting to be accepted
+    WouldBlock,
+}
+
+/// Reason VectorConfig::load refused a config file.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    /// the document doesn't describe a vector, with the message of the parser
+    Parse(String),
+    /// the extension of the file has no loader, see the json and yaml features
+    UnknownFormat,
+    ConfigError(ConfigError),
+}
+
+#[cfg(feature = "serde")]
+impl From<ConfigError> for LoadError {
+    fn from(e: ConfigError) -> LoadError {
+        LoadError::ConfigError(e)
+    }
 }
 
 impl From<Errno> for ResourceError {
@@ -49,6 +168,12 @@ impl From<ShmMapError> for ResourceError {
     }
 }
 
+impl From<ConfigError> for TransferError {
+    fn from(e: ConfigError) -> TransferError {
+        TransferError::ConfigError(e)
+    }
+}
+
 impl From<ResourceError> for TransferError {
     fn from(e: ResourceError) -> TransferError {
         TransferError::ResourceError(e)
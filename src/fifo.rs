@@ -0,0 +1,103 @@
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+
+use nix::{
+    Result,
+    errno::Errno,
+    fcntl::{OFlag, open},
+    libc,
+    sys::stat::{Mode, fstat},
+    unistd::{mkfifo, read, unlink, write},
+};
+
+use crate::trace::*;
+use crate::unix::random_u64;
+
+/// Notification fd of a channel, stands in for the eventfd of Linux. It's a FIFO opened
+/// for reading and writing, so both peers share the one fd passed in the handshake.
+/// Each written byte counts as one event, like an eventfd in semaphore mode.
+/// kqueue user events of macOS can't be passed as an fd.
+#[derive(Debug)]
+pub struct EventFd(OwnedFd);
+
+impl EventFd {
+    pub fn new() -> Result<Self> {
+        eventfd_create()
+    }
+
+    /// Takes the last event, fails with EAGAIN if there is none.
+    pub fn read(&self) -> Result<u64> {
+        let mut buf = [0u8; 1];
+
+        match read(self.0.as_fd(), &mut buf)? {
+            0 => Err(Errno::EAGAIN),
+            _ => Ok(1),
+        }
+    }
+
+    /// Adds value events, fails with EAGAIN if the FIFO is full.
+    pub fn write(&self, value: u64) -> Result<usize> {
+        let events = value.min(libc::PIPE_BUF as u64) as usize;
+
+        write(self.0.as_fd(), &vec![1u8; events])
+    }
+
+    /// # Safety
+    ///
+    /// fd has to be a FIFO opened with O_RDWR and O_NONBLOCK.
+    pub unsafe fn from_owned_fd(fd: OwnedFd) -> Self {
+        Self(fd)
+    }
+}
+
+impl AsFd for EventFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl AsRawFd for EventFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl From<EventFd> for OwnedFd {
+    fn from(evd: EventFd) -> Self {
+        evd.0
+    }
+}
+
+/// The FIFO is unlinked once it's open, only the fds keep it alive.
+pub(crate) fn eventfd_create() -> Result<EventFd> {
+    let path = std::env::temp_dir().join(format!(
+        "rtipc-{}-{:x}.fifo",
+        std::process::id(),
+        random_u64()?
+    ));
+
+    mkfifo(&path, Mode::S_IRUSR | Mode::S_IWUSR)
+        .inspect_err(|e| error!("mkfifo {path:?} failed {e:?}"))?;
+
+    let fd = open(
+        &path,
+        OFlag::O_RDWR | OFlag::O_NONBLOCK | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    );
+
+    let _ = unlink(&path);
+
+    Ok(EventFd(
+        fd.inspect_err(|e| error!("open {path:?} failed {e:?}"))?,
+    ))
+}
+
+pub(crate) fn into_eventfd(fd: OwnedFd) -> Result<EventFd> {
+    let stat = fstat(fd.as_fd()).inspect_err(|e| error!("fstat failed {e:?}"))?;
+
+    if stat.st_mode & libc::S_IFMT != libc::S_IFIFO {
+        error!("fd is not a FIFO");
+        return Err(Errno::EBADF);
+    }
+
+    Ok(EventFd(fd))
+}
@@ -0,0 +1,120 @@
+use std::os::fd::BorrowedFd;
+
+use flatbuffers::{Follow, InvalidFlatbuffer, Verifiable, VerifierOptions};
+
+use crate::channel::{ChannelVector, Consumer, Producer};
+use crate::error::*;
+use crate::queue::{ForcePushResult, PopResult, TryPushResult};
+use crate::trace::*;
+
+/// u32 size of the buffer in native byte order and 4 reserved bytes, in front of the
+/// buffer of a slot, so the buffer keeps the 8 byte alignment of the slot
+const SLOT_HEADER_SIZE: usize = 8;
+
+/// Producer of a queue carrying FlatBuffers, a FlatBufferBuilder builds back to front
+/// into its own buffer, the finished buffer is copied into the slot once.
+pub struct FlatbufProducer {
+    producer: Producer<u64>,
+}
+
+impl FlatbufProducer {
+    /// Copies a finished buffer, e.g. finished_data of a FlatBufferBuilder after finish,
+    /// into the current message. The message isn't pushed yet.
+    /// Fails if the buffer exceeds the slot, the current message stays as it was.
+    pub fn write(&mut self, data: &[u8]) -> Result<(), ShmMapError> {
+        let slot = self.producer.message_slot();
+
+        let Some(buf) = slot.get_mut(SLOT_HEADER_SIZE..SLOT_HEADER_SIZE + data.len()) else {
+            error!("buffer of {} bytes exceeds the slot", data.len());
+            return Err(ShmMapError::OutOfBounds);
+        };
+
+        buf.copy_from_slice(data);
+
+        slot[..4].copy_from_slice(&(data.len() as u32).to_ne_bytes());
+        slot[4..SLOT_HEADER_SIZE].fill(0);
+
+        Ok(())
+    }
+
+    pub fn force_push(&mut self) -> ForcePushResult {
+        self.producer.force_push()
+    }
+
+    pub fn try_push(&mut self) -> TryPushResult {
+        self.producer.try_push()
+    }
+
+    pub fn eventfd(&self) -> Option<BorrowedFd<'_>> {
+        self.producer.eventfd()
+    }
+}
+
+/// Consumer of a queue carrying FlatBuffers, the root table is read in place.
+pub struct FlatbufConsumer {
+    consumer: Consumer<u64>,
+    options: VerifierOptions,
+}
+
+impl FlatbufConsumer {
+    /// The buffer of the current message.
+    pub fn data(&self) -> Option<&[u8]> {
+        let slot = self.consumer.message_slot()?;
+
+        let size = u32::from_ne_bytes(slot[..4].try_into().unwrap()) as usize;
+
+        /* a corrupt size is caught by the verifier */
+        let buf = &slot[SLOT_HEADER_SIZE..];
+
+        Some(&buf[..size.min(buf.len())])
+    }
+
+    /// Verifies the current message and returns its root, e.g. a table of the generated
+    /// code. The root borrows the slot, it has to be dropped before the next pop.
+    pub fn root<'a, T>(&'a self) -> Option<Result<T::Inner, InvalidFlatbuffer>>
+    where
+        T: 'a + Follow<'a> + Verifiable,
+    {
+        let data = self.data()?;
+        Some(flatbuffers::root_with_opts::<T>(&self.options, data))
+    }
+
+    /// Limits of the verifier, e.g. the depth and table count for messages of an untrusted peer.
+    pub fn set_verifier_options(&mut self, options: VerifierOptions) {
+        self.options = options;
+    }
+
+    pub fn pop(&mut self) -> PopResult {
+        self.consumer.pop()
+    }
+
+    /// See Consumer::pop_spin.
+    pub fn pop_spin(&mut self, polls: u32) -> PopResult {
+        self.consumer.pop_spin(polls)
+    }
+
+    pub fn flush(&mut self) -> PopResult {
+        self.consumer.flush()
+    }
+
+    pub fn eventfd(&self) -> Option<BorrowedFd<'_>> {
+        self.consumer.eventfd()
+    }
+}
+
+impl ChannelVector {
+    /// Takes a queue producer for FlatBuffers,
+    /// the message size of the channel has to hold the 8 byte slot header.
+    pub fn take_flatbuf_producer(&mut self, index: usize) -> Option<FlatbufProducer> {
+        let producer = self.take_producer::<u64>(index)?;
+        Some(FlatbufProducer { producer })
+    }
+
+    pub fn take_flatbuf_consumer(&mut self, index: usize) -> Option<FlatbufConsumer> {
+        let consumer = self.take_consumer::<u64>(index)?;
+        Some(FlatbufConsumer {
+            consumer,
+            options: VerifierOptions::default(),
+        })
+    }
+}
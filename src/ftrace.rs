@@ -0,0 +1,100 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Cursor, Write};
+use std::os::fd::AsFd;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use nix::{errno::Errno, libc, unistd::write};
+
+use crate::trace::*;
+
+const TRACE_MARKERS: [&str; 2] = [
+    "/sys/kernel/tracing/trace_marker",
+    "/sys/kernel/debug/tracing/trace_marker",
+];
+
+static MARKER: OnceLock<File> = OnceLock::new();
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Writes a marker for every tracepoint of the queues to the ftrace buffer, so the
+/// message flow lines up with the scheduler events when chasing deadline misses, e.g.
+///
+/// ```text
+/// rtipc: push queue=0x7f3a1c000040 depth=2
+/// ```
+///
+/// The tracepoints and their arguments are those of the usdt feature. Every marker
+/// is a write syscall, while disabled a tracepoint costs a relaxed load.
+/// Needs write access to trace_marker of tracefs, usually root.
+pub fn enable() -> Result<(), Errno> {
+    let mut result = Err(Errno::ENOENT);
+
+    for path in TRACE_MARKERS {
+        result = enable_at(path);
+        if result.is_ok() {
+            break;
+        }
+    }
+
+    result
+}
+
+/// Like enable with the trace_marker at path, e.g. of an ftrace instance. The file
+/// opened first is kept for the lifetime of the process, later paths are ignored.
+pub fn enable_at(path: impl AsRef<Path>) -> Result<(), Errno> {
+    let path = path.as_ref();
+
+    if MARKER.get().is_none() {
+        let file = OpenOptions::new().write(true).open(path).map_err(|e| {
+            error!("opening {} failed {e}", path.display());
+            Errno::from_raw(e.raw_os_error().unwrap_or(libc::EIO))
+        })?;
+
+        /* a concurrent enable may have won, its file is used */
+        let _ = MARKER.set(file);
+    }
+
+    ENABLED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Writes the marker of tracepoint name, see crate::tracepoint for the arguments.
+pub(crate) fn marker(name: &'static str, args: &[u64]) {
+    let Some(file) = MARKER.get() else {
+        return;
+    };
+
+    let names: &[&str] = match name {
+        "push" => &["queue", "depth"],
+        "pop" => &["queue", "message", "size"],
+        "eventfd_write" => &["queue", "fd"],
+        _ => &["queue"],
+    };
+
+    /* formatted on the stack, the hot path doesn't allocate */
+    let mut buf = [0u8; 128];
+    let mut cursor = Cursor::new(&mut buf[..]);
+
+    let _ = write!(cursor, "rtipc: {name}");
+    for (arg, value) in names.iter().zip(args) {
+        let _ = match *arg {
+            "queue" | "message" => write!(cursor, " {arg}={value:#x}"),
+            _ => write!(cursor, " {arg}={value}"),
+        };
+    }
+    let _ = writeln!(cursor);
+
+    let len = cursor.position() as usize;
+
+    /* a marker lost to a full buffer isn't worth an error in the hot path */
+    let _ = write(file.as_fd(), &buf[..len]);
+}
@@ -1,13 +1,52 @@
-use std::mem::size_of;
-
-use crate::Index;
+use crate::Layout;
+use crate::cacheline;
 use crate::error::*;
+use crate::index_size;
 use crate::max_cacheline_size;
+use crate::trace::*;
 
 const RTIC_MAGIC: u16 = 0x1f0c;
-const RTIC_VERSION: u16 = 2;
 
-#[repr(C)]
+/// version of the TLV protocol written by default
+pub const RTIC_VERSION: u16 = 9;
+
+/// oldest version of the TLV protocol still spoken, requests are answered in their
+/// version, so servers and clients of a fleet can be upgraded independently.
+/// Requests of FIXED_LAYOUT_VERSION predate the TLV protocol and are accepted as well.
+pub const MIN_VERSION: u16 = 8;
+
+/// first version with layout descriptors at the start of every channel
+const DESCRIPTOR_VERSION: u16 = 9;
+
+/// version of the fixed request layout of librtipc before the TLV protocol,
+/// still accepted by parse_request and spoken with ConnectOptions::legacy
+pub(crate) const FIXED_LAYOUT_VERSION: u16 = 2;
+
+const ENDIANNESS_LITTLE: u8 = 1;
+const ENDIANNESS_BIG: u8 = 2;
+
+/// header of FIXED_LAYOUT_VERSION:
+/// magic, version, cacheline_size, atomic_size as native endian u16
+pub(crate) const FIXED_HEADER_SIZE: usize = 8;
+
+/// magic, version, cacheline_size, atomic_size as little endian u16,
+/// followed by the endianness of the shared memory, 3 reserved bytes
+/// and the cookie as little endian u32
+pub const HEADER_SIZE: usize = 16;
+
+const COOKIE_OFFSET: usize = 12;
+
+/// Fields of a verified header.
+pub(crate) struct HeaderInfo {
+    pub version: u16,
+    /// alignment used for the shared memory layout
+    pub cacheline_size: usize,
+    /// width of the queue indices in shared memory
+    pub atomic_size: usize,
+    /// per-deployment application id, always 0 for FIXED_LAYOUT_VERSION
+    pub cookie: u32,
+}
+
 struct Header {
     magic: u16,
     version: u16,
@@ -15,57 +54,175 @@ struct Header {
     atomic_size: u16,
 }
 
-pub const HEADER_SIZE: usize = size_of::<Header>();
+impl Header {
+    fn read(buf: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Self {
+        let field = |idx: usize| from_bytes([buf[2 * idx], buf[2 * idx + 1]]);
+
+        Self {
+            magic: field(0),
+            version: field(1),
+            cacheline_size: field(2),
+            atomic_size: field(3),
+        }
+    }
+}
+
+fn native_endianness() -> u8 {
+    if cfg!(target_endian = "little") {
+        ENDIANNESS_LITTLE
+    } else {
+        ENDIANNESS_BIG
+    }
+}
 
-pub(crate) fn verify_header(buf: &[u8]) -> Result<(), HeaderError> {
-    if buf.len() < size_of::<Header>() {
+/// Verifies the header of a message.
+/// The cache line size and atomic size of the peer may differ from ours,
+/// except for FIXED_LAYOUT_VERSION.
+pub(crate) fn verify_header(buf: &[u8]) -> Result<HeaderInfo, HeaderError> {
+    if buf.len() < FIXED_HEADER_SIZE {
         return Err(HeaderError::SizeExceedsRequest);
     }
 
-    let cacheline_size: u16 = max_cacheline_size().try_into().unwrap();
-    let atomic_size: u16 = std::mem::size_of::<Index>().try_into().unwrap();
+    let le = Header::read(buf, u16::from_le_bytes);
+    let ne = Header::read(buf, u16::from_ne_bytes);
+
+    let header = if le.magic == RTIC_MAGIC && is_supported_version(le.version) {
+        if buf.len() < HEADER_SIZE {
+            return Err(HeaderError::SizeExceedsRequest);
+        }
 
-    let ptr: *const Header = buf.as_ptr() as *const Header;
+        /* the shared memory content is always in native byte order */
+        if buf[8] != native_endianness() {
+            return Err(HeaderError::EndiannessMismatch);
+        }
 
-    let header = unsafe { ptr.read_unaligned() };
+        le
+    } else if ne.magic == RTIC_MAGIC && ne.version == FIXED_LAYOUT_VERSION {
+        if ne.cacheline_size as usize != max_cacheline_size() {
+            return Err(HeaderError::CachelineSizeMismatch);
+        }
 
-    if header.magic != RTIC_MAGIC {
+        if ne.atomic_size as usize != index_size() {
+            return Err(HeaderError::AtomicSizeMismatch);
+        }
+
+        ne
+    } else if le.magic == RTIC_MAGIC || ne.magic == RTIC_MAGIC {
+        return Err(HeaderError::VersionMismatch);
+    } else {
         return Err(HeaderError::MagicMismatch);
+    };
+
+    let cacheline_size = header.cacheline_size as usize;
+
+    /* u64 atomics are placed at cache line boundaries */
+    if !cacheline::is_valid(cacheline_size) || cacheline_size < size_of::<u64>() {
+        return Err(HeaderError::InvalidCachelineSize);
     }
 
-    if header.version != RTIC_VERSION {
-        return Err(HeaderError::VersionMismatch);
+    let cookie = if header.version == FIXED_LAYOUT_VERSION {
+        0
+    } else {
+        u32::from_le_bytes(buf[COOKIE_OFFSET..HEADER_SIZE].try_into().unwrap())
+    };
+
+    Ok(HeaderInfo {
+        version: header.version,
+        cacheline_size,
+        atomic_size: header.atomic_size as usize,
+        cookie,
+    })
+}
+
+/// Whether version is a TLV version we still speak.
+pub(crate) fn is_supported_version(version: u16) -> bool {
+    (MIN_VERSION..=RTIC_VERSION).contains(&version)
+}
+
+/// Whether the channels of a vector negotiated in version start with descriptors.
+pub(crate) fn has_descriptors(version: u16) -> bool {
+    version >= DESCRIPTOR_VERSION
+}
+
+/// Version of a request for a vector with layout, the oldest one knowing its channels.
+pub(crate) fn layout_version(layout: Layout) -> u16 {
+    if layout.descriptors {
+        RTIC_VERSION
+    } else {
+        MIN_VERSION
     }
+}
 
-    if header.cacheline_size != cacheline_size {
-        return Err(HeaderError::CachelineSizeMismatch);
+/// Cache line size and index width of layout as the u16 fields of a header.
+pub(crate) fn layout_fields(layout: Layout) -> Result<(u16, u16), ConfigError> {
+    let cacheline_size = u16::try_from(layout.cacheline_size);
+    let atomic_size = u16::try_from(layout.index_size);
+
+    match (cacheline_size, atomic_size) {
+        (Ok(cacheline_size), Ok(atomic_size)) => Ok((cacheline_size, atomic_size)),
+        _ => {
+            error!(
+                "layout {} {} exceeds the header",
+                layout.cacheline_size, layout.index_size
+            );
+            Err(ConfigError::LayoutOverflow)
+        }
     }
+}
 
-    if header.atomic_size != atomic_size {
-        return Err(HeaderError::AtomicSizeMismatch);
+pub(crate) fn write_header(
+    buf: &mut [u8],
+    layout: Layout,
+    version: u16,
+) -> Result<(), ConfigError> {
+    if buf.len() < HEADER_SIZE {
+        return Err(ConfigError::InfoTooLong {
+            size: HEADER_SIZE,
+            max: buf.len(),
+        });
     }
 
+    let (cacheline_size, atomic_size) = layout_fields(layout)?;
+
+    buf[0..2].copy_from_slice(&RTIC_MAGIC.to_le_bytes());
+    buf[2..4].copy_from_slice(&version.to_le_bytes());
+    buf[4..6].copy_from_slice(&cacheline_size.to_le_bytes());
+    buf[6..8].copy_from_slice(&atomic_size.to_le_bytes());
+    buf[8] = native_endianness();
+    buf[9..HEADER_SIZE].fill(0);
+
     Ok(())
 }
 
-pub(crate) fn write_header(buf: &mut [u8]) {
-    if buf.len() < size_of::<Header>() {
-        return;
+/// Writes the header of FIXED_LAYOUT_VERSION in native byte order,
+/// as the C librtipc does.
+pub(crate) fn write_fixed_header(buf: &mut [u8]) -> Result<(), ConfigError> {
+    if buf.len() < FIXED_HEADER_SIZE {
+        return Err(ConfigError::InfoTooLong {
+            size: FIXED_HEADER_SIZE,
+            max: buf.len(),
+        });
     }
 
-    let cacheline_size: u16 = max_cacheline_size().try_into().unwrap();
-    let atomic_size: u16 = std::mem::size_of::<Index>().try_into().unwrap();
+    let (cacheline_size, atomic_size) = layout_fields(Layout {
+        cacheline_size: max_cacheline_size(),
+        index_size: index_size(),
+        descriptors: false,
+    })?;
 
-    let header = Header {
-        magic: RTIC_MAGIC,
-        version: RTIC_VERSION,
-        cacheline_size,
-        atomic_size,
-    };
+    buf[0..2].copy_from_slice(&RTIC_MAGIC.to_ne_bytes());
+    buf[2..4].copy_from_slice(&FIXED_LAYOUT_VERSION.to_ne_bytes());
+    buf[4..6].copy_from_slice(&cacheline_size.to_ne_bytes());
+    buf[6..8].copy_from_slice(&atomic_size.to_ne_bytes());
 
-    let ptr: *mut Header = buf.as_ptr() as *mut Header;
+    Ok(())
+}
 
-    unsafe {
-        std::ptr::write(ptr, header);
-    };
+/// Sets the cookie of a message written by write_header.
+pub(crate) fn write_cookie(buf: &mut [u8], cookie: u32) {
+    if buf.len() < HEADER_SIZE {
+        return;
+    }
+
+    buf[COOKIE_OFFSET..HEADER_SIZE].copy_from_slice(&cookie.to_le_bytes());
 }
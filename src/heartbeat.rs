@@ -0,0 +1,88 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use nix::time::{ClockId, clock_gettime};
+
+use crate::Layout;
+use crate::error::*;
+use crate::shm::Chunk;
+
+/// Liveness stamps of both peers, placed in the shared memory region behind the arena.
+/// Every peer writes the CLOCK_MONOTONIC time of its last beat into its own cache line,
+/// the owner of the shared memory uses the first one.
+pub struct Heartbeat {
+    _chunk: Chunk,
+    own: *mut u64,
+    peer: *mut u64,
+}
+
+impl Heartbeat {
+    pub(crate) fn new(chunk: Chunk, layout: Layout, owner: bool) -> Result<Self, ShmMapError> {
+        let first: *mut u64 = chunk.get_ptr(0)?;
+        let second: *mut u64 = chunk.get_ptr(layout.cacheline_size)?;
+
+        if !first.is_aligned() || !second.is_aligned() {
+            return Err(ShmMapError::Misalignment);
+        }
+
+        let (own, peer) = if owner {
+            (first, second)
+        } else {
+            (second, first)
+        };
+
+        Ok(Self {
+            _chunk: chunk,
+            own,
+            peer,
+        })
+    }
+
+    pub(crate) fn shm_size(layout: Layout) -> NonZeroUsize {
+        NonZeroUsize::new(2 * layout.cacheline_size).unwrap()
+    }
+
+    pub(crate) fn init(&self) {
+        self.stamp(self.own).store(0, Ordering::SeqCst);
+        self.stamp(self.peer).store(0, Ordering::SeqCst);
+    }
+
+    fn stamp(&self, ptr: *mut u64) -> &AtomicU64 {
+        unsafe { AtomicU64::from_ptr(ptr) }
+    }
+
+    pub(crate) fn now() -> u64 {
+        /* CLOCK_MONOTONIC never fails on linux */
+        let ts = clock_gettime(ClockId::CLOCK_MONOTONIC).unwrap();
+        Duration::from(ts).as_nanos() as u64
+    }
+
+    /// Tells the peer we are alive, has to be called periodically,
+    /// more often than the timeout the peer passes to peer_alive.
+    pub fn beat(&self) {
+        self.stamp(self.own).store(Self::now(), Ordering::Release);
+    }
+
+    /// Time since the last beat of the peer, None if the peer never did beat.
+    pub fn peer_silence(&self) -> Option<Duration> {
+        let stamp = self.stamp(self.peer).load(Ordering::Acquire);
+
+        if stamp == 0 {
+            return None;
+        }
+
+        Some(Duration::from_nanos(Self::now().saturating_sub(stamp)))
+    }
+
+    /// Returns true if the peer did beat within timeout.
+    pub fn peer_alive(&self, timeout: Duration) -> bool {
+        self.peer_silence()
+            .is_some_and(|silence| silence <= timeout)
+    }
+}
+
+// the heartbeat has its own shared memory region
+unsafe impl Send for Heartbeat {}
+// only atomics are accessed
+unsafe impl Sync for Heartbeat {}
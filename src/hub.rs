@@ -0,0 +1,109 @@
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::sync::Weak;
+
+use nix::unistd::dup;
+
+use crate::channel::{BroadcastProducer, ChannelVector};
+use crate::error::*;
+use crate::resource::VectorResource;
+use crate::{ChannelConfig, ChannelKind, EventFd, Layout, QueueConfig, VectorConfig};
+
+/// Broadcast channel whose shared memory is mapped by the vectors of several clients,
+/// e.g. a server fanning one stream out to its client processes. Clients are attached
+/// by Server::accept_broadcast, each one gets an eventfd of its own that push writes.
+/// A client stays subscribed until the vector returned for it by accept_broadcast is dropped.
+pub struct BroadcastHub<T: Copy> {
+    producer: BroadcastProducer<T>,
+    /// vector sent to the clients, seen from the hub
+    vconfig: VectorConfig,
+    layout: Layout,
+    shmfd: OwnedFd,
+    /// eventfd of every client and the socket of its control connection
+    subscribers: Vec<(EventFd, Weak<OwnedFd>)>,
+}
+
+impl<T: Copy> BroadcastHub<T> {
+    pub fn new(config: QueueConfig) -> Result<Self, TransferError> {
+        let channel = ChannelConfig {
+            kind: ChannelKind::Broadcast,
+            ..ChannelConfig::new(config, false)
+        };
+
+        let vconfig = VectorConfig {
+            producers: vec![channel],
+            ..Default::default()
+        };
+
+        vconfig.validate(usize::MAX)?;
+
+        let layout = Layout::native();
+        let mut rsc = VectorResource::allocate_layout(&vconfig, layout)?;
+        let shmfd = dup(rsc.shmfd())?;
+
+        /* no client maps the memory yet, the hub initializes it */
+        rsc.owner = false;
+
+        let mut vec = ChannelVector::new(rsc)?;
+
+        let producer = vec
+            .take_broadcast_producer(0)
+            .ok_or(ResourceError::InvalidArgument)?;
+
+        /* every client is notified by an eventfd of its own */
+        let vconfig = VectorConfig {
+            producers: vec![ChannelConfig {
+                eventfd: true,
+                ..vconfig.producers[0].clone()
+            }],
+            ..vconfig
+        };
+
+        Ok(Self {
+            producer,
+            vconfig,
+            layout,
+            shmfd,
+            subscribers: Vec::new(),
+        })
+    }
+
+    pub fn current_message(&mut self) -> &mut T {
+        self.producer.current_message()
+    }
+
+    /// Publishes the current message and notifies every subscribed client.
+    pub fn push(&mut self) {
+        self.producer.push();
+
+        self.subscribers
+            .retain(|(_, socket)| socket.strong_count() > 0);
+
+        for (eventfd, _) in &self.subscribers {
+            let _ = eventfd.write(1);
+        }
+    }
+
+    /// Number of clients subscribed.
+    pub fn subscribers(&self) -> usize {
+        self.subscribers
+            .iter()
+            .filter(|(_, socket)| socket.strong_count() > 0)
+            .count()
+    }
+
+    pub(crate) fn vconfig(&self) -> &VectorConfig {
+        &self.vconfig
+    }
+
+    pub(crate) fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub(crate) fn shmfd(&self) -> BorrowedFd<'_> {
+        self.shmfd.as_fd()
+    }
+
+    pub(crate) fn subscribe(&mut self, eventfd: EventFd, socket: Weak<OwnedFd>) {
+        self.subscribers.push((eventfd, socket));
+    }
+}
@@ -1,37 +1,174 @@
+/* without the handshake parts of the resource, pool and queue code have no users */
+#![cfg_attr(not(feature = "socket"), allow(dead_code))]
+
+#[cfg(target_os = "android")]
+mod android;
+mod arena;
+#[cfg(feature = "socket")]
+mod auth;
+mod broadcast;
 #[cfg(feature = "predefined_cacheline_size")]
 mod cache_env;
 #[cfg(not(feature = "predefined_cacheline_size"))]
 mod cache_linux;
+mod cacheline;
+#[cfg(feature = "capnp")]
+mod capnproto;
 mod channel;
+mod cheader;
+#[cfg(feature = "serde")]
+mod config_file;
+#[cfg(feature = "socket")]
+mod control;
+mod counters;
+mod descriptor;
+mod device;
+mod dmabuf;
 pub mod error;
+#[cfg(target_os = "macos")]
+mod fifo;
+#[cfg(feature = "flatbuffers")]
+mod flatbuf;
+#[cfg(feature = "ftrace")]
+pub mod ftrace;
+#[cfg(feature = "socket")]
 mod header;
+mod heartbeat;
+#[cfg(feature = "socket")]
+mod hub;
+#[cfg(target_os = "macos")]
+mod macos;
+mod metrics;
+mod mpsc;
+mod pool;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+#[cfg(feature = "socket")]
 mod protocol;
 mod queue;
+#[cfg(feature = "socket")]
+mod quota;
+#[cfg(not(feature = "socket"))]
+mod quota {
+    /// Without the servers no vector is charged to the quota of a client.
+    #[derive(Debug)]
+    pub(crate) enum QuotaCharge {}
+}
+mod report;
 mod resource;
+#[cfg(feature = "rt")]
+pub mod rt;
+mod seqlock;
+#[cfg(feature = "socket")]
+mod server_loop;
 mod shm;
+#[cfg(feature = "socket")]
 mod socket;
+#[cfg(feature = "socket")]
+mod spawn;
+mod stamped;
+#[cfg(feature = "rustix")]
+mod sys_rustix;
+#[cfg(feature = "socket")]
+mod tcp;
+#[cfg(feature = "socket")]
+mod tlv;
+mod topic;
+mod trace;
+mod tracepoint;
+#[cfg(feature = "socket")]
+mod transport;
 mod unix;
+#[cfg(feature = "socket")]
+mod unix_message;
+#[cfg(all(feature = "socket", not(target_os = "macos")))]
+mod vsock;
 
-#[macro_use]
+#[cfg_attr(all(feature = "socket", not(feature = "rustix")), macro_use)]
 extern crate nix;
 
-use std::{num::NonZeroUsize, sync::atomic::AtomicU32};
+#[cfg(all(feature = "rustix", not(target_os = "linux")))]
+compile_error!("the rustix feature needs memfd and eventfd of Linux");
 
-#[cfg(feature = "predefined_cacheline_size")]
-pub use crate::cache_env::max_cacheline_size;
+#[cfg(all(feature = "rt", not(target_os = "linux")))]
+compile_error!("the rt feature needs the scheduling policies of Linux");
 
-#[cfg(not(feature = "predefined_cacheline_size"))]
-pub use crate::cache_linux::max_cacheline_size;
+use std::{num::NonZeroUsize, sync::atomic::AtomicU32};
+
+use crate::descriptor::Descriptor;
+#[cfg(feature = "socket")]
+use crate::trace::error;
 
-pub use channel::{ChannelVector, Consumer, Producer};
+pub use arena::{Arena, ArenaHandle, MAX_ARENA_BLOCKS};
+pub use cacheline::{
+    CACHELINE_SIZE_VAR, CachelineSource, MAX_CACHELINE_SIZE, cacheline_aligned, cacheline_source,
+    max_cacheline_size, set_cacheline_size,
+};
+#[cfg(feature = "capnp")]
+pub use capnproto::{CapnpConsumer, CapnpProducer, SlotAllocator, SlotSegment};
+pub use channel::{
+    BroadcastConsumer, BroadcastProducer, ChannelVector, ConflatedConsumer, ConflatedProducer,
+    Consumer, CounterConsumer, CounterProducer, MpscConsumer, MpscProducer, PriorityConsumer,
+    PriorityProducer, Producer, ResizeChannels, StateConsumer, StateProducer,
+};
+pub use cheader::{CField, CHeader, CMessage, CType};
+#[cfg(feature = "serde")]
+pub use config_file::{ChannelProfile, ChannelProfiles};
+#[cfg(feature = "socket")]
+pub use control::{ConfigHandler, ConfigRecord, Control, ControlMessage};
+pub use device::{CacheOp, DeviceMemory};
+pub use dmabuf::DmaBuf;
 pub use error::*;
+#[cfg(feature = "flatbuffers")]
+pub use flatbuf::{FlatbufConsumer, FlatbufProducer};
+#[cfg(feature = "socket")]
+pub use header::{MIN_VERSION, RTIC_VERSION};
+pub use heartbeat::Heartbeat;
+#[cfg(feature = "socket")]
+pub use hub::BroadcastHub;
+pub use metrics::{ChannelMetrics, LatencyHistogram, MetricsHandle, VectorMetrics};
+pub use pool::ShmPool;
+#[cfg(feature = "prometheus")]
+pub use prometheus::PrometheusExporter;
 pub use queue::{ForcePushResult, PopResult, TryPushResult};
+#[cfg(feature = "socket")]
+pub use quota::{ClientQuota, QuotaScope};
+pub use report::{ChannelUsage, MemoryReport};
 pub use resource::VectorResource;
-pub use socket::{Server, client_connect, client_connect_fd};
+#[cfg(feature = "socket")]
+pub use server_loop::{
+    Client, ClientId, HANDSHAKE_TIMEOUT, IdleHandler, Keepalive, ServerEvent, ServerLoop,
+};
+pub use shm::{MemoryRegion, ShmBacking};
+#[cfg(feature = "socket")]
+pub use socket::{
+    AbstractAddr, ConnectInProgress, ConnectOptions, PeerCredentials, PeerInfo, Server,
+    SocketOptions, ToUnixAddr, client_connect, client_connect_fd, client_connect_fd_with,
+    client_connect_info, client_connect_info_fd, client_connect_info_transport,
+    client_connect_nonblocking, client_connect_transport, client_connect_with, client_resume,
+    client_resume_fd,
+};
+#[cfg(feature = "socket")]
+pub use spawn::{INHERITED_FD_VAR, client_connect_inherited, spawn_with_vector};
+pub use stamped::{Stamped, StampedConsumer, StampedProducer};
+#[cfg(feature = "socket")]
+pub use tcp::{TcpServer, client_connect_tcp};
+pub use topic::{PublishResult, Publisher, Reliability, Subscription, TopicQos, Topics};
+#[cfg(feature = "socket")]
+pub use transport::{StreamTransport, Transport, UnixTransport};
+#[cfg(all(feature = "socket", not(target_os = "macos")))]
+pub use vsock::{VsockServer, client_connect_vsock};
 
+#[cfg(target_os = "macos")]
+pub use fifo::EventFd;
 pub use nix::errno::Errno;
+#[cfg(not(target_os = "macos"))]
 pub use nix::sys::eventfd::EventFd;
 
+#[cfg(feature = "capnp")]
+pub use capnp;
+#[cfg(feature = "flatbuffers")]
+pub use flatbuffers;
 pub use log;
 
 pub(crate) type AtomicIndex = AtomicU32;
@@ -42,48 +179,336 @@ pub fn index_size() -> usize {
     std::mem::size_of::<Index>()
 }
 
+/// Index widths a queue can be placed in shared memory with,
+/// 64 bit indices need 64 bit atomics.
+pub(crate) fn is_supported_index_size(size: usize) -> bool {
+    size == size_of::<u32>() || (cfg!(target_has_atomic = "64") && size == size_of::<u64>())
+}
+
+/// Parameters of the shared memory layout, agreed on by both peers during the handshake.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Layout {
+    pub cacheline_size: usize,
+    /// width of the queue indices
+    pub index_size: usize,
+    /// every channel starts with a cache line holding its descriptor,
+    /// not for peers of FIXED_LAYOUT_VERSION
+    pub descriptors: bool,
+}
+
+impl Layout {
+    pub(crate) fn native() -> Self {
+        Self {
+            cacheline_size: max_cacheline_size(),
+            index_size: index_size(),
+            descriptors: true,
+        }
+    }
+}
+
 pub(crate) fn mem_align(size: usize, alignment: usize) -> usize {
     (size + alignment - 1) & !(alignment - 1)
 }
 
-pub(crate) fn cacheline_aligned(size: usize) -> usize {
-    mem_align(size, max_cacheline_size())
+/// mem_align, None if the aligned size overflows
+pub(crate) fn checked_mem_align(size: usize, alignment: usize) -> Option<usize> {
+    size.checked_add(alignment - 1)
+        .map(|size| size & !(alignment - 1))
 }
 
 #[derive(Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(deny_unknown_fields)
+)]
 pub struct QueueConfig {
+    #[cfg_attr(feature = "serde", serde(default))]
     pub additional_messages: usize,
     pub message_size: NonZeroUsize,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, deserialize_with = "config_file::info")
+    )]
     pub info: Vec<u8>,
+    /// fingerprint of the message type, see [`schema_fingerprint`]
+    pub schema: Option<u64>,
+}
+
+/// FNV-1a hash of a message type description, e.g. its field names and types.
+/// Both peers have to hash the same description for the same message type.
+pub const fn schema_fingerprint(desc: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut i = 0;
+
+    while i < desc.len() {
+        hash ^= desc[i] as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+        i += 1;
+    }
+
+    hash
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ChannelKind {
+    /// wait-free message queue
+    #[default]
+    Queue,
+
+    /// single latest-state record protected by a seqlock,
+    /// additional_messages is ignored
+    State,
+
+    /// message_size / 8 shared atomic u64 counters,
+    /// additional_messages and eventfd are ignored
+    Counters,
+
+    /// bounded queue with multiple producers pushing concurrently,
+    /// the queue never discards messages
+    MultiProducer,
+
+    /// ring with a single producer and any number of consumers,
+    /// slow consumers miss messages, but never block the producer
+    Broadcast,
+
+    /// two queues with the same configuration and one eventfd, messages pushed
+    /// with push_priority are popped before all other messages
+    Priority,
+
+    /// at most one pending message, a push replaces the pending message
+    /// in place, additional_messages is ignored
+    Conflated,
+}
+
+impl ChannelKind {
+    pub(crate) fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            0 => Some(ChannelKind::Queue),
+            1 => Some(ChannelKind::State),
+            2 => Some(ChannelKind::Counters),
+            3 => Some(ChannelKind::MultiProducer),
+            4 => Some(ChannelKind::Broadcast),
+            5 => Some(ChannelKind::Priority),
+            6 => Some(ChannelKind::Conflated),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn to_raw(self) -> u32 {
+        match self {
+            ChannelKind::Queue => 0,
+            ChannelKind::State => 1,
+            ChannelKind::Counters => 2,
+            ChannelKind::MultiProducer => 3,
+            ChannelKind::Broadcast => 4,
+            ChannelKind::Priority => 5,
+            ChannelKind::Conflated => 6,
+        }
+    }
+
+    /// Storage of the channel preceded by its descriptor.
+    pub(crate) fn shm_size(self, config: &QueueConfig, layout: Layout) -> NonZeroUsize {
+        let size = self.storage_size(config, layout).get() + Descriptor::shm_size(layout);
+        NonZeroUsize::new(size).unwrap()
+    }
+
+    fn storage_size(self, config: &QueueConfig, layout: Layout) -> NonZeroUsize {
+        match self {
+            ChannelKind::Queue => config.shm_size(layout),
+            ChannelKind::State | ChannelKind::Conflated => {
+                seqlock::SeqLock::shm_size(config, layout)
+            }
+            ChannelKind::Counters => counters::CounterArray::shm_size(config, layout),
+            ChannelKind::MultiProducer => mpsc::MpscQueue::shm_size(config, layout),
+            ChannelKind::Broadcast => broadcast::BroadcastQueue::shm_size(config, layout),
+            ChannelKind::Priority => NonZeroUsize::new(2 * config.shm_size(layout).get()).unwrap(),
+        }
+    }
+
+    pub(crate) fn usage(self, config: &QueueConfig, layout: Layout) -> ChannelUsage {
+        let messages = MIN_MSGS + config.additional_messages;
+        let message_size = mem_align(config.message_size.get(), layout.cacheline_size);
+
+        let (slots, slot_size) = match self {
+            ChannelKind::Queue | ChannelKind::MultiProducer | ChannelKind::Broadcast => {
+                (messages, message_size)
+            }
+            ChannelKind::Priority => (2 * messages, message_size),
+            ChannelKind::State | ChannelKind::Conflated => (1, message_size),
+            ChannelKind::Counters => (
+                config.message_size.get() / size_of::<u64>(),
+                size_of::<u64>(),
+            ),
+        };
+
+        ChannelUsage {
+            kind: self,
+            slots,
+            slot_size,
+            shm_size: self.shm_size(config, layout).get(),
+        }
+    }
 }
 
 #[derive(Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(deny_unknown_fields)
+)]
 pub struct ChannelConfig {
     pub queue: QueueConfig,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub kind: ChannelKind,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub eventfd: bool,
 }
 
+impl ChannelConfig {
+    /// Queue channel, the kind can be changed afterwards like the other fields,
+    /// e.g. ChannelConfig { kind: ChannelKind::State, ..ChannelConfig::new(queue, false) }.
+    pub fn new(queue: QueueConfig, eventfd: bool) -> Self {
+        Self {
+            queue,
+            kind: ChannelKind::default(),
+            eventfd,
+        }
+    }
+
+    /// Shared memory of the channel in the native layout, a peer with larger cache
+    /// lines makes it grow.
+    pub fn calc_shm_size(&self) -> usize {
+        self.shm_size(Layout::native()).get()
+    }
+
+    pub(crate) fn shm_size(&self, layout: Layout) -> NonZeroUsize {
+        self.kind.shm_size(&self.queue, layout)
+    }
+}
+
 impl QueueConfig {
-    fn data_size(&self) -> usize {
+    /// Queue without info and schema fingerprint, fields added in later versions
+    /// get their defaults as well.
+    pub fn new(additional_messages: usize, message_size: NonZeroUsize) -> Self {
+        Self {
+            additional_messages,
+            message_size,
+            info: Vec::new(),
+            schema: None,
+        }
+    }
+
+    /// additional messages and message size as the u32 fields of a request,
+    /// None if they don't fit, see VectorConfig::validate
+    pub(crate) fn wire_fields(&self) -> Option<(u32, u32)> {
+        let additional_messages = u32::try_from(self.additional_messages).ok()?;
+        let message_size = u32::try_from(self.message_size.get()).ok()?;
+        Some((additional_messages, message_size))
+    }
+
+    fn data_size(&self, layout: Layout) -> usize {
         let n = MIN_MSGS + self.additional_messages;
 
-        n * cacheline_aligned(self.message_size.get())
+        n * mem_align(self.message_size.get(), layout.cacheline_size)
     }
 
-    fn queue_size(&self) -> usize {
+    fn queue_size(&self, layout: Layout) -> usize {
         let n = 2 + MIN_MSGS + self.additional_messages;
-        cacheline_aligned(n * std::mem::size_of::<Index>())
+        mem_align(n * layout.index_size, layout.cacheline_size)
     }
 
-    pub(crate) fn shm_size(&self) -> NonZeroUsize {
-        NonZeroUsize::new(self.queue_size() + self.data_size()).unwrap()
+    pub(crate) fn shm_size(&self, layout: Layout) -> NonZeroUsize {
+        NonZeroUsize::new(self.queue_size(layout) + self.data_size(layout)).unwrap()
+    }
+
+    /// Upper bound of the shared memory of a channel of any kind with this queue,
+    /// None if it overflows. Two queues, as the priority kind holds, of indices of
+    /// layout.index_size or of the u64 sequences of the multi-producer and broadcast kinds.
+    fn shm_size_bound(&self, layout: Layout) -> Option<usize> {
+        let n = MIN_MSGS.checked_add(self.additional_messages)?;
+
+        let index_size = layout.index_size.max(size_of::<u64>());
+        let indices = n.checked_add(2)?.checked_mul(index_size)?;
+        let indices = checked_mem_align(indices, layout.cacheline_size)?;
+
+        let slot = checked_mem_align(self.message_size.get(), layout.cacheline_size)?;
+        let messages = n.checked_mul(slot)?;
+
+        indices
+            .checked_add(messages)?
+            .checked_mul(2)?
+            .checked_add(Descriptor::shm_size(layout))
+    }
+}
+
+/// Blocks of an Arena, they share the mapping of the vector and follow its channels.
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(deny_unknown_fields)
+)]
+pub struct ArenaConfig {
+    pub block_size: NonZeroUsize,
+    pub num_blocks: NonZeroUsize,
+}
+
+impl ArenaConfig {
+    /// block size and number of blocks as the u32 fields of a request,
+    /// None if they don't fit, see VectorConfig::validate
+    pub(crate) fn wire_fields(&self) -> Option<(u32, u32)> {
+        let block_size = u32::try_from(self.block_size.get()).ok()?;
+        let num_blocks = u32::try_from(self.num_blocks.get()).ok()?;
+        Some((block_size, num_blocks))
+    }
+
+    /// shm_size, None if it overflows
+    fn checked_shm_size(&self, layout: Layout) -> Option<usize> {
+        let n = self.num_blocks.get();
+        let handles = checked_mem_align(
+            n.checked_mul(std::mem::size_of::<u32>())?,
+            layout.cacheline_size,
+        )?;
+        let block = checked_mem_align(self.block_size.get(), layout.cacheline_size)?;
+        handles.checked_add(n.checked_mul(block)?)
+    }
+
+    pub(crate) fn shm_size(&self, layout: Layout) -> NonZeroUsize {
+        let n = self.num_blocks.get();
+        let size = mem_align(n * std::mem::size_of::<u32>(), layout.cacheline_size)
+            + n * mem_align(self.block_size.get(), layout.cacheline_size);
+        NonZeroUsize::new(size).unwrap()
     }
 }
 
+/// Default is a vector without channels, fields added in later versions are
+/// filled in by ..Default::default().
+#[derive(Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(deny_unknown_fields)
+)]
 pub struct VectorConfig {
+    #[cfg_attr(feature = "serde", serde(default))]
     pub producers: Vec<ChannelConfig>,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub consumers: Vec<ChannelConfig>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, deserialize_with = "config_file::info")
+    )]
     pub info: Vec<u8>,
+    pub arena: Option<ArenaConfig>,
+    /// liveness stamps of both peers, see [`Heartbeat`]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub heartbeat: bool,
 }
 
 impl VectorConfig {
@@ -95,19 +520,222 @@ impl VectorConfig {
         self.consumers.iter().map(|c| c.eventfd as usize).sum()
     }
 
+    /// number of fds sent during the handshake: the shared memory fd and the eventfds
+    pub fn count_fds(&self) -> usize {
+        1 + self.count_producer_eventfds() + self.count_consumer_eventfds()
+    }
+
+    /// Without eventfds push and pop of every channel stay in the shared memory,
+    /// e.g. for hard real-time loops that can't afford to enter the kernel.
+    /// Consumers wait for messages with pop_spin then.
+    pub fn is_syscall_free(&self) -> bool {
+        self.count_fds() == 1
+    }
+
+    /// Checks the vector before any fd is created, max_shm_size caps the shared memory
+    /// of the native layout, a server may refuse vectors below the cap anyway.
+    pub fn validate(&self, max_shm_size: usize) -> Result<(), ConfigError> {
+        if self.producers.is_empty()
+            && self.consumers.is_empty()
+            && self.arena.is_none()
+            && !self.heartbeat
+        {
+            return Err(ConfigError::NoChannels);
+        }
+
+        let overflows = |c: &ChannelConfig| {
+            c.queue.message_size.get() > u32::MAX as usize
+                || MIN_MSGS
+                    .checked_add(c.queue.additional_messages)
+                    .is_none_or(|n| n > u32::MAX as usize)
+        };
+
+        if let Some(index) = self.producers.iter().position(overflows) {
+            return Err(ConfigError::QueueOverflow {
+                producer: true,
+                index,
+            });
+        }
+
+        if let Some(index) = self.consumers.iter().position(overflows) {
+            return Err(ConfigError::QueueOverflow {
+                producer: false,
+                index,
+            });
+        }
+
+        if self.arena.as_ref().is_some_and(|arena| {
+            arena.wire_fields().is_none() || arena.num_blocks.get() > MAX_ARENA_BLOCKS
+        }) {
+            return Err(ConfigError::ArenaOverflow);
+        }
+
+        #[cfg(feature = "socket")]
+        {
+            let size = protocol::create_request(self, Layout::native())?.len();
+
+            if size > protocol::MAX_MESSAGE_SIZE {
+                return Err(ConfigError::InfoTooLong {
+                    size,
+                    max: protocol::MAX_MESSAGE_SIZE,
+                });
+            }
+        }
+
+        /* the exact sizes are summed unchecked, absurd channels have to be caught before */
+        if self.shm_size_bound(Layout::native()).is_none() {
+            return Err(ConfigError::ShmSizeExceeded {
+                size: usize::MAX,
+                max: max_shm_size,
+            });
+        }
+
+        let size = self.calc_shm_size();
+
+        if size > max_shm_size {
+            return Err(ConfigError::ShmSizeExceeded {
+                size,
+                max: max_shm_size,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Shared memory of the vector in the native layout, e.g. for RLIMIT_MEMLOCK before
+    /// connecting. The server may ask for a layout with larger cache lines, see
+    /// ChannelVector::total_shm_size for the size actually mapped.
     pub fn calc_shm_size(&self) -> usize {
-        let producers_size: usize = self
+        self.calc_layout_shm_size(Layout::native())
+    }
+
+    /// Upper bound of calc_layout_shm_size, None if the channels or the arena overflow.
+    fn shm_size_bound(&self, layout: Layout) -> Option<usize> {
+        let channels = self
             .producers
             .iter()
-            .map(|c| c.queue.shm_size().get())
-            .sum();
+            .chain(self.consumers.iter())
+            .try_fold(0usize, |sum, c| {
+                sum.checked_add(c.queue.shm_size_bound(layout)?)
+            })?;
 
-        let consumers_size: usize = self
-            .consumers
-            .iter()
-            .map(|c| c.queue.shm_size().get())
-            .sum();
+        let arena = match &self.arena {
+            Some(arena) => arena.checked_shm_size(layout)?,
+            None => 0,
+        };
+
+        channels
+            .checked_add(arena)?
+            .checked_add(Heartbeat::shm_size(layout).get())
+    }
+
+    /// shared memory size for a layout negotiated with the peer during the handshake
+    pub(crate) fn calc_layout_shm_size(&self, layout: Layout) -> usize {
+        let shm_size = |c: &ChannelConfig| c.shm_size(layout).get();
+
+        let producers_size: usize = self.producers.iter().map(shm_size).sum();
+
+        let consumers_size: usize = self.consumers.iter().map(shm_size).sum();
+
+        let arena_size = self.arena.as_ref().map_or(0, |a| a.shm_size(layout).get());
+
+        let heartbeat_size = if self.heartbeat {
+            Heartbeat::shm_size(layout).get()
+        } else {
+            0
+        };
+
+        producers_size + consumers_size + arena_size + heartbeat_size
+    }
+}
+
+/// Upper bounds for vectors requested by clients,
+/// checked before any shared memory is mapped.
+#[cfg(feature = "socket")]
+#[derive(Clone, Debug)]
+pub struct ServerLimits {
+    /// producers and consumers of a vector
+    pub max_channels: usize,
+    /// messages of a single queue, additional_messages + 3
+    pub max_queue_depth: usize,
+    pub max_message_size: usize,
+    /// size of the whole shared memory region
+    pub max_shm_size: usize,
+    /// dma-bufs attached to a vector, 0 refuses vectors with dma-bufs
+    pub max_dmabufs: usize,
+}
+
+#[cfg(feature = "socket")]
+impl Default for ServerLimits {
+    fn default() -> Self {
+        Self {
+            max_channels: 1024,
+            max_queue_depth: 0x10000,
+            max_message_size: 0x100000,
+            max_shm_size: 0x40000000,
+            max_dmabufs: 0,
+        }
+    }
+}
+
+#[cfg(feature = "socket")]
+impl ServerLimits {
+    /// fds of a vector within the limits: the shared memory, an eventfd per channel
+    /// and the dma-bufs
+    pub(crate) fn max_fds(&self) -> usize {
+        self.max_channels
+            .saturating_add(self.max_dmabufs)
+            .saturating_add(1)
+    }
+
+    /// the channels are checked first, the shm size of absurd channels could overflow
+    pub(crate) fn check(&self, vconfig: &VectorConfig, layout: Layout) -> Result<(), RequestError> {
+        let num_channels = vconfig.producers.len() + vconfig.consumers.len();
+
+        if num_channels > self.max_channels {
+            error!("request exceeds channel limit {num_channels}");
+            return Err(RequestError::LimitExceeded);
+        }
+
+        for channel in vconfig.producers.iter().chain(vconfig.consumers.iter()) {
+            let queue = &channel.queue;
+
+            if MIN_MSGS
+                .checked_add(queue.additional_messages)
+                .is_none_or(|depth| depth > self.max_queue_depth)
+                || queue.message_size.get() > self.max_message_size
+            {
+                error!(
+                    "request exceeds queue limits {} {}",
+                    queue.additional_messages,
+                    queue.message_size.get()
+                );
+                return Err(RequestError::LimitExceeded);
+            }
+        }
+
+        if let Some(arena) = &vconfig.arena {
+            let size = arena.block_size.get().checked_mul(arena.num_blocks.get());
+
+            /* handles can't address more blocks */
+            if size.is_none_or(|size| size > self.max_shm_size)
+                || arena.num_blocks.get() > MAX_ARENA_BLOCKS
+            {
+                error!("request exceeds arena limit");
+                return Err(RequestError::LimitExceeded);
+            }
+        }
+
+        /* with generous limits the size could still overflow, unlike its checked upper bound */
+        let shm_size = vconfig
+            .shm_size_bound(layout)
+            .map(|_| vconfig.calc_layout_shm_size(layout));
+
+        if shm_size.is_none_or(|size| size > self.max_shm_size) {
+            error!("request exceeds shm size limit {shm_size:?}");
+            return Err(RequestError::LimitExceeded);
+        }
 
-        producers_size + consumers_size
+        Ok(())
     }
 }
@@ -0,0 +1,105 @@
+use std::ffi::c_void;
+use std::num::NonZeroUsize;
+use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd};
+
+use nix::{Result, errno::Errno, libc, sys::stat::fstat};
+
+use crate::trace::*;
+use crate::unix::{shm_named_create, shm_named_unlink};
+
+/// macOS has no memfd, the shared memory is a POSIX shared memory object that's
+/// unlinked right away. The size of the object can't change once it's set,
+/// it needs no seals.
+pub fn shmfd_create(size: NonZeroUsize) -> Result<OwnedFd> {
+    let (fd, name) = shm_named_create(size)?;
+
+    shm_named_unlink(&name);
+
+    Ok(fd)
+}
+
+/// fstat reports no file type for POSIX shared memory objects,
+/// regular files could be truncated by the peer.
+pub(crate) fn check_memfd(fd: BorrowedFd<'_>) -> Result<()> {
+    let stat = fstat(fd).inspect_err(|e| error!("fstat failed {e:?}"))?;
+
+    if stat.st_mode & libc::S_IFMT != 0 {
+        error!("fd is not a shared memory object {:o}", stat.st_mode);
+        return Err(Errno::EBADF);
+    }
+
+    Ok(())
+}
+
+/// macOS has no MSG_NOSIGNAL, a peer that closed the socket results in EPIPE
+/// instead of SIGPIPE with SO_NOSIGPIPE.
+pub(crate) fn set_nosigpipe(socket: BorrowedFd<'_>) -> Result<()> {
+    let enable: libc::c_int = 1;
+
+    let res = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_NOSIGPIPE,
+            (&enable as *const libc::c_int).cast::<c_void>(),
+            size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+
+    Errno::result(res).map(drop)
+}
+
+/// macOS has no SOCK_CLOEXEC and MSG_CMSG_CLOEXEC, the flag is set afterwards.
+pub(crate) fn set_cloexec(fd: BorrowedFd<'_>) -> Result<()> {
+    nix::fcntl::fcntl(fd, nix::fcntl::F_SETFD(nix::fcntl::FdFlag::FD_CLOEXEC)).map(drop)
+}
+
+/// Cache line size reported by the kernel, 128 bytes on Apple silicon.
+#[cfg(not(feature = "predefined_cacheline_size"))]
+pub(crate) fn sysctl_cacheline_size() -> Option<usize> {
+    let mut size: u64 = 0;
+    let mut len = size_of::<u64>();
+
+    let res = unsafe {
+        libc::sysctlbyname(
+            c"hw.cachelinesize".as_ptr(),
+            (&mut size as *mut u64).cast::<c_void>(),
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+
+    if res != 0 {
+        return None;
+    }
+
+    usize::try_from(size).ok().filter(|&size| size > 0)
+}
+
+/// macOS has no dma-bufs.
+pub(crate) fn check_dmabuf(_fd: BorrowedFd<'_>) -> Result<NonZeroUsize> {
+    error!("dma-bufs aren't supported on macOS");
+    Err(Errno::EOPNOTSUPP)
+}
+
+/// macOS has no fallocate for shared memory objects.
+pub(crate) fn shm_preallocate(
+    _fd: BorrowedFd<'_>,
+    _offset: usize,
+    _len: NonZeroUsize,
+) -> Result<()> {
+    error!("preallocating shared memory isn't supported on macOS");
+    Err(Errno::EOPNOTSUPP)
+}
+
+/// macOS has no O_TMPFILE.
+pub(crate) fn shm_tmpfile_create(_dir: &std::path::Path, _size: NonZeroUsize) -> Result<OwnedFd> {
+    error!("unnamed files aren't supported on macOS");
+    Err(Errno::EOPNOTSUPP)
+}
+
+/// There are no unnamed files of shm_tmpfile_create to link.
+pub(crate) fn link_file(_fd: BorrowedFd<'_>, _path: &std::path::Path) -> Result<()> {
+    Err(Errno::EOPNOTSUPP)
+}
@@ -0,0 +1,263 @@
+use std::sync::{
+    Arc, OnceLock,
+    atomic::{AtomicU64, Ordering},
+};
+use std::time::Duration;
+
+/* log-linear buckets: 2^SUB_BITS buckets per power of two, below 2^SUB_BITS one per value,
+ * so every bucket is within 1/2^SUB_BITS of its values, the whole u64 range is covered */
+const SUB_BITS: u32 = 4;
+const BUCKETS: usize = (u64::BITS - SUB_BITS + 1) as usize * (1 << SUB_BITS);
+
+fn bucket(value: u64) -> usize {
+    if value < 1 << SUB_BITS {
+        return value as usize;
+    }
+
+    let shift = u64::BITS - 1 - value.leading_zeros() - SUB_BITS;
+    let mantissa = (value >> shift) as usize;
+
+    ((shift as usize + 1) << SUB_BITS) + mantissa - (1 << SUB_BITS)
+}
+
+/// Highest value falling into bucket.
+fn bucket_max(bucket: usize) -> u64 {
+    if bucket < 1 << SUB_BITS {
+        return bucket as u64;
+    }
+
+    let shift = (bucket >> SUB_BITS) - 1;
+    let mantissa = (1 << SUB_BITS) + (bucket & ((1 << SUB_BITS) - 1)) as u64;
+
+    /* the last bucket ends at u64::MAX */
+    ((((mantissa + 1) as u128) << shift) - 1) as u64
+}
+
+/// Counters of a queue taken from a vector, shared by the producer or consumer and
+/// the vector. Only the thread owning the producer or consumer writes them, so they
+/// are relaxed loads and stores without read-modify-write, on cache lines of their own.
+#[derive(Default)]
+#[repr(align(128))]
+pub(crate) struct QueueCounters {
+    pushes: AtomicU64,
+    pops: AtomicU64,
+    discards: AtomicU64,
+    queue_full: AtomicU64,
+    wakeups: AtomicU64,
+    max_depth: AtomicU64,
+    /// set up for consumers of stamped messages only
+    latency: OnceLock<LatencyCounters>,
+}
+
+struct LatencyCounters {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    sum: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+}
+
+fn bump(counter: &AtomicU64) {
+    counter.store(counter.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+}
+
+impl QueueCounters {
+    pub(crate) fn pushed(&self, depth: usize) {
+        bump(&self.pushes);
+
+        let depth = depth as u64;
+        if depth > self.max_depth.load(Ordering::Relaxed) {
+            self.max_depth.store(depth, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn popped(&self) {
+        bump(&self.pops);
+    }
+
+    pub(crate) fn discarded(&self) {
+        bump(&self.discards);
+    }
+
+    pub(crate) fn full(&self) {
+        bump(&self.queue_full);
+    }
+
+    pub(crate) fn woken(&self) {
+        bump(&self.wakeups);
+    }
+
+    pub(crate) fn enable_latency(&self) {
+        self.latency.get_or_init(|| LatencyCounters {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+            min: AtomicU64::new(u64::MAX),
+            max: AtomicU64::new(0),
+        });
+    }
+
+    /// Adds a latency in nanoseconds to the histogram, if enabled.
+    pub(crate) fn latency(&self, nanos: u64) {
+        let Some(latency) = self.latency.get() else {
+            return;
+        };
+
+        bump(&latency.buckets[bucket(nanos)]);
+        bump(&latency.count);
+
+        let sum = latency.sum.load(Ordering::Relaxed);
+        latency
+            .sum
+            .store(sum.saturating_add(nanos), Ordering::Relaxed);
+
+        if nanos < latency.min.load(Ordering::Relaxed) {
+            latency.min.store(nanos, Ordering::Relaxed);
+        }
+        if nanos > latency.max.load(Ordering::Relaxed) {
+            latency.max.store(nanos, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> ChannelMetrics {
+        ChannelMetrics {
+            pushes: self.pushes.load(Ordering::Relaxed),
+            pops: self.pops.load(Ordering::Relaxed),
+            discards: self.discards.load(Ordering::Relaxed),
+            queue_full: self.queue_full.load(Ordering::Relaxed),
+            wakeups: self.wakeups.load(Ordering::Relaxed),
+            max_depth: self.max_depth.load(Ordering::Relaxed),
+            latency: self.latency.get().map(|latency| LatencyHistogram {
+                buckets: latency
+                    .buckets
+                    .iter()
+                    .map(|b| b.load(Ordering::Relaxed))
+                    .collect(),
+                count: latency.count.load(Ordering::Relaxed),
+                sum: latency.sum.load(Ordering::Relaxed),
+                min: latency.min.load(Ordering::Relaxed),
+                max: latency.max.load(Ordering::Relaxed),
+            }),
+        }
+    }
+}
+
+/// Snapshot of the counters of a channel. Producers count pushes, discards,
+/// queue_full and max_depth, consumers pops and discards, both their wakeups.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelMetrics {
+    /// messages pushed, including those that discarded an older message
+    pub pushes: u64,
+    /// messages popped, including those following discarded messages
+    pub pops: u64,
+    /// force_push discarding the oldest message, pop noticing discarded messages
+    pub discards: u64,
+    /// try_push refused by a full queue
+    pub queue_full: u64,
+    /// eventfd notifications written by the producer, read by the consumer
+    pub wakeups: u64,
+    /// most messages queued and not consumed yet, as seen by the producer after a push
+    pub max_depth: u64,
+    /// producer to consumer latency of the messages, consumers of stamped messages only,
+    /// see ChannelVector::take_stamped_consumer
+    pub latency: Option<LatencyHistogram>,
+}
+
+/// HDR-style histogram of latencies: log-linear buckets with 16 buckets per power
+/// of two, so a percentile is at most 1/16 above the latency it stands for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+}
+
+impl LatencyHistogram {
+    /// Number of recorded latencies.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_nanos(self.min))
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_nanos(self.max))
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_nanos(self.sum / self.count))
+    }
+
+    /// Latency below which percentile percent of the latencies are,
+    /// e.g. 99.9 for the jitter of a real-time loop.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0 * self.count as f64).ceil() as u64)
+            .clamp(1, self.count);
+
+        let mut seen = 0;
+
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let nanos = bucket_max(bucket).clamp(self.min, self.max);
+                return Some(Duration::from_nanos(nanos));
+            }
+        }
+
+        self.max()
+    }
+
+    /// Non-empty buckets as the highest latency of the bucket and its count,
+    /// in ascending order, e.g. for exporting the histogram.
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(bucket, count)| (Duration::from_nanos(bucket_max(bucket)), *count))
+    }
+}
+
+/// Counters of the channels of a vector, see ChannelVector::metrics. Only queues
+/// taken with take_producer and take_consumer are counted, the counters of other
+/// channels stay 0.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VectorMetrics {
+    pub producers: Vec<ChannelMetrics>,
+    pub consumers: Vec<ChannelMetrics>,
+}
+
+/// Counters of a vector detached from it, e.g. for an exporter thread,
+/// see ChannelVector::metrics_handle.
+#[derive(Clone)]
+pub struct MetricsHandle {
+    producers: Vec<Arc<QueueCounters>>,
+    consumers: Vec<Arc<QueueCounters>>,
+}
+
+impl MetricsHandle {
+    pub(crate) fn new(producers: &[Arc<QueueCounters>], consumers: &[Arc<QueueCounters>]) -> Self {
+        Self {
+            producers: producers.to_vec(),
+            consumers: consumers.to_vec(),
+        }
+    }
+
+    pub fn snapshot(&self) -> VectorMetrics {
+        let snapshot =
+            |counters: &Vec<Arc<QueueCounters>>| counters.iter().map(|c| c.snapshot()).collect();
+
+        VectorMetrics {
+            producers: snapshot(&self.producers),
+            consumers: snapshot(&self.consumers),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::Layout;
+use crate::MIN_MSGS;
+use crate::QueueConfig;
+use crate::error::*;
+use crate::mem_align;
+use crate::queue::TryPushResult;
+use crate::shm::{Chunk, Span};
+
+/// Bounded queue for multiple producers and a single consumer.
+/// Producers reserve a slot by CAS on head, every slot has a sequence number
+/// telling whether it's free for position `pos` (seq == pos)
+/// or ready for the consumer (seq == pos + 1).
+/// Positions are 64 bit wide, so they never wrap around in practice.
+pub(crate) struct MpscQueue {
+    _chunk: Chunk,
+    message_size: NonZeroUsize,
+    head: *mut u64,
+    seqs: Vec<*mut u64>,
+    messages: Vec<*mut ()>,
+}
+
+impl MpscQueue {
+    pub(crate) fn new(
+        chunk: Chunk,
+        config: &QueueConfig,
+        layout: Layout,
+    ) -> Result<Self, ShmMapError> {
+        let queue_len = config.additional_messages + MIN_MSGS;
+        let message_size =
+            NonZeroUsize::new(mem_align(config.message_size.get(), layout.cacheline_size)).unwrap();
+
+        let mut offset_seq = 0;
+        let mut offset = mem_align((1 + queue_len) * size_of::<u64>(), layout.cacheline_size);
+
+        let head: *mut u64 = chunk.get_ptr(offset_seq)?;
+        offset_seq += size_of::<u64>();
+
+        if !head.is_aligned() {
+            return Err(ShmMapError::Misalignment);
+        }
+
+        let mut seqs: Vec<*mut u64> = Vec::with_capacity(queue_len);
+        let mut messages: Vec<*mut ()> = Vec::with_capacity(queue_len);
+
+        for _ in 0..queue_len {
+            let seq: *mut u64 = chunk.get_ptr(offset_seq)?;
+            let message: *mut () = chunk.get_span_ptr(&Span {
+                offset,
+                size: message_size,
+            })?;
+
+            seqs.push(seq);
+            messages.push(message);
+
+            offset_seq += size_of::<u64>();
+            offset += message_size.get();
+        }
+
+        Ok(Self {
+            _chunk: chunk,
+            message_size,
+            head,
+            seqs,
+            messages,
+        })
+    }
+
+    pub(crate) fn shm_size(config: &QueueConfig, layout: Layout) -> NonZeroUsize {
+        let n = MIN_MSGS + config.additional_messages;
+        let size = mem_align((1 + n) * size_of::<u64>(), layout.cacheline_size)
+            + n * mem_align(config.message_size.get(), layout.cacheline_size);
+        NonZeroUsize::new(size).unwrap()
+    }
+
+    pub(crate) fn init(&self) {
+        self.head().store(0, Ordering::SeqCst);
+
+        for idx in 0..self.len() {
+            self.seq(idx).store(idx as u64, Ordering::SeqCst);
+        }
+    }
+
+    pub(crate) fn message_size(&self) -> NonZeroUsize {
+        self.message_size
+    }
+
+    fn len(&self) -> usize {
+        self.seqs.len()
+    }
+
+    fn head(&self) -> &AtomicU64 {
+        unsafe { AtomicU64::from_ptr(self.head) }
+    }
+
+    fn seq(&self, idx: usize) -> &AtomicU64 {
+        unsafe { AtomicU64::from_ptr(self.seqs[idx]) }
+    }
+
+    fn slot(&self, pos: u64) -> usize {
+        (pos % self.len() as u64) as usize
+    }
+
+    /// Copies val into the next free slot, can be called concurrently.
+    pub(crate) fn push<T: Copy>(&self, val: &T) -> TryPushResult {
+        let mut pos = self.head().load(Ordering::Relaxed);
+
+        loop {
+            let seq = self.seq(self.slot(pos)).load(Ordering::Acquire);
+            let diff = seq.wrapping_sub(pos) as i64;
+
+            if diff == 0 {
+                match self.head().compare_exchange_weak(
+                    pos,
+                    pos + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break,
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                /* slot is still used by the consumer */
+                return TryPushResult::QueueFull;
+            } else {
+                /* another producer reserved the slot */
+                pos = self.head().load(Ordering::Relaxed);
+            }
+        }
+
+        let slot = self.slot(pos);
+
+        unsafe { std::ptr::write_volatile(self.messages[slot].cast::<T>(), *val) };
+
+        self.seq(slot).store(pos + 1, Ordering::Release);
+
+        TryPushResult::Success
+    }
+
+    /// Position of the next message for the consumer, derived from the sequences,
+    /// so the consumer of a resumed vector continues where the previous one stopped.
+    /// A message the previous consumer didn't release yet is delivered again.
+    pub(crate) fn consumer_pos(&self) -> u64 {
+        let len = self.len() as u64;
+
+        (0..self.len())
+            .map(|idx| {
+                let seq = self.seq(idx).load(Ordering::Acquire);
+                /* seq == pos for a free slot, pos + 1 for a ready one */
+                if seq % len == idx as u64 {
+                    seq
+                } else {
+                    seq.saturating_sub(1)
+                }
+            })
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Returns true if a producer published the message for position pos.
+    pub(crate) fn ready(&self, pos: u64) -> bool {
+        self.seq(self.slot(pos)).load(Ordering::Acquire) == pos + 1
+    }
+
+    /// Hands the slot of position pos back to the producers.
+    pub(crate) fn release(&self, pos: u64) {
+        self.seq(self.slot(pos))
+            .store(pos + self.len() as u64, Ordering::Release);
+    }
+
+    pub(crate) fn message(&self, pos: u64) -> *const () {
+        self.messages[self.slot(pos)]
+    }
+}
+
+// every MpscQueue has its own shared memory region
+unsafe impl Send for MpscQueue {}
+// push only operates on atomics and reserved slots
+unsafe impl Sync for MpscQueue {}
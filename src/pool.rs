@@ -0,0 +1,174 @@
+use std::num::NonZeroUsize;
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::ptr::NonNull;
+use std::sync::{Arc, Mutex};
+
+use nix::errno::Errno;
+use nix::unistd::{SysconfVar, dup, sysconf};
+
+use crate::error::*;
+use crate::mem_align;
+use crate::shm::{Chunk, MapOptions, MemoryRegion, SharedMemory, Span};
+use crate::trace::*;
+use crate::unix::shmfd_create;
+
+pub(crate) fn page_size() -> usize {
+    sysconf(SysconfVar::PAGE_SIZE)
+        .ok()
+        .flatten()
+        .map_or(4096, |size| size as usize)
+}
+
+/// Single sealed memfd the server carves the vectors of many clients out of,
+/// mapped once by the server. Every client of the pool receives the fd of the whole pool
+/// and maps only its own region, so a pool must only serve mutually trusted clients.
+#[derive(Debug)]
+pub struct ShmPool {
+    fd: OwnedFd,
+    chunk: Chunk,
+    page_size: usize,
+    /// unused regions sorted by offset
+    free: Mutex<Vec<Span>>,
+}
+
+impl ShmPool {
+    pub fn new(size: NonZeroUsize) -> Result<Arc<Self>, ResourceError> {
+        let page_size = page_size();
+        let size = NonZeroUsize::new(mem_align(size.get(), page_size)).unwrap();
+
+        let fd = shmfd_create(size)?;
+
+        let chunk = SharedMemory::new(dup(&fd)?, None, MapOptions::default())?.alloc(0, size)?;
+
+        Ok(Arc::new(Self {
+            fd,
+            chunk,
+            page_size,
+            free: Mutex::new(vec![Span { offset: 0, size }]),
+        }))
+    }
+
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+
+    /// bytes not used by any vector
+    pub fn available(&self) -> usize {
+        self.free
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|span| span.size.get())
+            .sum()
+    }
+
+    /// Reserves a page aligned region, first fit.
+    pub(crate) fn alloc(self: &Arc<Self>, size: NonZeroUsize) -> Result<PoolRegion, ResourceError> {
+        let size = NonZeroUsize::new(mem_align(size.get(), self.page_size)).unwrap();
+
+        let mut free = self.free.lock().unwrap();
+
+        let Some(index) = free.iter().position(|span| span.size >= size) else {
+            error!("pool exhausted, requested {size}");
+            return Err(Errno::ENOMEM.into());
+        };
+
+        let offset = free[index].offset;
+
+        match NonZeroUsize::new(free[index].size.get() - size.get()) {
+            Some(rest) => {
+                free[index] = Span {
+                    offset: offset + size.get(),
+                    size: rest,
+                }
+            }
+            None => {
+                free.remove(index);
+            }
+        }
+
+        let span = Span { offset, size };
+
+        let ptr = match self.chunk.get_span_ptr(&span) {
+            Ok(ptr) => NonNull::new(ptr.cast()).ok_or(ResourceError::InvalidArgument)?,
+            Err(e) => {
+                drop(free);
+                self.release(span);
+                return Err(e.into());
+            }
+        };
+
+        Ok(PoolRegion {
+            pool: self.clone(),
+            span,
+            ptr,
+        })
+    }
+
+    fn release(&self, span: Span) {
+        let mut free = self.free.lock().unwrap();
+
+        let index = free.partition_point(|s| s.offset < span.offset);
+
+        free.insert(index, span);
+
+        /* merge with the successor first, the index of the predecessor stays valid */
+        if index + 1 < free.len()
+            && free[index].offset + free[index].size.get() == free[index + 1].offset
+        {
+            let next = free.remove(index + 1);
+            free[index].size = free[index].size.saturating_add(next.size.get());
+        }
+
+        if index > 0 && free[index - 1].offset + free[index - 1].size.get() == free[index].offset {
+            let this = free.remove(index);
+            free[index - 1].size = free[index - 1].size.saturating_add(this.size.get());
+        }
+    }
+}
+
+/// Region of a pool used by a single vector, released on drop.
+#[derive(Debug)]
+pub(crate) struct PoolRegion {
+    pool: Arc<ShmPool>,
+    span: Span,
+    ptr: NonNull<u8>,
+}
+
+impl PoolRegion {
+    pub(crate) fn offset(&self) -> usize {
+        self.span.offset
+    }
+
+    pub(crate) fn fd(&self) -> BorrowedFd<'_> {
+        self.pool.fd()
+    }
+}
+
+impl MemoryRegion for PoolRegion {
+    fn map(&self) -> NonNull<u8> {
+        self.ptr
+    }
+
+    fn size(&self) -> NonZeroUsize {
+        self.span.size
+    }
+
+    fn as_fd(&self) -> Option<BorrowedFd<'_>> {
+        Some(self.pool.fd())
+    }
+}
+
+impl Drop for PoolRegion {
+    fn drop(&mut self) {
+        self.pool.release(self.span);
+    }
+}
+
+// the mapping of the pool is only handed out as regions, the free list is locked
+unsafe impl Send for ShmPool {}
+unsafe impl Sync for ShmPool {}
+
+// the region is part of the pool's mapping
+unsafe impl Send for PoolRegion {}
+unsafe impl Sync for PoolRegion {}
@@ -0,0 +1,198 @@
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::trace::*;
+use crate::{ChannelMetrics, MetricsHandle, VectorMetrics};
+
+/// name, type, help and value of a metric family
+type Family = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&ChannelMetrics) -> u64,
+);
+
+const FAMILIES: [Family; 6] = [
+    ("pushes_total", "counter", "messages pushed", |m| m.pushes),
+    ("pops_total", "counter", "messages popped", |m| m.pops),
+    (
+        "discards_total",
+        "counter",
+        "messages discarded by force_push, noticed by pop",
+        |m| m.discards,
+    ),
+    (
+        "queue_full_total",
+        "counter",
+        "pushes refused by a full queue",
+        |m| m.queue_full,
+    ),
+    (
+        "wakeups_total",
+        "counter",
+        "eventfd notifications written or read",
+        |m| m.wakeups,
+    ),
+    (
+        "max_depth",
+        "gauge",
+        "most messages queued and not consumed yet",
+        |m| m.max_depth,
+    ),
+];
+
+/// Exports the channel metrics of vectors in the Prometheus text format, served by
+/// a tiny HTTP endpoint or written for the textfile collector of the node exporter.
+/// Samples are labeled with the name of the vector, the direction and the index of
+/// the channel, e.g.
+///
+/// ```text
+/// rtipc_channel_pushes_total{vector="robot",direction="producer",channel="0"} 42
+/// ```
+///
+/// Clones share the registered vectors, so a server registers the vectors of its
+/// clients while the endpoint thread serves them.
+#[derive(Clone, Default)]
+pub struct PrometheusExporter {
+    vectors: Arc<Mutex<Vec<(String, MetricsHandle)>>>,
+}
+
+impl PrometheusExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the vector of handle under name, replacing a vector registered under the same name.
+    pub fn register(&self, name: &str, handle: MetricsHandle) {
+        let mut vectors = self.vectors.lock().unwrap();
+        vectors.retain(|(n, _)| n != name);
+        vectors.push((name.to_owned(), handle));
+    }
+
+    /// Removes the vector, e.g. of a disconnected client.
+    pub fn unregister(&self, name: &str) {
+        self.vectors.lock().unwrap().retain(|(n, _)| n != name);
+    }
+
+    /// Metrics of all registered vectors in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let snapshots: Vec<(String, VectorMetrics)> = self
+            .vectors
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, handle)| (escape(name), handle.snapshot()))
+            .collect();
+
+        let mut text = String::new();
+
+        for (family, kind, help, value) in FAMILIES {
+            let _ = writeln!(text, "# HELP rtipc_channel_{family} {help}");
+            let _ = writeln!(text, "# TYPE rtipc_channel_{family} {kind}");
+
+            for (name, metrics) in &snapshots {
+                let channels = [
+                    ("producer", &metrics.producers),
+                    ("consumer", &metrics.consumers),
+                ];
+
+                for (direction, channels) in channels {
+                    for (index, channel) in channels.iter().enumerate() {
+                        let _ = writeln!(
+                            text,
+                            "rtipc_channel_{family}{{vector=\"{name}\",direction=\"{direction}\",channel=\"{index}\"}} {}",
+                            value(channel)
+                        );
+                    }
+                }
+            }
+        }
+
+        text
+    }
+
+    /// Writes the metrics for the textfile collector, e.g. to
+    /// /var/lib/node_exporter/rtipc.prom. The file is replaced by a rename,
+    /// the collector never reads a partially written file.
+    pub fn write_textfile(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        std::fs::write(&tmp, self.render())?;
+        std::fs::rename(&tmp, path)
+    }
+
+    /// Serves GET /metrics on listener in a thread of its own until accepting fails,
+    /// requests are answered one by one, a request has to arrive within a second.
+    pub fn serve(&self, listener: TcpListener) -> JoinHandle<()> {
+        let exporter = self.clone();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        error!("metrics endpoint accept failed: {e}");
+                        return;
+                    }
+                };
+
+                if let Err(e) = exporter.answer(stream) {
+                    warn!("metrics request failed: {e}");
+                }
+            }
+        })
+    }
+
+    fn answer(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        /* a stalled client must not block the scrapes of the others */
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+        let mut reader = BufReader::new(&stream);
+
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+
+        /* the headers are of no interest */
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 2 {
+            line.clear();
+        }
+
+        let mut words = request.split_whitespace();
+        let method = words.next().unwrap_or_default();
+        let target = words.next().unwrap_or_default();
+
+        let (status, body) = if method != "GET" {
+            ("405 Method Not Allowed", String::new())
+        } else if target == "/metrics" {
+            ("200 OK", self.render())
+        } else {
+            ("404 Not Found", String::new())
+        };
+
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\n\
+             Content-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n\
+             {body}",
+            body.len()
+        )?;
+        stream.flush()
+    }
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
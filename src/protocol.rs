@@ -1,13 +1,144 @@
 use std::num::NonZeroUsize;
 
 use crate::{
-    ChannelConfig, QueueConfig, VectorConfig,
+    ArenaConfig, ChannelConfig, ChannelKind, Layout, QueueConfig, ServerLimits, VectorConfig,
+    control::{ConfigRecord, ControlMessage},
     error::*,
-    header::{HEADER_SIZE, verify_header, write_header},
-    log::error,
+    header::{
+        FIXED_HEADER_SIZE, FIXED_LAYOUT_VERSION, HEADER_SIZE, RTIC_VERSION, has_descriptors,
+        layout_fields, layout_version, verify_header, write_fixed_header, write_header,
+    },
+    tlv::{FLAG_CRITICAL, Record, TlvReader, TlvWriter},
+    trace::{debug, error},
 };
 
-#[repr(C)]
+/* top level records of a request, also used for the vector of RSP_VECTOR */
+const REQ_VECTOR_INFO: u16 = 1;
+const REQ_ARENA: u16 = 2;
+/* channel produced by the sender */
+const REQ_PRODUCER: u16 = 3;
+/* channel consumed by the sender */
+const REQ_CONSUMER: u16 = 4;
+/* the requester only sends REQ_VECTOR_INFO and lets the server define the vector */
+pub(crate) const REQ_QUERY: u16 = 5;
+/* number of fds sent with the request, they may be spread over several messages */
+const REQ_FD_COUNT: u16 = 6;
+/* u64 token of a session to resume, replaces the vector */
+pub(crate) const REQ_RESUME: u16 = 7;
+/* the vector has heartbeat stamps, empty value */
+const REQ_HEARTBEAT: u16 = 8;
+/* name of the shared memory object in /dev/shm, replaces the fds on transports without them */
+pub(crate) const REQ_SHM_NAME: u16 = 9;
+/* u64 offset of the vector in shared memory both peers got from elsewhere, replaces the fds */
+pub(crate) const REQ_SHM_OFFSET: u16 = 10;
+/* u32 backing of the attached shared memory, BACKING_MEMFD if missing */
+pub(crate) const REQ_SHM_BACKING: u16 = 11;
+/* u32 number of dma-bufs, their fds follow the eventfds */
+const REQ_DMABUFS: u16 = 12;
+
+/* nested records of REQ_PRODUCER and REQ_CONSUMER */
+const CH_ADDITIONAL_MESSAGES: u16 = 1;
+const CH_MESSAGE_SIZE: u16 = 2;
+const CH_KIND: u16 = 3;
+const CH_EVENTFD: u16 = 4;
+const CH_INFO: u16 = 5;
+/* u64 fingerprint of the message type */
+const CH_SCHEMA: u16 = 6;
+
+/* nested records of REQ_ARENA */
+const ARENA_BLOCK_SIZE: u16 = 1;
+const ARENA_NUM_BLOCKS: u16 = 2;
+
+/* records of a response */
+const RSP_STATUS: u16 = 1;
+const RSP_CACHELINE_SIZE: u16 = 2;
+const RSP_INDEX_SIZE: u16 = 3;
+/* vector defined by the server, nested records like a request */
+const RSP_VECTOR: u16 = 4;
+/* vector-level info of the server */
+const RSP_INFO: u16 = 5;
+/* reason of STATUS_REJECTED */
+const RSP_REJECT_CODE: u16 = 6;
+const RSP_REJECT_MESSAGE: u16 = 7;
+/* application payload returned by the accept filter */
+const RSP_PAYLOAD: u16 = 8;
+/* u64 token for resuming the session, only sent by servers keeping sessions */
+const RSP_SESSION_TOKEN: u16 = 9;
+/* the requester allocated the shared memory of a resumed vector */
+const RSP_OWNER: u16 = 10;
+/* u64 offset of the vector in the attached shared memory of a pool */
+const RSP_POOL_OFFSET: u16 = 11;
+/* u32 backing of the attached shared memory like REQ_SHM_BACKING */
+const RSP_SHM_BACKING: u16 = 12;
+
+/* values of REQ_SHM_BACKING and RSP_SHM_BACKING */
+const BACKING_MEMFD: u32 = 0;
+/* regular file, not sealed */
+const BACKING_FILE: u32 = 1;
+
+const STATUS_ACCEPTED: u32 = 0;
+const STATUS_REJECTED: u32 = 1;
+const STATUS_RETRY: u32 = 2;
+
+/* response of FIXED_LAYOUT_VERSION */
+const LEGACY_RESPONSE_SIZE: usize = 4;
+
+pub(crate) struct Request {
+    pub vconfig: VectorConfig,
+    /// alignment of the shared memory layout chosen by the requester
+    pub cacheline_size: usize,
+    /// width of the queue indices chosen by the requester
+    pub index_size: usize,
+    /// the channels start with descriptors, false before version 9
+    pub descriptors: bool,
+    /// version spoken by the requester, it's answered in the same version
+    pub version: u16,
+    /// the requester asks the server to define the vector
+    pub query: bool,
+    /// the requester resumes the session with this token
+    pub resume: Option<u64>,
+    /// the shared memory is a named object instead of an fd
+    pub shm_name: Option<String>,
+    /// the vector is at this offset of the shared memory provided to both peers
+    pub shm_offset: Option<usize>,
+    /// the attached shared memory is a regular file instead of a memfd
+    pub file_backed: bool,
+    /// number of dma-bufs attached after the eventfds
+    pub dmabufs: usize,
+}
+
+pub(crate) enum Response {
+    /// info is the server's vector-level info, payload is returned by the accept filter,
+    /// both are empty for acknowledgements. A token of 0 means no session is kept.
+    Accepted {
+        info: Vec<u8>,
+        payload: Vec<u8>,
+        token: u64,
+    },
+    Rejected(Rejection),
+    /// the requester has to repeat the request with a layout aligned to
+    /// at least layout.cacheline_size and indices of layout.index_size
+    Retry {
+        layout: Layout,
+    },
+    /// vector defined by the server or of a resumed session, the fds are attached
+    /// to the response, owner is set if the requester allocated the shared memory
+    Vector {
+        vconfig: VectorConfig,
+        layout: Layout,
+        token: u64,
+        owner: bool,
+        /// the vector is a region of a shared memory pool
+        pool_offset: Option<usize>,
+        /// the attached shared memory is a regular file instead of a memfd
+        file_backed: bool,
+    },
+}
+
+/// channel entry of FIXED_LAYOUT_VERSION: additional_messages, message_size, eventfd
+/// and info_size as native endian u32, the same on 32 and 64 bit peers
+const CHANNEL_ENTRY_SIZE: usize = 4 * size_of::<u32>();
+
 struct ChannelEntry {
     additional_messages: u32,
     message_size: u32,
@@ -16,116 +147,46 @@ struct ChannelEntry {
 }
 
 impl ChannelEntry {
-    fn from_config(config: &ChannelConfig) -> Self {
-        Self {
-            additional_messages: config.queue.additional_messages as u32,
-            message_size: config.queue.message_size.get() as u32,
-            eventfd: config.eventfd as u32,
-            info_size: config.queue.info.len() as u32,
-        }
-    }
-}
-
-struct Layout {
-    vector_info_offset: usize,
-    num_channels: [usize; 2],
-    channel_table: usize,
-    vector_info: usize,
-    channel_infos: usize,
-    size: usize,
-}
-
-impl Layout {
-    pub(self) fn calc(vconfig: &VectorConfig) -> Self {
-        let mut offset = HEADER_SIZE;
-
-        let vector_info_offset = offset;
-        offset += size_of::<u32>();
-
-        let num_channels: [usize; 2] = [offset, offset + size_of::<u32>()];
-        offset += 2 * size_of::<u32>();
-
-        let channel_table: usize = offset;
-
-        offset += (vconfig.producers.len() + vconfig.consumers.len()) * size_of::<ChannelEntry>();
-
-        let vector_info = offset;
-        offset += vconfig.info.len();
-
-        let channel_infos = offset;
-
-        for config in &vconfig.producers {
-            offset += config.queue.info.len();
-        }
-
-        for config in &vconfig.consumers {
-            offset += config.queue.info.len();
-        }
-
-        let size = offset;
+    fn read(buf: &[u8; CHANNEL_ENTRY_SIZE]) -> Self {
+        let field = |idx: usize| {
+            u32::from_ne_bytes([
+                buf[4 * idx],
+                buf[4 * idx + 1],
+                buf[4 * idx + 2],
+                buf[4 * idx + 3],
+            ])
+        };
 
         Self {
-            vector_info_offset,
-            num_channels,
-            channel_table,
-            vector_info,
-            channel_infos,
-            size,
+            additional_messages: field(0),
+            message_size: field(1),
+            eventfd: field(2),
+            info_size: field(3),
         }
     }
-}
 
-fn request_read<T>(request: &[u8], offset: usize) -> Result<T, RequestError> {
-    if offset + size_of::<T>() > request.len() {
-        return Err(RequestError::OutOfBounds);
+    fn write(&self, buf: &mut Vec<u8>) {
+        for field in [
+            self.additional_messages,
+            self.message_size,
+            self.eventfd,
+            self.info_size,
+        ] {
+            buf.extend_from_slice(&field.to_ne_bytes());
+        }
     }
-
-    let ptr = unsafe { request.as_ptr().byte_add(offset) as *const T };
-
-    Ok(unsafe { ptr.read_unaligned() })
 }
 
-fn req_get_mut_ptr<T>(request: &mut [u8], offset: usize) -> Result<*mut T, RequestError> {
-    if offset + size_of::<T>() > request.len() {
-        return Err(RequestError::OutOfBounds);
-    }
-
-    let ptr = unsafe { request.as_mut_ptr().byte_add(offset) as *mut T };
-
-    Ok(ptr)
-}
-
-fn request_write<T: Copy>(request: &[u8], offset: usize, val: &T) -> Result<(), RequestError> {
-    if offset + size_of::<T>() > request.len() {
-        return Err(RequestError::OutOfBounds);
-    }
-
-    let ptr = unsafe { request.as_ptr().byte_add(offset) as *mut T };
-
-    unsafe {
-        ptr.write_unaligned(*val);
-    }
-
-    Ok(())
+/// Fixed size field of a FIXED_LAYOUT_VERSION request at offset.
+fn request_bytes<const N: usize>(request: &[u8], offset: usize) -> Result<&[u8; N], RequestError> {
+    request
+        .get(offset..)
+        .and_then(|tail| tail.first_chunk::<N>())
+        .ok_or(RequestError::OutOfBounds)
 }
 
-fn request_write_channel(
-    request: &mut [u8],
-    config: &ChannelConfig,
-    entry_offset: &mut usize,
-    info_offset: &mut usize,
-) {
-    let entry_ptr = req_get_mut_ptr::<ChannelEntry>(request, *entry_offset).unwrap();
-    unsafe {
-        entry_ptr.write_unaligned(ChannelEntry::from_config(config));
-    }
-
-    if !config.queue.info.is_empty() {
-        request[*info_offset..*info_offset + config.queue.info.len()]
-            .clone_from_slice(config.queue.info.as_slice());
-        *info_offset += config.queue.info.len();
-    }
-    *entry_offset += size_of::<ChannelEntry>();
+fn request_u32(request: &[u8], offset: usize) -> Result<u32, RequestError> {
+    request_bytes(request, offset).map(|bytes| u32::from_ne_bytes(*bytes))
 }
 
 fn request_read_entry(
@@ -133,9 +194,11 @@ fn request_read_entry(
     entry_offset: &mut usize,
     info_offset: &mut usize,
 ) -> Result<ChannelConfig, RequestError> {
-    let entry = request_read::<ChannelEntry>(request, *entry_offset).inspect_err(|_| {
-        error!("request message too short");
-    })?;
+    let entry = request_bytes(request, *entry_offset)
+        .map(ChannelEntry::read)
+        .inspect_err(|_| {
+            error!("request message too short");
+        })?;
 
     if entry.message_size == 0 {
         error!("request: message size = 0 not allowed");
@@ -146,7 +209,7 @@ fn request_read_entry(
 
     let info_size = entry.info_size as usize;
 
-    if *info_offset + info_size > request.len() {
+    if info_size > request.len() - *info_offset {
         error!("request message too small for channel infos");
         return Err(RequestError::OutOfBounds);
     }
@@ -156,7 +219,7 @@ fn request_read_entry(
         _ => request[*info_offset..*info_offset + info_size].to_vec(),
     };
 
-    *entry_offset += size_of::<ChannelEntry>();
+    *entry_offset += CHANNEL_ENTRY_SIZE;
     *info_offset += info_size;
 
     Ok(ChannelConfig {
@@ -164,40 +227,41 @@ fn request_read_entry(
             additional_messages: entry.additional_messages as usize,
             message_size,
             info,
+            schema: None,
         },
+        kind: ChannelKind::Queue,
         eventfd: entry.eventfd != 0,
     })
 }
 
-pub fn parse_request(request: &[u8]) -> Result<VectorConfig, RequestError> {
-    let header = request
-        .get(0..HEADER_SIZE)
-        .ok_or(RequestError::OutOfBounds)?;
-
-    verify_header(header).inspect_err(|e| {
-        error!("parse header failed {e:?}");
-    })?;
-
-    let mut offset: usize = HEADER_SIZE;
+/// Parser for requests with the fixed layout of FIXED_LAYOUT_VERSION,
+/// these requests are in native byte order. The layout only knows queues:
+/// header, vector info size, number of channels consumed and produced by us,
+/// the channel entries, the vector info and the channel infos.
+fn parse_request_fixed(request: &[u8]) -> Result<VectorConfig, RequestError> {
+    let mut offset: usize = FIXED_HEADER_SIZE;
 
-    let vector_info_size = request_read::<u32>(request, offset).inspect_err(|_| {
+    let vector_info_size = request_u32(request, offset).inspect_err(|_| {
         error!("request message too short");
     })? as usize;
     offset += size_of::<u32>();
 
-    let num_consumers = request_read::<u32>(request, offset).inspect_err(|_| {
+    let num_consumers = request_u32(request, offset).inspect_err(|_| {
         error!("request message too small");
     })? as usize;
     offset += size_of::<u32>();
 
-    let num_producers = request_read::<u32>(request, offset).inspect_err(|_| {
+    let num_producers = request_u32(request, offset).inspect_err(|_| {
         error!("request message too small");
     })? as usize;
     offset += size_of::<u32>();
 
-    let vector_info_offset = offset + (num_consumers + num_producers) * size_of::<ChannelEntry>();
+    let vector_info_offset = (num_consumers + num_producers)
+        .checked_mul(CHANNEL_ENTRY_SIZE)
+        .and_then(|size| size.checked_add(offset))
+        .ok_or(RequestError::OutOfBounds)?;
 
-    let mut channel_info_offset = vector_info_offset + vector_info_size;
+    let mut channel_info_offset = vector_info_offset.saturating_add(vector_info_size);
 
     if channel_info_offset > request.len() {
         error!("request message too small for vector info");
@@ -225,58 +289,458 @@ pub fn parse_request(request: &[u8]) -> Result<VectorConfig, RequestError> {
         consumers,
         producers,
         info,
+        arena: None,
+        heartbeat: false,
     })
 }
 
-pub fn create_request(vconfig: &VectorConfig) -> Vec<u8> {
-    let layout = Layout::calc(vconfig);
+/// Request with the fixed layout of FIXED_LAYOUT_VERSION for a server of the C librtipc,
+/// in native byte order. The channels are listed from the server's point of view,
+/// the server has to share our cache line size and index width.
+/// Kinds, arenas and schemas have no fields, Handshake::check_legacy refuses them.
+pub(crate) fn create_request_fixed(vconfig: &VectorConfig) -> Result<Vec<u8>, ConfigError> {
+    let mut request = vec![0; FIXED_HEADER_SIZE];
 
-    let mut request: Vec<u8> = vec![0; layout.size];
+    write_fixed_header(&mut request)?;
 
-    write_header(request.as_mut_slice());
+    /* our producers are the consumers of the server */
+    let channels = || vconfig.producers.iter().chain(vconfig.consumers.iter());
 
-    request_write(
-        request.as_mut_slice(),
-        layout.vector_info_offset,
-        &(vconfig.info.len() as u32),
-    )
-    .unwrap();
+    for field in [
+        info_size(&vconfig.info)?,
+        channel_count(vconfig.producers.len())?,
+        channel_count(vconfig.consumers.len())?,
+    ] {
+        request.extend_from_slice(&field.to_ne_bytes());
+    }
 
-    request_write(
-        request.as_mut_slice(),
-        layout.num_channels[0],
-        &(vconfig.producers.len() as u32),
-    )
-    .unwrap();
+    for (producer, configs) in [(true, &vconfig.producers), (false, &vconfig.consumers)] {
+        for (index, config) in configs.iter().enumerate() {
+            let (additional_messages, message_size) = config
+                .queue
+                .wire_fields()
+                .ok_or(ConfigError::QueueOverflow { producer, index })?;
+
+            ChannelEntry {
+                additional_messages,
+                message_size,
+                eventfd: u32::from(config.eventfd),
+                info_size: info_size(&config.queue.info)?,
+            }
+            .write(&mut request);
+        }
+    }
 
-    request_write(
-        request.as_mut_slice(),
-        layout.num_channels[1],
-        &(vconfig.consumers.len() as u32),
-    )
-    .unwrap();
+    request.extend_from_slice(&vconfig.info);
 
-    let mut entry_offset = layout.channel_table;
+    for config in channels() {
+        request.extend_from_slice(&config.queue.info);
+    }
 
-    request[layout.vector_info..layout.vector_info + vconfig.info.len()]
-        .clone_from_slice(vconfig.info.as_slice());
+    Ok(request)
+}
 
-    let mut info_offset = layout.channel_infos;
+fn skip_record(record: &Record) -> Result<(), RequestError> {
+    if record.critical() {
+        error!("request: unknown critical record {}", record.tag);
+        return Err(RequestError::UnknownRecord(record.tag));
+    }
 
-    vconfig
-        .producers
-        .iter()
-        .for_each(|c| request_write_channel(&mut request, c, &mut entry_offset, &mut info_offset));
+    debug!("request: skip unknown record {}", record.tag);
+    Ok(())
+}
 
-    vconfig
-        .consumers
-        .iter()
-        .for_each(|c| request_write_channel(&mut request, c, &mut entry_offset, &mut info_offset));
+fn parse_channel(record: &Record) -> Result<ChannelConfig, RequestError> {
+    let mut additional_messages = 0;
+    let mut message_size = None;
+    let mut kind = ChannelKind::Queue;
+    let mut eventfd = false;
+    let mut info = Vec::with_capacity(0);
+    let mut schema = None;
+
+    for nested in record.nested() {
+        let nested = nested?;
+        match nested.tag {
+            CH_ADDITIONAL_MESSAGES => additional_messages = nested.u32()? as usize,
+            CH_MESSAGE_SIZE => message_size = NonZeroUsize::new(nested.u32()? as usize),
+            CH_KIND => {
+                kind = ChannelKind::from_raw(nested.u32()?).ok_or_else(|| {
+                    error!("request: unknown channel kind");
+                    RequestError::InvalidChannelKind
+                })?
+            }
+            CH_EVENTFD => eventfd = nested.u32()? != 0,
+            CH_INFO => info = nested.value.to_vec(),
+            CH_SCHEMA => schema = Some(nested.u64()?),
+            _ => skip_record(&nested)?,
+        }
+    }
 
-    request
+    let message_size = message_size.ok_or_else(|| {
+        error!("request: message size = 0 not allowed");
+        RequestError::MissingRecord(CH_MESSAGE_SIZE)
+    })?;
+
+    Ok(ChannelConfig {
+        queue: QueueConfig {
+            additional_messages,
+            message_size,
+            info,
+            schema,
+        },
+        kind,
+        eventfd,
+    })
+}
+
+fn parse_arena(record: &Record) -> Result<ArenaConfig, RequestError> {
+    let mut block_size = None;
+    let mut num_blocks = None;
+
+    for nested in record.nested() {
+        let nested = nested?;
+        match nested.tag {
+            ARENA_BLOCK_SIZE => block_size = NonZeroUsize::new(nested.u32()? as usize),
+            ARENA_NUM_BLOCKS => num_blocks = NonZeroUsize::new(nested.u32()? as usize),
+            _ => skip_record(&nested)?,
+        }
+    }
+
+    Ok(ArenaConfig {
+        block_size: block_size.ok_or(RequestError::MissingRecord(ARENA_BLOCK_SIZE))?,
+        num_blocks: num_blocks.ok_or(RequestError::MissingRecord(ARENA_NUM_BLOCKS))?,
+    })
+}
+
+/// Parses the records of a vector, returns the vector and whether REQ_QUERY was found.
+fn parse_vector(buf: &[u8]) -> Result<(VectorConfig, bool), RequestError> {
+    let mut vconfig = VectorConfig {
+        producers: Vec::new(),
+        consumers: Vec::new(),
+        info: Vec::with_capacity(0),
+        arena: None,
+        heartbeat: false,
+    };
+    let mut query = false;
+
+    for record in TlvReader::new(buf) {
+        let record = record.inspect_err(|e| error!("request: parse record failed {e:?}"))?;
+        match record.tag {
+            REQ_VECTOR_INFO => vconfig.info = record.value.to_vec(),
+            REQ_ARENA => vconfig.arena = Some(parse_arena(&record)?),
+            REQ_HEARTBEAT => vconfig.heartbeat = true,
+            /* the sender's producers are our consumers */
+            REQ_PRODUCER => vconfig.consumers.push(parse_channel(&record)?),
+            REQ_CONSUMER => vconfig.producers.push(parse_channel(&record)?),
+            REQ_QUERY => query = true,
+            /* read by parse_fd_count before the request is complete */
+            REQ_FD_COUNT => {}
+            /* read by parse_request */
+            REQ_RESUME | REQ_SHM_NAME | REQ_SHM_OFFSET | REQ_SHM_BACKING | REQ_DMABUFS => {}
+            _ => skip_record(&record)?,
+        }
+    }
+
+    Ok((vconfig, query))
+}
+
+/// Parses a request, vectors exceeding limits are refused.
+pub(crate) fn parse_request(
+    request: &[u8],
+    limits: &ServerLimits,
+) -> Result<Request, RequestError> {
+    let header = verify_header(request).inspect_err(|e| {
+        error!("parse header failed {e:?}");
+    })?;
+
+    let (vconfig, query) = if header.version == FIXED_LAYOUT_VERSION {
+        (parse_request_fixed(request)?, false)
+    } else {
+        parse_vector(&request[HEADER_SIZE..])?
+    };
+
+    let descriptors = has_descriptors(header.version);
+
+    let layout = Layout {
+        cacheline_size: header.cacheline_size,
+        index_size: header.atomic_size,
+        descriptors,
+    };
+
+    limits.check(&vconfig, layout)?;
+
+    let resume = find_record(request, REQ_RESUME)
+        .map(|record| record.u64())
+        .transpose()?;
+
+    let shm_name = find_record(request, REQ_SHM_NAME)
+        .map(|record| {
+            String::from_utf8(record.value.to_vec())
+                .map_err(|_| RequestError::MalformedRecord(REQ_SHM_NAME))
+        })
+        .transpose()?;
+
+    let shm_offset = find_record(request, REQ_SHM_OFFSET)
+        .map(|record| {
+            usize::try_from(record.u64()?)
+                .map_err(|_| RequestError::MalformedRecord(REQ_SHM_OFFSET))
+        })
+        .transpose()?;
+
+    let file_backed = find_record(request, REQ_SHM_BACKING)
+        .map(|record| parse_backing(&record))
+        .transpose()?
+        .unwrap_or(false);
+
+    let dmabufs = find_record(request, REQ_DMABUFS)
+        .map(|record| record.u32())
+        .transpose()?
+        .map_or(0, |n| n as usize);
+
+    if dmabufs > limits.max_dmabufs {
+        error!("request exceeds dma-buf limit {dmabufs}");
+        return Err(RequestError::LimitExceeded);
+    }
+
+    Ok(Request {
+        vconfig,
+        cacheline_size: header.cacheline_size,
+        index_size: header.atomic_size,
+        descriptors,
+        version: header.version,
+        query,
+        resume,
+        shm_name,
+        shm_offset,
+        file_backed,
+        dmabufs,
+    })
+}
+
+/// Returns true for BACKING_FILE.
+fn parse_backing(record: &Record) -> Result<bool, RequestError> {
+    match record.u32()? {
+        BACKING_MEMFD => Ok(false),
+        BACKING_FILE => Ok(true),
+        backing => {
+            error!("unknown shared memory backing {backing}");
+            Err(RequestError::MalformedRecord(record.tag))
+        }
+    }
+}
+
+/// Returns true if the request uses FIXED_LAYOUT_VERSION and expects a legacy response.
+pub(crate) fn is_legacy_request(request: &[u8]) -> bool {
+    verify_header(request).is_ok_and(|h| h.version == FIXED_LAYOUT_VERSION)
+}
+
+/// First top level record with tag, None for requests of FIXED_LAYOUT_VERSION.
+fn find_record(request: &[u8], tag: u16) -> Option<Record<'_>> {
+    if !verify_header(request).is_ok_and(|h| h.version != FIXED_LAYOUT_VERSION) {
+        return None;
+    }
+
+    TlvReader::new(&request[HEADER_SIZE..])
+        .map_while(|record| record.ok())
+        .find(|record| record.tag == tag)
+}
+
+/// Number of fds announced by a request, 0 if the request doesn't announce them.
+/// The request isn't verified yet, only the fds are collected with this number.
+pub(crate) fn parse_fd_count(request: &[u8]) -> usize {
+    find_record(request, REQ_FD_COUNT)
+        .and_then(|record| record.u32().ok())
+        .map_or(0, |n| n as usize)
+}
+
+/// Returns true if the request resumes a session, the request isn't verified yet.
+pub(crate) fn is_resume_request(request: &[u8]) -> bool {
+    find_record(request, REQ_RESUME).is_some()
+}
+
+/// Version to answer a request in, the current one for requests with an invalid header.
+pub(crate) fn request_version(request: &[u8]) -> u16 {
+    verify_header(request).map_or(RTIC_VERSION, |h| h.version)
+}
+
+/// Length of an info as the u32 field of a FIXED_LAYOUT_VERSION request.
+fn info_size(info: &[u8]) -> Result<u32, ConfigError> {
+    u32::try_from(info.len()).map_err(|_| ConfigError::InfoTooLong {
+        size: info.len(),
+        max: u32::MAX as usize,
+    })
+}
+
+/// Number or index of channels as the u32 field of a request.
+fn channel_count(n: usize) -> Result<u32, ConfigError> {
+    u32::try_from(n).map_err(|_| ConfigError::ChannelOverflow)
+}
+
+/// Fails with overflow if the queue of config doesn't fit into the u32 fields.
+fn write_channel(
+    writer: &mut TlvWriter,
+    tag: u16,
+    config: &ChannelConfig,
+    overflow: ConfigError,
+) -> Result<(), ConfigError> {
+    let (additional_messages, message_size) = config.queue.wire_fields().ok_or(overflow)?;
+
+    writer.put_nested(tag, FLAG_CRITICAL, |w| {
+        w.put_u32(CH_ADDITIONAL_MESSAGES, FLAG_CRITICAL, additional_messages);
+        w.put_u32(CH_MESSAGE_SIZE, FLAG_CRITICAL, message_size);
+        w.put_u32(CH_KIND, FLAG_CRITICAL, config.kind.to_raw());
+        w.put_u32(CH_EVENTFD, FLAG_CRITICAL, u32::from(config.eventfd));
+        if !config.queue.info.is_empty() {
+            w.put_bytes(CH_INFO, 0, &config.queue.info);
+        }
+        if let Some(schema) = config.queue.schema {
+            w.put_u64(CH_SCHEMA, 0, schema);
+        }
+    });
+
+    Ok(())
+}
+
+fn write_vector(writer: &mut TlvWriter, vconfig: &VectorConfig) -> Result<(), ConfigError> {
+    if !vconfig.info.is_empty() {
+        writer.put_bytes(REQ_VECTOR_INFO, 0, &vconfig.info);
+    }
+
+    if let Some(arena) = &vconfig.arena {
+        let (block_size, num_blocks) = arena.wire_fields().ok_or(ConfigError::ArenaOverflow)?;
+
+        writer.put_nested(REQ_ARENA, FLAG_CRITICAL, |w| {
+            w.put_u32(ARENA_BLOCK_SIZE, FLAG_CRITICAL, block_size);
+            w.put_u32(ARENA_NUM_BLOCKS, FLAG_CRITICAL, num_blocks);
+        });
+    }
+
+    if vconfig.heartbeat {
+        writer.put_bytes(REQ_HEARTBEAT, FLAG_CRITICAL, &[]);
+    }
+
+    for (tag, producer, configs) in [
+        (REQ_PRODUCER, true, &vconfig.producers),
+        (REQ_CONSUMER, false, &vconfig.consumers),
+    ] {
+        for (index, config) in configs.iter().enumerate() {
+            let overflow = ConfigError::QueueOverflow { producer, index };
+            write_channel(writer, tag, config, overflow)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn create_request(vconfig: &VectorConfig, layout: Layout) -> Result<Vec<u8>, ConfigError> {
+    create_backed_request(vconfig, layout, false, 0)
+}
+
+/// Request with attached fds, file_backed if the shared memory is a regular file.
+/// The fds of dmabufs dma-bufs follow the eventfds.
+pub(crate) fn create_backed_request(
+    vconfig: &VectorConfig,
+    layout: Layout,
+    file_backed: bool,
+    dmabufs: usize,
+) -> Result<Vec<u8>, ConfigError> {
+    let mut header = vec![0; HEADER_SIZE];
+
+    write_header(header.as_mut_slice(), layout, layout_version(layout))?;
+
+    let mut writer = TlvWriter::new(header);
+
+    write_vector(&mut writer, vconfig)?;
+
+    let fd_count = vconfig
+        .count_fds()
+        .checked_add(dmabufs)
+        .ok_or(ConfigError::ChannelOverflow)?;
+    writer.put_u32(REQ_FD_COUNT, 0, channel_count(fd_count)?);
+
+    if dmabufs > 0 {
+        /* a server not knowing dma-bufs would assign their fds to nothing */
+        writer.put_u32(REQ_DMABUFS, FLAG_CRITICAL, channel_count(dmabufs)?);
+    }
+
+    if file_backed {
+        /* a server expecting a sealed memfd would refuse the file anyway */
+        writer.put_u32(REQ_SHM_BACKING, FLAG_CRITICAL, BACKING_FILE);
+    }
+
+    writer.finish()
+}
+
+/// Request for a transport without fd passing, the shared memory is the named object.
+pub(crate) fn create_named_request(
+    vconfig: &VectorConfig,
+    layout: Layout,
+    name: &str,
+) -> Result<Vec<u8>, ConfigError> {
+    let mut header = vec![0; HEADER_SIZE];
+
+    write_header(header.as_mut_slice(), layout, layout_version(layout))?;
+
+    let mut writer = TlvWriter::new(header);
+
+    write_vector(&mut writer, vconfig)?;
+
+    writer.put_bytes(REQ_SHM_NAME, FLAG_CRITICAL, name.as_bytes());
+
+    writer.finish()
+}
+
+/// Request for a vector at offset of shared memory both peers got from elsewhere.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn create_provided_request(
+    vconfig: &VectorConfig,
+    layout: Layout,
+    offset: usize,
+) -> Result<Vec<u8>, ConfigError> {
+    let mut header = vec![0; HEADER_SIZE];
+
+    write_header(header.as_mut_slice(), layout, layout_version(layout))?;
+
+    let mut writer = TlvWriter::new(header);
+
+    write_vector(&mut writer, vconfig)?;
+
+    writer.put_u64(REQ_SHM_OFFSET, FLAG_CRITICAL, offset as u64);
+
+    writer.finish()
+}
+
+/// Request for the vector of a previous session.
+pub(crate) fn create_resume(token: u64, version: u16) -> Result<Vec<u8>, ConfigError> {
+    let mut header = vec![0; HEADER_SIZE];
+
+    write_header(header.as_mut_slice(), Layout::native(), version)?;
+
+    let mut writer = TlvWriter::new(header);
+
+    writer.put_u64(REQ_RESUME, FLAG_CRITICAL, token);
+
+    writer.finish()
+}
+
+/// Request for a vector defined by the server, layout is the layout preferred by the requester.
+pub(crate) fn create_query(info: &[u8], layout: Layout) -> Result<Vec<u8>, ConfigError> {
+    let mut header = vec![0; HEADER_SIZE];
+
+    write_header(header.as_mut_slice(), layout, layout_version(layout))?;
+
+    let mut writer = TlvWriter::new(header);
+
+    writer.put_bytes(REQ_QUERY, FLAG_CRITICAL, &[]);
+
+    if !info.is_empty() {
+        writer.put_bytes(REQ_VECTOR_INFO, 0, info);
+    }
+
+    writer.finish()
 }
 
-pub(crate) fn create_response(success: bool) -> Vec<u8> {
+pub(crate) fn create_legacy_response(success: bool) -> Vec<u8> {
     if success {
         vec![0, 0, 0, 0]
     } else {
@@ -284,10 +748,1102 @@ pub(crate) fn create_response(success: bool) -> Vec<u8> {
     }
 }
 
-pub(crate) fn parse_response(response: &[u8]) -> Result<(), TransferError> {
-    if response != vec![0, 0, 0, 0] {
-        Err(TransferError::ResponseError)
-    } else {
-        Ok(())
+/// Response in the version of the request it answers.
+pub(crate) fn create_response(response: &Response, version: u16) -> Result<Vec<u8>, ConfigError> {
+    let layout = match response {
+        Response::Vector { layout, .. } => *layout,
+        _ => Layout::native(),
+    };
+
+    let mut header = vec![0; HEADER_SIZE];
+
+    write_header(header.as_mut_slice(), layout, version)?;
+
+    let mut writer = TlvWriter::new(header);
+
+    match response {
+        Response::Accepted {
+            info,
+            payload,
+            token,
+        } => {
+            writer.put_u32(RSP_STATUS, FLAG_CRITICAL, STATUS_ACCEPTED);
+            if !info.is_empty() {
+                writer.put_bytes(RSP_INFO, 0, info);
+            }
+            if !payload.is_empty() {
+                writer.put_bytes(RSP_PAYLOAD, 0, payload);
+            }
+            if *token != 0 {
+                writer.put_u64(RSP_SESSION_TOKEN, 0, *token);
+            }
+        }
+        Response::Rejected(rejection) => {
+            writer.put_u32(RSP_STATUS, FLAG_CRITICAL, STATUS_REJECTED);
+            writer.put_u32(RSP_REJECT_CODE, 0, rejection.code);
+            if !rejection.message.is_empty() {
+                writer.put_bytes(RSP_REJECT_MESSAGE, 0, rejection.message.as_bytes());
+            }
+        }
+        Response::Retry { layout } => {
+            writer.put_u32(RSP_STATUS, FLAG_CRITICAL, STATUS_RETRY);
+            let (cacheline_size, index_size) = layout_fields(*layout)?;
+            writer.put_u32(RSP_CACHELINE_SIZE, FLAG_CRITICAL, cacheline_size.into());
+            writer.put_u32(RSP_INDEX_SIZE, FLAG_CRITICAL, index_size.into());
+        }
+        Response::Vector {
+            vconfig,
+            token,
+            owner,
+            pool_offset,
+            file_backed,
+            ..
+        } => {
+            writer.put_u32(RSP_STATUS, FLAG_CRITICAL, STATUS_ACCEPTED);
+            let mut result = Ok(());
+            writer.put_nested(RSP_VECTOR, FLAG_CRITICAL, |w| {
+                result = write_vector(w, vconfig)
+            });
+            result?;
+            if *token != 0 {
+                writer.put_u64(RSP_SESSION_TOKEN, 0, *token);
+            }
+            if *owner {
+                /* mapping the vector as non-owner would swap the channels */
+                writer.put_u32(RSP_OWNER, FLAG_CRITICAL, 1);
+            }
+            if let Some(offset) = pool_offset {
+                /* mapping the whole pool would map the wrong region */
+                writer.put_u64(RSP_POOL_OFFSET, FLAG_CRITICAL, *offset as u64);
+            }
+            if *file_backed {
+                writer.put_u32(RSP_SHM_BACKING, FLAG_CRITICAL, BACKING_FILE);
+            }
+        }
+    }
+
+    writer.finish()
+}
+
+fn parse_response_tlv(response: &[u8]) -> Result<Response, RequestError> {
+    let header = verify_header(response)?;
+
+    let mut status = None;
+    let mut vector = None;
+    let mut cacheline_size = None;
+    let mut index_size = None;
+    let mut info = Vec::with_capacity(0);
+    let mut payload = Vec::with_capacity(0);
+    let mut rejection = Rejection::default();
+    let mut token = 0;
+    let mut owner = false;
+    let mut pool_offset = None;
+    let mut file_backed = false;
+
+    for record in TlvReader::new(&response[HEADER_SIZE..]) {
+        let record = record?;
+        match record.tag {
+            RSP_STATUS => status = Some(record.u32()?),
+            RSP_CACHELINE_SIZE => cacheline_size = Some(record.u32()? as usize),
+            RSP_INDEX_SIZE => index_size = Some(record.u32()? as usize),
+            RSP_VECTOR => vector = Some(parse_vector(record.value)?.0),
+            RSP_INFO => info = record.value.to_vec(),
+            RSP_PAYLOAD => payload = record.value.to_vec(),
+            RSP_SESSION_TOKEN => token = record.u64()?,
+            RSP_OWNER => owner = record.u32()? != 0,
+            RSP_POOL_OFFSET => {
+                let offset = usize::try_from(record.u64()?)
+                    .map_err(|_| RequestError::MalformedRecord(RSP_POOL_OFFSET))?;
+                pool_offset = Some(offset);
+            }
+            RSP_SHM_BACKING => file_backed = parse_backing(&record)?,
+            RSP_REJECT_CODE => rejection.code = record.u32()?,
+            RSP_REJECT_MESSAGE => {
+                rejection.message = String::from_utf8_lossy(record.value).into_owned()
+            }
+            _ => skip_record(&record)?,
+        }
+    }
+
+    match status.ok_or(RequestError::MissingRecord(RSP_STATUS))? {
+        STATUS_ACCEPTED => Ok(match vector {
+            Some(vconfig) => Response::Vector {
+                vconfig,
+                layout: Layout {
+                    cacheline_size: header.cacheline_size,
+                    index_size: header.atomic_size,
+                    descriptors: has_descriptors(header.version),
+                },
+                token,
+                owner,
+                pool_offset,
+                file_backed,
+            },
+            None => Response::Accepted {
+                info,
+                payload,
+                token,
+            },
+        }),
+        STATUS_REJECTED => Ok(Response::Rejected(rejection)),
+        STATUS_RETRY => Ok(Response::Retry {
+            layout: Layout {
+                cacheline_size: cacheline_size
+                    .ok_or(RequestError::MissingRecord(RSP_CACHELINE_SIZE))?,
+                index_size: index_size.ok_or(RequestError::MissingRecord(RSP_INDEX_SIZE))?,
+                descriptors: has_descriptors(header.version),
+            },
+        }),
+        _ => Err(RequestError::MalformedRecord(RSP_STATUS)),
+    }
+}
+
+pub(crate) fn parse_response(response: &[u8]) -> Result<Response, TransferError> {
+    if response.len() == LEGACY_RESPONSE_SIZE {
+        return if response == [0, 0, 0, 0] {
+            Ok(Response::Accepted {
+                info: Vec::with_capacity(0),
+                payload: Vec::with_capacity(0),
+                token: 0,
+            })
+        } else {
+            Ok(Response::Rejected(Rejection::default()))
+        };
+    }
+
+    parse_response_tlv(response).map_err(|e| {
+        error!("parse response failed {e:?}");
+        TransferError::ResponseError
+    })
+}
+
+/* fragments of messages exceeding MAX_FRAGMENT_SIZE:
+ * magic, flags as little endian u16, size of the whole message as little endian u32,
+ * followed by the next part of the message */
+const FRAGMENT_MAGIC: u16 = 0x1f0d;
+const FRAGMENT_HEADER_SIZE: usize = 8;
+const FRAGMENT_MORE: u16 = 1;
+
+/// largest datagram sent during the handshake, well below the default socket buffer size
+pub(crate) const MAX_FRAGMENT_SIZE: usize = 0x10000;
+/// upper bound for reassembled messages
+pub(crate) const MAX_MESSAGE_SIZE: usize = 0x1000000;
+
+/// Splits a message into fragments of at most MAX_FRAGMENT_SIZE bytes,
+/// smaller messages are returned unchanged.
+pub(crate) fn split_message(msg: &[u8]) -> Vec<Vec<u8>> {
+    if msg.len() <= MAX_FRAGMENT_SIZE {
+        return vec![msg.to_vec()];
+    }
+
+    let size = (msg.len() as u32).to_le_bytes();
+    let parts: Vec<&[u8]> = msg
+        .chunks(MAX_FRAGMENT_SIZE - FRAGMENT_HEADER_SIZE)
+        .collect();
+    let last = parts.len() - 1;
+
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let flags = if i < last { FRAGMENT_MORE } else { 0 };
+            let mut fragment = Vec::with_capacity(FRAGMENT_HEADER_SIZE + part.len());
+            fragment.extend_from_slice(&FRAGMENT_MAGIC.to_le_bytes());
+            fragment.extend_from_slice(&flags.to_le_bytes());
+            fragment.extend_from_slice(&size);
+            fragment.extend_from_slice(part);
+            fragment
+        })
+        .collect()
+}
+
+pub(crate) fn is_fragment(buf: &[u8]) -> bool {
+    buf.len() > FRAGMENT_HEADER_SIZE && buf[0..2] == FRAGMENT_MAGIC.to_le_bytes()
+}
+
+/// Collects the fragments of a message.
+#[derive(Default)]
+pub(crate) struct Reassembly {
+    msg: Vec<u8>,
+    size: usize,
+}
+
+impl Reassembly {
+    /// Appends a fragment, returns Some(true) once the message is complete
+    /// and None if the fragment doesn't continue the message.
+    pub(crate) fn push(&mut self, fragment: &[u8]) -> Option<bool> {
+        if !is_fragment(fragment) {
+            return None;
+        }
+
+        let flags = u16::from_le_bytes(fragment[2..4].try_into().unwrap());
+        let size = u32::from_le_bytes(fragment[4..8].try_into().unwrap()) as usize;
+
+        if self.msg.is_empty() {
+            if size > MAX_MESSAGE_SIZE {
+                error!("fragmented message too large {size}");
+                return None;
+            }
+            self.size = size;
+            self.msg.reserve_exact(size);
+        } else if size != self.size {
+            return None;
+        }
+
+        let part = &fragment[FRAGMENT_HEADER_SIZE..];
+
+        if self.msg.len() + part.len() > self.size {
+            return None;
+        }
+
+        self.msg.extend_from_slice(part);
+
+        let complete = flags & FRAGMENT_MORE == 0;
+
+        if complete && self.msg.len() != self.size {
+            return None;
+        }
+
+        Some(complete)
+    }
+
+    pub(crate) fn into_message(self) -> Vec<u8> {
+        self.msg
+    }
+}
+
+/* fds exceeding the limit of a single message are sent with continuation messages:
+ * magic and the number of attached fds as little endian u16 */
+const FD_CONTINUATION_MAGIC: u16 = 0x1f0e;
+const FD_CONTINUATION_SIZE: usize = 4;
+
+pub(crate) fn create_fd_continuation(num_fds: usize) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(FD_CONTINUATION_SIZE);
+    msg.extend_from_slice(&FD_CONTINUATION_MAGIC.to_le_bytes());
+    msg.extend_from_slice(&(num_fds as u16).to_le_bytes());
+    msg
+}
+
+/// Returns the number of fds attached to a continuation message.
+pub(crate) fn parse_fd_continuation(buf: &[u8]) -> Option<usize> {
+    if buf.len() != FD_CONTINUATION_SIZE || buf[0..2] != FD_CONTINUATION_MAGIC.to_le_bytes() {
+        return None;
+    }
+
+    Some(u16::from_le_bytes([buf[2], buf[3]]) as usize)
+}
+
+/* control messages after the handshake, every message is a single record */
+const CTRL_PING: u16 = 1;
+const CTRL_PONG: u16 = 2;
+const CTRL_STATS_QUERY: u16 = 3;
+const CTRL_STATS: u16 = 4;
+/* nested channel records */
+const CTRL_ADD_PRODUCER: u16 = 5;
+const CTRL_ADD_CONSUMER: u16 = 6;
+/* u32 channel index */
+const CTRL_CLOSE_PRODUCER: u16 = 7;
+const CTRL_CLOSE_CONSUMER: u16 = 8;
+const CTRL_SHUTDOWN: u16 = 9;
+const CTRL_GOODBYE: u16 = 10;
+/* nested CFG_ENTRY records */
+const CTRL_CONFIG: u16 = 11;
+/* request of the resized vector, the shared memory fd is attached */
+const CTRL_RESIZE: u16 = 12;
+/* u32, 1 if the peer accepted the resize */
+const CTRL_RESIZED: u16 = 13;
+const CTRL_SWITCHED: u16 = 14;
+
+/* nested records of CTRL_CONFIG */
+const CFG_ENTRY: u16 = 1;
+/* nested records of CFG_ENTRY */
+const CFG_KEY: u16 = 1;
+const CFG_VALUE: u16 = 2;
+
+fn parse_config_entry(record: &Record) -> Result<ConfigRecord, RequestError> {
+    let mut key = None;
+    let mut value = Vec::with_capacity(0);
+
+    for nested in record.nested() {
+        let nested = nested?;
+        match nested.tag {
+            CFG_KEY => key = Some(nested.u32()?),
+            CFG_VALUE => value = nested.value.to_vec(),
+            _ => skip_record(&nested)?,
+        }
+    }
+
+    Ok(ConfigRecord {
+        key: key.ok_or(RequestError::MissingRecord(CFG_KEY))?,
+        value,
+    })
+}
+
+fn parse_config(record: &Record) -> Result<Vec<ConfigRecord>, RequestError> {
+    let mut records = Vec::new();
+
+    for nested in record.nested() {
+        let nested = nested?;
+        match nested.tag {
+            CFG_ENTRY => records.push(parse_config_entry(&nested)?),
+            _ => skip_record(&nested)?,
+        }
+    }
+
+    Ok(records)
+}
+
+/// Control message in the version negotiated by the handshake.
+pub(crate) fn create_control(msg: &ControlMessage, version: u16) -> Result<Vec<u8>, ConfigError> {
+    let layout = Layout {
+        descriptors: has_descriptors(version),
+        ..Layout::native()
+    };
+
+    let mut header = vec![0; HEADER_SIZE];
+
+    write_header(header.as_mut_slice(), layout, version)?;
+
+    let mut writer = TlvWriter::new(header);
+
+    match msg {
+        ControlMessage::Ping(token) => writer.put_u64(CTRL_PING, FLAG_CRITICAL, *token),
+        ControlMessage::Pong(token) => writer.put_u64(CTRL_PONG, FLAG_CRITICAL, *token),
+        ControlMessage::StatsQuery => writer.put_bytes(CTRL_STATS_QUERY, FLAG_CRITICAL, &[]),
+        ControlMessage::Stats(stats) => writer.put_bytes(CTRL_STATS, FLAG_CRITICAL, stats),
+        /* the added channel is the only one of the message */
+        ControlMessage::AddProducer(config) => write_channel(
+            &mut writer,
+            CTRL_ADD_PRODUCER,
+            config,
+            ConfigError::QueueOverflow {
+                producer: true,
+                index: 0,
+            },
+        )?,
+        ControlMessage::AddConsumer(config) => write_channel(
+            &mut writer,
+            CTRL_ADD_CONSUMER,
+            config,
+            ConfigError::QueueOverflow {
+                producer: false,
+                index: 0,
+            },
+        )?,
+        ControlMessage::CloseProducer(index) => {
+            writer.put_u32(CTRL_CLOSE_PRODUCER, FLAG_CRITICAL, channel_count(*index)?)
+        }
+        ControlMessage::CloseConsumer(index) => {
+            writer.put_u32(CTRL_CLOSE_CONSUMER, FLAG_CRITICAL, channel_count(*index)?)
+        }
+        ControlMessage::Shutdown => writer.put_bytes(CTRL_SHUTDOWN, FLAG_CRITICAL, &[]),
+        ControlMessage::Goodbye => writer.put_bytes(CTRL_GOODBYE, FLAG_CRITICAL, &[]),
+        ControlMessage::Config(records) => writer.put_nested(CTRL_CONFIG, FLAG_CRITICAL, |w| {
+            for record in records {
+                w.put_nested(CFG_ENTRY, FLAG_CRITICAL, |e| {
+                    e.put_u32(CFG_KEY, FLAG_CRITICAL, record.key);
+                    e.put_bytes(CFG_VALUE, 0, &record.value);
+                });
+            }
+        }),
+        ControlMessage::Resize(vconfig) => writer.put_bytes(
+            CTRL_RESIZE,
+            FLAG_CRITICAL,
+            &create_backed_request(vconfig, layout, false, 0)?,
+        ),
+        ControlMessage::Resized(accepted) => {
+            writer.put_u32(CTRL_RESIZED, FLAG_CRITICAL, u32::from(*accepted))
+        }
+        ControlMessage::Switched => writer.put_bytes(CTRL_SWITCHED, FLAG_CRITICAL, &[]),
+    }
+
+    writer.finish()
+}
+
+/// The single record of a control message.
+pub(crate) fn parse_control_record(msg: &[u8]) -> Result<Record<'_>, RequestError> {
+    verify_header(msg).inspect_err(|e| error!("control: parse header failed {e:?}"))?;
+
+    TlvReader::new(&msg[HEADER_SIZE..])
+        .next()
+        .ok_or(RequestError::OutOfBounds)?
+}
+
+/// msg is a Resize, even one that is refused.
+pub(crate) fn is_resize(msg: &[u8]) -> bool {
+    parse_control_record(msg).is_ok_and(|record| record.tag == CTRL_RESIZE)
+}
+
+/// A Resize exceeding limits is refused, like the request of the handshake.
+pub(crate) fn parse_control(
+    msg: &[u8],
+    limits: &ServerLimits,
+) -> Result<ControlMessage, RequestError> {
+    let record = parse_control_record(msg)?;
+
+    /* the sender's producers are our consumers */
+    let msg = match record.tag {
+        CTRL_PING => ControlMessage::Ping(record.u64()?),
+        CTRL_PONG => ControlMessage::Pong(record.u64()?),
+        CTRL_STATS_QUERY => ControlMessage::StatsQuery,
+        CTRL_STATS => ControlMessage::Stats(record.value.to_vec()),
+        CTRL_ADD_PRODUCER => ControlMessage::AddConsumer(parse_channel(&record)?),
+        CTRL_ADD_CONSUMER => ControlMessage::AddProducer(parse_channel(&record)?),
+        CTRL_CLOSE_PRODUCER => ControlMessage::CloseConsumer(record.u32()? as usize),
+        CTRL_CLOSE_CONSUMER => ControlMessage::CloseProducer(record.u32()? as usize),
+        CTRL_SHUTDOWN => ControlMessage::Shutdown,
+        CTRL_GOODBYE => ControlMessage::Goodbye,
+        CTRL_CONFIG => ControlMessage::Config(parse_config(&record)?),
+        CTRL_RESIZE => ControlMessage::Resize(parse_request(record.value, limits)?.vconfig),
+        CTRL_RESIZED => ControlMessage::Resized(record.u32()? != 0),
+        CTRL_SWITCHED => ControlMessage::Switched,
+        tag => {
+            error!("control: unknown message {tag}");
+            return Err(RequestError::UnknownRecord(tag));
+        }
+    };
+
+    Ok(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MAX_ARENA_BLOCKS;
+    use crate::header::MIN_VERSION;
+
+    fn channel(additional_messages: usize, message_size: usize) -> ChannelConfig {
+        ChannelConfig {
+            queue: QueueConfig {
+                additional_messages,
+                message_size: NonZeroUsize::new(message_size).unwrap(),
+                info: Vec::new(),
+                schema: None,
+            },
+            kind: ChannelKind::Queue,
+            eventfd: false,
+        }
+    }
+
+    fn vector(producers: Vec<ChannelConfig>, consumers: Vec<ChannelConfig>) -> VectorConfig {
+        VectorConfig {
+            producers,
+            consumers,
+            info: Vec::new(),
+            arena: None,
+            heartbeat: false,
+        }
+    }
+
+    /// Parses a request with the default limits.
+    fn parse(request: &[u8]) -> Result<Request, RequestError> {
+        parse_request(request, &ServerLimits::default())
+    }
+
+    #[test]
+    fn records_are_little_endian_and_memory_native() {
+        let vconfig = vector(vec![channel(2, 24)], Vec::new());
+        let mut request = create_request(&vconfig, Layout::native()).unwrap();
+
+        /* header and records are little endian on every architecture */
+        assert_eq!(request[2..4], RTIC_VERSION.to_le_bytes());
+        assert_eq!(
+            request[HEADER_SIZE..HEADER_SIZE + 2],
+            REQ_PRODUCER.to_le_bytes()
+        );
+
+        let producer = find_record(&request, REQ_PRODUCER).unwrap();
+        let additional = producer.nested().next().unwrap().unwrap();
+        assert_eq!(additional.tag, CH_ADDITIONAL_MESSAGES);
+        assert_eq!(additional.value, 2u32.to_le_bytes());
+
+        let parsed = parse(&request).unwrap();
+        assert_eq!(parsed.vconfig.consumers[0].queue.additional_messages, 2);
+        assert_eq!(parsed.vconfig.consumers[0].queue.message_size.get(), 24);
+
+        /* the shared memory of the requester is in the other byte order */
+        request[8] = if cfg!(target_endian = "little") { 2 } else { 1 };
+        assert!(matches!(
+            parse(&request),
+            Err(RequestError::HeaderError(HeaderError::EndiannessMismatch))
+        ));
+
+        let acknowledgement = Response::Accepted {
+            info: Vec::new(),
+            payload: Vec::new(),
+            token: 0,
+        };
+        let mut response = create_response(&acknowledgement, RTIC_VERSION).unwrap();
+        response[8] = request[8];
+        assert!(parse_response(&response).is_err());
+    }
+
+    #[test]
+    fn cacheline_sizes_are_negotiated() {
+        let vconfig = vector(vec![channel(1, 8)], vec![channel(0, 8)]);
+        let large = Layout {
+            cacheline_size: 256,
+            ..Layout::native()
+        };
+
+        /* the requester aligns to larger cache lines than ours */
+        let request = create_request(&vconfig, large).unwrap();
+        assert_eq!(parse(&request).unwrap().cacheline_size, 256);
+
+        /* the server asks for its cache line size */
+        let response = create_response(&Response::Retry { layout: large }, RTIC_VERSION).unwrap();
+        let Ok(Response::Retry { layout }) = parse_response(&response) else {
+            panic!("no retry");
+        };
+        assert_eq!(layout, large);
+
+        /* cache lines below a u64 or not a power of 2 */
+        for cacheline_size in [4, 48] {
+            let mut request = request.clone();
+            request[4..6].copy_from_slice(&(cacheline_size as u16).to_le_bytes());
+            assert!(matches!(
+                parse(&request),
+                Err(RequestError::HeaderError(HeaderError::InvalidCachelineSize))
+            ));
+        }
+    }
+
+    #[test]
+    fn index_widths_are_negotiated() {
+        let vconfig = vector(vec![channel(1, 8)], Vec::new());
+
+        for index_size in [size_of::<u32>(), size_of::<u64>()] {
+            let layout = Layout {
+                index_size,
+                ..Layout::native()
+            };
+
+            let request = create_request(&vconfig, layout).unwrap();
+            assert_eq!(request[6..8], (index_size as u16).to_le_bytes());
+            assert_eq!(parse(&request).unwrap().index_size, index_size);
+
+            let response = create_response(&Response::Retry { layout }, RTIC_VERSION).unwrap();
+            let Ok(Response::Retry { layout: retry }) = parse_response(&response) else {
+                panic!("no retry");
+            };
+            assert_eq!(retry.index_size, index_size);
+        }
+    }
+
+    #[test]
+    fn queries_are_answered_with_the_vector() {
+        let request = create_query(b"sensor", Layout::native()).unwrap();
+        let parsed = parse(&request).unwrap();
+        assert!(parsed.query);
+        assert_eq!(parsed.vconfig.info, b"sensor");
+        assert!(parsed.vconfig.producers.is_empty() && parsed.vconfig.consumers.is_empty());
+
+        /* the vector of a plain request isn't a query */
+        let vconfig = vector(vec![channel(1, 8)], Vec::new());
+        let request = create_request(&vconfig, Layout::native()).unwrap();
+        assert!(!parse(&request).unwrap().query);
+
+        let vconfig = VectorConfig {
+            info: b"server".to_vec(),
+            heartbeat: true,
+            ..vector(vec![channel(3, 16)], vec![channel(1, 8), channel(0, 32)])
+        };
+        let response = Response::Vector {
+            vconfig,
+            layout: Layout::native(),
+            token: 7,
+            owner: true,
+            pool_offset: Some(0x1000),
+            file_backed: true,
+        };
+        let response = create_response(&response, RTIC_VERSION).unwrap();
+
+        let Ok(Response::Vector {
+            vconfig,
+            layout,
+            token,
+            owner,
+            pool_offset,
+            file_backed,
+        }) = parse_response(&response)
+        else {
+            panic!("no vector");
+        };
+
+        /* the producers of the server are the consumers of the requester */
+        assert_eq!(vconfig.info, b"server");
+        assert!(vconfig.heartbeat);
+        assert_eq!(vconfig.consumers.len(), 1);
+        assert_eq!(vconfig.consumers[0].queue.additional_messages, 3);
+        assert_eq!(vconfig.producers.len(), 2);
+        assert_eq!(vconfig.producers[1].queue.message_size.get(), 32);
+        assert_eq!(layout, Layout::native());
+        assert_eq!(
+            (token, owner, pool_offset, file_backed),
+            (7, true, Some(0x1000), true)
+        );
+    }
+
+    #[test]
+    fn server_info_is_returned() {
+        let response = Response::Accepted {
+            info: b"server".to_vec(),
+            payload: Vec::new(),
+            token: 0,
+        };
+        let response = create_response(&response, RTIC_VERSION).unwrap();
+
+        let Ok(Response::Accepted {
+            info,
+            payload,
+            token,
+        }) = parse_response(&response)
+        else {
+            panic!("not accepted");
+        };
+        assert_eq!(info, b"server");
+        assert!(payload.is_empty());
+        assert_eq!(token, 0);
+
+        /* a legacy server accepts without info */
+        let Ok(Response::Accepted { info, .. }) = parse_response(&create_legacy_response(true))
+        else {
+            panic!("not accepted");
+        };
+        assert!(info.is_empty());
+    }
+
+    #[test]
+    fn rejections_keep_their_reason() {
+        let rejection = Rejection::new(42, "too many clients");
+        let response =
+            create_response(&Response::Rejected(rejection.clone()), RTIC_VERSION).unwrap();
+
+        let Ok(Response::Rejected(parsed)) = parse_response(&response) else {
+            panic!("not rejected");
+        };
+        assert_eq!(parsed, rejection);
+
+        /* the message is optional */
+        let rejection = Rejection::new(Rejection::UNSPECIFIED, "");
+        let response =
+            create_response(&Response::Rejected(rejection.clone()), RTIC_VERSION).unwrap();
+        assert!(matches!(parse_response(&response), Ok(Response::Rejected(r)) if r == rejection));
+
+        /* a legacy server rejects without reason */
+        let Ok(Response::Rejected(parsed)) = parse_response(&create_legacy_response(false)) else {
+            panic!("not rejected");
+        };
+        assert_eq!(parsed, Rejection::default());
+    }
+
+    #[test]
+    fn payloads_are_returned() {
+        /* larger than a fragment, the transport splits the response */
+        let sent = (0..0x20000).map(|i| i as u8).collect::<Vec<u8>>();
+        let response = Response::Accepted {
+            info: b"server".to_vec(),
+            payload: sent.clone(),
+            token: 3,
+        };
+        let response = create_response(&response, RTIC_VERSION).unwrap();
+
+        let Ok(Response::Accepted {
+            info,
+            payload,
+            token,
+        }) = parse_response(&response)
+        else {
+            panic!("not accepted");
+        };
+        assert_eq!(payload, sent);
+        assert_eq!(info, b"server");
+        assert_eq!(token, 3);
+    }
+
+    #[test]
+    fn cookies_are_carried_in_the_header() {
+        let vconfig = vector(vec![channel(1, 8)], Vec::new());
+
+        let mut request = create_request(&vconfig, Layout::native()).unwrap();
+        assert_eq!(verify_header(&request).unwrap().cookie, 0);
+
+        crate::header::write_cookie(&mut request, 0xdeadbeef);
+        assert_eq!(request[12..HEADER_SIZE], 0xdeadbeef_u32.to_le_bytes());
+        assert_eq!(verify_header(&request).unwrap().cookie, 0xdeadbeef);
+        assert!(parse(&request).is_ok());
+
+        /* the fixed header has no room for a cookie */
+        let request = create_request_fixed(&vconfig).unwrap();
+        assert_eq!(verify_header(&request).unwrap().cookie, 0);
+    }
+
+    #[test]
+    fn short_header_buffers_are_refused() {
+        let mut buf = [0xffu8; HEADER_SIZE - 1];
+
+        assert_eq!(
+            write_header(&mut buf, Layout::native(), layout_version(Layout::native())),
+            Err(ConfigError::InfoTooLong {
+                size: HEADER_SIZE,
+                max: HEADER_SIZE - 1,
+            })
+        );
+        assert_eq!(
+            write_fixed_header(&mut buf[..FIXED_HEADER_SIZE - 1]),
+            Err(ConfigError::InfoTooLong {
+                size: FIXED_HEADER_SIZE,
+                max: FIXED_HEADER_SIZE - 1,
+            })
+        );
+        assert!(buf.iter().all(|&b| b == 0xff));
+    }
+
+    /// Fragment of a message of size bytes.
+    fn fragment(flags: u16, size: usize, part: &[u8]) -> Vec<u8> {
+        let mut fragment = FRAGMENT_MAGIC.to_le_bytes().to_vec();
+        fragment.extend_from_slice(&flags.to_le_bytes());
+        fragment.extend_from_slice(&(size as u32).to_le_bytes());
+        fragment.extend_from_slice(part);
+        fragment
+    }
+
+    #[test]
+    fn large_messages_are_split_and_reassembled() {
+        /* small messages aren't fragments */
+        let msg = vec![1; MAX_FRAGMENT_SIZE];
+        assert_eq!(split_message(&msg), vec![msg.clone()]);
+        assert!(!is_fragment(&msg));
+
+        let msg = (0..3 * MAX_FRAGMENT_SIZE)
+            .map(|i| i as u8)
+            .collect::<Vec<u8>>();
+        let fragments = split_message(&msg);
+        assert_eq!(fragments.len(), 4);
+        assert!(fragments.iter().all(|f| f.len() <= MAX_FRAGMENT_SIZE));
+
+        let mut reassembly = Reassembly::default();
+        let (last, rest) = fragments.split_last().unwrap();
+        for fragment in rest {
+            assert_eq!(reassembly.push(fragment), Some(false));
+        }
+        assert_eq!(reassembly.push(last), Some(true));
+        assert_eq!(reassembly.into_message(), msg);
+    }
+
+    #[test]
+    fn broken_fragments_are_refused() {
+        /* not a fragment */
+        assert_eq!(Reassembly::default().push(&[0; 16]), None);
+        assert_eq!(Reassembly::default().push(&fragment(0, 0, &[])), None);
+
+        /* the message is too large to collect */
+        let oversized = fragment(FRAGMENT_MORE, MAX_MESSAGE_SIZE + 1, &[0; 8]);
+        assert_eq!(Reassembly::default().push(&oversized), None);
+
+        /* the size changes between fragments */
+        let mut reassembly = Reassembly::default();
+        assert_eq!(
+            reassembly.push(&fragment(FRAGMENT_MORE, 16, &[0; 8])),
+            Some(false)
+        );
+        assert_eq!(reassembly.push(&fragment(0, 24, &[0; 8])), None);
+
+        /* the fragments exceed the size */
+        let mut reassembly = Reassembly::default();
+        assert_eq!(
+            reassembly.push(&fragment(FRAGMENT_MORE, 16, &[0; 8])),
+            Some(false)
+        );
+        assert_eq!(
+            reassembly.push(&fragment(FRAGMENT_MORE, 16, &[0; 12])),
+            None
+        );
+
+        /* the last fragment ends before the size */
+        let mut reassembly = Reassembly::default();
+        assert_eq!(
+            reassembly.push(&fragment(FRAGMENT_MORE, 32, &[0; 8])),
+            Some(false)
+        );
+        assert_eq!(reassembly.push(&fragment(0, 32, &[0; 8])), None);
+    }
+
+    #[test]
+    fn fd_continuations_carry_their_count() {
+        for num_fds in [1, 253, u16::MAX as usize] {
+            let msg = create_fd_continuation(num_fds);
+            assert_eq!(parse_fd_continuation(&msg), Some(num_fds));
+            assert!(!is_fragment(&msg));
+        }
+
+        /* a request or a truncated continuation */
+        let msg = create_fd_continuation(2);
+        assert_eq!(parse_fd_continuation(&msg[..3]), None);
+        assert_eq!(
+            parse_fd_continuation(&[msg.as_slice(), &[0]].concat()),
+            None
+        );
+        assert_eq!(parse_fd_continuation(&[0x0c, 0x1f, 2, 0]), None);
+    }
+
+    /// Request with an additional record of tag.
+    fn append_record(request: Vec<u8>, tag: u16, flags: u16) -> Vec<u8> {
+        let mut writer = TlvWriter::new(request);
+        writer.put_u32(tag, flags, 1);
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn requests_exceeding_limits_are_refused() {
+        /* two full queues exceed the shared memory */
+        let full = vector(vec![channel(5, 64)], vec![channel(5, 64)]);
+        let limits = ServerLimits {
+            max_channels: 2,
+            max_queue_depth: 8,
+            max_message_size: 64,
+            max_shm_size: full.calc_layout_shm_size(Layout::native()) - 1,
+            max_dmabufs: 0,
+        };
+        let refused = |vconfig: &VectorConfig| {
+            let request = create_request(vconfig, Layout::native()).unwrap();
+            matches!(
+                parse_request(&request, &limits),
+                Err(RequestError::LimitExceeded)
+            )
+        };
+
+        assert!(!refused(&vector(vec![channel(5, 64)], vec![channel(0, 8)])));
+        assert!(refused(&vector(
+            vec![channel(1, 8); 2],
+            vec![channel(1, 8)]
+        )));
+        assert!(refused(&vector(vec![channel(6, 8)], Vec::new())));
+        assert!(refused(&vector(vec![channel(1, 65)], Vec::new())));
+
+        let arena = |block_size, num_blocks| VectorConfig {
+            arena: Some(ArenaConfig {
+                block_size: NonZeroUsize::new(block_size).unwrap(),
+                num_blocks: NonZeroUsize::new(num_blocks).unwrap(),
+            }),
+            ..vector(vec![channel(1, 8)], Vec::new())
+        };
+        assert!(!refused(&arena(8, 2)));
+        assert!(refused(&arena(64, 0x1000)));
+
+        /* small enough, but beyond the blocks a handle addresses */
+        let request = create_request(&arena(8, MAX_ARENA_BLOCKS + 1), Layout::native()).unwrap();
+        assert!(matches!(parse(&request), Err(RequestError::LimitExceeded)));
+
+        /* every queue is within the limits, the vector isn't */
+        assert!(refused(&full));
+        let request = create_backed_request(
+            &vector(vec![channel(1, 8)], Vec::new()),
+            Layout::native(),
+            false,
+            1,
+        )
+        .unwrap();
+        assert!(matches!(
+            parse_request(&request, &limits),
+            Err(RequestError::LimitExceeded)
+        ));
+    }
+
+    #[test]
+    fn requests_overflowing_the_shm_size_are_refused() {
+        let limits = ServerLimits {
+            max_channels: usize::MAX,
+            max_queue_depth: usize::MAX,
+            max_message_size: usize::MAX,
+            max_shm_size: usize::MAX,
+            max_dmabufs: 0,
+        };
+
+        let vconfig = vector(
+            vec![channel(u32::MAX as usize, u32::MAX as usize)],
+            Vec::new(),
+        );
+        let request = create_request(&vconfig, Layout::native()).unwrap();
+        assert!(matches!(
+            parse_request(&request, &limits),
+            Err(RequestError::LimitExceeded)
+        ));
+    }
+
+    #[test]
+    fn malformed_requests_are_refused() {
+        let vconfig = vector(vec![channel(1, 8)], Vec::new());
+        let request = create_request(&vconfig, Layout::native()).unwrap();
+
+        assert!(matches!(
+            parse(&request[..HEADER_SIZE - 1]),
+            Err(RequestError::HeaderError(HeaderError::SizeExceedsRequest))
+        ));
+
+        /* the last record is cut off */
+        assert!(matches!(
+            parse(&request[..request.len() - 1]),
+            Err(RequestError::OutOfBounds)
+        ));
+        assert!(matches!(
+            parse(&request[..HEADER_SIZE + 3]),
+            Err(RequestError::OutOfBounds)
+        ));
+
+        /* the first record claims more than the request */
+        let mut oversized = request.clone();
+        oversized[HEADER_SIZE + 4..HEADER_SIZE + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(parse(&oversized), Err(RequestError::OutOfBounds)));
+
+        /* records of later versions are skipped unless they are critical */
+        let unknown = append_record(request.clone(), 0x7fff, 0);
+        assert!(parse(&unknown).is_ok());
+
+        let unknown = append_record(request.clone(), 0x7fff, FLAG_CRITICAL);
+        assert!(matches!(
+            parse(&unknown),
+            Err(RequestError::UnknownRecord(0x7fff))
+        ));
+
+        /* the same within a channel */
+        let mut writer = TlvWriter::new(request[..HEADER_SIZE].to_vec());
+        writer.put_nested(REQ_PRODUCER, FLAG_CRITICAL, |w| {
+            w.put_u32(CH_MESSAGE_SIZE, FLAG_CRITICAL, 8);
+            w.put_u32(0x7fff, FLAG_CRITICAL, 1);
+        });
+        assert!(matches!(
+            parse(&writer.finish().unwrap()),
+            Err(RequestError::UnknownRecord(0x7fff))
+        ));
+    }
+
+    #[test]
+    fn fd_counts_are_announced() {
+        let mut vconfig = vector(vec![channel(1, 8); 2], vec![channel(1, 8)]);
+        vconfig.producers[1].eventfd = true;
+        vconfig.consumers[0].eventfd = true;
+
+        let request = create_request(&vconfig, Layout::native()).unwrap();
+        assert_eq!(parse_fd_count(&request), vconfig.count_fds());
+        assert_eq!(parse_fd_count(&request), 3);
+
+        /* the dma-bufs follow the eventfds */
+        let request = create_backed_request(&vconfig, Layout::native(), false, 2).unwrap();
+        assert_eq!(parse_fd_count(&request), 5);
+
+        /* no count, a malformed one or none of the TLV protocol */
+        let request = create_resume(1, RTIC_VERSION).unwrap();
+        assert_eq!(parse_fd_count(&request), 0);
+
+        let mut writer = TlvWriter::new(request[..HEADER_SIZE].to_vec());
+        writer.put_bytes(REQ_FD_COUNT, 0, &[3, 0]);
+        assert_eq!(parse_fd_count(&writer.finish().unwrap()), 0);
+
+        assert_eq!(parse_fd_count(&create_request_fixed(&vconfig).unwrap()), 0);
+        assert_eq!(parse_fd_count(&[0; 4]), 0);
+    }
+
+    #[test]
+    fn requests_are_answered_in_their_version() {
+        let vconfig = vector(vec![channel(1, 8)], Vec::new());
+        let old = Layout {
+            descriptors: false,
+            ..Layout::native()
+        };
+
+        /* vectors without descriptors are requested in the oldest version */
+        let request = create_request(&vconfig, old).unwrap();
+        let parsed = parse(&request).unwrap();
+        assert_eq!(parsed.version, MIN_VERSION);
+        assert!(!parsed.descriptors);
+        assert_eq!(request_version(&request), MIN_VERSION);
+
+        let request = create_request(&vconfig, Layout::native()).unwrap();
+        let parsed = parse(&request).unwrap();
+        assert_eq!(parsed.version, RTIC_VERSION);
+        assert!(parsed.descriptors);
+
+        /* the answer of an old requester has no descriptors either */
+        let response = create_response(&Response::Retry { layout: old }, MIN_VERSION).unwrap();
+        assert_eq!(request_version(&response), MIN_VERSION);
+        let Ok(Response::Retry { layout }) = parse_response(&response) else {
+            panic!("no retry");
+        };
+        assert!(!layout.descriptors);
+
+        for version in [MIN_VERSION - 1, RTIC_VERSION + 1] {
+            let mut request = request.clone();
+            request[2..4].copy_from_slice(&version.to_le_bytes());
+            assert!(matches!(
+                parse(&request),
+                Err(RequestError::HeaderError(HeaderError::VersionMismatch))
+            ));
+            assert_eq!(request_version(&request), RTIC_VERSION);
+        }
+
+        /* librtipc before the TLV protocol */
+        let request = create_request_fixed(&vconfig).unwrap();
+        assert!(is_legacy_request(&request));
+        assert_eq!(parse(&request).unwrap().version, FIXED_LAYOUT_VERSION);
+    }
+
+    /* sizes exceeding u32 only exist with 64 bit pointers */
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn fields_exceeding_u32_are_refused() {
+        let layout = Layout::native();
+
+        let vconfig = vector(vec![channel(1, 8)], vec![channel(1, 1 << 32)]);
+        assert_eq!(
+            create_request(&vconfig, layout),
+            Err(ConfigError::QueueOverflow {
+                producer: false,
+                index: 0
+            })
+        );
+        assert_eq!(
+            create_request_fixed(&vconfig),
+            Err(ConfigError::QueueOverflow {
+                producer: false,
+                index: 0
+            })
+        );
+
+        let vconfig = vector(vec![channel(1 << 32, 8)], Vec::new());
+        assert_eq!(
+            create_request(&vconfig, layout),
+            Err(ConfigError::QueueOverflow {
+                producer: true,
+                index: 0
+            })
+        );
+
+        let vconfig = VectorConfig {
+            arena: Some(ArenaConfig {
+                block_size: NonZeroUsize::new(1 << 32).unwrap(),
+                num_blocks: NonZeroUsize::new(1).unwrap(),
+            }),
+            ..vector(vec![channel(1, 8)], Vec::new())
+        };
+        assert_eq!(
+            create_request(&vconfig, layout),
+            Err(ConfigError::ArenaOverflow)
+        );
+
+        let msg = ControlMessage::AddProducer(channel(1, 1 << 32));
+        assert_eq!(
+            create_control(&msg, RTIC_VERSION),
+            Err(ConfigError::QueueOverflow {
+                producer: true,
+                index: 0
+            })
+        );
+
+        let msg = ControlMessage::CloseConsumer(1 << 32);
+        assert_eq!(
+            create_control(&msg, RTIC_VERSION),
+            Err(ConfigError::ChannelOverflow)
+        );
     }
 }
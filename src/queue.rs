@@ -1,10 +1,13 @@
 use std::num::NonZeroUsize;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+use crate::Layout;
 use crate::QueueConfig;
-use crate::cacheline_aligned;
 use crate::error::*;
+use crate::mem_align;
 use crate::shm::{Chunk, Span};
+#[cfg(feature = "poison")]
+use crate::trace::*;
 
 use crate::AtomicIndex;
 use crate::Index;
@@ -18,7 +21,97 @@ const ORIGIN_MASK: Index = CONSUMED_FLAG;
 
 const INDEX_MASK: Index = !(ORIGIN_MASK | FIRST_FLAG);
 
-#[derive(PartialEq, Eq)]
+/* unwritten and released messages are filled with it by the poison feature */
+#[cfg(feature = "poison")]
+const POISON: u8 = 0xa5;
+
+/* flags of a wide index are in the upper bits of the u64 */
+fn to_wide(val: Index) -> u64 {
+    if val == INVALID_INDEX {
+        u64::MAX
+    } else {
+        (((val & !INDEX_MASK) as u64) << Index::BITS) | (val & INDEX_MASK) as u64
+    }
+}
+
+fn from_wide(val: u64) -> Index {
+    if val == u64::MAX {
+        INVALID_INDEX
+    } else {
+        ((val >> Index::BITS) as Index & !INDEX_MASK) | (val as Index & INDEX_MASK)
+    }
+}
+
+/// Index in shared memory, the width is negotiated during the handshake.
+/// Indices are always handled as Index, only the shared memory representation differs.
+#[derive(Copy, Clone)]
+enum IndexPtr {
+    Narrow(*mut Index),
+    Wide(*mut u64),
+}
+
+impl IndexPtr {
+    fn new(chunk: &Chunk, offset: usize, index_size: usize) -> Result<Self, ShmMapError> {
+        if index_size == size_of::<u64>() {
+            let ptr: *mut u64 = chunk.get_ptr(offset)?;
+            if !ptr.is_aligned() {
+                return Err(ShmMapError::Misalignment);
+            }
+            Ok(IndexPtr::Wide(ptr))
+        } else {
+            Ok(IndexPtr::Narrow(chunk.get_ptr(offset)?))
+        }
+    }
+
+    fn load(self) -> Index {
+        match self {
+            IndexPtr::Narrow(ptr) => unsafe { AtomicIndex::from_ptr(ptr) }.load(Ordering::SeqCst),
+            IndexPtr::Wide(ptr) => {
+                from_wide(unsafe { AtomicU64::from_ptr(ptr) }.load(Ordering::SeqCst))
+            }
+        }
+    }
+
+    fn store(self, val: Index) {
+        match self {
+            IndexPtr::Narrow(ptr) => {
+                unsafe { AtomicIndex::from_ptr(ptr) }.store(val, Ordering::SeqCst)
+            }
+            IndexPtr::Wide(ptr) => {
+                unsafe { AtomicU64::from_ptr(ptr) }.store(to_wide(val), Ordering::SeqCst)
+            }
+        }
+    }
+
+    fn fetch_or(self, val: Index) -> Index {
+        match self {
+            IndexPtr::Narrow(ptr) => {
+                unsafe { AtomicIndex::from_ptr(ptr) }.fetch_or(val, Ordering::SeqCst)
+            }
+            IndexPtr::Wide(ptr) => from_wide(
+                unsafe { AtomicU64::from_ptr(ptr) }.fetch_or(to_wide(val), Ordering::SeqCst),
+            ),
+        }
+    }
+
+    fn compare_exchange(self, current: Index, new: Index) -> bool {
+        match self {
+            IndexPtr::Narrow(ptr) => unsafe { AtomicIndex::from_ptr(ptr) }
+                .compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok(),
+            IndexPtr::Wide(ptr) => unsafe { AtomicU64::from_ptr(ptr) }
+                .compare_exchange(
+                    to_wide(current),
+                    to_wide(new),
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .is_ok(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum PopResult {
     /// An invalid index was written to shared memory (unrecoverable error).
     QueueError,
@@ -38,7 +131,33 @@ pub enum PopResult {
     SuccessMessagesDiscarded,
 }
 
-#[derive(PartialEq, Eq)]
+/// Spin loop hints between two polls of spin_pop at most, the backoff doubles while no
+/// message arrives, so the consumer doesn't keep the cache line of the producer busy.
+const MAX_SPIN_BACKOFF: u32 = 64;
+
+/// Polls pop until a message arrives, polls times at most and at least once.
+/// Neither the polls nor the spin loop hints in between enter the kernel.
+pub(crate) fn spin_pop(polls: u32, mut pop: impl FnMut() -> PopResult) -> PopResult {
+    let mut backoff = 1;
+    let mut result = pop();
+
+    for _ in 1..polls {
+        if result != PopResult::NoMessage && result != PopResult::NoNewMessage {
+            break;
+        }
+
+        for _ in 0..backoff {
+            std::hint::spin_loop();
+        }
+
+        backoff = (backoff * 2).min(MAX_SPIN_BACKOFF);
+        result = pop();
+    }
+
+    result
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum ForcePushResult {
     /// An invalid index was written to shared memory (unrecoverable error).
     QueueError,
@@ -50,7 +169,7 @@ pub enum ForcePushResult {
     SuccessMessageDiscarded,
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum TryPushResult {
     /// An invalid index was written to shared memory (unrecoverable error).
     QueueError,
@@ -63,35 +182,45 @@ pub enum TryPushResult {
 }
 
 pub(crate) struct Queue {
-    _chunk: Chunk,
+    chunk: Chunk,
     message_size: NonZeroUsize,
-    head: *mut Index,
-    tail: *mut Index,
-    chain: Vec<*mut Index>,
+    head: IndexPtr,
+    tail: IndexPtr,
+    chain: Vec<IndexPtr>,
     messages: Vec<*mut ()>,
 }
 
 impl Queue {
-    pub(crate) fn new(chunk: Chunk, config: &QueueConfig) -> Result<Self, ShmMapError> {
+    /// Offset of the first message behind the indices, tail, head and the chain.
+    pub(crate) fn messages_offset(config: &QueueConfig, layout: Layout) -> usize {
         let queue_len = config.additional_messages + MIN_MSGS;
-        let index_size = size_of::<Index>();
-        let queue_size = (2 + queue_len) * index_size;
-        let message_size = NonZeroUsize::new(cacheline_aligned(config.message_size.get())).unwrap();
+        mem_align((2 + queue_len) * layout.index_size, layout.cacheline_size)
+    }
+
+    pub(crate) fn new(
+        chunk: Chunk,
+        config: &QueueConfig,
+        layout: Layout,
+    ) -> Result<Self, ShmMapError> {
+        let queue_len = config.additional_messages + MIN_MSGS;
+        let index_size = layout.index_size;
+        let message_size =
+            NonZeroUsize::new(mem_align(config.message_size.get(), layout.cacheline_size)).unwrap();
 
         let mut offset_index = 0;
-        let mut offset = cacheline_aligned(queue_size);
+        let mut offset = Self::messages_offset(config, layout);
 
-        let tail: *mut Index = chunk.get_ptr(offset_index)?;
+        let tail = IndexPtr::new(&chunk, offset_index, index_size)?;
         offset_index += index_size;
 
-        let head: *mut Index = chunk.get_ptr(offset_index)?;
+        let head = IndexPtr::new(&chunk, offset_index, index_size)?;
         offset_index += index_size;
 
-        let mut chain: Vec<*mut Index> = Vec::with_capacity(queue_len);
+        let mut chain: Vec<IndexPtr> = Vec::with_capacity(queue_len);
         let mut messages: Vec<*mut ()> = Vec::with_capacity(queue_len);
 
         for _ in 0..queue_len {
-            let index: *mut Index = chunk.get_ptr(offset_index)?;
+            let index = IndexPtr::new(&chunk, offset_index, index_size)?;
             let message: *mut () = chunk.get_span_ptr(&Span {
                 offset,
                 size: message_size,
@@ -105,7 +234,7 @@ impl Queue {
         }
 
         Ok(Self {
-            _chunk: chunk,
+            chunk,
             message_size,
             head,
             tail,
@@ -121,61 +250,102 @@ impl Queue {
     pub(crate) fn init(&self) {
         self.tail_store(INVALID_INDEX);
         self.head_store(INVALID_INDEX);
+
+        #[cfg(feature = "poison")]
+        for idx in 0..self.len() {
+            self.poison_message(idx as Index);
+        }
     }
 
     pub(crate) fn message_size(&self) -> NonZeroUsize {
         self.message_size
     }
 
-    fn tail(&self) -> &AtomicIndex {
-        unsafe { AtomicIndex::from_ptr(self.tail) }
+    pub(crate) fn same_memory(&self, other: &Queue) -> bool {
+        self.chunk.same_memory(&other.chunk)
     }
 
-    fn head(&self) -> &AtomicIndex {
-        unsafe { AtomicIndex::from_ptr(self.head) }
-    }
-
-    fn chain(&self, idx: Index) -> &AtomicIndex {
-        unsafe { AtomicIndex::from_ptr(self.chain[idx as usize]) }
+    /// Address of the queue in the shared memory, identifies it in the USDT probes.
+    #[cfg(any(feature = "usdt", feature = "lttng", feature = "ftrace"))]
+    pub(crate) fn addr(&self) -> usize {
+        self.chunk.get_ptr::<u8>(0).map_or(0, |ptr| ptr as usize)
     }
 
     pub(self) fn tail_load(&self) -> Index {
-        self.tail().load(Ordering::SeqCst)
+        self.tail.load()
     }
 
     pub(self) fn tail_store(&self, val: Index) {
-        self.tail().store(val, Ordering::SeqCst)
+        self.tail.store(val)
     }
 
     pub(self) fn tail_fetch_or(&self, val: Index) -> Index {
-        self.tail().fetch_or(val, Ordering::SeqCst)
+        self.tail.fetch_or(val)
     }
 
     pub(self) fn tail_compare_exchange(&self, current: Index, new: Index) -> bool {
-        self.tail()
-            .compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst)
-            .is_ok()
+        self.tail.compare_exchange(current, new)
     }
 
     pub(self) fn head_load(&self) -> Index {
-        self.head().load(Ordering::SeqCst)
+        self.head.load()
     }
 
     pub(self) fn head_store(&self, val: Index) {
-        self.head().store(val, Ordering::SeqCst);
+        self.head.store(val);
     }
 
     pub(self) fn chain_load(&self, idx: Index) -> Index {
-        self.chain(idx).load(Ordering::SeqCst)
+        self.chain[idx as usize].load()
     }
 
     pub(self) fn queue_store(&self, idx: Index, val: Index) {
-        self.chain(idx).store(val, Ordering::SeqCst);
+        self.chain[idx as usize].store(val);
     }
 
     pub(self) fn len(&self) -> usize {
         self.chain.len()
     }
+
+    fn clean_message(&self, idx: Index) {
+        self.chunk
+            .clean(self.messages[idx as usize], self.message_size.get());
+    }
+
+    fn invalidate_message(&self, idx: Index) {
+        self.chunk
+            .invalidate(self.messages[idx as usize], self.message_size.get());
+    }
+
+    #[cfg(feature = "poison")]
+    fn poison_message(&self, idx: Index) {
+        unsafe {
+            std::ptr::write_bytes(
+                self.messages[idx as usize].cast::<u8>(),
+                POISON,
+                self.message_size.get(),
+            );
+        }
+    }
+}
+
+/// Logs reads of a message holding a word of the poison pattern, i.e. a message or
+/// a field the producer didn't write. A no-op without the poison feature.
+#[cfg_attr(not(feature = "poison"), allow(unused_variables))]
+pub(crate) fn check_poison(msg: *const (), size: usize) {
+    #[cfg(feature = "poison")]
+    {
+        let bytes = unsafe { std::slice::from_raw_parts(msg.cast::<u8>(), size) };
+        /* messages smaller than a word are checked as a whole */
+        let word = size_of::<u64>().min(size).max(1);
+
+        if bytes
+            .chunks_exact(word)
+            .any(|word| word.iter().all(|&b| b == POISON))
+        {
+            error!("message {msg:p} is poisoned, it wasn't written or was released already");
+        }
+    }
 }
 
 // every Queue has its own shared memory region
@@ -187,10 +357,98 @@ pub struct ProducerQueue {
     head: Index, /* last message in chain that can be used by consumer, chain[head] is always INDEX_END */
     current: Index, /* message used by producer, will become head  */
     overrun: Index, /* message used by consumer when tail moved away by producer, will become current when released by consumer */
+    stamps: Vec<u64>, /* number of the push that queued the message, for the depth of the queue */
+    pushes: u64,
 }
 
 impl ProducerQueue {
     pub(crate) fn new(queue: Queue) -> Self {
+        /* the queue of a resumed vector keeps its messages */
+        match Self::recover(queue) {
+            Ok(producer) => producer,
+            Err(queue) => Self::reset(queue),
+        }
+    }
+
+    /// Restores the producer state from a queue used by a previous producer,
+    /// fails if the queue was never used or isn't consistent.
+    fn recover(queue: Queue) -> Result<Self, Queue> {
+        let head = queue.head_load();
+        let tail = queue.tail_load();
+
+        if !queue.is_valid_index(head) || !queue.is_valid_index(tail & INDEX_MASK) {
+            return Err(queue);
+        }
+
+        let queue_len = queue.len();
+        let chain: Vec<Index> = (0..queue_len)
+            .map(|i| queue.chain_load(i as Index))
+            .collect();
+
+        /* messages from tail to head are queued for the consumer */
+        let mut queued = vec![false; queue_len];
+        let mut idx = tail & INDEX_MASK;
+
+        loop {
+            if queued[idx as usize] {
+                return Err(queue);
+            }
+
+            queued[idx as usize] = true;
+
+            if idx == head {
+                break;
+            }
+
+            idx = chain[idx as usize];
+
+            if !queue.is_valid_index(idx) {
+                return Err(queue);
+            }
+        }
+
+        let free: Vec<Index> = (0..queue_len as Index)
+            .filter(|&i| !queued[i as usize])
+            .collect();
+
+        let links_to_queue = |i: Index| {
+            let next = chain[i as usize];
+            queue.is_valid_index(next) && queued[next as usize]
+        };
+
+        let (current, overrun) = if tail & CONSUMED_FLAG == 0 && free.len() == 2 {
+            /* the previous producer may have overrun the consumer, which still uses
+             * the overrun message, only the message linking to the queue is ours */
+            let Some(&current) = free.iter().find(|&&i| links_to_queue(i)) else {
+                return Err(queue);
+            };
+            let overrun = free.iter().copied().find(|&i| i != current).unwrap();
+            (current, overrun)
+        } else {
+            /* no free message links to the first one of the free messages */
+            let Some(&current) = free
+                .iter()
+                .find(|&&i| !free.iter().any(|&j| chain[j as usize] == i))
+            else {
+                return Err(queue);
+            };
+            (current, INVALID_INDEX)
+        };
+
+        let stamps = vec![0; queue_len];
+
+        Ok(Self {
+            queue,
+            head,
+            chain,
+            current,
+            overrun,
+            stamps,
+            pushes: 0,
+        })
+    }
+
+    fn reset(queue: Queue) -> Self {
         let queue_len = queue.len();
         let mut chain: Vec<Index> = Vec::with_capacity(queue_len);
         let last = queue_len - 1;
@@ -209,6 +467,8 @@ impl ProducerQueue {
             chain,
             current: 0,
             overrun: INVALID_INDEX,
+            stamps: vec![0; queue_len],
+            pushes: 0,
         }
     }
 
@@ -217,6 +477,16 @@ impl ProducerQueue {
         ptr.cast()
     }
 
+    #[cfg(any(feature = "usdt", feature = "lttng", feature = "ftrace"))]
+    pub(crate) fn addr(&self) -> usize {
+        self.queue.addr()
+    }
+
+    #[cfg(any(feature = "capnp", feature = "flatbuffers"))]
+    pub(crate) fn message_size(&self) -> NonZeroUsize {
+        self.queue.message_size
+    }
+
     fn queue_store(&mut self, idx: Index, val: Index) {
         self.chain[idx as usize] = val;
         self.queue.queue_store(idx, val);
@@ -227,7 +497,13 @@ impl ProducerQueue {
         self.queue.tail_compare_exchange(tail, next)
     }
 
+    fn stamp(&mut self) {
+        self.pushes += 1;
+        self.stamps[self.current as usize] = self.pushes;
+    }
+
     fn enqueue_first_message(&mut self) {
+        self.stamp();
         self.queue_store(self.current, INVALID_INDEX);
 
         self.queue.tail_store(self.current | FIRST_FLAG);
@@ -238,6 +514,7 @@ impl ProducerQueue {
     }
 
     fn enqueue_message(&mut self) {
+        self.stamp();
         self.queue_store(self.current, INVALID_INDEX);
 
         self.queue_store(self.head, self.current);
@@ -285,16 +562,64 @@ impl ProducerQueue {
             !consumed
         } else {
             let next = self.chain[self.current as usize];
-            let full: bool = next == (tail & INDEX_MASK);
 
-            !full
+            next == (tail & INDEX_MASK)
+        }
+    }
+
+    /// Messages queued and not consumed yet, from the stamps of the pushes, so it
+    /// takes a load of the tail only. Messages queued by a previous producer of a
+    /// resumed queue aren't stamped, the depth is capped to the queue length.
+    pub(crate) fn depth(&self) -> usize {
+        if self.head == INVALID_INDEX {
+            return 0;
+        }
+
+        let tail = self.queue.tail_load();
+
+        if !self.queue.is_valid_index(tail & INDEX_MASK) {
+            return 0;
         }
+
+        let oldest = self.stamps[(tail & INDEX_MASK) as usize];
+        let mut depth = self.pushes.saturating_sub(oldest) + 1;
+
+        if tail & CONSUMED_FLAG != 0 {
+            depth -= 1;
+        }
+
+        depth.min(self.queue.len() as u64) as usize
+    }
+
+    pub(crate) fn force_push(&mut self) -> ForcePushResult {
+        let result = self.force_push_current();
+        if result != ForcePushResult::QueueError {
+            self.acquired();
+        }
+        result
+    }
+
+    pub(crate) fn try_push(&mut self) -> TryPushResult {
+        let result = self.try_push_current();
+        if result == TryPushResult::Success {
+            self.acquired();
+        }
+        result
+    }
+
+    /* the producer writes the new current message next, the poison feature
+     * makes leftovers of the consumer and unwritten fields visible */
+    fn acquired(&self) {
+        #[cfg(feature = "poison")]
+        self.queue.poison_message(self.current);
     }
 
     /* inserts the next message into the queue and
      * if the queue is full, discard the last message that is not
      * used by consumer. Returns pointer to new message */
-    pub(crate) fn force_push(&mut self) -> ForcePushResult {
+    fn force_push_current(&mut self) -> ForcePushResult {
+        self.queue.clean_message(self.current);
+
         let next = self.chain[self.current as usize];
 
         if self.head == INVALID_INDEX {
@@ -374,7 +699,9 @@ impl ProducerQueue {
     }
 
     /* trys to insert the next message into the queue */
-    pub(crate) fn try_push(&mut self) -> TryPushResult {
+    fn try_push_current(&mut self) -> TryPushResult {
+        self.queue.clean_message(self.current);
+
         let next = self.chain[self.current as usize];
 
         if self.head == INVALID_INDEX {
@@ -427,12 +754,86 @@ impl ConsumerQueue {
         Self { queue, current: 0 }
     }
 
+    pub(crate) fn into_queue(self) -> Queue {
+        self.queue
+    }
+
+    /// Moves the messages not consumed yet to queue, which replaces the queue of the consumer.
+    /// queue has to be unused and its messages at least as large, returns the number of
+    /// moved messages. The message popped last isn't moved, its slot belongs to the old
+    /// queue, current_message is None until the next pop.
+    pub(crate) fn migrate(&mut self, queue: Queue) -> usize {
+        let mut producer = ProducerQueue::reset(queue);
+        let mut moved = 0;
+
+        while matches!(
+            self.pop(),
+            PopResult::Success | PopResult::SuccessMessagesDiscarded
+        ) {
+            let src = self.queue.messages[self.current as usize];
+
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    src.cast::<u8>(),
+                    producer.current_message().cast::<u8>(),
+                    self.queue.message_size.get(),
+                );
+            }
+
+            producer.force_push();
+            moved += 1;
+        }
+
+        /* slot 0 of queue is the first moved message or the one the producer writes */
+        *self = Self {
+            queue: producer.queue,
+            current: INVALID_INDEX,
+        };
+        moved
+    }
+
     pub(crate) fn current_message(&self) -> Option<*const ()> {
         let ptr = self.queue.messages.get(self.current as usize)?;
         Some(ptr.cast())
     }
 
+    #[cfg(any(feature = "usdt", feature = "lttng", feature = "ftrace"))]
+    pub(crate) fn addr(&self) -> usize {
+        self.queue.addr()
+    }
+
+    #[cfg(any(feature = "capnp", feature = "flatbuffers"))]
+    pub(crate) fn message_size(&self) -> NonZeroUsize {
+        self.queue.message_size
+    }
+
+    /// Whether the queue is placed in the same shared memory as the queue of producer.
+    pub(crate) fn same_memory(&self, producer: &ProducerQueue) -> bool {
+        self.queue.same_memory(&producer.queue)
+    }
+
     pub(crate) fn flush(&mut self) -> PopResult {
+        let result = self.flush_tail();
+        self.fetched(result)
+    }
+
+    pub(crate) fn pop(&mut self) -> PopResult {
+        let result = self.pop_tail();
+        self.fetched(result)
+    }
+
+    /* the consumer reads the new message next */
+    fn fetched(&self, result: PopResult) -> PopResult {
+        if matches!(
+            result,
+            PopResult::Success | PopResult::SuccessMessagesDiscarded
+        ) {
+            self.queue.invalidate_message(self.current);
+        }
+        result
+    }
+
+    fn flush_tail(&mut self) -> PopResult {
         loop {
             let tail = self.queue.tail_fetch_or(CONSUMED_FLAG);
 
@@ -464,7 +865,7 @@ impl ConsumerQueue {
         }
     }
 
-    pub(crate) fn pop(&mut self) -> PopResult {
+    fn pop_tail(&mut self) -> PopResult {
         let tail = self.queue.tail_fetch_or(CONSUMED_FLAG);
 
         if tail == INVALID_INDEX {
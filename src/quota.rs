@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::error::*;
+use crate::socket::PeerCredentials;
+use crate::trace::*;
+use crate::{Layout, VectorConfig};
+
+/// Clients sharing a quota.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum QuotaScope {
+    /// all processes of a user
+    #[default]
+    Uid,
+    Pid,
+}
+
+/// Upper bounds of the resources held by all accepted vectors of a client,
+/// counted until the shared memory of a vector is unmapped.
+#[derive(Clone, Debug)]
+pub struct ClientQuota {
+    pub scope: QuotaScope,
+    /// size of the shared memory regions
+    pub max_shm_size: usize,
+    /// producers and consumers
+    pub max_channels: usize,
+    pub max_eventfds: usize,
+}
+
+impl Default for ClientQuota {
+    fn default() -> Self {
+        Self {
+            scope: QuotaScope::Uid,
+            max_shm_size: usize::try_from(0x100000000u64).unwrap_or(usize::MAX),
+            max_channels: 0x10000,
+            max_eventfds: 0x1000,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct Usage {
+    shm_size: usize,
+    channels: usize,
+    eventfds: usize,
+}
+
+impl Usage {
+    fn of(vconfig: &VectorConfig, layout: Layout) -> Self {
+        Self {
+            shm_size: vconfig.calc_layout_shm_size(layout),
+            channels: vconfig.producers.len() + vconfig.consumers.len(),
+            eventfds: vconfig.count_producer_eventfds() + vconfig.count_consumer_eventfds(),
+        }
+    }
+}
+
+/// Resources in use per client.
+#[derive(Debug)]
+pub(crate) struct QuotaLedger {
+    quota: ClientQuota,
+    used: Mutex<HashMap<u32, Usage>>,
+}
+
+impl QuotaLedger {
+    pub(crate) fn new(quota: ClientQuota) -> Arc<Self> {
+        Arc::new(Self {
+            quota,
+            used: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn key(&self, cred: &PeerCredentials) -> u32 {
+        match self.quota.scope {
+            QuotaScope::Uid => cred.uid,
+            QuotaScope::Pid => cred.pid as u32,
+        }
+    }
+
+    /// Counts the vector against the quota of the client,
+    /// must be called before the shared memory is mapped.
+    pub(crate) fn charge(
+        self: &Arc<Self>,
+        cred: &PeerCredentials,
+        vconfig: &VectorConfig,
+        layout: Layout,
+    ) -> Result<QuotaCharge, RequestError> {
+        let key = self.key(cred);
+        let usage = Usage::of(vconfig, layout);
+
+        let mut used = self.used.lock().unwrap();
+        let total = used.get(&key).copied().unwrap_or_default();
+
+        let sum = Usage {
+            shm_size: total.shm_size + usage.shm_size,
+            channels: total.channels + usage.channels,
+            eventfds: total.eventfds + usage.eventfds,
+        };
+
+        if sum.shm_size > self.quota.max_shm_size
+            || sum.channels > self.quota.max_channels
+            || sum.eventfds > self.quota.max_eventfds
+        {
+            error!("client {key} exceeds quota, using {total:?}, requested {usage:?}");
+            return Err(RequestError::LimitExceeded);
+        }
+
+        used.insert(key, sum);
+
+        Ok(QuotaCharge {
+            ledger: self.clone(),
+            key,
+            usage,
+        })
+    }
+}
+
+/// Resources of a vector counted against the quota of its client,
+/// given back on drop.
+#[derive(Debug)]
+pub(crate) struct QuotaCharge {
+    ledger: Arc<QuotaLedger>,
+    key: u32,
+    usage: Usage,
+}
+
+impl Drop for QuotaCharge {
+    fn drop(&mut self) {
+        let mut used = self.ledger.used.lock().unwrap();
+
+        let Some(total) = used.get_mut(&self.key) else {
+            return;
+        };
+
+        total.shm_size -= self.usage.shm_size;
+        total.channels -= self.usage.channels;
+        total.eventfds -= self.usage.eventfds;
+
+        if *total == Usage::default() {
+            used.remove(&self.key);
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use crate::ChannelKind;
+
+/// Shared memory of a single channel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelUsage {
+    pub kind: ChannelKind,
+    /// message slots of the channel, the counters of ChannelKind::Counters
+    pub slots: usize,
+    /// bytes of a slot, messages are padded to cache lines
+    pub slot_size: usize,
+    /// bytes of shared memory including the indices of the channel
+    pub shm_size: usize,
+}
+
+/// Memory used by a vector, e.g. for exporting the memory usage per IPC link,
+/// see ChannelVector::memory_report.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// bytes of the mapping holding the vector, the whole region for user supplied memory
+    pub mapped: usize,
+    /// bytes locked into memory, either mapped or 0
+    pub locked: usize,
+    /// bytes used by the channels, the arena and the heartbeat stamps
+    pub used: usize,
+    /// bytes of the mapped dma-bufs
+    pub dmabufs: usize,
+    pub producers: Vec<ChannelUsage>,
+    pub consumers: Vec<ChannelUsage>,
+}
@@ -2,39 +2,107 @@ use std::{
     collections::VecDeque,
     num::NonZeroUsize,
     os::fd::{AsFd, BorrowedFd, OwnedFd},
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use nix::sys::eventfd::EventFd;
+use nix::unistd::dup;
 
 use crate::{
-    ChannelConfig, QueueConfig, VectorConfig,
+    ArenaConfig, ChannelConfig, ChannelKind, EventFd, Layout, QueueConfig, VectorConfig, cacheline,
     error::*,
-    protocol::{create_request, parse_request},
-    unix::{check_memfd, eventfd_create, into_eventfd, shmfd_create},
+    index_size, is_supported_index_size, max_cacheline_size,
+    pool::{PoolRegion, ShmPool},
+    quota::QuotaCharge,
+    shm::{MapOptions, MemoryRegion, ShmBacking},
+    trace::error,
+    unix::{
+        check_dmabuf, check_file, check_memfd, eventfd_create, fd_size, into_eventfd, link_file,
+        shm_file_create, shm_named_create, shm_named_unlink, shm_preallocate, shm_tmpfile_create,
+        shmfd_create,
+    },
 };
 use nix::errno::Errno;
 
+#[cfg(feature = "socket")]
+use crate::{
+    ServerLimits,
+    protocol::{
+        REQ_QUERY, REQ_RESUME, REQ_SHM_BACKING, REQ_SHM_NAME, REQ_SHM_OFFSET,
+        create_backed_request, create_named_request, parse_request,
+    },
+    unix::shm_named_open,
+};
+
 pub struct ChannelResource {
     pub config: QueueConfig,
+    pub kind: ChannelKind,
     pub eventfd: Option<EventFd>,
 }
 
 impl ChannelResource {
-    pub fn new(config: &QueueConfig, eventfd_raw: Option<OwnedFd>) -> Result<Self, Errno> {
+    pub fn new(
+        config: &QueueConfig,
+        kind: ChannelKind,
+        eventfd_raw: Option<OwnedFd>,
+    ) -> Result<Self, Errno> {
         let eventfd = eventfd_raw.map(into_eventfd).transpose()?;
         Ok(Self {
             config: config.clone(),
+            kind,
             eventfd,
         })
     }
+
+    pub(crate) fn shm_size(&self, layout: Layout) -> NonZeroUsize {
+        self.kind.shm_size(&self.config, layout)
+    }
+}
+
+/// Shared memory without seals a server accepts from its clients.
+#[cfg(feature = "socket")]
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct Unsealed {
+    /// regular files, see ShmBacking::File
+    pub file: bool,
+    /// objects passed by name, see ShmBacking::Named
+    pub named: bool,
 }
 
 pub struct VectorResource {
     pub consumers: Vec<ChannelResource>,
     pub producers: Vec<ChannelResource>,
     pub info: Vec<u8>,
+    pub arena: Option<ArenaConfig>,
+    pub heartbeat: bool,
     pub shmfd: OwnedFd,
     pub owner: bool,
+    /// alignment of the shared memory layout, at least max_cacheline_size of both peers
+    pub cacheline_size: usize,
+    /// width of the queue indices in shared memory
+    pub index_size: usize,
+    /// the channels start with descriptors, see Layout::descriptors
+    pub(crate) descriptors: bool,
+    /// the shared memory was initialized by a previous session and keeps its messages
+    pub(crate) resumed: bool,
+    /// quota of the client the vector was accepted from, kept until the shared memory is unmapped
+    pub(crate) charge: Option<QuotaCharge>,
+    /// region of a pool holding the shared memory, the pool is already mapped
+    pub(crate) region: Option<PoolRegion>,
+    /// memory supplied by the user the channels are placed in instead of mapping shmfd
+    pub(crate) memory: Option<Box<dyn MemoryRegion>>,
+    /// offset of the vector in the received shared memory of a pool
+    pub(crate) pool_offset: Option<usize>,
+    /// how the shared memory is mapped, set by the options of the client or the server
+    pub(crate) map: MapOptions,
+    /// the shared memory is a regular file instead of a sealed memfd
+    pub(crate) file_backed: bool,
+    /// path the unnamed file of a TmpFile backing is linked to
+    pub(crate) link: Option<PathBuf>,
+    /// name of the shared memory object, passed to the peer instead of the fd
+    pub(crate) shm_name: Option<String>,
+    /// dma-bufs passed along with the shared memory, mapped by ChannelVector::new
+    pub(crate) dmabufs: Vec<OwnedFd>,
 }
 
 impl VectorResource {
@@ -54,7 +122,7 @@ impl VectorResource {
                 None
             };
 
-            let channel = ChannelResource::new(&config.queue, eventfd)?;
+            let channel = ChannelResource::new(&config.queue, config.kind, eventfd)?;
 
             channels.push(channel);
         }
@@ -69,6 +137,15 @@ impl VectorResource {
     ) -> Result<Self, TransferError> {
         check_memfd(shmfd.as_fd())?;
 
+        Self::with_shmfd(vconfig, shmfd, consumer_eventfds, producer_eventfds)
+    }
+
+    fn with_shmfd(
+        vconfig: &VectorConfig,
+        shmfd: OwnedFd,
+        consumer_eventfds: VecDeque<OwnedFd>,
+        producer_eventfds: VecDeque<OwnedFd>,
+    ) -> Result<Self, TransferError> {
         let consumers = Self::create_channel_resources(&vconfig.consumers, consumer_eventfds)?;
         let producers = Self::create_channel_resources(&vconfig.producers, producer_eventfds)?;
 
@@ -76,19 +153,186 @@ impl VectorResource {
             producers,
             consumers,
             info: vconfig.info.clone(),
+            arena: vconfig.arena.clone(),
+            heartbeat: vconfig.heartbeat,
             shmfd,
             owner: false,
+            cacheline_size: max_cacheline_size(),
+            index_size: index_size(),
+            descriptors: true,
+            resumed: false,
+            charge: None,
+            region: None,
+            memory: None,
+            pool_offset: None,
+            map: MapOptions::default(),
+            file_backed: false,
+            link: None,
+            shm_name: None,
+            dmabufs: Vec::new(),
         })
     }
 
     pub fn allocate(vconfig: &VectorConfig) -> Result<Self, ResourceError> {
-        let mut producers = Vec::<ChannelResource>::with_capacity(vconfig.producers.len());
-        let mut consumers = Vec::<ChannelResource>::with_capacity(vconfig.consumers.len());
+        Self::allocate_layout(vconfig, Layout::native())
+    }
 
-        let shm_size =
-            NonZeroUsize::new(vconfig.calc_shm_size()).ok_or(ResourceError::InvalidArgument)?;
+    /// Allocates with the layout of a peer on another architecture, e.g. with larger
+    /// cache lines or u64 indices. A peer refuses cache lines smaller than its own,
+    /// cache lines larger than MAX_CACHELINE_SIZE are refused here.
+    pub fn allocate_with_layout(
+        vconfig: &VectorConfig,
+        cacheline_size: usize,
+        index_size: usize,
+    ) -> Result<Self, ResourceError> {
+        /* u64 atomics are placed at cache line boundaries */
+        if !cacheline::is_valid(cacheline_size)
+            || cacheline_size < size_of::<u64>()
+            || !is_supported_index_size(index_size)
+        {
+            error!("layout {cacheline_size} {index_size} not supported");
+            return Err(ResourceError::InvalidArgument);
+        }
+
+        let layout = Layout {
+            cacheline_size,
+            index_size,
+            descriptors: true,
+        };
+
+        Self::allocate_layout(vconfig, layout)
+    }
+
+    pub(crate) fn allocate_layout(
+        vconfig: &VectorConfig,
+        layout: Layout,
+    ) -> Result<Self, ResourceError> {
+        Self::allocate_backed(vconfig, layout, &ShmBacking::Memfd)
+    }
+
+    /// Allocates the shared memory with backing, the file of a TmpFile backing
+    /// gets its name by link_backing.
+    pub(crate) fn allocate_backed(
+        vconfig: &VectorConfig,
+        layout: Layout,
+        backing: &ShmBacking,
+    ) -> Result<Self, ResourceError> {
+        if *backing == ShmBacking::Named {
+            let (mut rsc, name) = Self::allocate_named(vconfig, layout)?;
+            rsc.shm_name = Some(name);
+            return Ok(rsc);
+        }
+
+        let shm_size = NonZeroUsize::new(vconfig.calc_layout_shm_size(layout))
+            .ok_or(ResourceError::InvalidArgument)?;
+
+        let shmfd = match backing {
+            ShmBacking::Memfd | ShmBacking::Named => shmfd_create(shm_size)?,
+            ShmBacking::File(path) => shm_file_create(path, shm_size)?,
+            ShmBacking::TmpFile(path) => {
+                let dir = path
+                    .parent()
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .unwrap_or(Path::new("."));
+                shm_tmpfile_create(dir, shm_size)?
+            }
+        };
+
+        let mut rsc = Self::allocate_channels(vconfig, layout, shmfd)?;
+
+        rsc.file_backed = backing.is_file();
+
+        if let ShmBacking::TmpFile(path) = backing {
+            rsc.link = Some(path.clone());
+        }
+
+        Ok(rsc)
+    }
 
-        let shmfd = shmfd_create(shm_size)?;
+    /// Reserves the pages of the shared memory of the vector up front, a producer
+    /// could hit SIGBUS under memory pressure otherwise. Fails with ENOMEM or ENOSPC
+    /// if the memory isn't available.
+    pub fn preallocate(&self) -> Result<(), Errno> {
+        let size = NonZeroUsize::new(self.get_config().calc_layout_shm_size(self.layout()))
+            .ok_or(Errno::EINVAL)?;
+
+        shm_preallocate(self.shmfd.as_fd(), self.pool_offset.unwrap_or(0), size)
+    }
+
+    /// Links the unnamed file of a TmpFile backing to its path, a no-op for other backings.
+    pub(crate) fn link_backing(&self) -> Result<(), Errno> {
+        match &self.link {
+            Some(path) => link_file(self.shmfd.as_fd(), path),
+            None => Ok(()),
+        }
+    }
+
+    /// Allocates the shared memory as named object for a peer that can't receive fds,
+    /// the name has to be unlinked once the peer opened it.
+    pub(crate) fn allocate_named(
+        vconfig: &VectorConfig,
+        layout: Layout,
+    ) -> Result<(Self, String), ResourceError> {
+        let shm_size = NonZeroUsize::new(vconfig.calc_layout_shm_size(layout))
+            .ok_or(ResourceError::InvalidArgument)?;
+
+        let (shmfd, name) = shm_named_create(shm_size)?;
+
+        match Self::allocate_channels(vconfig, layout, shmfd) {
+            Ok(rsc) => Ok((rsc, name)),
+            Err(e) => {
+                shm_named_unlink(&name);
+                Err(e)
+            }
+        }
+    }
+
+    /// Places the vector at offset of the shared memory provided to both peers,
+    /// e.g. the memory of an ivshmem device.
+    #[cfg(not(target_os = "macos"))]
+    pub(crate) fn allocate_provided(
+        vconfig: &VectorConfig,
+        layout: Layout,
+        shm: BorrowedFd<'_>,
+        offset: usize,
+    ) -> Result<Self, TransferError> {
+        Self::check_layout(layout)?;
+
+        let shmfd = dup(shm)?;
+
+        Self::check_shm_size(vconfig, layout, &shmfd, Some(offset))?;
+
+        let mut rsc = Self::allocate_channels(vconfig, layout, shmfd)?;
+        rsc.pool_offset = Some(offset);
+        Ok(rsc)
+    }
+
+    /// Carves the shared memory out of pool, the peer receives the fd of the whole pool.
+    pub(crate) fn allocate_pooled(
+        vconfig: &VectorConfig,
+        layout: Layout,
+        pool: &Arc<ShmPool>,
+    ) -> Result<Self, ResourceError> {
+        let shm_size = NonZeroUsize::new(vconfig.calc_layout_shm_size(layout))
+            .ok_or(ResourceError::InvalidArgument)?;
+
+        let region = pool.alloc(shm_size)?;
+
+        let mut rsc = Self::allocate_channels(vconfig, layout, dup(region.fd())?)?;
+
+        rsc.pool_offset = Some(region.offset());
+        rsc.region = Some(region);
+
+        Ok(rsc)
+    }
+
+    fn allocate_channels(
+        vconfig: &VectorConfig,
+        layout: Layout,
+        shmfd: OwnedFd,
+    ) -> Result<Self, ResourceError> {
+        let mut producers = Vec::<ChannelResource>::with_capacity(vconfig.producers.len());
+        let mut consumers = Vec::<ChannelResource>::with_capacity(vconfig.consumers.len());
 
         for config in &vconfig.consumers {
             let eventfd = if config.eventfd {
@@ -100,6 +344,7 @@ impl VectorResource {
 
             let channel = ChannelResource {
                 config: config.queue.clone(),
+                kind: config.kind,
                 eventfd,
             };
 
@@ -116,6 +361,7 @@ impl VectorResource {
 
             let channel = ChannelResource {
                 config: config.queue.clone(),
+                kind: config.kind,
                 eventfd,
             };
 
@@ -126,17 +372,48 @@ impl VectorResource {
             consumers,
             producers,
             info: vconfig.info.clone(),
+            arena: vconfig.arena.clone(),
+            heartbeat: vconfig.heartbeat,
             shmfd,
             owner: true,
+            cacheline_size: layout.cacheline_size,
+            index_size: layout.index_size,
+            descriptors: layout.descriptors,
+            resumed: false,
+            charge: None,
+            region: None,
+            memory: None,
+            pool_offset: None,
+            map: MapOptions::default(),
+            file_backed: false,
+            link: None,
+            shm_name: None,
+            dmabufs: Vec::new(),
         })
     }
 
-    fn get_config(&self) -> VectorConfig {
+    /// Places the channels in region instead of mapping shmfd, e.g. a mapping of the
+    /// same memory by a device driver. The region has to hold the whole vector and
+    /// is initialized by the peer that isn't the owner, as with shmfd.
+    pub fn set_region(&mut self, region: Box<dyn MemoryRegion>) {
+        self.memory = Some(region);
+    }
+
+    pub(crate) fn layout(&self) -> Layout {
+        Layout {
+            cacheline_size: self.cacheline_size,
+            index_size: self.index_size,
+            descriptors: self.descriptors,
+        }
+    }
+
+    pub(crate) fn get_config(&self) -> VectorConfig {
         let consumers = self
             .consumers
             .iter()
             .map(|q| ChannelConfig {
                 queue: q.config.clone(),
+                kind: q.kind,
                 eventfd: q.eventfd.is_some(),
             })
             .collect();
@@ -145,6 +422,7 @@ impl VectorResource {
             .iter()
             .map(|q| ChannelConfig {
                 queue: q.config.clone(),
+                kind: q.kind,
                 eventfd: q.eventfd.is_some(),
             })
             .collect();
@@ -153,15 +431,18 @@ impl VectorResource {
             consumers,
             producers,
             info: self.info.clone(),
+            arena: self.arena.clone(),
+            heartbeat: self.heartbeat,
         }
     }
 
     pub fn add_consumer(
         &mut self,
         config: &QueueConfig,
+        kind: ChannelKind,
         eventfd: Option<OwnedFd>,
     ) -> Result<(), Errno> {
-        let channel = ChannelResource::new(config, eventfd)?;
+        let channel = ChannelResource::new(config, kind, eventfd)?;
         self.consumers.push(channel);
         Ok(())
     }
@@ -169,9 +450,10 @@ impl VectorResource {
     pub fn add_producer(
         &mut self,
         config: &QueueConfig,
+        kind: ChannelKind,
         eventfd: Option<OwnedFd>,
     ) -> Result<(), Errno> {
-        let channel = ChannelResource::new(config, eventfd)?;
+        let channel = ChannelResource::new(config, kind, eventfd)?;
         self.producers.push(channel);
         Ok(())
     }
@@ -192,6 +474,14 @@ impl VectorResource {
         self.shmfd.as_fd()
     }
 
+    /// Attaches a dma-buf passed to the peer along with the shared memory,
+    /// returns the index the messages of the channels refer to it by.
+    pub fn add_dmabuf(&mut self, fd: OwnedFd) -> Result<usize, Errno> {
+        check_dmabuf(fd.as_fd())?;
+        self.dmabufs.push(fd);
+        Ok(self.dmabufs.len() - 1)
+    }
+
     fn collect_eventfds(channels: &[ChannelResource]) -> Vec<BorrowedFd<'_>> {
         let fds: Vec<BorrowedFd<'_>> = channels
             .iter()
@@ -209,32 +499,304 @@ impl VectorResource {
         Self::collect_eventfds(&self.producers)
     }
 
-    pub fn serialize(&self) -> (Vec<u8>, Vec<BorrowedFd<'_>>) {
-        let vconfig = self.get_config();
-        let req = create_request(&vconfig);
+    /// fds in the order they are sent to the peer
+    pub(crate) fn collect_fds(&self) -> Vec<BorrowedFd<'_>> {
         let producer_eventfds = Self::collect_eventfds(&self.producers);
         let consumer_eventfds = Self::collect_eventfds(&self.consumers);
-        (
-            req,
-            [
-                vec![self.shmfd.as_fd()],
-                producer_eventfds,
-                consumer_eventfds,
-            ]
-            .concat(),
+
+        let dmabufs = self.dmabufs.iter().map(|fd| fd.as_fd()).collect();
+
+        [
+            vec![self.shmfd.as_fd()],
+            producer_eventfds,
+            consumer_eventfds,
+            dmabufs,
+        ]
+        .concat()
+    }
+
+    /// Request for the vector and the fds to attach to it.
+    #[cfg(feature = "socket")]
+    pub fn serialize(&self) -> Result<(Vec<u8>, Vec<BorrowedFd<'_>>), ConfigError> {
+        let vconfig = self.get_config();
+
+        if let Some(name) = &self.shm_name {
+            /* the peer opens the shared memory by its name, no fds are passed */
+            return Ok((
+                create_named_request(&vconfig, self.layout(), name)?,
+                Vec::new(),
+            ));
+        }
+        let req = create_backed_request(
+            &vconfig,
+            self.layout(),
+            self.file_backed,
+            self.dmabufs.len(),
+        )?;
+        Ok((req, self.collect_fds()))
+    }
+
+    /// Resource of the other side of the vector with duplicated fds,
+    /// for peers that don't need a handshake.
+    pub(crate) fn peer(&self) -> Result<Self, TransferError> {
+        let vconfig = self.get_config();
+
+        let mirrored = VectorConfig {
+            producers: vconfig.consumers,
+            consumers: vconfig.producers,
+            ..vconfig
+        };
+
+        let fds = self
+            .collect_fds()
+            .into_iter()
+            .map(dup)
+            .collect::<Result<VecDeque<OwnedFd>, Errno>>()?;
+
+        Self::from_config(
+            &mirrored,
+            self.layout(),
+            fds,
+            None,
+            self.file_backed,
+            self.dmabufs.len(),
         )
     }
 
-    pub fn deserialize(request: &[u8], mut fds: VecDeque<OwnedFd>) -> Result<Self, TransferError> {
-        let vconfig = parse_request(request)?;
+    /// Creates the resource of the peer that didn't allocate the shared memory,
+    /// vconfig is already mapped to our point of view.
+    fn check_layout(layout: Layout) -> Result<(), TransferError> {
+        /* the layout has to be aligned to our cache lines as well */
+        if layout.cacheline_size < max_cacheline_size() {
+            error!(
+                "layout cacheline size {} smaller than {}",
+                layout.cacheline_size,
+                max_cacheline_size()
+            );
+            return Err(RequestError::from(HeaderError::CachelineSizeMismatch).into());
+        }
+
+        if !is_supported_index_size(layout.index_size) {
+            error!("layout index size {} not supported", layout.index_size);
+            return Err(RequestError::from(HeaderError::AtomicSizeMismatch).into());
+        }
+
+        Ok(())
+    }
+
+    /// The channels are mapped at offsets computed from the layout,
+    /// a pool holds the vector somewhere in a larger region.
+    fn check_shm_size(
+        vconfig: &VectorConfig,
+        layout: Layout,
+        shmfd: &OwnedFd,
+        pool_offset: Option<usize>,
+    ) -> Result<(), TransferError> {
+        let shm_size = vconfig.calc_layout_shm_size(layout);
+        let received = fd_size(shmfd.as_fd())?;
+
+        let fits = match pool_offset {
+            Some(offset) => offset
+                .checked_add(shm_size)
+                .is_some_and(|end| end <= received),
+            None => received == shm_size,
+        };
+
+        if !fits {
+            error!("expected shm size {shm_size}, received {received}");
+            return Err(TransferError::ShmSizeMismatch {
+                expected: shm_size,
+                received,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn from_config(
+        vconfig: &VectorConfig,
+        layout: Layout,
+        mut fds: VecDeque<OwnedFd>,
+        pool_offset: Option<usize>,
+        file_backed: bool,
+        dmabufs: usize,
+    ) -> Result<Self, TransferError> {
+        Self::check_layout(layout)?;
+
+        let expected = vconfig.count_fds() + dmabufs;
+
+        /* fds are assigned by position, a wrong count would shift them to other channels */
+        if fds.len() != expected {
+            error!("expected {expected} fds, received {}", fds.len());
+            return Err(TransferError::FileDescriptorCountMismatch {
+                expected,
+                received: fds.len(),
+            });
+        }
+
+        let dmabufs = Vec::from(fds.split_off(fds.len() - dmabufs));
+
+        for fd in &dmabufs {
+            check_dmabuf(fd.as_fd())?;
+        }
+
         let shmfd = fds
             .pop_front()
             .ok_or(TransferError::MissingFileDescriptor)?;
 
+        /* without the seals the peer could shrink the memfd after its size was checked */
+        if file_backed {
+            check_file(shmfd.as_fd())?;
+        } else {
+            check_memfd(shmfd.as_fd())?;
+        }
+
+        Self::check_shm_size(vconfig, layout, &shmfd, pool_offset)?;
+
         let n_consumer_eventfds = vconfig.count_consumer_eventfds();
 
         let producer_eventfds = fds.split_off(n_consumer_eventfds);
 
-        VectorResource::new(&vconfig, shmfd, fds, producer_eventfds)
+        let mut rsc = Self::with_shmfd(vconfig, shmfd, fds, producer_eventfds)?;
+        rsc.cacheline_size = layout.cacheline_size;
+        rsc.index_size = layout.index_size;
+        rsc.descriptors = layout.descriptors;
+        rsc.pool_offset = pool_offset;
+        rsc.file_backed = file_backed;
+        rsc.dmabufs = dmabufs;
+        Ok(rsc)
+    }
+
+    /// Resource of a vector the server keeps for a session, vconfig is seen from the
+    /// server and fds are ordered like collect_fds.
+    #[cfg(feature = "socket")]
+    pub(crate) fn from_session(
+        vconfig: &VectorConfig,
+        layout: Layout,
+        mut fds: VecDeque<OwnedFd>,
+        owner: bool,
+        file_backed: bool,
+    ) -> Result<Self, TransferError> {
+        let shmfd = fds
+            .pop_front()
+            .ok_or(TransferError::MissingFileDescriptor)?;
+
+        let consumer_eventfds = fds.split_off(vconfig.count_producer_eventfds());
+
+        let mut rsc = Self::with_shmfd(vconfig, shmfd, consumer_eventfds, fds)?;
+        rsc.owner = owner;
+        rsc.cacheline_size = layout.cacheline_size;
+        rsc.index_size = layout.index_size;
+        rsc.descriptors = layout.descriptors;
+        rsc.file_backed = file_backed;
+        Ok(rsc)
+    }
+
+    /// Resource of a vector in shared memory that wasn't received as fd, e.g. a named
+    /// object or a region shared with a VM at offset. The shared memory isn't sealed,
+    /// the peer has to be trusted not to truncate it.
+    pub(crate) fn from_unsealed(
+        vconfig: &VectorConfig,
+        layout: Layout,
+        shmfd: OwnedFd,
+        offset: Option<usize>,
+    ) -> Result<Self, TransferError> {
+        Self::check_layout(layout)?;
+
+        if vconfig.count_fds() != 1 {
+            error!("eventfds can't be passed without fds");
+            return Err(ConfigError::EventFdsUnsupported.into());
+        }
+
+        Self::check_shm_size(vconfig, layout, &shmfd, offset)?;
+
+        let mut rsc = Self::with_shmfd(vconfig, shmfd, VecDeque::new(), VecDeque::new())?;
+        rsc.cacheline_size = layout.cacheline_size;
+        rsc.index_size = layout.index_size;
+        rsc.pool_offset = offset;
+        Ok(rsc)
+    }
+
+    #[cfg(feature = "socket")]
+    pub fn deserialize(request: &[u8], fds: VecDeque<OwnedFd>) -> Result<Self, TransferError> {
+        Self::deserialize_limited(request, fds, &ServerLimits::default())
+    }
+
+    /// Deserializes a request, vectors exceeding limits are refused before anything is mapped.
+    #[cfg(feature = "socket")]
+    pub fn deserialize_limited(
+        request: &[u8],
+        fds: VecDeque<OwnedFd>,
+        limits: &ServerLimits,
+    ) -> Result<Self, TransferError> {
+        Self::deserialize_backed(request, fds, limits, Unsealed::default())
+    }
+
+    /// Like deserialize_limited, shared memory without seals is only accepted
+    /// as far as unsealed allows it.
+    #[cfg(feature = "socket")]
+    pub(crate) fn deserialize_backed(
+        request: &[u8],
+        fds: VecDeque<OwnedFd>,
+        limits: &ServerLimits,
+        unsealed: Unsealed,
+    ) -> Result<Self, TransferError> {
+        let request = parse_request(request, limits)?;
+
+        if request.query {
+            error!("request asks for a server defined vector");
+            return Err(RequestError::UnknownRecord(REQ_QUERY).into());
+        }
+
+        if request.resume.is_some() {
+            error!("request resumes a session");
+            return Err(RequestError::UnknownRecord(REQ_RESUME).into());
+        }
+
+        if request.shm_name.is_some() && !unsealed.named {
+            error!("request names its shared memory");
+            return Err(RequestError::UnknownRecord(REQ_SHM_NAME).into());
+        }
+
+        if request.shm_offset.is_some() {
+            error!("request places the vector in provided shared memory");
+            return Err(RequestError::UnknownRecord(REQ_SHM_OFFSET).into());
+        }
+
+        let layout = Layout {
+            cacheline_size: request.cacheline_size,
+            index_size: request.index_size,
+            descriptors: request.descriptors,
+        };
+
+        if let Some(name) = request.shm_name {
+            if !fds.is_empty() {
+                error!("request names its shared memory and passes fds");
+                return Err(TransferError::FileDescriptorCountMismatch {
+                    expected: 0,
+                    received: fds.len(),
+                });
+            }
+
+            let shmfd = shm_named_open(&name)?;
+
+            let mut rsc = Self::from_unsealed(&request.vconfig, layout, shmfd, None)?;
+            rsc.shm_name = Some(name);
+            return Ok(rsc);
+        }
+
+        if request.file_backed && !unsealed.file {
+            error!("request backs its shared memory with a file");
+            return Err(RequestError::UnknownRecord(REQ_SHM_BACKING).into());
+        }
+
+        Self::from_config(
+            &request.vconfig,
+            layout,
+            fds,
+            None,
+            request.file_backed,
+            request.dmabufs,
+        )
     }
 }
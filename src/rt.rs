@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use nix::{
+    errno::Errno,
+    libc,
+    sched::{CpuSet, sched_setaffinity},
+    sys::mman::{MlockAllFlags, mlockall},
+    unistd::Pid,
+};
+
+use crate::trace::*;
+
+/* from linux/sched/types.h, libc has no struct sched_attr */
+#[repr(C)]
+struct SchedAttr {
+    size: u32,
+    sched_policy: u32,
+    sched_flags: u64,
+    sched_nice: i32,
+    sched_priority: u32,
+    sched_runtime: u64,
+    sched_deadline: u64,
+    sched_period: u64,
+}
+
+/// stack touched by one frame of prefault_stack
+const STACK_CHUNK: usize = 4096;
+
+/// Pins the calling thread to cpu, e.g. a core isolated with isolcpus.
+pub fn pin_to_cpu(cpu: usize) -> Result<(), Errno> {
+    let mut set = CpuSet::new();
+    set.set(cpu)
+        .inspect_err(|_| error!("cpu {cpu} exceeds the cpu set"))?;
+
+    sched_setaffinity(Pid::from_raw(0), &set)
+        .inspect_err(|e| error!("pinning to cpu {cpu} failed {e:?}"))
+}
+
+/// Runs the calling thread with SCHED_FIFO at priority, 1 to 99 on Linux.
+/// Needs CAP_SYS_NICE or an RLIMIT_RTPRIO of at least priority.
+pub fn set_fifo_priority(priority: i32) -> Result<(), Errno> {
+    let min = unsafe { libc::sched_get_priority_min(libc::SCHED_FIFO) };
+    let max = unsafe { libc::sched_get_priority_max(libc::SCHED_FIFO) };
+
+    if !(min..=max).contains(&priority) {
+        error!("SCHED_FIFO priority {priority} not in {min}..={max}");
+        return Err(Errno::EINVAL);
+    }
+
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+
+    /* pid 0 is the calling thread, not the whole process */
+    let res = unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) };
+
+    Errno::result(res)
+        .map(drop)
+        .inspect_err(|e| error!("SCHED_FIFO {priority} failed {e:?}"))
+}
+
+/// Runs the calling thread with SCHED_DEADLINE, it gets runtime of every period and
+/// has to be done deadline after the period started. The kernel refuses the thread if
+/// runtime <= deadline <= period doesn't hold or the CPUs are already overcommitted.
+/// SCHED_DEADLINE threads can't be pinned to a single cpu of a multi-cpu root domain.
+pub fn set_deadline(runtime: Duration, deadline: Duration, period: Duration) -> Result<(), Errno> {
+    let nanos = |d: Duration| u64::try_from(d.as_nanos()).map_err(|_| Errno::EINVAL);
+
+    let attr = SchedAttr {
+        size: size_of::<SchedAttr>() as u32,
+        sched_policy: libc::SCHED_DEADLINE as u32,
+        sched_flags: 0,
+        sched_nice: 0,
+        sched_priority: 0,
+        sched_runtime: nanos(runtime)?,
+        sched_deadline: nanos(deadline)?,
+        sched_period: nanos(period)?,
+    };
+
+    let res = unsafe { libc::syscall(libc::SYS_sched_setattr, 0, &attr, 0) };
+
+    Errno::result(res)
+        .map(drop)
+        .inspect_err(|e| error!("SCHED_DEADLINE {runtime:?}/{deadline:?}/{period:?} failed {e:?}"))
+}
+
+/// Locks all current and future pages of the process, so neither the shared memory
+/// nor the stack or the heap are paged out. Needs CAP_IPC_LOCK or a large enough
+/// RLIMIT_MEMLOCK.
+pub fn lock_memory() -> Result<(), Errno> {
+    mlockall(MlockAllFlags::MCL_CURRENT | MlockAllFlags::MCL_FUTURE)
+        .inspect_err(|e| error!("mlockall failed {e:?}"))
+}
+
+/// Touches size bytes of stack below the caller, so the control loop doesn't take
+/// page faults on its first deep call chain. With lock_memory the pages stay.
+/// size has to fit into the stack of the thread.
+#[inline(never)]
+pub fn prefault_stack(size: usize) {
+    let chunk = [0u8; STACK_CHUNK];
+
+    if size > STACK_CHUNK {
+        prefault_stack(size - STACK_CHUNK);
+    }
+
+    /* the chunk lives across the call, it can't become a tail call reusing the frame */
+    std::hint::black_box(&chunk);
+}
@@ -0,0 +1,147 @@
+use std::mem::MaybeUninit;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU32, Ordering, fence};
+
+use crate::Layout;
+use crate::QueueConfig;
+use crate::error::*;
+use crate::mem_align;
+use crate::shm::{Chunk, Span};
+
+/// Read attempts of SeqLock::read while the writer is updating the record. A writer
+/// dying in the middle of an update leaves the sequence odd, readers must not spin forever.
+const READ_RETRIES: u32 = 0x10000;
+
+/// Sequence of the record after the write following seq,
+/// 0 is skipped on wrap around, it's reserved for the unwritten record.
+pub(crate) fn next_sequence(seq: u32) -> u32 {
+    match (seq | 1).wrapping_add(1) {
+        0 => 2,
+        next => next,
+    }
+}
+
+/// Single record protected by a sequence counter.
+/// An odd sequence means the writer is updating the record,
+/// a sequence of 0 means the record was never written.
+pub(crate) struct SeqLock {
+    _chunk: Chunk,
+    message_size: NonZeroUsize,
+    seq: *mut u32,
+    data: *mut (),
+}
+
+impl SeqLock {
+    pub(crate) fn new(
+        chunk: Chunk,
+        config: &QueueConfig,
+        layout: Layout,
+    ) -> Result<Self, ShmMapError> {
+        let message_size =
+            NonZeroUsize::new(mem_align(config.message_size.get(), layout.cacheline_size)).unwrap();
+
+        let seq: *mut u32 = chunk.get_ptr(0)?;
+        let data: *mut () = chunk.get_span_ptr(&Span {
+            offset: mem_align(size_of::<u32>(), layout.cacheline_size),
+            size: message_size,
+        })?;
+
+        Ok(Self {
+            _chunk: chunk,
+            message_size,
+            seq,
+            data,
+        })
+    }
+
+    pub(crate) fn shm_size(config: &QueueConfig, layout: Layout) -> NonZeroUsize {
+        let size = mem_align(size_of::<u32>(), layout.cacheline_size)
+            + mem_align(config.message_size.get(), layout.cacheline_size);
+        NonZeroUsize::new(size).unwrap()
+    }
+
+    pub(crate) fn init(&self) {
+        self.seq().store(0, Ordering::SeqCst);
+    }
+
+    pub(crate) fn message_size(&self) -> NonZeroUsize {
+        self.message_size
+    }
+
+    fn seq(&self) -> &AtomicU32 {
+        unsafe { AtomicU32::from_ptr(self.seq) }
+    }
+
+    pub(crate) fn sequence(&self) -> u32 {
+        self.seq().load(Ordering::Acquire)
+    }
+
+    /// Only one writer is allowed, the writer never waits.
+    pub(crate) fn write<T: Copy>(&self, val: &T) {
+        /* seq is always even outside of write */
+        let seq = self.seq().load(Ordering::Relaxed);
+
+        self.seq().store(seq | 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        unsafe { std::ptr::write_volatile(self.data.cast::<T>(), *val) };
+
+        self.seq().store(next_sequence(seq), Ordering::Release);
+    }
+
+    /// Single read attempt, returns None if the record was never written
+    /// or the writer is just updating it.
+    pub(crate) fn try_read<T: Copy>(&self) -> Option<T> {
+        self.try_read_sequence().map(|(val, _)| val)
+    }
+
+    /// Single read attempt like try_read, additionally returns the sequence of the record read.
+    fn try_read_sequence<T: Copy>(&self) -> Option<(T, u32)> {
+        let seq = self.seq().load(Ordering::Acquire);
+
+        if seq == 0 || seq & 1 == 1 {
+            return None;
+        }
+
+        let mut val = MaybeUninit::<T>::uninit();
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                self.data.cast::<u8>(),
+                val.as_mut_ptr().cast::<u8>(),
+                size_of::<T>(),
+            );
+        }
+
+        fence(Ordering::Acquire);
+
+        if self.seq().load(Ordering::Relaxed) != seq {
+            /* torn read */
+            return None;
+        }
+
+        Some((unsafe { val.assume_init() }, seq))
+    }
+
+    /// Retries try_read while the writer is updating the record, READ_RETRIES times at most.
+    /// Returns the record with its sequence, None if the record was never written
+    /// or the writer didn't finish its update.
+    pub(crate) fn read<T: Copy>(&self) -> Option<(T, u32)> {
+        for _ in 0..READ_RETRIES {
+            if let Some(read) = self.try_read_sequence() {
+                return Some(read);
+            }
+
+            if self.sequence() == 0 {
+                return None;
+            }
+
+            std::hint::spin_loop();
+        }
+
+        None
+    }
+}
+
+// every SeqLock has its own shared memory region
+unsafe impl Send for SeqLock {}
@@ -0,0 +1,351 @@
+use std::collections::BTreeMap;
+use std::os::fd::BorrowedFd;
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+
+use crate::ChannelVector;
+use crate::control::{Control, ControlMessage};
+use crate::error::*;
+use crate::socket::{PeerInfo, Server};
+use crate::trace::*;
+
+/// Identifies a client of a ServerLoop, ids aren't reused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClientId(u64);
+
+/// Keepalive of the control connections: a client is pinged after being silent for
+/// interval and removed after being silent for timeout. Clients have to answer pings,
+/// e.g. with Control::set_answer_pings, any other message counts as well.
+/// A stalled handshake delays pings and removals by up to the handshake timeout.
+#[derive(Copy, Clone, Debug)]
+pub struct Keepalive {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+/// Called for a client that exceeded the idle timeout, right before it's removed.
+pub type IdleHandler = Box<dyn FnMut(ClientId, &mut Client) + Send>;
+
+/// Connected client, the control connection is kept by the loop for detecting
+/// disconnects, legacy clients have none.
+pub struct Client {
+    vector: ChannelVector,
+    control: Option<Control>,
+    peer: PeerInfo,
+    /// last message received from the client
+    last_seen: Instant,
+    /// token and time of the last keepalive ping
+    ping: Option<(u64, Instant)>,
+}
+
+impl Client {
+    pub fn vector(&mut self) -> &mut ChannelVector {
+        &mut self.vector
+    }
+
+    pub fn peer(&self) -> &PeerInfo {
+        &self.peer
+    }
+
+    pub fn control(&self) -> Option<&Control> {
+        self.control.as_ref()
+    }
+
+    /// Time since the client sent its last control message.
+    pub fn idle(&self) -> Duration {
+        self.last_seen.elapsed()
+    }
+
+    /// Time the next ping is due, or the client is idle for too long.
+    fn keepalive_due(&self, keepalive: &Keepalive) -> Instant {
+        let last_ping = self.ping.map_or(self.last_seen, |(_, sent)| sent);
+
+        (self.last_seen.max(last_ping) + keepalive.interval).min(self.last_seen + keepalive.timeout)
+    }
+}
+
+pub enum ServerEvent {
+    Connected(ClientId),
+    /// control message of a client, Goodbye is reported as Disconnected
+    Message(ClientId, ControlMessage),
+    /// the client was removed, its vector is dropped already
+    Disconnected(ClientId),
+    /// the handshake with a new client failed, the loop keeps running
+    AcceptFailed(TransferError),
+}
+
+/// Upper bound for the handshake of a new client, unless the server has its own,
+/// see Server::set_handshake_timeout.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Serves any number of clients on a single thread: accepts new clients,
+/// receives their control messages and reaps disconnected ones.
+/// The handshake of a new client runs on the calling thread, a client stalling
+/// its handshake holds up the others until the handshake timeout of the server.
+pub struct ServerLoop {
+    server: Server,
+    clients: BTreeMap<ClientId, Client>,
+    next_id: u64,
+    keepalive: Option<Keepalive>,
+    idle_handler: Option<IdleHandler>,
+    next_token: u64,
+}
+
+impl ServerLoop {
+    pub fn new(mut server: Server) -> Self {
+        if server.handshake_timeout().is_none() {
+            server.set_handshake_timeout(Some(HANDSHAKE_TIMEOUT));
+        }
+
+        Self {
+            server,
+            clients: BTreeMap::new(),
+            next_id: 0,
+            keepalive: None,
+            idle_handler: None,
+            next_token: 0,
+        }
+    }
+
+    /// Enables the keepalive of the control connections, None disables it.
+    pub fn set_keepalive(&mut self, keepalive: Option<Keepalive>) {
+        self.keepalive = keepalive;
+    }
+
+    /// handler is called for every client removed by the keepalive.
+    pub fn set_idle_handler(&mut self, handler: IdleHandler) {
+        self.idle_handler = Some(handler);
+    }
+
+    pub fn server(&self) -> &Server {
+        &self.server
+    }
+
+    pub fn client(&mut self, id: ClientId) -> Option<&mut Client> {
+        self.clients.get_mut(&id)
+    }
+
+    pub fn clients(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.clients.keys().copied()
+    }
+
+    /// Removes the client from the loop, dropping it says goodbye to the client.
+    pub fn disconnect(&mut self, id: ClientId) -> Option<Client> {
+        self.clients.remove(&id)
+    }
+
+    /// Shuts the server down like Server::shutdown, the clients are dropped afterwards.
+    pub fn shutdown(self, timeout: Duration) -> Result<usize, Errno> {
+        let Self {
+            server, clients, ..
+        } = self;
+
+        let pending = server.shutdown(timeout);
+
+        drop(clients);
+
+        pending
+    }
+
+    fn accept(&mut self) -> ServerEvent {
+        let (mut vector, peer) = match self.server.accept() {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!("accept failed {e:?}");
+                return ServerEvent::AcceptFailed(e);
+            }
+        };
+
+        let id = ClientId(self.next_id);
+        self.next_id += 1;
+
+        let control = vector.take_control();
+
+        self.clients.insert(
+            id,
+            Client {
+                vector,
+                control,
+                peer,
+                last_seen: Instant::now(),
+                ping: None,
+            },
+        );
+
+        ServerEvent::Connected(id)
+    }
+
+    /// Returns None for the answer to a keepalive ping.
+    fn receive(&mut self, id: ClientId) -> Option<ServerEvent> {
+        let client = self.clients.get_mut(&id)?;
+
+        let received = match client.control.as_ref() {
+            Some(control) => control.receive(),
+            None => return Some(ServerEvent::Disconnected(id)),
+        };
+
+        client.last_seen = Instant::now();
+
+        match received {
+            Ok(ControlMessage::Goodbye) => {
+                info!("client {id:?} said goodbye");
+            }
+            Ok(ControlMessage::Pong(token)) if client.ping.is_some_and(|(t, _)| t == token) => {
+                client.ping = None;
+                return None;
+            }
+            Ok(msg) => return Some(ServerEvent::Message(id, msg)),
+            Err(e) => {
+                info!("client {id:?} disconnected {e:?}");
+            }
+        }
+
+        self.clients.remove(&id);
+
+        Some(ServerEvent::Disconnected(id))
+    }
+
+    /// Time the next keepalive action is due.
+    fn keepalive_due(&self) -> Option<Instant> {
+        let keepalive = self.keepalive.as_ref()?;
+
+        self.clients
+            .values()
+            .filter(|c| c.control.is_some())
+            .map(|c| c.keepalive_due(keepalive))
+            .min()
+    }
+
+    /// Pings the silent clients and removes the idle ones.
+    fn keepalive(&mut self) -> Vec<ServerEvent> {
+        let Some(keepalive) = self.keepalive else {
+            return Vec::with_capacity(0);
+        };
+
+        let now = Instant::now();
+        let mut removed = Vec::new();
+
+        for (id, client) in self.clients.iter_mut() {
+            let Some(control) = client.control.as_ref() else {
+                continue;
+            };
+
+            if now.duration_since(client.last_seen) >= keepalive.timeout {
+                info!("client {id:?} idle for {:?}", client.idle());
+                removed.push(*id);
+            } else if now >= client.keepalive_due(&keepalive) {
+                let token = self.next_token;
+                self.next_token += 1;
+
+                match control.ping(token) {
+                    Ok(()) => client.ping = Some((token, now)),
+                    Err(e) => {
+                        info!("pinging client {id:?} failed {e:?}");
+                        removed.push(*id);
+                    }
+                }
+            }
+        }
+
+        removed
+            .into_iter()
+            .map(|id| {
+                let client = self.clients.remove(&id);
+
+                if let (Some(mut client), Some(handler)) = (client, self.idle_handler.as_mut()) {
+                    handler(id, &mut client);
+                }
+                ServerEvent::Disconnected(id)
+            })
+            .collect()
+    }
+
+    /// Waits until a client connects or sends a control message,
+    /// returns no events if timeout expired, None waits forever.
+    /// With a keepalive, it returns early without events when a keepalive action was due.
+    pub fn poll(&mut self, timeout: Option<Duration>) -> Result<Vec<ServerEvent>, Errno> {
+        let keepalive = self
+            .keepalive_due()
+            .map(|due| due.saturating_duration_since(Instant::now()));
+
+        let timeout = match (timeout, keepalive) {
+            (Some(timeout), Some(keepalive)) => Some(timeout.min(keepalive)),
+            (timeout, keepalive) => timeout.or(keepalive),
+        };
+
+        let timeout = match timeout {
+            /* rounded up, waking up before the keepalive is due would spin */
+            Some(timeout) => PollTimeout::try_from(Duration::from_millis(
+                timeout.as_micros().div_ceil(1000) as u64,
+            ))
+            .unwrap_or(PollTimeout::MAX),
+            None => PollTimeout::NONE,
+        };
+
+        let (listener_ready, ready) = {
+            let ids: Vec<ClientId> = self
+                .clients
+                .iter()
+                .filter(|(_, c)| c.control.is_some())
+                .map(|(id, _)| *id)
+                .collect();
+
+            let fds: Vec<BorrowedFd<'_>> = std::iter::once(self.server.fd())
+                .chain(
+                    ids.iter()
+                        .filter_map(|id| self.clients[id].control.as_ref().map(|c| c.fd())),
+                )
+                .collect();
+
+            let mut pollfds: Vec<PollFd> = fds
+                .iter()
+                .map(|fd| PollFd::new(*fd, PollFlags::POLLIN))
+                .collect();
+
+            match poll(&mut pollfds, timeout) {
+                Ok(_) | Err(Errno::EINTR) => {}
+                Err(e) => return Err(e),
+            }
+
+            let is_ready = |fd: &PollFd| fd.any().unwrap_or(false);
+
+            let ready: Vec<ClientId> = ids
+                .iter()
+                .zip(pollfds.iter().skip(1))
+                .filter(|(_, fd)| is_ready(fd))
+                .map(|(id, _)| *id)
+                .collect();
+
+            (is_ready(&pollfds[0]), ready)
+        };
+
+        let mut events: Vec<ServerEvent> = ready
+            .into_iter()
+            .filter_map(|id| self.receive(id))
+            .collect();
+
+        events.extend(self.keepalive());
+
+        if listener_ready {
+            events.push(self.accept());
+        }
+
+        Ok(events)
+    }
+
+    /// Handles events until handler returns false.
+    pub fn run<F>(&mut self, mut handler: F) -> Result<(), Errno>
+    where
+        F: FnMut(&mut Self, ServerEvent) -> bool,
+    {
+        loop {
+            for event in self.poll(None)? {
+                if !handler(self, event) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
@@ -4,22 +4,36 @@ use std::{
     fmt,
     mem::size_of,
     num::NonZeroUsize,
-    os::fd::OwnedFd,
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+    path::PathBuf,
     ptr::NonNull,
-    sync::{Arc, Weak},
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
 };
 
 use nix::{
     errno::Errno,
-    libc::c_void,
-    sys::{
-        mman::{MapFlags, ProtFlags, mlock, mmap, munmap},
-        stat::fstat,
-    },
+    libc::{self, c_void},
+    sys::mman::{MapFlags, ProtFlags, mlock},
 };
 
+#[cfg(not(feature = "rustix"))]
+use nix::sys::mman::{mmap, mmap_anonymous, munmap};
+
+#[cfg(feature = "rustix")]
+use crate::sys_rustix::{mmap, mmap_anonymous, munmap};
+
+#[cfg(not(target_os = "macos"))]
+use nix::sys::mman::{MmapAdvise, madvise};
+
 use crate::error::*;
-use crate::log::*;
+use crate::mem_align;
+use crate::pool::{PoolRegion, page_size};
+use crate::quota::QuotaCharge;
+use crate::trace::*;
+use crate::unix::fd_size;
 
 #[derive(Debug, Copy, Clone)]
 pub(crate) struct Span {
@@ -27,6 +41,7 @@ pub(crate) struct Span {
     pub size: NonZeroUsize,
 }
 
+#[derive(Debug)]
 pub(crate) struct Chunk {
     shm: Arc<SharedMemory>,
     offset: usize,
@@ -34,6 +49,11 @@ pub(crate) struct Chunk {
 }
 
 impl Chunk {
+    /// Whether both chunks are placed in the same shared memory.
+    pub(crate) fn same_memory(&self, other: &Chunk) -> bool {
+        Arc::ptr_eq(&self.shm, &other.shm)
+    }
+
     pub(crate) fn get_ptr<T>(&self, offset: usize) -> Result<*mut T, ShmMapError> {
         let size = NonZeroUsize::new(size_of::<T>()).unwrap();
         let ptr = self.get_span_ptr(&Span { offset, size })?;
@@ -50,13 +70,187 @@ impl Chunk {
 
         Ok(ptr)
     }
+
+    /// Makes our writes to [ptr, ptr + len) visible to a non-coherent peer.
+    pub(crate) fn clean(&self, ptr: *const (), len: usize) {
+        if !self.shm.coherent {
+            self.shm.memory.clean(ptr.cast(), len);
+        }
+    }
+
+    /// Makes the writes of a non-coherent peer to [ptr, ptr + len) visible to us.
+    pub(crate) fn invalidate(&self, ptr: *const (), len: usize) {
+        if !self.shm.coherent {
+            self.shm.memory.invalidate(ptr.cast(), len);
+        }
+    }
+
+    /// Splits the chunk into [0, at) and [at, size).
+    pub(crate) fn split(self, at: NonZeroUsize) -> Result<(Chunk, Chunk), ShmMapError> {
+        let size = self.size.get().checked_sub(at.get());
+        let size = size
+            .and_then(NonZeroUsize::new)
+            .ok_or(ShmMapError::OutOfBounds)?;
+
+        let second = Chunk {
+            shm: self.shm.clone(),
+            offset: self.offset + at.get(),
+            size,
+        };
+
+        let first = Chunk {
+            shm: self.shm,
+            offset: self.offset,
+            size: at,
+        };
+
+        Ok((first, second))
+    }
 }
 
-#[derive(Debug)]
+/// How a vector maps its shared memory.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct MapOptions {
+    pub prefault: bool,
+    pub lock: bool,
+    pub core_dump: Option<bool>,
+    /// regions of at least this size are mapped for transparent huge pages
+    pub hugepage_threshold: Option<usize>,
+}
+
+/// Size of a transparent huge page with 4 KiB base pages on x86-64 and arm64
+const HUGEPAGE_SIZE: usize = 2 << 20;
+
+/// Reserves an address range for size bytes starting at a huge page boundary, so the
+/// chunks line up with the huge pages of the mapping placed there with MAP_FIXED.
+fn reserve_aligned(size: NonZeroUsize) -> Result<NonNull<c_void>, Errno> {
+    let size = mem_align(size.get(), page_size());
+    let len = size.checked_add(HUGEPAGE_SIZE).ok_or(Errno::ENOMEM)?;
+
+    let ptr = unsafe {
+        mmap_anonymous(
+            None,
+            NonZeroUsize::new(len).ok_or(Errno::EINVAL)?,
+            ProtFlags::PROT_NONE,
+            MapFlags::MAP_PRIVATE | MapFlags::MAP_NORESERVE,
+        )
+    }?;
+
+    let start = ptr.as_ptr() as usize;
+    let head = mem_align(start, HUGEPAGE_SIZE) - start;
+    let tail = len - head - size;
+
+    /* give back the unaligned ends, the range in between is replaced by the mapping */
+    let aligned = unsafe { ptr.byte_add(head) };
+
+    if head > 0 {
+        unsafe { munmap(ptr, head) }?;
+    }
+
+    if tail > 0 {
+        unsafe { munmap(aligned.byte_add(size), tail) }?;
+    }
+
+    Ok(aligned)
+}
+
+/// What the shared memory of a vector allocated by us is backed by.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ShmBacking {
+    /// sealed memfd, gone once both peers unmapped it
+    #[default]
+    Memfd,
+    /// regular file at the path, an existing file is truncated. The messages
+    /// survive both processes, e.g. for offline analysis of the rings.
+    File(PathBuf),
+    /// unnamed file (O_TMPFILE) in the directory of the path, linked to the path once
+    /// the server accepted the vector, so the path never names the file of a failed
+    /// handshake. Linking fails if the path exists, it needs Linux 6.10 or later,
+    /// CAP_DAC_READ_SEARCH or /proc.
+    TmpFile(PathBuf),
+    /// POSIX shared memory object (shm_open) in /dev/shm, passed by its name instead of
+    /// an fd, e.g. for transports without fd passing or peers in separate containers
+    /// sharing /dev/shm. The vector can't have eventfds, the name is removed once the
+    /// handshake is over.
+    Named,
+}
+
+impl ShmBacking {
+    pub(crate) fn is_file(&self) -> bool {
+        matches!(self, ShmBacking::File(_) | ShmBacking::TmpFile(_))
+    }
+}
+
+/// Memory the channels of a vector are placed in, e.g. a UIO or ivshmem BAR mapping
+/// or a preallocated static arena, see ChannelVector::with_region and
+/// VectorResource::set_region. The memory has to stay mapped until the region is dropped.
+pub trait MemoryRegion: Send + Sync {
+    /// start of the region, aligned to the cache line size of the layout
+    fn map(&self) -> NonNull<u8>;
+
+    fn size(&self) -> NonZeroUsize;
+
+    /// fd of the memory, None for memory that can't be passed to a peer
+    fn as_fd(&self) -> Option<BorrowedFd<'_>>;
+
+    /// false if the other side accesses the memory without snooping our caches, e.g. a
+    /// coprocessor. Queues then clean each message before it's pushed and invalidate it
+    /// once it's popped. The indices are atomics and have to be coherent anyway.
+    fn coherent(&self) -> bool {
+        true
+    }
+
+    /// Writes the cache lines of [ptr, ptr + len) back to the memory.
+    fn clean(&self, _ptr: *const u8, _len: usize) {}
+
+    /// Discards the cache lines of [ptr, ptr + len), so the next read fetches the memory.
+    fn invalidate(&self, _ptr: *const u8, _len: usize) {}
+}
+
+/// Own mapping of an fd, unmapped on drop.
+struct Mapping {
+    ptr: NonNull<c_void>,
+    size: NonZeroUsize,
+}
+
+impl MemoryRegion for Mapping {
+    fn map(&self) -> NonNull<u8> {
+        self.ptr.cast()
+    }
+
+    fn size(&self) -> NonZeroUsize {
+        self.size
+    }
+
+    /* the fd is closed once it's mapped */
+    fn as_fd(&self) -> Option<BorrowedFd<'_>> {
+        None
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        debug!("unmap {:?}", self.ptr);
+        if let Err(_e) = unsafe { munmap(self.ptr, self.size.get()) } {
+            error!("munmap failed with : {_e}");
+        }
+    }
+}
+
+// the mapping is only accessed through the chunks of its SharedMemory
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
 pub struct SharedMemory {
     me: Weak<Self>,
     ptr: *mut (),
     size: NonZeroUsize,
+    /// own mapping, region of a pool or memory supplied by the user
+    memory: Box<dyn MemoryRegion>,
+    /// cached memory.coherent(), checked on the data path
+    coherent: bool,
+    locked: AtomicBool,
+    _charge: Option<QuotaCharge>,
 }
 
 impl SharedMemory {
@@ -72,42 +266,201 @@ impl SharedMemory {
         })
     }
 
-    pub fn new(fd: OwnedFd) -> Result<Arc<Self>, Errno> {
-        let stat = fstat(&fd)?;
+    /// charge is given back to the quota of the client once the memory is unmapped
+    pub(crate) fn new(
+        fd: OwnedFd,
+        charge: Option<QuotaCharge>,
+        options: MapOptions,
+    ) -> Result<Arc<Self>, Errno> {
+        #[cfg(not(target_os = "macos"))]
+        let empty = Errno::EBADFD;
+        #[cfg(target_os = "macos")]
+        let empty = Errno::EBADF;
+
+        let size = NonZeroUsize::new(fd_size(fd.as_fd())?).ok_or(empty)?;
+
+        Self::map(&fd, 0, size, charge, options)
+    }
+
+    /// Maps size bytes of fd starting at the page aligned offset. With prefault all pages
+    /// are faulted in by the mapping, not by the first access on the data path.
+    /// Regions exceeding the hugepage threshold are aligned to huge pages and advised
+    /// for them, for a memfd shmem_enabled of transparent_hugepage has to allow it.
+    pub(crate) fn map(
+        fd: &OwnedFd,
+        offset: usize,
+        size: NonZeroUsize,
+        charge: Option<QuotaCharge>,
+        options: MapOptions,
+    ) -> Result<Arc<Self>, Errno> {
+        let offset = libc::off_t::try_from(offset).map_err(|_| Errno::EINVAL)?;
 
-        let size = NonZeroUsize::new(stat.st_size as usize).ok_or(Errno::EBADFD)?;
+        let hugepage = options
+            .hugepage_threshold
+            .is_some_and(|threshold| size.get() >= threshold);
+
+        let mut flags = MapFlags::MAP_SHARED;
+
+        #[cfg(not(target_os = "macos"))]
+        if options.prefault {
+            flags |= MapFlags::MAP_POPULATE;
+        }
+
+        let addr = if hugepage {
+            flags |= MapFlags::MAP_FIXED;
+            let addr = reserve_aligned(size)?;
+            NonZeroUsize::new(addr.as_ptr() as usize)
+        } else {
+            None
+        };
 
         let ptr = unsafe {
             mmap(
-                None,                                         // Desired addr
+                addr,                                         // Desired addr
                 size,                                         // size of mapping
                 ProtFlags::PROT_READ | ProtFlags::PROT_WRITE, // Permissions on pages
-                MapFlags::MAP_SHARED,                         // What kind of mapping
-                &fd,                                          // fd
-                0,                                            // Offset into fd
+                flags,                                        // What kind of mapping
+                fd,                                           // fd
+                offset,                                       // Offset into fd
             )
-        }?;
+        }
+        .inspect_err(|_| {
+            if let Some(addr) = addr {
+                /* the reservation isn't replaced by a failed mapping */
+                let addr = NonNull::new(addr.get() as *mut c_void).unwrap();
+                let _ = unsafe { munmap(addr, size.get()) };
+            }
+        })?;
 
-        unsafe {
-            mlock(ptr, size.get())?;
+        /* macOS has no MAP_POPULATE, the pages are faulted in by reading them */
+        #[cfg(target_os = "macos")]
+        if options.prefault {
+            for offset in (0..size.get()).step_by(page_size()) {
+                unsafe { std::ptr::read_volatile(ptr.cast::<u8>().as_ptr().add(offset)) };
+            }
         }
 
-        Ok(Arc::new_cyclic(|me| Self {
+        let mapping = Mapping { ptr, size };
+
+        #[cfg(not(target_os = "macos"))]
+        if hugepage {
+            /* the regular pages still work without transparent huge pages */
+            if let Err(e) = unsafe { madvise(ptr, size.get(), MmapAdvise::MADV_HUGEPAGE) } {
+                info!("MADV_HUGEPAGE failed {e:?}");
+            }
+        }
+
+        Ok(Self::with_memory(Box::new(mapping), charge))
+    }
+
+    /// Places the channels in memory, e.g. a region of a pool or supplied by the user.
+    pub(crate) fn with_memory(
+        memory: Box<dyn MemoryRegion>,
+        charge: Option<QuotaCharge>,
+    ) -> Arc<Self> {
+        Arc::new_cyclic(|me| Self {
             me: me.clone(),
-            ptr: ptr.as_ptr().cast(),
-            size,
-        }))
+            ptr: memory.map().as_ptr().cast(),
+            size: memory.size(),
+            coherent: memory.coherent(),
+            locked: AtomicBool::new(false),
+            memory,
+            _charge: charge,
+        })
     }
-}
 
-impl Drop for SharedMemory {
-    fn drop(&mut self) {
-        let ptr: NonNull<c_void> = NonNull::new(self.ptr as *mut c_void).unwrap();
-        debug!("unmap {ptr:?}");
-        if let Err(_e) = unsafe { munmap(ptr, self.size.get()) } {
-            error!("munmap failed with : {_e}");
+    /// Locks the mapping into memory, so the data path never suffers from major faults.
+    /// Fails with LockLimit if RLIMIT_MEMLOCK doesn't allow locking the mapping,
+    /// memory locked by other mappings counts against the limit as well.
+    pub fn lock(&self) -> Result<(), ResourceError> {
+        let ptr = NonNull::new(self.ptr.cast::<c_void>()).ok_or(ResourceError::InvalidArgument)?;
+
+        match unsafe { mlock(ptr, self.size.get()) } {
+            Ok(()) => {
+                self.locked.store(true, Ordering::Relaxed);
+                Ok(())
+            }
+            /* EPERM for a limit of 0 without CAP_IPC_LOCK */
+            Err(e @ (Errno::ENOMEM | Errno::EPERM)) => {
+                let mut limit = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+
+                if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } != 0 {
+                    return Err(e.into());
+                }
+
+                /* rlim_t is 32 bit wide on 32 bit targets */
+                #[allow(clippy::useless_conversion)]
+                let limit = u64::from(limit.rlim_cur);
+
+                error!(
+                    "locking {} bytes failed, RLIMIT_MEMLOCK is {limit}",
+                    self.size
+                );
+
+                Err(ShmMapError::LockLimit {
+                    size: self.size.get(),
+                    limit,
+                }
+                .into())
+            }
+            Err(e) => Err(e.into()),
         }
     }
+
+    /// Includes the mapping in core dumps or excludes it, only mappings excluded before
+    /// can be included, the rest is subject to coredump_filter.
+    #[cfg(not(target_os = "macos"))]
+    pub fn set_core_dump(&self, include: bool) -> Result<(), Errno> {
+        let ptr = NonNull::new(self.ptr.cast::<c_void>()).ok_or(Errno::EINVAL)?;
+
+        let advice = if include {
+            MmapAdvise::MADV_DODUMP
+        } else {
+            MmapAdvise::MADV_DONTDUMP
+        };
+
+        unsafe { madvise(ptr, self.size.get(), advice) }
+    }
+
+    /// macOS can't exclude mappings from core dumps.
+    #[cfg(target_os = "macos")]
+    pub fn set_core_dump(&self, _include: bool) -> Result<(), Errno> {
+        error!("excluding memory from core dumps isn't supported on macOS");
+        Err(Errno::EOPNOTSUPP)
+    }
+
+    pub(crate) fn size(&self) -> NonZeroUsize {
+        self.size
+    }
+
+    pub(crate) fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn is_coherent(&self) -> bool {
+        self.coherent
+    }
+
+    /// Uses the region of a pool, the pool stays mapped as long as the region is used.
+    pub(crate) fn carve(region: PoolRegion, charge: Option<QuotaCharge>) -> Arc<Self> {
+        Self::with_memory(Box::new(region), charge)
+    }
+}
+
+// the memory is only accessed through the chunks, each channel synchronizes its own
+unsafe impl Send for SharedMemory {}
+unsafe impl Sync for SharedMemory {}
+
+impl fmt::Debug for SharedMemory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedMemory")
+            .field("ptr", &self.ptr)
+            .field("size", &self.size)
+            .finish()
+    }
 }
 
 impl fmt::Display for SharedMemory {
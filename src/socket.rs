@@ -1,134 +1,1918 @@
 use nix::NixPath;
 use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
 use nix::sys::socket::{
-    AddressFamily, Backlog, SockFlag, SockType, UnixAddr, accept, bind, connect, listen, socket,
+    Backlog, UnixAddr, bind, connect, getsockname, getsockopt, listen, sockopt,
 };
-use nix::unistd::unlink;
-use std::os::fd::{OwnedFd, RawFd};
+use nix::unistd::{dup, unlink};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd, RawFd};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt, chown};
 use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::VectorConfig;
+use crate::auth::Authenticator;
 use crate::channel::ChannelVector;
+use crate::control::{Admission, Control, ControlMessage, send_control};
 use crate::error::*;
-use crate::protocol::{create_response, parse_response};
-use crate::resource::VectorResource;
-use crate::unix::{UnixMessageRx, UnixMessageTx};
+use crate::header::{
+    FIXED_LAYOUT_VERSION, RTIC_VERSION, has_descriptors, is_supported_version, verify_header,
+};
+use crate::hub::BroadcastHub;
+use crate::pool::ShmPool;
+use crate::protocol::{
+    Response, create_legacy_response, create_query, create_request_fixed, create_response,
+    create_resume, is_legacy_request, is_resume_request, parse_fd_count, parse_request,
+    parse_response, request_version,
+};
+use crate::quota::{ClientQuota, QuotaCharge, QuotaLedger};
+use crate::resource::{Unsealed, VectorResource};
+use crate::shm::{MapOptions, ShmBacking};
+use crate::trace::{error, info};
+use crate::transport::{Transport, UnixTransport};
+use crate::unix::{ShmName, eventfd_create, io_errno, random_u64};
+use crate::unix_message::{
+    MESSAGE_SOCKET, accept_socket, is_readable, message_socket, message_socketpair,
+};
+use crate::{ChannelKind, EventFd, Layout, ServerLimits, VectorConfig, is_supported_index_size};
+
+/// Socket address in the abstract namespace, no socket file is created,
+/// so there's no stale file to clean up.
+pub struct AbstractAddr<'a>(pub &'a [u8]);
+
+/// Address of the server socket: a path or an AbstractAddr.
+pub trait ToUnixAddr {
+    fn to_unix_addr(&self) -> Result<UnixAddr, Errno>;
+}
+
+impl<P: ?Sized + NixPath> ToUnixAddr for P {
+    fn to_unix_addr(&self) -> Result<UnixAddr, Errno> {
+        UnixAddr::new(self)
+    }
+}
+
+impl ToUnixAddr for AbstractAddr<'_> {
+    #[cfg(not(target_os = "macos"))]
+    fn to_unix_addr(&self) -> Result<UnixAddr, Errno> {
+        UnixAddr::new_abstract(self.0)
+    }
+
+    /// macOS has no abstract namespace.
+    #[cfg(target_os = "macos")]
+    fn to_unix_addr(&self) -> Result<UnixAddr, Errno> {
+        error!("abstract socket addresses aren't supported on macOS");
+        Err(Errno::EOPNOTSUPP)
+    }
+}
+
+/// Access control of the socket file created by Server::with_options.
+#[derive(Clone, Default)]
+pub struct SocketOptions {
+    /// permission bits of the socket file, e.g. 0o660
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// creates missing parent directories with the default permissions
+    pub create_dirs: bool,
+}
+
+impl SocketOptions {
+    fn has_access(&self) -> bool {
+        self.mode.is_some() || self.uid.is_some() || self.gid.is_some()
+    }
+
+    fn apply(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(mode) = self.mode {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
+
+        if self.uid.is_some() || self.gid.is_some() {
+            chown(path, self.uid, self.gid)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Removes the socket file left behind by a crashed server. Fails with EADDRINUSE
+/// if a server still answers or the path isn't a socket.
+fn remove_stale(addr: &UnixAddr) -> Result<(), Errno> {
+    let Some(path) = addr.path() else {
+        /* abstract names vanish with their socket */
+        return Err(Errno::EADDRINUSE);
+    };
+
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {}
+        Ok(_) => return Err(Errno::EADDRINUSE),
+        Err(e) => return Err(io_errno(e)),
+    }
+
+    let probe = message_socket(false)?;
+
+    match connect(probe.as_raw_fd(), addr) {
+        Err(Errno::ECONNREFUSED) => {
+            info!("removing stale socket {path:?}");
+            match unlink(path) {
+                Err(Errno::ENOENT) => Ok(()),
+                result => result,
+            }
+        }
+        Ok(()) => {
+            error!("{path:?} is used by another server");
+            Err(Errno::EADDRINUSE)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Options of the client side of the handshake.
+#[derive(Clone, Default)]
+pub struct ConnectOptions {
+    /// per-deployment application id, must match the cookie of the server
+    pub cookie: u32,
+    /// shared key for signing the handshake messages, must match the key of the server
+    #[cfg(feature = "hmac")]
+    pub key: Option<Vec<u8>>,
+    /// upper bound for the whole handshake, None waits forever for the server
+    pub timeout: Option<Duration>,
+    /// faults in all pages of the shared memory when mapping it,
+    /// so the first messages don't suffer from page faults
+    pub prefault: bool,
+    /// locks the shared memory into memory, see SharedMemory::lock
+    pub lock: bool,
+    /// includes the shared memory in core dumps or excludes it,
+    /// None keeps the default of the kernel (coredump_filter)
+    pub core_dump: Option<bool>,
+    /// backing of the shared memory allocated by us, a file backed vector is only
+    /// accepted by servers allowing it, see Server::set_file_backing
+    pub backing: ShmBacking,
+    /// dma-bufs passed to the server along with the shared memory, the messages refer
+    /// to them by their index, see ChannelVector::dmabuf. The server has to allow
+    /// them with ServerLimits::max_dmabufs.
+    pub dmabufs: Vec<Arc<OwnedFd>>,
+    /// shared memory of at least this size is aligned to transparent huge pages
+    /// and advised for them (MADV_HUGEPAGE), None maps it with regular pages
+    pub hugepage_threshold: Option<usize>,
+    /// reserves the pages of the shared memory with fallocate when it's created, so a
+    /// producer can't hit SIGBUS under memory pressure, see VectorResource::preallocate
+    pub preallocate: bool,
+    /// speaks the protocol of FIXED_LAYOUT_VERSION for servers of the C librtipc: a request
+    /// in native byte order with a 4 byte response, channels without descriptors and no
+    /// control connection. Only queues are supported, without cookie, key or dma-bufs.
+    pub legacy: bool,
+    /// protocol version spoken with the server, MIN_VERSION up to RTIC_VERSION,
+    /// e.g. to connect to servers not upgraded yet. None speaks RTIC_VERSION.
+    pub version: Option<u16>,
+}
+
+impl ConnectOptions {
+    pub(crate) fn authenticator(&self) -> Authenticator {
+        let mut auth = Authenticator::default();
+
+        auth.set_cookie(self.cookie);
+
+        #[cfg(feature = "hmac")]
+        auth.set_key(self.key.clone());
+
+        auth
+    }
+
+    pub(crate) fn map_options(&self) -> MapOptions {
+        MapOptions {
+            prefault: self.prefault,
+            lock: self.lock,
+            core_dump: self.core_dump,
+            hugepage_threshold: self.hugepage_threshold,
+        }
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    pub(crate) fn version(&self) -> Result<u16, TransferError> {
+        let version = self.version.unwrap_or(RTIC_VERSION);
+
+        if !is_supported_version(version) {
+            error!("protocol version {version} not supported");
+            return Err(ResourceError::InvalidArgument.into());
+        }
+
+        Ok(version)
+    }
+
+    /// Layout of a vector allocated by us, the server has to understand it.
+    pub(crate) fn layout(&self) -> Result<Layout, TransferError> {
+        Ok(Layout {
+            descriptors: !self.legacy && has_descriptors(self.version()?),
+            ..Layout::native()
+        })
+    }
+}
+
+/// Identity of the client process, recorded by the kernel when the client connected.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl PeerCredentials {
+    #[cfg(not(target_os = "macos"))]
+    fn of(socket: &OwnedFd) -> Result<Self, Errno> {
+        let cred = getsockopt(socket, sockopt::PeerCredentials)?;
+
+        Ok(Self {
+            pid: cred.pid(),
+            uid: cred.uid(),
+            gid: cred.gid(),
+        })
+    }
+
+    /// The first group of the peer is its effective group.
+    #[cfg(target_os = "macos")]
+    fn of(socket: &OwnedFd) -> Result<Self, Errno> {
+        let cred = getsockopt(socket, sockopt::LocalPeerCred)?;
+        let pid = getsockopt(socket, sockopt::LocalPeerPid)?;
+
+        Ok(Self {
+            pid,
+            uid: cred.uid(),
+            gid: cred.groups().first().copied().unwrap_or(u32::MAX),
+        })
+    }
+}
+
+/// Identity of an accepted client.
+#[derive(Clone, Debug)]
+pub struct PeerInfo {
+    pub credentials: PeerCredentials,
+    /// info of the vector requested by the client
+    pub info: Vec<u8>,
+}
+
+/// Shared memory and eventfds of an accepted vector, kept for a reconnecting client.
+struct Session {
+    /// vector from the server's point of view
+    vconfig: VectorConfig,
+    layout: Layout,
+    fds: Vec<OwnedFd>,
+    /// the client allocated the shared memory
+    client_owner: bool,
+    file_backed: bool,
+    /// info of the client's request, checked by the filter again on resume
+    info: Vec<u8>,
+    /// only a client with the credentials of the first one may resume the session
+    uid: u32,
+    gid: u32,
+    holder: Holder,
+}
+
+/// Client holding the vector of a session, only a session without one can be resumed.
+enum Holder {
+    None,
+    /// a handshake of the session is running, or its client has no control connection
+    Handshake,
+    /// the client of the control connection, until it hangs up
+    Control(Weak<OwnedFd>),
+}
+
+impl Holder {
+    fn is_attached(&self) -> bool {
+        match self {
+            Holder::None => false,
+            Holder::Handshake => true,
+            Holder::Control(socket) => socket.upgrade().is_some_and(|socket| {
+                let mut fds = [PollFd::new(socket.as_fd(), PollFlags::empty())];
+                /* a failing poll keeps the session with its client */
+                poll(&mut fds, PollTimeout::ZERO).is_err()
+                    || !fds[0]
+                        .revents()
+                        .is_some_and(|r| r.intersects(PollFlags::POLLHUP | PollFlags::POLLERR))
+            }),
+        }
+    }
+}
+
+type RequestFilter<'a> = &'a dyn Fn(&VectorConfig, &PeerCredentials) -> Result<Vec<u8>, Rejection>;
+type ResourceFilter<'a> =
+    &'a dyn Fn(&VectorResource, &PeerCredentials) -> Result<Vec<u8>, Rejection>;
+/// Filter of a resumed session, gets the info of the client that created it.
+type SessionFilter<'a> = &'a dyn Fn(&VectorResource, &[u8]) -> Result<(), Rejection>;
+
+/// Filter of a request, deciding on the vector as requested or once the fds are checked.
+#[derive(Copy, Clone)]
+enum Policy<'a> {
+    Request(RequestFilter<'a>),
+    Resource(ResourceFilter<'a>),
+}
 
 pub struct Server {
     sockfd: OwnedFd,
     addr: UnixAddr,
+    unlink: bool,
+    info: Vec<u8>,
+    auth: Authenticator,
+    limits: ServerLimits,
+    quota: Option<Arc<QuotaLedger>>,
+    pool: Option<Arc<ShmPool>>,
+    resume: bool,
+    map: MapOptions,
+    unsealed: Unsealed,
+    sessions: Mutex<HashMap<u64, Session>>,
+    /// control connections of the accepted clients, notified on shutdown
+    controls: Mutex<Vec<(Weak<OwnedFd>, u16)>>,
+    handshake_timeout: Option<Duration>,
 }
 
 impl Server {
-    pub fn new<P: ?Sized + NixPath>(path: &P, backlog: Backlog) -> Result<Self, Errno> {
-        let addr = UnixAddr::new(path)?;
-        let sockfd = socket(
-            AddressFamily::Unix,
-            SockType::SeqPacket,
-            SockFlag::empty(),
-            None,
-        )?;
-        bind(sockfd.as_raw_fd(), &addr)?;
+    pub fn new<A: ?Sized + ToUnixAddr>(addr: &A, backlog: Backlog) -> Result<Self, Errno> {
+        let addr = addr.to_unix_addr()?;
+        let sockfd = message_socket(false)?;
+        match bind(sockfd.as_raw_fd(), &addr) {
+            Err(Errno::EADDRINUSE) => {
+                remove_stale(&addr)?;
+                bind(sockfd.as_raw_fd(), &addr)?;
+            }
+            result => result?,
+        }
         listen(&sockfd, backlog)?;
-        Ok(Self { sockfd, addr })
+        Ok(Self::with_listener(sockfd, addr, true))
     }
 
-    fn handle_request<F>(socket: RawFd, filter: F) -> Result<ChannelVector, TransferError>
+    /// Creates the socket file with the access control of options. The socket is bound
+    /// in a private directory and moved to path once mode and owner are set,
+    /// so no client can connect before.
+    pub fn with_options(
+        path: &Path,
+        backlog: Backlog,
+        options: &SocketOptions,
+    ) -> Result<Self, Errno> {
+        let parent = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+
+        if options.create_dirs {
+            fs::create_dir_all(parent).map_err(io_errno)?;
+        }
+
+        if !options.has_access() {
+            return Self::new(path, backlog);
+        }
+
+        let name = path.file_name().ok_or(Errno::EINVAL)?;
+
+        let private = parent.join(format!(".rtipc-{}-{:x}", std::process::id(), random_u64()?));
+
+        fs::DirBuilder::new()
+            .mode(0o700)
+            .create(&private)
+            .map_err(io_errno)?;
+
+        let tmp = private.join(name);
+
+        let result = Self::new(tmp.as_path(), backlog).and_then(|mut server| {
+            options.apply(&tmp).map_err(io_errno)?;
+            if fs::symlink_metadata(path).is_ok() {
+                remove_stale(&UnixAddr::new(path)?)?;
+            }
+            fs::rename(&tmp, path).map_err(io_errno)?;
+            server.addr = UnixAddr::new(path)?;
+            Ok(server)
+        });
+
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+
+        let _ = fs::remove_dir(&private);
+
+        result
+    }
+
+    /// Serves an already bound and listening unix seqpacket socket, a stream socket on
+    /// macOS, e.g. created by a supervisor. The socket file isn't removed on drop, see set_unlink_on_drop.
+    pub fn from_listener(sockfd: OwnedFd) -> Result<Self, Errno> {
+        if getsockopt(&sockfd, sockopt::SockType)? != MESSAGE_SOCKET
+            || !getsockopt(&sockfd, sockopt::AcceptConn)?
+        {
+            error!("socket is not a listening {MESSAGE_SOCKET:?} socket");
+            return Err(Errno::EINVAL);
+        }
+
+        let addr = getsockname::<UnixAddr>(sockfd.as_raw_fd())?;
+
+        Ok(Self::with_listener(sockfd, addr, false))
+    }
+
+    fn with_listener(sockfd: OwnedFd, addr: UnixAddr, unlink: bool) -> Self {
+        Self {
+            sockfd,
+            addr,
+            unlink,
+            info: Vec::with_capacity(0),
+            auth: Authenticator::default(),
+            limits: ServerLimits::default(),
+            quota: None,
+            pool: None,
+            resume: false,
+            map: MapOptions::default(),
+            unsealed: Unsealed::default(),
+            sessions: Mutex::new(HashMap::new()),
+            controls: Mutex::new(Vec::new()),
+            handshake_timeout: None,
+        }
+    }
+
+    /// Server without an address, it accepts no clients and only serves loopback.
+    pub fn unbound() -> Result<Self, Errno> {
+        let sockfd = message_socket(true)?;
+
+        /* an unbound socket has an unnamed address */
+        let addr = getsockname::<UnixAddr>(sockfd.as_raw_fd())?;
+
+        Ok(Self::with_listener(sockfd, addr, false))
+    }
+
+    /// Removes the socket file when the server is dropped, enabled for Server::new.
+    pub fn set_unlink_on_drop(&mut self, enable: bool) {
+        self.unlink = enable;
+    }
+
+    /// listening socket for polling, readable when a client connects
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        self.sockfd.as_fd()
+    }
+
+    /// Sets the vector-level info sent to every accepted client,
+    /// e.g. version, capabilities or channel annotations.
+    pub fn set_info(&mut self, info: Vec<u8>) {
+        self.info = info;
+    }
+
+    /// Requests exceeding limits are rejected before any shared memory is mapped.
+    pub fn set_limits(&mut self, limits: ServerLimits) {
+        self.limits = limits;
+    }
+
+    /// Upper bound for the handshake with a client, a client that doesn't send its request,
+    /// its fds or its acknowledgement in time fails with TimedOut. None waits forever.
+    pub fn set_handshake_timeout(&mut self, timeout: Option<Duration>) {
+        self.handshake_timeout = timeout;
+    }
+
+    pub(crate) fn handshake_timeout(&self) -> Option<Duration> {
+        self.handshake_timeout
+    }
+
+    fn handshake_deadline(&self) -> Option<Instant> {
+        self.handshake_timeout
+            .map(|timeout| Instant::now() + timeout)
+    }
+
+    /// Limits the resources held by all vectors of a client, the vectors count against
+    /// the quota until their shared memory is unmapped. Requests exceeding the quota
+    /// are rejected before anything is mapped.
+    pub fn set_quota(&mut self, quota: ClientQuota) {
+        self.quota = Some(QuotaLedger::new(quota));
+    }
+
+    /// Carves the vectors defined by the server (accept_with_layout) out of pool instead of
+    /// creating a memfd per vector. A region is reused once the vector of the server is
+    /// dropped, so a vector must only be dropped after its client disconnected.
+    pub fn set_pool(&mut self, pool: Arc<ShmPool>) {
+        self.pool = Some(pool);
+    }
+
+    /// Faults in all pages of the shared memory when mapping the vector of a client,
+    /// so the first messages don't suffer from page faults.
+    pub fn set_prefault(&mut self, enable: bool) {
+        self.map.prefault = enable;
+    }
+
+    /// Locks the shared memory of the vector of a client into memory,
+    /// accepting fails if RLIMIT_MEMLOCK doesn't allow it.
+    pub fn set_lock(&mut self, enable: bool) {
+        self.map.lock = enable;
+    }
+
+    /// Includes the shared memory of the vector of a client in core dumps or excludes it,
+    /// e.g. for small core dumps or for post-mortem access to the messages.
+    pub fn set_core_dump(&mut self, include: bool) {
+        self.map.core_dump = Some(include);
+    }
+
+    /// Maps the shared memory of vectors of at least threshold bytes for transparent
+    /// huge pages, see ConnectOptions::hugepage_threshold.
+    pub fn set_hugepage_threshold(&mut self, threshold: usize) {
+        self.map.hugepage_threshold = Some(threshold);
+    }
+
+    /// Accepts vectors whose shared memory is backed by a regular file, see
+    /// ConnectOptions::backing. A file can't be sealed, only allow trusted clients,
+    /// a client truncating the file crashes the server with SIGBUS.
+    pub fn set_file_backing(&mut self, allow: bool) {
+        self.unsealed.file = allow;
+    }
+
+    /// Accepts vectors whose shared memory is passed by name, see ShmBacking::Named.
+    /// Like a file, a shared memory object can't be sealed, only allow trusted clients.
+    pub fn set_named_shm(&mut self, allow: bool) {
+        self.unsealed.named = allow;
+    }
+
+    /// Counts the vector against the quota of the client.
+    fn charge(
+        &self,
+        cred: &PeerCredentials,
+        vconfig: &VectorConfig,
+        layout: Layout,
+    ) -> Result<Option<QuotaCharge>, RequestError> {
+        self.quota
+            .as_ref()
+            .map(|quota| quota.charge(cred, vconfig, layout))
+            .transpose()
+    }
+
+    /// Keeps the shared memory and eventfds of every accepted vector, so a client
+    /// can resume its vector after a reconnect, e.g. after a crash.
+    /// Sessions are kept until end_session is called with their token.
+    /// A session is resumed only by a client with the uid and gid of the first one,
+    /// once the client holding it hung up its control connection, and only if the
+    /// filter of the accept still admits it. Sessions of clients without control
+    /// connection, e.g. of accept_transport, stay attached until end_session.
+    pub fn set_resume(&mut self, enable: bool) {
+        self.resume = enable;
+    }
+
+    /// Releases the shared memory and eventfds kept for the session,
+    /// returns false for an unknown token.
+    pub fn end_session(&self, token: u64) -> bool {
+        self.sessions.lock().unwrap().remove(&token).is_some()
+    }
+
+    /// Control connection of an accepted client speaking version, kept track of for shutdown.
+    /// Resizes of the client are held to the limits and the quota of its handshake.
+    /// The client holds the session of token until it hangs up.
+    fn control(
+        &self,
+        socket: OwnedFd,
+        version: u16,
+        cred: PeerCredentials,
+        token: Option<u64>,
+    ) -> Control {
+        let mut control = Control::new(socket, self.auth.clone(), version);
+
+        control.set_admission(Admission {
+            limits: self.limits.clone(),
+            unsealed: self.unsealed,
+            quota: self.quota.clone().map(|quota| (quota, cred)),
+        });
+
+        let mut controls = self.controls.lock().unwrap();
+
+        controls.retain(|(socket, _)| socket.strong_count() > 0);
+        controls.push((control.socket(), version));
+
+        if let Some(token) = token
+            && let Some(session) = self.sessions.lock().unwrap().get_mut(&token)
+        {
+            session.holder = Holder::Control(control.socket());
+        }
+
+        control
+    }
+
+    /// Asks all connected clients to stop using their vectors by sending Shutdown
+    /// and waits up to timeout until they hung up, then the server is dropped,
+    /// which unlinks the socket file if enabled. A client acknowledges by dropping
+    /// its vector, or at least its Control. Legacy clients and clients of dropped
+    /// vectors aren't notified. Returns the number of clients that didn't hang up in time.
+    pub fn shutdown(self, timeout: Duration) -> Result<usize, Errno> {
+        let mut pending: Vec<Arc<OwnedFd>> = self
+            .controls
+            .lock()
+            .unwrap()
+            .drain(..)
+            .filter_map(|(socket, version)| Some((socket.upgrade()?, version)))
+            .filter(|(socket, version)| {
+                send_control(
+                    socket.as_fd(),
+                    &self.auth,
+                    &ControlMessage::Shutdown,
+                    *version,
+                )
+                .inspect_err(|e| info!("client gone before shutdown {e:?}"))
+                .is_ok()
+            })
+            .map(|(socket, _)| socket)
+            .collect();
+
+        info!("shutdown, waiting for {} clients", pending.len());
+
+        let deadline = Instant::now() + timeout;
+
+        while !pending.is_empty() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            if remaining.is_zero() {
+                break;
+            }
+
+            /* POLLHUP is reported without asking for it, messages are left to the Control */
+            let hung_up: Vec<bool> = {
+                let mut fds: Vec<PollFd> = pending
+                    .iter()
+                    .map(|socket| PollFd::new(socket.as_fd(), PollFlags::empty()))
+                    .collect();
+
+                match poll(
+                    &mut fds,
+                    PollTimeout::try_from(remaining).unwrap_or(PollTimeout::MAX),
+                ) {
+                    Ok(_) | Err(Errno::EINTR) => {}
+                    Err(e) => return Err(e),
+                }
+
+                fds.iter()
+                    .map(|fd| {
+                        fd.revents()
+                            .is_some_and(|r| r.intersects(PollFlags::POLLHUP | PollFlags::POLLERR))
+                    })
+                    .collect()
+            };
+
+            let mut hung_up = hung_up.into_iter();
+            pending.retain(|_| !hung_up.next().unwrap_or(false));
+        }
+
+        if !pending.is_empty() {
+            error!("{} clients didn't hang up", pending.len());
+        }
+
+        Ok(pending.len())
+    }
+
+    /// Keeps duplicates of the fds of rsc, returns the token of the session
+    /// or 0 if sessions aren't enabled.
+    fn add_session(
+        &self,
+        rsc: &VectorResource,
+        client_owner: bool,
+        cred: &PeerCredentials,
+        info: &[u8],
+    ) -> Result<u64, Errno> {
+        if !self.resume {
+            return Ok(0);
+        }
+
+        /* the region returns to the pool or the user with the vector */
+        if rsc.region.is_some() || rsc.memory.is_some() {
+            info!("vectors of a pool can't be resumed");
+            return Ok(0);
+        }
+
+        /* a resumed vector is passed without its dma-bufs */
+        if !rsc.dmabufs.is_empty() {
+            info!("vectors with dma-bufs can't be resumed");
+            return Ok(0);
+        }
+
+        /* the client of a vector passed by name may not be able to receive the fds */
+        if rsc.shm_name.is_some() {
+            info!("vectors passed by name can't be resumed");
+            return Ok(0);
+        }
+
+        let fds = rsc
+            .collect_fds()
+            .into_iter()
+            .map(dup)
+            .collect::<Result<Vec<OwnedFd>, Errno>>()?;
+
+        let session = Session {
+            vconfig: rsc.get_config(),
+            layout: rsc.layout(),
+            fds,
+            client_owner,
+            file_backed: rsc.file_backed,
+            info: info.to_vec(),
+            uid: cred.uid,
+            gid: cred.gid,
+            holder: Holder::Handshake,
+        };
+
+        let mut sessions = self.sessions.lock().unwrap();
+
+        let token = loop {
+            let token = random_u64()?;
+            if token != 0 && !sessions.contains_key(&token) {
+                break token;
+            }
+        };
+
+        sessions.insert(token, session);
+
+        Ok(token)
+    }
+
+    /// Checks a resume request, admit is the filter of the accept. Returns the resource
+    /// of the session and the info of its client, the session is held by the handshake.
+    fn handle_resume(
+        &self,
+        req: &[u8],
+        cred: &PeerCredentials,
+        admit: SessionFilter<'_>,
+    ) -> Result<(u64, VectorResource, Vec<u8>), TransferError> {
+        let request = parse_request(self.auth.verify_request(req)?, &self.limits)?;
+
+        let token = request.resume.ok_or(TransferError::ResponseError)?;
+
+        let refused = |reason: &str| {
+            error!("{reason}");
+            TransferError::Rejected(Rejection::new(Rejection::UNSPECIFIED, reason))
+        };
+
+        let (rsc, info) = {
+            let sessions = self.sessions.lock().unwrap();
+
+            let Some(session) = sessions.get(&token) else {
+                return Err(refused("unknown session"));
+            };
+
+            if (session.uid, session.gid) != (cred.uid, cred.gid) {
+                return Err(refused("session of another client"));
+            }
+
+            if session.holder.is_attached() {
+                return Err(refused("session is attached"));
+            }
+
+            let fds = session
+                .fds
+                .iter()
+                .map(dup)
+                .collect::<Result<VecDeque<OwnedFd>, Errno>>()?;
+
+            let rsc = VectorResource::from_session(
+                &session.vconfig,
+                session.layout,
+                fds,
+                !session.client_owner,
+                session.file_backed,
+            )?;
+
+            (rsc, session.info.clone())
+        };
+
+        /* the filter runs unlocked, it may end sessions */
+        admit(&rsc, &info).map_err(TransferError::Rejected)?;
+
+        let mut sessions = self.sessions.lock().unwrap();
+
+        match sessions.get_mut(&token) {
+            Some(session) if !session.holder.is_attached() => session.holder = Holder::Handshake,
+            Some(_) => return Err(refused("session is attached")),
+            None => return Err(refused("unknown session")),
+        }
+
+        Ok((token, rsc, info))
+    }
+
+    /// Sends the shared memory and eventfds of a session to a reconnected client.
+    /// The returned vector has no channels, they stay with the vector of the session.
+    /// Additionally returns the version spoken by the client.
+    fn resume<T: Transport>(
+        &self,
+        transport: &mut T,
+        req: &[u8],
+        credentials: PeerCredentials,
+        deadline: Option<Instant>,
+        admit: SessionFilter<'_>,
+    ) -> Result<(ChannelVector, PeerInfo, u16), TransferError> {
+        let version = request_version(req);
+
+        let (token, rsc, info) = match self.handle_resume(req, &credentials, admit) {
+            Ok(resumed) => resumed,
+            Err(e) => {
+                let rejection = Response::Rejected(Self::rejection(&e));
+                self.send_response(transport, &rejection, req)?;
+                return Err(e);
+            }
+        };
+
+        let result = self.send_session(transport, req, version, deadline, token, &rsc);
+
+        /* the client gave up, another one may resume the session */
+        if result.is_err()
+            && let Some(session) = self.sessions.lock().unwrap().get_mut(&token)
+        {
+            session.holder = Holder::None;
+        }
+
+        result?;
+
+        info!("session resumed");
+
+        let peer = PeerInfo { credentials, info };
+
+        Ok((ChannelVector::resumed_session(token), peer, version))
+    }
+
+    /// Sends the resource of the session token and waits for the acknowledgement.
+    fn send_session<T: Transport>(
+        &self,
+        transport: &mut T,
+        req: &[u8],
+        version: u16,
+        deadline: Option<Instant>,
+        token: u64,
+        rsc: &VectorResource,
+    ) -> Result<(), TransferError> {
+        let response = Response::Vector {
+            vconfig: rsc.get_config(),
+            layout: rsc.layout(),
+            token,
+            owner: !rsc.owner,
+            pool_offset: None,
+            file_backed: rsc.file_backed,
+        };
+
+        transport.send_response(
+            &self
+                .auth
+                .sign_reply(create_response(&response, version)?, req),
+            &rsc.collect_fds(),
+        )?;
+
+        let ack = transport.recv_request(deadline)?;
+
+        match parse_response(self.auth.verify_reply(&ack, req)?)? {
+            Response::Accepted { .. } => Ok(()),
+            _ => Err(TransferError::ResponseError),
+        }
+    }
+
+    /// Sets the per-deployment application id, clients with another cookie are rejected.
+    /// Unrelated rtipc applications sharing a socket directory use different cookies.
+    pub fn set_cookie(&mut self, cookie: u32) {
+        self.auth.set_cookie(cookie);
+    }
+
+    /// Requires all requests to be signed with key, responses are signed as well
+    /// and carry the nonce of the request, so they can't be replayed to another client.
+    #[cfg(feature = "hmac")]
+    pub fn set_key(&mut self, key: &[u8]) {
+        self.auth.set_key(Some(key.to_vec()));
+    }
+
+    /// Sends the response to req, in the version of req.
+    fn send_response<T: Transport>(
+        &self,
+        transport: &mut T,
+        response: &Response,
+        req: &[u8],
+    ) -> Result<(), TransferError> {
+        let response = create_response(response, request_version(req))?;
+        transport.send_response(&self.auth.sign_reply(response, req), &[])
+    }
+
+    /// Receives the request, a request with a layout aligned to a smaller cache line size
+    /// than ours or with unsupported index width is answered once with a retry response.
+    fn receive_request<T: Transport>(
+        &self,
+        transport: &mut T,
+        deadline: Option<Instant>,
+    ) -> Result<(Vec<u8>, VecDeque<OwnedFd>), TransferError> {
+        let layout = Layout::native();
+        let mut retried = false;
+
+        loop {
+            let req = transport.recv_request(deadline)?;
+
+            /* the count isn't verified yet, the continuation messages it announces aren't
+             * read beyond the fds a vector within the limits can have */
+            let fd_count = parse_fd_count(&req);
+
+            if fd_count > self.limits.max_fds() {
+                error!("request announces {fd_count} fds");
+                return Err(RequestError::LimitExceeded.into());
+            }
+
+            let fds = transport.recv_fds(fd_count)?;
+
+            let retry = !retried
+                && !is_legacy_request(&req)
+                && verify_header(&req).is_ok_and(|h| {
+                    h.cacheline_size < layout.cacheline_size
+                        || !is_supported_index_size(h.atomic_size)
+                });
+
+            if !retry {
+                return Ok((req, fds));
+            }
+
+            info!("request layout not supported, retry with {layout:?}");
+
+            self.send_response(transport, &Response::Retry { layout }, &req)?;
+
+            retried = true;
+        }
+    }
+
+    /// Reason sent to the client for a failed request.
+    fn rejection(e: &TransferError) -> Rejection {
+        match e {
+            TransferError::Rejected(rejection) => rejection.clone(),
+            e => Rejection::new(Rejection::UNSPECIFIED, format!("{e:?}")),
+        }
+    }
+
+    fn handle_request(
+        &self,
+        req: &[u8],
+        fds: VecDeque<OwnedFd>,
+        cred: &PeerCredentials,
+        policy: Policy<'_>,
+    ) -> Result<(ChannelVector, Vec<u8>), TransferError> {
+        let content = self.auth.verify_request(req)?;
+
+        /* the fds of a request rejected here aren't even inspected */
+        let screened = match policy {
+            Policy::Request(filter) => {
+                let request = parse_request(content, &self.limits)?;
+                Some(filter(&request.vconfig, cred).map_err(TransferError::Rejected)?)
+            }
+            Policy::Resource(_) => None,
+        };
+
+        let mut rsc =
+            VectorResource::deserialize_backed(content, fds, &self.limits, self.unsealed)?;
+
+        let payload = match (policy, screened) {
+            (Policy::Resource(filter), _) => filter(&rsc, cred).map_err(TransferError::Rejected)?,
+            (Policy::Request(_), payload) => payload.unwrap_or_default(),
+        };
+
+        rsc.charge = self.charge(cred, &rsc.get_config(), rsc.layout())?;
+        rsc.map = self.map;
+
+        let token = self.add_session(&rsc, true, cred, &rsc.info)?;
+
+        let mut vec = ChannelVector::new(rsc)?;
+
+        vec.set_session_token(token);
+
+        Ok((vec, payload))
+    }
+
+    /// Accepts a client if filter returns Ok, the returned payload (e.g. a token or an
+    /// assigned ID) is embedded in the response. The rejection is sent to the client otherwise.
+    /// filter gets the credentials of the client process for restricting access to local users.
+    pub fn conditional_accept<F>(
+        &self,
+        filter: F,
+    ) -> Result<(ChannelVector, PeerInfo), TransferError>
+    where
+        F: Fn(&VectorResource, &PeerCredentials) -> Result<Vec<u8>, Rejection>,
+    {
+        let socket = accept_socket(self.sockfd.as_fd())?;
+
+        self.handshake(socket, Policy::Resource(&filter))
+    }
+
+    /// Accepts a client like conditional_accept, but filter decides on the vector as
+    /// parsed from the request, before the shared memory and eventfds of the client
+    /// are inspected or mapped. vconfig is seen from the server.
+    pub fn accept_with<F>(&self, filter: F) -> Result<(ChannelVector, PeerInfo), TransferError>
     where
-        F: Fn(&VectorResource) -> bool,
+        F: Fn(&VectorConfig, &PeerCredentials) -> Result<Vec<u8>, Rejection>,
     {
-        let mut req = UnixMessageRx::receive(socket.as_raw_fd())?;
+        let socket = accept_socket(self.sockfd.as_fd())?;
+
+        self.handshake(socket, Policy::Request(&filter))
+    }
+
+    /// Server side of the handshake on a connected socket.
+    fn handshake(
+        &self,
+        socket: OwnedFd,
+        policy: Policy<'_>,
+    ) -> Result<(ChannelVector, PeerInfo), TransferError> {
+        let cred = PeerCredentials::of(&socket)?;
+
+        let mut transport = UnixTransport::new(socket.as_fd());
+
+        let (mut vec, peer, version) = self.serve(&mut transport, cred, policy)?;
+
+        /* legacy clients don't know about the control connection */
+        if version != FIXED_LAYOUT_VERSION {
+            let token = vec.session_token();
+            vec.set_control(self.control(socket, version, cred, token));
+        }
+
+        Ok((vec, peer))
+    }
+
+    /// Handshake of a client defining its vector or resuming a session,
+    /// additionally returns the version spoken by the client, FIXED_LAYOUT_VERSION
+    /// for the legacy protocol.
+    fn serve<T: Transport>(
+        &self,
+        transport: &mut T,
+        cred: PeerCredentials,
+        policy: Policy<'_>,
+    ) -> Result<(ChannelVector, PeerInfo, u16), TransferError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("rtipc.server.handshake", pid = cred.pid).entered();
 
-        let fds = req.take_fds();
+        let deadline = self.handshake_deadline();
 
-        let rsc = VectorResource::deserialize(req.content(), fds)?;
+        let (req, fds) = self.receive_request(transport, deadline)?;
 
-        if !filter(&rsc) {
-            return Err(TransferError::Rejected);
+        if is_resume_request(&req) {
+            let admit = |rsc: &VectorResource, _: &[u8]| {
+                match policy {
+                    Policy::Request(filter) => filter(&rsc.get_config(), &cred),
+                    Policy::Resource(filter) => filter(rsc, &cred),
+                }
+                .map(drop)
+            };
+            return self.resume(transport, &req, cred, deadline, &admit);
         }
 
-        let vec = ChannelVector::new(rsc)?;
+        let result = self.handle_request(&req, fds, &cred, policy);
 
-        Ok(vec)
+        let version = request_version(&req);
+
+        #[cfg(feature = "tracing")]
+        match &result {
+            Ok((vec, _)) => {
+                tracing::info!(version, session = ?vec.session_token(), "client accepted")
+            }
+            Err(e) => tracing::warn!(version, error = ?e, "client rejected"),
+        }
+
+        if version == FIXED_LAYOUT_VERSION {
+            transport.send_response(&create_legacy_response(result.is_ok()), &[])?;
+        } else {
+            let response = match &result {
+                Ok((vec, payload)) => Response::Accepted {
+                    info: self.info.clone(),
+                    payload: payload.clone(),
+                    token: vec.session_token().unwrap_or(0),
+                },
+                Err(e) => Response::Rejected(Self::rejection(e)),
+            };
+            self.send_response(transport, &response, &req)?;
+        }
+
+        let (vec, _) = result?;
+
+        let peer = PeerInfo {
+            credentials: cred,
+            info: vec.info().clone(),
+        };
+
+        Ok((vec, peer, version))
     }
 
-    pub fn conditional_accept<F>(&self, filter: F) -> Result<ChannelVector, TransferError>
+    /// Accepts a client over another control plane, e.g. D-Bus, like conditional_accept.
+    /// credentials are those of the client as far as the transport knows them,
+    /// the vector has no control connection.
+    pub fn accept_transport<T, F>(
+        &self,
+        transport: &mut T,
+        credentials: PeerCredentials,
+        filter: F,
+    ) -> Result<(ChannelVector, PeerInfo), TransferError>
     where
-        F: Fn(&VectorResource) -> bool,
+        T: Transport,
+        F: Fn(&VectorResource, &PeerCredentials) -> Result<Vec<u8>, Rejection>,
     {
-        let socket = accept(self.sockfd.as_raw_fd())?;
+        let (vec, peer, _) = self.serve(transport, credentials, Policy::Resource(&filter))?;
 
-        let result = Self::handle_request(socket, filter);
+        Ok((vec, peer))
+    }
 
-        let response_msg = create_response(result.is_ok());
+    pub fn accept(&self) -> Result<(ChannelVector, PeerInfo), TransferError> {
+        self.conditional_accept(|_, _| Ok(Vec::with_capacity(0)))
+    }
 
-        let response = UnixMessageTx::new(response_msg, Vec::with_capacity(0));
+    /// Runs the whole handshake with a client in this process over a socketpair, both
+    /// vectors map the same memfd. Meant for unit tests of producer and consumer logic,
+    /// the settings of the server apply, the listening socket isn't used.
+    /// Returns the vectors of the client and of the server.
+    pub fn loopback(
+        &self,
+        vconfig: VectorConfig,
+        options: &ConnectOptions,
+    ) -> Result<(ChannelVector, ChannelVector), TransferError> {
+        let (server, client) = message_socketpair()?;
 
-        response.send(socket)?;
-        result
+        let (client, server) = thread::scope(|scope| {
+            /* the socket is closed when the client gives up, the server isn't left waiting */
+            let client =
+                scope.spawn(move || client_connect_fd_with(client.as_raw_fd(), vconfig, options));
+            let server =
+                self.handshake(server, Policy::Resource(&|_, _| Ok(Vec::with_capacity(0))));
+            (client.join().unwrap(), server)
+        });
+
+        let client = client?;
+        let (server, _) = server?;
+
+        Ok((client, server))
+    }
+
+    /// Fails with WouldBlock if no client is connecting, otherwise the handshake
+    /// runs like accept. Only a single thread may accept on the server.
+    pub fn accept_nonblocking(&self) -> Result<(ChannelVector, PeerInfo), TransferError> {
+        if !is_readable(self.sockfd.as_raw_fd())? {
+            return Err(TransferError::WouldBlock);
+        }
+
+        self.accept()
+    }
+
+    fn handle_query<F>(
+        &self,
+        req: &[u8],
+        cred: &PeerCredentials,
+        define: F,
+    ) -> Result<(VectorResource, Vec<u8>), TransferError>
+    where
+        F: Fn(&[u8]) -> Result<VectorConfig, Rejection>,
+    {
+        let request = parse_request(self.auth.verify_request(req)?, &self.limits)?;
+
+        if !request.query {
+            error!("request defines its own vector");
+            return Err(TransferError::Rejected(Rejection::new(
+                Rejection::UNSPECIFIED,
+                "server defines the vector",
+            )));
+        }
+
+        let vconfig = define(&request.vconfig.info).map_err(TransferError::Rejected)?;
+
+        vconfig.validate(usize::MAX)?;
+
+        /* a client of an older version doesn't know descriptors */
+        let layout = Layout {
+            descriptors: request.descriptors,
+            ..query_layout(request.cacheline_size, request.index_size)
+        };
+
+        let charge = self.charge(cred, &vconfig, layout)?;
+
+        let mut rsc = match &self.pool {
+            Some(pool) => VectorResource::allocate_pooled(&vconfig, layout, pool)?,
+            None => VectorResource::allocate_layout(&vconfig, layout)?,
+        };
+
+        rsc.charge = charge;
+        rsc.map = self.map;
+
+        Ok((rsc, request.vconfig.info))
+    }
+
+    /// Accepts a client that lets the server define the vector.
+    /// define is called with the info of the client and returns the vector
+    /// from the server's point of view or the reason to reject the client.
+    pub fn accept_with_layout<F>(
+        &self,
+        define: F,
+    ) -> Result<(ChannelVector, PeerInfo), TransferError>
+    where
+        F: Fn(&[u8]) -> Result<VectorConfig, Rejection>,
+    {
+        let socket = accept_socket(self.sockfd.as_fd())?;
+
+        let credentials = PeerCredentials::of(&socket)?;
+
+        let mut transport = UnixTransport::new(socket.as_fd());
+
+        let (mut vec, peer, version) = self.serve_query(&mut transport, credentials, define)?;
+
+        let token = vec.session_token();
+        vec.set_control(self.control(socket, version, credentials, token));
+
+        Ok((vec, peer))
+    }
+
+    /// Handshake of a client letting the server define the vector or resuming a session,
+    /// additionally returns the version spoken by the client.
+    fn serve_query<T, F>(
+        &self,
+        transport: &mut T,
+        credentials: PeerCredentials,
+        define: F,
+    ) -> Result<(ChannelVector, PeerInfo, u16), TransferError>
+    where
+        T: Transport,
+        F: Fn(&[u8]) -> Result<VectorConfig, Rejection>,
+    {
+        let deadline = self.handshake_deadline();
+
+        let req = transport.recv_request(deadline)?;
+
+        if is_resume_request(&req) {
+            let admit = |_: &VectorResource, info: &[u8]| define(info).map(drop);
+            return self.resume(transport, &req, credentials, deadline, &admit);
+        }
+
+        let version = request_version(&req);
+
+        let (rsc, info) = match self.handle_query(&req, &credentials, define) {
+            Ok(query) => query,
+            Err(e) => {
+                let rejection = Response::Rejected(Self::rejection(&e));
+                self.send_response(transport, &rejection, &req)?;
+                return Err(e);
+            }
+        };
+
+        let token = self.add_session(&rsc, false, &credentials, &info)?;
+
+        let response = Response::Vector {
+            vconfig: rsc.get_config(),
+            layout: rsc.layout(),
+            token,
+            owner: false,
+            pool_offset: rsc.pool_offset,
+            file_backed: rsc.file_backed,
+        };
+
+        transport.send_response(
+            &self
+                .auth
+                .sign_reply(create_response(&response, version)?, &req),
+            &rsc.collect_fds(),
+        )?;
+
+        /* the client initializes the shared memory before it acknowledges */
+        let ack = transport.recv_request(deadline)?;
+
+        match parse_response(self.auth.verify_reply(&ack, &req)?)? {
+            Response::Accepted { .. } => {
+                let mut vec = ChannelVector::new(rsc)?;
+                vec.set_session_token(token);
+                Ok((vec, PeerInfo { credentials, info }, version))
+            }
+            _ => Err(TransferError::ResponseError),
+        }
+    }
+
+    /// Accepts a client that lets the server define the vector, like accept_with_layout,
+    /// and subscribes it to hub. The vector of the client maps the shared memory of hub,
+    /// its consumer 0 is the broadcast channel with an eventfd of its own. filter is called
+    /// with the info and the credentials of the client. The returned vector has no channels,
+    /// the client stays subscribed until it's dropped.
+    pub fn accept_broadcast<T, F>(
+        &self,
+        hub: &mut BroadcastHub<T>,
+        filter: F,
+    ) -> Result<(ChannelVector, PeerInfo), TransferError>
+    where
+        T: Copy,
+        F: Fn(&[u8], &PeerCredentials) -> Result<(), Rejection>,
+    {
+        let socket = accept_socket(self.sockfd.as_fd())?;
+
+        let credentials = PeerCredentials::of(&socket)?;
+
+        let mut transport = UnixTransport::new(socket.as_fd());
+
+        let deadline = self.handshake_deadline();
+
+        let req = transport.recv_request(deadline)?;
+
+        let version = request_version(&req);
+
+        let (eventfd, info) = match self.handle_subscription(&req, &credentials, hub, filter) {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                let rejection = Response::Rejected(Self::rejection(&e));
+                self.send_response(&mut transport, &rejection, &req)?;
+                return Err(e);
+            }
+        };
+
+        /* the hub initialized the shared memory, the client only maps it */
+        let response = Response::Vector {
+            vconfig: hub.vconfig().clone(),
+            layout: hub.layout(),
+            token: 0,
+            owner: true,
+            pool_offset: None,
+            file_backed: false,
+        };
+
+        transport.send_response(
+            &self
+                .auth
+                .sign_reply(create_response(&response, version)?, &req),
+            &[hub.shmfd(), eventfd.as_fd()],
+        )?;
+
+        let ack = transport.recv_request(deadline)?;
+
+        match parse_response(self.auth.verify_reply(&ack, &req)?)? {
+            Response::Accepted { .. } => {}
+            _ => return Err(TransferError::ResponseError),
+        }
+
+        let control = self.control(socket, version, credentials, None);
+
+        hub.subscribe(eventfd, control.socket());
+
+        let mut vec = ChannelVector::without_channels();
+        vec.set_control(control);
+
+        info!("client subscribed to broadcast");
+
+        Ok((vec, PeerInfo { credentials, info }))
+    }
+
+    /// Checks the query of a client of hub, returns the eventfd of the client and its info.
+    fn handle_subscription<T, F>(
+        &self,
+        req: &[u8],
+        cred: &PeerCredentials,
+        hub: &BroadcastHub<T>,
+        filter: F,
+    ) -> Result<(EventFd, Vec<u8>), TransferError>
+    where
+        T: Copy,
+        F: Fn(&[u8], &PeerCredentials) -> Result<(), Rejection>,
+    {
+        let request = parse_request(self.auth.verify_request(req)?, &self.limits)?;
+
+        let refused = |reason: &str| {
+            error!("{reason}");
+            TransferError::Rejected(Rejection::new(Rejection::UNSPECIFIED, reason))
+        };
+
+        if !request.query {
+            return Err(refused("server defines the vector"));
+        }
+
+        /* the layout of the hub is fixed, all clients map the same memory */
+        if !request.descriptors || request.cacheline_size > hub.layout().cacheline_size {
+            return Err(refused("layout of the broadcast not supported"));
+        }
+
+        filter(&request.vconfig.info, cred).map_err(TransferError::Rejected)?;
+
+        Ok((eventfd_create()?, request.vconfig.info))
+    }
+}
+
+/// Layout of a vector defined by the server, aligned to the larger cache lines of both peers.
+pub(crate) fn query_layout(cacheline_size: usize, index_size: usize) -> Layout {
+    let native = Layout::native();
+
+    Layout {
+        cacheline_size: cacheline_size.max(native.cacheline_size),
+        descriptors: true,
+        index_size: if is_supported_index_size(index_size) {
+            index_size
+        } else {
+            native.index_size
+        },
+    }
+}
+
+/// ETIMEDOUT is only caused by the deadline of the handshake.
+pub(crate) fn timed_out(e: Errno) -> TransferError {
+    match e {
+        Errno::ETIMEDOUT => {
+            error!("handshake timed out");
+            TransferError::TimedOut
+        }
+        e => e.into(),
+    }
+}
+
+/// Receives the response to request, a signed response has to carry the nonce of request.
+fn receive_response<T: Transport>(
+    transport: &mut T,
+    auth: &Authenticator,
+    request: &[u8],
+    deadline: Option<Instant>,
+) -> Result<Response, TransferError> {
+    let msg = transport.recv_response(deadline)?;
+
+    let content = auth.verify_reply(&msg, request).map_err(|e| {
+        error!("response verification failed {e:?}");
+        TransferError::ResponseError
+    })?;
+
+    parse_response(content)
+}
+
+/// The socket stays with the caller, the control connection uses a duplicate.
+fn control(socket: RawFd, auth: Authenticator, version: u16) -> Result<Control, Errno> {
+    let socket = dup(unsafe { BorrowedFd::borrow_raw(socket) })?;
+    Ok(Control::new(socket, auth, version))
+}
+
+/// Client side of the handshake for a vector defined by the client,
+/// every request is answered by a single response.
+struct Handshake {
+    vconfig: VectorConfig,
+    auth: Authenticator,
+    layout: Layout,
+    map: MapOptions,
+    backing: ShmBacking,
+    dmabufs: Vec<Arc<OwnedFd>>,
+    preallocate: bool,
+    legacy: bool,
+    /// protocol version spoken with the server
+    version: u16,
+    rsc: Option<VectorResource>,
+    /// last request sent, the responses carry its nonce
+    request: Vec<u8>,
+    /// removes the name of a Named backing once the handshake is over
+    shm_name: Option<ShmName>,
+}
+
+impl Handshake {
+    fn start<T: Transport>(
+        transport: &mut T,
+        vconfig: VectorConfig,
+        options: &ConnectOptions,
+    ) -> Result<Self, TransferError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("rtipc.client.handshake").entered();
+
+        vconfig.validate(usize::MAX)?;
+
+        if options.backing == ShmBacking::Named
+            && (vconfig.count_fds() != 1 || !options.dmabufs.is_empty())
+        {
+            return Err(ConfigError::EventFdsUnsupported.into());
+        }
+
+        if options.legacy {
+            Self::check_legacy(&vconfig, options)?;
+        }
+
+        let mut handshake = Self {
+            vconfig,
+            auth: options.authenticator(),
+            layout: options.layout()?,
+            map: options.map_options(),
+            backing: options.backing.clone(),
+            dmabufs: options.dmabufs.clone(),
+            preallocate: options.preallocate,
+            legacy: options.legacy,
+            version: options.version()?,
+            rsc: None,
+            request: Vec::new(),
+            shm_name: None,
+        };
+
+        handshake.send_request(transport)?;
+
+        Ok(handshake)
     }
 
-    pub fn accept(&self) -> Result<ChannelVector, TransferError> {
-        self.conditional_accept(|_| true)
+    /// The legacy protocol only knows plain queues.
+    fn check_legacy(vconfig: &VectorConfig, options: &ConnectOptions) -> Result<(), TransferError> {
+        #[cfg(feature = "hmac")]
+        let keyed = options.key.is_some();
+        #[cfg(not(feature = "hmac"))]
+        let keyed = false;
+
+        let queues = vconfig
+            .producers
+            .iter()
+            .chain(vconfig.consumers.iter())
+            .all(|config| config.kind == ChannelKind::Queue);
+
+        if !queues
+            || vconfig.arena.is_some()
+            || vconfig.heartbeat
+            || keyed
+            || options.cookie != 0
+            || options.version.is_some()
+            || !options.dmabufs.is_empty()
+            || options.backing != ShmBacking::Memfd
+        {
+            error!(
+                "legacy handshake supports only queues in a memfd, without arena, cookie, key or version"
+            );
+            return Err(ResourceError::InvalidArgument.into());
+        }
+
+        Ok(())
+    }
+
+    fn send_request<T: Transport>(&mut self, transport: &mut T) -> Result<(), TransferError> {
+        let mut rsc = VectorResource::allocate_backed(&self.vconfig, self.layout, &self.backing)?;
+
+        if self.preallocate {
+            rsc.preallocate()?;
+        }
+
+        for fd in &self.dmabufs {
+            rsc.add_dmabuf(dup(fd.as_fd())?)?;
+        }
+
+        /* the name of a retried request replaces the previous one */
+        self.shm_name = rsc.shm_name.clone().map(ShmName);
+
+        if self.legacy {
+            /* the fixed request has no room for a cookie or signature */
+            let req = create_request_fixed(&self.vconfig)?;
+            transport.send_request(&req, &rsc.collect_fds())?;
+            self.request = req;
+        } else {
+            let (req, fds) = rsc.serialize()?;
+            let req = self.auth.sign_request(req)?;
+            transport.send_request(&req, &fds)?;
+            self.request = req;
+        }
+
+        self.rsc = Some(rsc);
+
+        Ok(())
+    }
+
+    /// Handles the response to the last request, returns the vector once the server accepted it.
+    fn handle_response<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        response: Response,
+    ) -> Result<Option<ChannelVector>, TransferError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("rtipc.client.handshake", version = self.version).entered();
+
+        match response {
+            Response::Accepted {
+                info,
+                payload,
+                token,
+            } => {
+                let mut rsc = self.rsc.take().ok_or(TransferError::ResponseError)?;
+                rsc.map = self.map;
+                rsc.link_backing()?;
+                let mut vec = ChannelVector::new(rsc)?;
+                vec.set_server_info(info);
+                vec.set_payload(payload);
+                vec.set_session_token(token);
+                #[cfg(feature = "tracing")]
+                tracing::info!(layout = ?self.layout, "vector accepted");
+                Ok(Some(vec))
+            }
+            Response::Retry { layout: server } => {
+                /* the server needs a layout aligned to its larger cache lines
+                 * or a different index width */
+                let retry = Layout {
+                    cacheline_size: self.layout.cacheline_size.max(server.cacheline_size),
+                    index_size: server.index_size,
+                    descriptors: self.layout.descriptors,
+                };
+
+                if retry == self.layout || !is_supported_index_size(retry.index_size) {
+                    return Err(TransferError::ResponseError);
+                }
+
+                info!("server requests layout {retry:?}");
+                self.layout = retry;
+                self.send_request(transport)?;
+                Ok(None)
+            }
+            Response::Rejected(rejection) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(rejection = ?rejection, "vector rejected");
+                Err(TransferError::Rejected(rejection))
+            }
+            Response::Vector { .. } => Err(TransferError::ResponseError),
+        }
     }
 }
 
+/// Connects over another control plane, e.g. D-Bus, like client_connect_fd_with.
+/// The vector has no control connection.
+pub fn client_connect_transport<T: Transport>(
+    transport: &mut T,
+    vconfig: VectorConfig,
+    options: &ConnectOptions,
+) -> Result<ChannelVector, TransferError> {
+    let deadline = options.deadline();
+    let mut handshake = Handshake::start(transport, vconfig, options)?;
+
+    loop {
+        let response = receive_response(transport, &handshake.auth, &handshake.request, deadline)?;
+
+        if let Some(vec) = handshake.handle_response(transport, response)? {
+            return Ok(vec);
+        }
+    }
+}
+
+pub fn client_connect_fd_with(
+    socket: RawFd,
+    vconfig: VectorConfig,
+    options: &ConnectOptions,
+) -> Result<ChannelVector, TransferError> {
+    let mut transport = UnixTransport::new(unsafe { BorrowedFd::borrow_raw(socket) });
+
+    let mut vec = client_connect_transport(&mut transport, vconfig, options)?;
+
+    /* a legacy server doesn't know about the control connection */
+    if !options.legacy {
+        vec.set_control(control(
+            socket,
+            options.authenticator(),
+            options.version()?,
+        )?);
+    }
+
+    Ok(vec)
+}
+
+/// Handshake started by client_connect_nonblocking, driven by the caller's event loop:
+/// wait until fd is readable and call advance until it returns the vector.
+pub struct ConnectInProgress {
+    socket: OwnedFd,
+    handshake: Option<Handshake>,
+    deadline: Option<Instant>,
+}
+
+impl ConnectInProgress {
+    /// socket for polling, readable when the server responded
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+
+    /// Returns None as long as the server didn't respond, doesn't block on a silent server.
+    /// Fails with TimedOut once the timeout of the connect options expired.
+    pub fn advance(&mut self) -> Result<Option<ChannelVector>, TransferError> {
+        let socket = self.socket.as_raw_fd();
+
+        let Some(handshake) = self.handshake.as_mut() else {
+            return Err(Errno::EALREADY.into());
+        };
+
+        if !is_readable(socket)? {
+            if self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Err(timed_out(Errno::ETIMEDOUT));
+            }
+            return Ok(None);
+        }
+
+        let mut transport = UnixTransport::new(self.socket.as_fd());
+
+        let response = receive_response(
+            &mut transport,
+            &handshake.auth,
+            &handshake.request,
+            self.deadline,
+        )?;
+
+        let Some(mut vec) = handshake.handle_response(&mut transport, response)? else {
+            return Ok(None);
+        };
+
+        if !handshake.legacy {
+            vec.set_control(control(socket, handshake.auth.clone(), handshake.version)?);
+        }
+
+        self.handshake = None;
+
+        Ok(Some(vec))
+    }
+}
+
+/// Connects and sends the request without waiting for the server,
+/// connecting to a listening unix socket doesn't wait for the server to accept.
+pub fn client_connect_nonblocking<A: ?Sized + ToUnixAddr>(
+    addr: &A,
+    vconfig: VectorConfig,
+    options: &ConnectOptions,
+) -> Result<ConnectInProgress, TransferError> {
+    let socket = connect_addr(addr)?;
+
+    let handshake = Handshake::start(&mut UnixTransport::new(socket.as_fd()), vconfig, options)?;
+
+    Ok(ConnectInProgress {
+        socket,
+        handshake: Some(handshake),
+        deadline: options.deadline(),
+    })
+}
+
 pub fn client_connect_fd(
     socket: RawFd,
     vconfig: VectorConfig,
 ) -> Result<ChannelVector, TransferError> {
-    let rsc = VectorResource::allocate(&vconfig)?;
+    client_connect_fd_with(socket, vconfig, &ConnectOptions::default())
+}
+
+/// Receives the vector defined by the server for a query or a resumed session and
+/// acknowledges it once the shared memory is initialized, returns the vector
+/// and whether we own the shared memory.
+fn receive_vector<T: Transport>(
+    transport: &mut T,
+    auth: &Authenticator,
+    request: &[u8],
+    deadline: Option<Instant>,
+) -> Result<(VectorResource, VectorConfig, bool), TransferError> {
+    let (vconfig, layout, owner, pool_offset, file_backed) =
+        match receive_response(transport, auth, request, deadline)? {
+            Response::Vector {
+                vconfig,
+                layout,
+                owner,
+                pool_offset,
+                file_backed,
+                ..
+            } => (vconfig, layout, owner, pool_offset, file_backed),
+            Response::Rejected(rejection) => return Err(TransferError::Rejected(rejection)),
+            _ => return Err(TransferError::ResponseError),
+        };
+
+    let fds = transport.recv_fds(vconfig.count_fds())?;
+
+    let rsc = VectorResource::from_config(&vconfig, layout, fds, pool_offset, file_backed, 0)?;
+
+    Ok((rsc, vconfig, owner))
+}
+
+/// Acknowledges the vector received for request.
+fn send_ack<T: Transport>(
+    transport: &mut T,
+    auth: &Authenticator,
+    request: &[u8],
+    version: u16,
+) -> Result<(), TransferError> {
+    let ack = Response::Accepted {
+        info: Vec::with_capacity(0),
+        payload: Vec::with_capacity(0),
+        token: 0,
+    };
+
+    transport.send_request(
+        &auth.sign_reply(create_response(&ack, version)?, request),
+        &[],
+    )
+}
+
+/// Connects with a vector defined by the server over another control plane,
+/// like client_connect_info_fd. The vector has no control connection.
+pub fn client_connect_info_transport<T: Transport>(
+    transport: &mut T,
+    info: &[u8],
+    options: &ConnectOptions,
+) -> Result<ChannelVector, TransferError> {
+    let auth = options.authenticator();
+    let deadline = options.deadline();
 
-    let (req_msg, fds) = rsc.serialize();
+    let request = auth.sign_request(create_query(info, options.layout()?)?)?;
 
-    let req = UnixMessageTx::new(req_msg, fds);
+    transport.send_request(&request, &[])?;
 
-    req.send(socket)?;
+    let (mut rsc, vconfig, _) = receive_vector(transport, &auth, &request, deadline)?;
 
-    let response = UnixMessageRx::receive(socket.as_raw_fd())?;
+    rsc.map = options.map_options();
 
-    parse_response(response.content().as_slice())?;
+    let mut vec = ChannelVector::new(rsc)?;
 
-    let vec = ChannelVector::new(rsc)?;
+    vec.set_server_info(vconfig.info);
+
+    send_ack(transport, &auth, &request, options.version()?)?;
 
     Ok(vec)
 }
 
-pub fn client_connect<P: ?Sized + NixPath>(
-    path: &P,
-    vconfig: VectorConfig,
+/// Connects with a vector defined by the server, info is handed to the server's define callback.
+pub fn client_connect_info_fd(
+    socket: RawFd,
+    info: &[u8],
+    options: &ConnectOptions,
 ) -> Result<ChannelVector, TransferError> {
-    let socket = socket(
-        AddressFamily::Unix,
-        SockType::SeqPacket,
-        SockFlag::empty(),
-        None,
-    )?;
+    let mut transport = UnixTransport::new(unsafe { BorrowedFd::borrow_raw(socket) });
 
-    let addr = UnixAddr::new(path)?;
+    let mut vec = client_connect_info_transport(&mut transport, info, options)?;
 
-    connect(socket.as_raw_fd(), &addr)?;
+    vec.set_control(control(
+        socket,
+        options.authenticator(),
+        options.version()?,
+    )?);
+
+    Ok(vec)
+}
+
+/// Reconnects to the vector of a session, token is the session token of the
+/// previous vector. The returned vector maps the same shared memory,
+/// its channels continue where the previous vector stopped.
+pub fn client_resume_fd(
+    socket: RawFd,
+    token: u64,
+    options: &ConnectOptions,
+) -> Result<ChannelVector, TransferError> {
+    let auth = options.authenticator();
+    let deadline = options.deadline();
+
+    let mut transport = UnixTransport::new(unsafe { BorrowedFd::borrow_raw(socket) });
+
+    let version = options.version()?;
+
+    let request = auth.sign_request(create_resume(token, version)?)?;
 
-    let rsc = VectorResource::allocate(&vconfig)?;
+    transport.send_request(&request, &[])?;
 
-    let (req_msg, fds) = rsc.serialize();
+    let (mut rsc, vconfig, owner) = receive_vector(&mut transport, &auth, &request, deadline)?;
 
-    let req = UnixMessageTx::new(req_msg, fds);
+    rsc.owner = owner;
+    rsc.resumed = true;
+    rsc.map = options.map_options();
 
-    req.send(socket.as_raw_fd())?;
+    let mut vec = ChannelVector::new(rsc)?;
 
-    let response = UnixMessageRx::receive(socket.as_raw_fd())?;
+    vec.set_server_info(vconfig.info);
+    vec.set_session_token(token);
 
-    parse_response(response.content().as_slice())?;
+    send_ack(&mut transport, &auth, &request, version)?;
 
-    let vec = ChannelVector::new(rsc)?;
+    vec.set_control(control(socket, auth, version)?);
 
     Ok(vec)
 }
 
+pub fn client_resume<A: ?Sized + ToUnixAddr>(
+    addr: &A,
+    token: u64,
+    options: &ConnectOptions,
+) -> Result<ChannelVector, TransferError> {
+    let socket = connect_addr(addr)?;
+
+    client_resume_fd(socket.as_raw_fd(), token, options)
+}
+
+fn connect_addr<A: ?Sized + ToUnixAddr>(addr: &A) -> Result<OwnedFd, Errno> {
+    let socket = message_socket(false)?;
+
+    let addr = addr.to_unix_addr()?;
+
+    connect(socket.as_raw_fd(), &addr)?;
+
+    Ok(socket)
+}
+
+pub fn client_connect_with<A: ?Sized + ToUnixAddr>(
+    addr: &A,
+    vconfig: VectorConfig,
+    options: &ConnectOptions,
+) -> Result<ChannelVector, TransferError> {
+    let socket = connect_addr(addr)?;
+
+    client_connect_fd_with(socket.as_raw_fd(), vconfig, options)
+}
+
+pub fn client_connect<A: ?Sized + ToUnixAddr>(
+    addr: &A,
+    vconfig: VectorConfig,
+) -> Result<ChannelVector, TransferError> {
+    client_connect_with(addr, vconfig, &ConnectOptions::default())
+}
+
+pub fn client_connect_info<A: ?Sized + ToUnixAddr>(
+    addr: &A,
+    info: &[u8],
+    options: &ConnectOptions,
+) -> Result<ChannelVector, TransferError> {
+    let socket = connect_addr(addr)?;
+
+    client_connect_info_fd(socket.as_raw_fd(), info, options)
+}
+
+impl AsFd for Server {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.sockfd.as_fd()
+    }
+}
+
 impl Drop for Server {
     fn drop(&mut self) {
-        if let Some(path) = self.addr.path() {
+        if self.unlink
+            && let Some(path) = self.addr.path()
+        {
             let _ = unlink(path);
         }
     }
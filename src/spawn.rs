@@ -0,0 +1,163 @@
+use std::env;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command};
+
+use nix::errno::Errno;
+use nix::fcntl::{FcntlArg, FdFlag, fcntl};
+use nix::sys::socket::{getsockopt, sockopt};
+
+use crate::channel::ChannelVector;
+use crate::control::Control;
+use crate::error::*;
+use crate::protocol::{Response, create_response, parse_request, parse_response};
+use crate::resource::VectorResource;
+use crate::shm::ShmBacking;
+use crate::socket::{ConnectOptions, client_connect_info_fd, query_layout};
+use crate::trace::*;
+use crate::transport::{Transport, UnixTransport};
+use crate::unix::io_errno;
+use crate::unix_message::{MESSAGE_SOCKET, message_socketpair};
+use crate::{Layout, ServerLimits, VectorConfig};
+
+/// Environment variable holding the number of the socket inherited by the child.
+pub const INHERITED_FD_VAR: &str = "RTIPC_FD";
+
+fn handshake(
+    socket: BorrowedFd<'_>,
+    vconfig: &VectorConfig,
+    options: &ConnectOptions,
+) -> Result<(ChannelVector, u16), TransferError> {
+    let auth = options.authenticator();
+    let deadline = options.deadline();
+
+    let mut transport = UnixTransport::new(socket);
+
+    let req = transport.recv_request(deadline)?;
+
+    let request = parse_request(auth.verify_request(&req)?, &ServerLimits::default())?;
+
+    if !request.query {
+        error!("child defines its own vector");
+        return Err(TransferError::ResponseError);
+    }
+
+    let layout = Layout {
+        descriptors: request.descriptors,
+        ..query_layout(request.cacheline_size, request.index_size)
+    };
+
+    let mut rsc = VectorResource::allocate_backed(vconfig, layout, &options.backing)?;
+
+    rsc.map = options.map_options();
+
+    let response = Response::Vector {
+        vconfig: rsc.get_config(),
+        layout,
+        token: 0,
+        owner: false,
+        pool_offset: None,
+        file_backed: rsc.file_backed,
+    };
+
+    transport.send_response(
+        &auth.sign_reply(create_response(&response, request.version)?, &req),
+        &rsc.collect_fds(),
+    )?;
+
+    /* the child initializes the shared memory before it acknowledges */
+    let ack = transport.recv_request(deadline)?;
+
+    match parse_response(auth.verify_reply(&ack, &req)?)? {
+        Response::Accepted { .. } => {
+            rsc.link_backing()?;
+            Ok((ChannelVector::new(rsc)?, request.version))
+        }
+        _ => Err(TransferError::ResponseError),
+    }
+}
+
+/// Spawns command with one end of a socketpair inherited, its number is passed in
+/// INHERITED_FD_VAR. The child picks up the vector with client_connect_inherited,
+/// vconfig is the vector from the parent's point of view. The child is killed if the
+/// handshake fails, the timeout of options applies to the whole handshake.
+pub fn spawn_with_vector(
+    command: &mut Command,
+    vconfig: VectorConfig,
+    options: &ConnectOptions,
+) -> Result<(ChannelVector, Child), TransferError> {
+    vconfig.validate(usize::MAX)?;
+
+    if options.backing == ShmBacking::Named {
+        error!("the child inherits a socket passing fds, the shared memory isn't named");
+        return Err(Errno::EOPNOTSUPP.into());
+    }
+
+    let (socket, inherited) = message_socketpair()?;
+
+    let fd = inherited.as_raw_fd();
+
+    command.env(INHERITED_FD_VAR, fd.to_string());
+
+    /* clearing close-on-exec only in the child doesn't leak the fd
+     * to processes spawned concurrently by other threads */
+    unsafe {
+        command.pre_exec(move || {
+            fcntl(
+                BorrowedFd::borrow_raw(fd),
+                FcntlArg::F_SETFD(FdFlag::empty()),
+            )
+            .map(|_| ())
+            .map_err(|e| e.into())
+        });
+    }
+
+    let mut child = command.spawn().map_err(io_errno)?;
+
+    drop(inherited);
+
+    match handshake(socket.as_fd(), &vconfig, options) {
+        Ok((mut vec, version)) => {
+            info!("child {} connected", child.id());
+            vec.set_control(Control::new(socket, options.authenticator(), version));
+            Ok((vec, child))
+        }
+        Err(e) => {
+            error!("handshake with child {} failed {e:?}", child.id());
+            let _ = child.kill();
+            let _ = child.wait();
+            Err(e)
+        }
+    }
+}
+
+/// Takes the socket inherited from the parent, must only be called once per process.
+fn take_inherited() -> Result<OwnedFd, Errno> {
+    let fd: RawFd = env::var(INHERITED_FD_VAR)
+        .ok()
+        .and_then(|var| var.parse().ok())
+        .filter(|fd| *fd >= 0)
+        .ok_or_else(|| {
+            error!("{INHERITED_FD_VAR} not set");
+            Errno::EBADF
+        })?;
+
+    let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+
+    if getsockopt(&borrowed, sockopt::SockType)? != MESSAGE_SOCKET {
+        error!("inherited fd {fd} isn't a {MESSAGE_SOCKET:?} socket");
+        return Err(Errno::ENOTSOCK);
+    }
+
+    /* not passed on to the processes spawned by the child */
+    fcntl(borrowed, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Child side of spawn_with_vector, returns the vector defined by the parent.
+pub fn client_connect_inherited(options: &ConnectOptions) -> Result<ChannelVector, TransferError> {
+    let socket = take_inherited()?;
+
+    client_connect_info_fd(socket.as_raw_fd(), &[], options)
+}
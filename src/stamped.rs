@@ -0,0 +1,108 @@
+use std::os::fd::BorrowedFd;
+
+use crate::heartbeat::Heartbeat;
+use crate::queue::spin_pop;
+use crate::{ChannelVector, Consumer, ForcePushResult, PopResult, Producer, TryPushResult};
+
+/// Message with the time it was pushed, the message type of the channels of
+/// take_stamped_producer and take_stamped_consumer. The message size of the channel
+/// has to hold the stamp as well, e.g. size_of::<Stamped<T>>.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Stamped<T: Copy> {
+    /// CLOCK_MONOTONIC in nanoseconds, set by the producer right before the push
+    pub sent: u64,
+    pub msg: T,
+}
+
+/// Producer stamping every message with the time of the push.
+pub struct StampedProducer<T: Copy> {
+    producer: Producer<Stamped<T>>,
+}
+
+impl<T: Copy> StampedProducer<T> {
+    pub fn current_message(&mut self) -> &mut T {
+        &mut self.producer.current_message().msg
+    }
+
+    pub fn force_push(&mut self) -> ForcePushResult {
+        self.stamp();
+        self.producer.force_push()
+    }
+
+    pub fn try_push(&mut self) -> TryPushResult {
+        self.stamp();
+        self.producer.try_push()
+    }
+
+    pub fn eventfd(&self) -> Option<BorrowedFd<'_>> {
+        self.producer.eventfd()
+    }
+
+    fn stamp(&mut self) {
+        self.producer.current_message().sent = Heartbeat::now();
+    }
+}
+
+/// Consumer of stamped messages, the latency of every popped message goes into
+/// the latency histogram of the channel, see ChannelMetrics::latency.
+pub struct StampedConsumer<T: Copy> {
+    consumer: Consumer<Stamped<T>>,
+}
+
+impl<T: Copy> StampedConsumer<T> {
+    pub fn current_message(&self) -> Option<&T> {
+        self.consumer.current_message().map(|stamped| &stamped.msg)
+    }
+
+    /// Time the current message was pushed, CLOCK_MONOTONIC in nanoseconds.
+    pub fn sent(&self) -> Option<u64> {
+        self.consumer.current_message().map(|stamped| stamped.sent)
+    }
+
+    pub fn pop(&mut self) -> PopResult {
+        let result = self.consumer.pop();
+        self.received(result)
+    }
+
+    /// See Consumer::pop_spin.
+    pub fn pop_spin(&mut self, polls: u32) -> PopResult {
+        spin_pop(polls, || self.pop())
+    }
+
+    /// Only the latency of the latest message is recorded.
+    pub fn flush(&mut self) -> PopResult {
+        let result = self.consumer.flush();
+        self.received(result)
+    }
+
+    pub fn eventfd(&self) -> Option<BorrowedFd<'_>> {
+        self.consumer.eventfd()
+    }
+
+    fn received(&self, result: PopResult) -> PopResult {
+        if matches!(
+            result,
+            PopResult::Success | PopResult::SuccessMessagesDiscarded
+        ) && let Some(sent) = self.sent()
+        {
+            let latency = Heartbeat::now().saturating_sub(sent);
+            self.consumer.counters().latency(latency);
+        }
+        result
+    }
+}
+
+impl ChannelVector {
+    pub fn take_stamped_producer<T: Copy>(&mut self, index: usize) -> Option<StampedProducer<T>> {
+        let producer = self.take_producer::<Stamped<T>>(index)?;
+        Some(StampedProducer { producer })
+    }
+
+    /// Takes a consumer of stamped messages and enables the latency histogram of the channel.
+    pub fn take_stamped_consumer<T: Copy>(&mut self, index: usize) -> Option<StampedConsumer<T>> {
+        let consumer = self.take_consumer::<Stamped<T>>(index)?;
+        consumer.counters().enable_latency();
+        Some(StampedConsumer { consumer })
+    }
+}
@@ -0,0 +1,190 @@
+use std::num::NonZeroUsize;
+use std::os::fd::{AsFd, OwnedFd};
+use std::ptr::{NonNull, null_mut};
+
+use nix::{
+    Result,
+    errno::Errno,
+    libc::{self, c_void},
+    sys::{
+        eventfd::EventFd,
+        mman::{MapFlags, ProtFlags},
+    },
+};
+use rustix::{
+    event::{EventfdFlags, eventfd},
+    fs::{MemfdFlags, SealFlags, fcntl_add_seals, ftruncate, memfd_create},
+};
+
+#[cfg(feature = "socket")]
+use std::{
+    io::{IoSlice, IoSliceMut},
+    mem::MaybeUninit,
+    os::fd::{BorrowedFd, RawFd},
+};
+
+#[cfg(feature = "socket")]
+use rustix::{
+    cmsg_space,
+    net::{
+        RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, ReturnFlags, SendAncillaryBuffer,
+        SendAncillaryMessage, SendFlags, recvmsg, sendmsg,
+    },
+};
+
+use crate::trace::*;
+#[cfg(feature = "socket")]
+use crate::unix_message::MAX_FD;
+
+fn errno(e: rustix::io::Errno) -> Errno {
+    Errno::from_raw(e.raw_os_error())
+}
+
+/// Restarts a call interrupted by a signal, like restart_on_eintr of the nix backend.
+#[cfg(feature = "socket")]
+fn restart_on_eintr<T>(mut call: impl FnMut() -> rustix::io::Result<T>) -> Result<T> {
+    loop {
+        match call() {
+            Err(rustix::io::Errno::INTR) => continue,
+            result => return result.map_err(errno),
+        }
+    }
+}
+
+pub fn shmfd_create(size: NonZeroUsize) -> Result<OwnedFd> {
+    let fd = memfd_create("rtipc", MemfdFlags::ALLOW_SEALING).map_err(errno)?;
+    ftruncate(&fd, size.get() as u64).map_err(errno)?;
+    fcntl_add_seals(&fd, SealFlags::GROW | SealFlags::SHRINK | SealFlags::SEAL).map_err(errno)?;
+    Ok(fd)
+}
+
+pub(crate) fn eventfd_create() -> Result<EventFd> {
+    let fd = eventfd(
+        0,
+        EventfdFlags::CLOEXEC | EventfdFlags::SEMAPHORE | EventfdFlags::NONBLOCK,
+    )
+    .map_err(|e| {
+        error!("eventfd failed {e:?}");
+        errno(e)
+    })?;
+
+    Ok(unsafe { EventFd::from_owned_fd(fd) })
+}
+
+/// Same as nix::sys::mman::mmap, the flags have the values of the kernel.
+pub(crate) unsafe fn mmap<F: AsFd>(
+    addr: Option<NonZeroUsize>,
+    length: NonZeroUsize,
+    prot: ProtFlags,
+    flags: MapFlags,
+    f: F,
+    offset: libc::off_t,
+) -> Result<NonNull<c_void>> {
+    let ptr = unsafe {
+        rustix::mm::mmap(
+            addr.map_or(null_mut(), |addr| addr.get() as *mut c_void),
+            length.get(),
+            rustix::mm::ProtFlags::from_bits_retain(prot.bits() as u32),
+            rustix::mm::MapFlags::from_bits_retain(flags.bits() as u32),
+            f,
+            u64::try_from(offset).map_err(|_| Errno::EINVAL)?,
+        )
+    }
+    .map_err(errno)?;
+
+    NonNull::new(ptr).ok_or(Errno::ENOMEM)
+}
+
+/// Same as nix::sys::mman::mmap_anonymous.
+pub(crate) unsafe fn mmap_anonymous(
+    addr: Option<NonZeroUsize>,
+    length: NonZeroUsize,
+    prot: ProtFlags,
+    flags: MapFlags,
+) -> Result<NonNull<c_void>> {
+    let ptr = unsafe {
+        rustix::mm::mmap_anonymous(
+            addr.map_or(null_mut(), |addr| addr.get() as *mut c_void),
+            length.get(),
+            rustix::mm::ProtFlags::from_bits_retain(prot.bits() as u32),
+            rustix::mm::MapFlags::from_bits_retain(flags.bits() as u32),
+        )
+    }
+    .map_err(errno)?;
+
+    NonNull::new(ptr).ok_or(Errno::ENOMEM)
+}
+
+/// Same as nix::sys::mman::munmap.
+pub(crate) unsafe fn munmap(addr: NonNull<c_void>, len: usize) -> Result<()> {
+    unsafe { rustix::mm::munmap(addr.as_ptr(), len) }.map_err(errno)
+}
+
+/// Sends iov with fds attached, restarted on EINTR. A peer that closed the socket
+/// results in EPIPE instead of SIGPIPE.
+#[cfg(feature = "socket")]
+pub(crate) fn send_fds(socket: RawFd, iov: &[IoSlice<'_>], fds: &[RawFd]) -> Result<usize> {
+    let socket = unsafe { BorrowedFd::borrow_raw(socket) };
+    let fds: Vec<BorrowedFd<'_>> = fds
+        .iter()
+        .map(|fd| unsafe { BorrowedFd::borrow_raw(*fd) })
+        .collect();
+
+    let mut space = [MaybeUninit::uninit(); cmsg_space!(ScmRights(MAX_FD))];
+
+    restart_on_eintr(|| {
+        let mut control = SendAncillaryBuffer::new(&mut space);
+
+        if !fds.is_empty() && !control.push(SendAncillaryMessage::ScmRights(&fds)) {
+            return Err(rustix::io::Errno::TOOMANYREFS);
+        }
+
+        sendmsg(socket, iov, &mut control, SendFlags::NOSIGNAL)
+    })
+}
+
+/// Receives into iov, returns the number of bytes and the fds attached to them.
+/// Fails with EMSGSIZE if the datagram or its fds didn't fit.
+#[cfg(feature = "socket")]
+pub(crate) fn recv_fds(socket: RawFd, iov: &mut [IoSliceMut<'_>]) -> Result<(usize, Vec<OwnedFd>)> {
+    let socket = unsafe { BorrowedFd::borrow_raw(socket) };
+    let mut space = [MaybeUninit::uninit(); cmsg_space!(ScmRights(MAX_FD))];
+    let mut control = RecvAncillaryBuffer::new(&mut space);
+
+    let msg = restart_on_eintr(|| recvmsg(socket, iov, &mut control, RecvFlags::CMSG_CLOEXEC))?;
+
+    let mut fds = Vec::new();
+
+    for cmsg in control.drain() {
+        if let RecvAncillaryMessage::ScmRights(received) = cmsg {
+            fds.extend(received);
+        }
+    }
+
+    if msg
+        .flags
+        .intersects(ReturnFlags::TRUNC | ReturnFlags::CTRUNC)
+    {
+        error!("received message truncated {:?}", msg.flags);
+        return Err(Errno::EMSGSIZE);
+    }
+
+    Ok((msg.bytes, fds))
+}
+
+/// Size of the next datagram, it stays queued.
+#[cfg(feature = "socket")]
+pub(crate) fn peek_size(socket: RawFd) -> Result<usize> {
+    let socket = unsafe { BorrowedFd::borrow_raw(socket) };
+    let mut control = RecvAncillaryBuffer::default();
+
+    restart_on_eintr(|| {
+        recvmsg(
+            socket,
+            &mut [],
+            &mut control,
+            RecvFlags::PEEK | RecvFlags::TRUNC,
+        )
+    })
+    .map(|msg| msg.bytes)
+}
@@ -0,0 +1,198 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+use nix::errno::Errno;
+
+use crate::auth::Authenticator;
+use crate::channel::ChannelVector;
+use crate::error::*;
+use crate::protocol::{
+    MAX_MESSAGE_SIZE, REQ_SHM_NAME, Response, create_named_request, create_response, parse_request,
+    parse_response, request_version,
+};
+use crate::resource::VectorResource;
+use crate::socket::ConnectOptions;
+use crate::trace::*;
+use crate::unix::{ShmName, io_errno, shm_named_open};
+use crate::{Layout, ServerLimits, VectorConfig};
+
+pub(crate) fn io_error(e: std::io::Error) -> TransferError {
+    match e.kind() {
+        /* the read timeout expired */
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+            error!("handshake timed out");
+            TransferError::TimedOut
+        }
+        _ => io_errno(e).into(),
+    }
+}
+
+/// Sends msg with its length as little endian u32, a stream has no message boundaries.
+pub(crate) fn send_frame<S: Write>(stream: &mut S, msg: &[u8]) -> Result<(), TransferError> {
+    let len = u32::try_from(msg.len()).map_err(|_| Errno::EMSGSIZE)?;
+
+    stream.write_all(&len.to_le_bytes()).map_err(io_error)?;
+    stream.write_all(msg).map_err(io_error)
+}
+
+pub(crate) fn receive_frame<S: Read>(stream: &mut S) -> Result<Vec<u8>, TransferError> {
+    let mut len = [0u8; size_of::<u32>()];
+
+    stream.read_exact(&mut len).map_err(io_error)?;
+
+    let len = u32::from_le_bytes(len) as usize;
+
+    if len > MAX_MESSAGE_SIZE {
+        error!("message too large {len}");
+        return Err(Errno::EMSGSIZE.into());
+    }
+
+    let mut msg = vec![0; len];
+
+    stream.read_exact(&mut msg).map_err(io_error)?;
+
+    Ok(msg)
+}
+
+/// Handshake over tcp for peers that can't share a unix socket directory but share
+/// /dev/shm, e.g. containers on the same host. The shared memory is passed by name,
+/// eventfds aren't supported and there's no control connection.
+/// Named shared memory can't be sealed, only serve trusted clients.
+pub struct TcpServer {
+    listener: TcpListener,
+    info: Vec<u8>,
+    auth: Authenticator,
+    limits: ServerLimits,
+}
+
+impl TcpServer {
+    pub fn new<A: ToSocketAddrs>(addr: A) -> Result<Self, Errno> {
+        let listener = TcpListener::bind(addr).map_err(io_errno)?;
+
+        Ok(Self {
+            listener,
+            info: Vec::with_capacity(0),
+            auth: Authenticator::default(),
+            limits: ServerLimits::default(),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Errno> {
+        self.listener.local_addr().map_err(io_errno)
+    }
+
+    /// Sets the vector-level info sent to every accepted client.
+    pub fn set_info(&mut self, info: Vec<u8>) {
+        self.info = info;
+    }
+
+    pub fn set_limits(&mut self, limits: ServerLimits) {
+        self.limits = limits;
+    }
+
+    pub fn set_cookie(&mut self, cookie: u32) {
+        self.auth.set_cookie(cookie);
+    }
+
+    #[cfg(feature = "hmac")]
+    pub fn set_key(&mut self, key: &[u8]) {
+        self.auth.set_key(Some(key.to_vec()));
+    }
+
+    fn handle_request(&self, req: &[u8]) -> Result<ChannelVector, TransferError> {
+        let request = parse_request(self.auth.verify_request(req)?, &self.limits)?;
+
+        let Some(name) = request.shm_name else {
+            error!("request without shared memory name");
+            return Err(RequestError::MissingRecord(REQ_SHM_NAME).into());
+        };
+
+        let layout = Layout {
+            cacheline_size: request.cacheline_size,
+            index_size: request.index_size,
+            descriptors: request.descriptors,
+        };
+
+        let shmfd = shm_named_open(&name)?;
+
+        let rsc = VectorResource::from_unsealed(&request.vconfig, layout, shmfd, None)?;
+
+        Ok(ChannelVector::new(rsc)?)
+    }
+
+    /// Accepts a single client, returns its vector and address.
+    pub fn accept(&self) -> Result<(ChannelVector, SocketAddr), TransferError> {
+        let (mut stream, addr) = self.listener.accept().map_err(io_error)?;
+
+        let req = receive_frame(&mut stream)?;
+
+        let result = self.handle_request(&req);
+
+        let response = match &result {
+            Ok(_) => {
+                info!("tcp client {addr} accepted");
+                Response::Accepted {
+                    info: self.info.clone(),
+                    payload: Vec::with_capacity(0),
+                    token: 0,
+                }
+            }
+            Err(e) => Response::Rejected(Rejection::new(Rejection::UNSPECIFIED, format!("{e:?}"))),
+        };
+
+        send_frame(
+            &mut stream,
+            &self
+                .auth
+                .sign_reply(create_response(&response, request_version(&req))?, &req),
+        )?;
+
+        Ok((result?, addr))
+    }
+}
+
+/// Connects to a TcpServer, the vector must not have eventfds.
+pub fn client_connect_tcp<A: ToSocketAddrs>(
+    addr: A,
+    vconfig: VectorConfig,
+    options: &ConnectOptions,
+) -> Result<ChannelVector, TransferError> {
+    vconfig.validate(usize::MAX)?;
+
+    if vconfig.count_fds() != 1 {
+        return Err(ConfigError::EventFdsUnsupported.into());
+    }
+
+    let auth = options.authenticator();
+    let layout = Layout::native();
+
+    let mut stream = TcpStream::connect(addr).map_err(io_error)?;
+
+    stream.set_read_timeout(options.timeout).map_err(io_error)?;
+
+    let (mut rsc, name) = VectorResource::allocate_named(&vconfig, layout)?;
+    rsc.map = options.map_options();
+    let name = ShmName(name);
+
+    let request = auth.sign_request(create_named_request(&vconfig, layout, &name.0)?)?;
+
+    send_frame(&mut stream, &request)?;
+
+    let msg = receive_frame(&mut stream)?;
+
+    let content = auth.verify_reply(&msg, &request).map_err(|e| {
+        error!("response verification failed {e:?}");
+        TransferError::ResponseError
+    })?;
+
+    match parse_response(content)? {
+        Response::Accepted { info, payload, .. } => {
+            let mut vec = ChannelVector::new(rsc)?;
+            vec.set_server_info(info);
+            vec.set_payload(payload);
+            Ok(vec)
+        }
+        Response::Rejected(rejection) => Err(TransferError::Rejected(rejection)),
+        _ => Err(TransferError::ResponseError),
+    }
+}
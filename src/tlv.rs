@@ -0,0 +1,168 @@
+use crate::error::*;
+
+/// Records with this flag must be understood by the receiver,
+/// all other unknown records are skipped.
+pub(crate) const FLAG_CRITICAL: u16 = 1;
+
+/* all integers on the wire are little endian */
+struct RecordHeader {
+    tag: u16,
+    flags: u16,
+    length: u32,
+}
+
+/// tag and flags as u16, length as u32
+const RECORD_HEADER_SIZE: usize = 8;
+
+pub(crate) struct TlvWriter {
+    buf: Vec<u8>,
+    /// length of the first value exceeding the u32 length of a record
+    oversized: Option<usize>,
+}
+
+impl TlvWriter {
+    pub(crate) fn new(buf: Vec<u8>) -> Self {
+        Self {
+            buf,
+            oversized: None,
+        }
+    }
+
+    /// A value exceeding the u32 length isn't written, finish fails instead.
+    pub(crate) fn put_bytes(&mut self, tag: u16, flags: u16, value: &[u8]) {
+        let Ok(length) = u32::try_from(value.len()) else {
+            self.oversized.get_or_insert(value.len());
+            return;
+        };
+
+        self.buf.extend_from_slice(&tag.to_le_bytes());
+        self.buf.extend_from_slice(&flags.to_le_bytes());
+        self.buf.extend_from_slice(&length.to_le_bytes());
+        self.buf.extend_from_slice(value);
+    }
+
+    pub(crate) fn put_u32(&mut self, tag: u16, flags: u16, value: u32) {
+        self.put_bytes(tag, flags, &value.to_le_bytes());
+    }
+
+    pub(crate) fn put_u64(&mut self, tag: u16, flags: u16, value: u64) {
+        self.put_bytes(tag, flags, &value.to_le_bytes());
+    }
+
+    pub(crate) fn put_nested<F>(&mut self, tag: u16, flags: u16, f: F)
+    where
+        F: FnOnce(&mut TlvWriter),
+    {
+        let mut nested = TlvWriter::new(Vec::new());
+        f(&mut nested);
+
+        match nested.oversized {
+            Some(size) => {
+                self.oversized.get_or_insert(size);
+            }
+            None => self.put_bytes(tag, flags, &nested.buf),
+        }
+    }
+
+    /// The records written, InfoTooLong if a value exceeded the u32 length of a record.
+    pub(crate) fn finish(self) -> Result<Vec<u8>, ConfigError> {
+        match self.oversized {
+            Some(size) => Err(ConfigError::InfoTooLong {
+                size,
+                max: u32::MAX as usize,
+            }),
+            None => Ok(self.buf),
+        }
+    }
+}
+
+pub(crate) struct Record<'a> {
+    pub tag: u16,
+    pub flags: u16,
+    pub value: &'a [u8],
+}
+
+impl<'a> Record<'a> {
+    pub(crate) fn critical(&self) -> bool {
+        self.flags & FLAG_CRITICAL != 0
+    }
+
+    pub(crate) fn u32(&self) -> Result<u32, RequestError> {
+        let bytes: [u8; 4] = self
+            .value
+            .try_into()
+            .map_err(|_| RequestError::MalformedRecord(self.tag))?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn u64(&self) -> Result<u64, RequestError> {
+        let bytes: [u8; 8] = self
+            .value
+            .try_into()
+            .map_err(|_| RequestError::MalformedRecord(self.tag))?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn nested(&self) -> TlvReader<'a> {
+        TlvReader::new(self.value)
+    }
+}
+
+pub(crate) struct TlvReader<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> TlvReader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self { buf, offset: 0 }
+    }
+
+    fn read_header(&self) -> Result<RecordHeader, RequestError> {
+        let bytes = self
+            .buf
+            .get(self.offset..self.offset + RECORD_HEADER_SIZE)
+            .ok_or(RequestError::OutOfBounds)?;
+
+        Ok(RecordHeader {
+            tag: u16::from_le_bytes([bytes[0], bytes[1]]),
+            flags: u16::from_le_bytes([bytes[2], bytes[3]]),
+            length: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        })
+    }
+}
+
+impl<'a> Iterator for TlvReader<'a> {
+    type Item = Result<Record<'a>, RequestError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.buf.len() {
+            return None;
+        }
+
+        let header = match self.read_header() {
+            Ok(header) => header,
+            Err(e) => {
+                self.offset = self.buf.len();
+                return Some(Err(e));
+            }
+        };
+
+        let start = self.offset + RECORD_HEADER_SIZE;
+        /* a length beyond usize of a 32 bit peer is out of bounds as well */
+        let end = start.saturating_add(header.length as usize);
+
+        let Some(value) = self.buf.get(start..end) else {
+            self.offset = self.buf.len();
+            return Some(Err(RequestError::OutOfBounds));
+        };
+
+        self.offset = end;
+
+        Some(Ok(Record {
+            tag: header.tag,
+            flags: header.flags,
+            value,
+        }))
+    }
+}
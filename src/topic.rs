@@ -0,0 +1,397 @@
+use std::num::NonZeroUsize;
+use std::os::fd::BorrowedFd;
+
+use crate::channel::{ChannelVector, Consumer, Producer};
+use crate::error::*;
+use crate::queue::{ForcePushResult, PopResult, TryPushResult};
+use crate::{ChannelConfig, ChannelKind, QueueConfig, VectorConfig};
+
+/// What a publisher does with a sample if the subscriber falls behind by depth samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Reliability {
+    /// the sample replaces the oldest unread one (force_push), the publisher never waits
+    BestEffort,
+
+    /// the sample is refused (try_push), no published sample gets lost
+    #[default]
+    Reliable,
+}
+
+/// Quality of service of a topic, a subset of the DDS policies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TopicQos {
+    /// unread samples the queue of the topic holds, the queue holds at least 2
+    pub depth: usize,
+    pub reliability: Reliability,
+    /// the last published sample is delivered to a subscriber that attaches later,
+    /// and latest keeps returning it after it was read until a newer one arrives,
+    /// with_latched carries it over to the publisher of a new vector
+    pub latching: bool,
+    /// eventfd notification of the subscriber
+    pub eventfd: bool,
+}
+
+impl Default for TopicQos {
+    /// Keep the last 10 samples, reliable, volatile, like the ROS 2 default profile.
+    fn default() -> Self {
+        Self {
+            depth: 10,
+            reliability: Reliability::Reliable,
+            latching: false,
+            eventfd: false,
+        }
+    }
+}
+
+impl TopicQos {
+    fn channel<T: Copy>(&self, name: &str) -> Result<ChannelConfig, ConfigError> {
+        let message_size = NonZeroUsize::new(size_of::<T>()).ok_or(ConfigError::InvalidTopic)?;
+
+        if self.depth == 0 || name.is_empty() {
+            return Err(ConfigError::InvalidTopic);
+        }
+
+        Ok(ChannelConfig {
+            queue: QueueConfig {
+                /* a queue holds additional_messages + 2 unread messages */
+                additional_messages: self.depth.saturating_sub(2),
+                message_size,
+                info: name.as_bytes().to_vec(),
+                schema: None,
+            },
+            kind: ChannelKind::Queue,
+            eventfd: self.eventfd,
+        })
+    }
+}
+
+/// Builds the config of a vector from named topics, the name of a topic is the info
+/// of its channel. The peer finds a topic by name with take_publisher and
+/// take_subscription, its subscriptions are the publications of this side.
+#[derive(Clone, Default)]
+pub struct Topics {
+    producers: Vec<ChannelConfig>,
+    consumers: Vec<ChannelConfig>,
+}
+
+impl Topics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Topic this side publishes samples of type T to.
+    pub fn publish<T: Copy>(mut self, name: &str, qos: TopicQos) -> Result<Self, ConfigError> {
+        self.add(name, qos.channel::<T>(name)?, false)?;
+        Ok(self)
+    }
+
+    /// Topic this side subscribes to, the peer publishes samples of type T.
+    pub fn subscribe<T: Copy>(mut self, name: &str, qos: TopicQos) -> Result<Self, ConfigError> {
+        self.add(name, qos.channel::<T>(name)?, true)?;
+        Ok(self)
+    }
+
+    fn add(
+        &mut self,
+        name: &str,
+        channel: ChannelConfig,
+        consumer: bool,
+    ) -> Result<(), ConfigError> {
+        if self
+            .producers
+            .iter()
+            .chain(&self.consumers)
+            .any(|c| c.queue.info == name.as_bytes())
+        {
+            return Err(ConfigError::InvalidTopic);
+        }
+
+        if consumer {
+            self.consumers.push(channel);
+        } else {
+            self.producers.push(channel);
+        }
+
+        Ok(())
+    }
+
+    pub fn into_config(self) -> VectorConfig {
+        VectorConfig {
+            producers: self.producers,
+            consumers: self.consumers,
+            info: Vec::new(),
+            arena: None,
+            heartbeat: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PublishResult {
+    /// An invalid index was written to shared memory (unrecoverable error).
+    QueueError,
+
+    Published,
+
+    /// Best effort topic with depth unread samples, the oldest one was discarded.
+    OldestDiscarded,
+
+    /// Reliable topic with depth unread samples, the sample was not published.
+    QueueFull,
+}
+
+/// Publishes samples of type T to a named topic.
+pub struct Publisher<T: Copy> {
+    producer: Producer<T>,
+    qos: TopicQos,
+    latched: Option<T>,
+}
+
+impl<T: Copy> Publisher<T> {
+    pub fn publish(&mut self, sample: &T) -> PublishResult {
+        *self.producer.current_message() = *sample;
+
+        let result = match self.qos.reliability {
+            Reliability::BestEffort => match self.producer.force_push() {
+                ForcePushResult::Success => PublishResult::Published,
+                ForcePushResult::SuccessMessageDiscarded => PublishResult::OldestDiscarded,
+                ForcePushResult::QueueError => PublishResult::QueueError,
+            },
+            Reliability::Reliable => match self.producer.try_push() {
+                TryPushResult::Success => PublishResult::Published,
+                TryPushResult::QueueFull => PublishResult::QueueFull,
+                TryPushResult::QueueError => PublishResult::QueueError,
+            },
+        };
+
+        if self.qos.latching
+            && matches!(
+                result,
+                PublishResult::Published | PublishResult::OldestDiscarded
+            )
+        {
+            self.latched = Some(*sample);
+        }
+
+        result
+    }
+
+    /// Last published sample of a latching topic.
+    pub fn latched(&self) -> Option<&T> {
+        self.latched.as_ref()
+    }
+
+    /// Publishes the latched sample of the topic, e.g. of the publisher of an earlier
+    /// vector, so a subscriber connecting later starts with the last sample.
+    /// Publishers of topics without latching ignore it.
+    pub fn with_latched(mut self, latched: Option<&T>) -> Self {
+        if self.qos.latching
+            && let Some(sample) = latched
+        {
+            self.publish(sample);
+        }
+
+        self
+    }
+
+    pub fn qos(&self) -> &TopicQos {
+        &self.qos
+    }
+
+    pub fn eventfd(&self) -> Option<BorrowedFd<'_>> {
+        self.producer.eventfd()
+    }
+}
+
+/// Receives the samples of type T of a named topic.
+pub struct Subscription<T: Copy> {
+    consumer: Consumer<T>,
+    qos: TopicQos,
+    /// a sample was popped, the slot of the consumer holds the last one
+    received: bool,
+}
+
+impl<T: Copy> Subscription<T> {
+    /// Next unread sample in publishing order.
+    /// Returns the result of pop if there was no unread sample.
+    pub fn take(&mut self) -> Result<T, PopResult> {
+        match self.consumer.pop() {
+            PopResult::Success | PopResult::SuccessMessagesDiscarded => self.received = true,
+            result => return Err(result),
+        }
+
+        self.consumer
+            .current_message()
+            .copied()
+            .ok_or(PopResult::QueueError)
+    }
+
+    /// Newest sample, the unread samples before it are skipped. Latching topics return
+    /// the last sample again until a newer one arrives, other topics only unread samples.
+    pub fn latest(&mut self) -> Result<T, PopResult> {
+        let mut latest = self.take();
+
+        while let Ok(sample) = self.take() {
+            latest = Ok(sample);
+        }
+
+        match latest {
+            Err(PopResult::NoMessage | PopResult::NoNewMessage)
+                if self.qos.latching && self.received =>
+            {
+                self.consumer
+                    .current_message()
+                    .copied()
+                    .ok_or(PopResult::QueueError)
+            }
+            result => result,
+        }
+    }
+
+    pub fn qos(&self) -> &TopicQos {
+        &self.qos
+    }
+
+    pub fn eventfd(&self) -> Option<BorrowedFd<'_>> {
+        self.consumer.eventfd()
+    }
+}
+
+impl ChannelVector {
+    /// Publisher of the topic name, a producer of this side or a consumer of the peer.
+    pub fn take_publisher<T: Copy>(&mut self, name: &str, qos: TopicQos) -> Option<Publisher<T>> {
+        let index = (0..)
+            .map_while(|i| self.producer_slot(i))
+            .position(|info| info == Some(name.as_bytes()))?;
+
+        Some(Publisher {
+            producer: self.take_producer(index)?,
+            qos,
+            latched: None,
+        })
+    }
+
+    /// Subscription to the topic name, a consumer of this side or a producer of the peer.
+    pub fn take_subscription<T: Copy>(
+        &mut self,
+        name: &str,
+        qos: TopicQos,
+    ) -> Option<Subscription<T>> {
+        let index = (0..)
+            .map_while(|i| self.consumer_slot(i))
+            .position(|info| info == Some(name.as_bytes()))?;
+
+        Some(Subscription {
+            consumer: self.take_consumer(index)?,
+            qos,
+            received: false,
+        })
+    }
+}
+
+/// Declares the topics of a vector as two structs, one for each peer, with a typed field
+/// per topic, instead of taking the channels by index:
+///
+/// ```text
+/// topics! {
+///     pub struct ClientTopics => ServerTopics {
+///         publish command: MsgCommand[depth = 1, eventfd],
+///         subscribe event: MsgEvent[depth = 10, best_effort, latching],
+///     }
+/// }
+/// ```
+///
+/// The field name is the topic name. ClientTopics publishes the topics marked publish and
+/// subscribes to those marked subscribe, ServerTopics the reverse. ClientTopics::config
+/// gives the config of the vector, take of both structs takes the channels of all topics
+/// from the vector of its peer. Options of a topic: depth = n, reliable, best_effort,
+/// latching and eventfd, see TopicQos.
+#[macro_export]
+macro_rules! topics {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $owner:ident => $peer:ident {
+            $($dir:ident $name:ident : $ty:ty [$($opt:ident $(= $val:expr)?),* $(,)?]),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $owner {
+            $(pub $name: $crate::topics!(@owner $dir $ty),)*
+        }
+
+        $(#[$meta])*
+        $vis struct $peer {
+            $(pub $name: $crate::topics!(@peer $dir $ty),)*
+        }
+
+        impl $owner {
+            pub fn config() -> ::core::result::Result<$crate::VectorConfig, $crate::ConfigError> {
+                let topics = $crate::Topics::new();
+                $(
+                    let topics = $crate::topics!(
+                        @add topics $dir $name $ty, $crate::topics!(@qos $($opt $(= $val)?),*)
+                    )?;
+                )*
+                ::core::result::Result::Ok(topics.into_config())
+            }
+
+            /// Takes the channels of all topics, None if one of them is missing.
+            pub fn take(vector: &mut $crate::ChannelVector) -> ::core::option::Option<Self> {
+                ::core::option::Option::Some(Self {
+                    $($name: $crate::topics!(
+                        @take_owner vector $dir $name $ty, $crate::topics!(@qos $($opt $(= $val)?),*)
+                    )?,)*
+                })
+            }
+        }
+
+        impl $peer {
+            /// Takes the channels of all topics, None if one of them is missing.
+            pub fn take(vector: &mut $crate::ChannelVector) -> ::core::option::Option<Self> {
+                ::core::option::Option::Some(Self {
+                    $($name: $crate::topics!(
+                        @take_peer vector $dir $name $ty, $crate::topics!(@qos $($opt $(= $val)?),*)
+                    )?,)*
+                })
+            }
+        }
+    };
+
+    (@owner publish $ty:ty) => { $crate::Publisher<$ty> };
+    (@owner subscribe $ty:ty) => { $crate::Subscription<$ty> };
+    (@peer publish $ty:ty) => { $crate::Subscription<$ty> };
+    (@peer subscribe $ty:ty) => { $crate::Publisher<$ty> };
+
+    (@add $topics:ident publish $name:ident $ty:ty, $qos:expr) => {
+        $topics.publish::<$ty>(::core::stringify!($name), $qos)
+    };
+    (@add $topics:ident subscribe $name:ident $ty:ty, $qos:expr) => {
+        $topics.subscribe::<$ty>(::core::stringify!($name), $qos)
+    };
+
+    (@take_owner $vector:ident publish $name:ident $ty:ty, $qos:expr) => {
+        $vector.take_publisher::<$ty>(::core::stringify!($name), $qos)
+    };
+    (@take_owner $vector:ident subscribe $name:ident $ty:ty, $qos:expr) => {
+        $vector.take_subscription::<$ty>(::core::stringify!($name), $qos)
+    };
+    (@take_peer $vector:ident publish $name:ident $ty:ty, $qos:expr) => {
+        $vector.take_subscription::<$ty>(::core::stringify!($name), $qos)
+    };
+    (@take_peer $vector:ident subscribe $name:ident $ty:ty, $qos:expr) => {
+        $vector.take_publisher::<$ty>(::core::stringify!($name), $qos)
+    };
+
+    (@qos $($opt:ident $(= $val:expr)?),*) => {{
+        #[allow(unused_mut)]
+        let mut qos = $crate::TopicQos::default();
+        $($crate::topics!(@opt qos $opt $(= $val)?);)*
+        qos
+    }};
+
+    (@opt $qos:ident depth = $val:expr) => { $qos.depth = $val };
+    (@opt $qos:ident reliable) => { $qos.reliability = $crate::Reliability::Reliable };
+    (@opt $qos:ident best_effort) => { $qos.reliability = $crate::Reliability::BestEffort };
+    (@opt $qos:ident latching) => { $qos.latching = true };
+    (@opt $qos:ident eventfd) => { $qos.eventfd = true };
+}
@@ -0,0 +1,9 @@
+//! Diagnostics of the crate: log records, or with the tracing feature tracing events,
+//! which join the spans of the application.
+
+#[cfg(not(feature = "tracing"))]
+#[allow(unused_imports)]
+pub(crate) use log::{debug, error, info, warn};
+#[cfg(feature = "tracing")]
+#[allow(unused_imports)]
+pub(crate) use tracing::{debug, error, info, warn};
@@ -0,0 +1,69 @@
+//! Static tracepoints of the queues, USDT probes of the provider rtipc for bpftrace
+//! and perf with the usdt feature, LTTng-UST tracepoints of the provider rtipc with
+//! the lttng feature and markers in the ftrace buffer with the ftrace feature, see
+//! ftrace::enable, e.g.
+//!
+//! ```text
+//! bpftrace -e 'usdt:./app:rtipc:discard { @[arg0] = count(); }'
+//! lttng enable-event --userspace 'rtipc:*'
+//! ```
+//!
+//! The USDT probes are nops and their arguments aren't evaluated unless a tracer is
+//! attached, the LTTng tracepoints cost a call and a branch while no session enables
+//! them. queue is the address of the queue in the shared memory of the process.
+//!
+//! | tracepoint    | arguments                            | fires                                   |
+//! |---------------|--------------------------------------|-----------------------------------------|
+//! | push          | queue, depth                         | a message was pushed                    |
+//! | pop           | queue, message, size                 | a message was popped                    |
+//! | discard       | queue                                | force_push discarded the oldest message |
+//! | overrun       | queue                                | pop found messages discarded            |
+//! | eventfd_write | queue, fd                            | the producer notified the consumer      |
+
+/// Fires the tracepoint with the arguments, a nop without the usdt, lttng and ftrace features.
+macro_rules! tracepoint {
+    ($name:ident $(, $arg:expr)* $(,)?) => {
+        #[cfg(feature = "usdt")]
+        ::probe::probe_lazy!(rtipc, $name $(, $arg)*);
+        #[cfg(feature = "lttng")]
+        $crate::tracepoint::lttng::$name($($arg as u64),*);
+        #[cfg(feature = "ftrace")]
+        if $crate::ftrace::is_enabled() {
+            $crate::ftrace::marker(::core::stringify!($name), &[$($arg as u64),*]);
+        }
+    };
+}
+
+pub(crate) use tracepoint;
+
+/// Tracepoints of the provider in lttng/rtipc_tp.h, built by build.rs.
+#[cfg(feature = "lttng")]
+pub(crate) mod lttng {
+    unsafe extern "C" {
+        fn rtipc_lttng_push(queue: u64, depth: u64);
+        fn rtipc_lttng_pop(queue: u64, message: u64, size: u64);
+        fn rtipc_lttng_discard(queue: u64);
+        fn rtipc_lttng_overrun(queue: u64);
+        fn rtipc_lttng_eventfd_write(queue: u64, fd: u64);
+    }
+
+    pub(crate) fn push(queue: u64, depth: u64) {
+        unsafe { rtipc_lttng_push(queue, depth) }
+    }
+
+    pub(crate) fn pop(queue: u64, message: u64, size: u64) {
+        unsafe { rtipc_lttng_pop(queue, message, size) }
+    }
+
+    pub(crate) fn discard(queue: u64) {
+        unsafe { rtipc_lttng_discard(queue) }
+    }
+
+    pub(crate) fn overrun(queue: u64) {
+        unsafe { rtipc_lttng_overrun(queue) }
+    }
+
+    pub(crate) fn eventfd_write(queue: u64, fd: u64) {
+        unsafe { rtipc_lttng_eventfd_write(queue, fd) }
+    }
+}
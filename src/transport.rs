@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd};
+use std::time::Instant;
+
+use nix::errno::Errno;
+
+use crate::error::*;
+use crate::socket::timed_out;
+use crate::unix_message::{
+    UnixMessageRx, UnixMessageTx, stream_receive, stream_receive_fds, stream_send,
+};
+
+/// Connection the handshake runs over, e.g. a unix socket, a D-Bus connection or a pipe
+/// pair. Messages are sent and received as a whole, the shared memory and the eventfds of
+/// a vector are passed along with them. A transport that can't pass fds fails with
+/// EOPNOTSUPP if fds isn't empty.
+pub trait Transport {
+    /// Sends a request of the client, the acknowledgement of a vector defined by the server
+    /// is sent as request as well.
+    fn send_request(&mut self, req: &[u8], fds: &[BorrowedFd<'_>]) -> Result<(), TransferError>;
+
+    /// Receives the next request or acknowledgement,
+    /// fails with TimedOut if none arrived before deadline.
+    fn recv_request(&mut self, deadline: Option<Instant>) -> Result<Vec<u8>, TransferError>;
+
+    /// Sends a response of the server.
+    fn send_response(&mut self, rsp: &[u8], fds: &[BorrowedFd<'_>]) -> Result<(), TransferError>;
+
+    /// Receives the next response, fails with TimedOut if none arrived before deadline.
+    fn recv_response(&mut self, deadline: Option<Instant>) -> Result<Vec<u8>, TransferError>;
+
+    /// Takes the fds passed with the last received message,
+    /// num_fds is the number announced by its content.
+    fn recv_fds(&mut self, num_fds: usize) -> Result<VecDeque<OwnedFd>, TransferError>;
+}
+
+/// Transport over a connected unix seqpacket socket, used by Server and the client_connect
+/// functions. Large messages are fragmented, fds exceeding a single message follow
+/// in continuation messages. On macOS the socket is a stream socket, the messages are
+/// framed like with StreamTransport.
+pub struct UnixTransport<'a> {
+    socket: BorrowedFd<'a>,
+    last: Option<UnixMessageRx>,
+}
+
+impl<'a> UnixTransport<'a> {
+    pub fn new(socket: BorrowedFd<'a>) -> Self {
+        Self { socket, last: None }
+    }
+
+    fn send(&self, msg: &[u8], fds: &[BorrowedFd<'_>]) -> Result<(), TransferError> {
+        UnixMessageTx::new(msg.to_vec(), fds.to_vec()).send(self.socket.as_raw_fd())?;
+        Ok(())
+    }
+
+    fn receive(&mut self, deadline: Option<Instant>) -> Result<Vec<u8>, TransferError> {
+        let msg =
+            UnixMessageRx::receive_until(self.socket.as_raw_fd(), deadline).map_err(timed_out)?;
+
+        let content = msg.content().clone();
+
+        self.last = Some(msg);
+
+        Ok(content)
+    }
+}
+
+impl Transport for UnixTransport<'_> {
+    fn send_request(&mut self, req: &[u8], fds: &[BorrowedFd<'_>]) -> Result<(), TransferError> {
+        self.send(req, fds)
+    }
+
+    fn recv_request(&mut self, deadline: Option<Instant>) -> Result<Vec<u8>, TransferError> {
+        self.receive(deadline)
+    }
+
+    fn send_response(&mut self, rsp: &[u8], fds: &[BorrowedFd<'_>]) -> Result<(), TransferError> {
+        self.send(rsp, fds)
+    }
+
+    fn recv_response(&mut self, deadline: Option<Instant>) -> Result<Vec<u8>, TransferError> {
+        self.receive(deadline)
+    }
+
+    fn recv_fds(&mut self, num_fds: usize) -> Result<VecDeque<OwnedFd>, TransferError> {
+        let msg = self.last.as_mut().ok_or(Errno::ENOMSG)?;
+
+        msg.receive_fds(self.socket.as_raw_fd(), num_fds)
+            .map_err(timed_out)?;
+
+        Ok(msg.take_fds())
+    }
+}
+
+/// Transport over a connected unix stream socket, e.g. a stream socketpair. Each message
+/// is framed by its length as little endian u32, fds exceeding a single frame follow in
+/// continuation frames. Use it with client_connect_transport and Server::accept_transport,
+/// vectors connected over it have no control connection.
+pub struct StreamTransport<'a> {
+    socket: BorrowedFd<'a>,
+    fds: Vec<OwnedFd>,
+    deadline: Option<Instant>,
+}
+
+impl<'a> StreamTransport<'a> {
+    pub fn new(socket: BorrowedFd<'a>) -> Self {
+        Self {
+            socket,
+            fds: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    fn send(&self, msg: &[u8], fds: &[BorrowedFd<'_>]) -> Result<(), TransferError> {
+        stream_send(self.socket.as_raw_fd(), msg, fds)?;
+        Ok(())
+    }
+
+    fn receive(&mut self, deadline: Option<Instant>) -> Result<Vec<u8>, TransferError> {
+        let (content, fds) =
+            stream_receive(self.socket.as_raw_fd(), deadline).map_err(timed_out)?;
+
+        self.fds = fds;
+        self.deadline = deadline;
+
+        Ok(content)
+    }
+}
+
+impl Transport for StreamTransport<'_> {
+    fn send_request(&mut self, req: &[u8], fds: &[BorrowedFd<'_>]) -> Result<(), TransferError> {
+        self.send(req, fds)
+    }
+
+    fn recv_request(&mut self, deadline: Option<Instant>) -> Result<Vec<u8>, TransferError> {
+        self.receive(deadline)
+    }
+
+    fn send_response(&mut self, rsp: &[u8], fds: &[BorrowedFd<'_>]) -> Result<(), TransferError> {
+        self.send(rsp, fds)
+    }
+
+    fn recv_response(&mut self, deadline: Option<Instant>) -> Result<Vec<u8>, TransferError> {
+        self.receive(deadline)
+    }
+
+    fn recv_fds(&mut self, num_fds: usize) -> Result<VecDeque<OwnedFd>, TransferError> {
+        /* the continuation frames are due within the deadline of their message */
+        stream_receive_fds(
+            self.socket.as_raw_fd(),
+            &mut self.fds,
+            num_fds,
+            self.deadline,
+        )
+        .map_err(timed_out)?;
+
+        Ok(self.fds.drain(..).collect())
+    }
+}
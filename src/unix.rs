@@ -1,31 +1,67 @@
-use std::collections::VecDeque;
-use std::io::{IoSlice, IoSliceMut};
 use std::num::NonZeroUsize;
-use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
-use std::os::unix::io::RawFd;
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::path::Path;
 
 use nix::{
     Result,
     errno::Errno,
-    fcntl::{F_ADD_SEALS, SealFlag, fcntl, readlink},
+    fcntl::{OFlag, open},
+    libc,
+    sys::stat::{Mode, fstat},
+    unistd::ftruncate,
+};
+
+#[cfg(not(target_os = "macos"))]
+use std::os::fd::AsRawFd;
+
+#[cfg(not(target_os = "macos"))]
+use nix::{
+    fcntl::{AT_FDCWD, AtFlags, F_GET_SEALS, FallocateFlags, SealFlag, fallocate, fcntl},
     sys::{
-        eventfd::{EfdFlags, EventFd},
-        memfd::{MFdFlags, memfd_create},
-        socket::{ControlMessage, ControlMessageOwned, MsgFlags, recvmsg, sendmsg},
+        eventfd::EventFd,
+        statfs::{FsType, HUGETLBFS_MAGIC, TMPFS_MAGIC, fstatfs},
     },
-    unistd::ftruncate,
+    unistd::{Whence, linkat, lseek, write},
 };
 
-use crate::log::*;
+#[cfg(not(any(target_os = "macos", feature = "rustix")))]
+use nix::sys::eventfd::EfdFlags;
+
+#[cfg(not(any(target_os = "android", target_os = "macos", feature = "rustix")))]
+use nix::{
+    fcntl::F_ADD_SEALS,
+    sys::memfd::{MFdFlags, memfd_create},
+};
+
+#[cfg(not(target_os = "android"))]
+use nix::sys::mman::{shm_open, shm_unlink};
+
+#[cfg(feature = "rustix")]
+pub use crate::sys_rustix::shmfd_create;
+
+use crate::trace::*;
 
-//from kernel header file net/scm.h: SCM_MAX_FD
-const MAX_FD: usize = 253;
+#[cfg(target_os = "android")]
+pub use crate::android::shmfd_create;
 
-const PROC_SELF_FD: &str = "/proc/self/fd/";
+#[cfg(target_os = "android")]
+pub(crate) use crate::android::{shm_named_create, shm_named_open, shm_named_unlink};
 
+#[cfg(target_os = "macos")]
+pub use crate::macos::shmfd_create;
+
+#[cfg(target_os = "macos")]
+pub(crate) use crate::macos::{
+    check_dmabuf, check_memfd, link_file, shm_preallocate, shm_tmpfile_create,
+};
+
+#[cfg(target_os = "macos")]
+pub(crate) use crate::fifo::{eventfd_create, into_eventfd};
+
+#[cfg(not(any(target_os = "android", target_os = "macos", feature = "rustix")))]
 pub fn shmfd_create(size: NonZeroUsize) -> Result<OwnedFd> {
     let fd: OwnedFd = memfd_create("rtipc", MFdFlags::MFD_ALLOW_SEALING)?;
-    ftruncate(&fd, size.get() as i64)?;
+    ftruncate(&fd, file_size(size)?)?;
     fcntl(
         &fd,
         F_ADD_SEALS(SealFlag::F_SEAL_GROW | SealFlag::F_SEAL_SHRINK | SealFlag::F_SEAL_SEAL),
@@ -33,6 +69,149 @@ pub fn shmfd_create(size: NonZeroUsize) -> Result<OwnedFd> {
     Ok(fd)
 }
 
+/// Shared memory backed by the regular file at path, the file keeps the messages
+/// after both peers unmapped it.
+pub(crate) fn shm_file_create(path: &Path, size: NonZeroUsize) -> Result<OwnedFd> {
+    let fd = open(
+        path,
+        OFlag::O_RDWR | OFlag::O_CREAT | OFlag::O_TRUNC | OFlag::O_CLOEXEC,
+        Mode::S_IRUSR | Mode::S_IWUSR,
+    )
+    .inspect_err(|e| error!("open {path:?} failed {e:?}"))?;
+
+    ftruncate(&fd, file_size(size)?)?;
+
+    Ok(fd)
+}
+
+/// Reserves the pages of [offset, offset + len), so the first write to a page can't
+/// fail with SIGBUS when the memory is exhausted. Works on sealed memfds, the size
+/// doesn't change.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn shm_preallocate(fd: BorrowedFd<'_>, offset: usize, len: NonZeroUsize) -> Result<()> {
+    let offset = libc::off_t::try_from(offset).map_err(|_| Errno::EINVAL)?;
+    let len = libc::off_t::try_from(len.get()).map_err(|_| Errno::EINVAL)?;
+
+    fallocate(fd, FallocateFlags::empty(), offset, len)
+        .inspect_err(|e| error!("fallocate failed {e:?}"))
+}
+
+/// Unnamed regular file in dir, see link_file.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn shm_tmpfile_create(dir: &Path, size: NonZeroUsize) -> Result<OwnedFd> {
+    let fd = open(
+        dir,
+        OFlag::O_RDWR | OFlag::O_TMPFILE | OFlag::O_CLOEXEC,
+        Mode::S_IRUSR | Mode::S_IWUSR,
+    )
+    .inspect_err(|e| error!("O_TMPFILE in {dir:?} failed {e:?}"))?;
+
+    ftruncate(&fd, file_size(size)?)?;
+
+    Ok(fd)
+}
+
+/// Gives the unnamed file of shm_tmpfile_create a name, fails with EEXIST if path exists.
+/// linkat with AT_EMPTY_PATH needs Linux 6.10 or CAP_DAC_READ_SEARCH, older kernels
+/// refuse it with ENOENT and the file is linked by its /proc link instead.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn link_file(fd: BorrowedFd<'_>, path: &Path) -> Result<()> {
+    let linked = match linkat(fd, "", AT_FDCWD, path, AtFlags::AT_EMPTY_PATH) {
+        Err(Errno::ENOENT) => {
+            info!("linkat with AT_EMPTY_PATH refused, linking by /proc");
+            let proc_path = format!("/proc/self/fd/{}", fd.as_raw_fd());
+            linkat(
+                AT_FDCWD,
+                proc_path.as_str(),
+                AT_FDCWD,
+                path,
+                AtFlags::AT_SYMLINK_FOLLOW,
+            )
+        }
+        linked => linked,
+    };
+
+    linked.inspect_err(|e| error!("linking shared memory to {path:?} failed {e:?}"))
+}
+
+/// Checks the shared memory of a vector backed by a file. A file can't be sealed,
+/// the peer has to be trusted not to truncate it.
+pub(crate) fn check_file(fd: BorrowedFd<'_>) -> Result<()> {
+    let stat = fstat(fd).inspect_err(|e| error!("fstat failed {e:?}"))?;
+
+    if stat.st_mode & libc::S_IFMT != libc::S_IFREG {
+        error!("shared memory is not a regular file");
+        return Err(Errno::EBADF);
+    }
+
+    Ok(())
+}
+
+/// Shared memory object in /dev/shm for a peer that can't receive fds,
+/// only accessible by our user.
+#[cfg(not(target_os = "android"))]
+pub(crate) fn shm_named_create(size: NonZeroUsize) -> Result<(OwnedFd, String)> {
+    let name = format!("/rtipc-{}-{:x}", std::process::id(), random_u64()?);
+
+    let fd = shm_open(
+        name.as_str(),
+        OFlag::O_RDWR | OFlag::O_CREAT | OFlag::O_EXCL | OFlag::O_CLOEXEC,
+        Mode::S_IRUSR | Mode::S_IWUSR,
+    )
+    .inspect_err(|e| error!("shm_open {name} failed {e:?}"))?;
+
+    if let Err(e) = file_size(size).and_then(|size| ftruncate(&fd, size)) {
+        shm_named_unlink(&name);
+        return Err(e);
+    }
+
+    Ok((fd, name))
+}
+
+/// Opens the shared memory object created by the peer, name has the form /name.
+#[cfg(not(target_os = "android"))]
+pub(crate) fn shm_named_open(name: &str) -> Result<OwnedFd> {
+    if name.len() < 2 || !name.starts_with('/') || name[1..].contains('/') {
+        error!("invalid shared memory name {name}");
+        return Err(Errno::EINVAL);
+    }
+
+    let fd = shm_open(name, OFlag::O_RDWR | OFlag::O_CLOEXEC, Mode::empty())
+        .inspect_err(|e| error!("shm_open {name} failed {e:?}"))?;
+
+    #[cfg(not(target_os = "macos"))]
+    if fs_type(fd.as_fd())? != TMPFS_MAGIC {
+        error!("{name} is not on tmpfs");
+        return Err(Errno::EBADF);
+    }
+
+    #[cfg(target_os = "macos")]
+    check_memfd(fd.as_fd())?;
+
+    Ok(fd)
+}
+
+#[cfg(not(target_os = "android"))]
+pub(crate) fn shm_named_unlink(name: &str) {
+    if let Err(e) = shm_unlink(name) {
+        error!("shm_unlink {name} failed {e:?}");
+    }
+}
+
+/// Removes the name of the shared memory object once the handshake is over,
+/// the server has opened it by then.
+pub(crate) struct ShmName(pub String);
+
+impl Drop for ShmName {
+    fn drop(&mut self) {
+        shm_named_unlink(&self.0);
+    }
+}
+
+#[cfg(feature = "rustix")]
+pub(crate) use crate::sys_rustix::eventfd_create;
+
+#[cfg(not(any(target_os = "macos", feature = "rustix")))]
 pub(crate) fn eventfd_create() -> Result<EventFd> {
     let evd = EventFd::from_flags(
         EfdFlags::EFD_CLOEXEC | EfdFlags::EFD_SEMAPHORE | EfdFlags::EFD_NONBLOCK,
@@ -41,24 +220,32 @@ pub(crate) fn eventfd_create() -> Result<EventFd> {
     Ok(evd)
 }
 
-fn fd_link(fd: RawFd) -> Result<String> {
-    let path = format!("{PROC_SELF_FD}{fd}");
-    let oslink = readlink(path.as_str()).inspect_err(|e| error!("readlink failed {e:?}"))?;
-    let link = oslink
-        .to_str()
-        .ok_or(Errno::EBADF)
-        .inspect_err(|_| error!("oslink.to_str failed"))?
-        .to_owned();
-    Ok(link)
+/// Filesystem of eventfds and other anonymous inodes, from linux/magic.h
+#[cfg(not(target_os = "macos"))]
+const ANON_INODE_FS_MAGIC: FsType = FsType(0x09041934);
+
+/// Filesystem of dma-bufs, from linux/magic.h
+#[cfg(not(target_os = "macos"))]
+const DMA_BUF_MAGIC: FsType = FsType(0x444d4142);
+
+#[cfg(not(target_os = "macos"))]
+fn fs_type(fd: BorrowedFd<'_>) -> Result<FsType> {
+    let stat = fstatfs(fd).inspect_err(|e| error!("fstatfs failed {e:?}"))?;
+    Ok(stat.filesystem_type())
 }
 
+/* the fds are checked without /proc, which isn't mounted in every sandbox */
+#[cfg(not(target_os = "macos"))]
 pub(crate) fn into_eventfd(fd: OwnedFd) -> Result<EventFd> {
-    let expected = "anon_inode:[eventfd";
-
-    let link = fd_link(fd.as_raw_fd())?;
+    if fs_type(fd.as_fd())? != ANON_INODE_FS_MAGIC {
+        error!("fd is not an anonymous inode");
+        return Err(Errno::EBADF);
+    }
 
-    if link.get(0..expected.len()).ok_or(Errno::EBADF)? != expected {
-        error!("link is not eventfd {link:?}");
+    /* adding 0 to the counter is a no-op, other anonymous inodes
+     * like timerfd, signalfd or epoll refuse the write */
+    if write(fd.as_fd(), &0u64.to_ne_bytes()) != Ok(size_of::<u64>()) {
+        error!("fd is not eventfd");
         return Err(Errno::EBADF);
     }
 
@@ -67,87 +254,103 @@ pub(crate) fn into_eventfd(fd: OwnedFd) -> Result<EventFd> {
     Ok(efd)
 }
 
+#[cfg(not(target_os = "macos"))]
 pub(crate) fn check_memfd(fd: BorrowedFd<'_>) -> Result<()> {
-    let expected = "/memfd:";
+    /* the peer can't resize an ashmem region we mapped, it needs no seals */
+    #[cfg(target_os = "android")]
+    if crate::android::ashmem_size(fd).is_some() {
+        return Ok(());
+    }
 
-    let link = fd_link(fd.as_raw_fd())?;
+    let fs = fs_type(fd)?;
 
-    if link.get(0..expected.len()).ok_or(Errno::EBADF)? != expected {
-        error!("link is not memfd {link:?}");
-        Err(Errno::EBADF)
-    } else {
-        Ok(())
+    /* memfds live on the internal shmem or hugetlbfs mount */
+    if fs != TMPFS_MAGIC && fs != HUGETLBFS_MAGIC {
+        error!("fd is not memfd {fs:?}");
+        return Err(Errno::EBADF);
     }
-}
 
-pub(crate) struct UnixMessageTx<'a> {
-    content: Vec<u8>,
-    fds: Vec<BorrowedFd<'a>>,
+    /* without these seals the peer could truncate the memfd under our mapping,
+     * F_GET_SEALS fails for tmpfs files that weren't created by memfd_create */
+    let required = SealFlag::F_SEAL_GROW | SealFlag::F_SEAL_SHRINK;
+
+    let seals = SealFlag::from_bits_truncate(
+        fcntl(fd, F_GET_SEALS).inspect_err(|e| error!("F_GET_SEALS failed {e:?}"))?,
+    );
+
+    if !seals.contains(required) {
+        error!("memfd not sealed {seals:?}");
+        return Err(Errno::EPERM);
+    }
+
+    Ok(())
 }
 
-impl<'a> UnixMessageTx<'a> {
-    pub(crate) fn new(content: Vec<u8>, fds: Vec<BorrowedFd<'a>>) -> Self {
-        Self { content, fds }
+/// Size of a dma-buf, fstat reports 0 for dma-bufs.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn check_dmabuf(fd: BorrowedFd<'_>) -> Result<NonZeroUsize> {
+    if fs_type(fd)? != DMA_BUF_MAGIC {
+        error!("fd is not a dma-buf");
+        return Err(Errno::EBADF);
     }
 
-    pub(crate) fn send(&self, socket: RawFd) -> Result<usize> {
-        let iov = [IoSlice::new(&self.content)];
-        let fds: Vec<RawFd> = self.fds.iter().map(|fd| fd.as_raw_fd()).collect();
+    let size = lseek(fd, 0, Whence::SeekEnd).inspect_err(|e| error!("lseek failed {e:?}"))?;
 
-        let cmsg: &[ControlMessage] = &[ControlMessage::ScmRights(fds.as_slice())];
+    usize::try_from(size)
+        .ok()
+        .and_then(NonZeroUsize::new)
+        .ok_or(Errno::EBADF)
+}
 
-        sendmsg::<()>(socket, &iov, cmsg, MsgFlags::empty(), None)
+pub(crate) fn fd_size(fd: BorrowedFd<'_>) -> Result<usize> {
+    #[cfg(target_os = "android")]
+    if let Some(size) = crate::android::ashmem_size(fd) {
+        return Ok(size.get());
     }
+
+    let stat = fstat(fd).inspect_err(|e| error!("fstat failed {e:?}"))?;
+    usize::try_from(stat.st_size).map_err(|_| Errno::EOVERFLOW)
 }
 
-pub(crate) struct UnixMessageRx {
-    content: Vec<u8>,
-    fds: Vec<OwnedFd>,
+/// off_t is 32 bit wide on 32 bit targets without large file support.
+fn file_size(size: NonZeroUsize) -> Result<libc::off_t> {
+    libc::off_t::try_from(size.get()).map_err(|_| Errno::EFBIG)
 }
 
-impl UnixMessageRx {
-    pub(crate) fn receive(socket: RawFd) -> Result<Self> {
-        let recv_empty = recvmsg::<()>(
-            socket,
-            &mut [] as &mut [IoSliceMut],
-            None,
-            MsgFlags::union(MsgFlags::MSG_PEEK, MsgFlags::MSG_TRUNC),
-        )?;
+pub(crate) fn io_errno(e: std::io::Error) -> Errno {
+    Errno::from_raw(e.raw_os_error().unwrap_or(Errno::EIO as i32))
+}
 
-        if recv_empty.bytes == 0 {
-            return Err(Errno::ENOMSG);
-        }
+/// Random value from the kernel's entropy pool, by getrandom(2).
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn random_u64() -> Result<u64> {
+    let mut buf = [0u8; 8];
+    let mut filled = 0;
 
-        let mut content: Vec<u8> = vec![0; recv_empty.bytes];
-        let mut iov = [IoSliceMut::new(content.as_mut_slice())];
-        let mut cmsg = cmsg_space!([RawFd; MAX_FD]);
-
-        let recv_data = recvmsg::<()>(
-            socket,
-            &mut iov,
-            Some(&mut cmsg),
-            MsgFlags::union(MsgFlags::MSG_PEEK, MsgFlags::MSG_TRUNC),
-        )?;
-
-        let fds = recv_data.cmsgs()?.next().map_or_else(
-            || Ok(Vec::with_capacity(0)),
-            |fds| match fds {
-                ControlMessageOwned::ScmRights(fds) => Ok(fds
-                    .iter()
-                    .map(|fd| unsafe { OwnedFd::from_raw_fd(*fd) })
-                    .collect()),
-                _ => Err(Errno::EBADMSG),
-            },
-        )?;
-
-        Ok(Self { content, fds })
-    }
+    while filled < buf.len() {
+        let rest = &mut buf[filled..];
+        let res = unsafe { libc::getrandom(rest.as_mut_ptr().cast(), rest.len(), 0) };
 
-    pub(crate) fn content(&self) -> &Vec<u8> {
-        &self.content
+        match Errno::result(res) {
+            Ok(n) => filled += n as usize,
+            Err(Errno::EINTR) => {}
+            Err(e) => {
+                error!("getrandom failed {e:?}");
+                return Err(e);
+            }
+        }
     }
 
-    pub(crate) fn take_fds(&mut self) -> VecDeque<OwnedFd> {
-        self.fds.drain(0..).collect()
-    }
+    Ok(u64::from_ne_bytes(buf))
+}
+
+/// Random value from the kernel's entropy pool, by getentropy(2).
+#[cfg(target_os = "macos")]
+pub(crate) fn random_u64() -> Result<u64> {
+    let mut buf = [0u8; 8];
+    let res = unsafe { libc::getentropy(buf.as_mut_ptr().cast(), buf.len()) };
+
+    Errno::result(res).inspect_err(|e| error!("getentropy failed {e:?}"))?;
+
+    Ok(u64::from_ne_bytes(buf))
 }
@@ -0,0 +1,470 @@
+use std::collections::VecDeque;
+use std::io::{IoSlice, IoSliceMut};
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::os::unix::io::RawFd;
+use std::time::Instant;
+
+use nix::{
+    Result,
+    errno::Errno,
+    poll::{PollFd, PollFlags, PollTimeout, poll},
+    sys::socket::{AddressFamily, SockFlag, SockType, accept, socket, socketpair},
+};
+
+#[cfg(not(feature = "rustix"))]
+use nix::sys::socket::{ControlMessage, ControlMessageOwned, MsgFlags, recvmsg, sendmsg};
+
+#[cfg(target_os = "macos")]
+use std::os::fd::AsFd;
+
+use crate::protocol::{
+    MAX_MESSAGE_SIZE, Reassembly, create_fd_continuation, is_fragment, parse_fd_continuation,
+    split_message,
+};
+#[cfg(feature = "rustix")]
+use crate::sys_rustix::{peek_size, recv_fds, send_fds};
+use crate::trace::*;
+
+//from kernel header file net/scm.h: SCM_MAX_FD
+pub(crate) const MAX_FD: usize = 253;
+
+/// Socket type of the handshake and the control connection. macOS has no unix seqpacket
+/// sockets, the messages are framed on a stream socket instead.
+#[cfg(not(target_os = "macos"))]
+pub(crate) const MESSAGE_SOCKET: SockType = SockType::SeqPacket;
+#[cfg(target_os = "macos")]
+pub(crate) const MESSAGE_SOCKET: SockType = SockType::Stream;
+
+#[cfg(not(any(target_os = "macos", feature = "rustix")))]
+const SEND_FLAGS: MsgFlags = MsgFlags::MSG_NOSIGNAL;
+#[cfg(target_os = "macos")]
+const SEND_FLAGS: MsgFlags = MsgFlags::empty();
+
+#[cfg(not(any(target_os = "macos", feature = "rustix")))]
+const RECV_FLAGS: MsgFlags = MsgFlags::MSG_CMSG_CLOEXEC;
+#[cfg(target_os = "macos")]
+const RECV_FLAGS: MsgFlags = MsgFlags::empty();
+
+/// Unix socket of MESSAGE_SOCKET, a peer that closed the socket results in EPIPE
+/// instead of SIGPIPE on every platform.
+pub(crate) fn message_socket(cloexec: bool) -> Result<OwnedFd> {
+    #[cfg(not(target_os = "macos"))]
+    let flags = if cloexec {
+        SockFlag::SOCK_CLOEXEC
+    } else {
+        SockFlag::empty()
+    };
+    #[cfg(target_os = "macos")]
+    let flags = SockFlag::empty();
+
+    let socket = socket(AddressFamily::Unix, MESSAGE_SOCKET, flags, None)?;
+
+    #[cfg(target_os = "macos")]
+    {
+        crate::macos::set_nosigpipe(socket.as_fd())?;
+        if cloexec {
+            crate::macos::set_cloexec(socket.as_fd())?;
+        }
+    }
+
+    Ok(socket)
+}
+
+/// Connected pair of MESSAGE_SOCKET, both with close-on-exec set.
+pub(crate) fn message_socketpair() -> Result<(OwnedFd, OwnedFd)> {
+    #[cfg(not(target_os = "macos"))]
+    let flags = SockFlag::SOCK_CLOEXEC;
+    #[cfg(target_os = "macos")]
+    let flags = SockFlag::empty();
+
+    let pair = socketpair(AddressFamily::Unix, MESSAGE_SOCKET, None, flags)?;
+
+    #[cfg(target_os = "macos")]
+    for socket in [&pair.0, &pair.1] {
+        crate::macos::set_nosigpipe(socket.as_fd())?;
+        crate::macos::set_cloexec(socket.as_fd())?;
+    }
+
+    Ok(pair)
+}
+
+/// Accepts a connection on a listening MESSAGE_SOCKET.
+pub(crate) fn accept_socket(listener: BorrowedFd<'_>) -> Result<OwnedFd> {
+    let socket = unsafe { OwnedFd::from_raw_fd(accept(listener.as_raw_fd())?) };
+
+    #[cfg(target_os = "macos")]
+    crate::macos::set_nosigpipe(socket.as_fd())?;
+
+    Ok(socket)
+}
+
+/// Takes ownership of a received fd, macOS can't set close-on-exec on receipt.
+#[cfg(not(feature = "rustix"))]
+fn received_fd(fd: RawFd) -> OwnedFd {
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    #[cfg(target_os = "macos")]
+    let _ = crate::macos::set_cloexec(fd.as_fd());
+
+    fd
+}
+
+pub(crate) struct UnixMessageTx<'a> {
+    content: Vec<u8>,
+    fds: Vec<BorrowedFd<'a>>,
+}
+
+impl<'a> UnixMessageTx<'a> {
+    pub(crate) fn new(content: Vec<u8>, fds: Vec<BorrowedFd<'a>>) -> Self {
+        Self { content, fds }
+    }
+
+    /// Sends the message, large messages are split into several datagrams.
+    /// Up to MAX_FD file descriptors are attached to the first datagram,
+    /// the remaining ones follow in continuation messages. On a stream
+    /// MESSAGE_SOCKET the message is sent as a single frame.
+    /// A peer that closed the socket results in EPIPE instead of SIGPIPE.
+    pub(crate) fn send(&self, socket: RawFd) -> Result<usize> {
+        if MESSAGE_SOCKET == SockType::Stream {
+            stream_send(socket, &self.content, &self.fds)?;
+            return Ok(self.content.len());
+        }
+
+        let fds: Vec<RawFd> = self.fds.iter().map(|fd| fd.as_raw_fd()).collect();
+        let mut fd_chunks = fds.chunks(MAX_FD);
+        let first_fds = fd_chunks.next().unwrap_or(&[]);
+        let mut sent = 0;
+
+        for (i, fragment) in split_message(&self.content).iter().enumerate() {
+            let fds = if i == 0 { first_fds } else { &[] };
+
+            sent += send_fds(socket, &[IoSlice::new(fragment)], fds)?;
+        }
+
+        for chunk in fd_chunks {
+            let content = create_fd_continuation(chunk.len());
+
+            send_fds(socket, &[IoSlice::new(&content)], chunk)?;
+        }
+
+        Ok(sent)
+    }
+}
+
+/// Restarts a call interrupted by a signal, e.g. a timer of the application,
+/// a handshake isn't aborted halfway through with EINTR.
+#[cfg(not(feature = "rustix"))]
+fn restart_on_eintr<T>(mut call: impl FnMut() -> Result<T>) -> Result<T> {
+    loop {
+        match call() {
+            Err(Errno::EINTR) => continue,
+            result => return result,
+        }
+    }
+}
+
+/// Sends iov with fds attached, restarted on EINTR.
+#[cfg(not(feature = "rustix"))]
+fn send_fds(socket: RawFd, iov: &[IoSlice<'_>], fds: &[RawFd]) -> Result<usize> {
+    let cmsg: &[ControlMessage] = if fds.is_empty() {
+        &[]
+    } else {
+        &[ControlMessage::ScmRights(fds)]
+    };
+
+    restart_on_eintr(|| sendmsg::<()>(socket, iov, cmsg, SEND_FLAGS, None))
+}
+
+/// Receives into iov, returns the number of bytes and the fds attached to them.
+/// Fails with EMSGSIZE if the datagram or its fds didn't fit.
+#[cfg(not(feature = "rustix"))]
+fn recv_fds(socket: RawFd, iov: &mut [IoSliceMut<'_>]) -> Result<(usize, Vec<OwnedFd>)> {
+    let mut cmsg = cmsg_space!([RawFd; MAX_FD]);
+
+    restart_on_eintr(|| {
+        let msg = recvmsg::<()>(socket, iov, Some(&mut cmsg), RECV_FLAGS)?;
+
+        let mut fds = Vec::new();
+
+        for cmsg in msg.cmsgs()? {
+            if let ControlMessageOwned::ScmRights(received) = cmsg {
+                fds.extend(received.iter().map(|fd| received_fd(*fd)));
+            }
+        }
+
+        if msg
+            .flags
+            .intersects(MsgFlags::MSG_TRUNC | MsgFlags::MSG_CTRUNC)
+        {
+            error!("received message truncated {:?}", msg.flags);
+            return Err(Errno::EMSGSIZE);
+        }
+
+        Ok((msg.bytes, fds))
+    })
+}
+
+/// Size of the next datagram, it stays queued.
+#[cfg(not(feature = "rustix"))]
+fn peek_size(socket: RawFd) -> Result<usize> {
+    restart_on_eintr(|| {
+        recvmsg::<()>(
+            socket,
+            &mut [] as &mut [IoSliceMut],
+            None,
+            MsgFlags::union(MsgFlags::MSG_PEEK, MsgFlags::MSG_TRUNC),
+        )
+        .map(|msg| msg.bytes)
+    })
+}
+
+/// Waits until the socket is readable, fails with ETIMEDOUT once deadline passed.
+fn wait_readable(socket: RawFd, deadline: Option<Instant>) -> Result<()> {
+    let Some(deadline) = deadline else {
+        return Ok(());
+    };
+
+    let socket = unsafe { BorrowedFd::borrow_raw(socket) };
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let timeout = PollTimeout::try_from(remaining).unwrap_or(PollTimeout::MAX);
+        let mut fds = [PollFd::new(socket, PollFlags::POLLIN)];
+
+        match poll(&mut fds, timeout) {
+            Ok(0) => return Err(Errno::ETIMEDOUT),
+            Ok(_) => return Ok(()),
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Returns true if a message arrived or the peer disconnected, never blocks.
+pub(crate) fn is_readable(socket: RawFd) -> Result<bool> {
+    match wait_readable(socket, Some(Instant::now())) {
+        Ok(()) => Ok(true),
+        Err(Errno::ETIMEDOUT) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) struct UnixMessageRx {
+    content: Vec<u8>,
+    fds: Vec<OwnedFd>,
+    deadline: Option<Instant>,
+}
+
+impl UnixMessageRx {
+    /// Receives a message, fragments of large messages are reassembled.
+    pub(crate) fn receive(socket: RawFd) -> Result<Self> {
+        Self::receive_until(socket, None)
+    }
+
+    /// Like receive, but fails with ETIMEDOUT if the message isn't complete before deadline,
+    /// the deadline applies to receive_fds as well.
+    pub(crate) fn receive_until(socket: RawFd, deadline: Option<Instant>) -> Result<Self> {
+        if MESSAGE_SOCKET == SockType::Stream {
+            let (content, fds) = stream_receive(socket, deadline)?;
+            return Ok(Self {
+                content,
+                fds,
+                deadline,
+            });
+        }
+
+        let first = Self::receive_datagram(socket, deadline)?;
+
+        if !is_fragment(&first.content) {
+            return Ok(first);
+        }
+
+        let mut reassembly = Reassembly::default();
+        let mut fds = first.fds;
+        let mut complete = reassembly.push(&first.content).ok_or(Errno::EBADMSG)?;
+
+        while !complete {
+            let next = Self::receive_datagram(socket, deadline)?;
+            complete = reassembly.push(&next.content).ok_or(Errno::EBADMSG)?;
+            fds.extend(next.fds);
+        }
+
+        Ok(Self {
+            content: reassembly.into_message(),
+            fds,
+            deadline,
+        })
+    }
+
+    /// Receives continuation messages until num_fds file descriptors are collected.
+    pub(crate) fn receive_fds(&mut self, socket: RawFd, num_fds: usize) -> Result<()> {
+        if MESSAGE_SOCKET == SockType::Stream {
+            return stream_receive_fds(socket, &mut self.fds, num_fds, self.deadline);
+        }
+
+        while self.fds.len() < num_fds {
+            let next = Self::receive_datagram(socket, self.deadline)?;
+
+            let attached = parse_fd_continuation(&next.content).ok_or(Errno::EBADMSG)?;
+
+            if attached == 0 || attached != next.fds.len() {
+                error!(
+                    "fd continuation: expected {attached} fds, got {}",
+                    next.fds.len()
+                );
+                return Err(Errno::EBADMSG);
+            }
+
+            self.fds.extend(next.fds);
+        }
+
+        Ok(())
+    }
+
+    /// Sizes the next datagram by peeking with MSG_TRUNC, then consumes it with a real read
+    /// into a buffer of that size, so the following message is received by the next call.
+    fn receive_datagram(socket: RawFd, deadline: Option<Instant>) -> Result<Self> {
+        wait_readable(socket, deadline)?;
+
+        let size = peek_size(socket)?;
+
+        if size == 0 {
+            return Err(Errno::ENOMSG);
+        }
+
+        let mut content: Vec<u8> = vec![0; size];
+
+        /* consume the message, the handshake may send several messages on one socket */
+        let (received, fds) = recv_fds(socket, &mut [IoSliceMut::new(content.as_mut_slice())])?;
+
+        /* only a concurrent reader could have taken the probed datagram */
+        if received != size {
+            error!("probed datagram of {size} bytes, received {received}");
+            return Err(Errno::EBADMSG);
+        }
+
+        Ok(Self {
+            content,
+            fds,
+            deadline,
+        })
+    }
+
+    pub(crate) fn content(&self) -> &Vec<u8> {
+        &self.content
+    }
+
+    pub(crate) fn take_fds(&mut self) -> VecDeque<OwnedFd> {
+        self.fds.drain(0..).collect()
+    }
+}
+
+/// Sends buf on a stream socket, fds are attached to its first byte.
+fn stream_send_all(socket: RawFd, buf: &[u8], fds: &[RawFd]) -> Result<()> {
+    let mut sent = 0;
+
+    while sent < buf.len() {
+        let fds = if sent == 0 { fds } else { &[] };
+
+        sent += send_fds(socket, &[IoSlice::new(&buf[sent..])], fds)?;
+    }
+
+    Ok(())
+}
+
+/// Fills buf from a stream socket, fds received along the way are appended to fds.
+fn stream_receive_exact(
+    socket: RawFd,
+    buf: &mut [u8],
+    fds: &mut Vec<OwnedFd>,
+    deadline: Option<Instant>,
+) -> Result<()> {
+    let mut received = 0;
+
+    while received < buf.len() {
+        wait_readable(socket, deadline)?;
+
+        let (bytes, attached) = recv_fds(socket, &mut [IoSliceMut::new(&mut buf[received..])])?;
+
+        fds.extend(attached);
+
+        /* the peer closed the socket */
+        if bytes == 0 {
+            return Err(Errno::ENOMSG);
+        }
+
+        received += bytes;
+    }
+
+    Ok(())
+}
+
+/// Sends a message on a stream socket framed by its length as little endian u32,
+/// fds exceeding a single frame follow in continuation frames.
+pub(crate) fn stream_send(socket: RawFd, content: &[u8], fds: &[BorrowedFd<'_>]) -> Result<()> {
+    let fds: Vec<RawFd> = fds.iter().map(|fd| fd.as_raw_fd()).collect();
+    let mut fd_chunks = fds.chunks(MAX_FD);
+
+    let frame = |content: &[u8]| -> Result<Vec<u8>> {
+        let len = u32::try_from(content.len()).map_err(|_| Errno::EMSGSIZE)?;
+        Ok([&len.to_le_bytes(), content].concat())
+    };
+
+    stream_send_all(socket, &frame(content)?, fd_chunks.next().unwrap_or(&[]))?;
+
+    for chunk in fd_chunks {
+        stream_send_all(socket, &frame(&create_fd_continuation(chunk.len()))?, chunk)?;
+    }
+
+    Ok(())
+}
+
+/// Receives a frame sent by stream_send, fds of continuation frames aren't collected.
+pub(crate) fn stream_receive(
+    socket: RawFd,
+    deadline: Option<Instant>,
+) -> Result<(Vec<u8>, Vec<OwnedFd>)> {
+    let mut fds = Vec::new();
+    let mut len = [0u8; size_of::<u32>()];
+
+    stream_receive_exact(socket, &mut len, &mut fds, deadline)?;
+
+    let len = u32::from_le_bytes(len) as usize;
+
+    if len > MAX_MESSAGE_SIZE {
+        error!("stream frame too large {len}");
+        return Err(Errno::EMSGSIZE);
+    }
+
+    let mut content = vec![0; len];
+
+    stream_receive_exact(socket, &mut content, &mut fds, deadline)?;
+
+    Ok((content, fds))
+}
+
+/// Receives continuation frames until fds holds num_fds file descriptors.
+pub(crate) fn stream_receive_fds(
+    socket: RawFd,
+    fds: &mut Vec<OwnedFd>,
+    num_fds: usize,
+    deadline: Option<Instant>,
+) -> Result<()> {
+    while fds.len() < num_fds {
+        let (content, next) = stream_receive(socket, deadline)?;
+
+        let attached = parse_fd_continuation(&content).ok_or(Errno::EBADMSG)?;
+
+        if attached == 0 || attached != next.len() {
+            error!(
+                "fd continuation: expected {attached} fds, got {}",
+                next.len()
+            );
+            return Err(Errno::EBADMSG);
+        }
+
+        fds.extend(next);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,203 @@
+use std::fs::File;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::time::Duration;
+
+use nix::errno::Errno;
+use nix::libc::VMADDR_CID_ANY;
+use nix::sys::socket::{
+    AddressFamily, Backlog, SockFlag, SockType, VsockAddr, accept, bind, connect, getpeername,
+    listen, setsockopt, socket, sockopt,
+};
+use nix::sys::time::TimeVal;
+use nix::unistd::dup;
+
+use crate::auth::Authenticator;
+use crate::channel::ChannelVector;
+use crate::error::*;
+use crate::protocol::{
+    REQ_SHM_OFFSET, Response, create_provided_request, create_response, parse_request,
+    parse_response, request_version,
+};
+use crate::resource::VectorResource;
+use crate::socket::ConnectOptions;
+use crate::tcp::{receive_frame, send_frame};
+use crate::trace::*;
+use crate::{Layout, ServerLimits, VectorConfig};
+
+fn vsock_socket() -> Result<OwnedFd, Errno> {
+    socket(
+        AddressFamily::Vsock,
+        SockType::Stream,
+        SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+}
+
+/// Handshake over vsock for channels between a VM and its host. Both peers map the same
+/// shared memory they got from elsewhere, e.g. the host the memory backend of an ivshmem
+/// device and the guest the BAR of the device. The client places its vector at an offset
+/// of the shared memory, eventfds aren't supported and there's no control connection.
+/// The shared memory isn't sealed, only serve trusted guests.
+pub struct VsockServer {
+    sockfd: OwnedFd,
+    shm: OwnedFd,
+    info: Vec<u8>,
+    auth: Authenticator,
+    limits: ServerLimits,
+}
+
+impl VsockServer {
+    /// Listens on port for any cid, shm is the shared memory the vectors are placed in.
+    pub fn new(port: u32, shm: OwnedFd, backlog: Backlog) -> Result<Self, Errno> {
+        let sockfd = vsock_socket()?;
+
+        bind(sockfd.as_raw_fd(), &VsockAddr::new(VMADDR_CID_ANY, port))?;
+        listen(&sockfd, backlog)?;
+
+        Ok(Self {
+            sockfd,
+            shm,
+            info: Vec::with_capacity(0),
+            auth: Authenticator::default(),
+            limits: ServerLimits::default(),
+        })
+    }
+
+    /// listening socket for polling, readable when a client connects
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        self.sockfd.as_fd()
+    }
+
+    /// Sets the vector-level info sent to every accepted client.
+    pub fn set_info(&mut self, info: Vec<u8>) {
+        self.info = info;
+    }
+
+    pub fn set_limits(&mut self, limits: ServerLimits) {
+        self.limits = limits;
+    }
+
+    pub fn set_cookie(&mut self, cookie: u32) {
+        self.auth.set_cookie(cookie);
+    }
+
+    #[cfg(feature = "hmac")]
+    pub fn set_key(&mut self, key: &[u8]) {
+        self.auth.set_key(Some(key.to_vec()));
+    }
+
+    fn handle_request(&self, req: &[u8]) -> Result<ChannelVector, TransferError> {
+        let request = parse_request(self.auth.verify_request(req)?, &self.limits)?;
+
+        let Some(offset) = request.shm_offset else {
+            error!("request without shared memory offset");
+            return Err(RequestError::MissingRecord(REQ_SHM_OFFSET).into());
+        };
+
+        let layout = Layout {
+            cacheline_size: request.cacheline_size,
+            index_size: request.index_size,
+            descriptors: request.descriptors,
+        };
+
+        let shmfd = dup(&self.shm)?;
+
+        let rsc = VectorResource::from_unsealed(&request.vconfig, layout, shmfd, Some(offset))?;
+
+        Ok(ChannelVector::new(rsc)?)
+    }
+
+    /// Accepts a single client, returns its vector and cid.
+    pub fn accept(&self) -> Result<(ChannelVector, u32), TransferError> {
+        let socket = unsafe { OwnedFd::from_raw_fd(accept(self.sockfd.as_raw_fd())?) };
+
+        let cid = getpeername::<VsockAddr>(socket.as_raw_fd())?.cid();
+
+        let mut stream = File::from(socket);
+
+        let req = receive_frame(&mut stream)?;
+
+        let result = self.handle_request(&req);
+
+        let response = match &result {
+            Ok(_) => {
+                info!("vsock client {cid} accepted");
+                Response::Accepted {
+                    info: self.info.clone(),
+                    payload: Vec::with_capacity(0),
+                    token: 0,
+                }
+            }
+            Err(e) => Response::Rejected(Rejection::new(Rejection::UNSPECIFIED, format!("{e:?}"))),
+        };
+
+        send_frame(
+            &mut stream,
+            &self
+                .auth
+                .sign_reply(create_response(&response, request_version(&req))?, &req),
+        )?;
+
+        Ok((result?, cid))
+    }
+}
+
+/// Connects to a VsockServer, the vector is placed at offset of shm, the shared memory
+/// the server maps as well. Overlapping vectors of different clients aren't detected,
+/// the clients have to agree on their offsets. The vector must not have eventfds.
+pub fn client_connect_vsock(
+    cid: u32,
+    port: u32,
+    shm: BorrowedFd<'_>,
+    offset: usize,
+    vconfig: VectorConfig,
+    options: &ConnectOptions,
+) -> Result<ChannelVector, TransferError> {
+    vconfig.validate(usize::MAX)?;
+
+    if vconfig.count_fds() != 1 {
+        return Err(ConfigError::EventFdsUnsupported.into());
+    }
+
+    let auth = options.authenticator();
+    let layout = Layout::native();
+
+    let mut rsc = VectorResource::allocate_provided(&vconfig, layout, shm, offset)?;
+
+    rsc.map = options.map_options();
+
+    let socket = vsock_socket()?;
+
+    if let Some(timeout) = options.timeout {
+        /* a zero timeout would wait forever */
+        let timeout = timeout.max(Duration::from_micros(1));
+        let timeout = TimeVal::new(timeout.as_secs() as _, timeout.subsec_micros() as _);
+        setsockopt(&socket, sockopt::ReceiveTimeout, &timeout)?;
+    }
+
+    connect(socket.as_raw_fd(), &VsockAddr::new(cid, port))?;
+
+    let mut stream = File::from(socket);
+
+    let request = auth.sign_request(create_provided_request(&vconfig, layout, offset)?)?;
+
+    send_frame(&mut stream, &request)?;
+
+    let msg = receive_frame(&mut stream)?;
+
+    let content = auth.verify_reply(&msg, &request).map_err(|e| {
+        error!("response verification failed {e:?}");
+        TransferError::ResponseError
+    })?;
+
+    match parse_response(content)? {
+        Response::Accepted { info, payload, .. } => {
+            let mut vec = ChannelVector::new(rsc)?;
+            vec.set_server_info(info);
+            vec.set_payload(payload);
+            Ok(vec)
+        }
+        Response::Rejected(rejection) => Err(TransferError::Rejected(rejection)),
+        _ => Err(TransferError::ResponseError),
+    }
+}
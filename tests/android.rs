@@ -0,0 +1,43 @@
+/* Android apps create the shared memory with ASharedMemory, there's no /dev/shm */
+#![cfg(all(feature = "socket", target_os = "android"))]
+
+use rtipc::*;
+
+mod common;
+
+#[test]
+fn vectors_are_placed_in_ashmem() {
+    let vconfig = common::vector().producer(1, 8).eventfd().build();
+
+    /* ashmem regions can't be sealed, they are accepted anyway */
+    let server = Server::unbound().unwrap();
+    let (mut client, mut vector) = server
+        .loopback(vconfig.clone(), &ConnectOptions::default())
+        .unwrap();
+
+    /* fstat reports 0 for ashmem, the size is taken from ASharedMemory */
+    assert_eq!(client.total_shm_size(), vconfig.calc_shm_size());
+    assert_eq!(vector.total_shm_size(), vconfig.calc_shm_size());
+
+    let mut producer = client.take_producer::<u64>(0).unwrap();
+    let mut consumer = vector.take_consumer::<u64>(0).unwrap();
+
+    *producer.current_message() = 7;
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&7));
+}
+
+#[test]
+fn named_shm_is_unsupported() {
+    let options = ConnectOptions {
+        backing: ShmBacking::Named,
+        ..Default::default()
+    };
+
+    let mut server = Server::unbound().unwrap();
+    server.set_named_shm(true);
+
+    let result = server.loopback(common::vector().producer(0, 8).build(), &options);
+    assert!(result.is_err());
+}
@@ -0,0 +1,150 @@
+use rtipc::*;
+
+mod common;
+
+fn arenas(block_size: usize, num_blocks: usize) -> (Arena, Arena) {
+    let (mut owner, mut peer) =
+        ChannelVector::create_pair(common::vector().arena(block_size, num_blocks).build()).unwrap();
+
+    (owner.take_arena().unwrap(), peer.take_arena().unwrap())
+}
+
+#[test]
+fn handle_resolves_to_the_payload() {
+    let (mut sender, mut receiver) = arenas(1000, 4);
+
+    let handle = sender.alloc(500).unwrap();
+    sender.get_mut(handle).unwrap().fill(9);
+
+    let handle = ArenaHandle::from_raw(handle.to_raw());
+    let payload = receiver.get(handle).unwrap();
+    assert_eq!(payload.len(), 500);
+    assert!(payload.iter().all(|b| *b == 9));
+
+    assert!(receiver.free(handle));
+    assert!(receiver.get(handle).is_none());
+    assert!(!receiver.free(handle));
+}
+
+#[test]
+fn exhausted_arena_refuses_allocations() {
+    let (mut sender, mut receiver) = arenas(64, 3);
+
+    let handles: Vec<ArenaHandle> = (0..3).map(|_| sender.alloc(64).unwrap()).collect();
+
+    assert_eq!(sender.try_alloc(1), Err(ArenaError::Exhausted));
+    assert_eq!(
+        sender.try_alloc(65),
+        Err(ArenaError::PayloadTooLarge {
+            len: 65,
+            block_size: 64
+        })
+    );
+
+    /* a freed block is handed out again */
+    assert!(receiver.free(handles[1]));
+    let handle = sender.alloc(10).unwrap();
+    assert_eq!(handle.index(), handles[1].index());
+    assert!(sender.alloc(10).is_none());
+}
+
+#[test]
+fn both_sides_allocate() {
+    let (mut owner, mut peer) = arenas(64, 2);
+
+    let mine = owner.alloc(8).unwrap();
+    let theirs = peer.alloc(8).unwrap();
+    assert_ne!(mine.index(), theirs.index());
+
+    assert!(owner.get(theirs).is_some());
+    assert!(peer.get(mine).is_some());
+
+    assert!(owner.free(theirs));
+    assert!(peer.free(mine));
+}
+
+#[test]
+fn blocks_are_owned_by_one_side() {
+    let (mut sender, mut receiver) = arenas(64, 2);
+
+    let handle = sender.alloc(16).unwrap();
+
+    /* the sender neither resolves nor frees its sent block */
+    assert!(sender.get(handle).is_none());
+    assert!(!sender.free(handle));
+
+    /* the receiver doesn't write or release it */
+    assert!(receiver.get_mut(handle).is_none());
+    assert!(!receiver.release(handle));
+
+    assert!(receiver.free(handle));
+
+    /* an unsent block is released by the sender */
+    let unsent = sender.alloc(16).unwrap();
+    assert!(sender.release(unsent));
+    assert!(sender.get_mut(unsent).is_none());
+}
+
+#[test]
+fn forged_handles_are_refused() {
+    let (mut sender, mut receiver) = arenas(64, 2);
+
+    let handle = sender.alloc(16).unwrap();
+
+    let beyond = ArenaHandle::from_raw((16 << 32) | 2);
+    assert!(receiver.get(beyond).is_none());
+    assert!(!receiver.free(beyond));
+
+    let oversized = ArenaHandle::from_raw((65 << 32) | handle.index() as u64);
+    assert!(receiver.get(oversized).is_none());
+}
+
+#[test]
+fn stale_handles_are_refused() {
+    let (mut sender, mut receiver) = arenas(64, 1);
+
+    let stale = sender.alloc(16).unwrap();
+    assert!(receiver.free(stale));
+
+    /* the only block is handed out again with the same length */
+    let handle = sender.alloc(16).unwrap();
+    assert_eq!(handle.index(), stale.index());
+    assert_ne!(handle, stale);
+
+    assert!(sender.get_mut(stale).is_none());
+    assert!(!sender.release(stale));
+    assert!(receiver.get(stale).is_none());
+    assert!(!receiver.free(stale));
+
+    assert!(sender.get_mut(handle).is_some());
+    assert!(receiver.free(handle));
+}
+
+#[test]
+fn arenas_beyond_the_handle_index_are_refused() {
+    assert_eq!(
+        common::vector()
+            .arena(64, MAX_ARENA_BLOCKS + 1)
+            .build()
+            .validate(usize::MAX),
+        Err(ConfigError::ArenaOverflow)
+    );
+    assert_eq!(
+        common::vector()
+            .arena(64, MAX_ARENA_BLOCKS)
+            .build()
+            .validate(usize::MAX),
+        Ok(())
+    );
+}
+
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn oversized_arenas_are_refused() {
+    let vconfig = common::vector().arena(u32::MAX as usize + 1, 1).build();
+
+    assert_eq!(
+        vconfig.validate(usize::MAX),
+        Err(ConfigError::ArenaOverflow)
+    );
+}
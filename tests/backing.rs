@@ -0,0 +1,111 @@
+#![cfg(all(feature = "socket", target_os = "linux"))]
+
+use std::path::PathBuf;
+
+use rtipc::*;
+
+mod common;
+
+/// Empty directory in the temp dir, unique per test process and name.
+fn directory(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rtipc-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn tmpfile_is_linked_once_accepted() {
+    let dir = directory("tmpfile");
+    let path = dir.join("ring");
+
+    let mut server = Server::unbound().unwrap();
+    server.set_file_backing(true);
+
+    let options = ConnectOptions {
+        backing: ShmBacking::TmpFile(path.clone()),
+        ..Default::default()
+    };
+
+    let (mut client, mut server_vector) = server
+        .loopback(common::vector().producer(0, 8).eventfd().build(), &options)
+        .unwrap();
+
+    let mut producer = client.take_producer::<u64>(0).unwrap();
+    let mut consumer = server_vector.take_consumer::<u64>(0).unwrap();
+
+    *producer.current_message() = 0x1234_5678_9abc;
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+
+    /* the linked file is the shared memory of the vector */
+    let data = std::fs::read(&path).unwrap();
+    assert!(
+        data.windows(8)
+            .any(|w| w == 0x1234_5678_9abcu64.to_ne_bytes())
+    );
+
+    /* an existing file is never replaced */
+    assert!(
+        server
+            .loopback(common::vector().producer(0, 8).eventfd().build(), &options)
+            .is_err()
+    );
+
+    drop((producer, consumer, client, server_vector));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Shared memory objects of this process left in /dev/shm.
+fn named_shm() -> usize {
+    let prefix = format!("rtipc-{}-", std::process::id());
+
+    std::fs::read_dir("/dev/shm")
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with(&prefix)
+        })
+        .count()
+}
+
+#[test]
+fn named_shm_is_passed_by_name() {
+    let options = ConnectOptions {
+        backing: ShmBacking::Named,
+        ..Default::default()
+    };
+
+    let vconfig = common::vector().producer(0, 8).build();
+
+    /* a shared memory object can't be sealed, the server has to allow it */
+    let server = Server::unbound().unwrap();
+    assert!(server.loopback(vconfig.clone(), &options).is_err());
+
+    let mut server = Server::unbound().unwrap();
+    server.set_named_shm(true);
+
+    /* eventfds can't be passed along with the name */
+    let result = server.loopback(common::vector().producer(0, 8).eventfd().build(), &options);
+    assert!(matches!(
+        result,
+        Err(TransferError::ConfigError(ConfigError::EventFdsUnsupported))
+    ));
+
+    let (mut client, mut server_vector) = server.loopback(vconfig, &options).unwrap();
+
+    let mut producer = client.take_producer::<u64>(0).unwrap();
+    let mut consumer = server_vector.take_consumer::<u64>(0).unwrap();
+
+    *producer.current_message() = 7;
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&7));
+
+    /* the name is gone once both peers mapped the shared memory */
+    assert_eq!(named_shm(), 0);
+}
@@ -0,0 +1,153 @@
+use std::{num::NonZeroUsize, ptr::NonNull, thread};
+
+use rtipc::*;
+
+mod common;
+
+/// Broadcast channel with 2 + 3 slots, consumers see the last 4 messages.
+fn channels() -> (BroadcastProducer<u64>, BroadcastConsumer<u64>) {
+    let (mut owner, mut peer) = ChannelVector::create_pair(
+        common::vector()
+            .producer(2, 8)
+            .kind(ChannelKind::Broadcast)
+            .build(),
+    )
+    .unwrap();
+
+    (
+        owner.take_broadcast_producer(0).unwrap(),
+        peer.take_broadcast_consumer(0).unwrap(),
+    )
+}
+
+fn push(producer: &mut BroadcastProducer<u64>, values: std::ops::Range<u64>) {
+    for value in values {
+        *producer.current_message() = value;
+        producer.push();
+    }
+}
+
+#[test]
+fn every_consumer_gets_every_message() {
+    let (mut producer, consumer) = channels();
+    let mut consumers = [consumer.clone(), consumer.clone(), consumer];
+
+    assert!(consumers[0].pop() == PopResult::NoMessage);
+
+    push(&mut producer, 0..3);
+
+    for consumer in consumers.iter_mut() {
+        for value in 0..3 {
+            assert!(consumer.pop() == PopResult::Success);
+            assert_eq!(consumer.current_message(), Some(&value));
+        }
+        assert!(consumer.pop() == PopResult::NoNewMessage);
+    }
+}
+
+#[test]
+fn slow_consumer_doesnt_block_the_others() {
+    let (mut producer, mut fast) = channels();
+    let mut slow = fast.clone();
+
+    for value in 0..10 {
+        push(&mut producer, value..value + 1);
+        assert!(fast.pop() == PopResult::Success);
+        assert_eq!(fast.current_message(), Some(&value));
+    }
+
+    /* the slow consumer was overrun, it continues with the oldest message kept */
+    assert!(slow.pop() == PopResult::SuccessMessagesDiscarded);
+    assert_eq!(slow.current_message(), Some(&6));
+
+    for value in 7..10 {
+        assert!(slow.pop() == PopResult::Success);
+        assert_eq!(slow.current_message(), Some(&value));
+    }
+    assert!(slow.pop() == PopResult::NoNewMessage);
+}
+
+#[test]
+fn overrun_consumer_never_reads_torn_messages() {
+    let (mut owner, mut peer) = ChannelVector::create_pair(
+        common::vector()
+            .producer(0, 64)
+            .kind(ChannelKind::Broadcast)
+            .build(),
+    )
+    .unwrap();
+
+    let mut producer = owner.take_broadcast_producer::<[u64; 8]>(0).unwrap();
+    let mut consumer = peer.take_broadcast_consumer::<[u64; 8]>(0).unwrap();
+
+    const MESSAGES: u64 = 100_000;
+
+    let writer = thread::spawn(move || {
+        for value in 1..=MESSAGES {
+            *producer.current_message() = [value; 8];
+            producer.push();
+        }
+    });
+
+    let mut last = 0;
+
+    while last < MESSAGES {
+        match consumer.pop() {
+            PopResult::Success | PopResult::SuccessMessagesDiscarded => {
+                let message = consumer.current_message().unwrap();
+                assert!(message.iter().all(|v| *v == message[0]), "torn {message:?}");
+                assert!(message[0] > last);
+                last = message[0];
+            }
+            _ => thread::yield_now(),
+        }
+    }
+
+    writer.join().unwrap();
+}
+
+#[test]
+fn head_going_back_poisons_the_consumer() {
+    const SIZE: usize = 1 << 16;
+
+    let layout = std::alloc::Layout::from_size_align(SIZE, 4096).unwrap();
+    let ptr = NonNull::new(unsafe { std::alloc::alloc_zeroed(layout) }).unwrap();
+    let region = || Box::new(unsafe { DeviceMemory::new(ptr, NonZeroUsize::new(SIZE).unwrap()) });
+
+    let vconfig = common::vector()
+        .producer(2, 8)
+        .kind(ChannelKind::Broadcast)
+        .build();
+    let mut peer = ChannelVector::with_region(vconfig.clone(), region(), false).unwrap();
+    let mut owner = ChannelVector::with_region(vconfig, region(), true).unwrap();
+
+    let mut producer = owner.take_broadcast_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_broadcast_consumer::<u64>(0).unwrap();
+
+    let words = unsafe { std::slice::from_raw_parts_mut(ptr.as_ptr() as *mut u64, SIZE / 8) };
+
+    push(&mut producer, 0x100..0x103);
+    for value in 0x100..0x103 {
+        assert!(consumer.pop() == PopResult::Success);
+        assert_eq!(consumer.current_message(), Some(&value));
+    }
+
+    /* the head is the word counting the pushed messages */
+    let candidates: Vec<usize> = (0..words.len()).filter(|i| words[*i] == 3).collect();
+    push(&mut producer, 0x103..0x104);
+    let head = candidates.into_iter().find(|i| words[*i] == 4).unwrap();
+
+    assert!(consumer.pop() == PopResult::Success);
+
+    /* a hostile producer moves the head behind the cursor of the consumer */
+    words[head] = 1;
+    assert!(consumer.pop() == PopResult::QueueError);
+
+    /* the consumer stays poisoned, even with a plausible head again */
+    words[head] = 5;
+    assert!(consumer.pop() == PopResult::QueueError);
+    assert!(consumer.clone().pop() == PopResult::QueueError);
+
+    drop((producer, consumer, owner, peer));
+    unsafe { std::alloc::dealloc(ptr.as_ptr(), layout) };
+}
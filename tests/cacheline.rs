@@ -0,0 +1,35 @@
+/* every test binary has its own cache line size, the tests here change it */
+use rtipc::*;
+
+mod common;
+
+#[test]
+fn oversized_cache_lines_are_refused() {
+    /* the header of the handshake carries the cache line size in a u16 */
+    unsafe { std::env::set_var(CACHELINE_SIZE_VAR, "65536") };
+
+    assert_ne!(max_cacheline_size(), 65536);
+    assert_ne!(cacheline_source(), CachelineSource::Environment);
+
+    assert!(set_cacheline_size(65536).is_err());
+    assert!(set_cacheline_size(2 * MAX_CACHELINE_SIZE).is_err());
+    assert!(set_cacheline_size(3).is_err());
+    assert!(set_cacheline_size(4).is_err());
+
+    let vconfig = common::vector().heartbeat().build();
+
+    assert!(matches!(
+        VectorResource::allocate_with_layout(&vconfig, 65536, 8),
+        Err(ResourceError::InvalidArgument)
+    ));
+
+    set_cacheline_size(MAX_CACHELINE_SIZE).unwrap();
+    assert_eq!(max_cacheline_size(), MAX_CACHELINE_SIZE);
+    assert_eq!(cacheline_aligned(1), MAX_CACHELINE_SIZE);
+
+    #[cfg(feature = "socket")]
+    {
+        let rsc = VectorResource::allocate(&vconfig).unwrap();
+        assert!(rsc.serialize().is_ok());
+    }
+}
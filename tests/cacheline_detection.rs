@@ -0,0 +1,26 @@
+/* the detected cache line size, tests/cacheline.rs overrides it in its own binary */
+use rtipc::*;
+
+#[test]
+fn cacheline_size_is_detected() {
+    let cls = max_cacheline_size();
+    assert!(cls.is_power_of_two());
+    assert!(cls >= align_of::<f64>());
+
+    /* linux has the caches in sysfs, macOS in sysctl */
+    assert!(matches!(
+        cacheline_source(),
+        CachelineSource::Sysfs
+            | CachelineSource::Sysconf
+            | CachelineSource::Environment
+            | CachelineSource::Predefined
+    ));
+
+    assert_eq!(cacheline_aligned(1), cls);
+    assert_eq!(cacheline_aligned(cls), cls);
+    assert_eq!(cacheline_aligned(cls + 1), 2 * cls);
+
+    set_cacheline_size(2 * cls).unwrap();
+    assert_eq!(cacheline_source(), CachelineSource::Override);
+    assert_eq!(cacheline_aligned(1), 2 * cls);
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "capnp")]
+
+use rtipc::capnp::{primitive_list, text};
+use rtipc::*;
+
+mod common;
+
+#[test]
+fn messages_are_built_in_place() {
+    let (mut owner, mut peer) =
+        ChannelVector::create_pair(common::vector().producer(2, 256).build()).unwrap();
+
+    let mut producer = owner.take_capnp_producer(0).unwrap();
+    let mut consumer = peer.take_capnp_consumer(0).unwrap();
+
+    for round in 0..5u64 {
+        producer
+            .build(|message| {
+                let mut list = message.initn_root::<primitive_list::Builder<u64>>(3);
+                for i in 0..3 {
+                    list.set(i, round * 10 + i as u64);
+                }
+            })
+            .unwrap();
+        producer.force_push();
+
+        assert!(consumer.pop() == PopResult::Success);
+
+        let reader = consumer.reader().unwrap();
+        let list = reader.get_root::<primitive_list::Reader<u64>>().unwrap();
+        let values: Vec<u64> = list.iter().collect();
+        assert_eq!(values, [round * 10, round * 10 + 1, round * 10 + 2]);
+    }
+
+    producer
+        .build(|message| message.set_root("in place").unwrap())
+        .unwrap();
+    producer.force_push();
+
+    assert!(consumer.pop() == PopResult::Success);
+
+    let reader = consumer.reader().unwrap();
+    let text = reader.get_root::<text::Reader>().unwrap();
+    assert_eq!(text.to_str().unwrap(), "in place");
+}
+
+#[test]
+fn oversized_messages_are_refused() {
+    let (mut owner, mut peer) =
+        ChannelVector::create_pair(common::vector().producer(2, 64).build()).unwrap();
+
+    let mut producer = owner.take_capnp_producer(0).unwrap();
+    let mut consumer = peer.take_capnp_consumer(0).unwrap();
+
+    let result = producer.build(|message| {
+        message.initn_root::<primitive_list::Builder<u64>>(64);
+    });
+    assert!(result.is_err());
+
+    /* the refused message leaves an empty slot behind */
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+
+    let reader = consumer.reader().unwrap();
+    let list = reader.get_root::<primitive_list::Reader<u64>>().unwrap();
+    assert_eq!(list.len(), 0);
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::ptr::NonNull;
+
+use rtipc::*;
+
+rtipc::c_message! {
+    #[derive(Clone, Copy)]
+    pub struct MsgPoint {
+        pub x: f32,
+        pub y: f32,
+    }
+}
+
+rtipc::c_message! {
+    #[derive(Clone, Copy)]
+    pub struct MsgCommand {
+        pub id: u8,
+        pub value: f64,
+        pub points: [MsgPoint; 2],
+        pub data: [[u16; 3]; 2],
+        pub enabled: bool,
+    }
+}
+
+fn defines(header: &str) -> HashMap<String, usize> {
+    header
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.strip_prefix("#define ")?.split(' ');
+            let name = words.next()?;
+            let value = words.next()?.parse().ok()?;
+            Some((name.to_owned(), value))
+        })
+        .collect()
+}
+
+fn vector_config() -> VectorConfig {
+    Topics::new()
+        .publish::<MsgCommand>("command", TopicQos::default())
+        .unwrap()
+        .subscribe::<MsgPoint>("point", TopicQos::default())
+        .unwrap()
+        .into_config()
+}
+
+#[test]
+fn messages_are_declared_in_c() {
+    let header = CHeader::new("ROBOT_H")
+        .message::<MsgPoint>()
+        .message::<MsgCommand>()
+        .render();
+
+    assert!(header.starts_with("/* generated by rtipc, do not edit */\n#ifndef ROBOT_H\n"));
+    assert!(header.ends_with("#endif /* ROBOT_H */\n"));
+
+    assert!(header.contains(
+        "typedef struct MsgCommand {\n    uint8_t id;\n    double value;\n    \
+         MsgPoint points[2];\n    uint16_t data[2][3];\n    bool enabled;\n} MsgCommand;\n"
+    ));
+    assert!(header.contains(&format!(
+        "RTIPC_LAYOUT_ASSERT(sizeof(MsgCommand) == {}, \"size of MsgCommand\");",
+        size_of::<MsgCommand>()
+    )));
+    assert!(header.contains(
+        "RTIPC_LAYOUT_ASSERT(offsetof(MsgCommand, points) == 16, \"offset of MsgCommand.points\");"
+    ));
+}
+
+#[test]
+fn layouts_match_the_shared_memory() {
+    let vconfig = vector_config();
+
+    let header = CHeader::new("ROBOT_H").vector("robot", &vconfig).render();
+    let defines = defines(&header);
+
+    assert_eq!(defines["ROBOT_SHM_SIZE"], vconfig.calc_shm_size());
+    assert_eq!(defines["ROBOT_CACHELINE_SIZE"], max_cacheline_size());
+    assert_eq!(defines["ROBOT_COMMAND_OFFSET"], 0);
+    assert_eq!(
+        defines["ROBOT_POINT_OFFSET"],
+        defines["ROBOT_COMMAND_SHM_SIZE"]
+    );
+    assert_eq!(
+        defines["ROBOT_COMMAND_MESSAGE_SIZE"],
+        size_of::<MsgCommand>()
+    );
+
+    let layout = std::alloc::Layout::from_size_align(1 << 16, 256).unwrap();
+    let ptr = NonNull::new(unsafe { std::alloc::alloc_zeroed(layout) }).unwrap();
+    let region =
+        || Box::new(unsafe { DeviceMemory::new(ptr, NonZeroUsize::new(1 << 16).unwrap()) });
+
+    let mut peer = ChannelVector::with_region(vconfig.clone(), region(), false).unwrap();
+    let mut owner = ChannelVector::with_region(vconfig, region(), true).unwrap();
+
+    let slot = |msg: *const u8, name: &str| {
+        let offset = msg as usize
+            - ptr.as_ptr() as usize
+            - defines[&format!("ROBOT_{name}_MESSAGES_OFFSET")];
+        let slot_size = defines[&format!("ROBOT_{name}_SLOT_SIZE")];
+
+        assert_eq!(offset % slot_size, 0, "{name}");
+        assert!(
+            offset / slot_size < defines[&format!("ROBOT_{name}_SLOTS")],
+            "{name}"
+        );
+    };
+
+    let mut command = owner.take_producer::<MsgCommand>(0).unwrap();
+    let mut point = peer.take_producer::<MsgPoint>(0).unwrap();
+
+    slot(
+        command.current_message() as *const MsgCommand as *const u8,
+        "COMMAND",
+    );
+    slot(
+        point.current_message() as *const MsgPoint as *const u8,
+        "POINT",
+    );
+
+    drop((command, point, owner, peer));
+    unsafe { std::alloc::dealloc(ptr.as_ptr(), layout) };
+}
+
+#[test]
+fn unnamed_channels_are_numbered() {
+    let mut vconfig = vector_config();
+    vconfig.producers[0].queue.info = b"not a name".to_vec();
+    vconfig.consumers[0].queue.info = Vec::new();
+
+    let header = CHeader::new("ROBOT_H")
+        .vector_with_layout("robot", &vconfig, 128, 8)
+        .unwrap()
+        .render();
+    let defines = defines(&header);
+
+    assert_eq!(defines["ROBOT_CACHELINE_SIZE"], 128);
+    assert_eq!(defines["ROBOT_INDEX_SIZE"], 8);
+    assert!(defines.contains_key("ROBOT_PRODUCER_0_OFFSET"));
+    assert!(defines.contains_key("ROBOT_CONSUMER_0_MESSAGES_OFFSET"));
+
+    assert!(
+        CHeader::new("ROBOT_H")
+            .vector_with_layout("robot", &vconfig, 48, 4)
+            .is_err()
+    );
+}
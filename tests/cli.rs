@@ -0,0 +1,93 @@
+#![cfg(feature = "cli")]
+
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+
+const CLI: &str = env!("CARGO_BIN_EXE_rtipc-cli");
+
+const VECTOR: &str = "
+info = \"cli\"
+
+[[producers]]
+queue = { message_size = 16, additional_messages = 2, info = \"ping\" }
+eventfd = true
+
+[[consumers]]
+queue = { message_size = 16, additional_messages = 2, info = \"pong\" }
+eventfd = true
+";
+
+struct Echo(Child);
+
+impl Drop for Echo {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn run(args: &[&str]) -> (bool, String) {
+    let output = Command::new(CLI).args(args).output().unwrap();
+    let mut text = String::from_utf8(output.stdout).unwrap();
+    text.push_str(&String::from_utf8(output.stderr).unwrap());
+    (output.status.success(), text)
+}
+
+#[test]
+fn links_are_inspected_and_benchmarked() {
+    let dir = std::env::temp_dir().join(format!("rtipc-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let socket = dir.join("echo.sock");
+    let config = dir.join("vector.toml");
+    std::fs::write(&config, VECTOR).unwrap();
+
+    let socket = socket.to_str().unwrap();
+    let config = config.to_str().unwrap();
+
+    let _echo = Echo(
+        Command::new(CLI)
+            .args(["echo", socket])
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap(),
+    );
+
+    for _ in 0..100 {
+        if std::path::Path::new(socket).exists() {
+            break;
+        }
+        sleep(Duration::from_millis(10));
+    }
+
+    let (ok, text) = run(&["info", socket, config]);
+    assert!(ok, "{text}");
+    assert!(text.contains("vector info \"cli\""), "{text}");
+    assert!(
+        text.contains("producer 0: Queue, message 16 bytes, 5 slots"),
+        "{text}"
+    );
+    assert!(text.contains("info \"pong\""), "{text}");
+
+    let (ok, text) = run(&["bench", socket, config, "0", "0", "100"]);
+    assert!(ok, "{text}");
+    assert!(text.starts_with("100 round trips: min "), "{text}");
+
+    let (ok, _) = run(&["send", socket, config, "0", "de ad be ef"]);
+    assert!(ok);
+
+    let (ok, text) = run(&["send", socket, config, "1", "00"]);
+    assert!(!ok);
+    assert!(text.contains("no producer 1"), "{text}");
+
+    let (ok, text) = run(&["send", socket, config, "0", "0"]);
+    assert!(!ok);
+    assert!(text.contains("odd number of hex digits"), "{text}");
+
+    let (ok, text) = run(&["bogus"]);
+    assert!(!ok);
+    assert!(text.starts_with("usage:"), "{text}");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
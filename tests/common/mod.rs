@@ -0,0 +1,250 @@
+/* fixtures of the integration tests, every test uses a part of them */
+#![allow(dead_code)]
+
+use std::num::NonZeroUsize;
+
+use rtipc::*;
+
+pub fn queue(additional_messages: usize, message_size: usize) -> QueueConfig {
+    QueueConfig::new(
+        additional_messages,
+        NonZeroUsize::new(message_size).unwrap(),
+    )
+}
+
+pub fn channel(
+    kind: ChannelKind,
+    additional_messages: usize,
+    message_size: usize,
+    eventfd: bool,
+) -> ChannelConfig {
+    ChannelConfig {
+        kind,
+        ..ChannelConfig::new(queue(additional_messages, message_size), eventfd)
+    }
+}
+
+/// Vector without channels, info, arena and heartbeat stamps, to be completed by the
+/// methods of VectorBuilder, e.g. `vector().producer(1, 8).eventfd().build()`.
+pub fn vector() -> VectorBuilder {
+    VectorBuilder {
+        config: VectorConfig::default(),
+        last: None,
+    }
+}
+
+/// Builder of the vectors of the tests, channels get their index in the order they are added.
+/// kind, eventfd, queue_info and schema apply to the channel added last.
+pub struct VectorBuilder {
+    config: VectorConfig,
+    /// true for a producer
+    last: Option<bool>,
+}
+
+impl VectorBuilder {
+    /// Adds a queue without eventfd.
+    pub fn producer(self, additional_messages: usize, message_size: usize) -> Self {
+        let queue = queue(additional_messages, message_size);
+        self.producers([ChannelConfig::new(queue, false)])
+    }
+
+    /// Adds a queue without eventfd.
+    pub fn consumer(self, additional_messages: usize, message_size: usize) -> Self {
+        let queue = queue(additional_messages, message_size);
+        self.consumers([ChannelConfig::new(queue, false)])
+    }
+
+    /// Adds producers configured elsewhere, e.g. the channels of another vector.
+    pub fn producers(mut self, channels: impl IntoIterator<Item = ChannelConfig>) -> Self {
+        self.config.producers.extend(channels);
+        self.last = Some(true);
+        self
+    }
+
+    pub fn consumers(mut self, channels: impl IntoIterator<Item = ChannelConfig>) -> Self {
+        self.config.consumers.extend(channels);
+        self.last = Some(false);
+        self
+    }
+
+    pub fn kind(mut self, kind: ChannelKind) -> Self {
+        self.last_channel().kind = kind;
+        self
+    }
+
+    pub fn eventfd(mut self) -> Self {
+        self.last_channel().eventfd = true;
+        self
+    }
+
+    pub fn queue_info(mut self, info: &[u8]) -> Self {
+        self.last_channel().queue.info = info.to_vec();
+        self
+    }
+
+    pub fn schema(mut self, fingerprint: u64) -> Self {
+        self.last_channel().queue.schema = Some(fingerprint);
+        self
+    }
+
+    pub fn info(mut self, info: &[u8]) -> Self {
+        self.config.info = info.to_vec();
+        self
+    }
+
+    pub fn arena(mut self, block_size: usize, num_blocks: usize) -> Self {
+        self.config.arena = Some(ArenaConfig {
+            block_size: NonZeroUsize::new(block_size).unwrap(),
+            num_blocks: NonZeroUsize::new(num_blocks).unwrap(),
+        });
+        self
+    }
+
+    pub fn heartbeat(mut self) -> Self {
+        self.config.heartbeat = true;
+        self
+    }
+
+    pub fn build(self) -> VectorConfig {
+        self.config
+    }
+
+    fn last_channel(&mut self) -> &mut ChannelConfig {
+        let channels = match self.last {
+            Some(true) => &mut self.config.producers,
+            Some(false) => &mut self.config.consumers,
+            None => panic!("no channel added"),
+        };
+        channels.last_mut().unwrap()
+    }
+}
+
+/// Socket path in the temp dir, unique per test process and name.
+#[cfg(feature = "socket")]
+pub fn socket_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("rtipc-{name}-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Connects a client with options to a server listening on a socket named name,
+/// returns the vectors of the client and the server and the peer seen by the server.
+#[cfg(feature = "socket")]
+pub fn connect(
+    name: &str,
+    vconfig: VectorConfig,
+    options: ConnectOptions,
+) -> (ChannelVector, ChannelVector, PeerInfo) {
+    let path = socket_path(name);
+    let server = Server::new(path.as_path(), nix::sys::socket::Backlog::new(1).unwrap()).unwrap();
+
+    let client = std::thread::spawn(move || client_connect_with(path.as_path(), vconfig, &options));
+
+    let (vector, peer) = server.accept().unwrap();
+    let client = client.join().unwrap().unwrap();
+
+    (client, vector, peer)
+}
+
+/// Connected unix seqpacket sockets, e.g. for playing the peer of a handshake.
+#[cfg(all(feature = "socket", not(target_os = "macos")))]
+pub fn seqpacket_pair() -> (std::os::fd::OwnedFd, std::os::fd::OwnedFd) {
+    use nix::sys::socket::{AddressFamily, SockFlag, SockType, socketpair};
+
+    socketpair(
+        AddressFamily::Unix,
+        SockType::SeqPacket,
+        None,
+        SockFlag::SOCK_CLOEXEC,
+    )
+    .unwrap()
+}
+
+/// Request of librtipc 0.5.1, before the TLV protocol, for baseline_config(),
+/// as written by its VectorResource::serialize on x86_64 (64 byte cache lines, u32 indices).
+const BASELINE_REQUEST: [u8; 82] = [
+    12, 31, 2, 0, 64, 0, 4, 0, 8, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 8, 0, 0, 0, 1, 0, 0,
+    0, 3, 0, 0, 0, 2, 0, 0, 0, 16, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 24, 0, 0, 0, 1, 0,
+    0, 0, 0, 0, 0, 0, 98, 97, 115, 101, 108, 105, 110, 101, 99, 109, 100, 114, 115, 112,
+];
+
+/// Vector of BASELINE_REQUEST from the point of view of the requester.
+pub fn baseline_config() -> VectorConfig {
+    let channel = |additional_messages, message_size, info: &[u8], eventfd| {
+        let mut channel = channel(
+            ChannelKind::Queue,
+            additional_messages,
+            message_size,
+            eventfd,
+        );
+        channel.queue.info = info.to_vec();
+        channel
+    };
+
+    vector()
+        .producers([channel(1, 8, b"cmd", true)])
+        .consumers([channel(2, 16, b"rsp", false), channel(0, 24, b"", true)])
+        .info(b"baseline")
+        .build()
+}
+
+/// BASELINE_REQUEST as written on this machine, its header holds the cache line size
+/// and index width of the requester. The request is in native byte order.
+#[cfg(target_endian = "little")]
+pub fn baseline_request() -> Vec<u8> {
+    let mut request = BASELINE_REQUEST.to_vec();
+
+    request[4..6].copy_from_slice(&(max_cacheline_size() as u16).to_le_bytes());
+    request[6..8].copy_from_slice(&(index_size() as u16).to_le_bytes());
+
+    request
+}
+
+/// Shared memory and eventfds a librtipc 0.5.1 requester passes with its request for
+/// vconfig: a sealed memfd without descriptors in front of the queues, then the eventfds
+/// of the producers and of the consumers.
+#[cfg(all(feature = "socket", target_os = "linux"))]
+pub fn baseline_fds(vconfig: &VectorConfig) -> Vec<std::os::fd::OwnedFd> {
+    use nix::fcntl::{F_ADD_SEALS, SealFlag, fcntl};
+    use nix::sys::memfd::{MFdFlags, memfd_create};
+
+    let align = |size: usize| size.next_multiple_of(max_cacheline_size());
+
+    /* tail, head and the chain of the queue, followed by the messages */
+    let queue_size = |config: &ChannelConfig| {
+        let n = 3 + config.queue.additional_messages;
+        align((2 + n) * index_size()) + n * align(config.queue.message_size.get())
+    };
+
+    let channels = || vconfig.producers.iter().chain(vconfig.consumers.iter());
+    let size: usize = channels().map(queue_size).sum();
+
+    let shmfd = memfd_create("rtipc", MFdFlags::MFD_ALLOW_SEALING).unwrap();
+    nix::unistd::ftruncate(&shmfd, size.try_into().unwrap()).unwrap();
+    fcntl(
+        &shmfd,
+        F_ADD_SEALS(SealFlag::F_SEAL_GROW | SealFlag::F_SEAL_SHRINK | SealFlag::F_SEAL_SEAL),
+    )
+    .unwrap();
+
+    let eventfds = channels()
+        .filter(|config| config.eventfd)
+        .map(|_| std::os::fd::OwnedFd::from(EventFd::new().unwrap()));
+
+    std::iter::once(shmfd).chain(eventfds).collect()
+}
+
+/// Request and duplicates of the fds of rsc, for a receiving VectorResource.
+#[cfg(feature = "socket")]
+pub fn serialize(
+    rsc: &VectorResource,
+) -> (Vec<u8>, std::collections::VecDeque<std::os::fd::OwnedFd>) {
+    let (request, fds) = rsc.serialize().unwrap();
+
+    let fds = fds
+        .into_iter()
+        .map(|fd| fd.try_clone_to_owned().unwrap())
+        .collect();
+
+    (request, fds)
+}
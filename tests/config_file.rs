@@ -0,0 +1,209 @@
+#![cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+
+use rtipc::*;
+
+fn check_robot(config: &VectorConfig) {
+    assert_eq!(config.producers.len(), 2);
+    assert_eq!(config.consumers.len(), 1);
+    assert_eq!(config.info, b"robot");
+    assert!(config.heartbeat);
+
+    let command = &config.producers[0];
+    assert_eq!(command.queue.message_size.get(), 64);
+    assert_eq!(command.queue.additional_messages, 2);
+    assert_eq!(command.queue.info, b"command");
+    assert_eq!(command.kind, ChannelKind::Queue);
+    assert!(command.eventfd);
+
+    let state = &config.producers[1];
+    assert_eq!(state.kind, ChannelKind::State);
+    assert_eq!(state.queue.info, [1, 2, 3]);
+    assert_eq!(state.queue.schema, Some(42));
+    assert!(!state.eventfd);
+
+    let arena = config.arena.as_ref().unwrap();
+    assert_eq!(arena.block_size.get(), 256);
+    assert_eq!(arena.num_blocks.get(), 8);
+
+    /* the vector of a file is ready to use */
+    let (mut owner, mut peer) = ChannelVector::create_pair(config.clone()).unwrap();
+    let mut producer = owner.take_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_consumer::<u64>(0).unwrap();
+
+    *producer.current_message() = 7;
+    producer.force_push();
+
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(*consumer.current_message().unwrap(), 7);
+}
+
+#[cfg(feature = "json")]
+const ROBOT_JSON: &str = r#"{
+    "info": "robot",
+    "heartbeat": true,
+    "producers": [
+        { "queue": { "message_size": 64, "additional_messages": 2, "info": "command" }, "eventfd": true },
+        { "queue": { "message_size": 32, "info": [1, 2, 3], "schema": 42 }, "kind": "state" }
+    ],
+    "consumers": [
+        { "queue": { "message_size": 8 }, "kind": "conflated" }
+    ],
+    "arena": { "block_size": 256, "num_blocks": 8 }
+}"#;
+
+#[cfg(feature = "yaml")]
+const ROBOT_YAML: &str = "
+info: robot
+heartbeat: true
+producers:
+  - queue: { message_size: 64, additional_messages: 2, info: command }
+    eventfd: true
+  - queue: { message_size: 32, info: [1, 2, 3], schema: 42 }
+    kind: state
+consumers:
+  - queue: { message_size: 8 }
+    kind: conflated
+arena:
+  block_size: 256
+  num_blocks: 8
+";
+
+#[cfg(feature = "toml")]
+const ROBOT_TOML: &str = r#"
+info = "robot"
+heartbeat = true
+arena = { block_size = 256, num_blocks = 8 }
+
+[[producers]]
+queue = { message_size = 64, additional_messages = 2, info = "command" }
+eventfd = true
+
+[[producers]]
+queue = { message_size = 32, info = [1, 2, 3], schema = 42 }
+kind = "state"
+
+[[consumers]]
+queue = { message_size = 8 }
+kind = "conflated"
+"#;
+
+#[cfg(feature = "json")]
+#[test]
+fn vectors_are_read_from_json() {
+    check_robot(&VectorConfig::from_json(ROBOT_JSON).unwrap());
+
+    assert!(matches!(
+        VectorConfig::from_json(r#"{ "producers": [{ "queue": { "message_size": 0 } }] }"#),
+        Err(LoadError::Parse(_))
+    ));
+    assert!(matches!(
+        VectorConfig::from_json(r#"{ "producers": [], "depth": 3 }"#),
+        Err(LoadError::Parse(_))
+    ));
+    assert!(matches!(
+        VectorConfig::from_json("{}"),
+        Err(LoadError::ConfigError(ConfigError::NoChannels))
+    ));
+}
+
+#[cfg(feature = "yaml")]
+#[test]
+fn vectors_are_read_from_yaml() {
+    check_robot(&VectorConfig::from_yaml(ROBOT_YAML).unwrap());
+
+    assert!(matches!(
+        VectorConfig::from_yaml("producers:\n  - queue: { message_size: 8 }\n    kind: ring\n"),
+        Err(LoadError::Parse(_))
+    ));
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn vectors_are_read_from_toml() {
+    check_robot(&VectorConfig::from_toml(ROBOT_TOML).unwrap());
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn profiles_expand_to_channels() {
+    let profiles = ChannelProfiles::from_toml(
+        r#"
+        [low-latency]
+        kind = "conflated"
+
+        [bulk]
+        additional_messages = 64
+        eventfd = true
+        alignment = 4096
+        "#,
+    )
+    .unwrap();
+
+    let mut names: Vec<&str> = profiles.names().collect();
+    names.sort();
+    assert_eq!(names, ["bulk", "low-latency"]);
+
+    let size = std::num::NonZeroUsize::new(100).unwrap();
+
+    let channel = profiles.get("bulk").unwrap().channel(size, b"frames");
+    assert_eq!(channel.queue.message_size.get(), 4096);
+    assert_eq!(channel.queue.additional_messages, 64);
+    assert_eq!(channel.queue.info, b"frames");
+    assert_eq!(channel.kind, ChannelKind::Queue);
+    assert!(channel.eventfd);
+
+    let channel = profiles.get("low-latency").unwrap().channel(size, b"");
+    assert_eq!(channel.queue.message_size.get(), 100);
+    assert_eq!(channel.queue.additional_messages, 0);
+    assert_eq!(channel.kind, ChannelKind::Conflated);
+    assert!(!channel.eventfd);
+
+    assert!(profiles.get("realtime").is_none());
+
+    assert!(matches!(
+        ChannelProfiles::from_toml("[bulk]\nalignment = 48\n"),
+        Err(LoadError::Parse(_))
+    ));
+    assert!(matches!(
+        ChannelProfiles::from_toml("[bulk]\ndepth = 4\n"),
+        Err(LoadError::Parse(_))
+    ));
+}
+
+#[test]
+fn vectors_are_loaded_by_extension() {
+    let dir = std::env::temp_dir().join(format!("rtipc-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    #[cfg(feature = "json")]
+    {
+        let path = dir.join("robot.json");
+        std::fs::write(&path, ROBOT_JSON).unwrap();
+        check_robot(&VectorConfig::load(&path).unwrap());
+    }
+
+    #[cfg(feature = "yaml")]
+    {
+        let path = dir.join("robot.yml");
+        std::fs::write(&path, ROBOT_YAML).unwrap();
+        check_robot(&VectorConfig::load(&path).unwrap());
+    }
+
+    #[cfg(feature = "toml")]
+    {
+        let path = dir.join("robot.toml");
+        std::fs::write(&path, ROBOT_TOML).unwrap();
+        check_robot(&VectorConfig::load(&path).unwrap());
+    }
+
+    assert!(matches!(
+        VectorConfig::load(dir.join("robot.ini")),
+        Err(LoadError::UnknownFormat)
+    ));
+    assert!(matches!(
+        VectorConfig::load(dir.join("missing.json")),
+        Err(LoadError::Io(_) | LoadError::UnknownFormat)
+    ));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
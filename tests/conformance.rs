@@ -0,0 +1,281 @@
+/* Requests are serialized with the layout of a virtual architecture and parsed with
+ * the cache line size of another one. Both peers run in this process, the cache line
+ * size of the receiver is global, so the matrix runs in a single test. */
+#![cfg(feature = "socket")]
+
+use std::collections::VecDeque;
+use std::os::fd::{AsFd, OwnedFd};
+
+use nix::sys::stat::fstat;
+
+use rtipc::*;
+
+mod common;
+
+/// offset of the endianness of the shared memory in the header
+const ENDIANNESS_OFFSET: usize = 8;
+const ENDIANNESS_LITTLE: u8 = 1;
+const ENDIANNESS_BIG: u8 = 2;
+
+#[derive(Clone, Copy, Debug)]
+struct Arch {
+    cacheline_size: usize,
+    index_size: usize,
+    big_endian: bool,
+}
+
+const fn arch(cacheline_size: usize, index_size: usize, big_endian: bool) -> Arch {
+    Arch {
+        cacheline_size,
+        index_size,
+        big_endian,
+    }
+}
+
+const SENDERS: [Arch; 8] = [
+    arch(32, 4, false),
+    arch(64, 4, false),
+    arch(64, 8, false),
+    arch(128, 4, false),
+    arch(128, 8, false),
+    arch(256, 8, false),
+    arch(64, 4, true),
+    arch(128, 8, true),
+];
+
+/// cache line sizes of the receivers
+const RECEIVERS: [usize; 3] = [32, 64, 128];
+
+/// Vector with every kind of part the layout of the shared memory depends on.
+fn abi_vector() -> VectorConfig {
+    common::vector()
+        .producer(2, 24)
+        .producer(0, 40)
+        .kind(ChannelKind::State)
+        .consumer(1, 8)
+        .consumer(0, 16)
+        .kind(ChannelKind::Counters)
+        .info(b"abi")
+        .arena(64, 4)
+        .heartbeat()
+        .build()
+}
+
+fn shm_size(rsc: &VectorResource) -> usize {
+    fstat(rsc.shmfd.as_fd()).unwrap().st_size as usize
+}
+
+/// Request of arch, the TLV records are little endian on every architecture,
+/// only the endianness of the shared memory differs.
+fn serialize(rsc: &VectorResource, arch: Arch) -> (Vec<u8>, VecDeque<OwnedFd>) {
+    let (mut request, fds) = rsc.serialize().unwrap();
+
+    request[ENDIANNESS_OFFSET] = if arch.big_endian {
+        ENDIANNESS_BIG
+    } else {
+        ENDIANNESS_LITTLE
+    };
+
+    let fds = fds
+        .into_iter()
+        .map(|fd| fd.try_clone_to_owned().unwrap())
+        .collect();
+
+    (request, fds)
+}
+
+fn header_error(result: Result<VectorResource, TransferError>) -> Option<HeaderError> {
+    match result {
+        Err(TransferError::RequestError(RequestError::HeaderError(e))) => Some(e),
+        Err(e) => panic!("unexpected error {e:?}"),
+        Ok(_) => None,
+    }
+}
+
+/// Messages pass in both directions, both peers place the channels at the same offsets.
+fn exchange(sender: VectorResource, receiver: VectorResource) {
+    /* the receiver initializes the shared memory */
+    let mut receiver = ChannelVector::new(receiver).unwrap();
+    let mut sender = ChannelVector::new(sender).unwrap();
+
+    let mut producer = sender.take_producer::<[u64; 3]>(0).unwrap();
+    let mut consumer = receiver.take_consumer::<[u64; 3]>(0).unwrap();
+
+    *producer.current_message() = [1, 2, 3];
+    producer.force_push();
+
+    assert!(matches!(consumer.pop(), PopResult::Success));
+    assert_eq!(*consumer.current_message().unwrap(), [1, 2, 3]);
+
+    let mut producer = receiver.take_producer::<u64>(0).unwrap();
+    let mut consumer = sender.take_consumer::<u64>(0).unwrap();
+
+    *producer.current_message() = 0x0102030405060708;
+    producer.force_push();
+
+    assert!(matches!(consumer.pop(), PopResult::Success));
+    assert_eq!(*consumer.current_message().unwrap(), 0x0102030405060708);
+}
+
+#[test]
+fn layouts_are_negotiated() {
+    let vconfig = abi_vector();
+    let native_big_endian = cfg!(target_endian = "big");
+
+    for receiver_cls in RECEIVERS {
+        set_cacheline_size(receiver_cls).unwrap();
+
+        for sender in SENDERS {
+            let rsc = VectorResource::allocate_with_layout(
+                &vconfig,
+                sender.cacheline_size,
+                sender.index_size,
+            )
+            .unwrap();
+
+            let (request, fds) = serialize(&rsc, sender);
+            let result = header_error(VectorResource::deserialize(&request, fds));
+
+            let expected = if sender.big_endian != native_big_endian {
+                Some(HeaderError::EndiannessMismatch)
+            } else if sender.cacheline_size < receiver_cls {
+                Some(HeaderError::CachelineSizeMismatch)
+            } else {
+                None
+            };
+
+            assert_eq!(
+                result, expected,
+                "{sender:?} parsed with cache lines of {receiver_cls}"
+            );
+        }
+    }
+
+    for receiver_cls in RECEIVERS {
+        set_cacheline_size(receiver_cls).unwrap();
+
+        for sender in SENDERS.iter().filter(|sender| {
+            sender.big_endian == native_big_endian && sender.cacheline_size >= receiver_cls
+        }) {
+            let rsc = VectorResource::allocate_with_layout(
+                &vconfig,
+                sender.cacheline_size,
+                sender.index_size,
+            )
+            .unwrap();
+
+            let (request, fds) = serialize(&rsc, *sender);
+            let peer = VectorResource::deserialize(&request, fds).unwrap();
+
+            assert_eq!(peer.cacheline_size, sender.cacheline_size);
+            assert_eq!(peer.index_size, sender.index_size);
+            assert_eq!(shm_size(&peer), shm_size(&rsc));
+
+            exchange(rsc, peer);
+        }
+    }
+}
+
+#[test]
+fn long_queues_map_with_u64_indices() {
+    let vconfig = common::vector().producer(40, 24).consumer(40, 8).build();
+
+    /* the 45 indices of each queue take two cache lines at 8 bytes, no receiver
+     * has cache lines larger than the sender */
+    let rsc = VectorResource::allocate_with_layout(&vconfig, 256, 8).unwrap();
+    let (request, fds) = serialize(&rsc, arch(256, 8, cfg!(target_endian = "big")));
+    let peer = VectorResource::deserialize(&request, fds).unwrap();
+
+    assert_eq!(peer.index_size, 8);
+    assert_eq!(shm_size(&peer), shm_size(&rsc));
+
+    exchange(rsc, peer);
+}
+
+#[test]
+fn headers_are_refused() {
+    let rsc = VectorResource::allocate_with_layout(&abi_vector(), 256, 4).unwrap();
+    let (request, _) = rsc.serialize().unwrap();
+
+    let refused = |patch: fn(&mut Vec<u8>)| {
+        let mut request = request.clone();
+        patch(&mut request);
+        header_error(VectorResource::deserialize(&request, VecDeque::new()))
+    };
+
+    assert_eq!(
+        refused(|r| r[0..2].copy_from_slice(&0x1f0du16.to_le_bytes())),
+        Some(HeaderError::MagicMismatch)
+    );
+    assert_eq!(
+        refused(|r| r[2..4].copy_from_slice(&10u16.to_le_bytes())),
+        Some(HeaderError::VersionMismatch)
+    );
+    assert_eq!(
+        refused(|r| r[4..6].copy_from_slice(&48u16.to_le_bytes())),
+        Some(HeaderError::InvalidCachelineSize)
+    );
+    assert_eq!(
+        refused(|r| r[6..8].copy_from_slice(&2u16.to_le_bytes())),
+        Some(HeaderError::AtomicSizeMismatch)
+    );
+    assert_eq!(
+        refused(|r| r.truncate(12)),
+        Some(HeaderError::SizeExceedsRequest)
+    );
+}
+
+/* changing one of these breaks peers running another version of the crate,
+ * sizes of vector_config with an additional queue of 43 messages */
+const GOLDEN_SHM_SIZES: [(usize, usize, usize); 5] = [
+    (32, 4, 2560),
+    (64, 4, 4608),
+    (64, 8, 4800),
+    (128, 4, 9088),
+    (128, 8, 9216),
+];
+
+const GOLDEN_REQUEST: &str = "0c1f090080000800010000000000000001000000030000006162690200010018\
+    0000000100010004000000400000000200010004000000040000000800010000\
+    0000000300010030000000010001000400000002000000020001000400000018\
+    0000000300010004000000000000000400010004000000000000000300010030\
+    0000000100010004000000000000000200010004000000280000000300010004\
+    0000000100000004000100040000000000000004000100300000000100010004\
+    0000000100000002000100040000000800000003000100040000000000000004\
+    0001000400000000000000040001003000000001000100040000000000000002\
+    0001000400000010000000030001000400000002000000040001000400000000\
+    000000060000000400000001000000";
+
+#[test]
+fn layouts_do_not_drift() {
+    let vconfig = abi_vector();
+
+    let mut sized = abi_vector();
+    sized
+        .consumers
+        .push(common::channel(ChannelKind::Queue, 40, 8, false));
+
+    for (cacheline_size, index_size, size) in GOLDEN_SHM_SIZES {
+        let rsc = VectorResource::allocate_with_layout(&sized, cacheline_size, index_size).unwrap();
+
+        assert_eq!(shm_size(&rsc), size, "layout {cacheline_size} {index_size}");
+    }
+
+    /* at 8 bytes the 45 indices of the long queue take more cache lines than at 4 bytes */
+    for (cacheline_size, index_size, size) in GOLDEN_SHM_SIZES {
+        if index_size == 8 {
+            let narrow = GOLDEN_SHM_SIZES
+                .iter()
+                .find(|(cls, index_size, _)| *cls == cacheline_size && *index_size == 4)
+                .unwrap();
+
+            assert!(size > narrow.2, "layout {cacheline_size} {index_size}");
+        }
+    }
+
+    let rsc = VectorResource::allocate_with_layout(&vconfig, 128, 8).unwrap();
+    let (request, _) = rsc.serialize().unwrap();
+    let request: String = request.iter().map(|b| format!("{b:02x}")).collect();
+
+    assert_eq!(request, GOLDEN_REQUEST.replace(' ', ""));
+}
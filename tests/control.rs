@@ -0,0 +1,121 @@
+#![cfg(feature = "socket")]
+
+use rtipc::*;
+
+mod common;
+
+/// Client and server vector connected over the control connection of a handshake.
+fn connect() -> (ChannelVector, ChannelVector) {
+    Server::unbound()
+        .unwrap()
+        .loopback(
+            common::vector().producer(1, 8).build(),
+            &ConnectOptions::default(),
+        )
+        .unwrap()
+}
+
+#[test]
+fn pings_are_answered_with_their_token() {
+    let (mut client, mut vector) = connect();
+
+    let mut client_control = client.take_control().unwrap();
+    let server_control = vector.take_control().unwrap();
+
+    client_control.ping(42).unwrap();
+
+    let ControlMessage::Ping(token) = server_control.receive().unwrap() else {
+        panic!("expected a ping");
+    };
+    server_control.send(&ControlMessage::Pong(token)).unwrap();
+
+    assert!(matches!(
+        client_control.receive().unwrap(),
+        ControlMessage::Pong(42)
+    ));
+
+    /* a peer answering pings by itself doesn't return them */
+    client_control.set_answer_pings(true);
+
+    server_control.ping(7).unwrap();
+    server_control.send(&ControlMessage::StatsQuery).unwrap();
+
+    assert!(matches!(
+        client_control.receive().unwrap(),
+        ControlMessage::StatsQuery
+    ));
+    assert!(matches!(
+        server_control.receive().unwrap(),
+        ControlMessage::Pong(7)
+    ));
+}
+
+#[test]
+fn channels_are_seen_from_the_receiver() {
+    let (mut client, mut vector) = connect();
+
+    let client_control = client.take_control().unwrap();
+    let server_control = vector.take_control().unwrap();
+
+    client_control
+        .send(&ControlMessage::CloseProducer(0))
+        .unwrap();
+    client_control
+        .send(&ControlMessage::AddProducer(common::channel(
+            ChannelKind::State,
+            2,
+            16,
+            true,
+        )))
+        .unwrap();
+
+    assert!(matches!(
+        server_control.receive().unwrap(),
+        ControlMessage::CloseConsumer(0)
+    ));
+
+    let ControlMessage::AddConsumer(config) = server_control.receive().unwrap() else {
+        panic!("expected an added consumer");
+    };
+    assert_eq!(config.kind, ChannelKind::State);
+    assert_eq!(config.queue.additional_messages, 2);
+    assert_eq!(config.queue.message_size.get(), 16);
+    assert!(config.eventfd);
+}
+
+#[test]
+fn shutdown_is_answered_with_goodbye() {
+    let (mut client, mut vector) = connect();
+
+    let client_control = client.take_control().unwrap();
+    let server_control = vector.take_control().unwrap();
+
+    server_control.shutdown().unwrap();
+    assert!(matches!(
+        client_control.receive().unwrap(),
+        ControlMessage::Shutdown
+    ));
+
+    drop(client_control);
+
+    assert!(matches!(
+        server_control.receive().unwrap(),
+        ControlMessage::Goodbye
+    ));
+
+    /* the peer disconnected */
+    assert!(server_control.receive().is_err());
+}
+
+#[test]
+fn dropped_vector_says_goodbye() {
+    let (client, mut vector) = connect();
+    let server_control = vector.take_control().unwrap();
+
+    drop(client);
+
+    assert!(matches!(
+        server_control.receive().unwrap(),
+        ControlMessage::Goodbye
+    ));
+}
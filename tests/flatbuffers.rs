@@ -0,0 +1,75 @@
+#![cfg(feature = "flatbuffers")]
+
+use rtipc::flatbuffers::{FlatBufferBuilder, ForwardsUOffset, Vector};
+use rtipc::*;
+
+mod common;
+
+#[test]
+fn roots_are_read_in_place() {
+    let (mut owner, mut peer) =
+        ChannelVector::create_pair(common::vector().producer(2, 256).build()).unwrap();
+
+    let mut producer = owner.take_flatbuf_producer(0).unwrap();
+    let mut consumer = peer.take_flatbuf_consumer(0).unwrap();
+
+    let mut builder = FlatBufferBuilder::new();
+
+    for round in 0..5u64 {
+        builder.reset();
+        let values = builder.create_vector(&[round, round + 1, round + 2]);
+        builder.finish_minimal(values);
+
+        producer.write(builder.finished_data()).unwrap();
+        producer.force_push();
+
+        assert!(consumer.pop() == PopResult::Success);
+
+        let root = consumer.root::<Vector<u64>>().unwrap().unwrap();
+        assert_eq!(
+            root.iter().collect::<Vec<u64>>(),
+            [round, round + 1, round + 2]
+        );
+    }
+
+    builder.reset();
+    let strings = [builder.create_string("in"), builder.create_string("place")];
+    let strings = builder.create_vector(&strings);
+    builder.finish_minimal(strings);
+
+    producer.write(builder.finished_data()).unwrap();
+    producer.force_push();
+
+    assert!(consumer.pop() == PopResult::Success);
+
+    let root = consumer
+        .root::<Vector<ForwardsUOffset<&str>>>()
+        .unwrap()
+        .unwrap();
+    assert_eq!(root.iter().collect::<Vec<&str>>(), ["in", "place"]);
+}
+
+#[test]
+fn invalid_buffers_are_refused() {
+    let (mut owner, mut peer) =
+        ChannelVector::create_pair(common::vector().producer(2, 64).build()).unwrap();
+
+    let mut producer = owner.take_flatbuf_producer(0).unwrap();
+    let mut consumer = peer.take_flatbuf_consumer(0).unwrap();
+
+    let mut builder = FlatBufferBuilder::new();
+    let values = builder.create_vector(&[0u64; 16]);
+    builder.finish_minimal(values);
+
+    assert!(matches!(
+        producer.write(builder.finished_data()),
+        Err(ShmMapError::OutOfBounds)
+    ));
+
+    /* the root offset points behind the buffer */
+    producer.write(&[0xff, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+    producer.force_push();
+
+    assert!(consumer.pop() == PopResult::Success);
+    assert!(consumer.root::<Vector<u64>>().unwrap().is_err());
+}
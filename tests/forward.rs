@@ -0,0 +1,107 @@
+/* a client relaying the messages of one channel of a vector to another */
+use rtipc::*;
+
+mod common;
+
+/// Vector of a relay, the peer produces on channel 0, the relay produces on channel 0
+/// of the other direction.
+fn relay_vector(additional_messages: usize) -> VectorConfig {
+    common::vector()
+        .producer(additional_messages, 8)
+        .consumer(additional_messages, 8)
+        .build()
+}
+
+#[test]
+fn messages_are_forwarded_within_a_vector() {
+    let (mut peer, mut relay) = ChannelVector::create_pair(relay_vector(2)).unwrap();
+
+    let mut producer = peer.take_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_consumer::<u64>(0).unwrap();
+    let mut relay_consumer = relay.take_consumer::<u64>(0).unwrap();
+    let mut relay_producer = relay.take_producer::<u64>(0).unwrap();
+
+    /* nothing to forward, the result of pop is returned */
+    assert!(
+        relay_consumer.force_forward(&mut relay_producer)
+            == Err(ForwardError::Pop(PopResult::NoMessage))
+    );
+    assert!(consumer.pop() == PopResult::NoMessage);
+
+    for value in [7, 8] {
+        *producer.current_message() = value;
+        producer.force_push();
+    }
+
+    for value in [7, 8] {
+        assert!(relay_consumer.force_forward(&mut relay_producer) == Ok(ForcePushResult::Success));
+        assert!(consumer.pop() == PopResult::Success);
+        assert_eq!(consumer.current_message(), Some(&value));
+    }
+
+    /* the last message isn't forwarded again */
+    assert!(
+        relay_consumer.try_forward(&mut relay_producer)
+            == Err(ForwardError::Pop(PopResult::NoNewMessage))
+    );
+    assert!(consumer.pop() == PopResult::NoNewMessage);
+}
+
+#[test]
+fn try_forward_keeps_the_message_while_full() {
+    let (mut peer, mut relay) = ChannelVector::create_pair(relay_vector(0)).unwrap();
+
+    let mut producer = peer.take_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_consumer::<u64>(0).unwrap();
+    let mut relay_consumer = relay.take_consumer::<u64>(0).unwrap();
+    let mut relay_producer = relay.take_producer::<u64>(0).unwrap();
+
+    let mut forwarded = 0;
+
+    for value in 0..3 {
+        *producer.current_message() = value;
+        assert!(producer.try_push() == TryPushResult::Success);
+
+        if relay_consumer.try_forward(&mut relay_producer) == Ok(TryPushResult::Success) {
+            forwarded += 1;
+        }
+    }
+
+    /* the queue of the relay filled up, the rest waits in the queue of the peer */
+    assert!(relay_consumer.try_forward(&mut relay_producer) == Ok(TryPushResult::QueueFull));
+
+    for value in 0..forwarded {
+        assert!(consumer.pop() == PopResult::Success);
+        assert_eq!(consumer.current_message(), Some(&value));
+    }
+
+    while let Ok(TryPushResult::Success) = relay_consumer.try_forward(&mut relay_producer) {
+        assert!(consumer.pop() == PopResult::Success);
+        assert_eq!(consumer.current_message(), Some(&forwarded));
+        forwarded += 1;
+    }
+
+    assert_eq!(forwarded, 3);
+}
+
+#[test]
+fn separate_vectors_are_refused() {
+    let vconfig = common::vector().producer(2, 8).build();
+
+    let (mut source, mut relay_in) = ChannelVector::create_pair(vconfig.clone()).unwrap();
+    let (mut relay_out, _sink) = ChannelVector::create_pair(vconfig).unwrap();
+
+    let mut producer = source.take_producer::<u64>(0).unwrap();
+    let mut relay_consumer = relay_in.take_consumer::<u64>(0).unwrap();
+    let mut relay_producer = relay_out.take_producer::<u64>(0).unwrap();
+
+    *producer.current_message() = 7;
+    producer.force_push();
+
+    assert!(relay_consumer.force_forward(&mut relay_producer) == Err(ForwardError::SeparateMemory));
+    assert!(relay_consumer.try_forward(&mut relay_producer) == Err(ForwardError::SeparateMemory));
+
+    /* the message wasn't popped */
+    assert!(relay_consumer.pop() == PopResult::Success);
+    assert_eq!(relay_consumer.current_message(), Some(&7));
+}
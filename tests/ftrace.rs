@@ -0,0 +1,42 @@
+#![cfg(feature = "ftrace")]
+
+use rtipc::*;
+
+#[test]
+fn markers_are_written_once_enabled() {
+    let config = Topics::new()
+        .publish::<u64>("pose", TopicQos::default())
+        .unwrap()
+        .into_config();
+
+    let (mut owner, mut peer) = ChannelVector::create_pair(config).unwrap();
+
+    let mut producer = owner.take_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_consumer::<u64>(0).unwrap();
+
+    /* markers go to a plain file instead of the trace_marker of tracefs */
+    let path = std::env::temp_dir().join(format!("rtipc-marker-{}", std::process::id()));
+    std::fs::write(&path, "").unwrap();
+
+    producer.force_push();
+    assert!(!ftrace::is_enabled());
+
+    ftrace::enable_at(&path).unwrap();
+    assert!(ftrace::is_enabled());
+
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+
+    ftrace::disable();
+    producer.force_push();
+
+    let markers = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    let lines: Vec<&str> = markers.lines().collect();
+    assert_eq!(lines.len(), 2, "{markers}");
+    assert!(lines[0].starts_with("rtipc: push queue=0x"));
+    assert!(lines[0].ends_with(" depth=2"));
+    assert!(lines[1].starts_with("rtipc: pop queue=0x"));
+    assert!(lines[1].ends_with(" size=8"));
+}
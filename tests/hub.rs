@@ -0,0 +1,139 @@
+#![cfg(feature = "socket")]
+
+use std::os::fd::BorrowedFd;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use nix::sys::socket::Backlog;
+
+use rtipc::*;
+
+mod common;
+
+fn hub() -> BroadcastHub<u64> {
+    BroadcastHub::new(common::queue(2, 8)).unwrap()
+}
+
+fn readable(eventfd: BorrowedFd<'_>, timeout: u16) -> bool {
+    let mut fds = [PollFd::new(eventfd, PollFlags::POLLIN)];
+    poll(&mut fds, PollTimeout::from(timeout)).unwrap() == 1
+}
+
+/// Subscribes a client and waits for the messages 0, 1 and 2, woken by its eventfd.
+fn subscribe_as_child(path: &str) {
+    let mut vec = client_connect_info(path, b"child", &ConnectOptions::default()).unwrap();
+    let mut consumer = vec.take_broadcast_consumer::<u64>(0).unwrap();
+
+    let mut received = Vec::new();
+
+    while received.len() < 3 {
+        assert!(readable(consumer.eventfd().unwrap(), 5000));
+
+        if consumer.pop() == PopResult::Success {
+            received.push(*consumer.current_message().unwrap());
+        }
+    }
+
+    assert_eq!(received, [0, 1, 2]);
+}
+
+#[test]
+fn every_client_process_gets_every_message() {
+    if let Ok(path) = std::env::var("RTIPC_HUB_PATH") {
+        subscribe_as_child(&path);
+        return;
+    }
+
+    let path = common::socket_path("hub-processes");
+    let server = Server::new(path.as_path(), Backlog::new(2).unwrap()).unwrap();
+    let mut hub = hub();
+
+    let mut children: Vec<_> = (0..2)
+        .map(|_| {
+            Command::new(std::env::current_exe().unwrap())
+                .args(["every_client_process_gets_every_message", "--exact"])
+                .env("RTIPC_HUB_PATH", &path)
+                .stdout(Stdio::null())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+
+    let subscribed: Vec<_> = (0..2)
+        .map(|_| server.accept_broadcast(&mut hub, |_, _| Ok(())).unwrap())
+        .collect();
+
+    assert!(subscribed.iter().all(|(_, peer)| peer.info == b"child"));
+    assert_eq!(hub.subscribers(), 2);
+
+    for value in 0..3 {
+        *hub.current_message() = value;
+        hub.push();
+    }
+
+    for child in children.iter_mut() {
+        assert!(child.wait().unwrap().success());
+    }
+}
+
+#[test]
+fn dropped_clients_are_unsubscribed() {
+    let path = common::socket_path("hub-drop");
+    let server = Server::new(path.as_path(), Backlog::new(2).unwrap()).unwrap();
+    let mut hub = hub();
+
+    let mut subscribe = || {
+        let path = path.clone();
+        let client = thread::spawn(move || {
+            client_connect_info(path.as_path(), b"", &ConnectOptions::default())
+        });
+        let (vector, _) = server.accept_broadcast(&mut hub, |_, _| Ok(())).unwrap();
+        (client.join().unwrap().unwrap(), vector)
+    };
+
+    let (mut first, first_vector) = subscribe();
+    let (mut second, _second_vector) = subscribe();
+
+    let mut first = first.take_broadcast_consumer::<u64>(0).unwrap();
+    let mut second = second.take_broadcast_consumer::<u64>(0).unwrap();
+
+    drop(first_vector);
+    assert_eq!(hub.subscribers(), 1);
+
+    *hub.current_message() = 7;
+    hub.push();
+
+    /* the message is in the shared memory, only the subscribed client is woken */
+    assert!(readable(second.eventfd().unwrap(), 1000));
+    assert!(!readable(first.eventfd().unwrap(), 0));
+
+    for consumer in [&mut first, &mut second] {
+        assert!(consumer.pop() == PopResult::Success);
+        assert_eq!(consumer.current_message(), Some(&7));
+    }
+}
+
+#[test]
+fn clients_defining_their_vector_are_refused() {
+    let path = common::socket_path("hub-refused");
+    let server = Server::new(path.as_path(), Backlog::new(1).unwrap()).unwrap();
+    let mut hub = hub();
+
+    let client = {
+        let path = path.clone();
+        thread::spawn(move || {
+            client_connect(path.as_path(), common::vector().producer(0, 8).build())
+        })
+    };
+
+    assert!(matches!(
+        server.accept_broadcast(&mut hub, |_, _| Ok(())),
+        Err(TransferError::Rejected(_))
+    ));
+    assert!(matches!(
+        client.join().unwrap(),
+        Err(TransferError::Rejected(_))
+    ));
+    assert_eq!(hub.subscribers(), 0);
+}
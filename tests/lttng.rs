@@ -0,0 +1,45 @@
+#![cfg(all(feature = "lttng", target_os = "linux"))]
+
+use rtipc::*;
+
+/* the tracepoints defined by lttng/rtipc_tp.c are named provider:event */
+fn has_tracepoint(exe: &[u8], name: &str) -> bool {
+    let tracepoint = format!("rtipc:{name}\0");
+    exe.windows(tracepoint.len())
+        .any(|w| w == tracepoint.as_bytes())
+}
+
+#[test]
+fn tracepoints_fire_without_a_session() {
+    let config = Topics::new()
+        .publish::<u64>(
+            "pose",
+            TopicQos {
+                depth: 2,
+                eventfd: true,
+                ..TopicQos::default()
+            },
+        )
+        .unwrap()
+        .into_config();
+
+    let (mut owner, mut peer) = ChannelVector::create_pair(config).unwrap();
+
+    let mut producer = owner.take_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_consumer::<u64>(0).unwrap();
+
+    /* push, discard and eventfd_write, then pop and overrun */
+    for i in 0..4 {
+        *producer.current_message() = i;
+        producer.force_push();
+    }
+
+    assert!(consumer.pop() == PopResult::SuccessMessagesDiscarded);
+    assert_eq!(consumer.current_message(), Some(&2));
+
+    let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+
+    for name in ["push", "pop", "discard", "overrun", "eventfd_write"] {
+        assert!(has_tracepoint(&exe, name), "{name}");
+    }
+}
@@ -0,0 +1,81 @@
+/* macOS replaces memfds, eventfds and seqpacket sockets, see src/macos.rs */
+#![cfg(all(feature = "socket", target_os = "macos"))]
+
+use std::fs::File;
+
+use nix::errno::Errno;
+use nix::sys::stat::fstat;
+
+use rtipc::*;
+
+mod common;
+
+/// Queues in both directions, notified through FIFOs.
+fn fifo_vector() -> VectorConfig {
+    common::vector()
+        .producer(1, 8)
+        .eventfd()
+        .consumer(1, 8)
+        .eventfd()
+        .build()
+}
+
+#[test]
+fn vectors_are_connected_over_stream_sockets() {
+    let (mut client, mut vector, peer) =
+        common::connect("macos", fifo_vector(), ConnectOptions::default());
+
+    /* LOCAL_PEERPID and LOCAL_PEERCRED */
+    assert_eq!(peer.credentials.pid as u32, std::process::id());
+    assert_eq!(peer.credentials.uid, unsafe { nix::libc::getuid() });
+    assert_eq!(peer.credentials.gid, unsafe { nix::libc::getgid() });
+
+    let mut producer = client.take_producer::<u64>(0).unwrap();
+    let mut consumer = vector.take_consumer::<u64>(0).unwrap();
+
+    *producer.current_message() = 1;
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&1));
+
+    let mut producer = vector.take_producer::<u64>(0).unwrap();
+    let mut consumer = client.take_consumer::<u64>(0).unwrap();
+
+    *producer.current_message() = 2;
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&2));
+}
+
+#[test]
+fn shm_objects_are_sized_once() {
+    let vconfig = fifo_vector();
+    let rsc = VectorResource::allocate(&vconfig).unwrap();
+
+    /* fstat reports no file type for the unlinked object */
+    let stat = fstat(rsc.shmfd()).unwrap();
+    assert_eq!(stat.st_mode & nix::libc::S_IFMT, 0);
+    assert!(stat.st_size as usize >= vconfig.calc_shm_size());
+
+    let (client, _) = Server::unbound()
+        .unwrap()
+        .loopback(vconfig.clone(), &ConnectOptions::default())
+        .unwrap();
+    assert_eq!(client.total_shm_size(), vconfig.calc_shm_size());
+}
+
+#[test]
+fn linux_only_features_are_unsupported() {
+    let mut rsc = VectorResource::allocate(&fifo_vector()).unwrap();
+    assert_eq!(rsc.preallocate(), Err(Errno::EOPNOTSUPP));
+
+    let fd = File::open("/dev/null").unwrap().into();
+    assert_eq!(rsc.add_dmabuf(fd), Err(Errno::EOPNOTSUPP));
+
+    let options = ConnectOptions {
+        preallocate: true,
+        ..Default::default()
+    };
+    let result = Server::unbound().unwrap().loopback(fifo_vector(), &options);
+    assert!(result.is_err());
+}
@@ -0,0 +1,269 @@
+/* the options of the mappings are checked in /proc/self/smaps */
+#![cfg(all(feature = "socket", target_os = "linux"))]
+
+use std::os::fd::AsFd;
+
+use rtipc::*;
+
+mod common;
+
+/// Entry of /proc/self/smaps, the sizes in bytes.
+#[derive(Debug, Default)]
+struct Mapping {
+    start: usize,
+    size: usize,
+    rss: usize,
+    flags: Vec<String>,
+}
+
+impl Mapping {
+    /// flag of VmFlags, e.g. lo for locked
+    fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f == flag)
+    }
+}
+
+/// Mapping holding addr.
+fn mapping(addr: usize) -> Mapping {
+    let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+    let kib = |value: Option<&str>| value.unwrap().parse::<usize>().unwrap() * 1024;
+
+    let mut mapping = None;
+
+    for line in smaps.lines() {
+        let mut fields = line.split_whitespace();
+        let key = fields.next().unwrap_or_default();
+
+        /* the first line of an entry is the address range */
+        let range = key.split_once('-').and_then(|(start, end)| {
+            let start = usize::from_str_radix(start, 16).ok()?;
+            let end = usize::from_str_radix(end, 16).ok()?;
+            Some(start..end)
+        });
+
+        if let Some(range) = range {
+            mapping = range.contains(&addr).then(|| Mapping {
+                start: range.start,
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let Some(mapping) = mapping.as_mut() else {
+            continue;
+        };
+
+        match key {
+            "Size:" => mapping.size = kib(fields.next()),
+            "Rss:" => mapping.rss = kib(fields.next()),
+            "VmFlags:" => {
+                mapping.flags = fields.map(String::from).collect();
+                return std::mem::take(mapping);
+            }
+            _ => {}
+        }
+    }
+
+    panic!("no mapping at {addr:x}");
+}
+
+/// Mapping of the shared memory the producer of the vector writes to.
+fn producer_mapping(vector: &mut ChannelVector) -> Mapping {
+    let mut producer = vector.take_producer::<u64>(0).unwrap();
+    mapping(producer.current_message() as *mut u64 as usize)
+}
+
+/// Queue of 100 pages, most of them untouched by the handshake.
+fn large_vector() -> VectorConfig {
+    common::vector().producer(100, 4096).build()
+}
+
+#[test]
+fn prefaulted_mappings_are_resident() {
+    let options = ConnectOptions {
+        prefault: true,
+        ..Default::default()
+    };
+
+    let (mut client, _) = Server::unbound()
+        .unwrap()
+        .loopback(large_vector(), &options)
+        .unwrap();
+
+    let mapping = producer_mapping(&mut client);
+    assert_eq!(mapping.rss, mapping.size);
+
+    /* the server prefaults its mapping as well */
+    let mut server = Server::unbound().unwrap();
+    server.set_prefault(true);
+
+    let (_, mut vector) = server
+        .loopback(
+            common::vector().consumers(large_vector().producers).build(),
+            &ConnectOptions::default(),
+        )
+        .unwrap();
+
+    let mapping = producer_mapping(&mut vector);
+    assert_eq!(mapping.rss, mapping.size);
+
+    /* otherwise only the touched pages are resident */
+    let (mut client, _) = Server::unbound()
+        .unwrap()
+        .loopback(large_vector(), &ConnectOptions::default())
+        .unwrap();
+
+    let mapping = producer_mapping(&mut client);
+    assert!(mapping.rss < mapping.size, "{mapping:?}");
+}
+
+#[test]
+fn locked_mappings_are_reported() {
+    let options = ConnectOptions {
+        lock: true,
+        ..Default::default()
+    };
+
+    let mut server = Server::unbound().unwrap();
+    server.set_lock(true);
+
+    let vconfig = common::vector().producer(10, 64).build();
+    let (mut client, vector) = server.loopback(vconfig.clone(), &options).unwrap();
+
+    for vector in [&client, &vector] {
+        let report = vector.memory_report();
+        assert_eq!(report.locked, report.mapped);
+    }
+
+    /* the pages are faulted in by mlock */
+    let mapping = producer_mapping(&mut client);
+    assert!(mapping.has_flag("lo"));
+    assert_eq!(mapping.rss, mapping.size);
+
+    let (mut client, vector) = Server::unbound()
+        .unwrap()
+        .loopback(vconfig, &ConnectOptions::default())
+        .unwrap();
+
+    assert_eq!(vector.memory_report().locked, 0);
+    let mapping = producer_mapping(&mut client);
+    assert!(!mapping.has_flag("lo"));
+}
+
+#[test]
+fn mappings_are_excluded_from_core_dumps() {
+    let options = |core_dump| ConnectOptions {
+        core_dump,
+        ..Default::default()
+    };
+
+    let mut server = Server::unbound().unwrap();
+    server.set_core_dump(false);
+
+    let vconfig = common::vector().producer(1, 8).consumer(1, 8).build();
+
+    let (mut client, mut vector) = server
+        .loopback(vconfig.clone(), &options(Some(false)))
+        .unwrap();
+    assert!(producer_mapping(&mut client).has_flag("dd"));
+    assert!(producer_mapping(&mut vector).has_flag("dd"));
+
+    /* the kernel decides by default */
+    let server = Server::unbound().unwrap();
+
+    for core_dump in [None, Some(true)] {
+        let (mut client, mut vector) = server
+            .loopback(vconfig.clone(), &options(core_dump))
+            .unwrap();
+        assert!(!producer_mapping(&mut client).has_flag("dd"));
+        assert!(!producer_mapping(&mut vector).has_flag("dd"));
+    }
+}
+
+/// Size of a transparent huge page on x86-64 and arm64
+const HUGEPAGE_SIZE: usize = 2 << 20;
+
+/// Without transparent huge pages the advice fails, the mapping is aligned anyway.
+fn has_transparent_hugepages() -> bool {
+    std::path::Path::new("/sys/kernel/mm/transparent_hugepage").exists()
+}
+
+#[test]
+fn large_mappings_are_advised_for_huge_pages() {
+    let options = |hugepage_threshold| ConnectOptions {
+        hugepage_threshold,
+        ..Default::default()
+    };
+
+    let server = Server::unbound().unwrap();
+    let size = large_vector().calc_shm_size();
+
+    let (mut client, _) = server
+        .loopback(large_vector(), &options(Some(size)))
+        .unwrap();
+
+    /* the channels line up with the huge pages of the mapping */
+    let mapping = producer_mapping(&mut client);
+    assert_eq!(mapping.start % HUGEPAGE_SIZE, 0);
+    assert_eq!(mapping.has_flag("hg"), has_transparent_hugepages());
+
+    /* smaller regions and no threshold get regular pages */
+    for threshold in [Some(size + 1), None] {
+        let (mut client, _) = server
+            .loopback(large_vector(), &options(threshold))
+            .unwrap();
+        assert!(!producer_mapping(&mut client).has_flag("hg"));
+    }
+
+    /* the server advises its mapping as well */
+    let mut server = Server::unbound().unwrap();
+    server.set_hugepage_threshold(0);
+
+    let (_, mut vector) = server
+        .loopback(
+            common::vector().consumers(large_vector().producers).build(),
+            &ConnectOptions::default(),
+        )
+        .unwrap();
+
+    let mapping = producer_mapping(&mut vector);
+    assert_eq!(mapping.start % HUGEPAGE_SIZE, 0);
+    assert_eq!(mapping.has_flag("hg"), has_transparent_hugepages());
+}
+
+/// Bytes allocated for the file of fd.
+fn allocated(fd: std::os::fd::BorrowedFd<'_>) -> usize {
+    nix::sys::stat::fstat(fd).unwrap().st_blocks as usize * 512
+}
+
+#[test]
+fn shm_is_preallocated() {
+    let vconfig = large_vector();
+
+    let rsc = VectorResource::allocate(&vconfig).unwrap();
+    assert_eq!(allocated(rsc.shmfd()), 0);
+
+    rsc.preallocate().unwrap();
+    assert!(allocated(rsc.shmfd()) >= vconfig.calc_shm_size());
+
+    /* the client preallocates the file it creates */
+    let path = std::env::temp_dir().join(format!("rtipc-preallocate-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let options = ConnectOptions {
+        backing: ShmBacking::File(path.clone()),
+        preallocate: true,
+        ..Default::default()
+    };
+
+    let mut server = Server::unbound().unwrap();
+    server.set_file_backing(true);
+
+    let vectors = server.loopback(vconfig.clone(), &options).unwrap();
+
+    let file = std::fs::File::open(&path).unwrap();
+    assert!(allocated(file.as_fd()) >= vconfig.calc_shm_size());
+
+    drop(vectors);
+    std::fs::remove_file(&path).unwrap();
+}
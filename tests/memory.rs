@@ -0,0 +1,73 @@
+use rtipc::*;
+
+mod common;
+
+/// Vector with every part taking shared memory.
+fn full_vector() -> VectorConfig {
+    common::vector()
+        .producer(10, 64)
+        .eventfd()
+        .consumer(2, 24)
+        .kind(ChannelKind::State)
+        .arena(64, 4)
+        .heartbeat()
+        .build()
+}
+
+#[test]
+fn shm_size_is_known_before_connecting() {
+    let vconfig = full_vector();
+
+    let (vector, peer) = ChannelVector::create_pair(vconfig.clone()).unwrap();
+    assert_eq!(vector.total_shm_size(), vconfig.calc_shm_size());
+    assert_eq!(peer.total_shm_size(), vconfig.calc_shm_size());
+
+    /* the arena and the heartbeat stamps take their share */
+    let channels: usize = vconfig
+        .producers
+        .iter()
+        .chain(vconfig.consumers.iter())
+        .map(ChannelConfig::calc_shm_size)
+        .sum();
+    assert!(channels < vconfig.calc_shm_size());
+
+    let plain = common::vector()
+        .producers(vconfig.producers.clone())
+        .consumers(vconfig.consumers.clone())
+        .build();
+    assert_eq!(plain.calc_shm_size(), channels);
+}
+
+#[test]
+fn memory_report_lists_the_channels() {
+    let vconfig = full_vector();
+    let (mut vector, peer) = ChannelVector::create_pair(vconfig.clone()).unwrap();
+
+    let report = vector.memory_report();
+    assert_eq!(report.used, vconfig.calc_shm_size());
+    assert!(report.mapped >= report.used);
+    assert_eq!((report.locked, report.dmabufs), (0, 0));
+
+    let queue = ChannelUsage {
+        kind: ChannelKind::Queue,
+        slots: 13,
+        slot_size: cacheline_aligned(64),
+        shm_size: vconfig.producers[0].calc_shm_size(),
+    };
+    assert_eq!(report.producers, vec![queue.clone()]);
+    assert_eq!(report.consumers.len(), 1);
+    assert_eq!(report.consumers[0].kind, ChannelKind::State);
+    assert_eq!(
+        report.consumers[0].shm_size,
+        vconfig.consumers[0].calc_shm_size()
+    );
+
+    /* the peer sees the channels swapped */
+    let peer_report = peer.memory_report();
+    assert_eq!(peer_report.consumers, vec![queue]);
+    assert_eq!(peer_report.producers, report.consumers);
+
+    /* taken channels are still part of the vector */
+    let _producer = vector.take_producer::<[u8; 64]>(0).unwrap();
+    assert_eq!(vector.memory_report(), report);
+}
@@ -0,0 +1,226 @@
+/* the datagram fragmentation isn't used on the stream sockets of macOS */
+#![cfg(all(feature = "socket", not(target_os = "macos")))]
+
+use std::os::fd::{AsFd, BorrowedFd};
+use std::thread;
+
+use nix::sys::socket::Backlog;
+
+use rtipc::*;
+
+mod common;
+
+fn message(len: usize, seed: u8) -> Vec<u8> {
+    (0..len).map(|i| (i as u8).wrapping_add(seed) | 1).collect()
+}
+
+#[test]
+fn sequential_messages_are_consumed() {
+    let (tx, rx) = common::seqpacket_pair();
+    let eventfd = EventFd::new().unwrap();
+
+    let sizes = [16, 0x30000, 1, 300];
+
+    let sender = thread::spawn(move || {
+        let mut transport = UnixTransport::new(tx.as_fd());
+        let fds: Vec<BorrowedFd<'_>> = vec![eventfd.as_fd(); 300];
+
+        for (seed, len) in sizes.iter().enumerate() {
+            /* the last message needs fd continuations */
+            let attached = if *len == 300 { &fds[..] } else { &fds[..seed] };
+            transport
+                .send_request(&message(*len, seed as u8), attached)
+                .unwrap();
+        }
+    });
+
+    let mut transport = UnixTransport::new(rx.as_fd());
+
+    for (seed, len) in sizes.iter().enumerate() {
+        let msg = transport.recv_request(None).unwrap();
+        assert_eq!(msg, message(*len, seed as u8));
+
+        let num_fds = if *len == 300 { 300 } else { seed };
+        assert_eq!(transport.recv_fds(num_fds).unwrap().len(), num_fds);
+    }
+
+    sender.join().unwrap();
+}
+
+#[test]
+fn control_messages_follow_the_handshake() {
+    let server = Server::unbound().unwrap();
+
+    let (mut client, mut vector) = server
+        .loopback(
+            common::vector()
+                .producer(2, 8)
+                .eventfd()
+                .info(b"messages")
+                .build(),
+            &ConnectOptions::default(),
+        )
+        .unwrap();
+
+    let client_control = client.take_control().unwrap();
+    let server_control = vector.take_control().unwrap();
+
+    for token in 1..4 {
+        client_control.ping(token).unwrap();
+    }
+
+    for token in 1..4 {
+        match server_control.receive().unwrap() {
+            ControlMessage::Ping(received) => assert_eq!(received, token),
+            _ => panic!("expected ping {token}"),
+        }
+    }
+
+    drop(client_control);
+
+    assert!(matches!(
+        server_control.receive().unwrap(),
+        ControlMessage::Goodbye
+    ));
+}
+
+#[test]
+fn server_defined_vector_is_acknowledged() {
+    let path = std::env::temp_dir().join(format!("rtipc-messages-{}.sock", std::process::id()));
+    let server = Server::new(path.as_path(), Backlog::new(1).unwrap()).unwrap();
+
+    let client_path = path.clone();
+    let client = thread::spawn(move || {
+        client_connect_info(client_path.as_path(), b"query", &ConnectOptions::default())
+    });
+
+    let (mut vector, peer) = server
+        .accept_with_layout(|info| {
+            assert_eq!(info, b"query");
+            Ok(common::vector()
+                .producer(2, 8)
+                .eventfd()
+                .info(b"messages")
+                .build())
+        })
+        .unwrap();
+
+    let mut client = client.join().unwrap().unwrap();
+
+    assert_eq!(peer.info, b"query");
+    assert_eq!(client.server_info(), b"messages");
+
+    let mut producer = vector.take_producer::<u64>(0).unwrap();
+    let mut consumer = client.take_consumer::<u64>(0).unwrap();
+
+    for value in 0..3 {
+        *producer.current_message() = value;
+        producer.force_push();
+        assert!(consumer.pop() == PopResult::Success);
+        assert_eq!(*consumer.current_message().unwrap(), value);
+    }
+}
+
+#[test]
+fn older_clients_are_answered_in_their_version() {
+    let server = Server::unbound().unwrap();
+
+    let options = ConnectOptions {
+        version: Some(MIN_VERSION),
+        ..Default::default()
+    };
+
+    let (mut client, mut vector) = server
+        .loopback(
+            common::vector()
+                .producer(2, 8)
+                .eventfd()
+                .info(b"messages")
+                .build(),
+            &options,
+        )
+        .unwrap();
+
+    let mut producer = client.take_producer::<u64>(0).unwrap();
+    let mut consumer = vector.take_consumer::<u64>(0).unwrap();
+
+    *producer.current_message() = 8;
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(*consumer.current_message().unwrap(), 8);
+
+    /* the control connection keeps the version of the handshake */
+    let client_control = client.take_control().unwrap();
+    let server_control = vector.take_control().unwrap();
+
+    server_control.ping(1).unwrap();
+    assert!(matches!(
+        client_control.receive().unwrap(),
+        ControlMessage::Ping(1)
+    ));
+}
+
+#[test]
+fn unknown_versions_are_refused() {
+    let server = Server::unbound().unwrap();
+
+    for version in [MIN_VERSION - 1, RTIC_VERSION + 1] {
+        let options = ConnectOptions {
+            version: Some(version),
+            ..Default::default()
+        };
+
+        assert!(
+            server
+                .loopback(
+                    common::vector()
+                        .producer(2, 8)
+                        .eventfd()
+                        .info(b"messages")
+                        .build(),
+                    &options
+                )
+                .is_err()
+        );
+    }
+}
+
+/* requests of librtipc 0.5.1 are older than the TLV protocol and MIN_VERSION */
+#[cfg(all(target_endian = "little", target_os = "linux"))]
+#[test]
+fn baseline_clients_are_accepted() {
+    use std::os::fd::AsRawFd;
+
+    use nix::sys::socket::{AddressFamily, SockFlag, SockType, UnixAddr, connect, socket};
+
+    let path = common::socket_path("baseline");
+    let server = Server::new(path.as_path(), Backlog::new(1).unwrap()).unwrap();
+
+    let client = thread::spawn(move || {
+        let socket = socket(
+            AddressFamily::Unix,
+            SockType::SeqPacket,
+            SockFlag::SOCK_CLOEXEC,
+            None,
+        )
+        .unwrap();
+        connect(socket.as_raw_fd(), &UnixAddr::new(path.as_path()).unwrap()).unwrap();
+
+        let fds = common::baseline_fds(&common::baseline_config());
+        let fds: Vec<BorrowedFd<'_>> = fds.iter().map(|fd| fd.as_fd()).collect();
+
+        let mut transport = UnixTransport::new(socket.as_fd());
+        transport
+            .send_request(&common::baseline_request(), &fds)
+            .unwrap();
+        transport.recv_response(None).unwrap()
+    });
+
+    let (mut vector, peer) = server.accept().unwrap();
+
+    /* the 4 byte success response of librtipc 0.5.1 */
+    assert_eq!(client.join().unwrap(), [0; 4]);
+    assert_eq!(peer.info, b"baseline");
+    assert!(vector.take_control().is_none());
+    assert!(vector.take_consumer::<u64>(0).unwrap().eventfd().is_some());
+}
@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use rtipc::*;
+
+fn vector_config() -> VectorConfig {
+    let depth3 = TopicQos {
+        depth: 3,
+        ..TopicQos::default()
+    };
+
+    Topics::new()
+        .publish::<u64>("polled", depth3)
+        .unwrap()
+        .publish::<u64>(
+            "notified",
+            TopicQos {
+                eventfd: true,
+                ..depth3
+            },
+        )
+        .unwrap()
+        .publish::<u64>("unused", depth3)
+        .unwrap()
+        .into_config()
+}
+
+#[test]
+fn queues_are_counted() {
+    let (mut owner, mut peer) = ChannelVector::create_pair(vector_config()).unwrap();
+
+    let mut producer = owner.take_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_consumer::<u64>(0).unwrap();
+
+    for i in 0..3 {
+        *producer.current_message() = i;
+        assert!(producer.try_push() == TryPushResult::Success);
+    }
+    assert!(producer.try_push() == TryPushResult::QueueFull);
+
+    let metrics = owner.metrics().producers[0].clone();
+    assert_eq!(metrics.pushes, 3);
+    assert_eq!(metrics.queue_full, 1);
+    assert_eq!(metrics.max_depth, 3);
+    assert_eq!(metrics.discards, 0);
+
+    for _ in 0..3 {
+        assert!(consumer.pop() == PopResult::Success);
+    }
+    assert!(consumer.pop() == PopResult::NoNewMessage);
+
+    assert_eq!(peer.metrics().consumers[0].pops, 3);
+
+    /* the consumer keeps the last message, a single push after it is queued */
+    assert!(producer.force_push() == ForcePushResult::Success);
+    assert_eq!(owner.metrics().producers[0].max_depth, 3);
+
+    let mut discarded = 0;
+    for _ in 0..4 {
+        if producer.force_push() == ForcePushResult::SuccessMessageDiscarded {
+            discarded += 1;
+        }
+    }
+    assert!(discarded > 0);
+
+    assert!(consumer.pop() == PopResult::SuccessMessagesDiscarded);
+
+    let producers = owner.metrics().producers;
+    assert_eq!(producers[0].pushes, 8);
+    assert_eq!(producers[0].discards, discarded);
+    assert_eq!(producers[0].wakeups, 0);
+    assert_eq!(producers[2], ChannelMetrics::default());
+
+    let consumers = peer.metrics().consumers;
+    assert_eq!(consumers[0].pops, 4);
+    assert_eq!(consumers[0].discards, 1);
+}
+
+#[test]
+fn wakeups_are_counted() {
+    let (mut owner, mut peer) = ChannelVector::create_pair(vector_config()).unwrap();
+
+    let mut producer = owner.take_producer::<u64>(1).unwrap();
+    let mut consumer = peer.take_consumer::<u64>(1).unwrap();
+
+    assert!(matches!(
+        consumer.pop(),
+        PopResult::NoMessage | PopResult::NoNewMessage
+    ));
+
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+
+    assert_eq!(owner.metrics().producers[1].wakeups, 1);
+
+    let metrics = peer.metrics().consumers[1].clone();
+    assert_eq!(metrics.wakeups, 1);
+    assert_eq!(metrics.pops, 1);
+
+    /* the counters stay with the vector */
+    drop((producer, consumer));
+    assert_eq!(owner.metrics().producers[1].pushes, 1);
+}
+
+#[test]
+fn latencies_of_stamped_messages_are_recorded() {
+    let config = Topics::new()
+        .publish::<Stamped<u32>>("stamped", TopicQos::default())
+        .unwrap()
+        .publish::<u64>("plain", TopicQos::default())
+        .unwrap()
+        .into_config();
+
+    let (mut owner, mut peer) = ChannelVector::create_pair(config).unwrap();
+
+    let mut producer = owner.take_stamped_producer::<u32>(0).unwrap();
+    let mut consumer = peer.take_stamped_consumer::<u32>(0).unwrap();
+    let _plain = peer.take_consumer::<u64>(1).unwrap();
+
+    assert_eq!(
+        peer.metrics().consumers[0]
+            .latency
+            .as_ref()
+            .unwrap()
+            .count(),
+        0
+    );
+
+    let delay = Duration::from_millis(2);
+
+    for i in 0..4 {
+        *producer.current_message() = i;
+        assert!(producer.force_push() == ForcePushResult::Success);
+        std::thread::sleep(delay);
+        assert!(consumer.pop() == PopResult::Success);
+        assert_eq!(consumer.current_message(), Some(&i));
+    }
+
+    let metrics = peer.metrics().consumers;
+    assert!(metrics[1].latency.is_none());
+
+    let latency = metrics[0].latency.clone().unwrap();
+    assert_eq!(latency.count(), 4);
+    assert!(latency.min().unwrap() >= delay);
+    assert!(latency.mean().unwrap() >= latency.min().unwrap());
+    assert!(latency.percentile(50.0).unwrap() >= latency.min().unwrap());
+    assert!(latency.percentile(100.0) == latency.max());
+    assert_eq!(latency.buckets().map(|(_, count)| count).sum::<u64>(), 4);
+}
@@ -0,0 +1,95 @@
+use std::thread;
+
+use rtipc::*;
+
+mod common;
+
+const PRODUCERS: u32 = 4;
+const MESSAGES: u32 = 5_000;
+
+#[test]
+fn producers_push_concurrently() {
+    let (mut owner, mut peer) = ChannelVector::create_pair(
+        common::vector()
+            .producer(5, 8)
+            .kind(ChannelKind::MultiProducer)
+            .build(),
+    )
+    .unwrap();
+
+    let producer = owner.take_mpsc_producer::<(u32, u32)>(0).unwrap();
+    let mut consumer = peer.take_mpsc_consumer::<(u32, u32)>(0).unwrap();
+
+    let producers: Vec<_> = (0..PRODUCERS)
+        .map(|id| {
+            let producer = producer.clone();
+            thread::spawn(move || {
+                for seq in 0..MESSAGES {
+                    while producer.push(&(id, seq)) == TryPushResult::QueueFull {
+                        thread::yield_now();
+                    }
+                }
+            })
+        })
+        .collect();
+
+    drop(producer);
+
+    /* next expected message of every producer, messages of one producer stay in order */
+    let mut next = vec![0; PRODUCERS as usize];
+    let mut received = 0;
+
+    while received < PRODUCERS * MESSAGES {
+        if consumer.pop() != PopResult::Success {
+            thread::yield_now();
+            continue;
+        }
+
+        let (id, seq) = *consumer.current_message().unwrap();
+        assert_eq!(seq, next[id as usize], "producer {id}");
+        next[id as usize] += 1;
+        received += 1;
+    }
+
+    for producer in producers {
+        producer.join().unwrap();
+    }
+
+    assert!(next.iter().all(|n| *n == MESSAGES));
+    assert!(consumer.pop() == PopResult::NoNewMessage);
+}
+
+#[test]
+fn full_queue_refuses_pushes() {
+    let (mut owner, mut peer) = ChannelVector::create_pair(
+        common::vector()
+            .producer(0, 8)
+            .kind(ChannelKind::MultiProducer)
+            .build(),
+    )
+    .unwrap();
+
+    let producer = owner.take_mpsc_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_mpsc_consumer::<u64>(0).unwrap();
+
+    /* additional_messages + 3 slots */
+    for value in 0..3 {
+        assert!(producer.push(&value) == TryPushResult::Success);
+    }
+    assert!(producer.clone().push(&3) == TryPushResult::QueueFull);
+
+    /* the current message of the consumer keeps its slot */
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&0));
+    assert!(producer.push(&3) == TryPushResult::QueueFull);
+
+    assert!(consumer.pop() == PopResult::Success);
+    assert!(producer.push(&3) == TryPushResult::Success);
+
+    /* nothing was discarded */
+    for value in 1..=3 {
+        assert_eq!(consumer.current_message(), Some(&value));
+        consumer.pop();
+    }
+    assert!(consumer.pop() == PopResult::NoNewMessage);
+}
@@ -0,0 +1,53 @@
+/* unwritten and released messages are filled with 0xa5 */
+#![cfg(feature = "poison")]
+
+use rtipc::*;
+
+mod common;
+
+const POISON: u64 = 0xa5a5a5a5a5a5a5a5;
+
+#[test]
+fn unwritten_fields_are_poisoned() {
+    let (mut owner, mut peer) =
+        ChannelVector::create_pair(common::vector().producer(2, 16).build()).unwrap();
+
+    let mut producer = owner.take_producer::<[u64; 2]>(0).unwrap();
+    let mut consumer = peer.take_consumer::<[u64; 2]>(0).unwrap();
+
+    /* the initialized queue is poisoned */
+    assert_eq!(*producer.current_message(), [POISON; 2]);
+
+    producer.current_message()[0] = 1;
+    producer.force_push();
+
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&[1, POISON]));
+
+    /* the message acquired by the push is poisoned again */
+    assert_eq!(*producer.current_message(), [POISON; 2]);
+}
+
+#[test]
+fn released_messages_are_poisoned() {
+    let (mut owner, mut peer) =
+        ChannelVector::create_pair(common::vector().producer(0, 8).build()).unwrap();
+
+    let mut producer = owner.take_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_consumer::<u64>(0).unwrap();
+
+    /* every message of the queue was written once */
+    for value in 1..=4 {
+        *producer.current_message() = value;
+        producer.force_push();
+    }
+
+    /* the producer reuses a message holding an old value, it's poisoned anyway */
+    assert_eq!(*producer.current_message(), POISON);
+
+    /* the queued messages are untouched, only the oldest ones were discarded */
+    assert!(consumer.pop() == PopResult::SuccessMessagesDiscarded);
+    assert_eq!(consumer.current_message(), Some(&3));
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&4));
+}
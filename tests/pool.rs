@@ -0,0 +1,92 @@
+#![cfg(feature = "socket")]
+
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use nix::sys::socket::Backlog;
+
+use rtipc::*;
+
+mod common;
+
+fn server(name: &str, size: usize) -> (Server, Arc<ShmPool>, PathBuf) {
+    let path = common::socket_path(name);
+    let mut server = Server::new(path.as_path(), Backlog::new(4).unwrap()).unwrap();
+
+    let pool = ShmPool::new(NonZeroUsize::new(size).unwrap()).unwrap();
+    server.set_pool(pool.clone());
+
+    (server, pool, path)
+}
+
+fn accept(server: &Server, path: &Path) -> Result<(ChannelVector, ChannelVector), TransferError> {
+    let path = path.to_path_buf();
+    let client = thread::spawn(move || {
+        client_connect_info(path.as_path(), b"pool", &ConnectOptions::default())
+    });
+
+    /* defined by the server, with an eventfd and heartbeat stamps in the pool */
+    let vconfig = common::vector()
+        .producer(1, 8)
+        .consumer(1, 8)
+        .eventfd()
+        .heartbeat()
+        .build();
+    let accepted = server.accept_with_layout(|_| Ok(vconfig.clone()));
+    let client = client.join().unwrap();
+
+    Ok((client?, accepted?.0))
+}
+
+#[test]
+fn vectors_are_carved_out_of_the_pool() {
+    let (server, pool, path) = server("pool", 0x10000);
+    let total = pool.available();
+
+    let mut vectors: Vec<_> = (0..3)
+        .map(|value| {
+            let (mut client, mut vector) = accept(&server, &path).unwrap();
+
+            let mut producer = vector.take_producer::<u64>(0).unwrap();
+            let mut consumer = client.take_consumer::<u64>(0).unwrap();
+
+            *producer.current_message() = value;
+            assert!(producer.try_push() == TryPushResult::Success);
+            assert!(consumer.pop() == PopResult::Success);
+            assert_eq!(consumer.current_message(), Some(&value));
+
+            (client, vector, producer, consumer)
+        })
+        .collect();
+
+    assert!(pool.available() < total);
+
+    /* the region of a dropped vector is reused */
+    let available = pool.available();
+    vectors.remove(1);
+    assert!(pool.available() > available);
+
+    let reused = accept(&server, &path).unwrap();
+    assert_eq!(pool.available(), available);
+
+    /* the free regions merge again */
+    vectors.clear();
+    drop(reused);
+    assert_eq!(pool.available(), total);
+}
+
+#[test]
+fn exhausted_pool_refuses_clients() {
+    /* a single page holds a single vector */
+    let (server, pool, path) = server("pool-exhausted", 1);
+
+    let vectors = accept(&server, &path).unwrap();
+    assert_eq!(pool.available(), 0);
+
+    assert!(accept(&server, &path).is_err());
+
+    drop(vectors);
+    assert!(accept(&server, &path).is_ok());
+}
@@ -0,0 +1,73 @@
+use rtipc::*;
+
+mod common;
+
+fn channels() -> (PriorityProducer<u64>, PriorityConsumer<u64>) {
+    let vconfig = common::vector()
+        .producer(2, 8)
+        .kind(ChannelKind::Priority)
+        .eventfd()
+        .build();
+    let (mut owner, mut peer) = ChannelVector::create_pair(vconfig).unwrap();
+
+    (
+        owner.take_priority_producer(0).unwrap(),
+        peer.take_priority_consumer(0).unwrap(),
+    )
+}
+
+fn push(producer: &mut PriorityProducer<u64>, value: u64, priority: bool) {
+    *producer.current_message() = value;
+
+    if priority {
+        producer.push_priority();
+    } else {
+        producer.force_push();
+    }
+}
+
+fn pop(consumer: &mut PriorityConsumer<u64>) -> (u64, bool) {
+    assert!(consumer.pop() == PopResult::Success);
+    (*consumer.current_message().unwrap(), consumer.is_priority())
+}
+
+#[test]
+fn priority_messages_overtake_older_messages() {
+    let (mut producer, mut consumer) = channels();
+
+    push(&mut producer, 1, false);
+    push(&mut producer, 2, false);
+    push(&mut producer, 3, true);
+    push(&mut producer, 4, false);
+    push(&mut producer, 5, true);
+
+    assert_eq!(pop(&mut consumer), (3, true));
+
+    /* a priority message pushed later still overtakes the queued ones */
+    push(&mut producer, 6, true);
+
+    assert_eq!(pop(&mut consumer), (5, true));
+    assert_eq!(pop(&mut consumer), (6, true));
+    assert_eq!(pop(&mut consumer), (1, false));
+    assert_eq!(pop(&mut consumer), (2, false));
+    assert_eq!(pop(&mut consumer), (4, false));
+
+    assert!(consumer.pop() == PopResult::NoNewMessage);
+}
+
+#[test]
+fn both_lanes_share_the_eventfd() {
+    let (mut producer, mut consumer) = channels();
+
+    push(&mut producer, 1, false);
+    push(&mut producer, 2, true);
+
+    /* one count per push of either lane */
+    let eventfd = consumer.take_eventfd().unwrap();
+    assert!(eventfd.read().is_ok());
+    assert!(eventfd.read().is_ok());
+    assert!(eventfd.read().is_err());
+
+    assert_eq!(pop(&mut consumer), (2, true));
+    assert_eq!(pop(&mut consumer), (1, false));
+}
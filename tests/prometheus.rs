@@ -0,0 +1,72 @@
+#![cfg(feature = "prometheus")]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use rtipc::*;
+
+fn get(addr: std::net::SocketAddr, target: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn metrics_are_exported() {
+    let config = Topics::new()
+        .publish::<u64>("pose", TopicQos::default())
+        .unwrap()
+        .into_config();
+
+    let (mut owner, mut peer) = ChannelVector::create_pair(config).unwrap();
+
+    let mut producer = owner.take_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_consumer::<u64>(0).unwrap();
+
+    producer.force_push();
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+
+    let exporter = PrometheusExporter::new();
+    exporter.register("owner", owner.metrics_handle());
+    exporter.register("peer \"b\"", peer.metrics_handle());
+
+    let text = exporter.render();
+
+    assert!(text.contains("# TYPE rtipc_channel_pushes_total counter\n"));
+    assert!(text.contains("# TYPE rtipc_channel_max_depth gauge\n"));
+    assert!(text.contains(
+        "rtipc_channel_pushes_total{vector=\"owner\",direction=\"producer\",channel=\"0\"} 2\n"
+    ));
+    assert!(text.contains(
+        "rtipc_channel_max_depth{vector=\"owner\",direction=\"producer\",channel=\"0\"} 2\n"
+    ));
+    assert!(text.contains(
+        "rtipc_channel_pops_total{vector=\"peer \\\"b\\\"\",direction=\"consumer\",channel=\"0\"} 1\n"
+    ));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    exporter.serve(listener);
+
+    producer.force_push();
+
+    let response = get(addr, "/metrics");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains(
+        "rtipc_channel_pushes_total{vector=\"owner\",direction=\"producer\",channel=\"0\"} 3\n"
+    ));
+
+    assert!(get(addr, "/").starts_with("HTTP/1.1 404"));
+
+    exporter.unregister("owner");
+    assert!(!get(addr, "/metrics").contains("vector=\"owner\""));
+
+    let path = std::env::temp_dir().join(format!("rtipc-{}.prom", std::process::id()));
+    exporter.write_textfile(&path).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), exporter.render());
+    std::fs::remove_file(path).unwrap();
+}
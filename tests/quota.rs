@@ -0,0 +1,123 @@
+#![cfg(feature = "socket")]
+
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use nix::sys::socket::Backlog;
+
+use rtipc::*;
+
+mod common;
+
+fn server(name: &str, quota: ClientQuota) -> (Server, PathBuf) {
+    let path = common::socket_path(name);
+    let mut server = Server::new(path.as_path(), Backlog::new(4).unwrap()).unwrap();
+    server.set_quota(quota);
+    (server, path)
+}
+
+fn connect(path: &Path) -> thread::JoinHandle<Result<ChannelVector, TransferError>> {
+    let path = path.to_path_buf();
+    thread::spawn(move || client_connect(path.as_path(), common::vector().producer(1, 8).build()))
+}
+
+fn assert_limit_exceeded(result: Result<(ChannelVector, PeerInfo), TransferError>) {
+    assert!(
+        matches!(
+            result,
+            Err(TransferError::RequestError(RequestError::LimitExceeded))
+        ),
+        "{:?}",
+        result.err()
+    );
+}
+
+#[test]
+fn charges_are_returned_once_unmapped() {
+    let (server, path) = server(
+        "quota",
+        ClientQuota {
+            max_channels: 1,
+            ..Default::default()
+        },
+    );
+
+    let client = connect(&path);
+    let (mut vector, _) = server.accept().unwrap();
+    let client = client.join().unwrap().unwrap();
+
+    let refused = connect(&path);
+    assert_limit_exceeded(server.accept());
+    assert!(matches!(
+        refused.join().unwrap(),
+        Err(TransferError::Rejected(_))
+    ));
+
+    /* the consumer keeps the shared memory mapped */
+    let consumer = vector.take_consumer::<u64>(0).unwrap();
+    drop(vector);
+
+    let refused = connect(&path);
+    assert_limit_exceeded(server.accept());
+    assert!(refused.join().unwrap().is_err());
+
+    drop((consumer, client));
+
+    let client = connect(&path);
+    let _vector = server.accept().unwrap();
+    client.join().unwrap().unwrap();
+}
+
+/// Whether the shared memory of rsc is mapped into this process.
+#[cfg(target_os = "linux")]
+fn is_mapped(rsc: &VectorResource) -> bool {
+    use std::os::fd::AsFd;
+
+    let inode = nix::sys::stat::fstat(rsc.shmfd.as_fd()).unwrap().st_ino;
+    let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+
+    maps.lines()
+        .filter_map(|line| line.split_whitespace().nth(4))
+        .any(|field| field.parse() == Ok(inode))
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn exceeding_requests_are_rejected_before_mapping() {
+    use std::os::fd::AsFd;
+
+    let mut server = Server::unbound().unwrap();
+    server.set_quota(ClientQuota {
+        max_channels: 2,
+        ..Default::default()
+    });
+
+    let credentials = PeerCredentials {
+        pid: 1,
+        uid: 1000,
+        gid: 1000,
+    };
+
+    /* the requests are written by the test, the shared memory of a client isn't mapped */
+    let accept = |vconfig: &VectorConfig| {
+        let rsc = VectorResource::allocate(vconfig).unwrap();
+        let (tx, rx) = common::seqpacket_pair();
+
+        let (request, fds) = rsc.serialize().unwrap();
+        UnixTransport::new(tx.as_fd())
+            .send_request(&request, &fds)
+            .unwrap();
+
+        let mut transport = UnixTransport::new(rx.as_fd());
+        let result = server.accept_transport(&mut transport, credentials, |_, _| Ok(Vec::new()));
+        (rsc, result)
+    };
+
+    let (rsc, accepted) = accept(&common::vector().producer(1, 8).consumer(1, 8).build());
+    let _accepted = accepted.unwrap();
+    assert!(is_mapped(&rsc));
+
+    let (rsc, refused) = accept(&common::vector().producer(1, 8).build());
+    assert_limit_exceeded(refused);
+    assert!(!is_mapped(&rsc));
+}
@@ -0,0 +1,102 @@
+/* the hot path runs in a child under strict seccomp, any syscall but read, write,
+ * exit and sigreturn kills it */
+#![cfg(target_os = "linux")]
+
+use nix::libc;
+
+use rtipc::*;
+
+mod common;
+
+const MESSAGES: u64 = 10000;
+const POLLS: u32 = 1000;
+
+/// Runs hot_path in a forked child that may not enter the kernel,
+/// returns its exit code or the signal that killed it.
+fn run_without_syscalls(hot_path: impl FnOnce() -> i32) -> Result<i32, i32> {
+    let pid = unsafe { libc::fork() };
+    assert!(pid >= 0, "fork failed");
+
+    if pid == 0 {
+        let code = unsafe {
+            if libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_STRICT) != 0 {
+                libc::_exit(100);
+            }
+
+            hot_path()
+        };
+
+        /* exit_group of _exit isn't allowed in strict mode */
+        unsafe { libc::syscall(libc::SYS_exit, code) };
+        unreachable!();
+    }
+
+    let mut status = 0;
+    assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+
+    if libc::WIFEXITED(status) {
+        Ok(libc::WEXITSTATUS(status))
+    } else {
+        Err(libc::WTERMSIG(status))
+    }
+}
+
+#[test]
+fn hot_path_is_syscall_free() {
+    let vconfig = common::vector()
+        .producer(2, size_of::<u64>())
+        .producer(2, size_of::<u64>())
+        .kind(ChannelKind::Broadcast)
+        .info(b"realtime")
+        .build();
+
+    assert!(vconfig.is_syscall_free());
+
+    let (mut owner, mut peer) = ChannelVector::create_pair(vconfig).unwrap();
+
+    let mut producer = owner.take_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_consumer::<u64>(0).unwrap();
+    let mut broadcast_producer = owner.take_broadcast_producer::<u64>(1).unwrap();
+    let mut broadcast_consumer = peer.take_broadcast_consumer::<u64>(1).unwrap();
+
+    let result = run_without_syscalls(|| {
+        for i in 1..=MESSAGES {
+            *producer.current_message() = i;
+            producer.force_push();
+
+            *broadcast_producer.current_message() = i;
+            broadcast_producer.push();
+
+            if consumer.pop_spin(POLLS) != PopResult::Success
+                || consumer.current_message() != Some(&i)
+            {
+                return 1;
+            }
+
+            if broadcast_consumer.pop_spin(POLLS) != PopResult::Success
+                || broadcast_consumer.current_message() != Some(&i)
+            {
+                return 2;
+            }
+        }
+
+        /* nothing left, the consumer gives up after POLLS polls */
+        if consumer.pop_spin(POLLS) != PopResult::NoNewMessage {
+            return 3;
+        }
+
+        0
+    });
+
+    assert_eq!(result, Ok(0), "the hot path entered the kernel");
+}
+
+#[test]
+fn eventfds_are_not_syscall_free() {
+    let vconfig = common::vector()
+        .producer(2, size_of::<u64>())
+        .eventfd()
+        .build();
+
+    assert!(!vconfig.is_syscall_free());
+}
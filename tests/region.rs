@@ -0,0 +1,248 @@
+/* vectors placed in memory supplied by the user instead of a memfd */
+use std::alloc::{Layout, alloc_zeroed, dealloc};
+use std::num::NonZeroUsize;
+use std::os::fd::BorrowedFd;
+use std::ptr::NonNull;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rtipc::*;
+
+mod common;
+
+const SIZE: usize = 1 << 16;
+
+/// Zeroed memory, freed once the last region of it is dropped.
+struct Heap {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+// the regions of the heap are only used by the vectors placed in them
+unsafe impl Send for Heap {}
+unsafe impl Sync for Heap {}
+
+impl Heap {
+    fn new() -> Arc<Self> {
+        let layout = Layout::from_size_align(SIZE, 4096).unwrap();
+        let ptr = NonNull::new(unsafe { alloc_zeroed(layout) }).unwrap();
+        Arc::new(Self { ptr, layout })
+    }
+
+    /// Region of size bytes at offset.
+    fn region(self: &Arc<Self>, offset: usize, size: usize) -> Box<HeapRegion> {
+        assert!(offset + size <= SIZE);
+
+        Box::new(HeapRegion {
+            heap: self.clone(),
+            offset,
+            size: NonZeroUsize::new(size).unwrap(),
+        })
+    }
+}
+
+impl Drop for Heap {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr.as_ptr(), self.layout) };
+    }
+}
+
+struct HeapRegion {
+    heap: Arc<Heap>,
+    offset: usize,
+    size: NonZeroUsize,
+}
+
+impl MemoryRegion for HeapRegion {
+    fn map(&self) -> NonNull<u8> {
+        unsafe { self.heap.ptr.add(self.offset) }
+    }
+
+    fn size(&self) -> NonZeroUsize {
+        self.size
+    }
+
+    fn as_fd(&self) -> Option<BorrowedFd<'_>> {
+        None
+    }
+}
+
+#[test]
+fn vector_is_placed_in_the_region() {
+    let heap = Heap::new();
+
+    /* the peer initializes the memory, the owner finds the vector there */
+    let mut peer = ChannelVector::with_region(
+        common::vector().producer(2, 8).consumer(1, 16).build(),
+        heap.region(0, SIZE),
+        false,
+    )
+    .unwrap();
+    let mut owner = ChannelVector::with_region(
+        common::vector().producer(2, 8).consumer(1, 16).build(),
+        heap.region(0, SIZE),
+        true,
+    )
+    .unwrap();
+
+    assert_eq!(owner.memory_report().mapped, SIZE);
+
+    let mut producer = owner.take_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_consumer::<u64>(0).unwrap();
+
+    *producer.current_message() = 42;
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&42));
+
+    /* the channels of the peer are swapped */
+    let mut producer = peer.take_producer::<[u64; 2]>(0).unwrap();
+    let mut consumer = owner.take_consumer::<[u64; 2]>(0).unwrap();
+
+    *producer.current_message() = [1, 2];
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&[1, 2]));
+
+    let address = producer.current_message() as *mut [u64; 2] as usize;
+    assert!((heap.ptr.as_ptr() as usize..heap.ptr.as_ptr() as usize + SIZE).contains(&address));
+}
+
+#[test]
+fn unfit_regions_are_refused() {
+    let heap = Heap::new();
+
+    let result = ChannelVector::with_region(
+        common::vector().producer(2, 8).consumer(1, 16).build(),
+        heap.region(0, 64),
+        true,
+    );
+    assert!(matches!(
+        result,
+        Err(TransferError::ResourceError(ResourceError::ShmMapError(
+            ShmMapError::OutOfBounds
+        )))
+    ));
+
+    let result = ChannelVector::with_region(
+        common::vector().producer(2, 8).consumer(1, 16).build(),
+        heap.region(8, SIZE - 8),
+        true,
+    );
+    assert!(matches!(
+        result,
+        Err(TransferError::ResourceError(ResourceError::ShmMapError(
+            ShmMapError::Misalignment
+        )))
+    ));
+
+    /* there's no handshake passing the eventfds */
+    let vconfig = common::vector().producer(2, 8).eventfd().build();
+    let result = ChannelVector::with_region(vconfig, heap.region(0, SIZE), true);
+    assert!(matches!(
+        result,
+        Err(TransferError::ConfigError(ConfigError::EventFdsUnsupported))
+    ));
+}
+
+#[test]
+fn vectors_of_other_configs_are_refused() {
+    let heap = Heap::new();
+
+    let mut peer = ChannelVector::with_region(
+        common::vector().producer(2, 8).consumer(1, 16).build(),
+        heap.region(0, SIZE),
+        false,
+    )
+    .unwrap();
+
+    /* the descriptor of the first channel was written for 8 byte messages */
+    let mut vconfig = common::vector().producer(2, 8).consumer(1, 16).build();
+    vconfig.producers[0].queue.message_size = NonZeroUsize::new(128).unwrap();
+
+    let result = ChannelVector::with_region(vconfig, heap.region(0, SIZE), true);
+    assert!(matches!(
+        result,
+        Err(TransferError::ResourceError(ResourceError::ShmMapError(
+            ShmMapError::DescriptorMismatch { offset: 0 }
+        )))
+    ));
+
+    /* the peer is left untouched */
+    assert!(
+        ChannelVector::with_region(
+            common::vector().producer(2, 8).consumer(1, 16).build(),
+            heap.region(0, SIZE),
+            true
+        )
+        .is_ok()
+    );
+    assert!(peer.take_consumer::<[u64; 2]>(0).is_some());
+}
+
+/* messages cleaned and invalidated by the cache operations of device_memory */
+static CLEANED: AtomicUsize = AtomicUsize::new(0);
+static INVALIDATED: AtomicUsize = AtomicUsize::new(0);
+
+/// Device memory of the heap the other side accesses without snooping our caches.
+fn device_memory(heap: &Arc<Heap>) -> Box<DeviceMemory> {
+    let size = NonZeroUsize::new(SIZE).unwrap();
+
+    let memory = unsafe { DeviceMemory::new(heap.ptr, size) }.with_cache_ops(
+        |ptr, _| CLEANED.store(ptr as usize, Ordering::Relaxed),
+        |ptr, _| INVALIDATED.store(ptr as usize, Ordering::Relaxed),
+    );
+
+    Box::new(memory)
+}
+
+#[test]
+fn queues_maintain_the_caches_of_device_memory() {
+    let heap = Heap::new();
+    let vconfig = common::vector().producer(2, 8).build();
+
+    let mut peer =
+        ChannelVector::with_region(vconfig.clone(), device_memory(&heap), false).unwrap();
+    let mut owner = ChannelVector::with_region(vconfig, device_memory(&heap), true).unwrap();
+
+    let mut producer = owner.take_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_consumer::<u64>(0).unwrap();
+
+    /* the message is cleaned before it's pushed and invalidated once it's popped */
+    let message = producer.current_message() as *mut u64 as usize;
+    *producer.current_message() = 42;
+    producer.force_push();
+    assert_eq!(CLEANED.load(Ordering::Relaxed), message);
+
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(INVALIDATED.load(Ordering::Relaxed), message);
+    assert_eq!(consumer.current_message(), Some(&42));
+}
+
+#[test]
+fn device_memory_holds_only_maintained_channels() {
+    let heap = Heap::new();
+
+    /* states and the arena are accessed in place */
+    for vconfig in [
+        common::vector()
+            .producer(2, 8)
+            .kind(ChannelKind::State)
+            .build(),
+        common::vector().producer(2, 8).arena(64, 4).build(),
+    ] {
+        let result = ChannelVector::with_region(vconfig, device_memory(&heap), false);
+        assert!(matches!(
+            result,
+            Err(TransferError::ResourceError(ResourceError::InvalidArgument))
+        ));
+    }
+
+    /* coherent device memory holds any channel */
+    let memory = unsafe { DeviceMemory::new(heap.ptr, NonZeroUsize::new(SIZE).unwrap()) };
+    let vconfig = common::vector()
+        .producer(2, 8)
+        .kind(ChannelKind::State)
+        .build();
+    assert!(ChannelVector::with_region(vconfig, Box::new(memory), false).is_ok());
+}
@@ -0,0 +1,298 @@
+/* requests whose fds don't match the vector are refused before anything is mapped */
+#![cfg(all(feature = "socket", target_os = "linux"))]
+
+use std::os::fd::{AsFd, OwnedFd};
+
+use nix::fcntl::{F_ADD_SEALS, SealFlag, fcntl};
+use nix::sys::memfd::{MFdFlags, memfd_create};
+use nix::sys::stat::fstat;
+
+use rtipc::*;
+
+mod common;
+
+/// Vector the clients ask for.
+fn client_vector() -> VectorConfig {
+    common::vector()
+        .producer(1, 8)
+        .eventfd()
+        .consumer(0, 16)
+        .build()
+}
+
+/// memfd without seals, the peer could shrink it under the mapping of the receiver
+fn unsealed_memfd(size: usize) -> OwnedFd {
+    let fd = memfd_create("rtipc", MFdFlags::MFD_ALLOW_SEALING).unwrap();
+    nix::unistd::ftruncate(&fd, size.try_into().unwrap()).unwrap();
+    fd
+}
+
+/// memfd with the seals of an honest peer
+fn sealed_memfd(size: usize) -> OwnedFd {
+    let fd = unsealed_memfd(size);
+    fcntl(
+        &fd,
+        F_ADD_SEALS(SealFlag::F_SEAL_GROW | SealFlag::F_SEAL_SHRINK | SealFlag::F_SEAL_SEAL),
+    )
+    .unwrap();
+    fd
+}
+
+#[test]
+fn missing_fd_is_refused() {
+    let rsc = VectorResource::allocate(&client_vector()).unwrap();
+    let (request, mut fds) = common::serialize(&rsc);
+
+    /* shm and the eventfd of the producer */
+    fds.pop_back();
+
+    let result = VectorResource::deserialize(&request, fds);
+    assert!(
+        matches!(
+            result,
+            Err(TransferError::FileDescriptorCountMismatch {
+                expected: 2,
+                received: 1
+            })
+        ),
+        "{:?}",
+        result.err()
+    );
+}
+
+#[test]
+fn surplus_fd_is_refused() {
+    let rsc = VectorResource::allocate(&client_vector()).unwrap();
+    let (request, mut fds) = common::serialize(&rsc);
+
+    fds.push_back(OwnedFd::from(EventFd::new().unwrap()));
+
+    let result = VectorResource::deserialize(&request, fds);
+    assert!(
+        matches!(
+            result,
+            Err(TransferError::FileDescriptorCountMismatch {
+                expected: 2,
+                received: 3
+            })
+        ),
+        "{:?}",
+        result.err()
+    );
+}
+
+#[test]
+fn shm_of_wrong_size_is_refused() {
+    let rsc = VectorResource::allocate(&client_vector()).unwrap();
+    let (request, mut fds) = common::serialize(&rsc);
+
+    let size = fstat(&fds[0]).unwrap().st_size as usize;
+    fds[0] = sealed_memfd(size / 2);
+
+    let result = VectorResource::deserialize(&request, fds);
+    assert!(
+        matches!(
+            result,
+            Err(TransferError::ShmSizeMismatch { expected, received })
+                if expected == size && received == size / 2
+        ),
+        "{:?}",
+        result.err()
+    );
+}
+
+#[test]
+fn unsealed_shm_is_refused() {
+    let rsc = VectorResource::allocate(&client_vector()).unwrap();
+    let (request, mut fds) = common::serialize(&rsc);
+
+    let size = fstat(&fds[0]).unwrap().st_size as usize;
+    fds[0] = unsealed_memfd(size);
+
+    let result = VectorResource::deserialize(&request, fds);
+    assert!(
+        matches!(
+            result,
+            Err(TransferError::ResourceError(ResourceError::Errno(
+                Errno::EPERM
+            )))
+        ),
+        "{:?}",
+        result.err()
+    );
+}
+
+#[test]
+fn partially_sealed_shm_is_refused() {
+    let rsc = VectorResource::allocate(&client_vector()).unwrap();
+
+    for seals in [SealFlag::F_SEAL_GROW, SealFlag::F_SEAL_SHRINK] {
+        let (request, mut fds) = common::serialize(&rsc);
+
+        let size = fstat(&fds[0]).unwrap().st_size as usize;
+        fds[0] = unsealed_memfd(size);
+        fcntl(&fds[0], F_ADD_SEALS(seals)).unwrap();
+
+        let result = VectorResource::deserialize(&request, fds);
+        assert!(
+            matches!(
+                result,
+                Err(TransferError::ResourceError(ResourceError::Errno(
+                    Errno::EPERM
+                )))
+            ),
+            "{seals:?} {:?}",
+            result.err()
+        );
+    }
+
+    /* the seals themselves don't have to be sealed */
+    let (request, mut fds) = common::serialize(&rsc);
+    let size = fstat(&fds[0]).unwrap().st_size as usize;
+    fds[0] = unsealed_memfd(size);
+    fcntl(
+        &fds[0],
+        F_ADD_SEALS(SealFlag::F_SEAL_GROW | SealFlag::F_SEAL_SHRINK),
+    )
+    .unwrap();
+    assert!(VectorResource::deserialize(&request, fds).is_ok());
+}
+
+#[test]
+fn vectors_exceeding_the_limits_are_refused() {
+    let rsc = VectorResource::allocate(&client_vector()).unwrap();
+
+    let limits = [
+        ServerLimits {
+            max_channels: 1,
+            ..Default::default()
+        },
+        ServerLimits {
+            max_queue_depth: 3,
+            ..Default::default()
+        },
+        ServerLimits {
+            max_message_size: 8,
+            ..Default::default()
+        },
+        ServerLimits {
+            max_shm_size: 64,
+            ..Default::default()
+        },
+    ];
+
+    for limits in limits {
+        let (request, fds) = common::serialize(&rsc);
+
+        let result = VectorResource::deserialize_limited(&request, fds, &limits);
+        assert!(
+            matches!(
+                result,
+                Err(TransferError::RequestError(RequestError::LimitExceeded))
+            ),
+            "{limits:?} {:?}",
+            result.err()
+        );
+    }
+
+    /* the vector itself is fine */
+    let (request, fds) = common::serialize(&rsc);
+    assert!(VectorResource::deserialize(&request, fds).is_ok());
+}
+
+#[test]
+fn seals_are_checked_before_the_size() {
+    let rsc = VectorResource::allocate(&client_vector()).unwrap();
+    let (request, mut fds) = common::serialize(&rsc);
+
+    /* a size check without the seals could be undone by the peer right after it */
+    fds[0] = unsealed_memfd(1 << 20);
+
+    let result = VectorResource::deserialize(&request, fds);
+    assert!(
+        matches!(
+            result,
+            Err(TransferError::ResourceError(ResourceError::Errno(
+                Errno::EPERM
+            )))
+        ),
+        "{:?}",
+        result.err()
+    );
+}
+
+#[test]
+fn fds_other_than_dmabufs_are_refused() {
+    let mut rsc = VectorResource::allocate(&client_vector()).unwrap();
+
+    assert_eq!(rsc.add_dmabuf(sealed_memfd(4096)).err(), Some(Errno::EBADF));
+
+    let null = OwnedFd::from(std::fs::File::open("/dev/null").unwrap());
+    assert_eq!(rsc.add_dmabuf(null).err(), Some(Errno::EBADF));
+
+    /* the client fails before the request is sent */
+    let options = ConnectOptions {
+        dmabufs: vec![std::sync::Arc::new(sealed_memfd(4096))],
+        ..Default::default()
+    };
+
+    let result = Server::unbound()
+        .unwrap()
+        .loopback(client_vector(), &options);
+    assert!(
+        matches!(
+            result,
+            Err(TransferError::ResourceError(ResourceError::Errno(
+                Errno::EBADF
+            )))
+        ),
+        "{:?}",
+        result.err()
+    );
+}
+
+#[test]
+fn announced_fds_beyond_the_limits_are_refused() {
+    let rsc = VectorResource::allocate(&client_vector()).unwrap();
+    let (mut request, _) = rsc.serialize().unwrap();
+
+    /* the fd count record follows the channels: tag 6, no flags, 4 bytes */
+    let record = [6, 0, 0, 0, 4, 0, 0, 0];
+    let offset = request
+        .windows(record.len())
+        .rposition(|window| window == record)
+        .unwrap()
+        + record.len();
+    request[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+    let (tx, rx) = common::seqpacket_pair();
+    UnixTransport::new(tx.as_fd())
+        .send_request(&request, &[])
+        .unwrap();
+
+    /* without the cap the server would wait for continuation messages until EOF */
+    drop(tx);
+
+    let mut server = Server::unbound().unwrap();
+    server.set_limits(ServerLimits {
+        max_channels: 4,
+        ..Default::default()
+    });
+
+    let credentials = PeerCredentials {
+        pid: 1,
+        uid: 1000,
+        gid: 1000,
+    };
+
+    let mut transport = UnixTransport::new(rx.as_fd());
+    let result = server.accept_transport(&mut transport, credentials, |_, _| Ok(Vec::new()));
+    assert!(
+        matches!(
+            result,
+            Err(TransferError::RequestError(RequestError::LimitExceeded))
+        ),
+        "{:?}",
+        result.err()
+    );
+}
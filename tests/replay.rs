@@ -0,0 +1,76 @@
+/* a signed response carries the nonce of the request, a recorded one answers no other request */
+#![cfg(all(feature = "socket", feature = "hmac"))]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+
+use rtipc::*;
+
+mod common;
+
+const KEY: &[u8] = b"replay";
+
+fn options() -> ConnectOptions {
+    ConnectOptions {
+        key: Some(KEY.to_vec()),
+        ..Default::default()
+    }
+}
+
+/* frames of the tcp handshake: length as little endian u32, then the message */
+fn read_frame(stream: &mut TcpStream) -> Vec<u8> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).unwrap();
+
+    let mut msg = vec![0; u32::from_le_bytes(len) as usize];
+    stream.read_exact(&mut msg).unwrap();
+    msg
+}
+
+fn write_frame(stream: &mut TcpStream, msg: &[u8]) {
+    stream.write_all(&(msg.len() as u32).to_le_bytes()).unwrap();
+    stream.write_all(msg).unwrap();
+}
+
+fn connect(addr: SocketAddr) -> thread::JoinHandle<Result<ChannelVector, TransferError>> {
+    thread::spawn(move || {
+        client_connect_tcp(addr, common::vector().producer(0, 8).build(), &options())
+    })
+}
+
+#[test]
+fn replayed_response_is_rejected() {
+    let mut server = TcpServer::new("127.0.0.1:0").unwrap();
+    server.set_key(KEY);
+    let server_addr = server.local_addr().unwrap();
+
+    let server = thread::spawn(move || server.accept().map(|(vector, _)| vector));
+
+    /* the test sits between the client and the server and records the response */
+    let proxy = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = connect(proxy.local_addr().unwrap());
+
+    let (mut stream, _) = proxy.accept().unwrap();
+    let mut upstream = TcpStream::connect(server_addr).unwrap();
+    write_frame(&mut upstream, &read_frame(&mut stream));
+    let response = read_frame(&mut upstream);
+    write_frame(&mut stream, &response);
+
+    assert!(client.join().unwrap().is_ok());
+    let _vector = server.join().unwrap().unwrap();
+
+    /* the signature of the recorded response is still valid, its nonce isn't */
+    let client = connect(proxy.local_addr().unwrap());
+
+    let (mut stream, _) = proxy.accept().unwrap();
+    read_frame(&mut stream);
+    write_frame(&mut stream, &response);
+
+    let result = client.join().unwrap();
+    assert!(
+        matches!(result, Err(TransferError::ResponseError)),
+        "{:?}",
+        result.err()
+    );
+}
@@ -0,0 +1,112 @@
+#![cfg(feature = "socket")]
+
+use std::thread;
+
+use nix::sys::socket::Backlog;
+
+use rtipc::*;
+
+mod common;
+
+#[test]
+fn popped_message_is_dropped() {
+    let (mut client, mut server, _) = common::connect(
+        "resize",
+        common::vector().producer(1, 8).consumer(1, 8).build(),
+        ConnectOptions::default(),
+    );
+
+    let client_control = client.take_control().unwrap();
+    let server_control = server.take_control().unwrap();
+
+    let mut producer = server.take_producer::<u64>(0).unwrap();
+    let mut consumer = client.take_consumer::<u64>(0).unwrap();
+
+    for value in [1, 2] {
+        *producer.current_message() = value;
+        producer.force_push();
+    }
+
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&1));
+
+    let peer = thread::spawn(move || {
+        assert!(matches!(
+            server_control.receive().unwrap(),
+            ControlMessage::Resize(_)
+        ));
+        server
+            .accept_resize(
+                &server_control,
+                ResizeChannels::new().producer(0, &mut producer),
+            )
+            .unwrap();
+        (server, server_control)
+    });
+
+    client
+        .resize(
+            &client_control,
+            common::vector().producer(8, 8).consumer(8, 8).build(),
+            ResizeChannels::new().consumer(0, &mut consumer),
+        )
+        .unwrap();
+
+    let _server = peer.join().unwrap();
+
+    /* the slot of the popped message stayed in the old shared memory */
+    assert_eq!(consumer.current_message(), None);
+
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&2));
+    assert!(consumer.pop() == PopResult::NoNewMessage);
+}
+
+#[test]
+fn resize_beyond_the_limits_is_refused() {
+    let path = common::socket_path("resize-limits");
+    let mut server = Server::new(path.as_path(), Backlog::new(1).unwrap()).unwrap();
+
+    /* the vector of the handshake fits, the resized one doesn't */
+    server.set_limits(ServerLimits {
+        max_queue_depth: 8,
+        ..Default::default()
+    });
+
+    let client = thread::spawn(move || {
+        let mut client = client_connect_with(
+            path.as_path(),
+            common::vector().producer(1, 8).consumer(1, 8).build(),
+            &ConnectOptions::default(),
+        )
+        .unwrap();
+        let control = client.take_control().unwrap();
+
+        client
+            .resize(
+                &control,
+                common::vector().producer(8, 8).consumer(8, 8).build(),
+                ResizeChannels::new(),
+            )
+            .map(|_| ())
+    });
+
+    let (mut vector, _) = server.accept().unwrap();
+    let control = vector.take_control().unwrap();
+
+    let result = control.receive();
+    assert!(
+        matches!(
+            result,
+            Err(TransferError::RequestError(RequestError::LimitExceeded))
+        ),
+        "{:?}",
+        result.err()
+    );
+
+    let result = client.join().unwrap();
+    assert!(
+        matches!(result, Err(TransferError::Rejected(_))),
+        "{result:?}"
+    );
+}
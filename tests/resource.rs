@@ -0,0 +1,79 @@
+/* vectors built from fds the application passes by its own means, e.g. without the socket feature */
+use std::collections::VecDeque;
+use std::os::fd::{BorrowedFd, OwnedFd};
+
+use rtipc::*;
+
+mod common;
+
+/// Vector with eventfds on the producer and on one of the consumers.
+fn notified_vector() -> VectorConfig {
+    common::vector()
+        .producer(1, 8)
+        .eventfd()
+        .consumer(1, 16)
+        .consumer(2, 8)
+        .eventfd()
+        .build()
+}
+
+fn dup(fds: Vec<BorrowedFd<'_>>) -> VecDeque<OwnedFd> {
+    fds.into_iter()
+        .map(|fd| fd.try_clone_to_owned().unwrap())
+        .collect()
+}
+
+#[test]
+fn vectors_are_built_from_passed_fds() {
+    let vconfig = notified_vector();
+    let owner = VectorResource::allocate(&vconfig).unwrap();
+
+    /* the peer sees the channels swapped, its consumers are notified by our producers */
+    let mirrored = VectorConfig {
+        producers: vconfig.consumers.clone(),
+        consumers: vconfig.producers.clone(),
+        ..vconfig.clone()
+    };
+
+    let peer = VectorResource::new(
+        &mirrored,
+        owner.shmfd().try_clone_to_owned().unwrap(),
+        dup(owner.collect_producer_eventfds()),
+        dup(owner.collect_consumer_eventfds()),
+    )
+    .unwrap();
+
+    /* the peer initializes the shared memory */
+    let mut peer = ChannelVector::new(peer).unwrap();
+    let mut owner = ChannelVector::new(owner).unwrap();
+
+    let mut producer = owner.take_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_consumer::<u64>(0).unwrap();
+
+    *producer.current_message() = 1;
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&1));
+
+    let mut producer = peer.take_producer::<u64>(1).unwrap();
+    let mut consumer = owner.take_consumer::<u64>(1).unwrap();
+
+    *producer.current_message() = 2;
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&2));
+}
+
+#[test]
+fn missing_eventfds_are_refused() {
+    let vconfig = notified_vector();
+    let owner = VectorResource::allocate(&vconfig).unwrap();
+
+    let result = VectorResource::new(
+        &vconfig,
+        owner.shmfd().try_clone_to_owned().unwrap(),
+        VecDeque::new(),
+        VecDeque::new(),
+    );
+    assert!(matches!(result, Err(TransferError::MissingFileDescriptor)));
+}
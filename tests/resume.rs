@@ -0,0 +1,233 @@
+#![cfg(feature = "socket")]
+
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use nix::sys::socket::Backlog;
+
+use rtipc::*;
+
+mod common;
+
+fn server(name: &str) -> (Server, PathBuf) {
+    let path = common::socket_path(name);
+    let mut server = Server::new(path.as_path(), Backlog::new(2).unwrap()).unwrap();
+    server.set_resume(true);
+    (server, path)
+}
+
+fn resume(path: &Path, token: u64) -> thread::JoinHandle<Result<ChannelVector, TransferError>> {
+    let path = path.to_path_buf();
+    thread::spawn(move || client_resume(path.as_path(), token, &ConnectOptions::default()))
+}
+
+fn assert_rejected(result: Result<(), TransferError>) {
+    assert!(
+        matches!(result, Err(TransferError::Rejected(_))),
+        "{result:?}"
+    );
+}
+
+#[test]
+fn resumed_session_keeps_the_queues() {
+    let (server, path) = server("resume");
+
+    let client_path = path.clone();
+    let client = thread::spawn(move || {
+        client_connect_with(
+            client_path.as_path(),
+            common::vector().producer(4, 8).consumer(4, 8).build(),
+            &Default::default(),
+        )
+    });
+
+    let (mut vector, _) = server.accept().unwrap();
+    let mut client = client.join().unwrap().unwrap();
+
+    let token = client.session_token().unwrap();
+    assert_eq!(vector.session_token(), Some(token));
+    assert!(!client.is_resumed());
+
+    let mut producer = client.take_producer::<u64>(0).unwrap();
+    let mut server_producer = vector.take_producer::<u64>(0).unwrap();
+    let mut server_consumer = vector.take_consumer::<u64>(0).unwrap();
+
+    for value in [1, 2] {
+        *producer.current_message() = value;
+        assert!(producer.try_push() == TryPushResult::Success);
+    }
+    for value in 10..13 {
+        *server_producer.current_message() = value;
+        assert!(server_producer.try_push() == TryPushResult::Success);
+    }
+
+    /* the client goes away without ending the session */
+    drop((producer, client));
+
+    let client = resume(&path, token);
+    let (resumed, _) = server.accept().unwrap();
+    let mut client = client.join().unwrap().unwrap();
+
+    assert!(client.is_resumed());
+    assert_eq!(resumed.session_token(), Some(token));
+
+    let mut producer = client.take_producer::<u64>(0).unwrap();
+    let mut consumer = client.take_consumer::<u64>(0).unwrap();
+
+    for value in 10..13 {
+        assert!(consumer.pop() == PopResult::Success);
+        assert_eq!(consumer.current_message(), Some(&value));
+    }
+
+    *producer.current_message() = 3;
+    assert!(producer.try_push() == TryPushResult::Success);
+
+    for value in 1..4 {
+        assert!(server_consumer.pop() == PopResult::Success);
+        assert_eq!(server_consumer.current_message(), Some(&value));
+    }
+}
+
+#[test]
+fn stale_tokens_are_refused() {
+    let (server, path) = server("resume-stale");
+
+    let client_path = path.clone();
+    let client = thread::spawn(move || {
+        client_connect_with(
+            client_path.as_path(),
+            common::vector().producer(4, 8).consumer(4, 8).build(),
+            &Default::default(),
+        )
+    });
+
+    let (_vector, _) = server.accept().unwrap();
+    let token = client.join().unwrap().unwrap().session_token().unwrap();
+
+    /* a token the server never handed out */
+    let client = resume(&path, token.wrapping_add(1));
+    assert_rejected(server.accept().map(|_| ()));
+    assert_rejected(client.join().unwrap().map(|_| ()));
+
+    /* the token of an ended session */
+    assert!(server.end_session(token));
+    assert!(!server.end_session(token));
+
+    let client = resume(&path, token);
+    assert_rejected(server.accept().map(|_| ()));
+    assert_rejected(client.join().unwrap().map(|_| ()));
+}
+
+/// Connects a client and returns it with the vector accepted by the server.
+fn connect(server: &Server, path: &Path) -> (ChannelVector, ChannelVector) {
+    let path = path.to_path_buf();
+    let client = thread::spawn(move || {
+        client_connect_with(
+            path.as_path(),
+            common::vector().producer(4, 8).consumer(4, 8).build(),
+            &Default::default(),
+        )
+    });
+
+    let (vector, _) = server.accept().unwrap();
+    (client.join().unwrap().unwrap(), vector)
+}
+
+#[test]
+fn attached_sessions_are_refused() {
+    let (server, path) = server("resume-attached");
+
+    let (client, _vector) = connect(&server, &path);
+    let token = client.session_token().unwrap();
+
+    /* the first client still holds the vector */
+    let second = resume(&path, token);
+    assert_rejected(server.accept().map(|_| ()));
+    assert_rejected(second.join().unwrap().map(|_| ()));
+
+    drop(client);
+
+    let second = resume(&path, token);
+    let (_resumed, _) = server.accept().unwrap();
+    let second = second.join().unwrap().unwrap();
+
+    /* now the resumed client holds it */
+    let third = resume(&path, token);
+    assert_rejected(server.accept().map(|_| ()));
+    assert_rejected(third.join().unwrap().map(|_| ()));
+
+    drop(second);
+
+    let third = resume(&path, token);
+    server.accept().unwrap();
+    assert!(third.join().unwrap().unwrap().is_resumed());
+}
+
+#[test]
+fn resumes_pass_the_filter() {
+    let (server, path) = server("resume-filter");
+
+    let (client, _vector) = connect(&server, &path);
+    let token = client.session_token().unwrap();
+    drop(client);
+
+    let refuse = |_: &VectorConfig, _: &PeerCredentials| {
+        Err(Rejection::new(Rejection::UNSPECIFIED, "closed"))
+    };
+
+    let client = resume(&path, token);
+    assert_rejected(server.accept_with(refuse).map(|_| ()));
+    assert_rejected(client.join().unwrap().map(|_| ()));
+
+    /* the refused resume doesn't hold the session */
+    let client = resume(&path, token);
+    server.accept().unwrap();
+    assert!(client.join().unwrap().unwrap().is_resumed());
+}
+
+/// Child side, resumes the session of the token passed by the parent.
+fn resume_as_child(token: &str) {
+    let path = std::env::var("RTIPC_RESUME_PATH").unwrap();
+    let token = token.parse().unwrap();
+
+    let result = client_resume(path.as_str(), token, &ConnectOptions::default());
+    assert_rejected(result.map(|_| ()));
+}
+
+#[test]
+fn resumes_of_other_clients_are_refused() {
+    if let Ok(token) = std::env::var("RTIPC_RESUME_TOKEN") {
+        resume_as_child(&token);
+        return;
+    }
+
+    /* the child needs another group */
+    if unsafe { nix::libc::geteuid() } != 0 {
+        return;
+    }
+
+    let (server, path) = server("resume-credentials");
+
+    let (client, _vector) = connect(&server, &path);
+    let token = client.session_token().unwrap();
+    drop(client);
+
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args(["resumes_of_other_clients_are_refused", "--exact"])
+        .env("RTIPC_RESUME_TOKEN", token.to_string())
+        .env("RTIPC_RESUME_PATH", &path)
+        .gid(65534)
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    assert_rejected(server.accept().map(|_| ()));
+    assert!(child.wait().unwrap().success());
+
+    /* the session still belongs to the first client */
+    let client = resume(&path, token);
+    server.accept().unwrap();
+    assert!(client.join().unwrap().unwrap().is_resumed());
+}
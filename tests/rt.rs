@@ -0,0 +1,47 @@
+#![cfg(feature = "rt")]
+
+use std::thread;
+use std::time::Duration;
+
+use nix::libc;
+
+use rtipc::{Errno, rt};
+
+#[test]
+fn thread_is_pinned() {
+    thread::spawn(|| {
+        let cpu = unsafe { libc::sched_getcpu() };
+        assert!(cpu >= 0);
+
+        rt::pin_to_cpu(cpu as usize).unwrap();
+        assert_eq!(unsafe { libc::sched_getcpu() }, cpu);
+
+        assert_eq!(rt::pin_to_cpu(usize::MAX), Err(Errno::EINVAL));
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn invalid_policies_are_refused() {
+    thread::spawn(|| {
+        assert_eq!(rt::set_fifo_priority(0), Err(Errno::EINVAL));
+        assert_eq!(rt::set_fifo_priority(100), Err(Errno::EINVAL));
+
+        /* runtime exceeds the period */
+        let ms = Duration::from_millis;
+        assert!(rt::set_deadline(ms(2), ms(1), ms(1)).is_err());
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn stack_is_prefaulted() {
+    thread::Builder::new()
+        .stack_size(1 << 20)
+        .spawn(|| rt::prefault_stack(512 << 10))
+        .unwrap()
+        .join()
+        .unwrap();
+}
@@ -0,0 +1,78 @@
+/* the raw syscalls of the rustix backend, run with cargo test --features rustix */
+#![cfg(all(feature = "socket", feature = "rustix"))]
+
+use std::os::fd::{AsFd, BorrowedFd};
+use std::thread;
+
+use nix::fcntl::{F_GET_SEALS, F_GETFD, FdFlag, SealFlag, fcntl};
+
+use rtipc::*;
+
+mod common;
+
+#[test]
+fn shm_is_a_sealed_memfd() {
+    let rsc = VectorResource::allocate(&common::vector().producer(1, 8).build()).unwrap();
+
+    let seals = SealFlag::from_bits_truncate(fcntl(rsc.shmfd(), F_GET_SEALS).unwrap());
+    assert!(
+        seals.contains(SealFlag::F_SEAL_GROW | SealFlag::F_SEAL_SHRINK | SealFlag::F_SEAL_SEAL)
+    );
+}
+
+#[test]
+fn fds_are_passed_with_fragmented_messages() {
+    let (tx, rx) = common::seqpacket_pair();
+    let eventfd = EventFd::new().unwrap();
+
+    /* more fds than fit into a datagram, attached to a message of several fragments */
+    let request: Vec<u8> = (0..0x30000).map(|i| i as u8).collect();
+    let sent = request.clone();
+
+    let sender = thread::spawn(move || {
+        let fds: Vec<BorrowedFd<'_>> = vec![eventfd.as_fd(); 300];
+        UnixTransport::new(tx.as_fd())
+            .send_request(&sent, &fds)
+            .unwrap();
+        eventfd
+    });
+
+    let mut transport = UnixTransport::new(rx.as_fd());
+    assert_eq!(transport.recv_request(None).unwrap(), request);
+
+    let fds = transport.recv_fds(300).unwrap();
+    assert_eq!(fds.len(), 300);
+
+    /* received with MSG_CMSG_CLOEXEC */
+    for fd in &fds {
+        let flags = FdFlag::from_bits_truncate(fcntl(fd, F_GETFD).unwrap());
+        assert!(flags.contains(FdFlag::FD_CLOEXEC));
+    }
+
+    /* the received fds refer to the eventfd of the sender */
+    let eventfd = sender.join().unwrap();
+    eventfd.write(1).unwrap();
+    let received = unsafe { EventFd::from_owned_fd(fds.into_iter().next().unwrap()) };
+    assert_eq!(received.read().unwrap(), 1);
+}
+
+#[test]
+fn vectors_are_connected() {
+    let vconfig = common::vector().producer(2, 8).eventfd().build();
+
+    let (mut client, mut vector, _) = common::connect("rustix", vconfig, ConnectOptions::default());
+
+    let mut producer = client.take_producer::<u64>(0).unwrap();
+    let mut consumer = vector.take_consumer::<u64>(0).unwrap();
+
+    /* the eventfd is a semaphore, every push wakes a single pop */
+    for value in [1, 2] {
+        *producer.current_message() = value;
+        producer.force_push();
+    }
+
+    for value in [1, 2] {
+        assert!(consumer.pop() == PopResult::Success);
+        assert_eq!(consumer.current_message(), Some(&value));
+    }
+}
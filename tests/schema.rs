@@ -0,0 +1,64 @@
+use rtipc::*;
+
+mod common;
+
+const MSG: u64 = schema_fingerprint(b"Msg { a: u64 }");
+const OTHER: u64 = schema_fingerprint(b"Msg { a: u32 }");
+
+/// Channel 0 announces the schema MSG, channel 1 none.
+fn typed_vector() -> VectorConfig {
+    common::vector()
+        .producer(1, 8)
+        .schema(MSG)
+        .producer(1, 8)
+        .build()
+}
+
+#[test]
+fn channels_with_a_schema_are_checked() {
+    let (mut owner, mut peer) = ChannelVector::create_pair(typed_vector()).unwrap();
+
+    /* u64 doesn't state a schema */
+    assert!(owner.take_producer::<u64>(0).is_none());
+    assert!(peer.take_consumer::<u64>(0).is_none());
+
+    assert!(owner.take_producer_checked::<u64>(0, OTHER).is_none());
+    assert!(peer.take_consumer_checked::<u64>(0, OTHER).is_none());
+
+    let mut producer = owner.take_producer_checked::<u64>(0, MSG).unwrap();
+    let mut consumer = peer.take_consumer_checked::<u64>(0, MSG).unwrap();
+
+    *producer.current_message() = 7;
+    producer.force_push();
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&7));
+}
+
+#[test]
+fn channels_without_a_schema_match_every_type() {
+    let (mut owner, mut peer) = ChannelVector::create_pair(typed_vector()).unwrap();
+
+    assert!(owner.take_producer_checked::<u64>(1, OTHER).is_some());
+    assert!(peer.take_consumer::<u64>(1).is_some());
+}
+
+#[test]
+fn unchecked_takes_ignore_the_schema() {
+    let (mut owner, mut peer) = ChannelVector::create_pair(typed_vector()).unwrap();
+
+    assert!(owner.take_producer_unchecked::<[u8; 8]>(0).is_some());
+    assert!(peer.take_consumer_unchecked::<[u8; 8]>(0).is_some());
+}
+
+#[cfg(feature = "socket")]
+#[test]
+fn the_schema_is_announced_to_the_server() {
+    let server = Server::unbound().unwrap();
+    let (_client, mut vector) = server
+        .loopback(typed_vector(), &ConnectOptions::default())
+        .unwrap();
+
+    assert_eq!(vector.consumer_schema(0), Some(MSG));
+    assert!(vector.take_consumer::<u64>(0).is_none());
+    assert!(vector.take_consumer_checked::<u64>(0, MSG).is_some());
+}
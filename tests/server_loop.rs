@@ -0,0 +1,286 @@
+#![cfg(feature = "socket")]
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nix::sys::socket::Backlog;
+
+use rtipc::*;
+
+mod common;
+
+fn server_loop(name: &str) -> (ServerLoop, PathBuf) {
+    let path = common::socket_path(name);
+    let server = Server::new(path.as_path(), Backlog::new(4).unwrap()).unwrap();
+    (ServerLoop::new(server), path)
+}
+
+fn connect(path: &Path) -> ChannelVector {
+    client_connect(path, common::vector().producer(1, 8).build()).unwrap()
+}
+
+/// Server loop whose handshakes time out after timeout.
+#[cfg(not(target_os = "macos"))]
+fn stalling_server_loop(name: &str, timeout: Duration) -> (ServerLoop, PathBuf) {
+    let path = common::socket_path(name);
+    let mut server = Server::new(path.as_path(), Backlog::new(4).unwrap()).unwrap();
+    server.set_handshake_timeout(Some(timeout));
+    (ServerLoop::new(server), path)
+}
+
+/// Connects to the server without ever sending a request.
+#[cfg(not(target_os = "macos"))]
+fn connect_silently(path: &Path) -> std::os::fd::OwnedFd {
+    use nix::sys::socket::{AddressFamily, SockFlag, SockType, UnixAddr, connect, socket};
+    use std::os::fd::AsRawFd;
+
+    let socket = socket(
+        AddressFamily::Unix,
+        SockType::SeqPacket,
+        SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+    .unwrap();
+    connect(socket.as_raw_fd(), &UnixAddr::new(path).unwrap()).unwrap();
+    socket
+}
+
+#[test]
+fn disconnected_clients_are_reaped() {
+    let (mut server_loop, path) = server_loop("server-loop");
+
+    let client_path = path.clone();
+    let leaving = thread::spawn(move || drop(connect(&client_path)));
+
+    /* the other client sends its statistics and waits until they arrived */
+    let (arrived, wait) = mpsc::channel::<()>();
+    let client_path = path.clone();
+    let reporting = thread::spawn(move || {
+        let mut vector = connect(&client_path);
+        let control = vector.take_control().unwrap();
+        control.send(&ControlMessage::Stats(vec![1, 2])).unwrap();
+        wait.recv().unwrap();
+    });
+
+    let mut connected = 0;
+    let mut disconnected = 0;
+    let mut stats = 0;
+
+    server_loop
+        .run(|server_loop, event| {
+            match event {
+                ServerEvent::Connected(id) => {
+                    connected += 1;
+
+                    /* the clients are served with their vectors */
+                    let client = server_loop.client(id).unwrap();
+                    assert!(client.vector().take_consumer::<u64>(0).is_some());
+                }
+                ServerEvent::Disconnected(id) => {
+                    disconnected += 1;
+                    assert!(server_loop.client(id).is_none());
+                }
+                ServerEvent::Message(_, ControlMessage::Stats(values)) => {
+                    assert_eq!(values, vec![1, 2]);
+                    stats += 1;
+                    arrived.send(()).unwrap();
+                }
+                _ => panic!("unexpected event"),
+            }
+            disconnected < 2
+        })
+        .unwrap();
+
+    assert_eq!((connected, disconnected, stats), (2, 2, 1));
+    assert_eq!(server_loop.clients().count(), 0);
+
+    leaving.join().unwrap();
+    reporting.join().unwrap();
+}
+
+#[test]
+fn clients_are_disconnected_by_the_server() {
+    let (mut server_loop, path) = server_loop("server-loop-disconnect");
+
+    let client_path = path.clone();
+    let client = thread::spawn(move || {
+        let mut vector = connect(&client_path);
+        let control = vector.take_control().unwrap();
+
+        /* the server dropped the vector of the client */
+        assert!(matches!(
+            control.receive().unwrap(),
+            ControlMessage::Goodbye
+        ));
+    });
+
+    let id = loop {
+        let events = server_loop.poll(Some(Duration::from_secs(5))).unwrap();
+
+        if let Some(ServerEvent::Connected(id)) = events.into_iter().next() {
+            break id;
+        }
+    };
+
+    assert!(server_loop.disconnect(id).is_some());
+    assert!(server_loop.disconnect(id).is_none());
+    assert_eq!(server_loop.clients().count(), 0);
+
+    client.join().unwrap();
+}
+
+#[test]
+fn silent_clients_time_out() {
+    let (mut server_loop, path) = server_loop("server-loop-keepalive");
+
+    server_loop.set_keepalive(Some(Keepalive {
+        interval: Duration::from_millis(30),
+        timeout: Duration::from_millis(150),
+    }));
+
+    let idle = Arc::new(Mutex::new(Vec::new()));
+    let handled = idle.clone();
+    server_loop.set_idle_handler(Box::new(move |id, _| handled.lock().unwrap().push(id)));
+
+    /* one client answers the pings until the loop is dropped */
+    let client_path = path.clone();
+    let answering = thread::spawn(move || {
+        let mut vector = connect(&client_path);
+        let mut control = vector.take_control().unwrap();
+        control.set_answer_pings(true);
+
+        let result = control.receive();
+        assert!(matches!(result, Ok(ControlMessage::Goodbye) | Err(_)));
+    });
+
+    /* the other one stays silent */
+    let (release, released) = mpsc::channel::<()>();
+    let client_path = path.clone();
+    let silent = thread::spawn(move || {
+        let mut vector = connect(&client_path);
+        let _control = vector.take_control().unwrap();
+        released.recv().unwrap();
+    });
+
+    let start = Instant::now();
+    let mut connected = Vec::new();
+    let mut disconnected = Vec::new();
+
+    while start.elapsed() < Duration::from_millis(600) {
+        for event in server_loop.poll(Some(Duration::from_millis(100))).unwrap() {
+            match event {
+                ServerEvent::Connected(id) => connected.push(id),
+                ServerEvent::Disconnected(id) => disconnected.push(id),
+                ServerEvent::Message(_, ControlMessage::Pong(_)) => panic!("pong returned"),
+                _ => {}
+            }
+        }
+    }
+
+    assert_eq!(connected.len(), 2);
+    assert_eq!(disconnected.len(), 1);
+    assert_eq!(*idle.lock().unwrap(), disconnected);
+
+    /* the answering client is still there and was heard from recently */
+    let remaining: Vec<ClientId> = server_loop.clients().collect();
+    assert_eq!(remaining.len(), 1);
+    assert!(!disconnected.contains(&remaining[0]));
+    assert!(server_loop.client(remaining[0]).unwrap().idle() < Duration::from_millis(100));
+
+    release.send(()).unwrap();
+    silent.join().unwrap();
+
+    drop(server_loop);
+    answering.join().unwrap();
+}
+
+#[cfg(not(target_os = "macos"))]
+#[test]
+fn stalled_handshakes_dont_block_other_clients() {
+    let (mut server_loop, path) =
+        stalling_server_loop("server-loop-stalled", Duration::from_millis(100));
+
+    let _stalled = connect_silently(&path);
+
+    let client_path = path.clone();
+    let client = thread::spawn(move || connect(&client_path));
+
+    let start = Instant::now();
+    let mut timed_out = 0;
+
+    let connected = loop {
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let events = server_loop.poll(Some(Duration::from_secs(1))).unwrap();
+
+        if let Some(id) = events.into_iter().find_map(|event| match event {
+            ServerEvent::Connected(id) => Some(id),
+            ServerEvent::AcceptFailed(TransferError::TimedOut) => {
+                timed_out += 1;
+                None
+            }
+            _ => panic!("unexpected event"),
+        }) {
+            break id;
+        }
+    };
+
+    assert_eq!(timed_out, 1);
+    assert!(server_loop.client(connected).is_some());
+
+    client.join().unwrap();
+}
+
+#[cfg(not(target_os = "macos"))]
+#[test]
+fn silent_clients_time_out_during_stalled_handshakes() {
+    let (mut server_loop, path) =
+        stalling_server_loop("server-loop-keepalive-stalled", Duration::from_millis(200));
+
+    server_loop.set_keepalive(Some(Keepalive {
+        interval: Duration::from_millis(30),
+        timeout: Duration::from_millis(150),
+    }));
+
+    let (release, released) = mpsc::channel::<()>();
+    let client_path = path.clone();
+    let silent = thread::spawn(move || {
+        let mut vector = connect(&client_path);
+        let _control = vector.take_control().unwrap();
+        released.recv().unwrap();
+    });
+
+    let id = loop {
+        let events = server_loop.poll(Some(Duration::from_secs(5))).unwrap();
+
+        if let Some(ServerEvent::Connected(id)) = events.into_iter().next() {
+            break id;
+        }
+    };
+
+    /* the handshakes of these never complete */
+    let _stalled: Vec<_> = (0..2).map(|_| connect_silently(&path)).collect();
+
+    let start = Instant::now();
+    let mut timed_out = 0;
+
+    /* removed within the keepalive timeout plus the stalled handshakes */
+    while server_loop.client(id).is_some() {
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        for event in server_loop.poll(Some(Duration::from_millis(100))).unwrap() {
+            match event {
+                ServerEvent::AcceptFailed(TransferError::TimedOut) => timed_out += 1,
+                ServerEvent::Disconnected(disconnected) => assert_eq!(disconnected, id),
+                _ => panic!("unexpected event"),
+            }
+        }
+    }
+
+    assert!(timed_out >= 1);
+
+    release.send(()).unwrap();
+    silent.join().unwrap();
+}
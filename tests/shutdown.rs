@@ -0,0 +1,84 @@
+#![cfg(feature = "socket")]
+
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nix::sys::socket::Backlog;
+
+use rtipc::*;
+
+mod common;
+
+fn connect(path: &Path) -> thread::JoinHandle<Result<ChannelVector, TransferError>> {
+    let path = path.to_path_buf();
+    thread::spawn(move || client_connect(path.as_path(), common::vector().producer(1, 8).build()))
+}
+
+#[test]
+fn clients_are_asked_to_hang_up() {
+    let path = common::socket_path("shutdown");
+    let server = Server::new(path.as_path(), Backlog::new(4).unwrap()).unwrap();
+
+    /* a well behaved client drops its vector on Shutdown */
+    let client_path = path.clone();
+    let obeying = thread::spawn(move || {
+        let mut vector = client_connect(
+            client_path.as_path(),
+            common::vector().producer(1, 8).build(),
+        )
+        .unwrap();
+        let control = vector.take_control().unwrap();
+        assert!(matches!(
+            control.receive().unwrap(),
+            ControlMessage::Shutdown
+        ));
+    });
+    let (_obeying, _) = server.accept().unwrap();
+
+    /* a stubborn one keeps it until the test lets it go */
+    let (release, released) = mpsc::channel::<()>();
+    let client_path = path.clone();
+    let stubborn = thread::spawn(move || {
+        let _vector = client_connect(
+            client_path.as_path(),
+            common::vector().producer(1, 8).build(),
+        )
+        .unwrap();
+        released.recv().unwrap();
+    });
+    let (_stubborn, _) = server.accept().unwrap();
+
+    /* the vector of the server is dropped already, its client isn't notified */
+    let dropped = connect(&path);
+    drop(server.accept().unwrap());
+    let _dropped = dropped.join().unwrap().unwrap();
+
+    let timeout = Duration::from_millis(200);
+    let start = Instant::now();
+
+    assert_eq!(server.shutdown(timeout).unwrap(), 1);
+    assert!(start.elapsed() >= timeout);
+    assert!(!path.exists());
+
+    release.send(()).unwrap();
+    obeying.join().unwrap();
+    stubborn.join().unwrap();
+}
+
+#[test]
+fn pending_handshakes_are_refused() {
+    let path = common::socket_path("shutdown-pending");
+    let server = Server::new(path.as_path(), Backlog::new(4).unwrap()).unwrap();
+
+    /* the clients wait in the backlog for a response that never comes */
+    let pending: Vec<_> = (0..2).map(|_| connect(&path)).collect();
+    thread::sleep(Duration::from_millis(50));
+
+    assert_eq!(server.shutdown(Duration::from_millis(50)).unwrap(), 0);
+
+    for client in pending {
+        assert!(client.join().unwrap().is_err());
+    }
+}
@@ -0,0 +1,84 @@
+#![cfg(feature = "socket")]
+
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use rtipc::*;
+
+mod common;
+
+/// Vector from the point of view of the parent.
+fn parent_vector() -> VectorConfig {
+    common::vector()
+        .producer(1, 8)
+        .eventfd()
+        .consumer(1, 8)
+        .info(b"parent")
+        .build()
+}
+
+fn options() -> ConnectOptions {
+    ConnectOptions {
+        timeout: Some(Duration::from_secs(10)),
+        ..Default::default()
+    }
+}
+
+/// Pops the next message, panics if none arrives within a few seconds.
+fn receive(consumer: &mut Consumer<u64>) -> u64 {
+    let deadline = Instant::now() + Duration::from_secs(10);
+
+    while consumer.pop() != PopResult::Success {
+        assert!(Instant::now() < deadline, "no message");
+        std::thread::yield_now();
+    }
+
+    *consumer.current_message().unwrap()
+}
+
+/// Child side, the parent runs the test binary with only this test.
+fn answer_parent() {
+    let mut vector = client_connect_inherited(&options()).unwrap();
+    assert_eq!(vector.info(), b"parent");
+
+    let mut consumer = vector.take_consumer::<u64>(0).unwrap();
+    let mut producer = vector.take_producer::<u64>(0).unwrap();
+
+    *producer.current_message() = receive(&mut consumer) + 1;
+    producer.force_push();
+}
+
+#[test]
+fn child_inherits_the_vector() {
+    if std::env::var(INHERITED_FD_VAR).is_ok() {
+        answer_parent();
+        return;
+    }
+
+    /* not spawned by a parent */
+    assert!(client_connect_inherited(&options()).is_err());
+
+    let mut command = Command::new(std::env::current_exe().unwrap());
+    command
+        .args(["child_inherits_the_vector", "--exact"])
+        .stdout(Stdio::null());
+
+    let (mut vector, mut child) =
+        spawn_with_vector(&mut command, parent_vector(), &options()).unwrap();
+
+    let mut producer = vector.take_producer::<u64>(0).unwrap();
+    let mut consumer = vector.take_consumer::<u64>(0).unwrap();
+
+    *producer.current_message() = 41;
+    producer.force_push();
+    assert_eq!(receive(&mut consumer), 42);
+
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn child_not_connecting_fails_the_spawn() {
+    let mut command = Command::new("true");
+
+    assert!(spawn_with_vector(&mut command, parent_vector(), &options()).is_err());
+}
@@ -0,0 +1,213 @@
+use std::num::NonZeroUsize;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use rtipc::*;
+
+mod common;
+
+const REGION_SIZE: usize = 1 << 12;
+
+/// Owner and peer of a vector placed in memory of the test, so the test can
+/// play a producer dying in the middle of a write.
+struct Region {
+    ptr: NonNull<u8>,
+}
+
+fn region_layout() -> std::alloc::Layout {
+    std::alloc::Layout::from_size_align(REGION_SIZE, 256).unwrap()
+}
+
+impl Region {
+    fn new() -> Self {
+        let ptr = NonNull::new(unsafe { std::alloc::alloc_zeroed(region_layout()) }).unwrap();
+        Self { ptr }
+    }
+
+    fn pair(&self, vconfig: VectorConfig) -> (ChannelVector, ChannelVector) {
+        let region = || {
+            Box::new(unsafe {
+                DeviceMemory::new(self.ptr, NonZeroUsize::new(REGION_SIZE).unwrap())
+            })
+        };
+
+        /* the peer initializes the memory */
+        let peer = ChannelVector::with_region(vconfig.clone(), region(), false).unwrap();
+        let owner = ChannelVector::with_region(vconfig, region(), true).unwrap();
+
+        (owner, peer)
+    }
+
+    /// sequence of the record, behind the descriptor of the only channel
+    fn sequence(&self) -> &AtomicU32 {
+        unsafe { AtomicU32::from_ptr(self.ptr.as_ptr().add(max_cacheline_size()).cast()) }
+    }
+}
+
+/* the tests declare the region before the vectors, so it outlives them */
+impl Drop for Region {
+    fn drop(&mut self) {
+        unsafe { std::alloc::dealloc(self.ptr.as_ptr(), region_layout()) };
+    }
+}
+
+#[test]
+fn state_is_replaced() {
+    let (mut owner, mut peer) = ChannelVector::create_pair(
+        common::vector()
+            .producer(0, 8)
+            .kind(ChannelKind::State)
+            .build(),
+    )
+    .unwrap();
+
+    let mut producer = owner.take_state_producer::<u64>(0).unwrap();
+    let consumer = peer.take_state_consumer::<u64>(0).unwrap();
+
+    assert_eq!(consumer.read(), None);
+    assert_eq!(consumer.sequence(), 0);
+
+    producer.write(&1);
+    producer.write(&2);
+
+    assert_eq!(consumer.read(), Some(2));
+    assert_eq!(consumer.try_read(), Some(2));
+    assert_eq!(consumer.sequence(), 4);
+}
+
+#[test]
+fn reads_are_never_torn() {
+    let (mut owner, mut peer) = ChannelVector::create_pair(
+        common::vector()
+            .producer(0, 64)
+            .kind(ChannelKind::State)
+            .build(),
+    )
+    .unwrap();
+
+    let mut producer = owner.take_state_producer::<[u64; 8]>(0).unwrap();
+    let consumer = peer.take_state_consumer::<[u64; 8]>(0).unwrap();
+
+    let writer = std::thread::spawn(move || {
+        for i in 1..=100_000u64 {
+            producer.write(&[i; 8]);
+        }
+    });
+
+    let mut last = 0;
+
+    while last < 100_000 {
+        if let Some(state) = consumer.read() {
+            assert!(state.iter().all(|v| *v == state[0]), "torn read {state:?}");
+            assert!(state[0] >= last);
+            last = state[0];
+        }
+    }
+
+    writer.join().unwrap();
+}
+
+#[test]
+fn dead_writer_doesnt_block_readers() {
+    let memory = Region::new();
+    let (mut owner, mut peer) = memory.pair(
+        common::vector()
+            .producer(0, 8)
+            .kind(ChannelKind::State)
+            .build(),
+    );
+
+    let mut producer = owner.take_state_producer::<u64>(0).unwrap();
+    let consumer = peer.take_state_consumer::<u64>(0).unwrap();
+
+    producer.write(&1);
+    assert_eq!(consumer.read(), Some(1));
+
+    /* the producer died in the middle of its next write */
+    memory.sequence().fetch_or(1, Ordering::SeqCst);
+
+    assert_eq!(consumer.read(), None);
+    assert_eq!(consumer.try_read(), None);
+}
+
+#[test]
+fn conflated_pop_reports_replaced_messages() {
+    let (mut owner, mut peer) = ChannelVector::create_pair(
+        common::vector()
+            .producer(0, 8)
+            .kind(ChannelKind::Conflated)
+            .build(),
+    )
+    .unwrap();
+
+    let mut producer = owner.take_conflated_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_conflated_consumer::<u64>(0).unwrap();
+
+    assert!(consumer.pop() == PopResult::NoMessage);
+
+    producer.push(&1);
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&1));
+    assert!(consumer.pop() == PopResult::NoNewMessage);
+
+    producer.push(&2);
+    producer.push(&3);
+    assert!(consumer.pop() == PopResult::SuccessMessagesDiscarded);
+    assert_eq!(consumer.current_message(), Some(&3));
+
+    producer.push(&4);
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&4));
+}
+
+#[test]
+fn conflated_sequence_wraps_around() {
+    let memory = Region::new();
+    let (mut owner, mut peer) = memory.pair(
+        common::vector()
+            .producer(0, 8)
+            .kind(ChannelKind::Conflated)
+            .build(),
+    );
+
+    let mut producer = owner.take_conflated_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_conflated_consumer::<u64>(0).unwrap();
+
+    producer.push(&1);
+    assert!(consumer.pop() == PopResult::Success);
+
+    /* the last write before the sequence wraps around */
+    memory.sequence().store(u32::MAX - 1, Ordering::SeqCst);
+    consumer.pop();
+
+    producer.push(&2);
+    assert_eq!(memory.sequence().load(Ordering::SeqCst), 2);
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&2));
+}
+
+#[test]
+fn conflated_pop_survives_a_dead_producer() {
+    let memory = Region::new();
+    let (mut owner, mut peer) = memory.pair(
+        common::vector()
+            .producer(0, 8)
+            .kind(ChannelKind::Conflated)
+            .build(),
+    );
+
+    let mut producer = owner.take_conflated_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_conflated_consumer::<u64>(0).unwrap();
+
+    producer.push(&1);
+    memory.sequence().fetch_or(1, Ordering::SeqCst);
+    assert!(consumer.pop() == PopResult::NoMessage);
+
+    memory.sequence().fetch_and(!1, Ordering::SeqCst);
+    assert!(consumer.pop() == PopResult::Success);
+
+    producer.push(&2);
+    memory.sequence().fetch_or(1, Ordering::SeqCst);
+    assert!(consumer.pop() == PopResult::NoNewMessage);
+    assert_eq!(consumer.current_message(), Some(&1));
+}
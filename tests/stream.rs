@@ -0,0 +1,136 @@
+#![cfg(feature = "socket")]
+
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nix::sys::socket::{AddressFamily, SockFlag, SockType, socketpair};
+use nix::sys::stat::fstat;
+
+use rtipc::*;
+
+mod common;
+
+/// a single frame carries up to SCM_MAX_FD fds
+const MAX_FD: usize = 253;
+
+fn stream_pair() -> (OwnedFd, OwnedFd) {
+    socketpair(
+        AddressFamily::Unix,
+        SockType::Stream,
+        None,
+        SockFlag::empty(),
+    )
+    .unwrap()
+}
+
+fn same_file(a: BorrowedFd<'_>, b: BorrowedFd<'_>) -> bool {
+    let (a, b) = (fstat(a).unwrap(), fstat(b).unwrap());
+    (a.st_dev, a.st_ino) == (b.st_dev, b.st_ino)
+}
+
+#[test]
+fn handshake_runs_over_a_stream() {
+    let (server_socket, client_socket) = stream_pair();
+    let server = Server::unbound().unwrap();
+
+    /* the eventfds of 3 producers exceed no frame, the vector is passed as usual */
+    let vconfig = common::vector()
+        .producers(vec![common::channel(ChannelKind::Queue, 2, 8, true); 3])
+        .info(b"stream")
+        .build();
+
+    let client = thread::spawn(move || {
+        let mut transport = StreamTransport::new(client_socket.as_fd());
+        client_connect_transport(&mut transport, vconfig, &ConnectOptions::default())
+    });
+
+    let credentials = PeerCredentials {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+
+    let mut transport = StreamTransport::new(server_socket.as_fd());
+    let (mut vector, peer) = server
+        .accept_transport(&mut transport, credentials, |_, _| Ok(Vec::new()))
+        .unwrap();
+
+    let mut client = client.join().unwrap().unwrap();
+    assert_eq!(peer.info, b"stream");
+
+    for index in 0..3 {
+        let mut producer = client.take_producer::<u64>(index).unwrap();
+        let mut consumer = vector.take_consumer::<u64>(index).unwrap();
+
+        *producer.current_message() = index as u64;
+        producer.force_push();
+        assert!(consumer.pop() == PopResult::Success);
+        assert_eq!(consumer.current_message(), Some(&(index as u64)));
+    }
+}
+
+#[test]
+fn fds_beyond_a_frame_follow_in_continuations() {
+    let (tx, rx) = stream_pair();
+    let eventfd = EventFd::new().unwrap();
+    let (pipe, _) = nix::unistd::pipe().unwrap();
+
+    /* a single frame, exactly full, one more and several continuations */
+    let counts = [MAX_FD, MAX_FD + 1, 2 * MAX_FD + 94, 2];
+
+    let sender = {
+        let eventfd = eventfd.as_fd().try_clone_to_owned().unwrap();
+        let pipe = pipe.try_clone().unwrap();
+
+        thread::spawn(move || {
+            let mut transport = StreamTransport::new(tx.as_fd());
+
+            for (seed, count) in counts.iter().enumerate() {
+                /* the last fd tells the frames apart */
+                let mut fds: Vec<BorrowedFd<'_>> = vec![eventfd.as_fd(); count - 1];
+                fds.push(pipe.as_fd());
+
+                let msg = vec![seed as u8; 0x10000 * seed + 1];
+                transport.send_request(&msg, &fds).unwrap();
+            }
+        })
+    };
+
+    let mut transport = StreamTransport::new(rx.as_fd());
+
+    for (seed, count) in counts.iter().enumerate() {
+        let msg = transport.recv_request(None).unwrap();
+        assert_eq!(msg, vec![seed as u8; 0x10000 * seed + 1]);
+
+        let fds = transport.recv_fds(*count).unwrap();
+        assert_eq!(fds.len(), *count);
+
+        assert!(same_file(fds.back().unwrap().as_fd(), pipe.as_fd()));
+        assert!(
+            fds.iter()
+                .take(count - 1)
+                .all(|fd| same_file(fd.as_fd(), eventfd.as_fd()))
+        );
+    }
+
+    sender.join().unwrap();
+}
+
+#[test]
+fn missing_continuations_time_out() {
+    let (tx, rx) = stream_pair();
+    let eventfd = EventFd::new().unwrap();
+
+    let mut sender = StreamTransport::new(tx.as_fd());
+    sender
+        .send_request(b"request", &[eventfd.as_fd(); 2])
+        .unwrap();
+
+    /* the receiver expects more fds than were sent */
+    let mut transport = StreamTransport::new(rx.as_fd());
+    let deadline = Instant::now() + Duration::from_millis(100);
+
+    assert_eq!(transport.recv_request(Some(deadline)).unwrap(), b"request");
+    assert!(transport.recv_fds(MAX_FD + 2).is_err());
+}
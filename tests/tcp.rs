@@ -0,0 +1,127 @@
+#![cfg(feature = "socket")]
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+
+use rtipc::*;
+
+mod common;
+
+/// Shared memory objects of this process left in /dev/shm.
+#[cfg(target_os = "linux")]
+fn named_shm() -> usize {
+    let prefix = format!("rtipc-{}-", std::process::id());
+
+    std::fs::read_dir("/dev/shm")
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with(&prefix)
+        })
+        .count()
+}
+
+#[test]
+fn vector_is_passed_by_name() {
+    let mut server = TcpServer::new("127.0.0.1:0").unwrap();
+    server.set_info(b"tcp".to_vec());
+    let addr = server.local_addr().unwrap();
+
+    let client = thread::spawn(move || {
+        client_connect_tcp(
+            addr,
+            common::vector()
+                .producer(1, 8)
+                .info(b"client")
+                .heartbeat()
+                .build(),
+            &ConnectOptions::default(),
+        )
+    });
+
+    let (mut vector, _) = server.accept().unwrap();
+    let mut client = client.join().unwrap().unwrap();
+
+    assert_eq!(client.server_info(), b"tcp");
+    assert_eq!(vector.info(), b"client");
+
+    let mut producer = client.take_producer::<u64>(0).unwrap();
+    let mut consumer = vector.take_consumer::<u64>(0).unwrap();
+
+    *producer.current_message() = 5;
+    assert!(producer.try_push() == TryPushResult::Success);
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&5));
+
+    /* the name is gone once both peers mapped the shared memory */
+    #[cfg(target_os = "linux")]
+    assert_eq!(named_shm(), 0);
+}
+
+#[test]
+fn eventfds_are_refused() {
+    let server = TcpServer::new("127.0.0.1:0").unwrap();
+
+    let result = client_connect_tcp(
+        server.local_addr().unwrap(),
+        common::vector()
+            .producer(1, 8)
+            .eventfd()
+            .info(b"client")
+            .heartbeat()
+            .build(),
+        &ConnectOptions::default(),
+    );
+
+    assert!(matches!(
+        result,
+        Err(TransferError::ConfigError(ConfigError::EventFdsUnsupported))
+    ));
+}
+
+#[test]
+fn requests_without_a_name_are_refused() {
+    let server = TcpServer::new("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+
+    /* a request of a unix socket, its shared memory is passed as fd */
+    let client = thread::spawn(move || {
+        let rsc = VectorResource::allocate(
+            &common::vector()
+                .producer(1, 8)
+                .info(b"client")
+                .heartbeat()
+                .build(),
+        )
+        .unwrap();
+        let (request, _) = rsc.serialize().unwrap();
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(&(request.len() as u32).to_le_bytes())
+            .unwrap();
+        stream.write_all(&request).unwrap();
+
+        /* the rejection */
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len).unwrap();
+        assert!(u32::from_le_bytes(len) > 0);
+    });
+
+    let result = server.accept();
+    assert!(
+        matches!(
+            result,
+            Err(TransferError::RequestError(RequestError::MissingRecord(_)))
+        ),
+        "{:?}",
+        result.err()
+    );
+
+    client.join().unwrap();
+}
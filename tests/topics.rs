@@ -0,0 +1,194 @@
+use rtipc::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Pose {
+    x: f64,
+    y: f64,
+}
+
+fn pose(x: f64) -> Pose {
+    Pose { x, y: -x }
+}
+
+fn qos(depth: usize, reliability: Reliability, latching: bool) -> TopicQos {
+    TopicQos {
+        depth,
+        reliability,
+        latching,
+        ..TopicQos::default()
+    }
+}
+
+#[test]
+fn topics_are_matched_by_name() {
+    let reliable = qos(2, Reliability::Reliable, false);
+    let best_effort = qos(2, Reliability::BestEffort, false);
+
+    let config = Topics::new()
+        .publish::<Pose>("pose", reliable)
+        .unwrap()
+        .publish::<u32>("odometry", best_effort)
+        .unwrap()
+        .subscribe::<u64>("cmd_vel", reliable)
+        .unwrap()
+        .into_config();
+
+    let (mut owner, mut peer) = ChannelVector::create_pair(config).unwrap();
+
+    assert!(peer.take_publisher::<u32>("pose", reliable).is_none());
+
+    let mut odometry = owner
+        .take_publisher::<u32>("odometry", best_effort)
+        .unwrap();
+    let mut pose_pub = owner.take_publisher::<Pose>("pose", reliable).unwrap();
+    let mut pose_sub = peer.take_subscription::<Pose>("pose", reliable).unwrap();
+    let mut odometry_sub = peer
+        .take_subscription::<u32>("odometry", best_effort)
+        .unwrap();
+    let mut cmd_pub = peer.take_publisher::<u64>("cmd_vel", reliable).unwrap();
+    let mut cmd_sub = owner.take_subscription::<u64>("cmd_vel", reliable).unwrap();
+
+    assert!(owner.take_publisher::<Pose>("pose", reliable).is_none());
+
+    /* a reliable topic refuses samples beyond its depth */
+    assert_eq!(pose_pub.publish(&pose(1.0)), PublishResult::Published);
+    assert_eq!(pose_pub.publish(&pose(2.0)), PublishResult::Published);
+    assert_eq!(pose_pub.publish(&pose(3.0)), PublishResult::QueueFull);
+
+    assert_eq!(pose_sub.take().ok(), Some(pose(1.0)));
+    assert_eq!(pose_sub.take().ok(), Some(pose(2.0)));
+    assert!(pose_sub.take() == Err(PopResult::NoNewMessage));
+
+    /* a best effort topic drops the oldest samples */
+    for i in 0..4 {
+        odometry.publish(&i);
+    }
+    assert_eq!(odometry.publish(&4), PublishResult::OldestDiscarded);
+
+    assert_eq!(odometry_sub.take().ok(), Some(3));
+    assert_eq!(odometry_sub.take().ok(), Some(4));
+
+    cmd_pub.publish(&7);
+    assert_eq!(cmd_sub.latest().ok(), Some(7));
+    assert!(cmd_sub.latest() == Err(PopResult::NoNewMessage));
+}
+
+#[test]
+fn latched_samples_reach_later_subscribers() {
+    let latched = qos(1, Reliability::BestEffort, true);
+
+    let config = Topics::new()
+        .publish::<u64>("map", latched)
+        .unwrap()
+        .into_config();
+
+    let (mut owner, mut peer) = ChannelVector::create_pair(config.clone()).unwrap();
+
+    let mut publisher = owner.take_publisher::<u64>("map", latched).unwrap();
+    let mut subscription = peer.take_subscription::<u64>("map", latched).unwrap();
+
+    assert!(subscription.latest() == Err(PopResult::NoMessage));
+
+    publisher.publish(&1);
+    publisher.publish(&2);
+
+    /* the last sample stays readable */
+    assert_eq!(subscription.latest().ok(), Some(2));
+    assert_eq!(subscription.latest().ok(), Some(2));
+    assert!(subscription.take() == Err(PopResult::NoNewMessage));
+
+    /* a vector of a later subscriber starts with the latched sample */
+    let (mut owner, mut peer) = ChannelVector::create_pair(config).unwrap();
+
+    let later = owner
+        .take_publisher::<u64>("map", latched)
+        .unwrap()
+        .with_latched(publisher.latched());
+    let mut subscription = peer.take_subscription::<u64>("map", latched).unwrap();
+
+    assert_eq!(later.latched(), Some(&2));
+    assert_eq!(subscription.take().ok(), Some(2));
+}
+
+#[test]
+fn topics_are_validated() {
+    let qos = TopicQos::default();
+
+    assert_eq!(
+        Topics::new()
+            .publish::<u64>("a", qos)
+            .unwrap()
+            .subscribe::<u64>("a", qos)
+            .err(),
+        Some(ConfigError::InvalidTopic)
+    );
+    assert_eq!(
+        Topics::new().publish::<u64>("", qos).err(),
+        Some(ConfigError::InvalidTopic)
+    );
+    assert_eq!(
+        Topics::new()
+            .publish::<u64>("a", TopicQos { depth: 0, ..qos })
+            .err(),
+        Some(ConfigError::InvalidTopic)
+    );
+    assert_eq!(
+        Topics::new().publish::<()>("a", qos).err(),
+        Some(ConfigError::InvalidTopic)
+    );
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct MsgCommand(u32);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct MsgEvent(u64);
+
+rtipc::topics! {
+    struct ClientTopics => ServerTopics {
+        publish command: MsgCommand[depth = 1, eventfd],
+        subscribe event: MsgEvent[depth = 10, best_effort, latching],
+        subscribe status: u8[],
+    }
+}
+
+#[test]
+fn topics_are_declared_by_macro() {
+    let config = ClientTopics::config().unwrap();
+
+    assert_eq!(config.producers.len(), 1);
+    assert_eq!(config.consumers.len(), 2);
+    assert!(config.producers[0].eventfd);
+    assert_eq!(config.consumers[0].queue.info, b"event");
+
+    let (mut client, mut server) = ChannelVector::create_pair(config).unwrap();
+
+    let mut client = ClientTopics::take(&mut client).unwrap();
+    let mut server = ServerTopics::take(&mut server).unwrap();
+
+    assert_eq!(
+        client.command.publish(&MsgCommand(1)),
+        PublishResult::Published
+    );
+    assert_eq!(server.command.take().ok(), Some(MsgCommand(1)));
+
+    assert_eq!(server.event.qos().reliability, Reliability::BestEffort);
+    server.event.publish(&MsgEvent(2));
+    assert_eq!(client.event.latest().ok(), Some(MsgEvent(2)));
+    assert_eq!(client.event.latest().ok(), Some(MsgEvent(2)));
+
+    server.status.publish(&3);
+    assert_eq!(client.status.take().ok(), Some(3));
+}
+
+#[test]
+fn missing_topics_are_not_taken() {
+    let config = Topics::new()
+        .publish::<MsgCommand>("command", TopicQos::default())
+        .unwrap()
+        .into_config();
+
+    let (mut client, _server) = ChannelVector::create_pair(config).unwrap();
+
+    assert!(ClientTopics::take(&mut client).is_none());
+}
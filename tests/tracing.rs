@@ -0,0 +1,132 @@
+#![cfg(feature = "tracing")]
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+use rtipc::*;
+
+/// Events as "span: message", the span being the innermost entered one.
+#[derive(Clone, Default)]
+struct Recorder {
+    events: Arc<Mutex<Vec<String>>>,
+    spans: Arc<Mutex<HashMap<u64, &'static str>>>,
+    stack: Arc<Mutex<Vec<u64>>>,
+    next: Arc<AtomicU64>,
+}
+
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{value:?}");
+        }
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+        self.spans
+            .lock()
+            .unwrap()
+            .insert(id, span.metadata().name());
+        Id::from_u64(id)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut message = Message(String::new());
+        event.record(&mut message);
+
+        let span = self
+            .stack
+            .lock()
+            .unwrap()
+            .last()
+            .map_or("", |id| self.spans.lock().unwrap()[id]);
+
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("{span}: {}", message.0));
+    }
+
+    fn enter(&self, span: &Id) {
+        self.stack.lock().unwrap().push(span.into_u64());
+    }
+
+    fn exit(&self, _: &Id) {
+        self.stack.lock().unwrap().pop();
+    }
+}
+
+fn vector_config() -> VectorConfig {
+    Topics::new()
+        .publish::<u64>(
+            "pose",
+            TopicQos {
+                depth: 2,
+                ..TopicQos::default()
+            },
+        )
+        .unwrap()
+        .into_config()
+}
+
+#[test]
+fn queue_events_are_traced() {
+    let recorder = Recorder::default();
+
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let (mut owner, mut peer) = ChannelVector::create_pair(vector_config()).unwrap();
+
+        let mut producer = owner.take_producer::<u64>(0).unwrap();
+        let mut consumer = peer.take_consumer::<u64>(0).unwrap();
+
+        producer.try_push();
+        producer.try_push();
+        assert!(producer.try_push() == TryPushResult::QueueFull);
+
+        while producer.force_push() != ForcePushResult::SuccessMessageDiscarded {}
+
+        assert!(consumer.pop() == PopResult::SuccessMessagesDiscarded);
+    });
+
+    let events = recorder.events.lock().unwrap();
+
+    assert!(events.contains(&": pushed".to_owned()));
+    assert!(events.contains(&": push refused, queue full".to_owned()));
+    assert!(events.contains(&": push discarded the oldest message".to_owned()));
+    assert!(events.contains(&": popped after discarded messages".to_owned()));
+    assert!(events.contains(&": pop".to_owned()));
+}
+
+#[cfg(feature = "socket")]
+#[test]
+fn handshakes_are_spans() {
+    let recorder = Recorder::default();
+
+    let server = Server::unbound().unwrap();
+
+    tracing::subscriber::with_default(recorder.clone(), || {
+        server
+            .loopback(vector_config(), &ConnectOptions::default())
+            .unwrap();
+    });
+
+    let events = recorder.events.lock().unwrap();
+    assert!(events.contains(&"rtipc.server.handshake: client accepted".to_owned()));
+}
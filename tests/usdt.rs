@@ -0,0 +1,43 @@
+#![cfg(all(feature = "usdt", target_os = "linux"))]
+
+use rtipc::*;
+
+/* the stapsdt notes hold the provider and the name of a probe one after another */
+fn has_probe(exe: &[u8], name: &str) -> bool {
+    let note = format!("rtipc\0{name}\0");
+    exe.windows(note.len()).any(|w| w == note.as_bytes())
+}
+
+#[test]
+fn probes_are_nops_without_a_tracer() {
+    let config = Topics::new()
+        .publish::<u64>(
+            "pose",
+            TopicQos {
+                depth: 2,
+                eventfd: true,
+                ..TopicQos::default()
+            },
+        )
+        .unwrap()
+        .into_config();
+
+    let (mut owner, mut peer) = ChannelVector::create_pair(config).unwrap();
+
+    let mut producer = owner.take_producer::<u64>(0).unwrap();
+    let mut consumer = peer.take_consumer::<u64>(0).unwrap();
+
+    for i in 0..4 {
+        *producer.current_message() = i;
+        producer.force_push();
+    }
+
+    assert!(consumer.pop() == PopResult::SuccessMessagesDiscarded);
+    assert_eq!(consumer.current_message(), Some(&2));
+
+    let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+
+    for name in ["push", "pop", "discard", "overrun", "eventfd_write"] {
+        assert!(has_probe(&exe, name), "{name}");
+    }
+}
@@ -0,0 +1,94 @@
+use rtipc::*;
+
+mod common;
+
+#[test]
+fn vectors_are_checked() {
+    let mut vconfig = common::vector().build();
+    assert_eq!(vconfig.validate(usize::MAX), Err(ConfigError::NoChannels));
+
+    vconfig = common::vector().consumer(1, 8).build();
+    assert_eq!(vconfig.validate(usize::MAX), Ok(()));
+
+    assert!(matches!(
+        vconfig.validate(16),
+        Err(ConfigError::ShmSizeExceeded { max: 16, .. })
+    ));
+
+    /* the request is only written with the socket layer */
+    if cfg!(feature = "socket") {
+        vconfig.info = vec![0; 0x1000001];
+        assert!(matches!(
+            vconfig.validate(usize::MAX),
+            Err(ConfigError::InfoTooLong { .. })
+        ));
+    }
+}
+
+#[test]
+fn queue_lengths_overflowing_are_refused() {
+    let vconfig = common::vector()
+        .consumer(1, 8)
+        .consumer(usize::MAX, 8)
+        .build();
+
+    assert_eq!(
+        vconfig.validate(usize::MAX),
+        Err(ConfigError::QueueOverflow {
+            producer: false,
+            index: 1
+        })
+    );
+
+    let vconfig = common::vector().consumer(u32::MAX as usize, 8).build();
+
+    assert_eq!(
+        vconfig.validate(usize::MAX),
+        Err(ConfigError::QueueOverflow {
+            producer: false,
+            index: 0
+        })
+    );
+}
+
+#[test]
+fn shm_sizes_overflowing_are_refused() {
+    /* every field fits into the request, the shared memory of the vector doesn't */
+    let max = u32::MAX as usize;
+
+    let vconfig = common::vector()
+        .consumer(max - 3, max)
+        .kind(ChannelKind::Priority)
+        .build();
+
+    assert_eq!(
+        vconfig.validate(usize::MAX),
+        Err(ConfigError::ShmSizeExceeded {
+            size: usize::MAX,
+            max: usize::MAX
+        })
+    );
+
+    let arena = |num_blocks| common::vector().arena(max, num_blocks).build();
+
+    /* the blocks of an arena are limited by the handles */
+    assert_eq!(
+        arena(max).validate(usize::MAX),
+        Err(ConfigError::ArenaOverflow)
+    );
+
+    /* the largest arena only overflows 32 bit pointers */
+    let result = arena(MAX_ARENA_BLOCKS).validate(usize::MAX);
+
+    if cfg!(target_pointer_width = "32") {
+        assert_eq!(
+            result,
+            Err(ConfigError::ShmSizeExceeded {
+                size: usize::MAX,
+                max: usize::MAX
+            })
+        );
+    } else {
+        assert_eq!(result, Ok(()));
+    }
+}
@@ -0,0 +1,94 @@
+#![cfg(all(feature = "socket", target_os = "linux"))]
+
+use std::os::fd::{AsFd, OwnedFd};
+use std::thread;
+
+use nix::sys::memfd::{MFdFlags, memfd_create};
+use nix::sys::socket::Backlog;
+
+use rtipc::*;
+
+mod common;
+
+/// cid of the local host, reached through the vsock_loopback module
+const VMADDR_CID_LOCAL: u32 = 1;
+
+/// Shared memory both peers map, e.g. the memory of an ivshmem device.
+fn shared_memory(size: usize) -> OwnedFd {
+    let shm = memfd_create("vsock", MFdFlags::empty()).unwrap();
+    nix::unistd::ftruncate(&shm, size as nix::libc::off_t).unwrap();
+    shm
+}
+
+#[test]
+fn vectors_are_checked_before_connecting() {
+    let shm = shared_memory(4096);
+    let options = ConnectOptions::default();
+
+    /* no port is listening, the checks fail first */
+    let result = client_connect_vsock(
+        VMADDR_CID_LOCAL,
+        5555,
+        shm.as_fd(),
+        0,
+        common::vector().producer(1, 8).eventfd().build(),
+        &options,
+    );
+    assert!(matches!(
+        result,
+        Err(TransferError::ConfigError(ConfigError::EventFdsUnsupported))
+    ));
+
+    /* the vector doesn't fit behind the offset */
+    let result = client_connect_vsock(
+        VMADDR_CID_LOCAL,
+        5555,
+        shm.as_fd(),
+        4096,
+        common::vector().producer(1, 8).build(),
+        &options,
+    );
+    assert!(
+        matches!(result, Err(TransferError::ShmSizeMismatch { .. })),
+        "{:?}",
+        result.err()
+    );
+}
+
+#[test]
+#[ignore = "needs the vsock_loopback module"]
+fn vector_is_placed_at_the_offset() {
+    const PORT: u32 = 5556;
+    const OFFSET: usize = 0x1000;
+
+    let shm = shared_memory(0x10000);
+    let client_shm = shm.try_clone().unwrap();
+
+    let mut server = VsockServer::new(PORT, shm, Backlog::new(1).unwrap()).unwrap();
+    server.set_info(b"host".to_vec());
+
+    let client = thread::spawn(move || {
+        client_connect_vsock(
+            VMADDR_CID_LOCAL,
+            PORT,
+            client_shm.as_fd(),
+            OFFSET,
+            common::vector().producer(1, 8).build(),
+            &ConnectOptions::default(),
+        )
+    });
+
+    let (mut vector, cid) = server.accept().unwrap();
+    let mut client = client.join().unwrap().unwrap();
+
+    assert_eq!(cid, VMADDR_CID_LOCAL);
+    assert_eq!(client.server_info(), b"host");
+
+    let mut producer = client.take_producer::<u64>(0).unwrap();
+    let mut consumer = vector.take_consumer::<u64>(0).unwrap();
+
+    *producer.current_message() = 7;
+    assert!(producer.try_push() == TryPushResult::Success);
+    assert!(consumer.pop() == PopResult::Success);
+    assert_eq!(consumer.current_message(), Some(&7));
+}
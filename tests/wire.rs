@@ -0,0 +1,273 @@
+/* the wire format has to be the same for 32 and 64 bit peers */
+#![cfg(feature = "socket")]
+
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+use std::os::fd::OwnedFd;
+use std::thread;
+
+use nix::sys::socket::Backlog;
+
+use rtipc::*;
+
+mod common;
+
+const HEADER_SIZE: usize = 16;
+const RECORD_HEADER_SIZE: usize = 8;
+
+/// Vector with every record of the TLV protocol, a byte of info per message of each queue.
+fn wire_vector() -> VectorConfig {
+    common::vector()
+        .producer(3, 24)
+        .eventfd()
+        .queue_info(&[24; 4])
+        .producer(0, 64)
+        .kind(ChannelKind::State)
+        .queue_info(&[64])
+        .consumer(1, 8)
+        .eventfd()
+        .queue_info(&[8; 2])
+        .consumer(0, 16)
+        .kind(ChannelKind::Counters)
+        .queue_info(&[16])
+        .consumer(5, 40)
+        .kind(ChannelKind::Broadcast)
+        .queue_info(&[40; 6])
+        .info(b"wire")
+        .heartbeat()
+        .build()
+}
+
+fn dup_fds(rsc: &VectorResource) -> VecDeque<OwnedFd> {
+    let (_, fds) = rsc.serialize().unwrap();
+
+    fds.into_iter()
+        .map(|fd| fd.try_clone_to_owned().unwrap())
+        .collect()
+}
+
+/// kind, additional_messages, message_size, info and eventfd of a channel
+type Channel = (ChannelKind, usize, NonZeroUsize, Vec<u8>, bool);
+
+fn sent(channels: &[ChannelConfig]) -> Vec<Channel> {
+    channels
+        .iter()
+        .map(|c| {
+            let q = &c.queue;
+            (
+                c.kind,
+                q.additional_messages,
+                q.message_size,
+                q.info.clone(),
+                c.eventfd,
+            )
+        })
+        .collect()
+}
+
+/* ChannelResource isn't exported */
+macro_rules! received {
+    ($channels:expr) => {
+        $channels
+            .iter()
+            .map(|c| {
+                let q = &c.config;
+                let eventfd = c.eventfd.is_some();
+                (
+                    c.kind,
+                    q.additional_messages,
+                    q.message_size,
+                    q.info.clone(),
+                    eventfd,
+                )
+            })
+            .collect::<Vec<Channel>>()
+    };
+}
+
+#[test]
+fn request_round_trip() {
+    let vconfig = wire_vector();
+    let rsc = VectorResource::allocate(&vconfig).unwrap();
+    let (request, _) = rsc.serialize().unwrap();
+
+    let peer = VectorResource::deserialize(&request, dup_fds(&rsc)).unwrap();
+
+    /* the peer sees the vector from the other side */
+    assert_eq!(received!(peer.consumers), sent(&vconfig.producers));
+    assert_eq!(received!(peer.producers), sent(&vconfig.consumers));
+    assert_eq!(peer.info, vconfig.info);
+    assert_eq!(peer.heartbeat, vconfig.heartbeat);
+    assert_eq!(peer.cacheline_size, rsc.cacheline_size);
+    assert_eq!(peer.index_size, rsc.index_size);
+
+    /* serializing the received vector gives the same request back */
+    let mirrored = VectorConfig {
+        producers: vconfig.consumers.clone(),
+        consumers: vconfig.producers.clone(),
+        ..vconfig
+    };
+    let (mirrored_request, _) = VectorResource::allocate(&mirrored)
+        .unwrap()
+        .serialize()
+        .unwrap();
+    let (peer_request, _) = peer.serialize().unwrap();
+
+    assert_eq!(peer_request, mirrored_request);
+}
+
+#[test]
+fn request_is_little_endian() {
+    let rsc = VectorResource::allocate(&wire_vector()).unwrap();
+    let (request, _) = rsc.serialize().unwrap();
+
+    /* magic, version, cacheline_size and atomic_size */
+    assert_eq!(request[0..2], [0x0c, 0x1f]);
+    assert_eq!(request[2..4], 9u16.to_le_bytes());
+    assert_eq!(request[4..6], (rsc.cacheline_size as u16).to_le_bytes());
+    assert_eq!(request[6..8], (rsc.index_size as u16).to_le_bytes());
+
+    /* the records end exactly at the end of the request */
+    let mut offset = HEADER_SIZE;
+
+    while offset < request.len() {
+        let length = u32::from_le_bytes(request[offset + 4..offset + 8].try_into().unwrap());
+        offset += RECORD_HEADER_SIZE + length as usize;
+    }
+
+    assert_eq!(offset, request.len());
+}
+
+#[test]
+fn fixed_layout_round_trip() {
+    let path = "/tmp/rtipc_test_fixed_layout.sock";
+    let _ = std::fs::remove_file(path);
+
+    let vconfig = common::vector()
+        .producer(1, 8)
+        .eventfd()
+        .queue_info(&[8; 2])
+        .consumer(2, 16)
+        .eventfd()
+        .queue_info(&[16; 3])
+        .consumer(0, 32)
+        .eventfd()
+        .queue_info(&[32])
+        .info(b"fixed")
+        .build();
+
+    let server = Server::new(path, Backlog::new(1).unwrap()).unwrap();
+
+    let client = thread::spawn({
+        let vconfig = vconfig.clone();
+        move || {
+            let options = ConnectOptions {
+                legacy: true,
+                ..Default::default()
+            };
+            client_connect_with(path, vconfig, &options).unwrap()
+        }
+    });
+
+    let (mut vec, peer) = server.accept().unwrap();
+    let mut client = client.join().unwrap();
+
+    assert_eq!(peer.info, vconfig.info);
+    assert_eq!(vec.consumer_info(0), Some(&vconfig.producers[0].queue.info));
+    assert_eq!(vec.producer_info(1), Some(&vconfig.consumers[1].queue.info));
+
+    let mut producer = client.take_producer::<u64>(0).unwrap();
+    let mut consumer = vec.take_consumer::<u64>(0).unwrap();
+
+    *producer.current_message() = 0x0102030405060708;
+    producer.force_push();
+
+    assert!(matches!(consumer.pop(), PopResult::Success));
+    assert_eq!(*consumer.current_message().unwrap(), 0x0102030405060708);
+}
+
+#[cfg(all(target_endian = "little", target_os = "linux"))]
+#[test]
+fn baseline_requests_are_parsed() {
+    let fds = common::baseline_fds(&common::baseline_config());
+    let rsc = VectorResource::deserialize(&common::baseline_request(), fds.into()).unwrap();
+
+    assert_eq!(rsc.info(), b"baseline");
+    assert_eq!(rsc.collect_consumer_eventfds().len(), 1);
+    assert_eq!(rsc.collect_producer_eventfds().len(), 1);
+
+    let mut vector = ChannelVector::new(rsc).unwrap();
+
+    /* the channels of the requester, seen from the other side */
+    assert_eq!(vector.consumer_info(0).unwrap(), b"cmd");
+    assert_eq!(vector.producer_info(0).unwrap(), b"rsp");
+    assert_eq!(vector.producer_info(1).unwrap(), b"");
+    assert_eq!(vector.consumer_kind(0), Some(ChannelKind::Queue));
+    assert!(vector.take_consumer::<u64>(0).unwrap().eventfd().is_some());
+    assert!(
+        vector
+            .take_producer::<[u8; 16]>(0)
+            .unwrap()
+            .eventfd()
+            .is_none()
+    );
+    assert!(
+        vector
+            .take_producer::<[u8; 24]>(1)
+            .unwrap()
+            .eventfd()
+            .is_some()
+    );
+}
+
+/* the legacy protocol is the request layout of librtipc 0.5.1 in native byte order */
+#[cfg(all(target_endian = "little", not(target_os = "macos")))]
+#[test]
+fn legacy_requests_have_the_baseline_layout() {
+    use std::os::fd::{AsFd, AsRawFd};
+
+    let (server, client) = common::seqpacket_pair();
+
+    let client = std::thread::spawn(move || {
+        let options = ConnectOptions {
+            legacy: true,
+            ..Default::default()
+        };
+        client_connect_fd_with(client.as_raw_fd(), common::baseline_config(), &options)
+    });
+
+    let mut transport = UnixTransport::new(server.as_fd());
+
+    assert_eq!(
+        transport.recv_request(None).unwrap(),
+        common::baseline_request()
+    );
+    /* shared memory and the eventfds of cmd and the last consumer */
+    assert_eq!(transport.recv_fds(3).unwrap().len(), 3);
+
+    /* success response of a librtipc 0.5.1 server */
+    transport.send_response(&[0; 4], &[]).unwrap();
+
+    let mut client = client.join().unwrap().unwrap();
+    assert!(client.take_control().is_none());
+    assert!(client.take_producer::<u64>(0).is_some());
+}
+
+#[test]
+fn legacy_requests_refuse_arenas() {
+    let vconfig = VectorConfig {
+        arena: Some(ArenaConfig {
+            block_size: NonZeroUsize::new(64).unwrap(),
+            num_blocks: NonZeroUsize::new(4).unwrap(),
+        }),
+        ..common::baseline_config()
+    };
+
+    let options = ConnectOptions {
+        legacy: true,
+        ..Default::default()
+    };
+
+    let server = Server::unbound().unwrap();
+    assert!(server.loopback(vconfig, &options).is_err());
+}